@@ -0,0 +1,602 @@
+//! Generation of typed host-side wrappers for the kernels of a gpu crate.
+//!
+//! The gpu crate's sources are scanned for functions marked with `#[kernel]`, and a launch function
+//! with the same name and the host-side equivalent of its parameters is generated for each of them,
+//! see [`CudaBuilder::generate_kernel_bindings`](crate::CudaBuilder::generate_kernel_bindings).
+//! Generic kernels get a launch function for every instance declared with `kernel_instances!`.
+//!
+//! Types are emitted exactly as they are written in the kernel, so they must be in scope wherever
+//! the generated file is included.
+
+use crate::CudaBuilderError;
+use proc_macro2::{Group, TokenStream, TokenTree};
+use quote::ToTokens;
+use std::{
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+use syn::{
+    parse::{Parse, ParseStream},
+    punctuated::Punctuated,
+    ExprPath, FnArg, GenericArgument, GenericParam, Ident, Item, ItemFn, Pat, PathArguments,
+    ReturnType, Signature, Token, Type, TypePath,
+};
+
+const PRIMITIVES: &[&str] = &[
+    "bool", "char", "f32", "f64", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+    "u64", "u128", "usize",
+];
+
+/// Names used by the parameters every wrapper has.
+const RESERVED: &[&str] = &["module", "stream", "config", "output_len"];
+
+enum ParamKind {
+    Scalar(String),
+    Value(String),
+    /// A pointer (`len: false`) or a slice, which is passed as a pointer and a length.
+    Slice {
+        elem: String,
+        mutable: bool,
+        len: bool,
+    },
+    Box {
+        elem: String,
+        mutable: bool,
+    },
+}
+
+struct Param {
+    name: String,
+    kind: ParamKind,
+}
+
+struct Kernel {
+    name: String,
+    params: Vec<Param>,
+    /// The type of the values returned by every thread, see `#[kernel]`.
+    output: Option<String>,
+    /// The hash of the signature which `#[kernel]` embeds in the PTX.
+    abi_hash: u64,
+}
+
+pub(crate) fn generate(crate_path: &Path, out: &Path) -> Result<(), CudaBuilderError> {
+    let mut files = Vec::new();
+    collect_sources(&crate_path.join("src"), &mut files)
+        .map_err(CudaBuilderError::FailedToWriteKernelBindings)?;
+    // sort so that the generated file does not depend on the order of directory entries.
+    files.sort();
+
+    let mut found = Found::default();
+    for file in files {
+        let src =
+            fs::read_to_string(&file).map_err(CudaBuilderError::FailedToWriteKernelBindings)?;
+        match syn::parse_file(&src) {
+            Ok(parsed) => find_kernels(&parsed.items, &mut found),
+            Err(e) => println!(
+                "cargo:warning=Failed to parse {} for kernel bindings: {}",
+                file.display(),
+                e
+            ),
+        }
+    }
+    let kernels = found.into_kernels();
+
+    let mut code = String::from(
+        "// Generated by cuda_builder, do not edit.\n\n\
+        #[allow(dead_code)]\n\
+        fn assert_device_copy<T: ::cust::memory::DeviceCopy>(_val: &T) {}\n",
+    );
+    for kernel in &kernels {
+        emit_kernel(kernel, &mut code);
+    }
+    fs::write(out, code).map_err(CudaBuilderError::FailedToWriteKernelBindings)
+}
+
+pub(crate) fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, files)?;
+        } else if matches!(path.extension(), Some(ext) if ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The kernels found in the sources of the gpu crate.
+#[derive(Default)]
+struct Found {
+    kernels: Vec<Kernel>,
+    /// The generic kernels, which only get bindings for their instances.
+    generic: Vec<ItemFn>,
+    instances: Vec<KernelInstance>,
+}
+
+impl Found {
+    /// The kernels followed by the instances of the generic kernels, in the order they are declared.
+    fn into_kernels(self) -> Vec<Kernel> {
+        let mut kernels = self.kernels;
+        for instance in self.instances {
+            let generic = self
+                .generic
+                .iter()
+                .find(|func| func.sig.ident == instance.kernel);
+            match generic {
+                Some(generic) => match instance.signature(generic) {
+                    Some(sig) => kernels.push(kernel(&sig)),
+                    None => println!(
+                        "cargo:warning=`{}` does not have the generic arguments of `{}`, not \
+                        generating bindings for it",
+                        instance.name, instance.kernel
+                    ),
+                },
+                None => println!(
+                    "cargo:warning=Generic kernel `{}` of the instance `{}` not found, not \
+                    generating bindings for it",
+                    instance.kernel, instance.name
+                ),
+            }
+        }
+        kernels
+    }
+}
+
+/// One instance of `kernel_instances!`, `kernel::<Args> as name`, see `cuda_std::kernel_instances`.
+struct KernelInstance {
+    kernel: Ident,
+    args: Vec<GenericArgument>,
+    name: Ident,
+}
+
+impl Parse for KernelInstance {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse::<ExprPath>()?;
+        let segment = match path.path.segments.last() {
+            Some(segment) if path.path.segments.len() == 1 => segment,
+            _ => return Err(syn::Error::new_spanned(path, "expected a generic kernel")),
+        };
+        let args = match &segment.arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter().cloned().collect(),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    segment,
+                    "expected generic arguments",
+                ))
+            }
+        };
+        input.parse::<Token![as]>()?;
+        Ok(Self {
+            kernel: segment.ident.clone(),
+            args,
+            name: input.parse()?,
+        })
+    }
+}
+
+impl KernelInstance {
+    /// The signature of the kernel `kernel_instances!` makes for this instance: the signature of the
+    /// generic kernel with the generic parameters replaced by the arguments as written, like the
+    /// `macro_rules!` macro of the generic kernel does. `None` if the numbers of arguments differ.
+    fn signature(&self, generic: &ItemFn) -> Option<Signature> {
+        let params = &generic.sig.generics.params;
+        if params.len() != self.args.len() {
+            return None;
+        }
+        let substitutions = params
+            .iter()
+            .zip(&self.args)
+            .filter_map(|(param, arg)| match param {
+                GenericParam::Type(ty) => Some((ty.ident.clone(), arg.to_token_stream())),
+                GenericParam::Const(cnst) => Some((cnst.ident.clone(), arg.to_token_stream())),
+                GenericParam::Lifetime(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let substitute = |ty: &Type| -> Option<Type> {
+            syn::parse2(substitute_generics(ty.to_token_stream(), &substitutions)).ok()
+        };
+
+        let mut sig = generic.sig.clone();
+        sig.ident = self.name.clone();
+        sig.generics = Default::default();
+        // the names of the parameters are kept for the bindings, they are not part of the hash.
+        for input in &mut sig.inputs {
+            if let FnArg::Typed(arg) = input {
+                *arg.ty = substitute(&arg.ty)?;
+            }
+        }
+        if let ReturnType::Type(_, ty) = &mut sig.output {
+            **ty = substitute(ty)?;
+        }
+        Some(sig)
+    }
+}
+
+/// Replaces the identifiers of generic parameters in `tokens` with the tokens of their arguments.
+fn substitute_generics(tokens: TokenStream, substitutions: &[(Ident, TokenStream)]) -> TokenStream {
+    let mut out = TokenStream::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => {
+                match substitutions.iter().find(|(name, _)| *name == ident) {
+                    Some((_, arg)) => out.extend(arg.clone()),
+                    None => out.extend([TokenTree::Ident(ident)]),
+                }
+            }
+            TokenTree::Group(group) => {
+                let stream = substitute_generics(group.stream(), substitutions);
+                out.extend([TokenTree::Group(Group::new(group.delimiter(), stream))]);
+            }
+            token => out.extend([token]),
+        }
+    }
+    out
+}
+
+fn find_kernels(items: &[Item], found: &mut Found) {
+    for item in items {
+        match item {
+            Item::Fn(func) => {
+                if !is_kernel(func) {
+                    continue;
+                }
+                if func.sig.generics.params.is_empty() {
+                    found.kernels.push(kernel(&func.sig));
+                } else {
+                    found.generic.push(func.clone());
+                }
+            }
+            Item::Macro(item) if matches!(item.mac.path.segments.last(), Some(s) if s.ident == "kernel_instances") => {
+                match item
+                    .mac
+                    .parse_body_with(Punctuated::<KernelInstance, Token![,]>::parse_terminated)
+                {
+                    Ok(instances) => found.instances.extend(instances),
+                    Err(e) => println!(
+                        "cargo:warning=Failed to parse `kernel_instances!` for kernel bindings: {}",
+                        e
+                    ),
+                }
+            }
+            Item::Mod(module) => {
+                if let Some((_, items)) = &module.content {
+                    find_kernels(items, found);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn kernel(sig: &Signature) -> Kernel {
+    let params = sig
+        .inputs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, arg)| match arg {
+            FnArg::Typed(arg) => Some(Param {
+                name: param_name(&arg.pat, i),
+                kind: param_kind(&arg.ty),
+            }),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) => Some(tokens(ty)),
+        ReturnType::Default => None,
+    };
+    Kernel {
+        name: sig.ident.to_string(),
+        params,
+        output,
+        abi_hash: abi_hash(sig),
+    }
+}
+
+/// The hash of the parameter and return types of a kernel, which must be the same as the one
+/// `#[kernel]` embeds in the PTX: FNV-1a of `name(param,types)->output` with the types as written
+/// but without whitespace. A test pins both to the same hash.
+fn abi_hash(sig: &Signature) -> u64 {
+    let strip = |tokens: String| {
+        tokens
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+    };
+    let params = sig
+        .inputs
+        .iter()
+        .filter_map(|param| match param {
+            FnArg::Typed(ty) => Some(strip(tokens(&ty.ty))),
+            FnArg::Receiver(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) => strip(tokens(ty)),
+        ReturnType::Default => String::new(),
+    };
+    let signature = format!("{}({})->{}", sig.ident, params.join(","), output);
+    signature.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Whether a function is marked with `#[kernel]` or `#[cuda_std::kernel]`.
+pub(crate) fn is_kernel(func: &ItemFn) -> bool {
+    func.attrs
+        .iter()
+        .any(|attr| matches!(attr.path.segments.last(), Some(s) if s.ident == "kernel"))
+}
+
+fn param_name(pat: &Pat, index: usize) -> String {
+    match pat {
+        Pat::Ident(ident) => {
+            let name = ident.ident.to_string();
+            if RESERVED.contains(&name.as_str()) {
+                format!("{}_arg", name)
+            } else {
+                name
+            }
+        }
+        _ => format!("arg{}", index),
+    }
+}
+
+fn param_kind(ty: &Type) -> ParamKind {
+    match ty {
+        Type::Ptr(ptr) => ParamKind::Slice {
+            elem: tokens(&ptr.elem),
+            mutable: ptr.mutability.is_some(),
+            len: false,
+        },
+        Type::Reference(reference) => match &*reference.elem {
+            Type::Slice(slice) => ParamKind::Slice {
+                elem: tokens(&slice.elem),
+                mutable: reference.mutability.is_some(),
+                len: true,
+            },
+            // a `&str` is passed like a `&[u8]`, see `cust::function::KernelParam`.
+            Type::Path(path) if path.qself.is_none() && path.path.is_ident("str") => {
+                ParamKind::Slice {
+                    elem: "u8".to_string(),
+                    mutable: reference.mutability.is_some(),
+                    len: true,
+                }
+            }
+            elem => ParamKind::Box {
+                elem: tokens(elem),
+                mutable: reference.mutability.is_some(),
+            },
+        },
+        Type::Path(path) if is_primitive(path) => ParamKind::Scalar(tokens(ty)),
+        Type::Paren(paren) => param_kind(&paren.elem),
+        _ => ParamKind::Value(tokens(ty)),
+    }
+}
+
+fn is_primitive(path: &TypePath) -> bool {
+    match path.path.get_ident() {
+        Some(ident) if path.qself.is_none() => PRIMITIVES.contains(&ident.to_string().as_str()),
+        _ => false,
+    }
+}
+
+fn mut_str(mutable: bool) -> &'static str {
+    if mutable {
+        "mut "
+    } else {
+        ""
+    }
+}
+
+fn tokens(ty: &Type) -> String {
+    ty.to_token_stream().to_string()
+}
+
+fn emit_kernel(kernel: &Kernel, code: &mut String) {
+    let name = &kernel.name;
+    // writing to a String cannot fail.
+    let _ = writeln!(
+        code,
+        "\n/// Launches the `{name}` kernel from `module` on `stream`.",
+    );
+    if kernel.output.is_some() {
+        let _ = writeln!(
+            code,
+            "///\n\
+            /// Returns the buffer of the `output_len` values returned by the threads, which are only\n\
+            /// written once the kernel has run.",
+        );
+    }
+    let _ = writeln!(
+        code,
+        "///\n\
+        /// # Safety\n\
+        ///\n\
+        /// The kernel must be safe to run with the given arguments, the same as with `launch!`.",
+    );
+    if kernel.output.is_some() {
+        let _ = writeln!(
+            code,
+            "/// `config` must have at least `output_len` threads in total, the values of the others are\n\
+            /// uninitialized.",
+        );
+    }
+    let _ = writeln!(
+        code,
+        "#[allow(clippy::too_many_arguments)]\n\
+        pub unsafe fn {name}(\n    \
+            module: &::cust::module::Module,\n    \
+            stream: &::cust::stream::Stream,\n    \
+            config: ::cust::function::LaunchConfig,",
+    );
+    if kernel.output.is_some() {
+        let _ = writeln!(code, "    output_len: usize,");
+    }
+    for param in &kernel.params {
+        let ty = match &param.kind {
+            ParamKind::Scalar(ty) => ty.clone(),
+            ParamKind::Value(ty) => format!("&{}", ty),
+            ParamKind::Slice { elem, mutable, .. } => {
+                format!(
+                    "&{}::cust::memory::DeviceSlice<{}>",
+                    mut_str(*mutable),
+                    elem
+                )
+            }
+            ParamKind::Box { elem, mutable } => {
+                format!("&{}::cust::memory::DeviceBox<{}>", mut_str(*mutable), elem)
+            }
+        };
+        let _ = writeln!(code, "    {}: {},", param.name, ty);
+    }
+    let ret = match &kernel.output {
+        Some(ty) => format!("::cust::memory::DeviceBuffer<{}>", ty),
+        None => "()".to_string(),
+    };
+    let _ = writeln!(
+        code,
+        ") -> ::cust::error::CudaResult<{ret}> {{\n    \
+            let __function = module.get_function_checked(\"{name}\", {hash:#x})?;",
+        hash = kernel.abi_hash,
+    );
+
+    let mut args = Vec::new();
+    for Param { name, kind } in &kernel.params {
+        match kind {
+            ParamKind::Scalar(_) => args.push(format!("&{}", name)),
+            ParamKind::Value(_) => {
+                let _ = writeln!(code, "    assert_device_copy({});", name);
+                args.push(name.clone());
+            }
+            ParamKind::Slice { mutable, len, .. } => {
+                if *len {
+                    let _ = writeln!(code, "    let {name}_len = {name}.len();");
+                }
+                let ptr = if *mutable { "as_mut_ptr" } else { "as_ptr" };
+                let _ = writeln!(code, "    let {name} = {name}.{ptr}();");
+                args.push(format!("&{}", name));
+                if *len {
+                    args.push(format!("&{}_len", name));
+                }
+            }
+            ParamKind::Box { mutable, .. } => {
+                let ptr = if *mutable { "as_device_ptr" } else { "as_ptr" };
+                let _ = writeln!(code, "    let {name} = {name}.{ptr}();");
+                args.push(format!("&{}", name));
+            }
+        }
+    }
+    if let Some(ty) = &kernel.output {
+        // the output slice is the last parameter of the kernel.
+        let _ = writeln!(
+            code,
+            "    let mut __output = ::cust::memory::DeviceBuffer::<{ty}>::uninitialized(output_len)?;\n    \
+                let __output_ptr = __output.as_mut_ptr();",
+        );
+        args.push("&__output_ptr".to_string());
+        args.push("&output_len".to_string());
+    }
+
+    let _ = writeln!(
+        code,
+        "    stream.launch(\n        \
+            &__function,\n        \
+            config.grid,\n        \
+            config.block,\n        \
+            config.shared_mem_bytes,\n        \
+            &["
+    );
+    for arg in args {
+        let _ = writeln!(
+            code,
+            "            {} as *const _ as *mut ::std::ffi::c_void,",
+            arg
+        );
+    }
+    if kernel.output.is_some() {
+        let _ = writeln!(code, "        ],\n    )?;\n    Ok(__output)\n}}");
+    } else {
+        let _ = writeln!(code, "        ],\n    )\n}}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the same signature and hash are pinned in the tests of `cuda_std_macros`, which embeds it.
+    #[test]
+    fn abi_hash_matches_kernel_macro() {
+        let func: ItemFn =
+            syn::parse_str("pub unsafe fn add(a: &[f32], b: * mut f32, n: usize) {}").unwrap();
+        assert_eq!(abi_hash(&func.sig), 0xeaa6_1b1f_16b8_326c);
+    }
+
+    #[test]
+    fn passes_slices_and_strs_as_pointer_and_length() {
+        let file: syn::File = syn::parse_str(
+            "#[kernel] pub unsafe fn log(name: &str, values: &mut [f32], scale: f32) {}",
+        )
+        .unwrap();
+        let mut found = Found::default();
+        find_kernels(&file.items, &mut found);
+        let mut code = String::new();
+        emit_kernel(&found.kernels[0], &mut code);
+
+        assert!(code.contains("    name: &::cust::memory::DeviceSlice<u8>,\n"));
+        assert!(code.contains("    values: &mut ::cust::memory::DeviceSlice<f32>,\n"));
+        assert!(code.contains("    scale: f32,\n"));
+        assert!(code.contains("    let name_len = name.len();\n    let name = name.as_ptr();\n"));
+        assert!(code.contains("    let values = values.as_mut_ptr();\n"));
+        let args = code
+            .lines()
+            .filter_map(|line| {
+                line.trim()
+                    .strip_suffix(" as *const _ as *mut ::std::ffi::c_void,")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            args,
+            ["&name", "&name_len", "&values", "&values_len", "&scale"]
+        );
+    }
+
+    #[test]
+    fn binds_kernel_instances() {
+        let file: syn::File = syn::parse_str(
+            "kernel_instances! { map::<Scale, 4> as map_scale, missing::<u32> as nothing }
+             mod kernels {
+                 #[kernel]
+                 pub unsafe fn map<F: UnaryOp, const N: usize>(
+                     input: &[f32], op: F, table: [f32; N], output: *mut f32) -> [f32; N] {}
+             }",
+        )
+        .unwrap();
+        let mut found = Found::default();
+        find_kernels(&file.items, &mut found);
+        assert!(found.kernels.is_empty());
+        let kernels = found.into_kernels();
+        assert_eq!(kernels.len(), 1);
+
+        let kernel = &kernels[0];
+        assert_eq!(kernel.name, "map_scale");
+        let names = kernel
+            .params
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["input", "op", "table", "output"]);
+        assert!(matches!(&kernel.params[1].kind, ParamKind::Value(ty) if ty == "Scale"));
+        assert!(matches!(&kernel.params[2].kind, ParamKind::Value(ty) if ty == "[f32 ; 4]"));
+        assert_eq!(kernel.output.as_deref(), Some("[f32 ; 4]"));
+        // the hash of the kernel the macro of the generic kernel makes.
+        let instance: ItemFn = syn::parse_str(
+            "unsafe fn map_scale(__arg0: &[f32], __arg1: Scale, __arg2: [f32; 4], \
+             __arg3: *mut f32) -> [f32; 4] {}",
+        )
+        .unwrap();
+        assert_eq!(kernel.abi_hash, abi_hash(&instance.sig));
+    }
+}
@@ -0,0 +1,177 @@
+//! Checking that built PTX can be JIT compiled by the driver it is deployed with, see
+//! [`CudaBuilder::validate_for_driver`](crate::CudaBuilder::validate_for_driver).
+//!
+//! A driver can only JIT compile PTX whose ISA version is at most the one of the CUDA release it
+//! shipped with, loading newer PTX fails with `CUDA_ERROR_UNSUPPORTED_PTX_VERSION` (or
+//! `CUDA_ERROR_INVALID_PTX` with some drivers). Unlike cubins, PTX is not covered by CUDA's minor
+//! version compatibility, so PTX built with the libnvvm of CUDA 12.4 does not load with a CUDA 12.2
+//! driver.
+
+use crate::CudaBuilderError;
+use std::{fs, path::Path};
+
+/// The Linux driver branch of every CUDA release, with the PTX ISA version it introduced. Windows
+/// drivers use a different numbering, CUDA versions can be given instead.
+const RELEASES: &[(u32, (u32, u32), (u32, u32))] = &[
+    // (driver branch, CUDA version, PTX ISA version)
+    (410, (10, 0), (6, 3)),
+    (418, (10, 1), (6, 4)),
+    (440, (10, 2), (6, 5)),
+    (450, (11, 0), (7, 0)),
+    (455, (11, 1), (7, 1)),
+    (460, (11, 2), (7, 2)),
+    (465, (11, 3), (7, 3)),
+    (470, (11, 4), (7, 4)),
+    (495, (11, 5), (7, 5)),
+    (510, (11, 6), (7, 6)),
+    (515, (11, 7), (7, 7)),
+    (520, (11, 8), (7, 8)),
+    (525, (12, 0), (8, 0)),
+    (530, (12, 1), (8, 1)),
+    (535, (12, 2), (8, 2)),
+    (545, (12, 3), (8, 3)),
+    (550, (12, 4), (8, 4)),
+    (555, (12, 5), (8, 5)),
+    (560, (12, 6), (8, 5)),
+    (570, (12, 8), (8, 7)),
+    (575, (12, 9), (8, 8)),
+];
+
+/// The newest PTX ISA version the driver `version` can JIT compile, with the CUDA version it
+/// corresponds to. `version` is a driver version such as `535`, `535.x` or `535.104.05`, or a CUDA
+/// version such as `12.2`.
+fn max_ptx_version(version: &str) -> Result<((u32, u32), (u32, u32)), CudaBuilderError> {
+    let invalid = || CudaBuilderError::InvalidDriverVersion(version.to_string());
+    let mut parts = version.trim().split('.');
+    let major = parts
+        .next()
+        .and_then(|major| major.parse::<u32>().ok())
+        .ok_or_else(invalid)?;
+    // for CUDA versions, `x` and a missing minor version mean any minor version, so the oldest one
+    // is assumed.
+    let minor = match parts.next() {
+        None | Some("x") | Some("*") => 0,
+        Some(minor) => minor.parse::<u32>().map_err(|_| invalid())?,
+    };
+
+    // every driver of a branch supports the CUDA version of the branch.
+    let release = if major < 100 {
+        RELEASES
+            .iter()
+            .rev()
+            .find(|(_, cuda, _)| *cuda <= (major, minor))
+    } else {
+        RELEASES
+            .iter()
+            .rev()
+            .find(|(branch, _, _)| *branch <= major)
+    };
+    release
+        .map(|(_, cuda, ptx)| (*cuda, *ptx))
+        .ok_or_else(invalid)
+}
+
+/// The ISA version of the `.version` directive of PTX, such as `(7, 5)`.
+fn ptx_version(ptx: &str) -> Option<(u32, u32)> {
+    let version = ptx
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(".version"))?;
+    let (major, minor) = version.trim().split_once('.')?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Prints a cargo warning if the PTX file at `ptx` is too new for the driver `version`.
+pub(crate) fn validate(ptx: &Path, version: &str) -> Result<(), CudaBuilderError> {
+    let (cuda, max) = max_ptx_version(version)?;
+    let ptx_src = fs::read_to_string(ptx).unwrap_or_default();
+    let found = match ptx_version(&ptx_src) {
+        Some(found) => found,
+        None => return Ok(()),
+    };
+    if found > max {
+        println!(
+            "cargo:warning={} uses PTX ISA {}.{}, but drivers for {} (CUDA {}.{}) can only JIT compile PTX up to {}.{}. \
+             Loading it will fail with `UnsupportedPtxVersion`, build it with the libnvvm of CUDA {}.{} or older, \
+             or ship cubins compiled ahead of time.",
+            ptx.display(),
+            found.0,
+            found.1,
+            version,
+            cuda.0,
+            cuda.1,
+            max.0,
+            max.1,
+            cuda.0,
+            cuda.1,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_versions_to_ptx() {
+        let table: &[(&str, (u32, u32), (u32, u32))] = &[
+            // driver branches, the first one of a release and the last one before the next.
+            ("410", (10, 0), (6, 3)),
+            ("417.99", (10, 0), (6, 3)),
+            ("418", (10, 1), (6, 4)),
+            ("535", (12, 2), (8, 2)),
+            ("535.104.05", (12, 2), (8, 2)),
+            ("544.x", (12, 2), (8, 2)),
+            ("545", (12, 3), (8, 3)),
+            ("560", (12, 6), (8, 5)),
+            ("569", (12, 6), (8, 5)),
+            ("575.51.03", (12, 9), (8, 8)),
+            // newer drivers than the table can JIT compile at least its newest PTX.
+            ("999", (12, 9), (8, 8)),
+            // CUDA versions, `x` and a missing minor version are the oldest minor version.
+            ("10.0", (10, 0), (6, 3)),
+            ("11.8", (11, 8), (7, 8)),
+            ("12", (12, 0), (8, 0)),
+            ("12.x", (12, 0), (8, 0)),
+            ("12.*", (12, 0), (8, 0)),
+            (" 12.2\n", (12, 2), (8, 2)),
+            // 12.7 was never released.
+            ("12.7", (12, 6), (8, 5)),
+            ("13.0", (12, 9), (8, 8)),
+        ];
+        for &(version, cuda, ptx) in table {
+            assert_eq!(
+                max_ptx_version(version).unwrap(),
+                (cuda, ptx),
+                "version {:?}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        for version in ["", "x", "abc", "12.y", ".5", "9.2", "409", "409.x", "-1"] {
+            assert!(
+                matches!(
+                    max_ptx_version(version),
+                    Err(CudaBuilderError::InvalidDriverVersion(v)) if v == version
+                ),
+                "version {:?}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn reads_ptx_versions() {
+        let ptx = "//\n// Generated by NVIDIA NVVM Compiler\n//\n\n.version 7.5\n.target sm_61\n";
+        assert_eq!(ptx_version(ptx), Some((7, 5)));
+        assert_eq!(ptx_version("  .version 8.0  \n"), Some((8, 0)));
+        assert_eq!(ptx_version(".version 10.12"), Some((10, 12)));
+        assert_eq!(ptx_version(".target sm_61\n"), None);
+        assert_eq!(ptx_version(".version 8\n"), None);
+        assert_eq!(ptx_version(".version 8.x\n"), None);
+        assert_eq!(ptx_version(""), None);
+    }
+}
@@ -1,4 +1,18 @@
 //! Utility crate for easily building CUDA crates using rustc_codegen_nvvm. Derived from rust-gpu's spirv_builder.
+//!
+//! # Building without CUDA
+//!
+//! Building PTX only needs libnvvm and libdevice from the CUDA toolkit, not the driver, the rest of
+//! the toolkit or a GPU. On machines without a CUDA installation, such as CI runners producing PTX
+//! artifacts, set `CUDA_NVVM_ROOT` to a directory containing `nvvm/`, for example an extracted
+//! `cuda_nvcc` archive from <https://developer.download.nvidia.com/compute/cuda/redist/>. It is used
+//! both when building the codegen and when running it, and also provides `ptxas` for
+//! [`CudaBuilder::gpu_report`].
+
+mod bindings;
+mod driver;
+mod lints;
+mod tuning;
 
 pub use nvvm::*;
 use serde::Deserialize;
@@ -9,6 +23,7 @@ use std::{
     fmt,
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    time::{Duration, SystemTime},
 };
 
 #[derive(Debug)]
@@ -17,6 +32,10 @@ pub enum CudaBuilderError {
     CratePathDoesntExist(PathBuf),
     FailedToCopyPtxFile(std::io::Error),
     BuildFailed,
+    CudaDevrtNotFound,
+    FailedToWriteKernelBindings(std::io::Error),
+    InvalidTuningProfile(String),
+    InvalidDriverVersion(String),
 }
 
 impl fmt::Display for CudaBuilderError {
@@ -26,9 +45,21 @@ impl fmt::Display for CudaBuilderError {
                 write!(f, "Crate path {} does not exist", path.display())
             }
             CudaBuilderError::BuildFailed => f.write_str("Build failed"),
+            CudaBuilderError::CudaDevrtNotFound => {
+                f.write_str("Could not find the CUDA device runtime library (cudadevrt)")
+            }
             CudaBuilderError::FailedToCopyPtxFile(err) => {
                 f.write_str(&format!("Failed to copy PTX file: {:?}", err))
             }
+            CudaBuilderError::FailedToWriteKernelBindings(err) => {
+                f.write_str(&format!("Failed to write kernel bindings: {:?}", err))
+            }
+            CudaBuilderError::InvalidTuningProfile(err) => {
+                f.write_str(&format!("Invalid tuning profile: {}", err))
+            }
+            CudaBuilderError::InvalidDriverVersion(version) => {
+                write!(f, "Unknown driver version {:?}", version)
+            }
         }
     }
 }
@@ -51,10 +82,18 @@ pub struct CudaBuilder {
     /// An optional path to copy the final ptx file to.
     pub ptx_file_copy_path: Option<PathBuf>,
 
-    /// Whether to generate debug line number info.
-    /// This defaults to `true`, but nothing will be generated
-    /// if the gpu crate is built as release.
+    /// Whether to generate debug line number info, `.file` and `.loc` directives in the PTX
+    /// which map instructions to Rust source lines, so that profilers such as Nsight Compute can
+    /// show the source next to the SASS. This is also done for release builds since it does not
+    /// change the generated code, only the size of the PTX file.
+    ///
+    /// `true` by default.
     pub generate_line_info: bool,
+    /// Whether to generate full debug info (`-g` for nvvm), which allows stepping through kernels
+    /// and inspecting variables in cuda-gdb and Nsight, see [`debug`](Self::debug).
+    ///
+    /// `false` by default.
+    pub debug: bool,
     /// Whether to run libnvvm optimizations. This defaults to `false`
     /// but will be set to `true` if release is specified.
     pub nvvm_opts: bool,
@@ -117,6 +156,61 @@ pub struct CudaBuilder {
     ///
     /// `true` by default.
     pub override_libm: bool,
+    /// Whether the gpu crate launches kernels from other kernels (dynamic parallelism).
+    ///
+    /// PTX using dynamic parallelism references functions from the CUDA device runtime, therefore it must
+    /// be linked against `libcudadevrt` when loaded (for example with `cust::link::Linker::add_library`)
+    /// instead of being loaded directly. When this is enabled, the path to the library is exposed to the
+    /// crate being built through the `CUDA_DEVRT_PATH` environment variable, so it can be retrieved with
+    /// `env!("CUDA_DEVRT_PATH")`.
+    ///
+    /// This also makes the codegen generate relocatable device code (`-Cllvm-args=--rdc`): calls to the
+    /// device runtime are allowed, and the `#[no_mangle]` functions and statics of the crate keep their
+    /// linkage so that other PTX linked with this one can use them.
+    ///
+    /// `false` by default.
+    pub dynamic_parallelism: bool,
+    /// An optional path to write typed host-side launch functions for every `#[kernel]` in the
+    /// gpu crate to, see [`generate_kernel_bindings`](Self::generate_kernel_bindings).
+    pub kernel_bindings_path: Option<PathBuf>,
+    /// Whether to warn about kernel parameters which are never used and kernels which are never
+    /// launched by the crate being built, see [`lint_kernels`](Self::lint_kernels).
+    ///
+    /// `false` by default.
+    pub lint_kernels: bool,
+    /// Whether to write a report of the resources used by every kernel next to the PTX file and
+    /// warn about regressions, see [`gpu_report`](Self::gpu_report).
+    ///
+    /// `false` by default.
+    pub gpu_report: bool,
+    /// Whether to generate PTX with the NVPTX backend of the LLVM the codegen is built with
+    /// instead of libnvvm, see [`nvptx_backend`](Self::nvptx_backend).
+    ///
+    /// `false` by default.
+    pub nvptx_backend: bool,
+    /// An optional TOML file with register and launch bound hints for kernels, see
+    /// [`tuning_profile`](Self::tuning_profile).
+    pub tuning_profile: Option<PathBuf>,
+    /// Whether to remap the paths of the gpu crate, the cargo registry and the standard library
+    /// sources in the PTX to fixed paths, see [`reproducible`](Self::reproducible).
+    ///
+    /// `false` by default.
+    pub reproducible: bool,
+    /// Path prefixes to replace in the PTX, like rustc's `--remap-path-prefix`.
+    pub remap_path_prefixes: Vec<(PathBuf, String)>,
+    /// An optional driver version the PTX is deployed with, see
+    /// [`validate_for_driver`](Self::validate_for_driver).
+    pub target_driver: Option<String>,
+    /// Hand-written PTX files to link into the PTX, see [`link_ptx`](Self::link_ptx).
+    pub link_ptx: Vec<PathBuf>,
+    /// Hand-written LLVM IR files to link into the gpu crate, see
+    /// [`link_llvm_ir`](Self::link_llvm_ir).
+    pub link_llvm_ir: Vec<PathBuf>,
+    /// Whether to warn about shared memory accesses which likely cause bank conflicts, see
+    /// [`warn_bank_conflicts`](Self::warn_bank_conflicts).
+    ///
+    /// `false` by default.
+    pub warn_bank_conflicts: bool,
 }
 
 impl CudaBuilder {
@@ -127,6 +221,7 @@ impl CudaBuilder {
             nvptx_32: false,
             ptx_file_copy_path: None,
             generate_line_info: true,
+            debug: false,
             nvvm_opts: true,
             arch: NvvmArch::Compute61,
             ftz: false,
@@ -136,6 +231,18 @@ impl CudaBuilder {
             emit: None,
             optix: false,
             override_libm: true,
+            dynamic_parallelism: false,
+            kernel_bindings_path: None,
+            lint_kernels: false,
+            gpu_report: false,
+            nvptx_backend: false,
+            tuning_profile: None,
+            reproducible: false,
+            remap_path_prefixes: Vec::new(),
+            target_driver: None,
+            link_ptx: Vec::new(),
+            link_llvm_ir: Vec::new(),
+            warn_bank_conflicts: false,
         }
     }
 
@@ -153,14 +260,26 @@ impl CudaBuilder {
         self
     }
 
-    /// Whether to generate debug line number info.
-    /// This defaults to `true`, but nothing will be generated
-    /// if the gpu crate is built as release.
+    /// Whether to generate debug line number info, `.file` and `.loc` directives in the PTX
+    /// mapping instructions to Rust source lines.
     pub fn generate_line_info(mut self, generate_line_info: bool) -> Self {
         self.generate_line_info = generate_line_info;
         self
     }
 
+    /// Whether to generate full debug info for the kernels, `.loc` directives like
+    /// [`generate_line_info`](Self::generate_line_info) plus the locations of variables and the
+    /// scopes of inlined functions, which allows stepping through kernels and inspecting variables in
+    /// cuda-gdb and Nsight.
+    ///
+    /// libnvvm can only generate debug info without optimizations, so this disables
+    /// [`nvvm_opts`](Self::nvvm_opts) and the kernels are much slower. Use
+    /// [`generate_line_info`](Self::generate_line_info) for profiling instead.
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
     /// Whether to run libnvvm optimizations. This defaults to `false`
     /// but will be set to `true` if release is specified.
     pub fn nvvm_opts(mut self, nvvm_opts: bool) -> Self {
@@ -256,20 +375,357 @@ impl CudaBuilder {
         self
     }
 
+    /// Whether the gpu crate launches kernels from other kernels (dynamic parallelism).
+    ///
+    /// PTX using dynamic parallelism must be linked against `libcudadevrt` when loaded, the path
+    /// to the library is exposed through the `CUDA_DEVRT_PATH` environment variable. The codegen
+    /// generates relocatable device code (`-Cllvm-args=--rdc`) for it.
+    pub fn dynamic_parallelism(mut self, dynamic_parallelism: bool) -> Self {
+        self.dynamic_parallelism = dynamic_parallelism;
+        self
+    }
+
+    /// Generates a Rust file at `path` containing a launch function for every `#[kernel]` in the gpu
+    /// crate, with the same name and parameters mapped to their host-side equivalent. Launching a
+    /// kernel with mismatched arguments is then a compile error instead of undefined behavior.
+    ///
+    /// The file is meant to be included into a module which has the types used by the kernels in
+    /// scope, usually by depending on the gpu crate on the host:
+    ///
+    /// ```ignore
+    /// // build.rs
+    /// let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("kernels.rs");
+    /// CudaBuilder::new("../gpu/my_gpu_crate")
+    ///     .generate_kernel_bindings(out)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // main.rs
+    /// pub mod kernels {
+    ///     use my_gpu_crate::*;
+    ///     include!(concat!(env!("OUT_DIR"), "/kernels.rs"));
+    /// }
+    ///
+    /// unsafe {
+    ///     kernels::render(&module, &stream, LaunchConfig::new(blocks, threads, 0), &mut fb, &view)?;
+    /// }
+    /// ```
+    ///
+    /// Kernel parameters are mapped as follows:
+    /// - primitives are taken by value.
+    /// - raw pointers and slices are taken as `&DeviceSlice<T>` (`&mut` for mutable ones), and `&str`
+    ///   as a `&DeviceSlice<u8>` of UTF-8.
+    /// - references are taken as `&DeviceBox<T>` (`&mut` for mutable ones).
+    /// - anything else is taken by reference and must implement `DeviceCopy`.
+    ///
+    /// Generic kernels have no launch function themselves, every instance declared with
+    /// `kernel_instances!` gets one with the name of the instance and the generic arguments
+    /// substituted in the parameters.
+    ///
+    /// The launch functions load kernels with `Module::get_function_checked`, which fails with
+    /// `CudaError::KernelAbiMismatch` if the PTX was built from a kernel with different parameter or
+    /// return types than the bindings, for example when stale PTX is loaded.
+    pub fn generate_kernel_bindings(mut self, path: impl AsRef<Path>) -> Self {
+        self.kernel_bindings_path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Emits cargo warnings for `#[kernel]` parameters which are never used in the body of the
+    /// kernel, and for kernels whose name never appears in the crate being built (the host crate
+    /// whose build.rs runs the builder) or elsewhere in the gpu crate. Both make the PTX larger
+    /// than needed, unused parameters also take up parameter space on every launch.
+    ///
+    /// The checks are textual, so they only catch kernels and parameters which are definitely
+    /// unused. Parameters starting with an underscore are never reported.
+    pub fn lint_kernels(mut self, lint_kernels: bool) -> Self {
+        self.lint_kernels = lint_kernels;
+        self
+    }
+
+    /// Writes a report with the PTX size, registers, shared/constant/local memory and spills of
+    /// every kernel next to the built PTX file, with a `.gpu-report` extension (the same as
+    /// `--emit=gpu-report` for the codegen). Registers, memory and spills are computed by running
+    /// `ptxas` from the CUDA toolkit for [`arch`](Self::arch), only PTX sizes are reported if it
+    /// is not found.
+    ///
+    /// Every value which grew since the previous build is reported as a cargo warning, so resource
+    /// regressions such as new register spills are noticed right away.
+    pub fn gpu_report(mut self, gpu_report: bool) -> Self {
+        self.gpu_report = gpu_report;
+        self
+    }
+
+    /// Generates PTX with LLVM's NVPTX backend instead of libnvvm. The modules of the gpu crate are
+    /// linked and optimized by LLVM itself, which avoids libnvvm bugs and its restrictions on the
+    /// LLVM IR it accepts, and makes it possible to test whether a miscompilation comes from
+    /// libnvvm or from the codegen.
+    ///
+    /// [`arch`](Self::arch), [`ftz`](Self::ftz), [`fast_sqrt`](Self::fast_sqrt),
+    /// [`fast_div`](Self::fast_div) and [`fma_contraction`](Self::fma_contraction) are passed to
+    /// LLVM's equivalent options. libdevice is linked if the CUDA toolkit is found, otherwise
+    /// kernels calling libdevice functions (such as libm functions overriden with
+    /// [`override_libm`](Self::override_libm)) fail to build. Note that the codegen itself still
+    /// links to libnvvm.
+    pub fn nvptx_backend(mut self, nvptx_backend: bool) -> Self {
+        self.nvptx_backend = nvptx_backend;
+        self
+    }
+
+    /// Applies the register and launch bound hints of the TOML file at `path` to the kernels named in
+    /// it, like `#[kernel(max_registers = ..., max_threads = ..., min_blocks = ...)]` but without
+    /// changing the gpu crate. Every hint in the profile replaces the same hint given to `#[kernel]`,
+    /// the other hints of `#[kernel]` are kept:
+    ///
+    /// ```toml
+    /// [kernels.render]
+    /// max_threads = 256
+    /// min_blocks = 2
+    /// ```
+    ///
+    /// This closes the loop between profiling and building: `cust::tune::Tuner::profile_file`
+    /// records the block size that was fastest for every tuned kernel as its `max_threads`, so that
+    /// the next build lets ptxas allocate registers for exactly that block size. Values can also be
+    /// filled in by hand from a profiler, for example `max_registers` from the register counts and
+    /// spills reported by Nsight Compute. The profile not existing is not an error, the crate is then
+    /// built without hints.
+    pub fn tuning_profile(mut self, path: impl AsRef<Path>) -> Self {
+        self.tuning_profile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Makes the PTX independent of where the sources are on the building machine, so that builds
+    /// of the same sources with the same toolchain produce byte-identical PTX, for caching artifacts
+    /// and verifying GPU binaries.
+    ///
+    /// The PTX is otherwise already deterministic, but [line info](Self::generate_line_info) embeds
+    /// the paths of source files. This remaps the directory of the gpu crate to `.`, the cargo home
+    /// (with the sources of dependencies from crates.io) to `/cargo` and the sysroot (with the
+    /// sources of `core` and `alloc`) to `/rustc`, like the paths in the standard library shipped
+    /// by rustup. Other path dependencies can be remapped with
+    /// [`remap_path_prefix`](Self::remap_path_prefix).
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Replaces the path prefix `from` with `to` in the paths embedded in the PTX, like rustc's
+    /// `--remap-path-prefix`.
+    pub fn remap_path_prefix(mut self, from: impl AsRef<Path>, to: impl Into<String>) -> Self {
+        self.remap_path_prefixes
+            .push((from.as_ref().to_path_buf(), to.into()));
+        self
+    }
+
+    /// Checks that the built PTX can be JIT compiled by the driver `version` (or newer) which it is
+    /// deployed with, and emits a cargo warning if its PTX ISA version is too new for the driver.
+    /// Otherwise this would only be found out in production, when loading the PTX fails with
+    /// `UnsupportedPtxVersion`.
+    ///
+    /// The PTX ISA version is decided by the libnvvm the gpu crate is built with, not by
+    /// [`arch`](Self::arch), so this usually happens when the build machine has a newer CUDA
+    /// toolkit than the deployment machines have drivers. `version` is a Linux driver version such
+    /// as `"535"`, `"535.x"` or `"535.104.05"`, or a CUDA version such as `"12.2"`, which also works
+    /// for Windows drivers.
+    pub fn validate_for_driver(mut self, version: impl Into<String>) -> Self {
+        self.target_driver = Some(version.into());
+        self
+    }
+
+    /// Links a hand-written `.ptx` file into the PTX of the gpu crate, like the `cc` crate does for
+    /// C code in host crates. Its functions can be called from kernels by declaring them in an
+    /// `extern "C"` block:
+    ///
+    /// ```text
+    /// // fast_math.ptx
+    /// .visible .func (.param .f32 ret) fast_exp(.param .f32 x) { ... }
+    ///
+    /// // in the gpu crate
+    /// extern "C" {
+    ///     fn fast_exp(x: f32) -> f32;
+    /// }
+    /// ```
+    ///
+    /// The header directives of the file (`.version`, `.target` and `.address_size`) are dropped,
+    /// so it must not use instructions which need a newer PTX ISA or arch than the gpu crate is
+    /// built for. Every declaration is checked against the definition in the file, a declaration
+    /// whose parameters or return value have different sizes fails the build. The same is done
+    /// for functions defined with `global_asm!` in the gpu crate.
+    ///
+    /// The path must not contain spaces.
+    pub fn link_ptx(mut self, path: impl AsRef<Path>) -> Self {
+        self.link_ptx.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Links a hand-written LLVM IR (`.ll`) file into the gpu crate before it is optimized, so that
+    /// its functions can be inlined into the kernels which call them. Functions are declared in an
+    /// `extern "C"` block like with [`link_ptx`](Self::link_ptx), but unlike PTX the declarations
+    /// are not checked, LLVM only requires the types to match when linking.
+    ///
+    /// The IR must be accepted by libnvvm, which is based on an older LLVM than current clang, so
+    /// it is best written by hand or generated by the same CUDA version. The path must not contain
+    /// spaces.
+    pub fn link_llvm_ir(mut self, path: impl AsRef<Path>) -> Self {
+        self.link_llvm_ir.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Warns about loads and stores of shared memory statics (such as `shared_array!`) which
+    /// likely cause bank conflicts, for example a `[[f32; 32]; 32]` tile read by column, or a
+    /// `[f64; N]` array read as halves of 4 bytes with `thread_idx_x()` as the index. Conflicts
+    /// make a warp access shared memory several times in a row and only show up in a profiler.
+    ///
+    /// The codegen works out how far apart the addresses of neighbouring lanes are from how
+    /// `thread_idx_x()` is used in the index, assuming the x dimension of blocks is a multiple of
+    /// 32. Indices it cannot follow, such as values loaded from global memory, are skipped, so
+    /// this can miss conflicts but warns with the stride and the source line of every access it
+    /// finds. The warnings are shown as cargo warnings.
+    pub fn warn_bank_conflicts(mut self, warn_bank_conflicts: bool) -> Self {
+        self.warn_bank_conflicts = warn_bank_conflicts;
+        self
+    }
+
     /// Runs rustc to build the codegen and codegens the gpu crate, returning the path of the final
     /// ptx file. If [`ptx_file_copy_path`](Self::ptx_file_copy_path) is set, this returns the copied path.
     pub fn build(self) -> Result<PathBuf, CudaBuilderError> {
         println!("cargo:rerun-if-changed={}", self.path_to_crate.display());
-        let path = invoke_rustc(&self)?;
-        if let Some(copy_path) = self.ptx_file_copy_path {
-            std::fs::copy(path, &copy_path).map_err(CudaBuilderError::FailedToCopyPtxFile)?;
-            Ok(copy_path)
+        println!("cargo:rerun-if-env-changed=CUDA_NVVM_ROOT");
+        let path = self.build_ptx()?;
+        if self.lint_kernels {
+            if let Some(host) = env::var_os("CARGO_MANIFEST_DIR") {
+                lints::check(&self.path_to_crate, Path::new(&host));
+            }
+        }
+        if self.dynamic_parallelism {
+            let devrt = find_cudadevrt().ok_or(CudaBuilderError::CudaDevrtNotFound)?;
+            println!("cargo:rustc-env=CUDA_DEVRT_PATH={}", devrt.display());
+        }
+        Ok(path)
+    }
+
+    /// Builds the gpu crate like [`build`](Self::build), then keeps rebuilding it on a background
+    /// thread every time one of its files changes, calling `on_build` with the result of every
+    /// rebuild. Returns the result of the first build.
+    ///
+    /// This is meant to be called by the host application at runtime (not in build.rs) during
+    /// development, together with [`copy_to`](Self::copy_to) and `cust::module::HotReloadModule`
+    /// loading the copied PTX file, so that changes to kernels are picked up without restarting
+    /// the application:
+    ///
+    /// ```ignore
+    /// CudaBuilder::new("../gpu/my_gpu_crate")
+    ///     .copy_to("kernels.ptx")
+    ///     .watch(|res| {
+    ///         if let Err(e) = res {
+    ///             eprintln!("failed to rebuild kernels: {}", e);
+    ///         }
+    ///     })?;
+    ///
+    /// let mut module = HotReloadModule::new("kernels.ptx")?;
+    /// loop {
+    ///     module.reload_if_changed()?;
+    ///     let render = module.get_function("render")?;
+    ///     // ... launch `render` ...
+    /// }
+    /// ```
+    ///
+    /// The copied PTX file is replaced atomically, so it is never loaded half-written.
+    pub fn watch(
+        self,
+        mut on_build: impl FnMut(Result<PathBuf, CudaBuilderError>) + Send + 'static,
+    ) -> Result<PathBuf, CudaBuilderError> {
+        let first = self.build_ptx()?;
+        // fingerprint after building, cargo may write files such as Cargo.lock into the crate.
+        let mut fingerprint = source_fingerprint(&self.path_to_crate);
+        std::thread::spawn(move || loop {
+            std::thread::sleep(WATCH_INTERVAL);
+            if source_fingerprint(&self.path_to_crate) != fingerprint {
+                on_build(self.build_ptx());
+                fingerprint = source_fingerprint(&self.path_to_crate);
+            }
+        });
+        Ok(first)
+    }
+
+    fn build_ptx(&self) -> Result<PathBuf, CudaBuilderError> {
+        let path = invoke_rustc(self)?;
+        if self.gpu_report {
+            warn_gpu_report_regressions(&path);
+        }
+        if self.warn_bank_conflicts {
+            warn_bank_conflicts(&path);
+        }
+        if let Some(version) = &self.target_driver {
+            driver::validate(&path, version)?;
+        }
+        if let Some(bindings_path) = &self.kernel_bindings_path {
+            bindings::generate(&self.path_to_crate, bindings_path)?;
+        }
+        if let Some(copy_path) = &self.ptx_file_copy_path {
+            // copy to a temporary file and rename it so that anything watching the file never
+            // sees a partially written file.
+            let mut tmp = copy_path.clone().into_os_string();
+            tmp.push(".tmp");
+            std::fs::copy(path, &tmp).map_err(CudaBuilderError::FailedToCopyPtxFile)?;
+            std::fs::rename(&tmp, copy_path).map_err(CudaBuilderError::FailedToCopyPtxFile)?;
+            Ok(copy_path.clone())
         } else {
             Ok(path)
         }
     }
 }
 
+/// Prints the regressions listed in the gpu report of the PTX file at `ptx` as cargo warnings,
+/// warnings of the codegen are not shown when building from build.rs.
+fn warn_gpu_report_regressions(ptx: &Path) {
+    let report = std::fs::read_to_string(ptx.with_extension("gpu-report")).unwrap_or_default();
+    for regression in report
+        .lines()
+        .filter_map(|line| line.strip_prefix("# regression: "))
+    {
+        println!("cargo:warning={}", regression);
+    }
+}
+
+/// Prints the bank conflict warnings the codegen wrote next to the PTX file at `ptx` as cargo
+/// warnings.
+fn warn_bank_conflicts(ptx: &Path) {
+    let warnings =
+        std::fs::read_to_string(ptx.with_extension("bank-conflicts")).unwrap_or_default();
+    for warning in warnings.lines() {
+        println!("cargo:warning={}", warning);
+    }
+}
+
+/// How often [`CudaBuilder::watch`] checks the gpu crate for changes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The modification times of every file of a crate, except for build artifacts.
+fn source_fingerprint(dir: &Path) -> Vec<(PathBuf, Option<SystemTime>)> {
+    fn visit(dir: &Path, out: &mut Vec<(PathBuf, Option<SystemTime>)>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if entry.file_name() != "target" {
+                    visit(&path, out);
+                }
+            } else {
+                let modified = entry.metadata().and_then(|m| m.modified()).ok();
+                out.push((path, modified));
+            }
+        }
+    }
+
+    let mut files = Vec::new();
+    visit(dir, &mut files);
+    files.sort();
+    files
+}
+
 // https://github.com/rust-lang/cargo/blob/1857880b5124580c4aeb4e8bc5f1198f491d61b1/src/cargo/util/paths.rs#L29-L52
 fn dylib_path_envvar() -> &'static str {
     if cfg!(windows) {
@@ -306,16 +762,57 @@ fn find_rustc_codegen_nvvm() -> PathBuf {
 fn get_new_path_var() -> OsString {
     let split_paths = env::var_os(dylib_path_envvar()).unwrap_or_default();
     let mut paths = env::split_paths(&split_paths).collect::<Vec<_>>();
-    let possible_paths = if cfg!(target_os = "windows") {
-        vec![find_cuda_helper::find_cuda_root()
-            .unwrap()
-            .join("nvvm")
-            .join("bin")]
+    // libnvvm is all the codegen needs, which does not require a full CUDA installation.
+    if let Some(root) = find_cuda_helper::find_nvvm_root() {
+        if cfg!(target_os = "windows") {
+            paths.push(root.join("nvvm").join("bin"));
+        } else {
+            paths.push(root.join("nvvm").join("lib64"));
+        }
+    }
+    if !cfg!(target_os = "windows") {
+        paths.extend(find_cuda_helper::find_cuda_lib_dirs());
+    }
+    env::join_paths(&paths).expect("Failed to join paths for PATH")
+}
+
+/// The path remappings of [`CudaBuilder::reproducible`].
+fn reproducible_remaps(path_to_crate: &Path) -> Vec<(PathBuf, String)> {
+    let mut remaps = Vec::new();
+    let cargo_home = env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cargo")));
+    if let Some(cargo_home) = cargo_home {
+        remaps.push((cargo_home, "/cargo".to_string()));
+    }
+    // the sysroot of the toolchain of the gpu crate, whose sources are compiled by build-std.
+    let sysroot = Command::new("rustc")
+        .args(&["--print", "sysroot"])
+        .current_dir(path_to_crate)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok());
+    if let Some(sysroot) = sysroot {
+        remaps.push((PathBuf::from(sysroot.trim()), "/rustc".to_string()));
+    }
+    let crate_dir = path_to_crate
+        .canonicalize()
+        .unwrap_or_else(|_| path_to_crate.to_path_buf());
+    remaps.push((crate_dir, ".".to_string()));
+    remaps
+}
+
+fn find_cudadevrt() -> Option<PathBuf> {
+    let filename = if cfg!(target_os = "windows") {
+        "cudadevrt.lib"
     } else {
-        find_cuda_helper::find_cuda_lib_dirs()
+        "libcudadevrt.a"
     };
-    paths.extend(possible_paths);
-    env::join_paths(&paths).expect("Failed to join paths for PATH")
+    find_cuda_helper::find_cuda_lib_dirs()
+        .into_iter()
+        .map(|dir| dir.join(filename))
+        .find(|path| path.is_file())
 }
 
 /// Joins strings together while ensuring none of the strings contain the separator.
@@ -344,6 +841,16 @@ fn invoke_rustc(builder: &CudaBuilder) -> Result<PathBuf, CudaBuilderError> {
         rustc_codegen_nvvm.display(),
     )];
 
+    let mut remaps = Vec::new();
+    if builder.reproducible {
+        remaps.extend(reproducible_remaps(&builder.path_to_crate));
+    }
+    // rustc applies the last matching remapping, so the user's remappings take precedence.
+    remaps.extend(builder.remap_path_prefixes.iter().cloned());
+    for (from, to) in remaps {
+        rustflags.push(format!("--remap-path-prefix={}={}", from.display(), to));
+    }
+
     if let Some(emit) = &builder.emit {
         let string = match emit {
             EmitOption::LlvmIr => "llvm-ir",
@@ -352,9 +859,21 @@ fn invoke_rustc(builder: &CudaBuilder) -> Result<PathBuf, CudaBuilderError> {
         rustflags.push(format!("--emit={}", string));
     }
 
+    // line tables are all that is needed for `.loc` directives, the codegen tells nvvm to emit
+    // them whenever there is debuginfo.
+    if builder.debug {
+        rustflags.push("-Cdebuginfo=2".to_string());
+    } else if builder.generate_line_info {
+        rustflags.push("-Cdebuginfo=1".to_string());
+    }
+
     let mut llvm_args = vec![NvvmOption::Arch(builder.arch).to_string()];
 
-    if !builder.nvvm_opts {
+    if builder.debug {
+        llvm_args.push(NvvmOption::GenDebugInfo.to_string());
+    }
+
+    if !builder.nvvm_opts || builder.debug {
         llvm_args.push("-opt=0".to_string());
     }
 
@@ -378,6 +897,40 @@ fn invoke_rustc(builder: &CudaBuilder) -> Result<PathBuf, CudaBuilderError> {
         llvm_args.push("--override-libm".to_string());
     }
 
+    if builder.gpu_report {
+        llvm_args.push("--emit=gpu-report".to_string());
+    }
+
+    if builder.nvptx_backend {
+        llvm_args.push("--nvptx-backend".to_string());
+    }
+
+    if builder.warn_bank_conflicts {
+        llvm_args.push("--warn-bank-conflicts".to_string());
+    }
+
+    if builder.dynamic_parallelism {
+        llvm_args.push("--rdc".to_string());
+    }
+
+    if let Some(profile) = &builder.tuning_profile {
+        println!("cargo:rerun-if-changed={}", profile.display());
+        llvm_args.extend(tuning::kernel_hints_args(profile)?);
+    }
+
+    // rustc runs in the directory of the gpu crate, but the paths are relative to the build script.
+    let linked = [
+        ("--link-ptx=", &builder.link_ptx),
+        ("--link-llvm-ir=", &builder.link_llvm_ir),
+    ];
+    for (flag, paths) in linked {
+        for path in paths {
+            println!("cargo:rerun-if-changed={}", path.display());
+            let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.clone());
+            llvm_args.push([flag, &path.to_string_lossy()].concat());
+        }
+    }
+
     let llvm_args = llvm_args.join(" ");
     if !llvm_args.is_empty() {
         rustflags.push(["-Cllvm-args=", &llvm_args].concat());
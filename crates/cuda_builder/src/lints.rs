@@ -0,0 +1,137 @@
+//! Warnings about kernels which are dead weight in the PTX, see
+//! [`CudaBuilder::lint_kernels`](crate::CudaBuilder::lint_kernels).
+//!
+//! Both checks work on tokens instead of resolved names, so they can only miss problems and never
+//! report used parameters or kernels: a parameter counts as used if its name appears anywhere in
+//! the body of the kernel (including inside of macros), and a kernel counts as used if its name
+//! appears as an identifier or a string literal (for `Module::get_function`) in the host crate, or
+//! anywhere else in the gpu crate.
+
+use crate::bindings::{collect_sources, is_kernel};
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+use syn::{FnArg, Item, Pat};
+
+struct KernelInfo {
+    name: String,
+    file: PathBuf,
+    unused_params: Vec<String>,
+}
+
+/// Prints a cargo warning for every unused kernel parameter in the gpu crate at `crate_path`, and
+/// for every kernel which is not referenced by the host crate at `host_path`.
+pub(crate) fn check(crate_path: &Path, host_path: &Path) {
+    let gpu_files = sources(crate_path);
+    let mut kernels = Vec::new();
+    let mut gpu_names = HashMap::new();
+    for (file, src) in &gpu_files {
+        if let Ok(parsed) = syn::parse_file(src) {
+            find_kernels(&parsed.items, file, &mut kernels);
+        }
+        count_names(src, &mut gpu_names);
+    }
+
+    let mut host_names = HashMap::new();
+    for (_, src) in sources(host_path) {
+        count_names(&src, &mut host_names);
+    }
+
+    for kernel in &kernels {
+        for param in &kernel.unused_params {
+            println!(
+                "cargo:warning=parameter `{}` of kernel `{}` ({}) is never used, prefix it with \
+                 an underscore if this is intentional",
+                param,
+                kernel.name,
+                kernel.file.display()
+            );
+        }
+        // the definition of the kernel itself is one occurrence.
+        let gpu_uses = gpu_names.get(&kernel.name).copied().unwrap_or(0);
+        if !host_names.contains_key(&kernel.name) && gpu_uses <= 1 {
+            println!(
+                "cargo:warning=kernel `{}` ({}) is never launched by the host crate",
+                kernel.name,
+                kernel.file.display()
+            );
+        }
+    }
+}
+
+/// The contents of every source file in the `src` directory of a crate, missing or unreadable
+/// files are skipped since the lints are best-effort.
+fn sources(crate_path: &Path) -> Vec<(PathBuf, String)> {
+    let mut files = Vec::new();
+    let _ = collect_sources(&crate_path.join("src"), &mut files);
+    files.sort();
+    files
+        .into_iter()
+        .filter_map(|file| {
+            let src = fs::read_to_string(&file).ok()?;
+            Some((file, src))
+        })
+        .collect()
+}
+
+fn find_kernels(items: &[Item], file: &Path, kernels: &mut Vec<KernelInfo>) {
+    for item in items {
+        match item {
+            Item::Fn(func) if is_kernel(func) => {
+                let mut used = HashMap::new();
+                collect_names(func.block.to_token_stream(), &mut used);
+                let unused_params = func
+                    .sig
+                    .inputs
+                    .iter()
+                    .filter_map(|arg| match arg {
+                        FnArg::Typed(arg) => match &*arg.pat {
+                            Pat::Ident(ident) => Some(ident.ident.to_string()),
+                            _ => None,
+                        },
+                        FnArg::Receiver(_) => None,
+                    })
+                    .filter(|name| !name.starts_with('_') && !used.contains_key(name))
+                    .collect();
+                kernels.push(KernelInfo {
+                    name: func.sig.ident.to_string(),
+                    file: file.to_path_buf(),
+                    unused_params,
+                });
+            }
+            Item::Mod(module) => {
+                if let Some((_, items)) = &module.content {
+                    find_kernels(items, file, kernels);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Counts every identifier and string literal in a source file.
+fn count_names(src: &str, names: &mut HashMap<String, usize>) {
+    if let Ok(tokens) = src.parse::<TokenStream>() {
+        collect_names(tokens, names);
+    }
+}
+
+fn collect_names(tokens: TokenStream, names: &mut HashMap<String, usize>) {
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) => *names.entry(ident.to_string()).or_default() += 1,
+            TokenTree::Literal(lit) => {
+                let lit = lit.to_string();
+                if let Some(s) = lit.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
+                    *names.entry(s.to_string()).or_default() += 1;
+                }
+            }
+            TokenTree::Group(group) => collect_names(group.stream(), names),
+            TokenTree::Punct(_) => {}
+        }
+    }
+}
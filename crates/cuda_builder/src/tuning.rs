@@ -0,0 +1,71 @@
+//! Tuning profiles, per-kernel register and launch bound hints kept in a TOML file next to the gpu
+//! crate, see [`CudaBuilder::tuning_profile`](crate::CudaBuilder::tuning_profile).
+//!
+//! ```toml
+//! [kernels.render]
+//! max_threads = 256
+//! min_blocks = 2
+//!
+//! [kernels.denoise]
+//! max_registers = 64
+//! ```
+//!
+//! The hints are passed to the codegen with `--kernel-hints=render:max_threads=256,min_blocks=2`,
+//! which applies them like the hints of `#[kernel(...)]`. A hint in the profile replaces the same
+//! hint of `#[kernel(...)]`, hints which are not in the profile keep the value of `#[kernel(...)]`.
+
+use crate::CudaBuilderError;
+use serde::Deserialize;
+use std::{collections::BTreeMap, fs, io, path::Path};
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct TuningProfile {
+    #[serde(default)]
+    kernels: BTreeMap<String, KernelTuning>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KernelTuning {
+    max_registers: Option<u32>,
+    max_threads: Option<u32>,
+    min_blocks: Option<u32>,
+}
+
+/// The `--kernel-hints` codegen arguments of every kernel in the profile at `path`. A profile which
+/// does not exist yet has no hints.
+pub(crate) fn kernel_hints_args(path: &Path) -> Result<Vec<String>, CudaBuilderError> {
+    let profile = match fs::read_to_string(path) {
+        Ok(profile) => profile,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(CudaBuilderError::InvalidTuningProfile(e.to_string())),
+    };
+    let profile = toml::from_str::<TuningProfile>(&profile)
+        .map_err(|e| CudaBuilderError::InvalidTuningProfile(e.to_string()))?;
+
+    let mut args = Vec::new();
+    for (name, tuning) in profile.kernels {
+        let mut hints = Vec::new();
+        for (key, value) in [
+            ("max_registers", tuning.max_registers),
+            ("max_threads", tuning.max_threads),
+            ("min_blocks", tuning.min_blocks),
+        ] {
+            match value {
+                Some(0) => {
+                    return Err(CudaBuilderError::InvalidTuningProfile(format!(
+                        "`{}` of `{}` must be positive",
+                        key, name
+                    )))
+                }
+                Some(value) => hints.push(format!("{}={}", key, value)),
+                None => {}
+            }
+        }
+        if !hints.is_empty() {
+            args.push(format!("--kernel-hints={}:{}", name, hints.join(",")));
+        }
+    }
+    Ok(args)
+}
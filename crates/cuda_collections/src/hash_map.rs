@@ -0,0 +1,214 @@
+//! A hash map with open addressing and linear probing, which many threads can insert into and look up
+//! from at once.
+//!
+//! The map is two arrays of a power of two length: the keys, where [`Key::EMPTY`] marks empty slots,
+//! and the values. A key is inserted by claiming the first empty slot after its hash with an atomic
+//! compare-and-swap, so inserts never block each other and the map needs no locks. Keys cannot be
+//! removed, and the map does not grow: it should be created with about twice as many slots as the
+//! number of keys it will hold, probing gets slow when it is more than about 70% full.
+//!
+//! [`DeviceHashMap`] is a view of the arrays which is passed to kernels by value. The kernels of this
+//! crate insert and look up keys in bulk, with `u32` values:
+//!
+//! - `collections_hash_map_insert_{u32,u64}(map: DeviceHashMap<K, u32>, keys: &[K], values: &[u32], results: *mut Insert)`:
+//!   inserts `keys[i]` with the value `values[i]`, or `i` if `values` is empty (the usual mapping of a
+//!   join, from keys to the index of the row they came from). If `results` is not null, the
+//!   [`Insert`] result of every key is written there, which finds duplicates.
+//! - `collections_hash_map_find_{u32,u64}(map: DeviceHashMap<K, u32>, keys: &[K], out: *mut u32)`:
+//!   writes the value of every key to `out`, or [`NOT_FOUND`] for keys which are not in the map.
+//!
+//! On the host, [`GpuHashMap`](crate::GpuHashMap) allocates the arrays and launches these kernels.
+
+use crate::Key;
+#[cfg(target_os = "cuda")]
+use core::ptr::{read_volatile, write_volatile};
+
+/// The value the find kernels write for keys which are not in the map.
+pub const NOT_FOUND: u32 = u32::MAX;
+
+/// The result of [`DeviceHashMap::insert`].
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Insert {
+    /// The key was not in the map.
+    New = 0,
+    /// The key was already in the map, its value was replaced.
+    Existing = 1,
+    /// The key was not in the map and every slot is taken, nothing was inserted.
+    Full = 2,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for Insert {}
+
+/// A view of the arrays of a hash map, see the [module docs](self).
+#[repr(C)]
+#[derive(Debug)]
+pub struct DeviceHashMap<K, V> {
+    keys: *mut K,
+    values: *mut V,
+    capacity: u32,
+}
+
+impl<K, V> Clone for DeviceHashMap<K, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V> Copy for DeviceHashMap<K, V> {}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl<K: cust::memory::DeviceCopy, V: cust::memory::DeviceCopy> cust::memory::DeviceCopy
+    for DeviceHashMap<K, V>
+{
+}
+
+impl<K: Key, V: Copy> DeviceHashMap<K, V> {
+    /// Creates a view of the hash map with the slots `keys[..capacity]` and `values[..capacity]`.
+    ///
+    /// # Safety
+    ///
+    /// - `keys` and `values` must be valid for reads and writes of `capacity` elements in device
+    ///   memory whenever the map is used.
+    /// - The keys must be [`Key::EMPTY`] or have been inserted through a `DeviceHashMap` of the
+    ///   same capacity.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is not a power of two.
+    pub unsafe fn from_raw_parts(keys: *mut K, values: *mut V, capacity: u32) -> Self {
+        assert!(
+            capacity.is_power_of_two(),
+            "the capacity must be a power of two"
+        );
+        Self {
+            keys,
+            values,
+            capacity,
+        }
+    }
+
+    /// The number of slots of the map.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Inserts `key` with `value`, or replaces the value of `key` if it is already in the map.
+    ///
+    /// If several threads insert the same key at once, exactly one of them gets [`Insert::New`], and
+    /// the value of one of them (which one is unspecified) ends up in the map.
+    ///
+    /// # Safety
+    ///
+    /// Values are written after their keys, so the value of a key may not be visible yet to threads
+    /// which look it up at the same time as it is inserted. Inserting and looking up keys in
+    /// separate kernel launches is always fine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` is [`Key::EMPTY`].
+    #[cfg(target_os = "cuda")]
+    pub unsafe fn insert(&self, key: K, value: V) -> Insert {
+        assert!(key != K::EMPTY, "`Key::EMPTY` cannot be inserted");
+        let mask = self.capacity - 1;
+        let mut slot = key.hash() & mask;
+        for _ in 0..self.capacity {
+            let key_ptr = self.keys.add(slot as usize);
+            let mut current = read_volatile(key_ptr);
+            if current == K::EMPTY {
+                current = K::compare_and_swap(key_ptr, K::EMPTY, key);
+                if current == K::EMPTY {
+                    write_volatile(self.values.add(slot as usize), value);
+                    return Insert::New;
+                }
+            }
+            if current == key {
+                write_volatile(self.values.add(slot as usize), value);
+                return Insert::Existing;
+            }
+            slot = (slot + 1) & mask;
+        }
+        Insert::Full
+    }
+
+    /// The value of `key`, or `None` if it is not in the map.
+    ///
+    /// # Safety
+    ///
+    /// See [`insert`](Self::insert) for looking up keys while they are inserted.
+    #[cfg(target_os = "cuda")]
+    pub unsafe fn get(&self, key: K) -> Option<V> {
+        if key == K::EMPTY {
+            return None;
+        }
+        let mask = self.capacity - 1;
+        let mut slot = key.hash() & mask;
+        for _ in 0..self.capacity {
+            let current = read_volatile(self.keys.add(slot as usize));
+            if current == key {
+                return Some(read_volatile(self.values.add(slot as usize)));
+            }
+            if current == K::EMPTY {
+                return None;
+            }
+            slot = (slot + 1) & mask;
+        }
+        None
+    }
+
+    /// Whether `key` is in the map.
+    ///
+    /// # Safety
+    ///
+    /// See [`insert`](Self::insert) for looking up keys while they are inserted.
+    #[cfg(target_os = "cuda")]
+    pub unsafe fn contains_key(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::{DeviceHashMap, Insert, NOT_FOUND};
+    use cuda_std::{kernel, thread};
+
+    macro_rules! hash_map_kernels {
+        ($($ty:ident: $insert:ident, $find:ident;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $insert(
+                    map: DeviceHashMap<$ty, u32>,
+                    keys: &[$ty],
+                    values: &[u32],
+                    results: *mut Insert,
+                ) {
+                    let i = thread::index_1d() as usize;
+                    if i >= keys.len() {
+                        return;
+                    }
+                    let value = if values.is_empty() { i as u32 } else { values[i] };
+                    let result = map.insert(keys[i], value);
+                    if !results.is_null() {
+                        *results.add(i) = result;
+                    }
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $find(map: DeviceHashMap<$ty, u32>, keys: &[$ty], out: *mut u32) {
+                    let i = thread::index_1d() as usize;
+                    if let Some(&key) = keys.get(i) {
+                        *out.add(i) = map.get(key).unwrap_or(NOT_FOUND);
+                    }
+                }
+            )*
+        };
+    }
+
+    hash_map_kernels! {
+        u32: collections_hash_map_insert_u32, collections_hash_map_find_u32;
+        u64: collections_hash_map_insert_u64, collections_hash_map_find_u64;
+    }
+}
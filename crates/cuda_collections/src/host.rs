@@ -0,0 +1,142 @@
+use crate::{
+    hash_map::{DeviceHashMap, Insert},
+    Key,
+};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{CopyDestination, DeviceBuffer, DeviceCopy, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+use std::ptr::NonNull;
+
+/// The number of threads per block of the kernels.
+const BLOCK: u32 = 256;
+
+/// A hash map from `K` to `u32` in device memory, see the [`hash_map`](crate::hash_map) module.
+pub struct GpuHashMap<'a, K: Key + DeviceCopy> {
+    keys: DeviceBuffer<K>,
+    values: DeviceBuffer<u32>,
+    insert: Function<'a>,
+    find: Function<'a>,
+}
+
+impl<'a, K: Key + DeviceCopy> GpuHashMap<'a, K> {
+    /// Creates an empty map for up to `len` keys, with at least twice as many slots. `module` must be
+    /// the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn with_capacity(module: &'a Module, len: usize) -> CudaResult<Self> {
+        let slots = (len.max(1) * 2).next_power_of_two();
+        assert!(slots <= 1 << 31, "hash maps can have at most 2^31 slots");
+        let function = |name: &str| {
+            module.get_function(format!("collections_hash_map_{}_{}", name, K::SUFFIX))
+        };
+        Ok(Self {
+            keys: DeviceBuffer::from_slice(&vec![K::EMPTY; slots])?,
+            values: unsafe { DeviceBuffer::zeroed(slots)? },
+            insert: function("insert")?,
+            find: function("find")?,
+        })
+    }
+
+    /// Creates a map from `keys` on the host, where the value of every key is its index in `keys`.
+    /// Returns the map and the [`Insert`] result of every key, which is [`Insert::Existing`] for
+    /// duplicates.
+    pub fn from_keys(
+        module: &'a Module,
+        stream: &Stream,
+        keys: &[K],
+    ) -> CudaResult<(Self, Vec<Insert>)> {
+        let mut map = Self::with_capacity(module, keys.len())?;
+        let keys = DeviceBuffer::from_slice(keys)?;
+        let mut results = unsafe { DeviceBuffer::uninitialized(keys.len())? };
+        map.insert(stream, &keys, None, Some(&mut results))?;
+        stream.synchronize()?;
+        let mut host = vec![Insert::New; keys.len()];
+        results.copy_to(&mut host)?;
+        Ok((map, host))
+    }
+
+    /// The number of slots of the map.
+    pub fn capacity(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// A view of the map for passing to your own kernels.
+    pub fn view(&mut self) -> DeviceHashMap<K, u32> {
+        self.raw_view()
+    }
+
+    /// The view of the map, the kernels only write through it when it is borrowed mutably.
+    fn raw_view(&self) -> DeviceHashMap<K, u32> {
+        unsafe {
+            DeviceHashMap::from_raw_parts(
+                self.keys.as_ptr() as *mut K,
+                self.values.as_ptr() as *mut u32,
+                self.keys.len() as u32,
+            )
+        }
+    }
+
+    /// Removes every key, this waits for the device to finish using the map.
+    pub fn clear(&mut self) -> CudaResult<()> {
+        let empty = vec![K::EMPTY; self.keys.len()];
+        self.keys.copy_from(&empty)
+    }
+
+    /// Enqueues inserting `keys` on `stream`, with the values `values` or the index of every key if
+    /// `values` is `None`. If `results` is given, the [`Insert`] result of every key is written to
+    /// it.
+    pub fn insert(
+        &mut self,
+        stream: &Stream,
+        keys: &DeviceSlice<K>,
+        values: Option<&DeviceSlice<u32>>,
+        results: Option<&mut DeviceSlice<Insert>>,
+    ) -> CudaResult<()> {
+        if let Some(values) = values {
+            assert_eq!(values.len(), keys.len(), "every key needs a value");
+        }
+        let mut map = self.view();
+        let (mut keys_ptr, mut keys_len) = (keys.as_ptr(), keys.len());
+        let (mut values_ptr, mut values_len) = match values {
+            Some(values) => (values.as_ptr(), values.len()),
+            // the kernel takes a slice, whose pointer must not be null even when it is empty.
+            None => (NonNull::<u32>::dangling().as_ptr() as *const u32, 0),
+        };
+        let mut results = match results {
+            Some(results) => {
+                assert_eq!(results.len(), keys.len(), "every key needs a result");
+                results.as_mut_ptr()
+            }
+            None => std::ptr::null_mut(),
+        };
+        let params = params!(map, keys_ptr, keys_len, values_ptr, values_len, results);
+        unsafe {
+            stream.launch(
+                &self.insert,
+                blocks_for(keys.len(), BLOCK),
+                BLOCK,
+                0,
+                &params,
+            )
+        }
+    }
+
+    /// Enqueues looking up `keys` on `stream`, the value of every key is written to `out`, or
+    /// [`NOT_FOUND`](crate::hash_map::NOT_FOUND) if it is not in the map.
+    pub fn find(
+        &self,
+        stream: &Stream,
+        keys: &DeviceSlice<K>,
+        out: &mut DeviceSlice<u32>,
+    ) -> CudaResult<()> {
+        assert_eq!(out.len(), keys.len(), "every key needs an output");
+        let mut map = self.raw_view();
+        let (mut keys_ptr, mut keys_len) = (keys.as_ptr(), keys.len());
+        let mut out = out.as_mut_ptr();
+        let params = params!(map, keys_ptr, keys_len, out);
+        unsafe { stream.launch(&self.find, blocks_for(keys.len(), BLOCK), BLOCK, 0, &params) }
+    }
+}
@@ -0,0 +1,74 @@
+mod private {
+    pub trait Sealed {}
+    impl Sealed for u32 {}
+    impl Sealed for u64 {}
+}
+
+/// The key types of the hash tables of this crate, [`u32`] and [`u64`].
+///
+/// The largest value of the type ([`Key::EMPTY`]) marks empty slots, so it cannot be used as a key.
+pub trait Key: Copy + Eq + private::Sealed {
+    /// The key of empty slots.
+    const EMPTY: Self;
+    /// The suffix of the kernels of this crate for this type, `u32` or `u64`.
+    const SUFFIX: &'static str;
+
+    /// A hash of the key, every bit of the key changes about half of the bits of the hash.
+    fn hash(self) -> u32;
+
+    /// Atomically replaces the value at `ptr` with `new` if it is `current`, returns the previous value.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be valid for reads and writes and aligned.
+    #[cfg(target_os = "cuda")]
+    unsafe fn compare_and_swap(ptr: *mut Self, current: Self, new: Self) -> Self;
+}
+
+impl Key for u32 {
+    const EMPTY: Self = u32::MAX;
+    const SUFFIX: &'static str = "u32";
+
+    /// The finalizer of MurmurHash3.
+    #[inline(always)]
+    fn hash(self) -> u32 {
+        let mut h = self;
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xc2b2_ae35);
+        h ^ (h >> 16)
+    }
+
+    #[cfg(target_os = "cuda")]
+    #[inline(always)]
+    unsafe fn compare_and_swap(ptr: *mut Self, current: Self, new: Self) -> Self {
+        match cuda_std::atomic::compare_exchange(ptr, current, new) {
+            Ok(old) | Err(old) => old,
+        }
+    }
+}
+
+impl Key for u64 {
+    const EMPTY: Self = u64::MAX;
+    const SUFFIX: &'static str = "u64";
+
+    /// The 64-bit finalizer of MurmurHash3, truncated.
+    #[inline(always)]
+    fn hash(self) -> u32 {
+        let mut h = self;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        (h ^ (h >> 33)) as u32
+    }
+
+    #[cfg(target_os = "cuda")]
+    #[inline(always)]
+    unsafe fn compare_and_swap(ptr: *mut Self, current: Self, new: Self) -> Self {
+        match cuda_std::atomic::compare_exchange_u64(ptr, current, new) {
+            Ok(old) | Err(old) => old,
+        }
+    }
+}
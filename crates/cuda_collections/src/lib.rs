@@ -0,0 +1,36 @@
+//! Data structures in device memory, which the threads of kernels use concurrently.
+//!
+//! - [`hash_map`]: a lock-free hash map with open addressing, for joins, duplicate detection and
+//!   looking up sparse keys such as the cells of a spatial hash.
+//!
+//! Like `cuda_linalg`, this crate is used from both sides: the gpu crate depends on it for the device
+//! types and to get the kernels into its PTX (with the default `kernels` feature), and the host crate
+//! to allocate the structures and launch the kernels. Kernels are named
+//! `collections_<structure>_<operation>_<type>`, for example `collections_hash_map_insert_u32`.
+//!
+//! ```ignore
+//! // host
+//! let module = Module::from_str(PTX)?;
+//! let (mut map, results) = GpuHashMap::<u64>::from_keys(&module, &stream, &ids)?;
+//! let duplicates = results.iter().filter(|&&r| r == Insert::Existing).count();
+//! map.find(&stream, &queries, &mut rows)?;
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+mod key;
+
+pub use key::Key;
+
+pub mod hash_map;
+
+#[cfg(not(target_os = "cuda"))]
+mod host;
+
+#[cfg(not(target_os = "cuda"))]
+pub use host::*;
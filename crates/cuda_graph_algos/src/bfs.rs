@@ -0,0 +1,81 @@
+//! Breadth-first search, which finds the number of edges on the shortest path from a source vertex
+//! to every other vertex.
+//!
+//! The search goes level by level. The frontier of level `d` holds the vertices at distance `d`, and
+//! expanding it walks their out-edges with one warp per vertex, every lane taking every 32nd edge so
+//! that the loads of a vertex are coalesced. A neighbour which is still [`UNREACHED`] is claimed with a
+//! compare-and-swap of its distance (`cuda_std::atomic::compare_exchange`), and the thread which
+//! claimed it appends it to the next frontier. So every vertex is in at most one frontier, and a level
+//! costs as much as the edges of its frontier instead of as much as the whole graph. The search ends
+//! with the first empty frontier.
+//!
+//! The kernels are:
+//!
+//! - `graph_bfs_start(distances: &mut [u32], source: u32, frontier: *mut u32)`: launched with one
+//!   thread per vertex, sets the distance of `source` to 0 and of every other vertex to [`UNREACHED`],
+//!   and makes `source` the first frontier.
+//! - `graph_bfs_expand(row_offsets: &[u32], columns: &[u32], frontier: &[u32], level: u32, distances: *mut u32, next: *mut u32, next_len: *mut u32)`:
+//!   launched with 32 threads per vertex of `frontier`, which must be the vertices at distance
+//!   `level`. Appends the unreached neighbours to `next`, whose length at `next_len` must be zero
+//!   before the launch.
+//! - `graph_fill_u32(values: &mut [u32], value: u32)`: sets every element of `values`, used to reset
+//!   `next_len` between levels.
+//!
+//! On the host, [`Bfs`](crate::Bfs) launches them and reads the length of every frontier back, which
+//! synchronizes the stream once per level.
+
+/// The distance of vertices which cannot be reached from the source.
+pub const UNREACHED: u32 = u32::MAX;
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::UNREACHED;
+    use crate::frontier::{push, warp_index};
+    use cuda_std::{atomic, kernel, thread, warp};
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_bfs_start(distances: &mut [u32], source: u32, frontier: *mut u32) {
+        let i = thread::index_1d();
+        if let Some(distance) = distances.get_mut(i as usize) {
+            if i == source {
+                *distance = 0;
+                *frontier = source;
+            } else {
+                *distance = UNREACHED;
+            }
+        }
+    }
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_bfs_expand(
+        row_offsets: &[u32],
+        columns: &[u32],
+        frontier: &[u32],
+        level: u32,
+        distances: *mut u32,
+        next: *mut u32,
+        next_len: *mut u32,
+    ) {
+        // the whole warp has the same vertex, so it exits together.
+        let i = warp_index();
+        if i >= frontier.len() {
+            return;
+        }
+        let vertex = frontier[i] as usize;
+        let end = row_offsets[vertex + 1] as usize;
+        let mut edge = row_offsets[vertex] as usize + warp::lane_id() as usize;
+        while edge < end {
+            let neighbour = columns[edge];
+            let distance = distances.add(neighbour as usize);
+            // most neighbours were reached already, which a plain read finds without an atomic.
+            if distance.read_volatile() == UNREACHED
+                && atomic::compare_exchange(distance, UNREACHED, level + 1).is_ok()
+            {
+                push(next, next_len, neighbour);
+            }
+            edge += warp::WARP_SIZE as usize;
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! The pieces the kernels of the searches share: a float minimum, appending vertices to a frontier,
+//! and a kernel filling buffers.
+//!
+//! A frontier is a list of vertices in device memory with its length in another `u32`. Threads append
+//! to it by reserving a slot with an atomic add on the length, so the order of a frontier changes
+//! from run to run, which none of the algorithms depend on.
+
+use cuda_std::{atomic, kernel, thread, warp};
+
+/// [`atomic::fetch_min`] for non-negative floats (including infinity), whose bits compare like the
+/// floats when read as unsigned integers.
+#[inline(always)]
+pub unsafe fn atomic_min_f32(ptr: *mut f32, value: f32) -> f32 {
+    f32::from_bits(atomic::fetch_min(ptr as *mut u32, value.to_bits()))
+}
+
+/// Appends `vertex` to the frontier `list` with the length at `len`.
+///
+/// # Safety
+///
+/// `list` must have room for every vertex appended before the length is reset.
+#[inline(always)]
+pub unsafe fn push(list: *mut u32, len: *mut u32, vertex: u32) {
+    let slot = atomic::fetch_add(len, 1);
+    *list.add(slot as usize) = vertex;
+}
+
+/// The index of the warp of this thread in the grid, the searches expand one vertex per warp.
+#[inline(always)]
+pub fn warp_index() -> usize {
+    (thread::index_1d() / warp::WARP_SIZE) as usize
+}
+
+/// Sets every element of `values` to `value`, used to reset the lengths of frontiers on the stream
+/// of a search instead of with copies from the host.
+#[kernel]
+#[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+pub unsafe fn graph_fill_u32(values: &mut [u32], value: u32) {
+    let i = thread::index_1d() as usize;
+    if let Some(x) = values.get_mut(i) {
+        *x = value;
+    }
+}
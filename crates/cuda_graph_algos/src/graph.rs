@@ -0,0 +1,413 @@
+use crate::bfs::UNREACHED;
+use cust::{
+    error::CudaResult,
+    memory::{DeviceBuffer, DeviceSlice},
+};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// A directed graph in CSR form on the host, optionally with a non-negative weight per edge.
+///
+/// An undirected graph is stored with both directions of every edge.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrGraph {
+    row_offsets: Vec<u32>,
+    columns: Vec<u32>,
+    weights: Option<Vec<f32>>,
+}
+
+impl CsrGraph {
+    /// Creates a graph from its CSR arrays: the out-edges of vertex `v` go to the vertices
+    /// `columns[row_offsets[v]..row_offsets[v + 1]]`, with the same entries of `weights` as their
+    /// weights.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arrays are not a valid CSR graph, or if a weight is negative or not finite.
+    pub fn new(row_offsets: Vec<u32>, columns: Vec<u32>, weights: Option<Vec<f32>>) -> Self {
+        assert_eq!(
+            row_offsets.first(),
+            Some(&0),
+            "`row_offsets` must start with 0"
+        );
+        assert!(
+            row_offsets.windows(2).all(|w| w[0] <= w[1]),
+            "`row_offsets` must not decrease"
+        );
+        assert_eq!(
+            *row_offsets.last().unwrap() as usize,
+            columns.len(),
+            "`row_offsets` must end with the number of edges"
+        );
+        let vertices = row_offsets.len() - 1;
+        assert!(
+            vertices < u32::MAX as usize,
+            "the graph has too many vertices for u32 indices"
+        );
+        assert!(
+            columns.iter().all(|&column| (column as usize) < vertices),
+            "vertex index out of bounds"
+        );
+        if let Some(weights) = &weights {
+            assert_eq!(
+                columns.len(),
+                weights.len(),
+                "`columns` and `weights` have different lengths"
+            );
+            assert!(
+                weights.iter().all(|w| w.is_finite() && *w >= 0.0),
+                "weights must be finite and not negative"
+            );
+        }
+        Self {
+            row_offsets,
+            columns,
+            weights,
+        }
+    }
+
+    /// Creates an unweighted graph with `vertices` vertices from `(from, to)` edges in any order.
+    pub fn from_edges(vertices: usize, edges: &[(u32, u32)]) -> Self {
+        let (row_offsets, order) = Self::sort_edges(vertices, edges.iter().map(|&(from, _)| from));
+        let columns = order.iter().map(|&i| edges[i].1).collect();
+        Self::new(row_offsets, columns, None)
+    }
+
+    /// Creates a weighted graph with `vertices` vertices from `(from, to, weight)` edges in any order.
+    pub fn from_weighted_edges(vertices: usize, edges: &[(u32, u32, f32)]) -> Self {
+        let (row_offsets, order) =
+            Self::sort_edges(vertices, edges.iter().map(|&(from, _, _)| from));
+        let columns = order.iter().map(|&i| edges[i].1).collect();
+        let weights = order.iter().map(|&i| edges[i].2).collect();
+        Self::new(row_offsets, columns, Some(weights))
+    }
+
+    /// The row offsets of the edges leaving from `sources`, and the order of the edges sorted by their
+    /// source (a counting sort, which keeps the order of the edges of every vertex).
+    fn sort_edges(
+        vertices: usize,
+        sources: impl Iterator<Item = u32> + Clone,
+    ) -> (Vec<u32>, Vec<usize>) {
+        let mut row_offsets = vec![0; vertices + 1];
+        for from in sources.clone() {
+            assert!((from as usize) < vertices, "vertex index out of bounds");
+            row_offsets[from as usize + 1] += 1;
+        }
+        for v in 0..vertices {
+            row_offsets[v + 1] += row_offsets[v];
+        }
+        let mut next = row_offsets.clone();
+        let mut order = vec![0; *row_offsets.last().unwrap() as usize];
+        for (i, from) in sources.enumerate() {
+            order[next[from as usize] as usize] = i;
+            next[from as usize] += 1;
+        }
+        (row_offsets, order)
+    }
+
+    pub fn vertices(&self) -> usize {
+        self.row_offsets.len() - 1
+    }
+
+    pub fn edges(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn row_offsets(&self) -> &[u32] {
+        &self.row_offsets
+    }
+
+    pub fn columns(&self) -> &[u32] {
+        &self.columns
+    }
+
+    pub fn weights(&self) -> Option<&[f32]> {
+        self.weights.as_deref()
+    }
+
+    /// The vertices the out-edges of `vertex` go to.
+    pub fn neighbours(&self, vertex: u32) -> &[u32] {
+        let v = vertex as usize;
+        &self.columns[self.row_offsets[v] as usize..self.row_offsets[v + 1] as usize]
+    }
+
+    /// The number of out-edges of every vertex.
+    pub fn out_degrees(&self) -> Vec<u32> {
+        self.row_offsets.windows(2).map(|w| w[1] - w[0]).collect()
+    }
+
+    /// The weight of edge `edge`, 1 for unweighted graphs.
+    fn weight(&self, edge: usize) -> f32 {
+        self.weights.as_ref().map_or(1.0, |weights| weights[edge])
+    }
+
+    /// The graph with every edge reversed, keeping the weights.
+    pub fn transpose(&self) -> Self {
+        let mut edges = Vec::with_capacity(self.edges());
+        for from in 0..self.vertices() {
+            let range = self.row_offsets[from] as usize..self.row_offsets[from + 1] as usize;
+            for edge in range {
+                edges.push((self.columns[edge], from as u32, self.weight(edge)));
+            }
+        }
+        let transposed = Self::from_weighted_edges(self.vertices(), &edges);
+        match self.weights {
+            Some(_) => transposed,
+            None => Self {
+                weights: None,
+                ..transposed
+            },
+        }
+    }
+
+    /// The bucket width for [`Sssp`](crate::Sssp) suggested by Davidson et al., 32 times the mean
+    /// edge weight divided by the mean out-degree, which balances the parallelism of wide buckets
+    /// against the repeated relaxations they cause.
+    pub fn suggested_delta(&self) -> f32 {
+        if self.edges() == 0 {
+            return 1.0;
+        }
+        let mean_weight = match &self.weights {
+            Some(weights) => weights.iter().map(|&w| w as f64).sum::<f64>() / weights.len() as f64,
+            None => 1.0,
+        };
+        let mean_degree = self.edges() as f64 / self.vertices() as f64;
+        let delta = (32.0 * mean_weight / mean_degree) as f32;
+        // graphs with only zero weights still need to make progress.
+        if delta > 0.0 {
+            delta
+        } else {
+            1.0
+        }
+    }
+
+    /// The distance of every vertex from `source` in edges, [`UNREACHED`] for unreachable vertices,
+    /// computed on the CPU, for checking the results of [`Bfs`](crate::Bfs).
+    pub fn bfs(&self, source: u32) -> Vec<u32> {
+        let mut distances = vec![UNREACHED; self.vertices()];
+        distances[source as usize] = 0;
+        let mut queue = VecDeque::from(vec![source]);
+        while let Some(vertex) = queue.pop_front() {
+            let distance = distances[vertex as usize] + 1;
+            for &neighbour in self.neighbours(vertex) {
+                if distances[neighbour as usize] == UNREACHED {
+                    distances[neighbour as usize] = distance;
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        distances
+    }
+
+    /// The length of the shortest path from `source` to every vertex, infinity for unreachable
+    /// vertices, computed with Dijkstra on the CPU, for checking the results of [`Sssp`](crate::Sssp).
+    pub fn shortest_paths(&self, source: u32) -> Vec<f32> {
+        let mut distances = vec![f32::INFINITY; self.vertices()];
+        distances[source as usize] = 0.0;
+        // the bits of non-negative floats order like the floats, reversed for a min-heap.
+        let mut heap = BinaryHeap::from(vec![(Reverse(0u32), source)]);
+        while let Some((Reverse(bits), vertex)) = heap.pop() {
+            let v = vertex as usize;
+            if bits != distances[v].to_bits() {
+                continue;
+            }
+            for edge in self.row_offsets[v] as usize..self.row_offsets[v + 1] as usize {
+                let neighbour = self.columns[edge];
+                let distance = distances[v] + self.weight(edge);
+                if distance < distances[neighbour as usize] {
+                    distances[neighbour as usize] = distance;
+                    heap.push((Reverse(distance.to_bits()), neighbour));
+                }
+            }
+        }
+        distances
+    }
+
+    /// The PageRank of every vertex after `iterations` iterations, computed on the CPU, for checking
+    /// the results of [`PageRank`](crate::PageRank).
+    pub fn pagerank(&self, damping: f32, iterations: usize) -> Vec<f32> {
+        let n = self.vertices();
+        let degrees = self.out_degrees();
+        let mut ranks = vec![1.0 / n as f32; n];
+        for _ in 0..iterations {
+            let dangling = (0..n)
+                .filter(|&v| degrees[v] == 0)
+                .map(|v| ranks[v])
+                .sum::<f32>();
+            let mut next = vec![(1.0 - damping) / n as f32 + damping * dangling / n as f32; n];
+            for from in 0..n {
+                for &to in self.neighbours(from as u32) {
+                    next[to as usize] += damping * ranks[from] / degrees[from] as f32;
+                }
+            }
+            ranks = next;
+        }
+        ranks
+    }
+}
+
+/// A [`CsrGraph`] in device memory, which the algorithms of this crate run on.
+pub struct DeviceGraph {
+    row_offsets: DeviceBuffer<u32>,
+    columns: DeviceBuffer<u32>,
+    weights: Option<DeviceBuffer<f32>>,
+}
+
+impl DeviceGraph {
+    /// Uploads `graph`.
+    pub fn new(graph: &CsrGraph) -> CudaResult<Self> {
+        Ok(Self {
+            row_offsets: DeviceBuffer::from_slice(&graph.row_offsets)?,
+            columns: DeviceBuffer::from_slice(&graph.columns)?,
+            weights: match &graph.weights {
+                Some(weights) => Some(DeviceBuffer::from_slice(weights)?),
+                None => None,
+            },
+        })
+    }
+
+    pub fn vertices(&self) -> usize {
+        self.row_offsets.len() - 1
+    }
+
+    pub fn edges(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn row_offsets(&self) -> &DeviceSlice<u32> {
+        &self.row_offsets
+    }
+
+    pub fn columns(&self) -> &DeviceSlice<u32> {
+        &self.columns
+    }
+
+    pub fn weights(&self) -> Option<&DeviceSlice<f32>> {
+        self.weights.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 0 -> 1 -> 3, 0 -> 2 -> 3 and 1 -> 2, with 4 isolated.
+    fn weighted() -> CsrGraph {
+        CsrGraph::from_weighted_edges(
+            5,
+            &[
+                (1, 3, 6.0),
+                (0, 1, 1.0),
+                (2, 3, 1.0),
+                (0, 2, 4.0),
+                (1, 2, 2.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn sorts_edges_by_source() {
+        let graph = CsrGraph::from_edges(4, &[(2, 0), (0, 3), (2, 1), (0, 1)]);
+        assert_eq!(graph.row_offsets(), &[0, 2, 2, 4, 4]);
+        // the edges of every vertex stay in the order they were given in.
+        assert_eq!(graph.columns(), &[3, 1, 0, 1]);
+        assert_eq!(graph.weights(), None);
+        assert_eq!(graph.out_degrees(), vec![2, 0, 2, 0]);
+
+        let graph = weighted();
+        assert_eq!(graph.row_offsets(), &[0, 2, 4, 5, 5, 5]);
+        assert_eq!(graph.columns(), &[1, 2, 3, 2, 3]);
+        assert_eq!(graph.weights(), Some(&[1.0, 4.0, 6.0, 2.0, 1.0][..]));
+        assert_eq!(graph.neighbours(1), &[3, 2]);
+        assert_eq!(graph.neighbours(4), &[] as &[u32]);
+    }
+
+    #[test]
+    #[should_panic(expected = "vertex index out of bounds")]
+    fn rejects_edges_from_missing_vertices() {
+        CsrGraph::from_edges(2, &[(0, 1), (2, 0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "vertex index out of bounds")]
+    fn rejects_edges_to_missing_vertices() {
+        CsrGraph::from_edges(2, &[(0, 2)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "weights must be finite and not negative")]
+    fn rejects_negative_weights() {
+        CsrGraph::from_weighted_edges(2, &[(0, 1, -1.0)]);
+    }
+
+    #[test]
+    fn transposes() {
+        let graph = weighted();
+        let transposed = graph.transpose();
+        assert_eq!(transposed.row_offsets(), &[0, 0, 1, 3, 5, 5]);
+        assert_eq!(transposed.columns(), &[0, 0, 1, 1, 2]);
+        assert_eq!(transposed.weights(), Some(&[1.0, 4.0, 2.0, 6.0, 1.0][..]));
+
+        let graph = CsrGraph::from_edges(3, &[(0, 1), (0, 2), (1, 2)]);
+        let transposed = graph.transpose();
+        assert_eq!(transposed.columns(), &[0, 0, 1]);
+        assert_eq!(transposed.weights(), None);
+        assert_eq!(transposed.transpose(), graph);
+    }
+
+    #[test]
+    fn suggests_delta() {
+        // a mean weight of 14 / 5 and a mean degree of 1.
+        assert_eq!(weighted().suggested_delta(), 32.0 * 2.8);
+        assert_eq!(
+            CsrGraph::from_edges(2, &[(0, 1), (1, 0), (0, 0), (1, 1)]).suggested_delta(),
+            16.0
+        );
+        assert_eq!(CsrGraph::from_edges(3, &[]).suggested_delta(), 1.0);
+        assert_eq!(
+            CsrGraph::from_weighted_edges(2, &[(0, 1, 0.0)]).suggested_delta(),
+            1.0
+        );
+    }
+
+    #[test]
+    fn searches() {
+        let graph = weighted();
+        assert_eq!(graph.bfs(0), vec![0, 1, 1, 2, UNREACHED]);
+        assert_eq!(graph.bfs(2), vec![UNREACHED, UNREACHED, 0, 1, UNREACHED]);
+        // 0 -> 1 -> 2 -> 3 is shorter than the direct edges.
+        assert_eq!(
+            graph.shortest_paths(0),
+            vec![0.0, 1.0, 3.0, 4.0, f32::INFINITY]
+        );
+        assert_eq!(
+            graph.shortest_paths(1),
+            vec![f32::INFINITY, 0.0, 2.0, 3.0, f32::INFINITY]
+        );
+        // without weights every edge counts as 1.
+        let unweighted =
+            CsrGraph::new(graph.row_offsets().to_vec(), graph.columns().to_vec(), None);
+        assert_eq!(
+            unweighted.shortest_paths(0),
+            vec![0.0, 1.0, 1.0, 2.0, f32::INFINITY]
+        );
+    }
+
+    #[test]
+    fn ranks_pages() {
+        // a cycle keeps the uniform ranks.
+        let cycle = CsrGraph::from_edges(3, &[(0, 1), (1, 2), (2, 0)]);
+        for rank in cycle.pagerank(0.85, 10) {
+            assert!((rank - 1.0 / 3.0).abs() < 1e-6);
+        }
+
+        // vertex 1 is dangling, its rank is spread over both vertices.
+        let graph = CsrGraph::from_edges(2, &[(0, 1)]);
+        let ranks = graph.pagerank(0.85, 1);
+        assert!((ranks[0] - 0.2875).abs() < 1e-6, "{:?}", ranks);
+        assert!((ranks[1] - 0.7125).abs() < 1e-6, "{:?}", ranks);
+        let ranks = graph.pagerank(0.85, 50);
+        assert!((ranks[0] + ranks[1] - 1.0).abs() < 1e-5, "{:?}", ranks);
+        assert!(ranks[1] > ranks[0]);
+    }
+}
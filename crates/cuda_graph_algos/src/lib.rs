@@ -0,0 +1,59 @@
+//! Graph analytics on the GPU, over graphs in CSR form in device memory.
+//!
+//! - [`bfs`]: breadth-first search from a source vertex, expanding a frontier of vertices level by
+//!   level.
+//! - [`sssp`]: single-source shortest paths over non-negative edge weights with delta-stepping, which
+//!   relaxes the vertices in buckets of distances instead of one at a time like Dijkstra.
+//! - [`pagerank`]: PageRank by power iteration, using the SpMV and reductions of `cuda_linalg`.
+//!
+//! Graphs are built on the host as a [`CsrGraph`], where the out-edges of vertex `v` are the entries
+//! `row_offsets[v]..row_offsets[v + 1]` of `columns` (and of `weights`), and uploaded into device
+//! buffers as a [`DeviceGraph`]. [`CsrGraph`] also has slow reference implementations of the
+//! algorithms for checking the results of the kernels.
+//!
+//! Like `cuda_linalg`, this crate is used from both sides: the gpu crate depends on it to get the kernels
+//! into its PTX (with the default `kernels` feature, which also enables the kernels of `cuda_linalg`),
+//! and the host crate to upload graphs and run the algorithms. Kernels are named
+//! `graph_<algorithm>_<step>`, for example `graph_bfs_expand`.
+//!
+//! ```ignore
+//! // host
+//! let module = Module::from_str(PTX)?;
+//! let graph = CsrGraph::from_edges(vertices, &edges);
+//! let device_graph = DeviceGraph::new(&graph)?;
+//! let mut distances = unsafe { DeviceBuffer::uninitialized(graph.vertices())? };
+//! let mut bfs = Bfs::new(&module, &stream)?;
+//! let depth = bfs.run(&device_graph, 0, &mut distances)?;
+//!
+//! let mut pagerank = PageRank::new(&module, &stream, &graph)?;
+//! let mut ranks = unsafe { DeviceBuffer::uninitialized(graph.vertices())? };
+//! let convergence = pagerank.run(0.85, 1e-6, 100, &mut ranks)?;
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+pub mod bfs;
+pub mod pagerank;
+pub mod sssp;
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod frontier;
+
+#[cfg(not(target_os = "cuda"))]
+mod graph;
+#[cfg(not(target_os = "cuda"))]
+mod ranking;
+#[cfg(not(target_os = "cuda"))]
+mod search;
+
+#[cfg(not(target_os = "cuda"))]
+pub use graph::*;
+#[cfg(not(target_os = "cuda"))]
+pub use ranking::*;
+#[cfg(not(target_os = "cuda"))]
+pub use search::*;
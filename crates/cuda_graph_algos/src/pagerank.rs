@@ -0,0 +1,63 @@
+//! PageRank by power iteration.
+//!
+//! The rank of a vertex is the probability that a random surfer is on it, who follows a random
+//! out-edge with probability `damping` and jumps to a random vertex otherwise. Surfers on a vertex
+//! without out-edges (a dangling vertex) always jump. So with `n` vertices, every iteration computes
+//!
+//! ```text
+//! rank'[v] = (1 - damping) / n + damping * (sum of rank[u] / out_degree[u] over the edges u -> v + dangling / n)
+//! ```
+//!
+//! where `dangling` is the sum of the ranks of the dangling vertices, until the ranks stop changing.
+//!
+//! The sum over the in-edges is a sparse matrix-vector product with the transposed graph, whose
+//! entries are `1 / out_degree[u]`, so it is done by the SpMV kernels of `cuda_linalg` in whichever
+//! format suits the in-degrees of the graph, and the sums by its reductions. This module only adds the
+//! kernels which update the ranks:
+//!
+//! - `graph_pagerank_start(ranks: &mut [f32], out_degrees: &[u32], dangling: *mut f32)`: launched
+//!   with one thread per vertex, sets every rank to `1 / n` and `dangling` to the ranks of the
+//!   dangling vertices and zero for the others.
+//! - `graph_pagerank_update(contributions: &[f32], out_degrees: &[u32], damping: f32, dangling_sum: f32, ranks: &mut [f32], dangling: *mut f32, changes: *mut f32)`:
+//!   launched with one thread per vertex, computes the new ranks from the SpMV result
+//!   `contributions` in place, updates `dangling`, and writes how much every rank changed to
+//!   `changes`, whose sum decides when to stop.
+//!
+//! On the host, [`PageRank`](crate::PageRank) builds the transposed matrix and runs the iterations.
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use cuda_std::{kernel, thread, GpuFloat};
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_pagerank_start(ranks: &mut [f32], out_degrees: &[u32], dangling: *mut f32) {
+        let n = ranks.len();
+        let i = thread::index_1d() as usize;
+        if let Some(rank) = ranks.get_mut(i) {
+            *rank = 1.0 / n as f32;
+            *dangling.add(i) = if out_degrees[i] == 0 { *rank } else { 0.0 };
+        }
+    }
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_pagerank_update(
+        contributions: &[f32],
+        out_degrees: &[u32],
+        damping: f32,
+        dangling_sum: f32,
+        ranks: &mut [f32],
+        dangling: *mut f32,
+        changes: *mut f32,
+    ) {
+        let n = ranks.len() as f32;
+        let i = thread::index_1d() as usize;
+        if let Some(rank) = ranks.get_mut(i) {
+            let new = (1.0 - damping) / n + damping * (contributions[i] + dangling_sum / n);
+            *changes.add(i) = (new - *rank).abs();
+            *rank = new;
+            *dangling.add(i) = if out_degrees[i] == 0 { new } else { 0.0 };
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use crate::CsrGraph;
+use cuda_linalg::{Convergence, CsrMatrix, DeviceSparseMatrix, Reducer, Reduction};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceBuffer, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+
+/// The number of threads per block of the kernels.
+const BLOCK: u32 = 256;
+
+/// PageRank with the kernels of the [`pagerank`](crate::pagerank) module and the SpMV and reductions
+/// of `cuda_linalg`.
+///
+/// Every iteration reads two sums back to the host, which synchronizes the stream twice.
+pub struct PageRank<'a> {
+    stream: &'a Stream,
+    start: Function<'a>,
+    update: Function<'a>,
+    /// The transposed graph, with `1 / out_degree[u]` as the value of the edge `u -> v`.
+    matrix: DeviceSparseMatrix<'a, f32>,
+    reducer: Reducer<'a, f32>,
+    out_degrees: DeviceBuffer<u32>,
+    contributions: DeviceBuffer<f32>,
+    dangling: DeviceBuffer<f32>,
+    changes: DeviceBuffer<f32>,
+}
+
+impl<'a> PageRank<'a> {
+    /// Uploads the transposed matrix of `graph`. `module` must be the PTX of a gpu crate depending on
+    /// this crate with the `kernels` feature, every kernel is launched on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `graph` has no vertices.
+    pub fn new(module: &'a Module, stream: &'a Stream, graph: &CsrGraph) -> CudaResult<Self> {
+        let n = graph.vertices();
+        assert!(n != 0, "PageRank needs at least one vertex");
+        let out_degrees = graph.out_degrees();
+        let mut triplets = Vec::with_capacity(graph.edges());
+        for (from, &degree) in out_degrees.iter().enumerate() {
+            let weight = 1.0 / degree as f32;
+            for &to in graph.neighbours(from as u32) {
+                triplets.push((to as usize, from, weight));
+            }
+        }
+        let matrix = CsrMatrix::from_triplets(n, n, &triplets);
+        Ok(Self {
+            stream,
+            start: module.get_function("graph_pagerank_start")?,
+            update: module.get_function("graph_pagerank_update")?,
+            matrix: DeviceSparseMatrix::new(module, &matrix)?,
+            reducer: Reducer::new(module, stream)?,
+            out_degrees: DeviceBuffer::from_slice(&out_degrees)?,
+            contributions: unsafe { DeviceBuffer::uninitialized(n)? },
+            dangling: unsafe { DeviceBuffer::uninitialized(n)? },
+            changes: unsafe { DeviceBuffer::uninitialized(n)? },
+        })
+    }
+
+    pub fn vertices(&self) -> usize {
+        self.out_degrees.len()
+    }
+
+    /// Writes the PageRank of every vertex to `ranks`, starting from equal ranks. A surfer follows an
+    /// edge with probability `damping`, usually 0.85.
+    ///
+    /// The iteration stops when the ranks changed by at most `tolerance` in total (the L1 norm of the
+    /// change), or after `max_iterations` iterations. The residual of the result is that change.
+    pub fn run(
+        &mut self,
+        damping: f32,
+        tolerance: f32,
+        max_iterations: usize,
+        ranks: &mut DeviceSlice<f32>,
+    ) -> CudaResult<Convergence<f32>> {
+        let n = self.vertices();
+        assert_eq!(ranks.len(), n, "`ranks` must have an element per vertex");
+        let blocks = blocks_for(n, BLOCK);
+        let (mut ranks_ptr, mut ranks_len) = (ranks.as_mut_ptr(), n);
+        let (mut degrees, mut degrees_len) = (self.out_degrees.as_ptr(), n);
+        let mut dangling = self.dangling.as_mut_ptr();
+        let params = params!(ranks_ptr, ranks_len, degrees, degrees_len, dangling);
+        unsafe {
+            self.stream.launch(&self.start, blocks, BLOCK, 0, &params)?;
+        }
+
+        let mut residual = f32::INFINITY;
+        let mut iterations = 0;
+        while iterations < max_iterations && residual > tolerance {
+            // the dangling sum is read back while the SpMV runs.
+            let pending = self
+                .reducer
+                .reduce_to_host_async(Reduction::Sum, &self.dangling)?;
+            self.matrix
+                .spmv(self.stream, ranks, &mut self.contributions)?;
+            let mut dangling_sum = pending.wait()?;
+
+            let (mut contributions, mut contributions_len) = (self.contributions.as_ptr(), n);
+            let mut damping = damping;
+            let mut changes = self.changes.as_mut_ptr();
+            let params = params!(
+                contributions,
+                contributions_len,
+                degrees,
+                degrees_len,
+                damping,
+                dangling_sum,
+                ranks_ptr,
+                ranks_len,
+                dangling,
+                changes
+            );
+            unsafe {
+                self.stream
+                    .launch(&self.update, blocks, BLOCK, 0, &params)?;
+            }
+            residual = self.reducer.reduce(Reduction::Sum, &self.changes)?;
+            iterations += 1;
+        }
+
+        Ok(Convergence {
+            iterations,
+            residual,
+            converged: residual <= tolerance,
+        })
+    }
+}
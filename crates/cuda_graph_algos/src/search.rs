@@ -0,0 +1,319 @@
+use crate::DeviceGraph;
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{CopyDestination, DeviceBuffer, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+use std::ptr::NonNull;
+
+/// The number of threads per block of the kernels.
+const BLOCK: u32 = 256;
+/// The expand and relax kernels use a warp per vertex of the frontier.
+const WARP_SIZE: usize = 32;
+
+/// The current and next frontier of a search, and the counters the kernels write while building the
+/// next one.
+struct Frontiers<'a> {
+    fill: Function<'a>,
+    current: DeviceBuffer<u32>,
+    next: DeviceBuffer<u32>,
+    /// The length of `next`, and for SSSP the bits of the smallest distance beyond the bucket.
+    counters: DeviceBuffer<u32>,
+}
+
+impl<'a> Frontiers<'a> {
+    fn new(module: &'a Module) -> CudaResult<Self> {
+        Ok(Self {
+            fill: module.get_function("graph_fill_u32")?,
+            current: DeviceBuffer::from_slice(&[])?,
+            next: DeviceBuffer::from_slice(&[])?,
+            counters: unsafe { DeviceBuffer::zeroed(2)? },
+        })
+    }
+
+    /// Makes room for frontiers of `vertices` vertices, which is the most they can have.
+    fn reserve(&mut self, vertices: usize) -> CudaResult<()> {
+        if self.current.len() < vertices {
+            unsafe {
+                self.current = DeviceBuffer::uninitialized(vertices)?;
+                self.next = DeviceBuffer::uninitialized(vertices)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Enqueues setting counter `index` to `value`.
+    fn set_counter(&mut self, stream: &Stream, index: usize, value: u32) -> CudaResult<()> {
+        let counter = &mut self.counters[index..index + 1];
+        let (mut counter, mut len, mut value) = (counter.as_mut_ptr(), 1usize, value);
+        let params = params!(counter, len, value);
+        unsafe { stream.launch(&self.fill, 1, 1, 0, &params) }
+    }
+
+    /// Waits for the stream and reads the counters.
+    fn read_counters(&self, stream: &Stream) -> CudaResult<[u32; 2]> {
+        stream.synchronize()?;
+        let mut counters = [0; 2];
+        self.counters.copy_to(&mut counters[..])?;
+        Ok(counters)
+    }
+
+    fn swap(&mut self) {
+        std::mem::swap(&mut self.current, &mut self.next);
+    }
+}
+
+/// Breadth-first search with the kernels of the [`bfs`](crate::bfs) module.
+pub struct Bfs<'a> {
+    stream: &'a Stream,
+    start: Function<'a>,
+    expand: Function<'a>,
+    frontiers: Frontiers<'a>,
+}
+
+impl<'a> Bfs<'a> {
+    /// Creates a search using the kernels in `module`, which must be the PTX of a gpu crate depending
+    /// on this crate with the `kernels` feature. Every kernel is launched on `stream`.
+    pub fn new(module: &'a Module, stream: &'a Stream) -> CudaResult<Self> {
+        Ok(Self {
+            stream,
+            start: module.get_function("graph_bfs_start")?,
+            expand: module.get_function("graph_bfs_expand")?,
+            frontiers: Frontiers::new(module)?,
+        })
+    }
+
+    /// Writes the number of edges on the shortest path from `source` to every vertex of `graph` to
+    /// `distances`, or [`UNREACHED`](crate::bfs::UNREACHED) for vertices which cannot be reached.
+    /// Returns the largest distance of a reached vertex.
+    ///
+    /// This synchronizes the stream once per level of the search.
+    pub fn run(
+        &mut self,
+        graph: &DeviceGraph,
+        source: u32,
+        distances: &mut DeviceSlice<u32>,
+    ) -> CudaResult<u32> {
+        let vertices = graph.vertices();
+        assert_eq!(
+            distances.len(),
+            vertices,
+            "`distances` must have an element per vertex"
+        );
+        assert!(
+            (source as usize) < vertices,
+            "`source` is not a vertex of the graph"
+        );
+        self.frontiers.reserve(vertices)?;
+
+        let (mut distances_ptr, mut distances_len) = (distances.as_mut_ptr(), vertices);
+        let mut source = source;
+        let mut frontier = self.frontiers.current.as_mut_ptr();
+        let params = params!(distances_ptr, distances_len, source, frontier);
+        unsafe {
+            self.stream
+                .launch(&self.start, blocks_for(vertices, BLOCK), BLOCK, 0, &params)?;
+        }
+
+        let (mut offsets, mut offsets_len) = (graph.row_offsets().as_ptr(), vertices + 1);
+        let (mut columns, mut columns_len) = (graph.columns().as_ptr(), graph.edges());
+        let mut len = 1;
+        let mut level = 0u32;
+        loop {
+            self.frontiers.set_counter(self.stream, 0, 0)?;
+            let mut frontier = self.frontiers.current.as_ptr();
+            let mut next = self.frontiers.next.as_mut_ptr();
+            let mut next_len = self.frontiers.counters.as_mut_ptr();
+            let params = params!(
+                offsets,
+                offsets_len,
+                columns,
+                columns_len,
+                frontier,
+                len,
+                level,
+                distances_ptr,
+                next,
+                next_len
+            );
+            unsafe {
+                self.stream.launch(
+                    &self.expand,
+                    blocks_for(WARP_SIZE * len, BLOCK),
+                    BLOCK,
+                    0,
+                    &params,
+                )?;
+            }
+            len = self.frontiers.read_counters(self.stream)?[0] as usize;
+            if len == 0 {
+                return Ok(level);
+            }
+            self.frontiers.swap();
+            level += 1;
+        }
+    }
+}
+
+/// Single-source shortest paths with the delta-stepping kernels of the [`sssp`](crate::sssp) module.
+pub struct Sssp<'a> {
+    stream: &'a Stream,
+    start: Function<'a>,
+    relax: Function<'a>,
+    bucket: Function<'a>,
+    frontiers: Frontiers<'a>,
+    stamps: DeviceBuffer<u32>,
+}
+
+impl<'a> Sssp<'a> {
+    /// Creates a search using the kernels in `module`, which must be the PTX of a gpu crate depending
+    /// on this crate with the `kernels` feature. Every kernel is launched on `stream`.
+    pub fn new(module: &'a Module, stream: &'a Stream) -> CudaResult<Self> {
+        Ok(Self {
+            stream,
+            start: module.get_function("graph_sssp_start")?,
+            relax: module.get_function("graph_sssp_relax")?,
+            bucket: module.get_function("graph_sssp_bucket")?,
+            frontiers: Frontiers::new(module)?,
+            stamps: DeviceBuffer::from_slice(&[])?,
+        })
+    }
+
+    /// Writes the length of the shortest path from `source` to every vertex of `graph` to
+    /// `distances`, or infinity for vertices which cannot be reached. Edges of unweighted graphs have
+    /// a weight of 1.
+    ///
+    /// `delta` is the width of the buckets, see
+    /// [`CsrGraph::suggested_delta`](crate::CsrGraph::suggested_delta). This synchronizes the stream
+    /// after every relaxation of a frontier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `delta` is not positive and finite.
+    pub fn run(
+        &mut self,
+        graph: &DeviceGraph,
+        source: u32,
+        delta: f32,
+        distances: &mut DeviceSlice<f32>,
+    ) -> CudaResult<()> {
+        let vertices = graph.vertices();
+        assert_eq!(
+            distances.len(),
+            vertices,
+            "`distances` must have an element per vertex"
+        );
+        assert!(
+            (source as usize) < vertices,
+            "`source` is not a vertex of the graph"
+        );
+        assert!(
+            delta > 0.0 && delta.is_finite(),
+            "`delta` must be positive and finite"
+        );
+        self.frontiers.reserve(vertices)?;
+        if self.stamps.len() < vertices {
+            self.stamps = unsafe { DeviceBuffer::uninitialized(vertices)? };
+        }
+
+        let (mut distances_ptr, mut distances_len) = (distances.as_mut_ptr(), vertices);
+        let mut stamps = self.stamps.as_mut_ptr();
+        let mut source = source;
+        let mut frontier = self.frontiers.current.as_mut_ptr();
+        let params = params!(distances_ptr, distances_len, stamps, source, frontier);
+        unsafe {
+            self.stream
+                .launch(&self.start, blocks_for(vertices, BLOCK), BLOCK, 0, &params)?;
+        }
+
+        let (mut offsets, mut offsets_len) = (graph.row_offsets().as_ptr(), vertices + 1);
+        let (mut columns, mut columns_len) = (graph.columns().as_ptr(), graph.edges());
+        let (mut weights, mut weights_len) = match graph.weights() {
+            Some(weights) => (weights.as_ptr(), weights.len()),
+            // the kernel takes a slice, whose pointer must not be null even when it is empty.
+            None => (NonNull::<f32>::dangling().as_ptr() as *const f32, 0),
+        };
+        // every launch which builds a frontier has its own stamp, so that it appends every vertex
+        // at most once.
+        let mut stamp = 0u32;
+        let mut len = 1;
+        let mut upper = delta;
+        // the smallest distance beyond the bucket, the counter keeps it over the whole bucket.
+        let mut far = f32::INFINITY;
+        self.frontiers
+            .set_counter(self.stream, 1, f32::INFINITY.to_bits())?;
+        loop {
+            // relax the bucket below `upper` until none of its distances change.
+            while len != 0 {
+                self.frontiers.set_counter(self.stream, 0, 0)?;
+                let mut frontier = self.frontiers.current.as_ptr();
+                let mut next = self.frontiers.next.as_mut_ptr();
+                let mut counters = self.frontiers.counters.as_mut_ptr();
+                let params = params!(
+                    offsets,
+                    offsets_len,
+                    columns,
+                    columns_len,
+                    weights,
+                    weights_len,
+                    frontier,
+                    len,
+                    upper,
+                    stamp,
+                    distances_ptr,
+                    stamps,
+                    next,
+                    counters
+                );
+                unsafe {
+                    self.stream.launch(
+                        &self.relax,
+                        blocks_for(WARP_SIZE * len, BLOCK),
+                        BLOCK,
+                        0,
+                        &params,
+                    )?;
+                }
+                stamp += 1;
+                let [next_len, far_bits] = self.frontiers.read_counters(self.stream)?;
+                self.frontiers.swap();
+                len = next_len as usize;
+                far = f32::from_bits(far_bits);
+            }
+            if far == f32::INFINITY {
+                return Ok(());
+            }
+
+            // skip the empty buckets up to the closest distance beyond this bucket.
+            let mut lower = upper;
+            upper = (upper + delta).max(((far / delta).floor() + 1.0) * delta);
+            self.frontiers.set_counter(self.stream, 0, 0)?;
+            self.frontiers
+                .set_counter(self.stream, 1, f32::INFINITY.to_bits())?;
+            let mut next = self.frontiers.next.as_mut_ptr();
+            let mut counters = self.frontiers.counters.as_mut_ptr();
+            let params = params!(
+                distances_ptr,
+                distances_len,
+                lower,
+                upper,
+                stamp,
+                stamps,
+                next,
+                counters
+            );
+            unsafe {
+                self.stream
+                    .launch(&self.bucket, blocks_for(vertices, BLOCK), BLOCK, 0, &params)?;
+            }
+            stamp += 1;
+            let [next_len, far_bits] = self.frontiers.read_counters(self.stream)?;
+            self.frontiers.swap();
+            len = next_len as usize;
+            far = f32::from_bits(far_bits);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Single-source shortest paths over non-negative edge weights with delta-stepping, after
+//! [Δ-stepping: a parallelizable shortest path algorithm](https://doi.org/10.1016/S0196-6774(03)00076-2)
+//! by Meyer and Sanders, in the near-far form of [Work-Efficient Parallel GPU Methods for
+//! Single-Source Shortest Paths](https://doi.org/10.1109/IPDPS.2014.45) by Davidson et al.
+//!
+//! Dijkstra settles one vertex at a time, which leaves nothing to do in parallel, while Bellman-Ford
+//! relaxes every edge until nothing changes, which does a lot of useless work. Delta-stepping sits in
+//! between: the distances are split into buckets `[k delta, (k + 1) delta)`, and all the vertices of the
+//! current bucket are relaxed at once, again and again until the bucket stops changing. Only then does
+//! the search move on to the next bucket that has vertices, whose distances can no longer improve
+//! once the earlier buckets are done.
+//!
+//! Relaxing a frontier walks the out-edges of every vertex with one warp per vertex and lowers the
+//! distances of the neighbours with an atomic minimum. A neighbour whose distance improved and lies
+//! in the current bucket is appended to the next frontier (once, thanks to a stamp per vertex), one
+//! further away is left for a later bucket and only lowers the smallest distance seen beyond the
+//! bucket, from which the host picks the next bucket. The next bucket's frontier is collected by a
+//! pass over all vertices.
+//!
+//! A small `delta` approaches Dijkstra, with many tiny frontiers, a large one approaches
+//! Bellman-Ford. [`CsrGraph::suggested_delta`](crate::CsrGraph::suggested_delta) gives the heuristic of
+//! Davidson et al. which works well for most graphs.
+//!
+//! Distances are `f32`, with [`f32::INFINITY`] for unreachable vertices. Unweighted graphs (with no
+//! weights) use a weight of 1 for every edge. The kernels are:
+//!
+//! - `graph_sssp_start(distances: &mut [f32], stamps: *mut u32, source: u32, frontier: *mut u32)`:
+//!   launched with one thread per vertex, sets the distance of `source` to 0 and of every other
+//!   vertex to infinity, resets the stamps, and makes `source` the first frontier.
+//! - `graph_sssp_relax(row_offsets: &[u32], columns: &[u32], weights: &[f32], frontier: &[u32], upper: f32, stamp: u32, distances: *mut f32, stamps: *mut u32, next: *mut u32, counters: *mut u32)`:
+//!   launched with 32 threads per vertex of `frontier`. Neighbours whose distance improved to below
+//!   `upper` are appended to `next` with the length at `counters[0]`, the smallest improved distance
+//!   of at least `upper` is written to `counters[1]` with an atomic minimum on its bits. `stamp` must
+//!   be different for every launch.
+//! - `graph_sssp_bucket(distances: &[f32], lower: f32, upper: f32, stamp: u32, stamps: *mut u32, next: *mut u32, counters: *mut u32)`:
+//!   launched with one thread per vertex, appends the vertices with a distance in `[lower, upper)`
+//!   to `next` and lowers `counters[1]` to the smallest distance of at least `upper`.
+//!
+//! On the host, [`Sssp`](crate::Sssp) launches them and reads the counters back after every launch.
+
+/// The stamp of vertices which were not appended to a frontier yet.
+pub const NOT_QUEUED: u32 = u32::MAX;
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::NOT_QUEUED;
+    use crate::frontier::{atomic_min_f32, push, warp_index};
+    use cuda_std::{atomic, kernel, thread, warp};
+
+    /// Appends `vertex` to `next` unless it was already appended with `stamp`.
+    #[inline(always)]
+    unsafe fn push_once(vertex: u32, stamp: u32, stamps: *mut u32, next: *mut u32, len: *mut u32) {
+        if atomic::swap(stamps.add(vertex as usize), stamp) != stamp {
+            push(next, len, vertex);
+        }
+    }
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_sssp_start(
+        distances: &mut [f32],
+        stamps: *mut u32,
+        source: u32,
+        frontier: *mut u32,
+    ) {
+        let i = thread::index_1d();
+        if let Some(distance) = distances.get_mut(i as usize) {
+            *stamps.add(i as usize) = NOT_QUEUED;
+            if i == source {
+                *distance = 0.0;
+                *frontier = source;
+            } else {
+                *distance = f32::INFINITY;
+            }
+        }
+    }
+
+    #[kernel]
+    #[allow(
+        improper_ctypes_definitions,
+        clippy::missing_safety_doc,
+        clippy::too_many_arguments
+    )]
+    pub unsafe fn graph_sssp_relax(
+        row_offsets: &[u32],
+        columns: &[u32],
+        weights: &[f32],
+        frontier: &[u32],
+        upper: f32,
+        stamp: u32,
+        distances: *mut f32,
+        stamps: *mut u32,
+        next: *mut u32,
+        counters: *mut u32,
+    ) {
+        // the whole warp has the same vertex, so it exits together.
+        let i = warp_index();
+        if i >= frontier.len() {
+            return;
+        }
+        let vertex = frontier[i] as usize;
+        // the distance may have improved since the vertex was appended, then it is in the next
+        // frontier again, but relaxing with the newest distance already does part of that work.
+        let base = distances.add(vertex).read_volatile();
+        let end = row_offsets[vertex + 1] as usize;
+        let mut edge = row_offsets[vertex] as usize + warp::lane_id() as usize;
+        while edge < end {
+            let neighbour = columns[edge];
+            let weight = if weights.is_empty() {
+                1.0
+            } else {
+                weights[edge]
+            };
+            let distance = base + weight;
+            if distance < atomic_min_f32(distances.add(neighbour as usize), distance) {
+                if distance < upper {
+                    push_once(neighbour, stamp, stamps, next, counters);
+                } else {
+                    atomic_min_f32(counters.add(1) as *mut f32, distance);
+                }
+            }
+            edge += warp::WARP_SIZE as usize;
+        }
+    }
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn graph_sssp_bucket(
+        distances: &[f32],
+        lower: f32,
+        upper: f32,
+        stamp: u32,
+        stamps: *mut u32,
+        next: *mut u32,
+        counters: *mut u32,
+    ) {
+        let i = thread::index_1d();
+        if let Some(&distance) = distances.get(i as usize) {
+            if distance >= upper {
+                if distance != f32::INFINITY {
+                    atomic_min_f32(counters.add(1) as *mut f32, distance);
+                }
+            } else if distance >= lower {
+                push_once(i, stamp, stamps, next, counters);
+            }
+        }
+    }
+}
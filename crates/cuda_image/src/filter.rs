@@ -0,0 +1,405 @@
+//! Convolutions of images: filters with any 2D weights, separable filters such as the Gaussian blur,
+//! and the Sobel operator.
+//!
+//! The filters read and write [`Plane`]s, views of pitched 2D arrays with one value per pixel, of
+//! `f32` (a grayscale image or any single channel) or `[f32; 4]` (a linear
+//! [`Rgba32F`](crate::pixel::Rgba32F) image). Every block first loads the pixels its outputs depend on,
+//! its tile plus an apron of `radius` pixels on every side, into shared memory, so that the image is
+//! read from global memory about once instead of once per weight. Pixels outside the image take the
+//! value of the nearest pixel on its edge (clamp-to-edge).
+//!
+//! The filters are applied as correlations, like `filter2D` of OpenCV: the weight at `(i, j)` of a
+//! filter with radius `r` multiplies the pixel at `(x + i - r, y + j - r)`, so asymmetric filters are
+//! not flipped. The kernels are, for `T` being `f32` or `[f32; 4]` (with the suffix `rgba32f`):
+//!
+//! - `image_convolve_{f32,rgba32f}(src: Plane<T>, dst: Plane<T>, weights: &[f32], radius: u32)`:
+//!   applies the `(2 * radius + 1)^2` weights in rows, with `radius` at most [`MAX_RADIUS`]. Launched
+//!   with blocks of [`TILE`]`x`[`TILE`] threads, one per pixel.
+//! - `image_filter_rows_{f32,rgba32f}(src: Plane<T>, dst: Plane<T>, weights: &[f32])` and
+//!   `image_filter_columns_{f32,rgba32f}(src: Plane<T>, dst: Plane<T>, weights: &[f32])`: apply an odd
+//!   number of weights along the rows or the columns, with a radius of at most
+//!   [`MAX_SEPARABLE_RADIUS`]. Launched with blocks of [`SEPARABLE_BLOCK`] threads, every thread
+//!   computing [`OUTPUTS_PER_THREAD`] pixels along the filtered direction, which keeps the apron small
+//!   compared to the tile. Filtering the rows and then the columns applies the outer product of the
+//!   two filters with `2 (2r + 1)` instead of `(2r + 1)^2` weights per pixel.
+//! - `image_sobel_f32(src: Plane<f32>, gradients: Plane<[f32; 2]>, magnitudes: Plane<f32>)`: writes
+//!   the horizontal and vertical derivatives of the 3x3 Sobel operator to `gradients` and their
+//!   length to `magnitudes`, skipping the planes with a null pointer. Launched like the 2D
+//!   convolution.
+//!
+//! On the host, [`ImageFilters`](crate::ImageFilters) and [`SobelFilter`](crate::SobelFilter) launch
+//! them on `DevicePitchedBuffer`s.
+
+/// The width and height of the tiles of the 2D convolution and Sobel kernels, and of their blocks.
+pub const TILE: u32 = 16;
+/// The largest radius of the 2D convolution, a 15x15 filter.
+pub const MAX_RADIUS: u32 = 7;
+/// The blocks of the separable kernels, 32 threads along the rows and 8 along the columns.
+pub const SEPARABLE_BLOCK: (u32, u32) = (32, 8);
+/// The number of pixels every thread of the separable kernels computes.
+pub const OUTPUTS_PER_THREAD: u32 = 4;
+/// The largest radius of the separable filters, enough for a Gaussian with a sigma of 8.
+pub const MAX_SEPARABLE_RADIUS: u32 = 24;
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for [f32; 4] {}
+}
+
+/// The pixel types the filter kernels are defined for, `f32` and `[f32; 4]`.
+pub trait FilterPixel: Copy + private::Sealed {
+    const ZERO: Self;
+    /// The suffix of the kernels of this crate for this type, `f32` or `rgba32f`.
+    const SUFFIX: &'static str;
+
+    /// `sum + weight * self`.
+    fn weighted_add(self, weight: f32, sum: Self) -> Self;
+}
+
+impl FilterPixel for f32 {
+    const ZERO: Self = 0.0;
+    const SUFFIX: &'static str = "f32";
+
+    #[inline(always)]
+    fn weighted_add(self, weight: f32, sum: Self) -> Self {
+        sum + weight * self
+    }
+}
+
+impl FilterPixel for [f32; 4] {
+    const ZERO: Self = [0.0; 4];
+    const SUFFIX: &'static str = "rgba32f";
+
+    #[inline(always)]
+    fn weighted_add(self, weight: f32, sum: Self) -> Self {
+        [
+            sum[0] + weight * self[0],
+            sum[1] + weight * self[1],
+            sum[2] + weight * self[2],
+            sum[3] + weight * self[3],
+        ]
+    }
+}
+
+/// A 2D array of `T` with rows `pitch` bytes apart in device memory, which the filter kernels read
+/// and write. See the [module docs](self).
+#[repr(C)]
+#[derive(Debug)]
+pub struct Plane<T> {
+    data: *mut T,
+    width: u32,
+    height: u32,
+    pitch: usize,
+}
+
+impl<T> Clone for Plane<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Plane<T> {}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl<T: cust::memory::DeviceCopy> cust::memory::DeviceCopy for Plane<T> {}
+
+impl<T: Copy> Plane<T> {
+    /// # Safety
+    ///
+    /// `data` must be null or valid for reads (and writes, if the plane is written) of `height` rows
+    /// `pitch` bytes apart, each of `width` values, and aligned for `T`. The rows must not be freed
+    /// while the plane is used.
+    pub unsafe fn from_raw_parts(data: *mut T, width: u32, height: u32, pitch: usize) -> Self {
+        Self {
+            data,
+            width,
+            height,
+            pitch,
+        }
+    }
+
+    /// The number of values in every row.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The distance between the starts of two consecutive rows, in bytes.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// Whether the plane has no data, which the kernels treat as an output to skip.
+    pub fn is_null(&self) -> bool {
+        self.data.is_null()
+    }
+
+    unsafe fn at(&self, x: u32, y: u32) -> *mut T {
+        let row = (self.data as *mut u8).add(y as usize * self.pitch) as *mut T;
+        row.add(x as usize)
+    }
+
+    /// The value at `(x, y)`.
+    ///
+    /// # Safety
+    ///
+    /// `(x, y)` must be in bounds and no other thread may write the value at the same time.
+    pub unsafe fn read(&self, x: u32, y: u32) -> T {
+        *self.at(x, y)
+    }
+
+    /// The value at `(x, y)` moved to the nearest position in the plane.
+    ///
+    /// # Safety
+    ///
+    /// The plane must not be empty, and no other thread may write the value at the same time.
+    pub unsafe fn read_clamped(&self, x: i32, y: i32) -> T {
+        let x = x.clamp(0, self.width as i32 - 1) as u32;
+        let y = y.clamp(0, self.height as i32 - 1) as u32;
+        self.read(x, y)
+    }
+
+    /// Writes `value` to `(x, y)`.
+    ///
+    /// # Safety
+    ///
+    /// `(x, y)` must be in bounds and no other thread may access the value at the same time.
+    pub unsafe fn write(&self, x: u32, y: u32, value: T) {
+        *self.at(x, y) = value;
+    }
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::*;
+    use core::mem::MaybeUninit;
+    use cuda_std::{kernel, shared_array, thread, GpuFloat};
+
+    /// The largest tile of the 2D convolution, with its apron.
+    const CONVOLVE_TILE_LEN: usize = ((TILE + 2 * MAX_RADIUS) * (TILE + 2 * MAX_RADIUS)) as usize;
+    /// The pixels the blocks of the row kernels compute along the rows.
+    const SEPARABLE_SPAN: u32 = SEPARABLE_BLOCK.0 * OUTPUTS_PER_THREAD;
+    /// The largest tile of the row kernels, with its apron.
+    const ROWS_TILE_LEN: usize =
+        ((SEPARABLE_SPAN + 2 * MAX_SEPARABLE_RADIUS) * SEPARABLE_BLOCK.1) as usize;
+    /// The largest tile of the column kernels, with its apron.
+    const COLUMNS_TILE_LEN: usize = ((SEPARABLE_BLOCK.1 * OUTPUTS_PER_THREAD
+        + 2 * MAX_SEPARABLE_RADIUS)
+        * SEPARABLE_BLOCK.0) as usize;
+    /// The tile of the Sobel kernel, with its apron of one pixel.
+    const SOBEL_TILE_LEN: usize = ((TILE + 2) * (TILE + 2)) as usize;
+
+    /// Loads the `width`x`height` pixels of `src` whose top left pixel is `(x, y)` into `tile`,
+    /// with every thread of the block taking every n-th pixel, and waits for the whole block.
+    #[inline(always)]
+    unsafe fn load_tile<T: Copy>(
+        src: &Plane<T>,
+        tile: *mut T,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    ) {
+        let threads = thread::block_dim_x() * thread::block_dim_y();
+        let mut i = thread::thread_idx_y() * thread::block_dim_x() + thread::thread_idx_x();
+        while i < width * height {
+            let pixel = src.read_clamped(x + (i % width) as i32, y + (i / width) as i32);
+            *tile.add(i as usize) = pixel;
+            i += threads;
+        }
+        thread::sync_threads();
+    }
+
+    #[inline(always)]
+    unsafe fn convolve<T: FilterPixel>(
+        src: Plane<T>,
+        dst: Plane<T>,
+        weights: &[f32],
+        radius: u32,
+        tile: *mut T,
+    ) {
+        if radius > MAX_RADIUS || src.width() == 0 || src.height() == 0 {
+            return;
+        }
+        let side = TILE + 2 * radius;
+        let (left, top) = (thread::block_idx_x() * TILE, thread::block_idx_y() * TILE);
+        let r = radius as i32;
+        load_tile(&src, tile, left as i32 - r, top as i32 - r, side, side);
+
+        let (tx, ty) = (thread::thread_idx_x(), thread::thread_idx_y());
+        let (x, y) = (left + tx, top + ty);
+        if x >= dst.width() || y >= dst.height() {
+            return;
+        }
+        let size = 2 * radius + 1;
+        let mut sum = T::ZERO;
+        for j in 0..size {
+            let row = tile.add(((ty + j) * side + tx) as usize);
+            for i in 0..size {
+                sum = (*row.add(i as usize)).weighted_add(weights[(j * size + i) as usize], sum);
+            }
+        }
+        dst.write(x, y, sum);
+    }
+
+    #[inline(always)]
+    unsafe fn filter_rows<T: FilterPixel>(
+        src: Plane<T>,
+        dst: Plane<T>,
+        weights: &[f32],
+        tile: *mut T,
+    ) {
+        let radius = weights.len() as u32 / 2;
+        if radius > MAX_SEPARABLE_RADIUS || src.width() == 0 || src.height() == 0 {
+            return;
+        }
+        let (block_width, block_height) = SEPARABLE_BLOCK;
+        let width = SEPARABLE_SPAN + 2 * radius;
+        let (left, top) = (
+            thread::block_idx_x() * SEPARABLE_SPAN,
+            thread::block_idx_y() * block_height,
+        );
+        load_tile(
+            &src,
+            tile,
+            left as i32 - radius as i32,
+            top as i32,
+            width,
+            block_height,
+        );
+
+        let (tx, ty) = (thread::thread_idx_x(), thread::thread_idx_y());
+        let y = top + ty;
+        if y >= dst.height() {
+            return;
+        }
+        for k in 0..OUTPUTS_PER_THREAD {
+            // the lanes of a warp write consecutive pixels.
+            let column = tx + k * block_width;
+            let x = left + column;
+            if x >= dst.width() {
+                break;
+            }
+            let row = tile.add((ty * width + column) as usize);
+            let mut sum = T::ZERO;
+            for (i, &weight) in weights.iter().enumerate() {
+                sum = (*row.add(i)).weighted_add(weight, sum);
+            }
+            dst.write(x, y, sum);
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn filter_columns<T: FilterPixel>(
+        src: Plane<T>,
+        dst: Plane<T>,
+        weights: &[f32],
+        tile: *mut T,
+    ) {
+        let radius = weights.len() as u32 / 2;
+        if radius > MAX_SEPARABLE_RADIUS || src.width() == 0 || src.height() == 0 {
+            return;
+        }
+        let (block_width, block_height) = SEPARABLE_BLOCK;
+        let height = block_height * OUTPUTS_PER_THREAD + 2 * radius;
+        let (left, top) = (
+            thread::block_idx_x() * block_width,
+            thread::block_idx_y() * block_height * OUTPUTS_PER_THREAD,
+        );
+        load_tile(
+            &src,
+            tile,
+            left as i32,
+            top as i32 - radius as i32,
+            block_width,
+            height,
+        );
+
+        let (tx, ty) = (thread::thread_idx_x(), thread::thread_idx_y());
+        let x = left + tx;
+        if x >= dst.width() {
+            return;
+        }
+        for k in 0..OUTPUTS_PER_THREAD {
+            let row = ty + k * block_height;
+            let y = top + row;
+            if y >= dst.height() {
+                break;
+            }
+            let mut sum = T::ZERO;
+            for (j, &weight) in weights.iter().enumerate() {
+                let pixel = *tile.add(((row + j as u32) * block_width + tx) as usize);
+                sum = pixel.weighted_add(weight, sum);
+            }
+            dst.write(x, y, sum);
+        }
+    }
+
+    macro_rules! filter_kernels {
+        ($($ty:ty: $convolve:ident, $rows:ident, $columns:ident;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $convolve(src: Plane<$ty>, dst: Plane<$ty>, weights: &[f32], radius: u32) {
+                    let tile = shared_array![$ty; CONVOLVE_TILE_LEN];
+                    convolve(src, dst, weights, radius, tile);
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $rows(src: Plane<$ty>, dst: Plane<$ty>, weights: &[f32]) {
+                    let tile = shared_array![$ty; ROWS_TILE_LEN];
+                    filter_rows(src, dst, weights, tile);
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $columns(src: Plane<$ty>, dst: Plane<$ty>, weights: &[f32]) {
+                    let tile = shared_array![$ty; COLUMNS_TILE_LEN];
+                    filter_columns(src, dst, weights, tile);
+                }
+            )*
+        };
+    }
+
+    filter_kernels! {
+        f32: image_convolve_f32, image_filter_rows_f32, image_filter_columns_f32;
+        [f32; 4]: image_convolve_rgba32f, image_filter_rows_rgba32f, image_filter_columns_rgba32f;
+    }
+
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn image_sobel_f32(
+        src: Plane<f32>,
+        gradients: Plane<[f32; 2]>,
+        magnitudes: Plane<f32>,
+    ) {
+        if src.width() == 0 || src.height() == 0 {
+            return;
+        }
+        let tile = shared_array![f32; SOBEL_TILE_LEN];
+        let side = TILE + 2;
+        let (left, top) = (thread::block_idx_x() * TILE, thread::block_idx_y() * TILE);
+        load_tile(&src, tile, left as i32 - 1, top as i32 - 1, side, side);
+
+        let (tx, ty) = (thread::thread_idx_x(), thread::thread_idx_y());
+        let (x, y) = (left + tx, top + ty);
+        if x >= src.width() || y >= src.height() {
+            return;
+        }
+        let p = |i: u32, j: u32| *tile.add(((ty + j) * side + tx + i) as usize);
+        let gx = (p(2, 0) + 2.0 * p(2, 1) + p(2, 2)) - (p(0, 0) + 2.0 * p(0, 1) + p(0, 2));
+        let gy = (p(0, 2) + 2.0 * p(1, 2) + p(2, 2)) - (p(0, 0) + 2.0 * p(1, 0) + p(2, 0));
+        if !gradients.is_null() {
+            gradients.write(x, y, [gx, gy]);
+        }
+        if !magnitudes.is_null() {
+            magnitudes.write(x, y, (gx * gx + gy * gy).sqrt());
+        }
+    }
+}
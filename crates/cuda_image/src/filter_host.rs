@@ -0,0 +1,341 @@
+use crate::filter::{
+    FilterPixel, Plane, MAX_RADIUS, MAX_SEPARABLE_RADIUS, OUTPUTS_PER_THREAD, SEPARABLE_BLOCK, TILE,
+};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceBuffer, DeviceCopy, DevicePitchedBuffer},
+    module::Module,
+    params,
+    stream::Stream,
+};
+use std::marker::PhantomData;
+
+/// The plane of `buffer`, which the kernels only write through if it was borrowed mutably.
+fn plane<T: DeviceCopy>(buffer: &DevicePitchedBuffer<T>) -> Plane<T> {
+    unsafe {
+        Plane::from_raw_parts(
+            buffer.as_ptr() as *mut T,
+            buffer.width() as u32,
+            buffer.height() as u32,
+            buffer.pitch(),
+        )
+    }
+}
+
+fn assert_same_size<T: DeviceCopy, U: DeviceCopy>(
+    a: &DevicePitchedBuffer<T>,
+    b: &DevicePitchedBuffer<U>,
+) {
+    assert_eq!(
+        (a.width(), a.height()),
+        (b.width(), b.height()),
+        "the images have different sizes"
+    );
+}
+
+/// The weights of a 2D filter in device memory, for [`ImageFilters::convolve`].
+pub struct Filter2d {
+    radius: u32,
+    weights: DeviceBuffer<f32>,
+}
+
+impl Filter2d {
+    /// Uploads a filter of `2 * radius + 1` rows of `2 * radius + 1` weights, see the
+    /// [`filter`](crate::filter) module for how they are applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `radius` is larger than [`MAX_RADIUS`] or `weights` has the wrong length.
+    pub fn new(radius: usize, weights: &[f32]) -> CudaResult<Self> {
+        assert!(
+            radius <= MAX_RADIUS as usize,
+            "the radius of 2D filters is at most {}",
+            MAX_RADIUS
+        );
+        let size = 2 * radius + 1;
+        assert_eq!(
+            weights.len(),
+            size * size,
+            "a filter with radius {} has {} weights",
+            radius,
+            size * size
+        );
+        Ok(Self {
+            radius: radius as u32,
+            weights: DeviceBuffer::from_slice(weights)?,
+        })
+    }
+
+    /// The mean of the `(2 * radius + 1)^2` pixels around every pixel.
+    pub fn mean(radius: usize) -> CudaResult<Self> {
+        let size = 2 * radius + 1;
+        let weight = 1.0 / (size * size) as f32;
+        Self::new(radius, &vec![weight; size * size])
+    }
+
+    pub fn radius(&self) -> usize {
+        self.radius as usize
+    }
+}
+
+/// The weights of a separable filter in device memory, one filter along the rows and one along the
+/// columns, for [`ImageFilters::separable`].
+pub struct SeparableFilter {
+    rows: DeviceBuffer<f32>,
+    columns: DeviceBuffer<f32>,
+}
+
+impl SeparableFilter {
+    /// Uploads a filter of `row_weights` along the rows and `column_weights` along the columns.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of weights of a filter is even or more than `2 * MAX_SEPARABLE_RADIUS + 1`.
+    pub fn new(row_weights: &[f32], column_weights: &[f32]) -> CudaResult<Self> {
+        for weights in [row_weights, column_weights] {
+            assert!(
+                weights.len() % 2 == 1,
+                "separable filters have an odd number of weights"
+            );
+            assert!(
+                weights.len() / 2 <= MAX_SEPARABLE_RADIUS as usize,
+                "the radius of separable filters is at most {}",
+                MAX_SEPARABLE_RADIUS
+            );
+        }
+        Ok(Self {
+            rows: DeviceBuffer::from_slice(row_weights)?,
+            columns: DeviceBuffer::from_slice(column_weights)?,
+        })
+    }
+
+    /// The Gaussian blur with the standard deviation `sigma`, see [`gaussian_weights`].
+    pub fn gaussian(sigma: f32) -> CudaResult<Self> {
+        let weights = gaussian_weights(sigma);
+        Self::new(&weights, &weights)
+    }
+}
+
+/// The weights of a 1D Gaussian with the standard deviation `sigma` in pixels, normalized to sum to
+/// one, with a radius of `ceil(3 sigma)`.
+///
+/// # Panics
+///
+/// Panics if `sigma` is not positive or the radius would be larger than [`MAX_SEPARABLE_RADIUS`].
+pub fn gaussian_weights(sigma: f32) -> Vec<f32> {
+    assert!(sigma > 0.0, "`sigma` must be positive");
+    let radius = (3.0 * sigma).ceil() as i32;
+    assert!(
+        radius <= MAX_SEPARABLE_RADIUS as i32,
+        "a sigma of {} needs a radius larger than {}",
+        sigma,
+        MAX_SEPARABLE_RADIUS
+    );
+    let weights = (-radius..=radius)
+        .map(|x| (-(x * x) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f32>();
+    weights.into_iter().map(|w| w / sum).collect()
+}
+
+/// Launches the convolution kernels of the [`filter`](crate::filter) module on images of `T`
+/// pixels, `f32` or `[f32; 4]`.
+///
+/// ```ignore
+/// let filters = ImageFilters::<f32>::new(&module)?;
+/// let blur = SeparableFilter::gaussian(2.0)?;
+/// filters.separable(&stream, &image, &blur, &mut scratch, &mut blurred)?;
+/// ```
+pub struct ImageFilters<'a, T: FilterPixel + DeviceCopy> {
+    convolve: Function<'a>,
+    rows: Function<'a>,
+    columns: Function<'a>,
+    _pixel: PhantomData<T>,
+}
+
+impl<'a, T: FilterPixel + DeviceCopy> ImageFilters<'a, T> {
+    /// `module` must be the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn new(module: &'a Module) -> CudaResult<Self> {
+        let function = |name: &str| module.get_function(format!("image_{}_{}", name, T::SUFFIX));
+        Ok(Self {
+            convolve: function("convolve")?,
+            rows: function("filter_rows")?,
+            columns: function("filter_columns")?,
+            _pixel: PhantomData,
+        })
+    }
+
+    /// Enqueues applying `filter` to `src` on `stream`, writing the result to `dst`, which must have
+    /// the same size.
+    pub fn convolve(
+        &self,
+        stream: &Stream,
+        src: &DevicePitchedBuffer<T>,
+        filter: &Filter2d,
+        dst: &mut DevicePitchedBuffer<T>,
+    ) -> CudaResult<()> {
+        assert_same_size(src, dst);
+        let (mut src_plane, mut dst_plane) = (plane(src), plane(dst));
+        let (mut weights, mut weights_len) = (filter.weights.as_ptr(), filter.weights.len());
+        let mut radius = filter.radius;
+        let params = params!(src_plane, dst_plane, weights, weights_len, radius);
+        let grid = (
+            blocks_for(dst.width(), TILE),
+            blocks_for(dst.height(), TILE),
+        );
+        unsafe { stream.launch(&self.convolve, grid, (TILE, TILE), 0, &params) }
+    }
+
+    /// Enqueues applying the separable `filter` to `src` on `stream`: its row filter into `scratch`,
+    /// then its column filter into `dst`. Both must have the same size as `src`.
+    pub fn separable(
+        &self,
+        stream: &Stream,
+        src: &DevicePitchedBuffer<T>,
+        filter: &SeparableFilter,
+        scratch: &mut DevicePitchedBuffer<T>,
+        dst: &mut DevicePitchedBuffer<T>,
+    ) -> CudaResult<()> {
+        assert_same_size(src, scratch);
+        assert_same_size(src, dst);
+        let (width, height) = (src.width(), src.height());
+        let (block_width, block_height) = SEPARABLE_BLOCK;
+
+        let (mut src_plane, mut scratch_plane) = (plane(src), plane(scratch));
+        let (mut weights, mut weights_len) = (filter.rows.as_ptr(), filter.rows.len());
+        let params = params!(src_plane, scratch_plane, weights, weights_len);
+        let grid = (
+            blocks_for(width, block_width * OUTPUTS_PER_THREAD),
+            blocks_for(height, block_height),
+        );
+        unsafe {
+            stream.launch(&self.rows, grid, SEPARABLE_BLOCK, 0, &params)?;
+        }
+
+        let mut dst_plane = plane(dst);
+        let (mut weights, mut weights_len) = (filter.columns.as_ptr(), filter.columns.len());
+        let params = params!(scratch_plane, dst_plane, weights, weights_len);
+        let grid = (
+            blocks_for(width, block_width),
+            blocks_for(height, block_height * OUTPUTS_PER_THREAD),
+        );
+        unsafe { stream.launch(&self.columns, grid, SEPARABLE_BLOCK, 0, &params) }
+    }
+}
+
+/// Launches the Sobel kernel of the [`filter`](crate::filter) module, the usual first step of edge
+/// detection.
+pub struct SobelFilter<'a> {
+    sobel: Function<'a>,
+}
+
+impl<'a> SobelFilter<'a> {
+    /// `module` must be the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn new(module: &'a Module) -> CudaResult<Self> {
+        Ok(Self {
+            sobel: module.get_function("image_sobel_f32")?,
+        })
+    }
+
+    /// Enqueues computing the Sobel derivatives `[dx, dy]` of `src` into `gradients` and their lengths
+    /// into `magnitudes` on `stream`, either of which may be left out. Both must have the same size
+    /// as `src`.
+    pub fn apply(
+        &self,
+        stream: &Stream,
+        src: &DevicePitchedBuffer<f32>,
+        gradients: Option<&mut DevicePitchedBuffer<[f32; 2]>>,
+        magnitudes: Option<&mut DevicePitchedBuffer<f32>>,
+    ) -> CudaResult<()> {
+        let mut src_plane = plane(src);
+        let mut gradients = match gradients {
+            Some(gradients) => {
+                assert_same_size(src, gradients);
+                plane(gradients)
+            }
+            None => unsafe { Plane::from_raw_parts(std::ptr::null_mut(), 0, 0, 0) },
+        };
+        let mut magnitudes = match magnitudes {
+            Some(magnitudes) => {
+                assert_same_size(src, magnitudes);
+                plane(magnitudes)
+            }
+            None => unsafe { Plane::from_raw_parts(std::ptr::null_mut(), 0, 0, 0) },
+        };
+        let params = params!(src_plane, gradients, magnitudes);
+        let grid = (
+            blocks_for(src.width(), TILE),
+            blocks_for(src.height(), TILE),
+        );
+        unsafe { stream.launch(&self.sobel, grid, (TILE, TILE), 0, &params) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_gaussians() {
+        for sigma in [0.2, 0.5, 1.0, 2.5, 8.0] {
+            let weights = gaussian_weights(sigma);
+            let radius = (3.0 * sigma).ceil() as usize;
+            assert_eq!(weights.len(), 2 * radius + 1, "sigma {}", sigma);
+            assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+            for i in 0..radius {
+                assert_eq!(weights[i], weights[weights.len() - 1 - i]);
+                assert!(weights[i] < weights[i + 1]);
+            }
+        }
+        // the largest sigma which fits.
+        let sigma = MAX_SEPARABLE_RADIUS as f32 / 3.0;
+        assert_eq!(
+            gaussian_weights(sigma).len(),
+            2 * MAX_SEPARABLE_RADIUS as usize + 1
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "positive")]
+    fn rejects_zero_sigmas() {
+        gaussian_weights(0.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "positive")]
+    fn rejects_nan_sigmas() {
+        gaussian_weights(f32::NAN);
+    }
+
+    #[test]
+    #[should_panic(expected = "needs a radius larger than")]
+    fn rejects_wide_gaussians() {
+        gaussian_weights(MAX_SEPARABLE_RADIUS as f32 / 3.0 + 0.1);
+    }
+
+    #[test]
+    #[should_panic(expected = "the radius of 2D filters is at most")]
+    fn rejects_wide_filters() {
+        let size = 2 * MAX_RADIUS as usize + 3;
+        let _ = Filter2d::new(MAX_RADIUS as usize + 1, &vec![0.0; size * size]);
+    }
+
+    #[test]
+    #[should_panic(expected = "a filter with radius 1 has 9 weights")]
+    fn rejects_filters_of_the_wrong_length() {
+        let _ = Filter2d::new(1, &[0.0; 8]);
+    }
+
+    #[test]
+    #[should_panic(expected = "odd number of weights")]
+    fn rejects_even_separable_filters() {
+        let _ = SeparableFilter::new(&[1.0], &[0.5, 0.5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "the radius of separable filters is at most")]
+    fn rejects_wide_separable_filters() {
+        let _ = SeparableFilter::new(&[0.0; 2 * MAX_SEPARABLE_RADIUS as usize + 3], &[1.0]);
+    }
+}
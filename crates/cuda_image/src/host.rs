@@ -0,0 +1,117 @@
+use crate::{pixel::Pixel, view::ImageView};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceCopy, DevicePitchedBuffer, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+
+/// The width and height of the blocks of the conversion kernel.
+const BLOCK: u32 = 16;
+
+/// An image of `P` pixels in device memory, with padded rows like every [`DevicePitchedBuffer`].
+#[derive(Debug)]
+pub struct DeviceImage<P: Pixel> {
+    pixels: DevicePitchedBuffer<P>,
+}
+
+impl<P: Pixel + DeviceCopy> DeviceImage<P> {
+    /// Allocates an image of `width * height` transparent black pixels.
+    pub fn zeroed(width: usize, height: usize) -> CudaResult<Self> {
+        // all-zeroes is a valid pixel of every format.
+        let pixels = unsafe { DevicePitchedBuffer::zeroed(width, height)? };
+        Ok(Self { pixels })
+    }
+
+    /// The number of pixels in every row.
+    pub fn width(&self) -> usize {
+        self.pixels.width()
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> usize {
+        self.pixels.height()
+    }
+
+    /// The distance between the starts of two consecutive rows, in bytes.
+    pub fn pitch(&self) -> usize {
+        self.pixels.pitch()
+    }
+
+    /// The view of the image for writing it in kernels.
+    pub fn view(&mut self) -> ImageView {
+        let (width, height, pitch) = (self.width(), self.height(), self.pitch());
+        unsafe {
+            ImageView::from_raw_parts(
+                self.pixels.as_device_ptr().as_raw_mut() as *mut u8,
+                width as u32,
+                height as u32,
+                pitch,
+                P::FORMAT,
+            )
+        }
+    }
+
+    /// The buffer holding the pixels, for copying them in other ways.
+    pub fn pixels(&self) -> &DevicePitchedBuffer<P> {
+        &self.pixels
+    }
+
+    /// The buffer holding the pixels, for copying them in other ways.
+    pub fn pixels_mut(&mut self) -> &mut DevicePitchedBuffer<P> {
+        &mut self.pixels
+    }
+
+    /// Copies the pixels to `dest`, which must hold `width * height` pixels.
+    pub fn copy_to(&self, dest: &mut [P]) -> CudaResult<()> {
+        self.pixels.copy_to(dest)
+    }
+
+    /// Copies the pixels to a new `Vec` of `width * height` pixels.
+    pub fn as_host_vec(&self) -> CudaResult<Vec<P>> {
+        self.pixels.as_host_vec()
+    }
+}
+
+/// Launches the conversion kernel of this crate.
+pub struct ImageConverter<'a> {
+    convert: Function<'a>,
+}
+
+impl<'a> ImageConverter<'a> {
+    /// `module` must be the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn new(module: &'a Module) -> CudaResult<Self> {
+        Ok(Self {
+            convert: module.get_function("image_convert")?,
+        })
+    }
+
+    /// Enqueues converting the linear colors `src`, one per pixel in rows of `dst.width()`, times
+    /// `scale` to the format of `dst` on `stream`.
+    pub fn convert<P: Pixel + DeviceCopy>(
+        &self,
+        stream: &Stream,
+        src: &DeviceSlice<[f32; 3]>,
+        scale: f32,
+        dst: &mut DeviceImage<P>,
+    ) -> CudaResult<()> {
+        assert_eq!(
+            src.len(),
+            dst.width() * dst.height(),
+            "one color per pixel of the image"
+        );
+        let mut src = src.as_ptr();
+        let mut scale = scale;
+        let mut dst = dst.view();
+        let grid = (
+            blocks_for(dst.width() as usize, BLOCK),
+            blocks_for(dst.height() as usize, BLOCK),
+        );
+        unsafe {
+            let params = params!(src, scale, dst);
+            stream.launch(&self.convert, grid, (BLOCK, BLOCK), 0, &params)
+        }
+    }
+}
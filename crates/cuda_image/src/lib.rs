@@ -0,0 +1,73 @@
+//! Images in device memory, the conversion of linear colors to pixel formats, image filters, and
+//! saving images as PNG or OpenEXR.
+//!
+//! Renderers and image filters usually compute linear colors in `f32`, but present or save pixels
+//! of a smaller [`Format`](pixel::Format): 8-bit sRGB for display and PNGs, half floats for HDR
+//! textures and EXRs. The [`pixel`] module has the conversions, which work on both sides, and
+//! [`ImageView`] lets kernels write converted pixels into the pitched rows of a [`DeviceImage`].
+//! The [`filter`] module has tiled convolutions, the separable Gaussian blur and the Sobel operator
+//! over `f32` and `[f32; 4]` images.
+//!
+//! Like `cuda_linalg`, this crate is used from both sides: the gpu crate depends on it for
+//! [`ImageView`] and to get the conversion and filter kernels into its PTX (with the default
+//! `kernels` feature), and the host crate to allocate and save the images.
+//!
+//! ```ignore
+//! // host
+//! let module = Module::from_str(PTX)?;
+//! let converter = ImageConverter::new(&module)?;
+//! let mut image = DeviceImage::<Rgba8>::zeroed(width, height)?;
+//! converter.convert(&stream, &colors, 1.0 / samples as f32, &mut image)?;
+//! stream.synchronize()?;
+//! let pixels = image.as_host_vec()?;
+//! cuda_image::save("image.png", width, height, &pixels)?;
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+pub mod filter;
+pub mod pixel;
+pub mod view;
+
+pub use filter::{FilterPixel, Plane};
+pub use pixel::{Format, Pixel};
+pub use view::ImageView;
+
+#[cfg(not(target_os = "cuda"))]
+mod filter_host;
+#[cfg(not(target_os = "cuda"))]
+mod host;
+#[cfg(not(target_os = "cuda"))]
+pub mod save;
+
+#[cfg(not(target_os = "cuda"))]
+pub use filter_host::*;
+#[cfg(not(target_os = "cuda"))]
+pub use host::*;
+#[cfg(not(target_os = "cuda"))]
+pub use save::{save, SaveError};
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use crate::ImageView;
+    use cuda_std::{kernel, thread};
+
+    /// Converts a dense buffer of linear colors, one per pixel of `dst` in rows of `dst.width()`,
+    /// to the format of `dst`. The colors are multiplied by `scale`, such as one over the number of
+    /// samples of a renderer accumulating samples.
+    #[kernel]
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn image_convert(src: *const [f32; 3], scale: f32, dst: ImageView) {
+        let idx = thread::index_2d();
+        if idx.x >= dst.width() || idx.y >= dst.height() {
+            return;
+        }
+        let [r, g, b] = *src.add(idx.y as usize * dst.width() as usize + idx.x as usize);
+        dst.write(idx.x, idx.y, [r * scale, g * scale, b * scale]);
+    }
+}
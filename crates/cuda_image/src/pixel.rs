@@ -0,0 +1,199 @@
+//! Pixel formats and the conversion of linear colors to them.
+//!
+//! Colors are linear RGB `[f32; 3]`, the pixels always have an alpha channel (which is opaque when
+//! converting colors) so that their rows are aligned the way textures and image formats expect.
+
+#[cfg(target_os = "cuda")]
+use cuda_std::GpuFloat;
+use half::f16;
+
+/// An 8-bit sRGB encoded pixel, as displayed and saved in PNGs.
+pub type Rgba8 = [u8; 4];
+/// A linear pixel of half-precision floats (`Rgba16F` in graphics APIs), for HDR textures and EXRs.
+pub type Rgba16F = [f16; 4];
+/// A linear pixel of single-precision floats.
+pub type Rgba32F = [f32; 4];
+
+/// The format of the pixels of an image.
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum Format {
+    /// [`Rgba8`] pixels, sRGB encoded and clamped to `[0, 1]`.
+    Rgba8Srgb,
+    /// Linear [`Rgba16F`] pixels, values too large for a half become infinity.
+    Rgba16F,
+    /// Linear [`Rgba32F`] pixels.
+    Rgba32F,
+}
+
+impl Format {
+    /// The size of one pixel in bytes.
+    pub const fn pixel_size(self) -> usize {
+        match self {
+            Self::Rgba8Srgb => 4,
+            Self::Rgba16F => 8,
+            Self::Rgba32F => 16,
+        }
+    }
+}
+
+/// The pixel types of the [`Format`]s.
+pub trait Pixel: Copy {
+    const FORMAT: Format;
+
+    /// Converts a linear color to an opaque pixel.
+    fn from_linear(color: [f32; 3]) -> Self;
+
+    /// The linear color and alpha of the pixel.
+    fn to_linear(self) -> [f32; 4];
+
+    /// The pixel as 8-bit sRGB, clamped to `[0, 1]`.
+    fn to_srgb8(self) -> Rgba8 {
+        let [r, g, b, a] = self.to_linear();
+        [
+            encode8(srgb_encode(r)),
+            encode8(srgb_encode(g)),
+            encode8(srgb_encode(b)),
+            encode8(a),
+        ]
+    }
+}
+
+impl Pixel for Rgba8 {
+    const FORMAT: Format = Format::Rgba8Srgb;
+
+    fn from_linear([r, g, b]: [f32; 3]) -> Self {
+        [
+            encode8(srgb_encode(r)),
+            encode8(srgb_encode(g)),
+            encode8(srgb_encode(b)),
+            u8::MAX,
+        ]
+    }
+
+    fn to_linear(self) -> [f32; 4] {
+        let [r, g, b, a] = self.map(|c| c as f32 / u8::MAX as f32);
+        [srgb_decode(r), srgb_decode(g), srgb_decode(b), a]
+    }
+
+    fn to_srgb8(self) -> Rgba8 {
+        self
+    }
+}
+
+impl Pixel for Rgba16F {
+    const FORMAT: Format = Format::Rgba16F;
+
+    fn from_linear([r, g, b]: [f32; 3]) -> Self {
+        [
+            f16::from_f32(r),
+            f16::from_f32(g),
+            f16::from_f32(b),
+            f16::ONE,
+        ]
+    }
+
+    fn to_linear(self) -> [f32; 4] {
+        let [r, g, b, a] = self;
+        [r.to_f32(), g.to_f32(), b.to_f32(), a.to_f32()]
+    }
+}
+
+impl Pixel for Rgba32F {
+    const FORMAT: Format = Format::Rgba32F;
+
+    fn from_linear([r, g, b]: [f32; 3]) -> Self {
+        [r, g, b, 1.0]
+    }
+
+    fn to_linear(self) -> [f32; 4] {
+        self
+    }
+}
+
+/// Encodes a linear channel with the sRGB transfer function, clamped to `[0, 1]`.
+pub fn srgb_encode(linear: f32) -> f32 {
+    let x = linear.clamp(0.0, 1.0);
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decodes an sRGB encoded channel in `[0, 1]` to a linear one.
+pub fn srgb_decode(encoded: f32) -> f32 {
+    if encoded <= 0.04045 {
+        encoded / 12.92
+    } else {
+        ((encoded + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Quantizes a channel in `[0, 1]` to 8 bits.
+fn encode8(x: f32) -> u8 {
+    (x.clamp(0.0, 1.0) * u8::MAX as f32 + 0.5) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_srgb() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-6);
+        for i in 0..=1000 {
+            let linear = i as f32 / 1000.0;
+            let encoded = srgb_encode(linear);
+            assert!((0.0..=1.0 + 1e-6).contains(&encoded));
+            assert!((srgb_decode(encoded) - linear).abs() < 1e-5, "{}", linear);
+        }
+        // both pieces meet at the threshold.
+        assert!((srgb_encode(0.0031308) - 0.04045).abs() < 1e-5);
+        assert!((srgb_decode(0.04045) - 0.0031308).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clamps_srgb() {
+        assert_eq!(srgb_encode(-1.0), 0.0);
+        assert_eq!(srgb_encode(5.0), srgb_encode(1.0));
+        assert_eq!(Rgba8::from_linear([-1.0, 0.5, 5.0]), [0, 188, 255, 255]);
+    }
+
+    #[test]
+    fn round_trips_rgba8() {
+        for c in 0..=u8::MAX {
+            let px: Rgba8 = [c, c, c, u8::MAX];
+            let [r, g, b, a] = px.to_linear();
+            assert_eq!(a, 1.0);
+            assert_eq!(Rgba8::from_linear([r, g, b]), px);
+            assert_eq!(px.to_srgb8(), px);
+        }
+    }
+
+    #[test]
+    fn converts_float_pixels() {
+        let px = Rgba16F::from_linear([0.5, 2.0, 1e6]);
+        assert_eq!(px.to_linear(), [0.5, 2.0, f32::INFINITY, 1.0]);
+        assert_eq!(
+            Rgba32F::from_linear([0.5, 2.0, -1.0]),
+            [0.5, 2.0, -1.0, 1.0]
+        );
+        // sRGB encoded and clamped, the alpha stays linear.
+        assert_eq!([1.0, 0.0, 0.5, 0.5].to_srgb8(), [255, 0, 188, 128]);
+        assert_eq!(
+            Rgba16F::from_linear([2.0, 0.0, 0.5]).to_srgb8(),
+            [255, 0, 188, 255]
+        );
+    }
+
+    #[test]
+    fn sizes_formats() {
+        use core::mem::size_of;
+        assert_eq!(Rgba8::FORMAT.pixel_size(), size_of::<Rgba8>());
+        assert_eq!(Rgba16F::FORMAT.pixel_size(), size_of::<Rgba16F>());
+        assert_eq!(Rgba32F::FORMAT.pixel_size(), size_of::<Rgba32F>());
+    }
+}
@@ -0,0 +1,259 @@
+//! Saving images as PNG or OpenEXR.
+//!
+//! The pixels are in rows of `width` pixels starting with the top row, like in image files. PNGs
+//! are 8-bit sRGB, so colors brighter than white are clipped, EXRs keep the linear colors as
+//! half floats.
+
+use crate::pixel::Pixel;
+use image::{codecs::png::PngEncoder, ColorType, ImageError};
+use std::{
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+/// An error saving an image.
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Png(ImageError),
+    /// The extension of the path is not one of a supported format.
+    UnsupportedFormat(PathBuf),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(err) => err.fmt(f),
+            Self::Png(err) => err.fmt(f),
+            Self::UnsupportedFormat(path) => {
+                write!(f, "unsupported image format: {}", path.display())
+            }
+        }
+    }
+}
+
+impl Error for SaveError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            Self::Png(err) => Some(err),
+            Self::UnsupportedFormat(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<ImageError> for SaveError {
+    fn from(err: ImageError) -> Self {
+        Self::Png(err)
+    }
+}
+
+/// Saves an image of `width * height` pixels to `path`, as PNG or EXR depending on its extension.
+pub fn save<P: Pixel>(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    pixels: &[P],
+) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    let png = match extension.as_deref() {
+        Some("png") => true,
+        Some("exr") => false,
+        _ => return Err(SaveError::UnsupportedFormat(path.to_owned())),
+    };
+
+    let mut file = BufWriter::new(File::create(path)?);
+    if png {
+        save_png(&mut file, width, height, pixels)?;
+    } else {
+        save_exr(&mut file, width, height, pixels)?;
+    }
+    file.flush()?;
+    Ok(())
+}
+
+/// Writes an image of `width * height` pixels as an RGBA PNG.
+pub fn save_png<P: Pixel>(
+    w: impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[P],
+) -> Result<(), ImageError> {
+    assert_eq!(pixels.len(), width * height);
+    let data = pixels
+        .iter()
+        .flat_map(|px| px.to_srgb8())
+        .collect::<Vec<_>>();
+    PngEncoder::new(w).encode(&data, width as u32, height as u32, ColorType::Rgba8)
+}
+
+/// Writes an image of `width * height` pixels as a scanline OpenEXR file with uncompressed
+/// half-float RGBA channels.
+pub fn save_exr<P: Pixel>(
+    mut w: impl Write,
+    width: usize,
+    height: usize,
+    pixels: &[P],
+) -> io::Result<()> {
+    use half::f16;
+
+    assert_eq!(pixels.len(), width * height);
+    // the channels are stored in alphabetical order, as indices into the pixels.
+    const CHANNELS: [(&str, usize); 4] = [("A", 3), ("B", 2), ("G", 1), ("R", 0)];
+    const HALF: i32 = 1;
+
+    let mut header = Vec::new();
+    let mut attribute = |name: &str, ty: &str, value: &[u8]| {
+        for s in [name, ty] {
+            header.extend_from_slice(s.as_bytes());
+            header.push(0);
+        }
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    };
+
+    let mut channels = Vec::new();
+    for (name, _) in CHANNELS {
+        channels.extend_from_slice(name.as_bytes());
+        channels.push(0);
+        channels.extend_from_slice(&HALF.to_le_bytes());
+        // pLinear and three reserved bytes, then the x and y sampling.
+        channels.extend_from_slice(&[0; 4]);
+        channels.extend_from_slice(&1i32.to_le_bytes());
+        channels.extend_from_slice(&1i32.to_le_bytes());
+    }
+    channels.push(0);
+    let window = [0, 0, width as i32 - 1, height as i32 - 1]
+        .iter()
+        .flat_map(|v: &i32| v.to_le_bytes())
+        .collect::<Vec<_>>();
+
+    attribute("channels", "chlist", &channels);
+    // no compression
+    attribute("compression", "compression", &[0]);
+    attribute("dataWindow", "box2i", &window);
+    attribute("displayWindow", "box2i", &window);
+    // increasing y
+    attribute("lineOrder", "lineOrder", &[0]);
+    attribute("pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute("screenWindowCenter", "v2f", &[0; 8]);
+    attribute("screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // magic number and version 2 (single part scanline file).
+    w.write_all(&[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0])?;
+    w.write_all(&header)?;
+
+    // every scanline is a chunk of its y coordinate, the size of its data, then the data.
+    let line_size = width * CHANNELS.len() * 2;
+    let first_line = 8 + header.len() + height * 8;
+    for y in 0..height {
+        let offset = first_line + y * (8 + line_size);
+        w.write_all(&(offset as u64).to_le_bytes())?;
+    }
+    for (y, row) in pixels.chunks_exact(width.max(1)).enumerate() {
+        w.write_all(&(y as i32).to_le_bytes())?;
+        w.write_all(&(line_size as i32).to_le_bytes())?;
+        let row = row.iter().map(|px| px.to_linear()).collect::<Vec<_>>();
+        for (_, channel) in CHANNELS {
+            for px in &row {
+                w.write_all(&f16::from_f32(px[channel]).to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel::Rgba32F;
+    use half::f16;
+
+    const WIDTH: usize = 3;
+    const HEIGHT: usize = 2;
+
+    fn pixels() -> Vec<Rgba32F> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| [i as f32 / 8.0, 0.5, 2.0, 1.0 - i as f32 / 8.0])
+            .collect()
+    }
+
+    fn read_i32(bytes: &[u8], at: usize) -> i32 {
+        i32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn saves_png() {
+        let pixels = pixels();
+        let mut png = Vec::new();
+        save_png(&mut png, WIDTH, HEIGHT, &pixels).unwrap();
+        let image = image::load_from_memory(&png).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (WIDTH as u32, HEIGHT as u32));
+        for (i, px) in pixels.iter().enumerate() {
+            let (x, y) = ((i % WIDTH) as u32, (i / WIDTH) as u32);
+            assert_eq!(image.get_pixel(x, y).0, px.to_srgb8());
+        }
+    }
+
+    #[test]
+    fn saves_exr() {
+        let pixels = pixels();
+        let mut exr = Vec::new();
+        save_exr(&mut exr, WIDTH, HEIGHT, &pixels).unwrap();
+        assert_eq!(exr[..8], [0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+
+        let find = |needle: &[u8]| exr.windows(needle.len()).position(|w| w == needle);
+        let window = [0, 0, WIDTH as i32 - 1, HEIGHT as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect::<Vec<_>>();
+        for name in ["dataWindow", "displayWindow"] {
+            let at = find(format!("{}\0box2i\0", name).as_bytes()).unwrap();
+            let value = at + name.len() + "box2i".len() + 2;
+            assert_eq!(read_i32(&exr, value), 16);
+            assert_eq!(exr[value + 4..value + 20], window[..]);
+        }
+        assert!(find(b"channels\0chlist\0").is_some());
+
+        // the offset table follows the header, and points at the chunks of the scanlines.
+        let line_size = WIDTH * 4 * 2;
+        let table = exr.len() - HEIGHT * (8 + 8 + line_size);
+        for y in 0..HEIGHT {
+            let offset =
+                u64::from_le_bytes(exr[table + y * 8..table + y * 8 + 8].try_into().unwrap());
+            let chunk = offset as usize;
+            assert_eq!(read_i32(&exr, chunk), y as i32);
+            assert_eq!(read_i32(&exr, chunk + 4), line_size as i32);
+            // the channels are in the order A, B, G, R.
+            for (c, channel) in [3, 2, 1, 0].into_iter().enumerate() {
+                for x in 0..WIDTH {
+                    let at = chunk + 8 + (c * WIDTH + x) * 2;
+                    let value = f16::from_le_bytes([exr[at], exr[at + 1]]);
+                    assert_eq!(value.to_f32(), pixels[y * WIDTH + x][channel]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_extensions() {
+        let err = save("image.bmp", 1, 1, &[[0u8; 4]]).unwrap_err();
+        assert!(
+            matches!(err, SaveError::UnsupportedFormat(path) if path == Path::new("image.bmp"))
+        );
+    }
+}
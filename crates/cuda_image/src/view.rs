@@ -0,0 +1,85 @@
+//! Writing the pixels of images in kernels.
+
+use crate::pixel::{Format, Pixel, Rgba16F, Rgba32F, Rgba8};
+
+/// An image with rows `pitch` bytes apart and pixels of a [`Format`] chosen at runtime, for
+/// writing it in kernels. See [`DeviceImage::view`](crate::DeviceImage::view).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ImageView {
+    data: *mut u8,
+    width: u32,
+    height: u32,
+    pitch: usize,
+    format: Format,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for ImageView {}
+
+impl ImageView {
+    /// # Safety
+    ///
+    /// `data` must be valid for writes of `height` rows `pitch` bytes apart, each of `width` pixels
+    /// of `format`, and aligned for these pixels. The rows must not be freed while the view is
+    /// used.
+    pub unsafe fn from_raw_parts(
+        data: *mut u8,
+        width: u32,
+        height: u32,
+        pitch: usize,
+        format: Format,
+    ) -> Self {
+        Self {
+            data,
+            width,
+            height,
+            pitch,
+            format,
+        }
+    }
+
+    /// The number of pixels in every row.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The distance between the starts of two consecutive rows, in bytes.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Converts the linear `color` to the format of the image and writes it to pixel `(x, y)`.
+    ///
+    /// # Safety
+    ///
+    /// `(x, y)` must be in bounds and no other thread may access the pixel at the same time.
+    pub unsafe fn write(&self, x: u32, y: u32, color: [f32; 3]) {
+        match self.format {
+            Format::Rgba8Srgb => self.write_pixel(x, y, Rgba8::from_linear(color)),
+            Format::Rgba16F => self.write_pixel(x, y, Rgba16F::from_linear(color)),
+            Format::Rgba32F => self.write_pixel(x, y, Rgba32F::from_linear(color)),
+        }
+    }
+
+    /// Writes `pixel` to pixel `(x, y)`, without conversion.
+    ///
+    /// # Safety
+    ///
+    /// `(x, y)` must be in bounds, `P` must be the pixel type of the format of the image and no other
+    /// thread may access the pixel at the same time.
+    pub unsafe fn write_pixel<P: Pixel>(&self, x: u32, y: u32, pixel: P) {
+        debug_assert!(P::FORMAT == self.format);
+        let row = self.data.add(y as usize * self.pitch) as *mut P;
+        *row.add(x as usize) = pixel;
+    }
+}
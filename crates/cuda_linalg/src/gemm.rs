@@ -0,0 +1,177 @@
+//! Tiled general matrix multiplication, `C = alpha * A * B + beta * C`.
+//!
+//! Matrices are row-major with a leading dimension (the distance between the starts of two rows, at
+//! least the number of columns), so that submatrices can be multiplied in place. Every block computes a
+//! [`TILE`]x[`TILE`] tile of `C` with one thread per element, walking over `K` one tile at a time
+//! through shared memory. On sm_80 and newer the next tiles of `A` and `B` are loaded with `cp.async`
+//! while the current ones are multiplied, older GPUs load them with regular loads.
+//!
+//! The kernels (`linalg_gemm_f32` and `linalg_gemm_f64`) are launched with blocks of
+//! `(TILE, TILE)` threads and a grid of `(ceil(n / TILE), ceil(m / TILE))` blocks, see
+//! `gemm_launch_config` on the host.
+
+use crate::Scalar;
+#[cfg(feature = "kernels")]
+use core::mem::MaybeUninit;
+#[cfg(target_feature = "sm_80")]
+use cuda_std::pipeline;
+use cuda_std::thread;
+
+/// The width and height of the tiles of `C` computed by every block.
+pub const TILE: usize = crate::TILE as usize;
+
+/// The number of elements of shared memory [`gemm_block`] needs for each of its two buffers, which hold
+/// two tiles of `A` or `B` each.
+pub const SHARED_LEN: usize = 2 * TILE * TILE;
+
+/// The dimensions and layout of a matrix multiplication.
+#[derive(Clone, Copy, Debug)]
+pub struct GemmShape {
+    /// The number of rows of `A` and `C`.
+    pub m: usize,
+    /// The number of columns of `B` and `C`.
+    pub n: usize,
+    /// The number of columns of `A` and rows of `B`.
+    pub k: usize,
+    pub lda: usize,
+    pub ldb: usize,
+    pub ldc: usize,
+}
+
+/// Loads one element of a tile, or zero if it is outside of the matrix.
+#[inline(always)]
+unsafe fn load<T: Scalar>(dst: *mut T, src: *const T, valid: bool) {
+    #[cfg(target_feature = "sm_80")]
+    pipeline::memcpy_async_zfill(dst, src, valid);
+    #[cfg(not(target_feature = "sm_80"))]
+    {
+        *dst = if valid { *src } else { T::ZERO };
+    }
+}
+
+#[inline(always)]
+unsafe fn commit() {
+    #[cfg(target_feature = "sm_80")]
+    pipeline::commit();
+}
+
+#[inline(always)]
+unsafe fn wait_prior(_pending: u32) {
+    #[cfg(target_feature = "sm_80")]
+    pipeline::wait_prior(_pending);
+}
+
+/// Computes the tile of `C` of this block, see the [module docs](self).
+///
+/// # Safety
+///
+/// - Every thread of the block must call this function, the block must be `(TILE, TILE)` threads.
+/// - `a`, `b` and `c` must be valid for the shape, `c` must not overlap `a` or `b`.
+/// - `a_shared` and `b_shared` must point to distinct shared memory of [`SHARED_LEN`] elements each,
+/// aligned to the size of `T`.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn gemm_block<T: Scalar>(
+    shape: GemmShape,
+    alpha: T,
+    a: *const T,
+    b: *const T,
+    beta: T,
+    c: *mut T,
+    a_shared: *mut T,
+    b_shared: *mut T,
+) {
+    let tx = thread::thread_idx_x() as usize;
+    let ty = thread::thread_idx_y() as usize;
+    let row = thread::block_idx_y() as usize * TILE + ty;
+    let col = thread::block_idx_x() as usize * TILE + tx;
+    let tiles = (shape.k + TILE - 1) / TILE;
+
+    // every thread loads one element of the tile of A and one of the tile of B.
+    let load_tiles = |t: usize, buffer: usize| {
+        let offset = buffer * TILE * TILE + ty * TILE + tx;
+        let a_col = t * TILE + tx;
+        let a_valid = row < shape.m && a_col < shape.k;
+        let a_src = if a_valid {
+            a.add(row * shape.lda + a_col)
+        } else {
+            a
+        };
+        load(a_shared.add(offset), a_src, a_valid);
+
+        let b_row = t * TILE + ty;
+        let b_valid = b_row < shape.k && col < shape.n;
+        let b_src = if b_valid {
+            b.add(b_row * shape.ldb + col)
+        } else {
+            b
+        };
+        load(b_shared.add(offset), b_src, b_valid);
+    };
+
+    let mut acc = T::ZERO;
+    if tiles > 0 {
+        load_tiles(0, 0);
+    }
+    commit();
+    for t in 0..tiles {
+        if t + 1 < tiles {
+            load_tiles(t + 1, (t + 1) % 2);
+        }
+        commit();
+        // wait for the tile `t`, but not for the one which was just started.
+        wait_prior(1);
+        thread::sync_threads();
+
+        let a_tile = a_shared.add((t % 2) * TILE * TILE + ty * TILE);
+        let b_tile = b_shared.add((t % 2) * TILE * TILE + tx);
+        for i in 0..TILE {
+            acc += *a_tile.add(i) * *b_tile.add(i * TILE);
+        }
+        // the buffer is overwritten by the loads of the next iteration.
+        thread::sync_threads();
+    }
+
+    if row < shape.m && col < shape.n {
+        let out = c.add(row * shape.ldc + col);
+        *out = if beta == T::ZERO {
+            alpha * acc
+        } else {
+            alpha * acc + beta * *out
+        };
+    }
+}
+
+#[cfg(feature = "kernels")]
+macro_rules! gemm_kernels {
+    ($($name:ident, $ty:ident;)*) => {
+        $(
+            /// `C = alpha * A * B + beta * C` for row-major matrices, see the [module docs](self).
+            #[cuda_std::kernel]
+            #[allow(improper_ctypes_definitions, clippy::missing_safety_doc, clippy::too_many_arguments)]
+            pub unsafe fn $name(
+                m: usize,
+                n: usize,
+                k: usize,
+                alpha: $ty,
+                a: *const $ty,
+                lda: usize,
+                b: *const $ty,
+                ldb: usize,
+                beta: $ty,
+                c: *mut $ty,
+                ldc: usize,
+            ) {
+                let a_shared = cuda_std::shared_array![$ty; SHARED_LEN];
+                let b_shared = cuda_std::shared_array![$ty; SHARED_LEN];
+                let shape = GemmShape { m, n, k, lda, ldb, ldc };
+                gemm_block(shape, alpha, a, b, beta, c, a_shared, b_shared);
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "kernels")]
+gemm_kernels! {
+    linalg_gemm_f32, f32;
+    linalg_gemm_f64, f64;
+}
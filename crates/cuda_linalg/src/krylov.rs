@@ -0,0 +1,85 @@
+//! Building blocks for matrix-free Krylov solvers: conjugate gradients (CG) for symmetric positive
+//! definite systems and BiCGStab for general ones.
+//!
+//! "Matrix-free" means that the matrix is never stored, the solvers only need a function which
+//! applies it to a vector, which is usually a stencil or another kernel written for the problem.
+//! The vector operations the solvers need are kernels of this crate:
+//!
+//! - `linalg_axpy_{f32,f64}(x: &[T], alpha: T, y: *mut T)`: `y += alpha * x`.
+//! - `linalg_xpay_{f32,f64}(x: &[T], alpha: T, y: *mut T)`: `y = x + alpha * y`.
+//! - `linalg_dot_{f32,f64}(x: &[T], y: &[T], partials: *mut T, ticket: *mut u32, out: *mut T)`:
+//!   the dot product, reduced across the grid in a fixed order with
+//!   [`collective::grid_map_reduce`](cuda_std::collective::grid_map_reduce), so that solvers
+//!   converge in the same number of iterations on every run. Blocks must have a multiple of 32
+//!   threads.
+//! - `linalg_bicgstab_p_{f32,f64}(r: &[T], v: &[T], beta: T, omega: T, p: *mut T)`:
+//!   `p = r + beta * (p - omega * v)`.
+//!
+//! On the host, [`Solver`](crate::Solver) runs both solvers with these kernels and an operator
+//! given as a closure.
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use core::mem::MaybeUninit;
+    use cuda_std::{collective, kernel, shared_array, thread};
+
+    macro_rules! vector_kernels {
+        ($($ty:ident: $axpy:ident, $xpay:ident, $dot:ident, $bicgstab_p:ident;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $axpy(x: &[$ty], alpha: $ty, y: *mut $ty) {
+                    let i = thread::index_1d() as usize;
+                    if let Some(x) = x.get(i) {
+                        *y.add(i) += alpha * *x;
+                    }
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $xpay(x: &[$ty], alpha: $ty, y: *mut $ty) {
+                    let i = thread::index_1d() as usize;
+                    if let Some(x) = x.get(i) {
+                        let y = &mut *y.add(i);
+                        *y = *x + alpha * *y;
+                    }
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $dot(
+                    x: &[$ty],
+                    y: &[$ty],
+                    partials: *mut $ty,
+                    ticket: *mut u32,
+                    out: *mut $ty,
+                ) {
+                    let scratch = shared_array![$ty; 32];
+                    let map = |i: usize| x[i] * y[i];
+                    let total =
+                        collective::grid_map_reduce(x.len(), 0.0, map, partials, ticket, scratch, |a, b| a + b);
+                    if let Some(total) = total {
+                        if thread::thread_idx_x() == 0 {
+                            *out = total;
+                        }
+                    }
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $bicgstab_p(r: &[$ty], v: &[$ty], beta: $ty, omega: $ty, p: *mut $ty) {
+                    let i = thread::index_1d() as usize;
+                    if i < r.len() {
+                        let p = &mut *p.add(i);
+                        *p = r[i] + beta * (*p - omega * v[i]);
+                    }
+                }
+            )*
+        };
+    }
+
+    vector_kernels! {
+        f32: linalg_axpy_f32, linalg_xpay_f32, linalg_dot_f32, linalg_bicgstab_p_f32;
+        f64: linalg_axpy_f64, linalg_xpay_f64, linalg_dot_f64, linalg_bicgstab_p_f64;
+    }
+}
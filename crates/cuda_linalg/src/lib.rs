@@ -0,0 +1,68 @@
+//! Linear algebra kernels written in Rust, a small pure-Rust alternative to cuBLAS for problems which
+//! are too small or too custom to be worth a cuBLAS call.
+//!
+//! - [`gemm`]: tiled matrix multiplication through shared memory, which uses `cp.async` to overlap
+//!   loading the next tiles with computing on sm_80 and newer.
+//! - [`small`]: solving, inverting and taking determinants of many small (3x3, 4x4) matrices, one per
+//!   thread.
+//! - [`sparse`]: sparse matrix-vector products in the CSR, ELL and hybrid formats, and on the host a
+//!   [`DeviceSparseMatrix`] which picks the format from the row lengths of the matrix.
+//! - [`krylov`]: the vector kernels of matrix-free conjugate gradients and BiCGStab, and on the host
+//!   a [`Solver`] which drives them with an operator given as a closure.
+//! - [`reduce`]: sums, minimums and maximums of vectors, and on the host a [`Reducer`] which reads
+//!   their results back without synchronizing the device.
+//!
+//! This crate is used from both sides: add it to the gpu crate to get the kernels into its PTX (with
+//! the default `kernels` feature) and the device functions, and to the host crate to launch them.
+//! Kernels are named `linalg_<operation>_<type>`, for example `linalg_gemm_f32`. Generic device
+//! algorithms (reductions and scans across warps, blocks and grids) live in `cuda_std::collective`, the
+//! kernels of this crate instantiate them for its types.
+//!
+//! ```ignore
+//! // host
+//! let module = Module::from_str(PTX)?;
+//! let mut solver = Solver::<f32>::new(&module, &stream)?;
+//! let result = solver.cg(|v, out| apply_laplacian(&stream, v, out), &b, &mut x, 1e-6, 1000)?;
+//! assert!(result.converged);
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+mod scalar;
+
+pub use scalar::Scalar;
+
+#[cfg(target_os = "cuda")]
+pub mod gemm;
+pub mod krylov;
+pub mod reduce;
+pub mod small;
+pub mod sparse;
+
+#[cfg(not(target_os = "cuda"))]
+mod reducer;
+#[cfg(not(target_os = "cuda"))]
+mod solver;
+#[cfg(not(target_os = "cuda"))]
+mod spmv;
+
+#[cfg(not(target_os = "cuda"))]
+pub use reducer::*;
+#[cfg(not(target_os = "cuda"))]
+pub use solver::*;
+#[cfg(not(target_os = "cuda"))]
+pub use spmv::*;
+
+/// The width and height of the tiles of the output computed by every block of the GEMM kernels.
+pub const TILE: u32 = 16;
+
+/// The launch configuration of `linalg_gemm_{f32,f64}` for an `m`x`n` output matrix.
+#[cfg(not(target_os = "cuda"))]
+pub fn gemm_launch_config(m: usize, n: usize) -> cust::function::LaunchConfig {
+    cust::function::LaunchConfig::for_len_xy(n, m, TILE, TILE)
+}
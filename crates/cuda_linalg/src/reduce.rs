@@ -0,0 +1,56 @@
+//! Reductions of whole vectors to a single number:
+//!
+//! - `linalg_sum_{f32,f64}(x: &[T], partials: *mut T, ticket: *mut u32, out: *mut T)`: the sum
+//!   of `x`, zero if it is empty.
+//! - `linalg_min_{f32,f64}(...)` and `linalg_max_{f32,f64}(...)`: the smallest and largest
+//!   element of `x`, infinity and negative infinity if it is empty. NaNs are ignored.
+//!
+//! Like the dot product of the [`krylov`](crate::krylov) module, they are thin wrappers around
+//! [`collective::grid_map_reduce`](cuda_std::collective::grid_map_reduce), which reduces across
+//! the grid in a fixed order, so the result is the same on every run. `partials` needs room for
+//! one element per block and `ticket` must be zero when the kernel is launched (it is reset by the
+//! kernel). Blocks must have a multiple of 32 threads.
+//!
+//! On the host, [`Reducer`](crate::Reducer) launches them and reads the result back without
+//! synchronizing the stream, through
+//! [`Reducer::reduce_to_host_async`](crate::Reducer::reduce_to_host_async).
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use core::mem::MaybeUninit;
+    use cuda_std::{collective, kernel, shared_array, thread};
+
+    macro_rules! reduce_kernels {
+        ($($ty:ident { $($name:ident => $identity:expr, |$a:ident, $b:ident| $op:expr;)* })*) => {
+            $($(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $name(x: &[$ty], partials: *mut $ty, ticket: *mut u32, out: *mut $ty) {
+                    let scratch = shared_array![$ty; 32];
+                    let op = |$a: $ty, $b: $ty| $op;
+                    let total =
+                        collective::grid_map_reduce(x.len(), $identity, |i| x[i], partials, ticket, scratch, op);
+                    if let Some(total) = total {
+                        if thread::thread_idx_x() == 0 {
+                            *out = total;
+                        }
+                    }
+                }
+            )*)*
+        };
+    }
+
+    // `b < a` and `b > a` are false for NaN, which keeps the accumulator.
+    reduce_kernels! {
+        f32 {
+            linalg_sum_f32 => 0.0, |a, b| a + b;
+            linalg_min_f32 => f32::INFINITY, |a, b| if b < a { b } else { a };
+            linalg_max_f32 => f32::NEG_INFINITY, |a, b| if b > a { b } else { a };
+        }
+        f64 {
+            linalg_sum_f64 => 0.0, |a, b| a + b;
+            linalg_min_f64 => f64::INFINITY, |a, b| if b < a { b } else { a };
+            linalg_max_f64 => f64::NEG_INFINITY, |a, b| if b > a { b } else { a };
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use crate::Scalar;
+use cust::{
+    error::CudaResult,
+    event::{Event, EventFlags, EventStatus},
+    function::{blocks_for, Function},
+    memory::{
+        AsyncCopyDestination, DeviceBox, DeviceBuffer, DeviceCopy, DeviceSlice, LockedBuffer,
+    },
+    module::Module,
+    params,
+    stream::Stream,
+};
+use std::marker::PhantomData;
+
+/// The number of threads per block of the reduction kernels.
+const BLOCK: u32 = 256;
+/// The largest number of blocks the reductions are launched with, every thread of them reduces a
+/// strided part of the vector first.
+const MAX_REDUCE_BLOCKS: u32 = 1024;
+
+/// The reductions of the [`reduce`](crate::reduce) module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reduction {
+    /// The sum of the elements, zero for an empty vector.
+    Sum,
+    /// The smallest element, infinity for an empty vector.
+    Min,
+    /// The largest element, negative infinity for an empty vector.
+    Max,
+}
+
+/// Reduces device vectors to a number on the host with the kernels of the [`reduce`](crate::reduce)
+/// module.
+///
+/// The result is copied into a page-locked buffer owned by the reducer on the same stream as the
+/// reduction, so reading it back only waits for the reduction instead of for the whole device:
+///
+/// ```ignore
+/// let mut reducer = Reducer::<f32>::new(&module, &stream)?;
+/// let total = reducer.reduce_to_host_async(Reduction::Sum, &weights)?;
+/// // ... queue more work on the stream, or do work on the host ...
+/// let total = total.wait()?;
+/// ```
+pub struct Reducer<'a, T: Scalar + DeviceCopy> {
+    stream: &'a Stream,
+    sum: Function<'a>,
+    min: Function<'a>,
+    max: Function<'a>,
+    partials: DeviceBuffer<T>,
+    ticket: DeviceBox<u32>,
+    out: DeviceBuffer<T>,
+    staging: LockedBuffer<T>,
+}
+
+impl<'a, T: Scalar + DeviceCopy> Reducer<'a, T> {
+    /// Creates a reducer using the kernels in `module`, which must be the PTX of a gpu crate depending
+    /// on this crate with the `kernels` feature. Every kernel is launched on `stream`.
+    pub fn new(module: &'a Module, stream: &'a Stream) -> CudaResult<Self> {
+        let function = |name: &str| module.get_function(format!("linalg_{}_{}", name, T::SUFFIX));
+        Ok(Self {
+            stream,
+            sum: function("sum")?,
+            min: function("min")?,
+            max: function("max")?,
+            partials: unsafe { DeviceBuffer::uninitialized(MAX_REDUCE_BLOCKS as usize)? },
+            // the kernels reset the ticket to zero every time they are done with it.
+            ticket: unsafe { DeviceBox::zeroed()? },
+            out: unsafe { DeviceBuffer::uninitialized(1)? },
+            staging: LockedBuffer::new(&T::ZERO, 1)?,
+        })
+    }
+
+    /// Starts reducing `x` and copying the result to the host on the stream of the reducer,
+    /// returning a [`PendingReduction`] which can be waited on for the result.
+    ///
+    /// The reducer and `x` stay borrowed until the result is read, so only one reduction of a
+    /// reducer can be in flight at a time.
+    pub fn reduce_to_host_async<'r>(
+        &'r mut self,
+        reduction: Reduction,
+        x: &'r DeviceSlice<T>,
+    ) -> CudaResult<PendingReduction<'r, T>> {
+        let func = match reduction {
+            Reduction::Sum => &self.sum,
+            Reduction::Min => &self.min,
+            Reduction::Max => &self.max,
+        };
+        let (mut x_ptr, mut x_len) = (x.as_ptr(), x.len());
+        let mut partials = self.partials.as_device_ptr();
+        let mut ticket = self.ticket.as_device_ptr();
+        let mut out = self.out.as_device_ptr();
+        let params = params!(x_ptr, x_len, partials, ticket, out);
+        let blocks = blocks_for(x_len, BLOCK).min(MAX_REDUCE_BLOCKS);
+        unsafe {
+            self.stream.launch(func, blocks, BLOCK, 0, &params)?;
+            self.out.async_copy_to(&mut self.staging, self.stream)?;
+        }
+        let done = Event::new(EventFlags::DISABLE_TIMING)?;
+        done.record(self.stream)?;
+        Ok(PendingReduction {
+            staging: &self.staging,
+            done,
+            waited: false,
+            _x: PhantomData,
+        })
+    }
+
+    /// Reduces `x`, this waits for the stream to finish the reduction.
+    pub fn reduce(&mut self, reduction: Reduction, x: &DeviceSlice<T>) -> CudaResult<T> {
+        self.reduce_to_host_async(reduction, x)?.wait()
+    }
+}
+
+/// The result of a reduction which may still be in progress, created by
+/// [`Reducer::reduce_to_host_async`].
+///
+/// Dropping an unfinished reduction blocks until the copy of its result completes.
+pub struct PendingReduction<'a, T: DeviceCopy> {
+    staging: &'a LockedBuffer<T>,
+    done: Event,
+    waited: bool,
+    _x: PhantomData<&'a DeviceSlice<T>>,
+}
+
+impl<'a, T: DeviceCopy> PendingReduction<'a, T> {
+    /// Whether the result has arrived, in which case [`wait`](Self::wait) does not block.
+    pub fn is_ready(&self) -> CudaResult<bool> {
+        Ok(self.done.query()? == EventStatus::Ready)
+    }
+
+    /// Blocks until the result has arrived and returns it.
+    pub fn wait(mut self) -> CudaResult<T> {
+        self.done.synchronize()?;
+        self.waited = true;
+        Ok(self.staging[0])
+    }
+}
+
+impl<'a, T: DeviceCopy> Drop for PendingReduction<'a, T> {
+    fn drop(&mut self) {
+        if !self.waited {
+            // the staging buffer must not be freed or reused while the copy is still writing into it.
+            let _ = self.done.synchronize();
+        }
+    }
+}
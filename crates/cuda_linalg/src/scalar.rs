@@ -0,0 +1,83 @@
+use core::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+#[cfg(target_os = "cuda")]
+use cuda_std::GpuFloat;
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// The element types of matrices and vectors, [`f32`] and [`f64`].
+pub trait Scalar:
+    Copy
+    + Default
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + AddAssign
+    + SubAssign
+    + MulAssign
+    + private::Sealed
+{
+    const ZERO: Self;
+    const ONE: Self;
+    /// The suffix of the kernels of this crate for this type, `f32` or `f64`.
+    const SUFFIX: &'static str;
+
+    fn abs(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn to_f64(self) -> f64;
+    fn from_f64(x: f64) -> Self;
+}
+
+macro_rules! impl_scalar {
+    ($($ty:ident),*) => {
+        $(
+            impl Scalar for $ty {
+                const ZERO: Self = 0.0;
+                const ONE: Self = 1.0;
+                const SUFFIX: &'static str = stringify!($ty);
+
+                #[cfg(target_os = "cuda")]
+                #[inline(always)]
+                fn abs(self) -> Self {
+                    GpuFloat::abs(self)
+                }
+
+                #[cfg(not(target_os = "cuda"))]
+                #[inline(always)]
+                fn abs(self) -> Self {
+                    $ty::abs(self)
+                }
+
+                #[cfg(target_os = "cuda")]
+                #[inline(always)]
+                fn sqrt(self) -> Self {
+                    GpuFloat::sqrt(self)
+                }
+
+                #[cfg(not(target_os = "cuda"))]
+                #[inline(always)]
+                fn sqrt(self) -> Self {
+                    $ty::sqrt(self)
+                }
+
+                #[inline(always)]
+                fn to_f64(self) -> f64 {
+                    self as f64
+                }
+
+                #[inline(always)]
+                fn from_f64(x: f64) -> Self {
+                    x as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_scalar!(f32, f64);
@@ -0,0 +1,200 @@
+//! Solving many small dense systems at once, one system per thread.
+//!
+//! Small systems (3x3 and 4x4 are the usual sizes in geometry, physics and robotics) are far too small
+//! for a blocked solver, instead every thread solves one with Gaussian elimination in registers. The
+//! functions are `const` generic over the size so that the loops are fully unrolled, and they work on
+//! the CPU too, which is useful for checking results.
+//!
+//! The batched kernels take the matrices and right hand sides as slices and write one solution per
+//! system, solutions of singular systems are all `NaN`. They are named
+//! `linalg_solve{3,4}_{f32,f64}`, for example `linalg_solve3_f32(a: &[[[f32; 3]; 3]], b: &[[f32; 3]], x: *mut [f32; 3])`,
+//! and are launched with one thread per system.
+
+#![allow(clippy::needless_range_loop)]
+
+use crate::Scalar;
+
+/// Solves `a * x = b` with Gaussian elimination and partial pivoting, returns `None` if `a` is
+/// singular (a pivot is exactly zero). `a` is row-major, `a[row][column]`.
+#[inline]
+pub fn solve<T: Scalar, const N: usize>(mut a: [[T; N]; N], mut b: [T; N]) -> Option<[T; N]> {
+    for col in 0..N {
+        // swap the row with the largest element in this column into place, which keeps the
+        // elimination stable.
+        let mut pivot = col;
+        for row in col + 1..N {
+            if a[row][col].abs() > a[pivot][col].abs() {
+                pivot = row;
+            }
+        }
+        if a[pivot][col] == T::ZERO {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+
+        let inv = T::ONE / a[col][col];
+        for row in col + 1..N {
+            let factor = a[row][col] * inv;
+            for k in col..N {
+                let sub = factor * a[col][k];
+                a[row][k] -= sub;
+            }
+            let sub = factor * b[col];
+            b[row] -= sub;
+        }
+    }
+
+    let mut x = [T::ZERO; N];
+    for row in (0..N).rev() {
+        let mut sum = b[row];
+        for k in row + 1..N {
+            sum -= a[row][k] * x[k];
+        }
+        x[row] = sum / a[row][row];
+    }
+    Some(x)
+}
+
+/// The determinant of `a`, computed with the same elimination as [`solve`].
+#[inline]
+pub fn determinant<T: Scalar, const N: usize>(mut a: [[T; N]; N]) -> T {
+    let mut det = T::ONE;
+    for col in 0..N {
+        let mut pivot = col;
+        for row in col + 1..N {
+            if a[row][col].abs() > a[pivot][col].abs() {
+                pivot = row;
+            }
+        }
+        if a[pivot][col] == T::ZERO {
+            return T::ZERO;
+        }
+        if pivot != col {
+            a.swap(col, pivot);
+            det = -det;
+        }
+        det *= a[col][col];
+
+        let inv = T::ONE / a[col][col];
+        for row in col + 1..N {
+            let factor = a[row][col] * inv;
+            for k in col..N {
+                let sub = factor * a[col][k];
+                a[row][k] -= sub;
+            }
+        }
+    }
+    det
+}
+
+/// The inverse of `a`, or `None` if it is singular. Solving with [`solve`] is faster and more
+/// accurate than multiplying with the inverse, this is for when the inverse itself is needed.
+#[inline]
+pub fn inverse<T: Scalar, const N: usize>(a: [[T; N]; N]) -> Option<[[T; N]; N]> {
+    let mut inv = [[T::ZERO; N]; N];
+    for col in 0..N {
+        let mut unit = [T::ZERO; N];
+        unit[col] = T::ONE;
+        let x = solve(a, unit)?;
+        for row in 0..N {
+            inv[row][col] = x[row];
+        }
+    }
+    Some(inv)
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+macro_rules! solve_kernels {
+    ($($name:ident, $ty:ident, $n:literal;)*) => {
+        $(
+            /// Solves one system per thread, see the [module docs](self).
+            #[cuda_std::kernel]
+            #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+            pub unsafe fn $name(a: &[[[$ty; $n]; $n]], b: &[[$ty; $n]], x: *mut [$ty; $n]) {
+                let i = cuda_std::thread::index_1d() as usize;
+                if i >= a.len() || i >= b.len() {
+                    return;
+                }
+                *x.add(i) = solve(a[i], b[i]).unwrap_or([<$ty>::NAN; $n]);
+            }
+        )*
+    };
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+solve_kernels! {
+    linalg_solve3_f32, f32, 3;
+    linalg_solve4_f32, f32, 4;
+    linalg_solve3_f64, f64, 3;
+    linalg_solve4_f64, f64, 4;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close<const N: usize>(actual: [f64; N], expected: [f64; N]) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < 1e-12, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    const A: [[f64; 3]; 3] = [[2.0, 1.0, -1.0], [-3.0, -1.0, 2.0], [-2.0, 1.0, 2.0]];
+    const SINGULAR: [[f64; 3]; 3] = [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [1.0, 1.0, 1.0]];
+
+    #[test]
+    fn solves() {
+        assert_close(solve(A, [8.0, -11.0, -3.0]).unwrap(), [2.0, 3.0, -1.0]);
+        // the first pivot is zero without the row swap.
+        assert_eq!(
+            solve([[0.0, 1.0], [1.0, 0.0]], [2.0, 3.0]),
+            Some([3.0, 2.0])
+        );
+        let mut identity = [[0.0f32; 4]; 4];
+        for (i, row) in identity.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        assert_eq!(
+            solve(identity, [1.0, 2.0, 3.0, 4.0]),
+            Some([1.0, 2.0, 3.0, 4.0])
+        );
+    }
+
+    #[test]
+    fn computes_determinants() {
+        assert!((determinant(A) + 1.0).abs() < 1e-12);
+        assert_eq!(determinant([[0.0, 1.0], [1.0, 0.0]]), -1.0);
+        assert_eq!(determinant([[3.0f32]]), 3.0);
+        assert_eq!(
+            determinant([[2.0, 0.0, 0.0], [0.0, 3.0, 0.0], [0.0, 0.0, 4.0]]),
+            24.0
+        );
+    }
+
+    #[test]
+    fn inverts() {
+        let inv = inverse([[4.0, 7.0], [2.0, 6.0]]).unwrap();
+        assert_close(inv[0], [0.6, -0.7]);
+        assert_close(inv[1], [-0.2, 0.4]);
+
+        let inv = inverse(A).unwrap();
+        for (i, row) in A.iter().enumerate() {
+            let mut product = [0.0; 3];
+            for (j, out) in product.iter_mut().enumerate() {
+                *out = (0..3).map(|k| row[k] * inv[k][j]).sum();
+            }
+            let mut unit = [0.0; 3];
+            unit[i] = 1.0;
+            assert_close(product, unit);
+        }
+    }
+
+    #[test]
+    fn rejects_singular_matrices() {
+        assert_eq!(solve(SINGULAR, [1.0, 2.0, 3.0]), None);
+        assert_eq!(determinant(SINGULAR), 0.0);
+        assert_eq!(inverse(SINGULAR), None);
+        assert_eq!(solve([[0.0f32; 3]; 3], [0.0; 3]), None);
+    }
+}
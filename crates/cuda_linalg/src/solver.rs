@@ -0,0 +1,265 @@
+use crate::Scalar;
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{AsyncCopyDestination, DeviceBox, DeviceBuffer, DeviceCopy, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+
+/// The number of threads per block of the vector kernels.
+const BLOCK: u32 = 256;
+/// The largest number of blocks the dot product is launched with, every thread of them sums a strided
+/// part of the vectors first.
+const MAX_DOT_BLOCKS: u32 = 1024;
+
+/// The outcome of a solve.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Convergence<T> {
+    /// The number of times the operator was applied to a search direction.
+    pub iterations: usize,
+    /// The norm of the final residual `b - A x` divided by the norm of `b` (or not divided if `b` is
+    /// zero).
+    pub residual: T,
+    /// Whether the residual went below the tolerance. If it did not, `x` holds the last iterate.
+    pub converged: bool,
+}
+
+/// Runs matrix-free CG and BiCGStab with the vector kernels of this crate, see the
+/// [`krylov`](crate::krylov) module.
+///
+/// Every iteration reads a few dot products back to the host, which synchronizes the stream, so these
+/// solvers are meant for problems where applying the operator dominates the time of an iteration.
+pub struct Solver<'a, T: Scalar + DeviceCopy> {
+    stream: &'a Stream,
+    axpy: Function<'a>,
+    xpay: Function<'a>,
+    dot: Function<'a>,
+    bicgstab_p: Function<'a>,
+    partials: DeviceBuffer<T>,
+    ticket: DeviceBox<u32>,
+    out: DeviceBox<T>,
+}
+
+impl<'a, T: Scalar + DeviceCopy> Solver<'a, T> {
+    /// Creates a solver using the kernels in `module`, which must be the PTX of a gpu crate depending on
+    /// this crate with the `kernels` feature. Every kernel is launched on `stream`.
+    pub fn new(module: &'a Module, stream: &'a Stream) -> CudaResult<Self> {
+        let function = |name: &str| module.get_function(format!("linalg_{}_{}", name, T::SUFFIX));
+        Ok(Self {
+            stream,
+            axpy: function("axpy")?,
+            xpay: function("xpay")?,
+            dot: function("dot")?,
+            bicgstab_p: function("bicgstab_p")?,
+            partials: unsafe { DeviceBuffer::uninitialized(MAX_DOT_BLOCKS as usize)? },
+            // the dot product kernel resets the ticket to zero every time it is done with it.
+            ticket: unsafe { DeviceBox::zeroed()? },
+            out: unsafe { DeviceBox::uninitialized()? },
+        })
+    }
+
+    /// `y += alpha * x`.
+    pub fn axpy(&self, x: &DeviceSlice<T>, alpha: T, y: &mut DeviceSlice<T>) -> CudaResult<()> {
+        assert_eq!(x.len(), y.len(), "axpy on vectors of different lengths");
+        let (mut x_ptr, mut len, mut alpha, mut y_ptr) =
+            (x.as_ptr(), x.len(), alpha, y.as_mut_ptr());
+        let params = params!(x_ptr, len, alpha, y_ptr);
+        unsafe {
+            self.stream
+                .launch(&self.axpy, blocks_for(len, BLOCK), BLOCK, 0, &params)
+        }
+    }
+
+    /// `y = x + alpha * y`.
+    pub fn xpay(&self, x: &DeviceSlice<T>, alpha: T, y: &mut DeviceSlice<T>) -> CudaResult<()> {
+        assert_eq!(x.len(), y.len(), "xpay on vectors of different lengths");
+        let (mut x_ptr, mut len, mut alpha, mut y_ptr) =
+            (x.as_ptr(), x.len(), alpha, y.as_mut_ptr());
+        let params = params!(x_ptr, len, alpha, y_ptr);
+        unsafe {
+            self.stream
+                .launch(&self.xpay, blocks_for(len, BLOCK), BLOCK, 0, &params)
+        }
+    }
+
+    /// The dot product of `x` and `y`, this waits for the stream to finish.
+    pub fn dot(&mut self, x: &DeviceSlice<T>, y: &DeviceSlice<T>) -> CudaResult<T> {
+        assert_eq!(
+            x.len(),
+            y.len(),
+            "dot product of vectors of different lengths"
+        );
+        let (mut x_ptr, mut x_len, mut y_ptr, mut y_len) =
+            (x.as_ptr(), x.len(), y.as_ptr(), y.len());
+        let mut partials = self.partials.as_device_ptr();
+        let mut ticket = self.ticket.as_device_ptr();
+        let mut out = self.out.as_device_ptr();
+        let params = params!(x_ptr, x_len, y_ptr, y_len, partials, ticket, out);
+        let blocks = blocks_for(x_len, BLOCK).min(MAX_DOT_BLOCKS);
+        unsafe {
+            self.stream.launch(&self.dot, blocks, BLOCK, 0, &params)?;
+        }
+        self.stream.synchronize()?;
+        self.out.as_host_value()
+    }
+
+    fn norm(&mut self, x: &DeviceSlice<T>) -> CudaResult<T> {
+        Ok(self.dot(x, x)?.sqrt())
+    }
+
+    /// `r = b - A x`, returns the norm of `b` used to make residuals relative.
+    fn initial_residual(
+        &mut self,
+        op: &mut impl FnMut(&DeviceSlice<T>, &mut DeviceSlice<T>) -> CudaResult<()>,
+        b: &DeviceSlice<T>,
+        x: &DeviceSlice<T>,
+        r: &mut DeviceSlice<T>,
+        scratch: &mut DeviceSlice<T>,
+    ) -> CudaResult<T> {
+        op(x, scratch)?;
+        unsafe {
+            r.async_copy_from(b, self.stream)?;
+        }
+        self.axpy(scratch, -T::ONE, r)?;
+        let b_norm = self.norm(b)?;
+        Ok(if b_norm == T::ZERO { T::ONE } else { b_norm })
+    }
+
+    /// Solves `A x = b` with conjugate gradients, starting from the current value of `x`. `A` must be
+    /// symmetric positive definite.
+    ///
+    /// `op(v, out)` must compute `out = A v`, by launching kernels on the stream of the solver.
+    /// The solve stops when the norm of the residual divided by the norm of `b` is at most `tolerance`,
+    /// or after `max_iterations` iterations.
+    pub fn cg(
+        &mut self,
+        mut op: impl FnMut(&DeviceSlice<T>, &mut DeviceSlice<T>) -> CudaResult<()>,
+        b: &DeviceSlice<T>,
+        x: &mut DeviceSlice<T>,
+        tolerance: T,
+        max_iterations: usize,
+    ) -> CudaResult<Convergence<T>> {
+        assert_eq!(b.len(), x.len(), "`b` and `x` have different lengths");
+        let n = b.len();
+        let (mut r, mut p, mut ap) = unsafe {
+            (
+                DeviceBuffer::uninitialized(n)?,
+                DeviceBuffer::uninitialized(n)?,
+                DeviceBuffer::uninitialized(n)?,
+            )
+        };
+        let b_norm = self.initial_residual(&mut op, b, x, &mut r, &mut ap)?;
+        unsafe {
+            p.async_copy_from(&*r, self.stream)?;
+        }
+
+        let mut rs = self.dot(&r, &r)?;
+        let mut iterations = 0;
+        while iterations < max_iterations && rs.sqrt() / b_norm > tolerance {
+            op(&p, &mut ap)?;
+            let alpha = rs / self.dot(&p, &ap)?;
+            self.axpy(&p, alpha, x)?;
+            self.axpy(&ap, -alpha, &mut r)?;
+            let rs_next = self.dot(&r, &r)?;
+            self.xpay(&r, rs_next / rs, &mut p)?;
+            rs = rs_next;
+            iterations += 1;
+        }
+
+        let residual = rs.sqrt() / b_norm;
+        Ok(Convergence {
+            iterations,
+            residual,
+            converged: residual <= tolerance,
+        })
+    }
+
+    /// Solves `A x = b` with BiCGStab, starting from the current value of `x`. `A` may be any
+    /// non-singular matrix, the parameters are the same as for [`cg`](Self::cg).
+    ///
+    /// BiCGStab can break down on some systems, in which case this returns early without having
+    /// converged.
+    pub fn bicgstab(
+        &mut self,
+        mut op: impl FnMut(&DeviceSlice<T>, &mut DeviceSlice<T>) -> CudaResult<()>,
+        b: &DeviceSlice<T>,
+        x: &mut DeviceSlice<T>,
+        tolerance: T,
+        max_iterations: usize,
+    ) -> CudaResult<Convergence<T>> {
+        assert_eq!(b.len(), x.len(), "`b` and `x` have different lengths");
+        let n = b.len();
+        let (mut r, mut r_hat, mut t) = unsafe {
+            (
+                DeviceBuffer::uninitialized(n)?,
+                DeviceBuffer::uninitialized(n)?,
+                DeviceBuffer::uninitialized(n)?,
+            )
+        };
+        let (mut p, mut v) = unsafe { (DeviceBuffer::zeroed(n)?, DeviceBuffer::zeroed(n)?) };
+        let b_norm = self.initial_residual(&mut op, b, x, &mut r, &mut t)?;
+        unsafe {
+            r_hat.async_copy_from(&*r, self.stream)?;
+        }
+
+        let (mut rho, mut alpha, mut omega) = (T::ONE, T::ONE, T::ONE);
+        let mut residual = self.norm(&r)? / b_norm;
+        let mut iterations = 0;
+        while iterations < max_iterations && residual > tolerance {
+            let rho_next = self.dot(&r_hat, &r)?;
+            if rho_next == T::ZERO || omega == T::ZERO {
+                break;
+            }
+            let beta = (rho_next / rho) * (alpha / omega);
+            self.bicgstab_p(&r, &v, beta, omega, &mut p)?;
+            op(&p, &mut v)?;
+            alpha = rho_next / self.dot(&r_hat, &v)?;
+            // `r` becomes `s = r - alpha * v`.
+            self.axpy(&v, -alpha, &mut r)?;
+            self.axpy(&p, alpha, x)?;
+            iterations += 1;
+            residual = self.norm(&r)? / b_norm;
+            if residual <= tolerance {
+                break;
+            }
+
+            op(&r, &mut t)?;
+            omega = self.dot(&t, &r)? / self.dot(&t, &t)?;
+            self.axpy(&r, omega, x)?;
+            self.axpy(&t, -omega, &mut r)?;
+            rho = rho_next;
+            residual = self.norm(&r)? / b_norm;
+        }
+
+        Ok(Convergence {
+            iterations,
+            residual,
+            converged: residual <= tolerance,
+        })
+    }
+
+    fn bicgstab_p(
+        &self,
+        r: &DeviceSlice<T>,
+        v: &DeviceSlice<T>,
+        beta: T,
+        omega: T,
+        p: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        let (mut r_ptr, mut r_len, mut v_ptr, mut v_len) =
+            (r.as_ptr(), r.len(), v.as_ptr(), v.len());
+        let (mut beta, mut omega, mut p_ptr) = (beta, omega, p.as_mut_ptr());
+        let params = params!(r_ptr, r_len, v_ptr, v_len, beta, omega, p_ptr);
+        unsafe {
+            self.stream.launch(
+                &self.bicgstab_p,
+                blocks_for(r_len, BLOCK),
+                BLOCK,
+                0,
+                &params,
+            )
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Sparse matrix-vector products (SpMV), `y = A x`, in three formats.
+//!
+//! - **CSR** (compressed sparse rows): the usual row offsets, column indices and values. The kernels
+//!   use one warp per row ("CSR-vector"), so that the loads of a row are coalesced. This is the best
+//!   format for rows with many entries, and the worst for rows with a few.
+//! - **ELL** (ELLPACK): every row is padded to the same number of entries `width`, stored
+//!   column-major (entry `j` of row `i` is at `j * rows + i`) so that one thread per row reads
+//!   coalesced memory. Padding entries have the column [`ELL_PADDING`]. This is the best format for
+//!   matrices whose rows all have about the same length, such as stencils, and a very bad one if a few
+//!   rows are much longer than the others.
+//! - **Hybrid**: the first `width` entries of every row in ELL, the rest of the long rows in CSR, which
+//!   only lists the rows that overflow. This keeps most of the speed of ELL for irregular matrices.
+//!
+//! Indices are `u32`. The kernels are:
+//!
+//! - `linalg_spmv_csr_{f32,f64}(row_offsets: &[u32], columns: &[u32], values: &[T], x: &[T], y: *mut T)`,
+//!   launched with 32 threads per row.
+//! - `linalg_spmv_ell_{f32,f64}(width: u32, columns: &[u32], values: &[T], x: &[T], y: &mut [T])`,
+//!   launched with one thread per row.
+//! - `linalg_spmv_csr_add_{f32,f64}(rows: &[u32], row_offsets: &[u32], columns: &[u32], values: &[T], x: &[T], y: *mut T)`,
+//!   the CSR part of the hybrid format, which adds to `y` instead of overwriting it and is launched with
+//!   32 threads per row of `rows`.
+//!
+//! On the host, [`CsrMatrix::profile`](crate::CsrMatrix::profile) looks at the row lengths of a matrix
+//! and recommends a format, and [`DeviceSparseMatrix`](crate::DeviceSparseMatrix) uploads a matrix in a
+//! format and launches the right kernels.
+
+/// The column index of the padding entries of the ELL format, the kernels skip them.
+pub const ELL_PADDING: u32 = u32::MAX;
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::ELL_PADDING;
+    use cuda_std::{collective, kernel, thread, warp};
+
+    /// The part of row `row` this lane of the warp sums, every lane takes every 32nd entry.
+    #[inline(always)]
+    unsafe fn warp_row_sum<T>(
+        row: usize,
+        row_offsets: &[u32],
+        columns: &[u32],
+        values: &[T],
+        x: &[T],
+        zero: T,
+    ) -> T
+    where
+        T: Copy + core::ops::Add<Output = T> + core::ops::Mul<Output = T>,
+    {
+        let end = row_offsets[row + 1] as usize;
+        let mut i = row_offsets[row] as usize + warp::lane_id() as usize;
+        let mut sum = zero;
+        while i < end {
+            sum = sum + values[i] * x[columns[i] as usize];
+            i += warp::WARP_SIZE as usize;
+        }
+        collective::warp_reduce(sum, |a, b| a + b)
+    }
+
+    #[inline(always)]
+    fn warp_index() -> usize {
+        (thread::index_1d() / warp::WARP_SIZE) as usize
+    }
+
+    macro_rules! spmv_kernels {
+        ($($ty:ident: $csr:ident, $ell:ident, $csr_add:ident;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $csr(
+                    row_offsets: &[u32],
+                    columns: &[u32],
+                    values: &[$ty],
+                    x: &[$ty],
+                    y: *mut $ty,
+                ) {
+                    // the whole warp has the same row, so it exits together.
+                    let row = warp_index();
+                    if row + 1 >= row_offsets.len() {
+                        return;
+                    }
+                    let sum = warp_row_sum(row, row_offsets, columns, values, x, 0.0);
+                    if warp::lane_id() == 0 {
+                        *y.add(row) = sum;
+                    }
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $ell(width: u32, columns: &[u32], values: &[$ty], x: &[$ty], y: &mut [$ty]) {
+                    let rows = y.len();
+                    let row = thread::index_1d() as usize;
+                    if row >= rows {
+                        return;
+                    }
+                    let mut sum = 0.0;
+                    for j in 0..width as usize {
+                        let column = columns[j * rows + row];
+                        if column != ELL_PADDING {
+                            sum += values[j * rows + row] * x[column as usize];
+                        }
+                    }
+                    y[row] = sum;
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $csr_add(
+                    rows: &[u32],
+                    row_offsets: &[u32],
+                    columns: &[u32],
+                    values: &[$ty],
+                    x: &[$ty],
+                    y: *mut $ty,
+                ) {
+                    let i = warp_index();
+                    if i >= rows.len() {
+                        return;
+                    }
+                    let sum = warp_row_sum(i, row_offsets, columns, values, x, 0.0);
+                    if warp::lane_id() == 0 {
+                        *y.add(rows[i] as usize) += sum;
+                    }
+                }
+            )*
+        };
+    }
+
+    spmv_kernels! {
+        f32: linalg_spmv_csr_f32, linalg_spmv_ell_f32, linalg_spmv_csr_add_f32;
+        f64: linalg_spmv_csr_f64, linalg_spmv_ell_f64, linalg_spmv_csr_add_f64;
+    }
+}
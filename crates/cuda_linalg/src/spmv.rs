@@ -0,0 +1,604 @@
+use crate::{sparse::ELL_PADDING, Scalar};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceBuffer, DeviceCopy, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+
+/// The number of threads per block of the SpMV kernels.
+const BLOCK: u32 = 256;
+/// The CSR kernels use a warp per row.
+const WARP_SIZE: usize = 32;
+
+/// ELL is used if it stores at most this many times as many entries as the matrix has.
+const MAX_ELL_PADDING: f64 = 1.5;
+/// CSR is used instead of the hybrid format if rows have at least this many entries on average, which
+/// keeps most lanes of the warps busy.
+const MIN_CSR_MEAN_ROW_LEN: f64 = 32.0;
+
+/// The storage formats of [`DeviceSparseMatrix`], see the [`sparse`](crate::sparse) module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpmvFormat {
+    Csr,
+    Ell,
+    /// The first `width` entries of every row in ELL, the rest in CSR.
+    Hybrid {
+        width: usize,
+    },
+}
+
+/// A sparse matrix in CSR format on the host.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsrMatrix<T> {
+    cols: usize,
+    row_offsets: Vec<u32>,
+    columns: Vec<u32>,
+    values: Vec<T>,
+}
+
+impl<T: Scalar> CsrMatrix<T> {
+    /// Creates a matrix with `cols` columns from its CSR arrays: row `i` has the entries
+    /// `row_offsets[i]..row_offsets[i + 1]` of `columns` and `values`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arrays are not a valid CSR matrix.
+    pub fn new(cols: usize, row_offsets: Vec<u32>, columns: Vec<u32>, values: Vec<T>) -> Self {
+        assert!(
+            cols < ELL_PADDING as usize,
+            "the matrix has too many columns for u32 indices"
+        );
+        assert_eq!(
+            row_offsets.first(),
+            Some(&0),
+            "`row_offsets` must start with 0"
+        );
+        assert!(
+            row_offsets.windows(2).all(|w| w[0] <= w[1]),
+            "`row_offsets` must not decrease"
+        );
+        assert_eq!(
+            *row_offsets.last().unwrap() as usize,
+            columns.len(),
+            "`row_offsets` must end with the number of entries"
+        );
+        assert_eq!(
+            columns.len(),
+            values.len(),
+            "`columns` and `values` have different lengths"
+        );
+        assert!(
+            columns.iter().all(|&column| (column as usize) < cols),
+            "column index out of bounds"
+        );
+        Self {
+            cols,
+            row_offsets,
+            columns,
+            values,
+        }
+    }
+
+    /// Creates a `rows`x`cols` matrix from `(row, column, value)` entries in any order, entries with the
+    /// same row and column are summed.
+    pub fn from_triplets(rows: usize, cols: usize, triplets: &[(usize, usize, T)]) -> Self {
+        let mut sorted = triplets.to_vec();
+        sorted.sort_by_key(|&(row, column, _)| (row, column));
+
+        let mut row_offsets = vec![0; rows + 1];
+        let mut columns = Vec::with_capacity(sorted.len());
+        let mut values: Vec<T> = Vec::with_capacity(sorted.len());
+        let mut last = None;
+        for (row, column, value) in sorted {
+            assert!(row < rows, "row index out of bounds");
+            // checked before the cast, which could wrap around to a valid column.
+            assert!(column < cols, "column index out of bounds");
+            if last == Some((row, column)) {
+                *values.last_mut().unwrap() += value;
+                continue;
+            }
+            last = Some((row, column));
+            row_offsets[row + 1] += 1;
+            columns.push(column as u32);
+            values.push(value);
+        }
+        for row in 0..rows {
+            row_offsets[row + 1] += row_offsets[row];
+        }
+        Self::new(cols, row_offsets, columns, values)
+    }
+
+    pub fn rows(&self) -> usize {
+        self.row_offsets.len() - 1
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// The number of stored entries.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn row_offsets(&self) -> &[u32] {
+        &self.row_offsets
+    }
+
+    pub fn columns(&self) -> &[u32] {
+        &self.columns
+    }
+
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    fn row_range(&self, row: usize) -> std::ops::Range<usize> {
+        self.row_offsets[row] as usize..self.row_offsets[row + 1] as usize
+    }
+
+    /// `A x` on the CPU, for checking the results of the kernels.
+    pub fn multiply(&self, x: &[T]) -> Vec<T> {
+        assert_eq!(x.len(), self.cols, "`x` must have an element per column");
+        (0..self.rows())
+            .map(|row| {
+                self.row_range(row).fold(T::ZERO, |sum, i| {
+                    sum + self.values[i] * x[self.columns[i] as usize]
+                })
+            })
+            .collect()
+    }
+
+    /// Looks at the lengths of the rows, to pick a format with
+    /// [`MatrixProfile::recommended_format`].
+    pub fn profile(&self) -> MatrixProfile {
+        let rows = self.rows();
+        let nnz = self.nnz();
+        let max_row_len = (0..rows)
+            .map(|row| self.row_range(row).len())
+            .max()
+            .unwrap_or(0);
+
+        // the hybrid width is the largest width which at least a third of the rows fill up, the
+        // rule of thumb of Bell and Garland's "Efficient Sparse Matrix-Vector Multiplication on CUDA".
+        let mut rows_with_len = vec![0usize; max_row_len + 1];
+        for row in 0..rows {
+            rows_with_len[self.row_range(row).len()] += 1;
+        }
+        let min_rows = (rows / 3).max(1);
+        let mut hybrid_width = 0;
+        let mut rows_at_least = 0;
+        for len in (1..=max_row_len).rev() {
+            rows_at_least += rows_with_len[len];
+            if rows_at_least >= min_rows {
+                hybrid_width = len;
+                break;
+            }
+        }
+
+        MatrixProfile {
+            rows,
+            cols: self.cols,
+            nnz,
+            mean_row_len: if rows == 0 {
+                0.0
+            } else {
+                nnz as f64 / rows as f64
+            },
+            max_row_len,
+            hybrid_width,
+        }
+    }
+
+    /// The ELL arrays of the first `width` entries of every row.
+    fn ell(&self, width: usize) -> (Vec<u32>, Vec<T>) {
+        let rows = self.rows();
+        let mut columns = vec![ELL_PADDING; width * rows];
+        let mut values = vec![T::ZERO; width * rows];
+        for row in 0..rows {
+            for (j, i) in self.row_range(row).take(width).enumerate() {
+                columns[j * rows + row] = self.columns[i];
+                values[j * rows + row] = self.values[i];
+            }
+        }
+        (columns, values)
+    }
+
+    /// The rows which have more than `width` entries, and the CSR arrays of their entries after the
+    /// first `width`.
+    fn overflow(&self, width: usize) -> (Vec<u32>, Self) {
+        let mut rows = Vec::new();
+        let mut row_offsets = vec![0];
+        let mut columns = Vec::new();
+        let mut values = Vec::new();
+        for row in 0..self.rows() {
+            let range = self.row_range(row);
+            if range.len() > width {
+                rows.push(row as u32);
+                columns.extend_from_slice(&self.columns[range.start + width..range.end]);
+                values.extend_from_slice(&self.values[range.start + width..range.end]);
+                row_offsets.push(columns.len() as u32);
+            }
+        }
+        (rows, Self::new(self.cols, row_offsets, columns, values))
+    }
+}
+
+/// The row lengths of a matrix, see [`CsrMatrix::profile`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatrixProfile {
+    pub rows: usize,
+    pub cols: usize,
+    pub nnz: usize,
+    pub mean_row_len: f64,
+    pub max_row_len: usize,
+    /// The width of the ELL part of the hybrid format, the largest number of entries which at least a
+    /// third of the rows have.
+    pub hybrid_width: usize,
+}
+
+impl MatrixProfile {
+    /// The number of entries ELL stores (including padding) divided by the number of entries of the
+    /// matrix, 1 if every row has the same length.
+    pub fn ell_padding(&self) -> f64 {
+        if self.nnz == 0 {
+            1.0
+        } else {
+            (self.rows * self.max_row_len) as f64 / self.nnz as f64
+        }
+    }
+
+    /// The format which should be the fastest for this matrix:
+    ///
+    /// - ELL if the rows have about the same length, so that there is little padding.
+    /// - CSR if the rows are long enough to keep a warp per row busy.
+    /// - Hybrid otherwise.
+    pub fn recommended_format(&self) -> SpmvFormat {
+        if self.ell_padding() <= MAX_ELL_PADDING {
+            SpmvFormat::Ell
+        } else if self.mean_row_len >= MIN_CSR_MEAN_ROW_LEN || self.hybrid_width == 0 {
+            SpmvFormat::Csr
+        } else {
+            SpmvFormat::Hybrid {
+                width: self.hybrid_width,
+            }
+        }
+    }
+}
+
+struct DeviceCsr<'a, T: DeviceCopy> {
+    function: Function<'a>,
+    /// The rows of the overflow of the hybrid format, `None` for a plain CSR matrix.
+    rows: Option<DeviceBuffer<u32>>,
+    row_offsets: DeviceBuffer<u32>,
+    columns: DeviceBuffer<u32>,
+    values: DeviceBuffer<T>,
+}
+
+struct DeviceEll<'a, T: DeviceCopy> {
+    function: Function<'a>,
+    width: u32,
+    columns: DeviceBuffer<u32>,
+    values: DeviceBuffer<T>,
+}
+
+/// A sparse matrix on the device in one of the [`SpmvFormat`]s, which computes `y = A x` with the
+/// kernels of the [`sparse`](crate::sparse) module.
+pub struct DeviceSparseMatrix<'a, T: Scalar + DeviceCopy> {
+    rows: usize,
+    cols: usize,
+    format: SpmvFormat,
+    ell: Option<DeviceEll<'a, T>>,
+    csr: Option<DeviceCsr<'a, T>>,
+}
+
+impl<'a, T: Scalar + DeviceCopy> DeviceSparseMatrix<'a, T> {
+    /// Uploads `matrix` in the format recommended by its [`profile`](CsrMatrix::profile). `module` must
+    /// be the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn new(module: &'a Module, matrix: &CsrMatrix<T>) -> CudaResult<Self> {
+        Self::with_format(module, matrix, matrix.profile().recommended_format())
+    }
+
+    /// Uploads `matrix` in `format`.
+    pub fn with_format(
+        module: &'a Module,
+        matrix: &CsrMatrix<T>,
+        format: SpmvFormat,
+    ) -> CudaResult<Self> {
+        let function =
+            |name: &str| module.get_function(format!("linalg_spmv_{}_{}", name, T::SUFFIX));
+        let upload_ell = |width: usize| -> CudaResult<DeviceEll<'a, T>> {
+            let (columns, values) = matrix.ell(width);
+            Ok(DeviceEll {
+                function: function("ell")?,
+                width: width as u32,
+                columns: DeviceBuffer::from_slice(&columns)?,
+                values: DeviceBuffer::from_slice(&values)?,
+            })
+        };
+
+        let (ell, csr) = match format {
+            SpmvFormat::Csr => {
+                let csr = DeviceCsr {
+                    function: function("csr")?,
+                    rows: None,
+                    row_offsets: DeviceBuffer::from_slice(&matrix.row_offsets)?,
+                    columns: DeviceBuffer::from_slice(&matrix.columns)?,
+                    values: DeviceBuffer::from_slice(&matrix.values)?,
+                };
+                (None, Some(csr))
+            }
+            SpmvFormat::Ell => (Some(upload_ell(matrix.profile().max_row_len)?), None),
+            SpmvFormat::Hybrid { width } => {
+                let (rows, overflow) = matrix.overflow(width);
+                let csr = if rows.is_empty() {
+                    None
+                } else {
+                    Some(DeviceCsr {
+                        function: function("csr_add")?,
+                        rows: Some(DeviceBuffer::from_slice(&rows)?),
+                        row_offsets: DeviceBuffer::from_slice(&overflow.row_offsets)?,
+                        columns: DeviceBuffer::from_slice(&overflow.columns)?,
+                        values: DeviceBuffer::from_slice(&overflow.values)?,
+                    })
+                };
+                (Some(upload_ell(width)?), csr)
+            }
+        };
+
+        Ok(Self {
+            rows: matrix.rows(),
+            cols: matrix.cols(),
+            format,
+            ell,
+            csr,
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn format(&self) -> SpmvFormat {
+        self.format
+    }
+
+    /// Enqueues `y = A x` on `stream`.
+    ///
+    /// This has the signature of the operators of [`Solver`](crate::Solver) once the stream is bound,
+    /// `|x, y| matrix.spmv(&stream, x, y)`.
+    pub fn spmv(
+        &self,
+        stream: &Stream,
+        x: &DeviceSlice<T>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        assert_eq!(x.len(), self.cols, "`x` must have an element per column");
+        assert_eq!(y.len(), self.rows, "`y` must have an element per row");
+        if self.rows == 0 {
+            return Ok(());
+        }
+
+        let (mut x_ptr, mut x_len) = (x.as_ptr(), x.len());
+        if let Some(ell) = &self.ell {
+            let mut width = ell.width;
+            let (mut columns, mut columns_len) = (ell.columns.as_ptr(), ell.columns.len());
+            let (mut values, mut values_len) = (ell.values.as_ptr(), ell.values.len());
+            let (mut y_ptr, mut y_len) = (y.as_mut_ptr(), y.len());
+            let params = params!(
+                width,
+                columns,
+                columns_len,
+                values,
+                values_len,
+                x_ptr,
+                x_len,
+                y_ptr,
+                y_len
+            );
+            unsafe {
+                stream.launch(
+                    &ell.function,
+                    blocks_for(self.rows, BLOCK),
+                    BLOCK,
+                    0,
+                    &params,
+                )?;
+            }
+        }
+
+        if let Some(csr) = &self.csr {
+            let (mut offsets, mut offsets_len) = (csr.row_offsets.as_ptr(), csr.row_offsets.len());
+            let (mut columns, mut columns_len) = (csr.columns.as_ptr(), csr.columns.len());
+            let (mut values, mut values_len) = (csr.values.as_ptr(), csr.values.len());
+            let mut y_ptr = y.as_mut_ptr();
+            let grid = blocks_for(WARP_SIZE * (offsets_len - 1), BLOCK);
+            unsafe {
+                match &csr.rows {
+                    Some(rows) => {
+                        let (mut rows, mut rows_len) = (rows.as_ptr(), rows.len());
+                        let params = params!(
+                            rows,
+                            rows_len,
+                            offsets,
+                            offsets_len,
+                            columns,
+                            columns_len,
+                            values,
+                            values_len,
+                            x_ptr,
+                            x_len,
+                            y_ptr
+                        );
+                        stream.launch(&csr.function, grid, BLOCK, 0, &params)?;
+                    }
+                    None => {
+                        let params = params!(
+                            offsets,
+                            offsets_len,
+                            columns,
+                            columns_len,
+                            values,
+                            values_len,
+                            x_ptr,
+                            x_len,
+                            y_ptr
+                        );
+                        stream.launch(&csr.function, grid, BLOCK, 0, &params)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 6x5 matrix with rows of 3, 1, 0, 2, 1 and 2 entries.
+    fn matrix() -> CsrMatrix<f32> {
+        CsrMatrix::from_triplets(
+            6,
+            5,
+            &[
+                (5, 4, 9.0),
+                (0, 4, 3.0),
+                (3, 3, 6.0),
+                (0, 0, 1.0),
+                (1, 1, 4.0),
+                (4, 1, 7.0),
+                (0, 2, 2.0),
+                (3, 0, 5.0),
+                (5, 2, 8.0),
+            ],
+        )
+    }
+
+    #[test]
+    fn sorts_triplets() {
+        let matrix = matrix();
+        assert_eq!((matrix.rows(), matrix.cols(), matrix.nnz()), (6, 5, 9));
+        assert_eq!(matrix.row_offsets(), &[0, 3, 4, 4, 6, 7, 9]);
+        assert_eq!(matrix.columns(), &[0, 2, 4, 1, 0, 3, 1, 2, 4]);
+        assert_eq!(
+            matrix.values(),
+            &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0]
+        );
+        assert_eq!(
+            matrix.multiply(&[1.0, 10.0, 100.0, 1000.0, 10000.0]),
+            vec![30201.0, 40.0, 0.0, 6005.0, 70.0, 90800.0]
+        );
+    }
+
+    #[test]
+    fn sums_duplicate_triplets() {
+        let matrix = CsrMatrix::from_triplets(2, 2, &[(1, 0, 1.0), (0, 1, 2.0), (1, 0, 0.5)]);
+        assert_eq!(matrix.row_offsets(), &[0, 1, 2]);
+        assert_eq!(matrix.columns(), &[1, 0]);
+        assert_eq!(matrix.values(), &[2.0, 1.5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "column index out of bounds")]
+    fn rejects_columns_out_of_bounds() {
+        CsrMatrix::from_triplets(2, 3, &[(0, 3, 1.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "column index out of bounds")]
+    fn rejects_columns_wrapping_around_u32() {
+        CsrMatrix::from_triplets(2, 3, &[(0, (1 << 32) + 1, 1.0)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "row index out of bounds")]
+    fn rejects_rows_out_of_bounds() {
+        CsrMatrix::from_triplets(2, 3, &[(2, 0, 1.0)]);
+    }
+
+    #[test]
+    fn profiles_row_lengths() {
+        let profile = matrix().profile();
+        assert_eq!(
+            profile,
+            MatrixProfile {
+                rows: 6,
+                cols: 5,
+                nnz: 9,
+                mean_row_len: 1.5,
+                max_row_len: 3,
+                // 3 of the 6 rows have at least 2 entries, only one has 3.
+                hybrid_width: 2,
+            }
+        );
+        assert_eq!(profile.ell_padding(), 2.0);
+        assert_eq!(
+            profile.recommended_format(),
+            SpmvFormat::Hybrid { width: 2 }
+        );
+    }
+
+    #[test]
+    fn recommends_formats() {
+        let format = |triplets: &[(usize, usize, f32)], rows| {
+            CsrMatrix::from_triplets(rows, 64, triplets)
+                .profile()
+                .recommended_format()
+        };
+        let diagonal = (0..4).map(|i| (i, i, 1.0)).collect::<Vec<_>>();
+        assert_eq!(format(&diagonal, 4), SpmvFormat::Ell);
+        // 2 rows of 2 entries store 4 entries for 3.
+        assert_eq!(
+            format(&[(0, 0, 1.0), (0, 1, 1.0), (1, 0, 1.0)], 2),
+            SpmvFormat::Ell
+        );
+        assert_eq!(format(&[], 3), SpmvFormat::Ell);
+        // a single long row is not worth an ELL part.
+        let long_row = (0..10).map(|i| (0, i, 1.0)).collect::<Vec<_>>();
+        assert_eq!(format(&long_row, 9), SpmvFormat::Csr);
+        // rows of 64, 64, 1 and 1 entries, long enough for a warp each on average.
+        let long_rows = (0..2 * 64)
+            .map(|i| (i % 2, i / 2, 1.0))
+            .chain([(2, 0, 1.0), (3, 0, 1.0)])
+            .collect::<Vec<_>>();
+        assert_eq!(format(&long_rows, 4), SpmvFormat::Csr);
+    }
+
+    #[test]
+    fn splits_hybrid_rows() {
+        let matrix = matrix();
+        let (columns, values) = matrix.ell(2);
+        // column-major, entry `j` of row `i` at `j * rows + i`.
+        let p = ELL_PADDING;
+        assert_eq!(columns, vec![0, 1, p, 0, 1, 2, 2, p, p, 3, p, 4]);
+        assert_eq!(
+            values,
+            vec![1.0, 4.0, 0.0, 5.0, 7.0, 8.0, 2.0, 0.0, 0.0, 6.0, 0.0, 9.0]
+        );
+
+        let (rows, overflow) = matrix.overflow(2);
+        assert_eq!(rows, vec![0]);
+        assert_eq!(overflow.cols(), 5);
+        assert_eq!(overflow.row_offsets(), &[0, 1]);
+        assert_eq!(overflow.columns(), &[4]);
+        assert_eq!(overflow.values(), &[3.0]);
+
+        let (rows, overflow) = matrix.overflow(1);
+        assert_eq!(rows, vec![0, 3, 5]);
+        assert_eq!(overflow.row_offsets(), &[0, 2, 3, 4]);
+        assert_eq!(overflow.columns(), &[2, 4, 3, 4]);
+
+        let (rows, overflow) = matrix.overflow(3);
+        assert!(rows.is_empty());
+        assert_eq!(overflow.nnz(), 0);
+    }
+}
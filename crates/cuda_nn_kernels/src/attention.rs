@@ -0,0 +1,228 @@
+//! Multi-head scaled dot-product attention, `softmax(scale * Q K^T + mask) V` for every head, in three
+//! launches: the scores, their softmax (with `nn_softmax_f32` of the [`softmax`](crate::softmax)
+//! module), and the product of the probabilities with `V`.
+//!
+//! `Q`, `K` and `V` have a row per token with the heads side by side, `heads * head_dim` elements,
+//! which is how a linear layer writes them. The rows may be further apart than that, see
+//! [`AttentionShape`], so that `Q`, `K` and `V` can be read from the output of a single fused QKV
+//! projection, and the output is written in the same layout, ready for the output projection. The
+//! scores are `f32`, `heads` matrices of `q_len` rows of `kv_len` scores.
+//!
+//! With a causal mask, query `i` only sees the keys up to `i + kv_len - q_len`: all of them when
+//! decoding one token with a KV cache, and the earlier tokens of the same sequence when `q_len` is
+//! `kv_len`. The score kernel does not multiply the tiles which are entirely masked out, and the
+//! output kernel stops at the last key its queries can see.
+//!
+//! The kernels use the same tiled matrix multiplication as [`linear`](crate::linear), with blocks of
+//! [`BLOCK`](crate::BLOCK)x[`BLOCK`](crate::BLOCK) threads computing 64x64 tiles, and one head per
+//! `z` index of the grid:
+//!
+//! - `nn_attention_scores_{f32,f16,bf16}(shape: AttentionShape, q: *const T, k: *const T, scores: *mut f32)`:
+//!   writes `scale * q_i . k_j`, or negative infinity for masked scores, with a grid of
+//!   `(ceil(kv_len / 64), ceil(q_len / 64), heads)` blocks.
+//! - `nn_attention_output_{f32,f16,bf16}(shape: AttentionShape, probabilities: *const f32, v: *const T, out: *mut T)`:
+//!   writes the probabilities times `V`, with a grid of `(ceil(head_dim / 64), ceil(q_len / 64), heads)`
+//!   blocks.
+//!
+//! On the host, [`NnKernels::attention`](crate::NnKernels::attention) launches all three.
+
+/// The dimensions and layout of the inputs and the output of attention, see the
+/// [module docs](self). Strides are the distances between the starts of two rows, in elements.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct AttentionShape {
+    pub heads: usize,
+    /// The number of elements of the queries, keys and values of every head.
+    pub head_dim: usize,
+    /// The number of queries (rows of `Q`).
+    pub q_len: usize,
+    /// The number of keys and values (rows of `K` and `V`).
+    pub kv_len: usize,
+    pub q_stride: usize,
+    pub k_stride: usize,
+    pub v_stride: usize,
+    pub out_stride: usize,
+    /// The factor of the scores, usually `1 / sqrt(head_dim)`.
+    pub scale: f32,
+    /// Whether query `i` only sees the keys up to `i + kv_len - q_len`.
+    pub causal: bool,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for AttentionShape {}
+
+#[cfg(not(target_os = "cuda"))]
+impl AttentionShape {
+    /// The shape of `heads` heads of `head_dim` elements, with rows of `heads * head_dim` elements, a
+    /// scale of `1 / sqrt(head_dim)`, and no mask.
+    pub fn new(heads: usize, head_dim: usize, q_len: usize, kv_len: usize) -> Self {
+        let stride = heads * head_dim;
+        Self {
+            heads,
+            head_dim,
+            q_len,
+            kv_len,
+            q_stride: stride,
+            k_stride: stride,
+            v_stride: stride,
+            out_stride: stride,
+            scale: 1.0 / (head_dim as f32).sqrt(),
+            causal: false,
+        }
+    }
+
+    /// The number of scores, `heads * q_len * kv_len`.
+    pub fn scores_len(&self) -> usize {
+        self.heads * self.q_len * self.kv_len
+    }
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::AttentionShape;
+    use crate::{tile, Element};
+    use core::mem::MaybeUninit;
+    use cuda_std::{kernel, shared_array, thread};
+    use half::{bf16, f16};
+
+    #[inline(always)]
+    unsafe fn attention_scores<T: Element>(
+        shape: AttentionShape,
+        q: *const T,
+        k: *const T,
+        scores: *mut f32,
+        q_shared: *mut f32,
+        k_shared: *mut f32,
+    ) {
+        let head = thread::block_idx_z() as usize;
+        let offset = head * shape.head_dim;
+        let scores = scores.add(head * shape.q_len * shape.kv_len);
+        // query `i` sees the keys up to `i + visible`, without a causal mask this is unused.
+        let visible = shape.kv_len.saturating_sub(shape.q_len);
+        let (row0, col0) = tile::origin();
+        // the same for the whole block, so that all or none of its threads synchronize.
+        let masked = shape.causal && col0 > row0 + tile::TILE_M - 1 + visible;
+        let acc = if masked {
+            Default::default()
+        } else {
+            tile::multiply(
+                shape.q_len,
+                shape.kv_len,
+                shape.head_dim,
+                q.add(offset),
+                shape.q_stride,
+                k.add(offset),
+                shape.k_stride,
+                true,
+                q_shared,
+                k_shared,
+            )
+        };
+        tile::for_each_output(&acc, shape.q_len, shape.kv_len, |i, j, dot| {
+            *scores.add(i * shape.kv_len + j) = if shape.causal && j > i + visible {
+                f32::NEG_INFINITY
+            } else {
+                dot * shape.scale
+            };
+        });
+    }
+
+    #[inline(always)]
+    unsafe fn attention_output<T: Element>(
+        shape: AttentionShape,
+        probabilities: *const f32,
+        v: *const T,
+        out: *mut T,
+        probabilities_shared: *mut f32,
+        v_shared: *mut f32,
+    ) {
+        let head = thread::block_idx_z() as usize;
+        let offset = head * shape.head_dim;
+        let probabilities = probabilities.add(head * shape.q_len * shape.kv_len);
+        let (row0, _) = tile::origin();
+        // the probabilities of the keys after the last one the queries of the block see are zero.
+        let keys = if shape.causal {
+            (row0 + tile::TILE_M + shape.kv_len - shape.q_len).min(shape.kv_len)
+        } else {
+            shape.kv_len
+        };
+        let acc = tile::multiply(
+            shape.q_len,
+            shape.head_dim,
+            keys,
+            probabilities,
+            shape.kv_len,
+            v.add(offset),
+            shape.v_stride,
+            false,
+            probabilities_shared,
+            v_shared,
+        );
+        let out = out.add(offset);
+        tile::for_each_output(&acc, shape.q_len, shape.head_dim, |i, d, value| {
+            *out.add(i * shape.out_stride + d) = T::from_f32(value);
+        });
+    }
+
+    macro_rules! attention_kernels {
+        ($($scores:ident, $output:ident, $ty:ty;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $scores(
+                    shape: AttentionShape,
+                    q: *const $ty,
+                    k: *const $ty,
+                    scores: *mut f32,
+                ) {
+                    let q_shared = shared_array![f32; tile::SHARED_LEN];
+                    let k_shared = shared_array![f32; tile::SHARED_LEN];
+                    attention_scores(shape, q, k, scores, q_shared, k_shared);
+                }
+
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $output(
+                    shape: AttentionShape,
+                    probabilities: *const f32,
+                    v: *const $ty,
+                    out: *mut $ty,
+                ) {
+                    let probabilities_shared = shared_array![f32; tile::SHARED_LEN];
+                    let v_shared = shared_array![f32; tile::SHARED_LEN];
+                    attention_output(shape, probabilities, v, out, probabilities_shared, v_shared);
+                }
+            )*
+        };
+    }
+
+    attention_kernels! {
+        nn_attention_scores_f32, nn_attention_output_f32, f32;
+        nn_attention_scores_f16, nn_attention_output_f16, f16;
+        nn_attention_scores_bf16, nn_attention_output_bf16, bf16;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_heads_into_rows() {
+        let shape = AttentionShape::new(12, 64, 5, 7);
+        assert_eq!((shape.heads, shape.head_dim), (12, 64));
+        assert_eq!((shape.q_len, shape.kv_len), (5, 7));
+        for stride in [
+            shape.q_stride,
+            shape.k_stride,
+            shape.v_stride,
+            shape.out_stride,
+        ] {
+            assert_eq!(stride, 12 * 64);
+        }
+        assert_eq!(shape.scale, 0.125);
+        assert!(!shape.causal);
+        assert_eq!(shape.scores_len(), 12 * 5 * 7);
+        assert_eq!(AttentionShape::new(1, 1, 1, 1).scores_len(), 1);
+    }
+}
@@ -0,0 +1,163 @@
+use half::{bf16, f16};
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for half::f16 {}
+    impl Sealed for half::bf16 {}
+}
+
+/// The element types of activations and weights, [`f32`], [`f16`] and [`bf16`].
+///
+/// The kernels only load and store elements of this type, everything in between (products, sums,
+/// means, exponentials) is computed in `f32`.
+pub trait Element: Copy + private::Sealed {
+    /// The suffix of the kernels of this crate for this type, `f32`, `f16` or `bf16`.
+    const SUFFIX: &'static str;
+
+    fn to_f32(self) -> f32;
+    /// Converts an `f32`, rounding to nearest-even.
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Element for f32 {
+    const SUFFIX: &'static str = "f32";
+
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+}
+
+impl Element for f16 {
+    const SUFFIX: &'static str = "f16";
+
+    #[cfg(target_os = "cuda")]
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        let out: f32;
+        unsafe {
+            asm!(
+                "cvt.f32.f16 {}, {};",
+                out(reg32) out,
+                in(reg16) self.to_bits(),
+            );
+        }
+        out
+    }
+
+    #[cfg(not(target_os = "cuda"))]
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        f16::to_f32(self)
+    }
+
+    #[cfg(target_os = "cuda")]
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        let out: u16;
+        unsafe {
+            asm!(
+                "cvt.rn.f16.f32 {}, {};",
+                out(reg16) out,
+                in(reg32) value,
+            );
+        }
+        f16::from_bits(out)
+    }
+
+    #[cfg(not(target_os = "cuda"))]
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        f16::from_f32(value)
+    }
+}
+
+impl Element for bf16 {
+    const SUFFIX: &'static str = "bf16";
+
+    #[inline(always)]
+    fn to_f32(self) -> f32 {
+        // a bf16 is the upper half of an f32.
+        f32::from_bits((self.to_bits() as u32) << 16)
+    }
+
+    #[cfg(all(target_os = "cuda", target_feature = "sm_80"))]
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        let out: u16;
+        unsafe {
+            asm!(
+                "cvt.rn.bf16.f32 {}, {};",
+                out(reg16) out,
+                in(reg32) value,
+            );
+        }
+        bf16::from_bits(out)
+    }
+
+    #[cfg(not(all(target_os = "cuda", target_feature = "sm_80")))]
+    #[inline(always)]
+    fn from_f32(value: f32) -> Self {
+        bf16::from_f32(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXACT: [f32; 7] = [0.0, -0.0, 1.0, -2.5, 0.15625, 384.0, f32::INFINITY];
+
+    #[test]
+    fn round_trips_f16() {
+        for value in EXACT.iter().copied().chain([65504.0, -6.1035156e-5]) {
+            assert_eq!(f16::from_f32(value).to_f32(), value);
+        }
+        // halfway between 1 and the next half, rounded to even.
+        assert_eq!(<f16 as Element>::from_f32(1.0 + 0.5 / 1024.0).to_f32(), 1.0);
+        assert_eq!(
+            <f16 as Element>::from_f32(1.0 + 1.5 / 1024.0).to_f32(),
+            1.0 + 2.0 / 1024.0
+        );
+        assert_eq!(<f16 as Element>::from_f32(1e6).to_f32(), f32::INFINITY);
+        assert!(<f16 as Element>::from_f32(f32::NAN).to_f32().is_nan());
+    }
+
+    #[test]
+    fn round_trips_bf16() {
+        for value in EXACT.iter().copied().chain([3.0e38, -1.0e-38]) {
+            let value = bf16::from_f32(value).to_f32();
+            assert_eq!(<bf16 as Element>::from_f32(value).to_f32(), value);
+        }
+        for value in EXACT {
+            assert_eq!(<bf16 as Element>::from_f32(value).to_f32(), value);
+        }
+        // halfway between 1 and the next bf16, rounded to even.
+        assert_eq!(<bf16 as Element>::from_f32(1.0 + 0.5 / 128.0).to_f32(), 1.0);
+        assert_eq!(
+            <bf16 as Element>::from_f32(1.0 + 1.5 / 128.0).to_f32(),
+            1.0 + 2.0 / 128.0
+        );
+        assert!(<bf16 as Element>::from_f32(f32::NAN).to_f32().is_nan());
+        // the shift agrees with the conversion of `half`.
+        for bits in (0..=u16::MAX).step_by(97) {
+            let value = bf16::from_bits(bits);
+            let expected = bf16::to_f32(value);
+            let actual = Element::to_f32(value);
+            assert!(actual == expected || actual.is_nan() && expected.is_nan());
+        }
+    }
+
+    #[test]
+    fn names_types() {
+        assert_eq!(f32::SUFFIX, "f32");
+        assert_eq!(f16::SUFFIX, "f16");
+        assert_eq!(bf16::SUFFIX, "bf16");
+    }
+}
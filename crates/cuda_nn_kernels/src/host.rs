@@ -0,0 +1,502 @@
+use crate::{Activation, AttentionShape, Element, BLOCK, ROW_BLOCK};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceBuffer, DeviceCopy, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+use std::marker::PhantomData;
+
+/// The rows and columns of outputs computed by every block of the linear and attention kernels.
+const TILE: u32 = 64;
+/// The row kernels use a warp per row.
+const WARP_SIZE: usize = 32;
+
+/// The number of rows of `len` elements in `slice`.
+fn rows_of<T: DeviceCopy>(slice: &DeviceSlice<T>, len: usize, name: &str) -> usize {
+    assert!(
+        len != 0 && slice.len() % len == 0,
+        "`{}` must be rows of {} elements",
+        name,
+        len
+    );
+    slice.len() / len
+}
+
+/// The weights of a fully connected layer in device memory, for [`NnKernels::linear`].
+pub struct Linear<T: Element + DeviceCopy> {
+    in_features: usize,
+    out_features: usize,
+    weight: DeviceBuffer<T>,
+    bias: Option<DeviceBuffer<T>>,
+    activation: Activation,
+}
+
+impl<T: Element + DeviceCopy> Linear<T> {
+    /// Uploads a layer with `out_features` rows of `in_features` weights in `weight`, the layout of
+    /// `torch.nn.Linear`, and `out_features` elements in `bias`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` or `bias` has the wrong length.
+    pub fn new(
+        in_features: usize,
+        out_features: usize,
+        weight: &[T],
+        bias: Option<&[T]>,
+        activation: Activation,
+    ) -> CudaResult<Self> {
+        assert_eq!(
+            weight.len(),
+            in_features * out_features,
+            "`weight` must have `out_features` rows of `in_features` weights"
+        );
+        if let Some(bias) = bias {
+            assert_eq!(
+                bias.len(),
+                out_features,
+                "`bias` must have `out_features` elements"
+            );
+        }
+        Ok(Self {
+            in_features,
+            out_features,
+            weight: DeviceBuffer::from_slice(weight)?,
+            bias: bias.map(DeviceBuffer::from_slice).transpose()?,
+            activation,
+        })
+    }
+
+    pub fn in_features(&self) -> usize {
+        self.in_features
+    }
+
+    pub fn out_features(&self) -> usize {
+        self.out_features
+    }
+
+    pub fn activation(&self) -> Activation {
+        self.activation
+    }
+}
+
+/// The parameters of a layer normalization in device memory, for [`NnKernels::layer_norm`].
+pub struct LayerNorm<T: Element + DeviceCopy> {
+    gamma: DeviceBuffer<T>,
+    beta: DeviceBuffer<T>,
+    epsilon: f32,
+}
+
+impl<T: Element + DeviceCopy> LayerNorm<T> {
+    /// Uploads the scale `gamma` and the shift `beta` of every feature. `epsilon` is added to the
+    /// variance, usually `1e-5`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `gamma` and `beta` have different lengths or are empty.
+    pub fn new(gamma: &[T], beta: &[T], epsilon: f32) -> CudaResult<Self> {
+        assert!(!gamma.is_empty(), "a layer norm needs at least one feature");
+        assert_eq!(
+            gamma.len(),
+            beta.len(),
+            "`gamma` and `beta` must have an element per feature"
+        );
+        Ok(Self {
+            gamma: DeviceBuffer::from_slice(gamma)?,
+            beta: DeviceBuffer::from_slice(beta)?,
+            epsilon,
+        })
+    }
+
+    pub fn features(&self) -> usize {
+        self.gamma.len()
+    }
+
+    pub fn epsilon(&self) -> f32 {
+        self.epsilon
+    }
+}
+
+/// Launches the kernels of this crate on tensors of `T` elements, `f32`, `f16` or `bf16`. Tensors are
+/// row-major with a row per token, see the module docs of every operation.
+///
+/// Every method only enqueues its kernels on `stream`, so a whole forward pass runs without waiting for
+/// the device until its output is copied back.
+pub struct NnKernels<'a, T: Element + DeviceCopy> {
+    linear: Function<'a>,
+    layer_norm: Function<'a>,
+    softmax: Function<'a>,
+    softmax_f32: Function<'a>,
+    scores: Function<'a>,
+    output: Function<'a>,
+    _element: PhantomData<T>,
+}
+
+impl<'a, T: Element + DeviceCopy> NnKernels<'a, T> {
+    /// `module` must be the PTX of a gpu crate depending on this crate with the `kernels` feature.
+    pub fn new(module: &'a Module) -> CudaResult<Self> {
+        let function = |name: &str| module.get_function(format!("nn_{}_{}", name, T::SUFFIX));
+        Ok(Self {
+            linear: function("linear")?,
+            layer_norm: function("layer_norm")?,
+            softmax: function("softmax")?,
+            softmax_f32: module.get_function("nn_softmax_f32")?,
+            scores: function("attention_scores")?,
+            output: function("attention_output")?,
+            _element: PhantomData,
+        })
+    }
+
+    /// Enqueues `y = activation(x * W^T + bias)` for every row of `x`, see the
+    /// [`linear`](crate::linear) module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is not rows of `in_features` elements or `y` does not have `out_features`
+    /// elements for each of them.
+    pub fn linear(
+        &self,
+        stream: &Stream,
+        layer: &Linear<T>,
+        x: &DeviceSlice<T>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        self.launch_linear(stream, layer, x, None, y)
+    }
+
+    /// Enqueues `y = activation(x * W^T + bias) + residual` for every row of `x`, with `residual` of
+    /// the same size as `y`.
+    ///
+    /// # Panics
+    ///
+    /// Like [`linear`](Self::linear), and if `residual` has a different length than `y`.
+    pub fn linear_residual(
+        &self,
+        stream: &Stream,
+        layer: &Linear<T>,
+        x: &DeviceSlice<T>,
+        residual: &DeviceSlice<T>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        assert_eq!(
+            residual.len(),
+            y.len(),
+            "`residual` must have the same length as `y`"
+        );
+        self.launch_linear(stream, layer, x, Some(residual), y)
+    }
+
+    fn launch_linear(
+        &self,
+        stream: &Stream,
+        layer: &Linear<T>,
+        x: &DeviceSlice<T>,
+        residual: Option<&DeviceSlice<T>>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        let mut m = rows_of(x, layer.in_features, "x");
+        let (mut n, mut k) = (layer.out_features, layer.in_features);
+        assert_eq!(
+            y.len(),
+            m * n,
+            "`y` must have `out_features` elements per row of `x`"
+        );
+        let mut x = x.as_ptr();
+        let mut weight = layer.weight.as_ptr();
+        let mut bias = layer
+            .bias
+            .as_ref()
+            .map_or(std::ptr::null(), |bias| bias.as_ptr());
+        let mut activation = layer.activation;
+        let mut residual = residual.map_or(std::ptr::null(), |residual| residual.as_ptr());
+        let mut y = y.as_mut_ptr();
+        let params = params!(m, n, k, x, weight, bias, activation, residual, y);
+        let grid = (blocks_for(n, TILE), blocks_for(m, TILE));
+        unsafe { stream.launch(&self.linear, grid, (BLOCK, BLOCK), 0, &params) }
+    }
+
+    /// Enqueues normalizing every row of `x` into `y`, see the [`norm`](crate::norm) module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is not rows of `features` elements or `y` has a different length.
+    pub fn layer_norm(
+        &self,
+        stream: &Stream,
+        layer: &LayerNorm<T>,
+        x: &DeviceSlice<T>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        let (mut rows, mut cols) = (rows_of(x, layer.features(), "x"), layer.features());
+        assert_eq!(y.len(), x.len(), "`y` must have the same length as `x`");
+        let mut x = x.as_ptr();
+        let mut gamma = layer.gamma.as_ptr();
+        let mut beta = layer.beta.as_ptr();
+        let mut epsilon = layer.epsilon;
+        let mut y = y.as_mut_ptr();
+        let params = params!(rows, cols, x, gamma, beta, epsilon, y);
+        let blocks = blocks_for(WARP_SIZE * rows, ROW_BLOCK);
+        unsafe { stream.launch(&self.layer_norm, blocks, ROW_BLOCK, 0, &params) }
+    }
+
+    /// Enqueues writing the softmax of every row of `cols` elements of `x` to `y`, see the
+    /// [`softmax`](crate::softmax) module.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `x` is not rows of `cols` elements or `y` has a different length.
+    pub fn softmax(
+        &self,
+        stream: &Stream,
+        cols: usize,
+        x: &DeviceSlice<T>,
+        y: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        assert_eq!(y.len(), x.len(), "`y` must have the same length as `x`");
+        launch_softmax(
+            &self.softmax,
+            stream,
+            rows_of(x, cols, "x"),
+            cols,
+            x.as_ptr(),
+            y.as_mut_ptr(),
+        )
+    }
+
+    /// Enqueues writing the attention probabilities of `q` and `k` to `probabilities`, the softmax of
+    /// the scaled and masked scores, see the [`attention`](crate::attention) module.
+    ///
+    /// `q` and `k` start at the first element of the first head of their first row, so that they can be
+    /// subslices of the same buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` or `k` is too short for `shape`, `probabilities` does not have
+    /// [`scores_len`](AttentionShape::scores_len) elements, or the mask is causal with fewer keys than
+    /// queries.
+    pub fn attention_probabilities(
+        &self,
+        stream: &Stream,
+        shape: &AttentionShape,
+        q: &DeviceSlice<T>,
+        k: &DeviceSlice<T>,
+        probabilities: &mut DeviceSlice<f32>,
+    ) -> CudaResult<()> {
+        check_probabilities(shape, q, k, probabilities);
+        self.launch_probabilities(stream, shape, q, k, probabilities)
+    }
+
+    /// [`attention_probabilities`](Self::attention_probabilities) without the checks.
+    fn launch_probabilities(
+        &self,
+        stream: &Stream,
+        shape: &AttentionShape,
+        q: &DeviceSlice<T>,
+        k: &DeviceSlice<T>,
+        probabilities: &mut DeviceSlice<f32>,
+    ) -> CudaResult<()> {
+        let mut shape_param = *shape;
+        let (mut q, mut k) = (q.as_ptr(), k.as_ptr());
+        let mut scores = probabilities.as_mut_ptr();
+        let params = params!(shape_param, q, k, scores);
+        let grid = (
+            blocks_for(shape.kv_len, TILE),
+            blocks_for(shape.q_len, TILE),
+            shape.heads as u32,
+        );
+        unsafe {
+            stream.launch(&self.scores, grid, (BLOCK, BLOCK), 0, &params)?;
+        }
+        launch_softmax(
+            &self.softmax_f32,
+            stream,
+            shape.heads * shape.q_len,
+            shape.kv_len,
+            scores,
+            scores,
+        )
+    }
+
+    /// Enqueues attention over `q`, `k` and `v`, writing the output of every head to `out`, with
+    /// `probabilities` for the intermediate probabilities, see
+    /// [`attention_probabilities`](Self::attention_probabilities).
+    ///
+    /// # Panics
+    ///
+    /// Like [`attention_probabilities`](Self::attention_probabilities), and if `v` or `out` is too
+    /// short for `shape`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn attention(
+        &self,
+        stream: &Stream,
+        shape: &AttentionShape,
+        q: &DeviceSlice<T>,
+        k: &DeviceSlice<T>,
+        v: &DeviceSlice<T>,
+        probabilities: &mut DeviceSlice<f32>,
+        out: &mut DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        // nothing is launched unless every input is valid.
+        check_probabilities(shape, q, k, probabilities);
+        check_rows(v.len(), shape.kv_len, shape.v_stride, shape, "v");
+        check_rows(out.len(), shape.q_len, shape.out_stride, shape, "out");
+        self.launch_probabilities(stream, shape, q, k, probabilities)?;
+
+        let mut shape_param = *shape;
+        let mut probabilities = probabilities.as_ptr();
+        let (mut v, mut out) = (v.as_ptr(), out.as_mut_ptr());
+        let params = params!(shape_param, probabilities, v, out);
+        let grid = (
+            blocks_for(shape.head_dim, TILE),
+            blocks_for(shape.q_len, TILE),
+            shape.heads as u32,
+        );
+        unsafe { stream.launch(&self.output, grid, (BLOCK, BLOCK), 0, &params) }
+    }
+}
+
+fn launch_softmax<T>(
+    function: &Function,
+    stream: &Stream,
+    rows: usize,
+    cols: usize,
+    x: *const T,
+    y: *mut T,
+) -> CudaResult<()> {
+    let (mut rows, mut cols, mut x, mut y) = (rows, cols, x, y);
+    let params = params!(rows, cols, x, y);
+    let blocks = blocks_for(WARP_SIZE * rows, ROW_BLOCK);
+    unsafe { stream.launch(function, blocks, ROW_BLOCK, 0, &params) }
+}
+
+fn check_shape(shape: &AttentionShape) {
+    assert!(
+        shape.heads != 0 && shape.head_dim != 0 && shape.q_len != 0 && shape.kv_len != 0,
+        "attention needs at least one head, query and key, of at least one element"
+    );
+    assert!(
+        !shape.causal || shape.kv_len >= shape.q_len,
+        "a causal mask needs at least as many keys as queries"
+    );
+}
+
+/// The checks of [`NnKernels::attention_probabilities`].
+fn check_probabilities<T: DeviceCopy>(
+    shape: &AttentionShape,
+    q: &DeviceSlice<T>,
+    k: &DeviceSlice<T>,
+    probabilities: &DeviceSlice<f32>,
+) {
+    check_shape(shape);
+    check_rows(q.len(), shape.q_len, shape.q_stride, shape, "q");
+    check_rows(k.len(), shape.kv_len, shape.k_stride, shape, "k");
+    assert_eq!(
+        probabilities.len(),
+        shape.scores_len(),
+        "`probabilities` must have `heads * q_len * kv_len` elements"
+    );
+}
+
+/// Checks that `len` elements hold `rows` rows `stride` elements apart, of every head of `shape`.
+fn check_rows(len: usize, rows: usize, stride: usize, shape: &AttentionShape, name: &str) {
+    let row_len = shape.heads * shape.head_dim;
+    assert!(
+        stride >= row_len,
+        "the rows of `{}` overlap, their stride is less than `heads * head_dim`",
+        name
+    );
+    assert!(
+        len >= (rows - 1) * stride + row_len,
+        "`{}` is too short for its rows",
+        name
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cust::memory::DevicePointer;
+
+    /// A slice of `len` elements, only its length is used.
+    fn slice<T: DeviceCopy>(len: usize) -> &'static DeviceSlice<T> {
+        unsafe {
+            DeviceSlice::from_raw_parts(
+                DevicePointer::wrap(std::ptr::NonNull::dangling().as_ptr()),
+                len,
+            )
+        }
+    }
+
+    #[test]
+    fn counts_rows() {
+        assert_eq!(rows_of(slice::<f32>(12), 4, "x"), 3);
+        assert_eq!(rows_of(slice::<f32>(12), 12, "x"), 1);
+        assert_eq!(rows_of(slice::<f32>(0), 4, "x"), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "`x` must be rows of 5 elements")]
+    fn rejects_partial_rows() {
+        rows_of(slice::<f32>(12), 5, "x");
+    }
+
+    #[test]
+    #[should_panic(expected = "`x` must be rows of 0 elements")]
+    fn rejects_empty_rows() {
+        rows_of(slice::<f32>(0), 0, "x");
+    }
+
+    #[test]
+    fn checks_strided_rows() {
+        // 2 heads of 4 elements, 3 rows.
+        let shape = AttentionShape::new(2, 4, 3, 3);
+        check_rows(24, 3, 8, &shape, "q");
+        // the last row does not need the padding after it.
+        check_rows(2 * 24 + 8, 3, 24, &shape, "q");
+        check_rows(8, 1, 8, &shape, "q");
+    }
+
+    #[test]
+    #[should_panic(expected = "the rows of `k` overlap")]
+    fn rejects_overlapping_rows() {
+        check_rows(100, 3, 7, &AttentionShape::new(2, 4, 3, 3), "k");
+    }
+
+    #[test]
+    #[should_panic(expected = "`v` is too short for its rows")]
+    fn rejects_short_rows() {
+        check_rows(2 * 24 + 7, 3, 24, &AttentionShape::new(2, 4, 3, 3), "v");
+    }
+
+    #[test]
+    fn checks_attention_inputs() {
+        let shape = AttentionShape::new(2, 4, 3, 5);
+        check_probabilities(&shape, slice::<f32>(24), slice(40), slice(30));
+    }
+
+    #[test]
+    #[should_panic(expected = "`probabilities` must have")]
+    fn rejects_short_probabilities() {
+        let shape = AttentionShape::new(2, 4, 3, 5);
+        check_probabilities(&shape, slice::<f32>(24), slice(40), slice(29));
+    }
+
+    #[test]
+    #[should_panic(expected = "a causal mask needs at least as many keys as queries")]
+    fn rejects_causal_masks_without_enough_keys() {
+        let shape = AttentionShape {
+            causal: true,
+            ..AttentionShape::new(2, 4, 5, 3)
+        };
+        check_probabilities(&shape, slice::<f32>(40), slice(24), slice(30));
+    }
+
+    #[test]
+    #[should_panic(expected = "attention needs at least one head")]
+    fn rejects_empty_shapes() {
+        let shape = AttentionShape::new(2, 4, 0, 3);
+        check_probabilities(&shape, slice::<f32>(0), slice(24), slice(0));
+    }
+}
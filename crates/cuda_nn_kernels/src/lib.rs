@@ -0,0 +1,79 @@
+//! Fused kernels for neural network inference written in Rust, enough to run a small transformer end
+//! to end without cuDNN or cuBLAS.
+//!
+//! - [`linear`]: fully connected layers, a tiled matrix multiplication with the bias, the activation
+//!   (ReLU or GELU) and an optional residual applied before the result is stored.
+//! - [`norm`]: layer normalization with one warp per row.
+//! - [`softmax`]: the online softmax, which finds the maximum and the sum of the exponentials of a row
+//!   in one pass.
+//! - [`attention`]: multi-head scaled dot-product attention with an optional causal mask, reading `Q`,
+//!   `K` and `V` straight from the output of a QKV projection.
+//!
+//! Every kernel exists for [`f32`], [`f16`](half::f16) and [`bf16`](half::bf16) activations and
+//! weights (see [`Element`]), and computes in `f32`. Tensors are row-major with a row per token.
+//!
+//! Like `cuda_linalg`, this crate is used from both sides: the gpu crate depends on it to get the kernels
+//! into its PTX (with the default `kernels` feature), and the host crate to upload the weights and
+//! launch them with [`NnKernels`]. Kernels are named `nn_<operation>_<type>`, for example
+//! `nn_linear_f16`.
+//!
+//! ```ignore
+//! // host, the attention half of a GPT-2 block in f16
+//! let module = Module::from_str(PTX)?;
+//! let nn = NnKernels::<f16>::new(&module)?;
+//! let ln = LayerNorm::new(&gamma, &beta, 1e-5)?;
+//! let qkv = Linear::new(hidden, 3 * hidden, &qkv_weight, Some(&qkv_bias), Activation::Identity)?;
+//! let proj = Linear::new(hidden, hidden, &proj_weight, Some(&proj_bias), Activation::Identity)?;
+//!
+//! let shape = AttentionShape {
+//!     q_stride: 3 * hidden,
+//!     k_stride: 3 * hidden,
+//!     v_stride: 3 * hidden,
+//!     causal: true,
+//!     ..AttentionShape::new(heads, hidden / heads, tokens, tokens)
+//! };
+//! nn.layer_norm(&stream, &ln, &x, &mut normed)?;
+//! nn.linear(&stream, &qkv, &normed, &mut qkv_out)?;
+//! nn.attention(
+//!     &stream,
+//!     &shape,
+//!     &qkv_out,
+//!     &qkv_out[hidden..],
+//!     &qkv_out[2 * hidden..],
+//!     &mut probabilities,
+//!     &mut attended,
+//! )?;
+//! nn.linear_residual(&stream, &proj, &attended, &x, &mut y)?;
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr, asm, asm_experimental_arch),
+    register_attr(nvvm_internal)
+)]
+
+mod element;
+
+pub use element::Element;
+
+pub mod attention;
+pub mod linear;
+pub mod norm;
+pub mod softmax;
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod tile;
+
+#[cfg(not(target_os = "cuda"))]
+mod host;
+
+pub use attention::AttentionShape;
+#[cfg(not(target_os = "cuda"))]
+pub use host::*;
+pub use linear::Activation;
+
+/// The width and height of the blocks of the linear and attention kernels.
+pub const BLOCK: u32 = 16;
+/// The number of threads per block of the layer norm and softmax kernels, which use a warp per row.
+pub const ROW_BLOCK: u32 = 256;
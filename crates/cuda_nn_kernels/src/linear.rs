@@ -0,0 +1,123 @@
+//! Fully connected layers, `y = activation(x * W^T + bias) + residual`, as one kernel.
+//!
+//! `x` has a row of `in_features` elements per token, `W` a row of `in_features` weights per output
+//! feature (the layout of `torch.nn.Linear`, so that checkpoints can be uploaded as they are), and `y`
+//! a row of `out_features` elements per token. The bias, the activation and the residual (usually the
+//! input of the transformer block, added after the attention output projection and the second
+//! projection of the MLP) are applied to the `f32` accumulators before they are stored, instead of
+//! making extra passes over `y` in memory.
+//!
+//! The kernels are:
+//!
+//! - `nn_linear_{f32,f16,bf16}(m: usize, n: usize, k: usize, x: *const T, weight: *const T, bias: *const T, activation: Activation, residual: *const T, y: *mut T)`:
+//!   `m` rows of `k` inputs and `n` outputs. `bias` (of `n` elements) and `residual` (like `y`) may
+//!   be null. Launched with blocks of [`BLOCK`](crate::BLOCK)x[`BLOCK`](crate::BLOCK) threads and a
+//!   grid of `(ceil(n / 64), ceil(m / 64))` blocks, every block computing a 64x64 tile of `y` through
+//!   shared memory.
+//!
+//! On the host, [`NnKernels::linear`](crate::NnKernels::linear) launches them for a
+//! [`Linear`](crate::Linear) layer.
+
+/// The function applied to the outputs of a linear layer.
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activation {
+    Identity,
+    /// `max(x, 0)`.
+    Relu,
+    /// The GELU of GPT-2 and BERT, with the `tanh` approximation.
+    Gelu,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for Activation {}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::Activation;
+    use crate::{tile, Element};
+    use core::mem::MaybeUninit;
+    use cuda_std::{kernel, shared_array, GpuFloat};
+    use half::{bf16, f16};
+
+    impl Activation {
+        #[inline(always)]
+        fn apply(self, x: f32) -> f32 {
+            match self {
+                Activation::Identity => x,
+                Activation::Relu => x.max(0.0),
+                Activation::Gelu => {
+                    // sqrt(2 / pi)
+                    const C: f32 = 0.797_884_6;
+                    0.5 * x * (1.0 + GpuFloat::tanh(C * (x + 0.044715 * x * x * x)))
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    #[inline(always)]
+    unsafe fn linear<T: Element>(
+        m: usize,
+        n: usize,
+        k: usize,
+        x: *const T,
+        weight: *const T,
+        bias: *const T,
+        activation: Activation,
+        residual: *const T,
+        y: *mut T,
+        x_shared: *mut f32,
+        weight_shared: *mut f32,
+    ) {
+        let acc = tile::multiply(m, n, k, x, k, weight, k, true, x_shared, weight_shared);
+        tile::for_each_output(&acc, m, n, |row, col, value| {
+            let mut value = value;
+            if !bias.is_null() {
+                value += (*bias.add(col)).to_f32();
+            }
+            value = activation.apply(value);
+            let i = row * n + col;
+            if !residual.is_null() {
+                value += (*residual.add(i)).to_f32();
+            }
+            *y.add(i) = T::from_f32(value);
+        });
+    }
+
+    macro_rules! linear_kernels {
+        ($($name:ident, $ty:ty;)*) => {
+            $(
+                #[kernel]
+                #[allow(
+                    improper_ctypes_definitions,
+                    clippy::missing_safety_doc,
+                    clippy::too_many_arguments
+                )]
+                pub unsafe fn $name(
+                    m: usize,
+                    n: usize,
+                    k: usize,
+                    x: *const $ty,
+                    weight: *const $ty,
+                    bias: *const $ty,
+                    activation: Activation,
+                    residual: *const $ty,
+                    y: *mut $ty,
+                ) {
+                    let x_shared = shared_array![f32; tile::SHARED_LEN];
+                    let weight_shared = shared_array![f32; tile::SHARED_LEN];
+                    linear(
+                        m, n, k, x, weight, bias, activation, residual, y, x_shared, weight_shared,
+                    );
+                }
+            )*
+        };
+    }
+
+    linear_kernels! {
+        nn_linear_f32, f32;
+        nn_linear_f16, f16;
+        nn_linear_bf16, bf16;
+    }
+}
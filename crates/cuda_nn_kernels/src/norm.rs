@@ -0,0 +1,91 @@
+//! Layer normalization, `y = (x - mean) / sqrt(variance + epsilon) * gamma + beta` over every row.
+//!
+//! Every row is normalized by one warp: the lanes take every 32nd element, the mean and then the
+//! variance are summed in `f32` and reduced with warp shuffles, and the row is read a third time to
+//! write the result. The variance is the mean of the squared differences to the mean, which is more
+//! accurate than the mean of the squares minus the squared mean for rows far from zero, and the rows
+//! of a transformer (a few thousand elements) are read back from the L1 cache.
+//!
+//! The kernels are:
+//!
+//! - `nn_layer_norm_{f32,f16,bf16}(rows: usize, cols: usize, x: *const T, gamma: *const T, beta: *const T, epsilon: f32, y: *mut T)`:
+//!   normalizes `rows` rows of `cols` elements of `x` into `y`, which may be the same as `x`.
+//!   `gamma` and `beta` have `cols` elements. Launched with 32 threads per row, in blocks of
+//!   [`ROW_BLOCK`](crate::ROW_BLOCK) threads.
+//!
+//! On the host, [`NnKernels::layer_norm`](crate::NnKernels::layer_norm) launches them for a
+//! [`LayerNorm`](crate::LayerNorm).
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use crate::Element;
+    use cuda_std::{collective, kernel, thread, warp, GpuFloat};
+    use half::{bf16, f16};
+
+    #[allow(clippy::too_many_arguments)]
+    #[inline(always)]
+    unsafe fn layer_norm<T: Element>(
+        rows: usize,
+        cols: usize,
+        x: *const T,
+        gamma: *const T,
+        beta: *const T,
+        epsilon: f32,
+        y: *mut T,
+    ) {
+        // the whole warp has the same row, so it exits together.
+        let row = thread::index_1d() as usize / warp::WARP_SIZE as usize;
+        if row >= rows {
+            return;
+        }
+        let lane = warp::lane_id() as usize;
+        let stride = warp::WARP_SIZE as usize;
+        let (x, y) = (x.add(row * cols), y.add(row * cols));
+
+        let mut sum = 0.0;
+        for i in (lane..cols).step_by(stride) {
+            sum += (*x.add(i)).to_f32();
+        }
+        let mean = collective::warp_reduce(sum, |a, b| a + b) / cols as f32;
+
+        let mut squares = 0.0;
+        for i in (lane..cols).step_by(stride) {
+            let d = (*x.add(i)).to_f32() - mean;
+            squares += d * d;
+        }
+        let variance = collective::warp_reduce(squares, |a, b| a + b) / cols as f32;
+        let scale = 1.0 / GpuFloat::sqrt(variance + epsilon);
+
+        for i in (lane..cols).step_by(stride) {
+            let normalized = ((*x.add(i)).to_f32() - mean) * scale;
+            let value = normalized * (*gamma.add(i)).to_f32() + (*beta.add(i)).to_f32();
+            *y.add(i) = T::from_f32(value);
+        }
+    }
+
+    macro_rules! layer_norm_kernels {
+        ($($name:ident, $ty:ty;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $name(
+                    rows: usize,
+                    cols: usize,
+                    x: *const $ty,
+                    gamma: *const $ty,
+                    beta: *const $ty,
+                    epsilon: f32,
+                    y: *mut $ty,
+                ) {
+                    layer_norm(rows, cols, x, gamma, beta, epsilon, y);
+                }
+            )*
+        };
+    }
+
+    layer_norm_kernels! {
+        nn_layer_norm_f32, f32;
+        nn_layer_norm_f16, f16;
+        nn_layer_norm_bf16, bf16;
+    }
+}
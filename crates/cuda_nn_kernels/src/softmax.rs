@@ -0,0 +1,88 @@
+//! Softmax over every row, `y[i] = exp(x[i] - max) / sum(exp(x[j] - max))`.
+//!
+//! Like layer normalization, every row is handled by one warp. The maximum and the sum of the
+//! exponentials are computed in a single pass with the online softmax of [Online normalizer
+//! calculation for softmax](https://arxiv.org/abs/1805.02867) by Milakov and Gimelshein: every lane
+//! keeps the largest element it has seen and the sum of the exponentials relative to it, rescaling
+//! the sum whenever the maximum grows, and the warp combines the pairs of its lanes the same way. A
+//! second pass writes the probabilities.
+//!
+//! Elements of negative infinity (masked out) get a probability of zero, a row which is entirely
+//! masked out is all zeros instead of NaN.
+//!
+//! The kernels are:
+//!
+//! - `nn_softmax_{f32,f16,bf16}(rows: usize, cols: usize, x: *const T, y: *mut T)`: writes the softmax
+//!   of `rows` rows of `cols` elements of `x` to `y`, which may be the same as `x`. Launched with 32
+//!   threads per row, in blocks of [`ROW_BLOCK`](crate::ROW_BLOCK) threads.
+//!
+//! On the host, [`NnKernels::softmax`](crate::NnKernels::softmax) launches them, and
+//! [`NnKernels::attention_probabilities`](crate::NnKernels::attention_probabilities) applies
+//! `nn_softmax_f32` to the attention scores.
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use crate::Element;
+    use cuda_std::{collective, kernel, thread, warp, GpuFloat};
+    use half::{bf16, f16};
+
+    /// Combines two pairs of a maximum and the sum of the exponentials relative to it.
+    #[inline(always)]
+    fn combine((max, sum): (f32, f32), (other_max, other_sum): (f32, f32)) -> (f32, f32) {
+        if other_max > max {
+            (other_max, sum * GpuFloat::exp(max - other_max) + other_sum)
+        } else if other_max == f32::NEG_INFINITY {
+            // also when both are, where the difference would be NaN.
+            (max, sum)
+        } else {
+            (max, sum + other_sum * GpuFloat::exp(other_max - max))
+        }
+    }
+
+    #[inline(always)]
+    unsafe fn softmax<T: Element>(rows: usize, cols: usize, x: *const T, y: *mut T) {
+        // the whole warp has the same row, so it exits together.
+        let row = thread::index_1d() as usize / warp::WARP_SIZE as usize;
+        if row >= rows {
+            return;
+        }
+        let lane = warp::lane_id() as usize;
+        let stride = warp::WARP_SIZE as usize;
+        let (x, y) = (x.add(row * cols), y.add(row * cols));
+
+        let mut state = (f32::NEG_INFINITY, 0.0);
+        for i in (lane..cols).step_by(stride) {
+            state = combine(state, ((*x.add(i)).to_f32(), 1.0));
+        }
+        let (max, sum) = collective::warp_reduce(state, combine);
+        let scale = if sum > 0.0 { 1.0 / sum } else { 0.0 };
+
+        for i in (lane..cols).step_by(stride) {
+            let value = (*x.add(i)).to_f32();
+            let probability = if value == f32::NEG_INFINITY {
+                0.0
+            } else {
+                GpuFloat::exp(value - max) * scale
+            };
+            *y.add(i) = T::from_f32(probability);
+        }
+    }
+
+    macro_rules! softmax_kernels {
+        ($($name:ident, $ty:ty;)*) => {
+            $(
+                #[kernel]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+                pub unsafe fn $name(rows: usize, cols: usize, x: *const $ty, y: *mut $ty) {
+                    softmax(rows, cols, x, y);
+                }
+            )*
+        };
+    }
+
+    softmax_kernels! {
+        nn_softmax_f32, f32;
+        nn_softmax_f16, f16;
+        nn_softmax_bf16, bf16;
+    }
+}
@@ -0,0 +1,149 @@
+//! The tiled matrix multiplication of the linear and attention kernels, `C = A * B` with `A` of
+//! `m`x`k` and `B` of `k`x`n`, both row-major with a leading dimension, and `B` optionally stored
+//! transposed (`n` rows of `k` elements, like the weights of a linear layer or the keys of attention).
+//!
+//! Every block of [`BLOCK`]x[`BLOCK`] threads computes a [`TILE_M`]x[`TILE_N`] tile of `C`, walking
+//! over `k` [`TILE_K`] elements at a time. The tiles of `A` and `B` are converted to `f32` while they
+//! are loaded into shared memory, both stored with `k` as the outer dimension, with rows padded by one
+//! element so that storing the tiles transposed causes few bank conflicts. Every thread then accumulates
+//! 4x4 outputs in registers, the rows `ty + 16 r` and columns `tx + 16 c`, so that every value read
+//! from shared memory is used four times and the lanes of a warp store consecutive elements.
+
+use crate::Element;
+use cuda_std::thread;
+
+/// The number of rows of `C` computed by every block.
+pub const TILE_M: usize = 64;
+/// The number of columns of `C` computed by every block.
+pub const TILE_N: usize = 64;
+/// The number of elements of `k` loaded into shared memory at a time.
+pub const TILE_K: usize = 16;
+/// The width and height of the blocks.
+pub const BLOCK: usize = crate::BLOCK as usize;
+/// The number of rows and columns of outputs of every thread.
+const PER_THREAD: usize = TILE_M / BLOCK;
+const THREADS: usize = BLOCK * BLOCK;
+/// The distance between the rows of the tiles in shared memory, one more than their length.
+const STRIDE: usize = TILE_M + 1;
+/// The number of `f32`s of shared memory [`multiply`] needs for each of the tiles of `A` and `B`.
+pub const SHARED_LEN: usize = TILE_K * STRIDE;
+
+/// The outputs of a thread, see the [module docs](self).
+pub type Accumulators = [[f32; PER_THREAD]; PER_THREAD];
+
+/// The row and column of the first element of the tile of this block.
+#[inline(always)]
+pub fn origin() -> (usize, usize) {
+    (
+        thread::block_idx_y() as usize * TILE_M,
+        thread::block_idx_x() as usize * TILE_N,
+    )
+}
+
+/// Computes the outputs of this thread in the tile of `C` of this block.
+///
+/// # Safety
+///
+/// - Every thread of the block must call this function, the block must be [`BLOCK`]x[`BLOCK`]
+///   threads.
+/// - `a` and `b` must be valid for the shape.
+/// - `a_shared` and `b_shared` must point to distinct shared memory of [`SHARED_LEN`] `f32`s each.
+#[allow(clippy::too_many_arguments)]
+#[inline(always)]
+pub unsafe fn multiply<A: Element, B: Element>(
+    m: usize,
+    n: usize,
+    k: usize,
+    a: *const A,
+    lda: usize,
+    b: *const B,
+    ldb: usize,
+    b_transposed: bool,
+    a_shared: *mut f32,
+    b_shared: *mut f32,
+) -> Accumulators {
+    let (row0, col0) = origin();
+    let (tx, ty) = (
+        thread::thread_idx_x() as usize,
+        thread::thread_idx_y() as usize,
+    );
+    let id = ty * BLOCK + tx;
+    let mut acc = [[0.0; PER_THREAD]; PER_THREAD];
+    let mut k0 = 0;
+    while k0 < k {
+        for load in 0..TILE_M * TILE_K / THREADS {
+            let i = id + load * THREADS;
+            // consecutive threads load consecutive elements of a row.
+            let (row, kk) = (i / TILE_K, i % TILE_K);
+            let value = if row0 + row < m && k0 + kk < k {
+                (*a.add((row0 + row) * lda + k0 + kk)).to_f32()
+            } else {
+                0.0
+            };
+            *a_shared.add(kk * STRIDE + row) = value;
+
+            let (col, kk, offset) = if b_transposed {
+                let (col, kk) = (i / TILE_K, i % TILE_K);
+                (col, kk, (col0 + col) * ldb + k0 + kk)
+            } else {
+                let (col, kk) = (i % TILE_N, i / TILE_N);
+                (col, kk, (k0 + kk) * ldb + col0 + col)
+            };
+            let value = if col0 + col < n && k0 + kk < k {
+                (*b.add(offset)).to_f32()
+            } else {
+                0.0
+            };
+            *b_shared.add(kk * STRIDE + col) = value;
+        }
+        thread::sync_threads();
+
+        for kk in 0..TILE_K {
+            let a_row = a_shared.add(kk * STRIDE + ty);
+            let b_row = b_shared.add(kk * STRIDE + tx);
+            let mut a_values = [0.0; PER_THREAD];
+            let mut b_values = [0.0; PER_THREAD];
+            for i in 0..PER_THREAD {
+                a_values[i] = *a_row.add(i * BLOCK);
+                b_values[i] = *b_row.add(i * BLOCK);
+            }
+            for (acc_row, &a_value) in acc.iter_mut().zip(&a_values) {
+                for (out, &b_value) in acc_row.iter_mut().zip(&b_values) {
+                    *out += a_value * b_value;
+                }
+            }
+        }
+        // the tiles are overwritten by the loads of the next iteration.
+        thread::sync_threads();
+        k0 += TILE_K;
+    }
+    acc
+}
+
+/// Calls `f` with the row, the column and the value of every output of this thread which is inside
+/// the `m`x`n` matrix `C`.
+#[inline(always)]
+pub fn for_each_output(
+    acc: &Accumulators,
+    m: usize,
+    n: usize,
+    mut f: impl FnMut(usize, usize, f32),
+) {
+    let (row0, col0) = origin();
+    let (tx, ty) = (
+        thread::thread_idx_x() as usize,
+        thread::thread_idx_y() as usize,
+    );
+    for (r, acc_row) in acc.iter().enumerate() {
+        let row = row0 + ty + r * BLOCK;
+        if row >= m {
+            break;
+        }
+        for (c, &value) in acc_row.iter().enumerate() {
+            let col = col0 + tx + c * BLOCK;
+            if col < n {
+                f(row, col, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Uniform grids and spatial hashes, and the neighbor queries of kernels.
+//!
+//! Space is divided into cubic cells of a fixed size, and every cell is mapped to a bucket:
+//!
+//! - In a **uniform grid**, the buckets are the cells of a box of `dims` cells, points outside of the
+//!   box are put in the closest cell of its border. This is the fastest mapping if the points stay in
+//!   a known box.
+//! - In a **spatial hash**, the buckets are the entries of a hash table of the cell coordinates, so
+//!   space is unbounded and memory only depends on the number of buckets. Several cells can share a
+//!   bucket, the queries skip the points of the other cells.
+//!
+//! Building the grid sorts the indices of the points by bucket with a counting sort, see
+//! [`GpuSpatialGrid`](crate::GpuSpatialGrid). Kernels then get a [`GridView`] and iterate over the
+//! neighbors of a point with [`GridView::neighbors`], which visits the cells overlapping the query
+//! sphere. This is fastest when the radius is at most the cell size, so that at most 27 cells are
+//! visited.
+//!
+//! ```ignore
+//! #[kernel]
+//! pub unsafe fn density(grid: GridView, radius: f32, out: *mut f32) {
+//!     let i = thread::index_1d() as usize;
+//!     if i < grid.len() {
+//!         let mut sum = 0.0;
+//!         for (_, distance_sq) in grid.neighbors(grid.point(i), radius) {
+//!             sum += kernel_weight(distance_sq, radius);
+//!         }
+//!         *out.add(i) = sum;
+//!     }
+//! }
+//! ```
+
+#[cfg(target_os = "cuda")]
+use cuda_std::GpuFloat;
+
+/// How cells are mapped to buckets, see the [module docs](self).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpatialGrid {
+    origin: [f32; 3],
+    cell_size: f32,
+    /// The number of cells of a uniform grid, all zero for a spatial hash.
+    dims: [u32; 3],
+    /// The number of buckets of a spatial hash (a power of two), zero for a uniform grid.
+    hash_buckets: u32,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for SpatialGrid {}
+
+#[cfg(target_os = "cuda")]
+#[inline(always)]
+fn floor(x: f32) -> f32 {
+    GpuFloat::floor(x)
+}
+
+#[cfg(not(target_os = "cuda"))]
+#[inline(always)]
+fn floor(x: f32) -> f32 {
+    x.floor()
+}
+
+impl SpatialGrid {
+    /// A uniform grid of `dims` cells of `cell_size`, whose first cell starts at `origin`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` is not positive, or if there are no cells or more than `u32::MAX - 1`
+    /// of them.
+    pub fn uniform(origin: [f32; 3], cell_size: f32, dims: [u32; 3]) -> Self {
+        assert!(cell_size > 0.0, "the cell size must be positive");
+        let cells = dims
+            .iter()
+            .try_fold(1u32, |cells, &dim| cells.checked_mul(dim));
+        assert!(
+            matches!(cells, Some(cells) if cells > 0 && cells < u32::MAX),
+            "a uniform grid must have between 1 and `u32::MAX - 1` cells"
+        );
+        Self {
+            origin,
+            cell_size,
+            dims,
+            hash_buckets: 0,
+        }
+    }
+
+    /// A spatial hash of unbounded cells of `cell_size`, mapped to `buckets` buckets, which is rounded
+    /// up to a power of two. About as many buckets as points is a good start.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cell_size` is not positive, or `buckets` is zero or larger than `2^31`.
+    pub fn hashed(cell_size: f32, buckets: u32) -> Self {
+        assert!(cell_size > 0.0, "the cell size must be positive");
+        assert!(
+            buckets > 0 && buckets <= 1 << 31,
+            "a spatial hash must have between 1 and 2^31 buckets"
+        );
+        Self {
+            origin: [0.0; 3],
+            cell_size,
+            dims: [0; 3],
+            hash_buckets: buckets.next_power_of_two(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    pub fn is_hashed(&self) -> bool {
+        self.hash_buckets != 0
+    }
+
+    /// The number of buckets points are sorted into.
+    pub fn buckets(&self) -> u32 {
+        if self.is_hashed() {
+            self.hash_buckets
+        } else {
+            self.dims[0] * self.dims[1] * self.dims[2]
+        }
+    }
+
+    /// The coordinates of the cell containing `point`, clamped to the box of a uniform grid.
+    #[inline]
+    pub fn cell_of(&self, point: [f32; 3]) -> [i32; 3] {
+        let mut cell = [0; 3];
+        for axis in 0..3 {
+            let c = floor((point[axis] - self.origin[axis]) / self.cell_size) as i32;
+            cell[axis] = if self.is_hashed() {
+                c
+            } else {
+                c.clamp(0, self.dims[axis] as i32 - 1)
+            };
+        }
+        cell
+    }
+
+    /// The bucket of a cell, the cell must be inside of the box of a uniform grid.
+    #[inline]
+    pub fn bucket(&self, cell: [i32; 3]) -> u32 {
+        if self.is_hashed() {
+            // the hash of Teschner et al., "Optimized Spatial Hashing for Collision Detection of
+            // Deformable Objects".
+            let hash = (cell[0] as u32).wrapping_mul(73_856_093)
+                ^ (cell[1] as u32).wrapping_mul(19_349_663)
+                ^ (cell[2] as u32).wrapping_mul(83_492_791);
+            hash & (self.hash_buckets - 1)
+        } else {
+            cell[0] as u32 + self.dims[0] * (cell[1] as u32 + self.dims[1] * cell[2] as u32)
+        }
+    }
+}
+
+/// A built grid, for querying neighbors in kernels. See [`GpuSpatialGrid::view`](crate::GpuSpatialGrid::view).
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct GridView {
+    grid: SpatialGrid,
+    points: *const [f32; 3],
+    len: usize,
+    /// The start of every bucket in `indices`, and the number of points at the end.
+    starts: *const u32,
+    /// The indices of the points sorted by bucket.
+    indices: *const u32,
+}
+
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for GridView {}
+
+impl GridView {
+    /// # Safety
+    ///
+    /// - `points` must be valid for reads of `len` points and `indices` of `len` indices,
+    ///   `starts` of `grid.buckets() + 1` offsets.
+    /// - They must be the result of building `grid` over `points`, and must not change while the
+    ///   view is used.
+    pub unsafe fn from_raw_parts(
+        grid: SpatialGrid,
+        points: *const [f32; 3],
+        len: usize,
+        starts: *const u32,
+        indices: *const u32,
+    ) -> Self {
+        Self {
+            grid,
+            points,
+            len,
+            starts,
+            indices,
+        }
+    }
+
+    pub fn grid(&self) -> &SpatialGrid {
+        &self.grid
+    }
+
+    /// The number of points.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Point `i`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is out of bounds.
+    #[inline]
+    pub fn point(&self, i: usize) -> [f32; 3] {
+        assert!(i < self.len, "point index out of bounds");
+        unsafe { *self.points.add(i) }
+    }
+
+    /// The indices of the points of a bucket.
+    #[inline]
+    pub fn bucket_points(&self, bucket: u32) -> &[u32] {
+        assert!(bucket < self.grid.buckets(), "bucket index out of bounds");
+        unsafe {
+            let start = *self.starts.add(bucket as usize) as usize;
+            let end = *self.starts.add(bucket as usize + 1) as usize;
+            core::slice::from_raw_parts(self.indices.add(start), end - start)
+        }
+    }
+
+    /// The points at most `radius` away from `center`, as their index and squared distance, in no
+    /// particular order. If `center` is one of the points, it is included.
+    #[inline]
+    pub fn neighbors(&self, center: [f32; 3], radius: f32) -> Neighbors<'_> {
+        let lo = self
+            .grid
+            .cell_of([center[0] - radius, center[1] - radius, center[2] - radius]);
+        let hi = self
+            .grid
+            .cell_of([center[0] + radius, center[1] + radius, center[2] + radius]);
+        Neighbors {
+            view: self,
+            center,
+            radius_sq: radius * radius,
+            lo,
+            hi,
+            // one before the first cell, the first call to `next` moves to it.
+            cell: [lo[0] - 1, lo[1], lo[2]],
+            points: &[],
+        }
+    }
+}
+
+/// The iterator of [`GridView::neighbors`].
+#[derive(Clone, Debug)]
+pub struct Neighbors<'a> {
+    view: &'a GridView,
+    center: [f32; 3],
+    radius_sq: f32,
+    lo: [i32; 3],
+    hi: [i32; 3],
+    cell: [i32; 3],
+    /// The points of the current cell which were not visited yet.
+    points: &'a [u32],
+}
+
+impl<'a> Neighbors<'a> {
+    /// Moves to the next cell, returns `false` after the last one.
+    #[inline]
+    fn next_cell(&mut self) -> bool {
+        for axis in 0..3 {
+            if self.cell[axis] < self.hi[axis] {
+                self.cell[axis] += 1;
+                self.points = self.view.bucket_points(self.view.grid.bucket(self.cell));
+                return true;
+            }
+            self.cell[axis] = self.lo[axis];
+        }
+        false
+    }
+}
+
+impl<'a> Iterator for Neighbors<'a> {
+    type Item = (u32, f32);
+
+    #[inline]
+    fn next(&mut self) -> Option<(u32, f32)> {
+        loop {
+            while let Some((&index, rest)) = self.points.split_first() {
+                self.points = rest;
+                let point = self.view.point(index as usize);
+                // several cells of a spatial hash can share a bucket, only take the points of this one.
+                if self.view.grid.is_hashed() && self.view.grid.cell_of(point) != self.cell {
+                    continue;
+                }
+                let d = [
+                    point[0] - self.center[0],
+                    point[1] - self.center[1],
+                    point[2] - self.center[2],
+                ];
+                let distance_sq = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+                if distance_sq <= self.radius_sq {
+                    return Some((index, distance_sq));
+                }
+            }
+            if !self.next_cell() {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(all(target_os = "cuda", feature = "kernels"))]
+mod kernels {
+    use super::SpatialGrid;
+    use core::mem::MaybeUninit;
+    use cuda_std::{atomic, collective, kernel, shared_array, thread};
+
+    /// Computes the bucket of every point and its rank among the points of the bucket, and counts the
+    /// points of every bucket.
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn spatial_assign(
+        grid: SpatialGrid,
+        points: &[[f32; 3]],
+        buckets: *mut u32,
+        ranks: *mut u32,
+        counts: *mut u32,
+    ) {
+        let i = thread::index_1d() as usize;
+        if let Some(&point) = points.get(i) {
+            let bucket = grid.bucket(grid.cell_of(point));
+            *buckets.add(i) = bucket;
+            *ranks.add(i) = atomic::fetch_add(counts.add(bucket as usize), 1);
+        }
+    }
+
+    /// The exclusive prefix sum of the counts, launched with a single block. Resets the counts to zero
+    /// for the next build.
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn spatial_scan(counts: *mut u32, len: usize, starts: *mut u32) {
+        let scratch = shared_array![u32; 32];
+        let tile_total = shared_array![u32; 1];
+        let tid = thread::thread_idx_x() as usize;
+        let threads = thread::block_dim_x() as usize;
+
+        let mut carry = 0;
+        let mut tile = 0;
+        while tile < len {
+            let i = tile + tid;
+            let count = if i < len { *counts.add(i) } else { 0 };
+            let inclusive = collective::block_scan(count, scratch, |a, b| a + b);
+            if i < len {
+                *starts.add(i) = carry + inclusive - count;
+                *counts.add(i) = 0;
+            }
+            if tid == threads - 1 {
+                *tile_total = inclusive;
+            }
+            thread::sync_threads();
+            carry += *tile_total;
+            thread::sync_threads();
+            tile += threads;
+        }
+        if tid == 0 {
+            *starts.add(len) = carry;
+        }
+    }
+
+    /// Writes the index of every point to its place in the sorted order.
+    #[kernel]
+    #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+    pub unsafe fn spatial_scatter(
+        buckets: &[u32],
+        ranks: &[u32],
+        starts: &[u32],
+        indices: *mut u32,
+    ) {
+        let i = thread::index_1d() as usize;
+        if i < buckets.len() {
+            *indices.add((starts[buckets[i] as usize] + ranks[i]) as usize) = i as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sorts the points into the buckets like `GpuSpatialGrid::build`, returns the starts and indices.
+    fn sort(grid: &SpatialGrid, points: &[[f32; 3]]) -> (Vec<u32>, Vec<u32>) {
+        let buckets = points
+            .iter()
+            .map(|&point| grid.bucket(grid.cell_of(point)))
+            .collect::<Vec<_>>();
+        let mut starts = vec![0; grid.buckets() as usize + 1];
+        for &bucket in &buckets {
+            starts[bucket as usize + 1] += 1;
+        }
+        for i in 1..starts.len() {
+            starts[i] += starts[i - 1];
+        }
+        let mut next = starts.clone();
+        let mut indices = vec![0; points.len()];
+        for (i, &bucket) in buckets.iter().enumerate() {
+            indices[next[bucket as usize] as usize] = i as u32;
+            next[bucket as usize] += 1;
+        }
+        (starts, indices)
+    }
+
+    /// Points scattered over `[-1, 5)` on every axis.
+    fn points() -> Vec<[f32; 3]> {
+        let mut state = 1u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1 << 24) as f32 * 6.0 - 1.0
+        };
+        (0..200).map(|_| [next(), next(), next()]).collect()
+    }
+
+    fn assert_neighbors(grid: SpatialGrid) {
+        let points = points();
+        let (starts, indices) = sort(&grid, &points);
+        let view = unsafe {
+            GridView::from_raw_parts(
+                grid,
+                points.as_ptr(),
+                points.len(),
+                starts.as_ptr(),
+                indices.as_ptr(),
+            )
+        };
+        for radius in [0.5, 1.0, 1.5] {
+            for &center in points.iter().step_by(7).chain(&[[-3.0, 2.0, 8.0]]) {
+                let mut found = view
+                    .neighbors(center, radius)
+                    .map(|(index, distance_sq)| {
+                        assert!(distance_sq <= radius * radius);
+                        index
+                    })
+                    .collect::<Vec<_>>();
+                found.sort_unstable();
+                let expected = (0..points.len() as u32)
+                    .filter(|&i| {
+                        let d = [0, 1, 2].map(|axis| points[i as usize][axis] - center[axis]);
+                        d[0] * d[0] + d[1] * d[1] + d[2] * d[2] <= radius * radius
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(found, expected, "radius {} around {:?}", radius, center);
+            }
+        }
+    }
+
+    #[test]
+    fn indexes_uniform_cells() {
+        let grid = SpatialGrid::uniform([0.0; 3], 1.0, [4, 3, 2]);
+        assert_eq!(grid.buckets(), 24);
+        assert_eq!(grid.cell_of([0.0, 0.0, 0.0]), [0, 0, 0]);
+        assert_eq!(grid.cell_of([0.999, 0.999, 0.999]), [0, 0, 0]);
+        assert_eq!(grid.cell_of([1.0, 2.0, 1.0]), [1, 2, 1]);
+        // points outside of the box are clamped to its border, the upper faces included.
+        assert_eq!(grid.cell_of([4.0, 3.0, 2.0]), [3, 2, 1]);
+        assert_eq!(grid.cell_of([-0.5, -10.0, 100.0]), [0, 0, 1]);
+
+        let grid = SpatialGrid::uniform([-1.0; 3], 0.5, [4, 4, 4]);
+        assert_eq!(grid.cell_of([-1.0, -0.5, 0.9]), [0, 1, 3]);
+    }
+
+    #[test]
+    fn numbers_uniform_buckets() {
+        let grid = SpatialGrid::uniform([0.0; 3], 1.0, [4, 3, 2]);
+        assert_eq!(grid.bucket([0, 0, 0]), 0);
+        assert_eq!(grid.bucket([3, 0, 0]), 3);
+        assert_eq!(grid.bucket([0, 1, 0]), 4);
+        assert_eq!(grid.bucket([0, 0, 1]), 12);
+        assert_eq!(grid.bucket([3, 2, 1]), 23);
+        let mut buckets = Vec::new();
+        for z in 0..2 {
+            for y in 0..3 {
+                for x in 0..4 {
+                    buckets.push(grid.bucket([x, y, z]));
+                }
+            }
+        }
+        assert_eq!(buckets, (0..24).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn hashes_unbounded_cells() {
+        let grid = SpatialGrid::hashed(0.5, 100);
+        assert!(grid.is_hashed());
+        assert_eq!(grid.buckets(), 128);
+        assert_eq!(grid.cell_of([-0.25, -0.5, -0.75]), [-1, -1, -2]);
+        assert_eq!(grid.cell_of([1000.0, 0.0, 0.0]), [2000, 0, 0]);
+        assert_eq!(grid.bucket([0, 0, 0]), 0);
+        for x in -3..3 {
+            for y in -3..3 {
+                for z in -3..3 {
+                    assert!(grid.bucket([x, y, z]) < 128);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "cells")]
+    fn rejects_empty_uniform_grids() {
+        SpatialGrid::uniform([0.0; 3], 1.0, [4, 0, 4]);
+    }
+
+    #[test]
+    #[should_panic(expected = "buckets")]
+    fn rejects_empty_spatial_hashes() {
+        SpatialGrid::hashed(1.0, 0);
+    }
+
+    #[test]
+    fn finds_uniform_neighbors() {
+        // the points stick out of the box on every side.
+        assert_neighbors(SpatialGrid::uniform([0.0; 3], 1.0, [4, 4, 4]));
+    }
+
+    #[test]
+    fn finds_hashed_neighbors() {
+        // few buckets, so that many cells share one.
+        assert_neighbors(SpatialGrid::hashed(1.0, 8));
+        assert_neighbors(SpatialGrid::hashed(0.7, 1024));
+    }
+
+    #[test]
+    fn finds_nothing_in_empty_grids() {
+        let grid = SpatialGrid::uniform([0.0; 3], 1.0, [2, 2, 2]);
+        let (points, starts, indices) = ([], [0; 9], []);
+        let view = unsafe {
+            GridView::from_raw_parts(grid, points.as_ptr(), 0, starts.as_ptr(), indices.as_ptr())
+        };
+        assert_eq!(view.neighbors([1.0; 3], 1.0).count(), 0);
+    }
+}
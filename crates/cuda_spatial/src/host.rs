@@ -0,0 +1,114 @@
+use crate::grid::{GridView, SpatialGrid};
+use cust::{
+    error::CudaResult,
+    function::{blocks_for, Function},
+    memory::{DeviceBuffer, DeviceSlice},
+    module::Module,
+    params,
+    stream::Stream,
+};
+
+/// The number of threads per block of the kernels.
+const BLOCK: u32 = 256;
+/// The number of threads of the single block of the scan kernel.
+const SCAN_BLOCK: u32 = 1024;
+
+/// A [`SpatialGrid`] built over points in device memory, see the [`grid`](crate::grid) module.
+pub struct GpuSpatialGrid<'a> {
+    grid: SpatialGrid,
+    assign: Function<'a>,
+    scan: Function<'a>,
+    scatter: Function<'a>,
+    /// The number of points of every bucket, always zero between builds.
+    counts: DeviceBuffer<u32>,
+    starts: DeviceBuffer<u32>,
+    buckets: DeviceBuffer<u32>,
+    ranks: DeviceBuffer<u32>,
+    indices: DeviceBuffer<u32>,
+    points: *const [f32; 3],
+}
+
+impl<'a> GpuSpatialGrid<'a> {
+    /// Creates an empty grid. `module` must be the PTX of a gpu crate depending on this crate with the
+    /// `kernels` feature.
+    pub fn new(module: &'a Module, grid: SpatialGrid) -> CudaResult<Self> {
+        let buckets = grid.buckets() as usize;
+        Ok(Self {
+            grid,
+            assign: module.get_function("spatial_assign")?,
+            scan: module.get_function("spatial_scan")?,
+            scatter: module.get_function("spatial_scatter")?,
+            counts: unsafe { DeviceBuffer::zeroed(buckets)? },
+            starts: unsafe { DeviceBuffer::zeroed(buckets + 1)? },
+            buckets: DeviceBuffer::from_slice(&[])?,
+            ranks: DeviceBuffer::from_slice(&[])?,
+            indices: DeviceBuffer::from_slice(&[])?,
+            points: std::ptr::null(),
+        })
+    }
+
+    pub fn grid(&self) -> &SpatialGrid {
+        &self.grid
+    }
+
+    /// Enqueues sorting `points` into the grid on `stream`, replacing the points of the last build.
+    ///
+    /// The grid only stores the indices of the points, so `points` must not be freed or changed while
+    /// the grid is queried. The order of the points of every bucket changes from build to build.
+    pub fn build(&mut self, stream: &Stream, points: &DeviceSlice<[f32; 3]>) -> CudaResult<()> {
+        let len = points.len();
+        assert!(len < u32::MAX as usize, "too many points for u32 indices");
+        if self.indices.len() != len {
+            // the buffers may still be used by the last build.
+            stream.synchronize()?;
+            unsafe {
+                self.buckets = DeviceBuffer::uninitialized(len)?;
+                self.ranks = DeviceBuffer::uninitialized(len)?;
+                self.indices = DeviceBuffer::uninitialized(len)?;
+            }
+        }
+        self.points = points.as_ptr();
+
+        let mut grid = self.grid;
+        let (mut points_ptr, mut points_len) = (points.as_ptr(), len);
+        let mut buckets = self.buckets.as_mut_ptr();
+        let mut ranks = self.ranks.as_mut_ptr();
+        let mut counts = self.counts.as_mut_ptr();
+        let mut counts_len = self.counts.len();
+        let mut starts = self.starts.as_mut_ptr();
+        let mut starts_len = self.starts.len();
+        let mut buckets_len = len;
+        let mut ranks_len = len;
+        let mut indices = self.indices.as_mut_ptr();
+        let grid_size = blocks_for(len, BLOCK);
+        unsafe {
+            let params = params!(grid, points_ptr, points_len, buckets, ranks, counts);
+            stream.launch(&self.assign, grid_size, BLOCK, 0, &params)?;
+            let params = params!(counts, counts_len, starts);
+            stream.launch(&self.scan, 1, SCAN_BLOCK, 0, &params)?;
+            let params = params!(
+                buckets,
+                buckets_len,
+                ranks,
+                ranks_len,
+                starts,
+                starts_len,
+                indices
+            );
+            stream.launch(&self.scatter, grid_size, BLOCK, 0, &params)
+        }
+    }
+
+    /// The view of the grid for querying neighbors in kernels, see [`GridView::neighbors`].
+    pub fn view(&self) -> GridView {
+        unsafe {
+            GridView::from_raw_parts(
+                self.grid,
+                self.points,
+                self.indices.len(),
+                self.starts.as_ptr(),
+                self.indices.as_ptr(),
+            )
+        }
+    }
+}
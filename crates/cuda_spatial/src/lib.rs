@@ -0,0 +1,37 @@
+//! Neighbor search over point sets on the GPU, with uniform grids or spatial hashing.
+//!
+//! This is the usual acceleration structure of particle simulations (SPH, molecular dynamics,
+//! boids) and of photon mapping: [`GpuSpatialGrid`] sorts the points into the cells of a
+//! [`SpatialGrid`] on the GPU, and kernels find the points near a position with
+//! [`GridView::neighbors`](grid::GridView::neighbors). See the [`grid`] module for the details.
+//!
+//! Like `cuda_linalg`, this crate is used from both sides: the gpu crate depends on it for
+//! [`GridView`](grid::GridView) and to get the kernels building the grid into its PTX (with the
+//! default `kernels` feature), and the host crate to build the grid.
+//!
+//! ```ignore
+//! // host
+//! let module = Module::from_str(PTX)?;
+//! let mut grid = GpuSpatialGrid::new(&module, SpatialGrid::uniform([0.0; 3], radius, [64, 64, 64]))?;
+//! grid.build(&stream, &positions)?;
+//! unsafe {
+//!     launch!(density<<<blocks, 256, 0, stream>>>(grid.view(), radius, out.as_device_ptr()))?;
+//! }
+//! ```
+
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+pub mod grid;
+
+pub use grid::SpatialGrid;
+
+#[cfg(not(target_os = "cuda"))]
+mod host;
+
+#[cfg(not(target_os = "cuda"))]
+pub use host::*;
@@ -0,0 +1,90 @@
+//! Atomic operations on `u32`s in global memory, named like the methods of
+//! [`AtomicU32`](core::sync::atomic::AtomicU32), and a compare-exchange of `u64`s.
+//!
+//! They are relaxed, like `atomicAdd` and friends in CUDA C++: they only order accesses to the same
+//! address, use a [fence](crate::thread::device_fence) to order other accesses around them. For
+//! every function, `ptr` must be valid for reads and writes of a `u32` (or `u64`) in global memory.
+
+use crate::gpu_only;
+
+/// Atomically adds `value` to the `u32` at `ptr` and returns its previous value.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn fetch_add(ptr: *mut u32, value: u32) -> u32 {
+    let old;
+    asm!(
+        "atom.global.add.u32 {}, [{}], {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) value,
+    );
+    old
+}
+
+/// Atomically replaces the `u32` at `ptr` with `value` if `value` is smaller, and returns its previous
+/// value.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn fetch_min(ptr: *mut u32, value: u32) -> u32 {
+    let old;
+    asm!(
+        "atom.global.min.u32 {}, [{}], {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) value,
+    );
+    old
+}
+
+/// Atomically replaces the `u32` at `ptr` with `value` and returns its previous value.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn swap(ptr: *mut u32, value: u32) -> u32 {
+    let old;
+    asm!(
+        "atom.global.exch.b32 {}, [{}], {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) value,
+    );
+    old
+}
+
+/// Atomically replaces the `u32` at `ptr` with `new` if it is `current`. Returns the previous value,
+/// in `Ok` if it was replaced and in `Err` otherwise.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn compare_exchange(ptr: *mut u32, current: u32, new: u32) -> Result<u32, u32> {
+    let old;
+    asm!(
+        "atom.global.cas.b32 {}, [{}], {}, {};",
+        out(reg32) old,
+        in(reg64) ptr,
+        in(reg32) current,
+        in(reg32) new,
+    );
+    if old == current {
+        Ok(old)
+    } else {
+        Err(old)
+    }
+}
+
+/// Atomically replaces the `u64` at `ptr` with `new` if it is `current`, like [`compare_exchange`].
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn compare_exchange_u64(ptr: *mut u64, current: u64, new: u64) -> Result<u64, u64> {
+    let old;
+    asm!(
+        "atom.global.cas.b64 {}, [{}], {}, {};",
+        out(reg64) old,
+        in(reg64) ptr,
+        in(reg64) current,
+        in(reg64) new,
+    );
+    if old == current {
+        Ok(old)
+    } else {
+        Err(old)
+    }
+}
@@ -0,0 +1,173 @@
+//! Thread block clusters, groups of thread blocks which are co-scheduled on the same GPU processing
+//! cluster. Requires compute capability 9.0 (Hopper) or higher.
+//!
+//! The blocks of a cluster can synchronize with each other with [`sync`], and can read and write
+//! each other's shared memory (distributed shared memory) through pointers obtained with
+//! [`map_shared_rank`].
+//!
+//! The cluster size of a kernel is either declared at compile time with
+//! `#[kernel(cluster_dim = (x, y, z))]`, in which case the kernel can be launched normally, or
+//! given at launch time with cust's `Stream::launch_cluster`. Kernels which are not launched with
+//! clusters behave as if every block was its own cluster of one block.
+//!
+//! ```ignore
+//! use cuda_std::*;
+//!
+//! #[kernel(cluster_dim = (2, 1, 1))]
+//! pub unsafe fn swap_halves(out: *mut u32) {
+//!     let shared = shared_array![u32; 256];
+//!     shared.add(thread::thread_idx_x() as usize).write(thread::index_1d());
+//!     // make sure the other block of the cluster wrote its shared memory before reading it.
+//!     cluster::sync();
+//!
+//!     let other = cluster::map_shared_rank(shared, cluster::block_rank() ^ 1);
+//!     let value = *other.add(thread::thread_idx_x() as usize);
+//!     *out.add(thread::index_1d() as usize) = value;
+//!     // the other block must not exit while its shared memory is still being read.
+//!     cluster::sync();
+//! }
+//! ```
+
+use crate::gpu_only;
+use vek::Vec3;
+
+macro_rules! special_register {
+    ($($(#[$attr:meta])* $name:ident => $reg:literal),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name() -> Vec3<u32> {
+                let (x, y, z): (u32, u32, u32);
+                unsafe {
+                    asm!(
+                        concat!("mov.u32 {}, ", $reg, ".x;"),
+                        out(reg32) x,
+                    );
+                    asm!(
+                        concat!("mov.u32 {}, ", $reg, ".y;"),
+                        out(reg32) y,
+                    );
+                    asm!(
+                        concat!("mov.u32 {}, ", $reg, ".z;"),
+                        out(reg32) z,
+                    );
+                }
+                Vec3::new(x, y, z)
+            }
+        )*
+    };
+}
+
+special_register! {
+    /// The index of this thread's cluster within the grid.
+    cluster_idx => "%clusterid",
+    /// The dimensions of the grid, in clusters.
+    grid_dim => "%nclusterid",
+    /// The index of this thread's block within its cluster.
+    block_idx => "%cluster_ctaid",
+    /// The dimensions of this thread's cluster, in blocks.
+    dim => "%cluster_nctaid",
+}
+
+/// The linear index of this thread's block within its cluster, ranging from `0` to
+/// [`num_blocks`]` - 1`. This is the rank used by [`map_shared_rank`].
+#[gpu_only]
+#[inline(always)]
+pub fn block_rank() -> u32 {
+    let out;
+    unsafe {
+        asm!("mov.u32 {}, %cluster_ctarank;", out(reg32) out);
+    }
+    out
+}
+
+/// The number of blocks in this thread's cluster.
+#[gpu_only]
+#[inline(always)]
+pub fn num_blocks() -> u32 {
+    let out;
+    unsafe {
+        asm!("mov.u32 {}, %cluster_nctarank;", out(reg32) out);
+    }
+    out
+}
+
+/// Whether the kernel was launched with clusters, either at launch time or by declaring a cluster
+/// size at compile time.
+#[gpu_only]
+#[inline(always)]
+pub fn is_explicit() -> bool {
+    let out: u32;
+    unsafe {
+        asm!(
+            "{{",
+            ".reg .pred p;",
+            "mov.pred p, %is_explicit_cluster;",
+            "selp.u32 {}, 1, 0, p;",
+            "}}",
+            out(reg32) out,
+        );
+    }
+    out != 0
+}
+
+/// Synchronizes every thread of every block in this cluster, all shared and global memory writes
+/// made by them before this call are visible to all of them after it.
+///
+/// # Safety
+///
+/// Every thread of the cluster must call this function, the same as
+/// [`sync_threads`](crate::thread::sync_threads).
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn sync() {
+    arrive();
+    wait();
+}
+
+/// Arrives at the cluster barrier without waiting for the other threads, splitting [`sync`] in
+/// two so that independent work can be done between [`arrive`] and [`wait`]. Memory writes made
+/// before this call are visible to the other threads of the cluster after their [`wait`].
+///
+/// # Safety
+///
+/// Every thread of the cluster must call [`arrive`] then [`wait`], in that order.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn arrive() {
+    asm!("barrier.cluster.arrive;");
+}
+
+/// Waits until every thread of the cluster has called [`arrive`].
+///
+/// # Safety
+///
+/// The calling thread must have called [`arrive`] before.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn wait() {
+    asm!("barrier.cluster.wait;");
+}
+
+/// Maps a pointer to shared memory of this thread's block to the same location in the shared
+/// memory of the block with the given [`block_rank`] in this cluster. The returned pointer can be
+/// read from and written to like any other pointer.
+///
+/// # Safety
+///
+/// `ptr` must point to shared memory and `rank` must be less than [`num_blocks`]. The target
+/// block must not have exited while the returned pointer is used, which is usually ensured by a
+/// [`sync`] at the end of the kernel.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn map_shared_rank<T>(ptr: *mut T, rank: u32) -> *mut T {
+    let out: *mut T;
+    asm!(
+        "mapa.u64 {}, {}, {};",
+        out(reg64) out,
+        in(reg64) ptr,
+        in(reg32) rank,
+    );
+    out
+}
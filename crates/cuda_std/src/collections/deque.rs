@@ -0,0 +1,289 @@
+//! A work-stealing deque after [Dynamic Circular Work-Stealing Deque](https://dl.acm.org/doi/10.1145/1073970.1073974)
+//! by Chase and Lev, with the fences of [Correct and Efficient Work-Stealing for Weak Memory
+//! Models](https://dl.acm.org/doi/10.1145/2442516.2442524) by Lê et al.
+
+use crate::thread;
+use core::ptr::{addr_of_mut, read_volatile, write_volatile};
+
+/// The indices of a [`WorkDeque`], which live in global memory next to its items. Zeroed indices
+/// are an empty deque.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct DequeIndices {
+    /// The index of the oldest item, which is stolen next.
+    pub top: i64,
+    /// The index after the newest item, which is popped next.
+    pub bottom: i64,
+}
+
+#[cfg(all(not(target_os = "cuda"), feature = "cust"))]
+unsafe impl cust::memory::DeviceCopy for DequeIndices {}
+
+/// The outcome of [`WorkDeque::steal`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Steal<T> {
+    /// The deque was empty.
+    Empty,
+    /// An item was stolen.
+    Success(T),
+    /// Another thread took the item first, the deque may still have items.
+    Retry,
+}
+
+impl<T> Steal<T> {
+    /// The stolen item, if any.
+    pub fn success(self) -> Option<T> {
+        match self {
+            Steal::Success(item) => Some(item),
+            _ => None,
+        }
+    }
+}
+
+/// A work-stealing deque with a fixed capacity in global memory, which one thread (the owner) uses
+/// as a stack while any other thread can steal its oldest items, without locks.
+///
+/// Persistent kernels (launched with as many blocks as the GPU runs at once, which loop until the
+/// work is done) usually give every block a deque, which is used by the first thread of the block.
+/// New work, such as the children of a BVH node or the neighbours of a graph vertex, is pushed to
+/// the own deque and popped in depth-first order, which keeps the memory of the block small and
+/// its caches warm. Blocks whose deque ran empty steal from the deques of other blocks, taking the
+/// oldest items, which are usually the largest pieces of work:
+///
+/// ```no_run
+/// use cuda_std::collections::{Steal, WorkDeque};
+/// use cuda_std::{kernel, thread};
+///
+/// // `pending` starts as the number of items pushed before the launch.
+/// #[kernel]
+/// pub unsafe fn traverse(deques: &[WorkDeque<u32>], pending: *mut u32) {
+///     // only the first thread of every block takes part, the others would get work from it
+///     // through shared memory.
+///     if thread::thread_idx_x() != 0 {
+///         return;
+///     }
+///     let block = thread::block_idx_x() as usize;
+///     let own = &deques[block];
+///     let mut victim = block;
+///     while core::ptr::read_volatile(pending) != 0 {
+///         let node = match own.pop() {
+///             Some(node) => node,
+///             None => {
+///                 victim = (victim + 1) % deques.len();
+///                 match deques[victim].steal() {
+///                     Steal::Success(node) => node,
+///                     _ => continue,
+///                 }
+///             }
+///         };
+///         // process `node`, push its children with `own.push` and add them to `pending`, then
+///         // subtract one for `node` with an atomic.
+///     }
+/// }
+/// ```
+///
+/// Kernels take the deque as a parameter (or in a slice of deques) created with
+/// [`from_raw_parts`](Self::from_raw_parts) from two device allocations, the items and zeroed
+/// [`DequeIndices`]. Items can be pushed before the launch by writing them to the start of the
+/// items and setting `bottom` to their number.
+///
+/// # Memory ordering
+///
+/// Items are written and read with volatile accesses and ordered with device-wide fences, so a
+/// stolen item is always completely written, and every item is taken by exactly one thread. The
+/// deque does not order any other memory accesses: data an item refers to must be made visible
+/// with a [`device_fence`](crate::thread::device_fence) before pushing it.
+///
+/// As with [`Once`](crate::sync::Once), thieves never wait for the owner, but a kernel which waits
+/// for work to appear in a deque is only guaranteed to make progress if the owner of that deque
+/// is running, which is why the blocks of a persistent kernel must all be resident.
+#[derive(Debug)]
+#[repr(C)]
+pub struct WorkDeque<T> {
+    items: *mut T,
+    mask: u64,
+    indices: *mut DequeIndices,
+}
+
+impl<T> Clone for WorkDeque<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for WorkDeque<T> {}
+
+#[cfg(all(not(target_os = "cuda"), feature = "cust"))]
+unsafe impl<T: cust::memory::DeviceCopy> cust::memory::DeviceCopy for WorkDeque<T> {}
+
+unsafe impl<T: Send> Send for WorkDeque<T> {}
+unsafe impl<T: Send> Sync for WorkDeque<T> {}
+
+impl<T: Copy> WorkDeque<T> {
+    /// A deque which keeps up to `capacity` items at `items`, with its indices at `indices`.
+    ///
+    /// # Safety
+    ///
+    /// - `capacity` must be a power of two and `items` must be valid for reads and writes of
+    ///   `capacity` items.
+    /// - `indices` must be valid for reads and writes and aligned, and be zeroed or the indices of
+    ///   a deque with the same capacity and items.
+    /// - Both must stay valid while the deque is used, and only be accessed through deques.
+    pub unsafe fn from_raw_parts(
+        items: *mut T,
+        capacity: usize,
+        indices: *mut DequeIndices,
+    ) -> Self {
+        debug_assert!(capacity.is_power_of_two());
+        Self {
+            items,
+            mask: capacity as u64 - 1,
+            indices,
+        }
+    }
+
+    /// The number of items the deque can hold.
+    pub fn capacity(&self) -> usize {
+        (self.mask + 1) as usize
+    }
+
+    /// The number of items in the deque. This is only a snapshot if other threads use the deque.
+    pub fn len(&self) -> usize {
+        let top = load(self.top());
+        let bottom = load(self.bottom());
+        (bottom - top).max(0) as usize
+    }
+
+    /// Whether the deque is empty. This is only a snapshot if other threads use the deque.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn top(&self) -> *mut i64 {
+        unsafe { addr_of_mut!((*self.indices).top) }
+    }
+
+    fn bottom(&self) -> *mut i64 {
+        unsafe { addr_of_mut!((*self.indices).bottom) }
+    }
+
+    fn slot(&self, index: i64) -> *mut T {
+        unsafe { self.items.add((index as u64 & self.mask) as usize) }
+    }
+
+    /// Pushes `item` as the newest item, returning it back if the deque is full.
+    ///
+    /// # Safety
+    ///
+    /// Only the owner of the deque may call this, no other thread may call [`push`](Self::push) or
+    /// [`pop`](Self::pop) at the same time.
+    pub unsafe fn push(&self, item: T) -> Result<(), T> {
+        let (top_ptr, bottom_ptr) = (self.top(), self.bottom());
+        let bottom = load(bottom_ptr);
+        // an outdated top is smaller, so the deque only looks fuller than it is.
+        let top = load(top_ptr);
+        if bottom - top > self.mask as i64 {
+            return Err(item);
+        }
+        write_volatile(self.slot(bottom), item);
+        // the item must be visible before thieves see the new bottom.
+        thread::device_fence();
+        store(bottom_ptr, bottom + 1);
+        Ok(())
+    }
+
+    /// Pops the newest item, `None` if the deque is empty.
+    ///
+    /// # Safety
+    ///
+    /// Only the owner of the deque may call this, no other thread may call [`push`](Self::push) or
+    /// [`pop`](Self::pop) at the same time.
+    pub unsafe fn pop(&self) -> Option<T> {
+        let (top_ptr, bottom_ptr) = (self.top(), self.bottom());
+        let bottom = load(bottom_ptr) - 1;
+        store(bottom_ptr, bottom);
+        // thieves must see the smaller bottom before the owner reads top, otherwise both could
+        // take the last item.
+        thread::device_fence();
+        let top = load(top_ptr);
+        if top > bottom {
+            store(bottom_ptr, bottom + 1);
+            return None;
+        }
+        let item = read_volatile(self.slot(bottom));
+        if top == bottom {
+            // the last item, which a thief may be stealing at the same time.
+            let won = compare_exchange(top_ptr, top, top + 1);
+            store(bottom_ptr, bottom + 1);
+            return if won { Some(item) } else { None };
+        }
+        Some(item)
+    }
+
+    /// Steals the oldest item. Any thread may call this at any time, including the owner.
+    ///
+    /// [`Steal::Retry`] means another thread took the item which was about to be stolen, stealing
+    /// again may succeed, but trying another deque first spreads the thieves out.
+    pub fn steal(&self) -> Steal<T> {
+        let (top_ptr, bottom_ptr) = (self.top(), self.bottom());
+        let top = load(top_ptr);
+        // the bottom read must not be older than the top read, see `pop`.
+        thread::device_fence();
+        let bottom = load(bottom_ptr);
+        if top >= bottom {
+            return Steal::Empty;
+        }
+        // the slot may be overwritten by a push once top moved on, but then the swap fails and
+        // the item is discarded.
+        let item = unsafe { read_volatile(self.slot(top)) };
+        if compare_exchange(top_ptr, top, top + 1) {
+            Steal::Success(item)
+        } else {
+            Steal::Retry
+        }
+    }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn load(ptr: *mut i64) -> i64 {
+    unsafe { ptr.read_volatile() }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn store(ptr: *mut i64, value: i64) {
+    unsafe { ptr.write_volatile(value) }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn compare_exchange(ptr: *mut i64, current: i64, new: i64) -> bool {
+    unsafe {
+        crate::atomic::compare_exchange_u64(ptr as *mut u64, current as u64, new as u64).is_ok()
+    }
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn atomic<'a>(ptr: *mut i64) -> &'a core::sync::atomic::AtomicI64 {
+    // `AtomicI64` has the same layout as `i64`, and the indices are aligned to 8 bytes.
+    unsafe { &*(ptr as *const core::sync::atomic::AtomicI64) }
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn load(ptr: *mut i64) -> i64 {
+    atomic(ptr).load(core::sync::atomic::Ordering::SeqCst)
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn store(ptr: *mut i64, value: i64) {
+    atomic(ptr).store(value, core::sync::atomic::Ordering::SeqCst)
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn compare_exchange(ptr: *mut i64, current: i64, new: i64) -> bool {
+    use core::sync::atomic::Ordering;
+    atomic(ptr)
+        .compare_exchange(current, new, Ordering::SeqCst, Ordering::SeqCst)
+        .is_ok()
+}
@@ -0,0 +1,8 @@
+//! Data structures in global memory which many threads, blocks or kernels use at once.
+//!
+//! - [`WorkDeque`] is a work-stealing deque, which balances irregular work between the blocks of a
+//!   persistent kernel.
+
+mod deque;
+
+pub use deque::*;
@@ -0,0 +1,457 @@
+//! Cooperative reductions and scans over the threads of a warp or a thread block.
+//!
+//! These are building blocks (in the style of CUB) for writing larger parallel algorithms,
+//! warp-level operations are implemented using warp shuffles, block-level operations combine
+//! the results of every warp using a small amount of shared memory provided by the caller.
+//!
+//! Every operation takes an `op` which must be associative, the order in which elements are combined
+//! is unspecified. The operation does not need to be commutative.
+//!
+//! # Determinism
+//!
+//! For operations which are not exactly associative, such as float addition, the result depends on
+//! the order in which elements are combined. Every operation in this module combines elements in
+//! an order which only depends on the launch configuration, so running the same kernel with the
+//! same block and grid size on the same input gives bitwise identical results, with one exception:
+//! [`warp_reduce`] uses a butterfly pattern in which every lane combines the elements in a different
+//! order, so lanes may get results which differ in the last bits. [`warp_reduce_ordered`] gives
+//! every lane the same result, and the `deterministic` feature makes [`warp_reduce`] use it.
+//!
+//! Accumulating the results of blocks with float atomics (`atomicAdd` in CUDA C++) is not
+//! deterministic since the order in which blocks finish changes from run to run, [`grid_reduce`]
+//! and [`grid_scan`] combine the results of blocks in the order of their indices instead.
+//! [`grid_map_reduce`] builds on [`grid_reduce`] to reduce a whole range of indices with any grid size.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! #[kernel]
+//! pub unsafe fn sum(input: &[f32], out: *mut f32) {
+//!     let scratch = shared_array![f32; 32];
+//!     let idx = thread::index_1d() as usize;
+//!     let val = input.get(idx).copied().unwrap_or(0.0);
+//!     let total = collective::block_reduce(val, scratch, |a, b| a + b);
+//!     if thread::thread_idx_x() == 0 {
+//!         *out.add(thread::block_idx_x() as usize) = total;
+//!     }
+//! }
+//! ```
+
+use crate::gpu_only;
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::{
+    atomic, thread,
+    warp::{self, FULL_MASK, WARP_SIZE},
+};
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use core::ptr::{read_volatile, write_volatile};
+
+/// Reduces `value` across every thread of the warp using `op`. Every thread in the warp gets the result.
+///
+/// Every lane combines the elements in a different order, see [the module docs](self#determinism).
+/// With the `deterministic` feature, this is [`warp_reduce_ordered`].
+///
+/// # Safety
+///
+/// Every thread of the warp must call this function, if any thread in the warp has exited or does not
+/// reach this call, the behavior is undefined.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn warp_reduce<T: Copy>(value: T, op: impl Fn(T, T) -> T) -> T {
+    if cfg!(feature = "deterministic") {
+        return warp_reduce_ordered(value, op);
+    }
+    let mut value = value;
+    let mut offset = WARP_SIZE / 2;
+    while offset > 0 {
+        value = op(value, warp::shuffle_xor(FULL_MASK, value, offset));
+        offset /= 2;
+    }
+    value
+}
+
+/// Reduces `value` across every thread of the warp using `op` with a fixed tree: lane `i` is combined with
+/// lane `i + 16`, then with lane `i + 8`, and so on. Every thread in the warp gets the bitwise identical
+/// result of lane 0.
+///
+/// This is slightly slower than [`warp_reduce`] since it needs an extra shuffle to broadcast the result.
+///
+/// # Safety
+///
+/// Every thread of the warp must call this function, if any thread in the warp has exited or does not
+/// reach this call, the behavior is undefined.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn warp_reduce_ordered<T: Copy>(value: T, op: impl Fn(T, T) -> T) -> T {
+    let lane = warp::lane_id();
+    let mut value = value;
+    let mut offset = WARP_SIZE / 2;
+    while offset > 0 {
+        let other = warp::shuffle_down(FULL_MASK, value, offset);
+        if lane < offset {
+            value = op(value, other);
+        }
+        offset /= 2;
+    }
+    warp::shuffle(FULL_MASK, value, 0)
+}
+
+/// Computes an inclusive prefix scan of `value` across the threads of the warp using `op`. That is,
+/// lane `i` gets `value[0] op value[1] op ... op value[i]`.
+///
+/// # Safety
+///
+/// Every thread of the warp must call this function, if any thread in the warp has exited or does not
+/// reach this call, the behavior is undefined.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn warp_scan<T: Copy>(value: T, op: impl Fn(T, T) -> T) -> T {
+    let lane = warp::lane_id();
+    let mut value = value;
+    let mut offset = 1;
+    while offset < WARP_SIZE {
+        let other = warp::shuffle_up(FULL_MASK, value, offset);
+        if lane >= offset {
+            value = op(other, value);
+        }
+        offset *= 2;
+    }
+    value
+}
+
+/// The index of this thread inside of its block, flattened across every dimension.
+#[gpu_only]
+#[inline(always)]
+pub(crate) fn linear_thread_idx() -> u32 {
+    let idx = thread::thread_idx();
+    let dim = thread::block_dim();
+    idx.x + idx.y * dim.x + idx.z * dim.x * dim.y
+}
+
+#[gpu_only]
+#[inline(always)]
+pub(crate) fn block_threads() -> u32 {
+    let dim = thread::block_dim();
+    dim.x * dim.y * dim.z
+}
+
+/// The index of this block inside of the grid, flattened across every dimension.
+#[gpu_only]
+#[inline(always)]
+fn linear_block_idx() -> u32 {
+    let idx = thread::block_idx();
+    let dim = thread::grid_dim();
+    idx.x + idx.y * dim.x + idx.z * dim.x * dim.y
+}
+
+#[gpu_only]
+#[inline(always)]
+fn grid_blocks() -> u32 {
+    let dim = thread::grid_dim();
+    dim.x * dim.y * dim.z
+}
+
+/// Reduces `value` across every thread of the block using `op`. Every thread in the block gets the result.
+///
+/// `scratch` must point to shared memory with room for at least 32 elements of `T`, usually
+/// obtained through [`shared_array!`](crate::shared_array). It may be reused as soon as this function returns.
+///
+/// # Safety
+///
+/// - Every thread of the block must call this function with the same `scratch`, it contains
+/// [`sync_threads`](crate::thread::sync_threads) calls.
+/// - The number of threads in the block must be a multiple of the warp size (32).
+/// - `scratch` must be valid for reads and writes of 32 elements.
+#[gpu_only]
+pub unsafe fn block_reduce<T: Copy>(value: T, scratch: *mut T, op: impl Fn(T, T) -> T) -> T {
+    let tid = linear_thread_idx();
+    let warp_result = warp_reduce(value, &op);
+    if tid % WARP_SIZE == 0 {
+        *scratch.add((tid / WARP_SIZE) as usize) = warp_result;
+    }
+    thread::sync_threads();
+
+    if tid == 0 {
+        let warps = block_threads() / WARP_SIZE;
+        let mut total = *scratch;
+        for i in 1..warps {
+            total = op(total, *scratch.add(i as usize));
+        }
+        *scratch = total;
+    }
+    thread::sync_threads();
+    let total = *scratch;
+    thread::sync_threads();
+    total
+}
+
+/// Computes an inclusive prefix scan of `value` across the threads of the block using `op`, threads are
+/// ordered by their flattened index inside of the block (`x` first, then `y`, then `z`).
+///
+/// `scratch` must point to shared memory with room for at least 32 elements of `T`, usually
+/// obtained through [`shared_array!`](crate::shared_array). It may be reused as soon as this function returns.
+///
+/// # Safety
+///
+/// - Every thread of the block must call this function with the same `scratch`, it contains
+/// [`sync_threads`](crate::thread::sync_threads) calls.
+/// - The number of threads in the block must be a multiple of the warp size (32).
+/// - `scratch` must be valid for reads and writes of 32 elements.
+#[gpu_only]
+pub unsafe fn block_scan<T: Copy>(value: T, scratch: *mut T, op: impl Fn(T, T) -> T) -> T {
+    let tid = linear_thread_idx();
+    let warp_idx = tid / WARP_SIZE;
+    let scanned = warp_scan(value, &op);
+    if tid % WARP_SIZE == WARP_SIZE - 1 {
+        *scratch.add(warp_idx as usize) = scanned;
+    }
+    thread::sync_threads();
+
+    if tid == 0 {
+        let warps = block_threads() / WARP_SIZE;
+        let mut acc = *scratch;
+        for i in 1..warps {
+            acc = op(acc, *scratch.add(i as usize));
+            *scratch.add(i as usize) = acc;
+        }
+    }
+    thread::sync_threads();
+    let result = if warp_idx > 0 {
+        op(*scratch.add(warp_idx as usize - 1), scanned)
+    } else {
+        scanned
+    };
+    thread::sync_threads();
+    result
+}
+
+/// Reduces `value` across every thread of the grid using `op`. Every thread of the last block to finish
+/// gets `Some` with the result, every other thread gets `None`.
+///
+/// Every block is first reduced with [`block_reduce`], then the last block to finish combines the results
+/// of every block in the order of their (flattened) indices. The result is therefore the same on every run
+/// for the same grid and block size, unlike accumulating the results of blocks with atomics.
+///
+/// `partials` must point to global memory with room for one `T` per block, and `ticket` to a `u32` in global
+/// memory which is `0` when the kernel is launched. `ticket` is reset to `0` before this function returns
+/// in the last block, so the same `partials` and `ticket` can be used for every launch on a stream.
+///
+/// # Safety
+///
+/// - Every requirement of [`block_reduce`].
+/// - Every thread of the grid must call this function with the same `partials` and `ticket`, and no other
+/// kernel may use them at the same time.
+/// - `partials` must be valid for reads and writes of one element per block and `ticket` must be valid
+/// for reads and writes.
+#[gpu_only]
+pub unsafe fn grid_reduce<T: Copy>(
+    value: T,
+    partials: *mut T,
+    ticket: *mut u32,
+    scratch: *mut T,
+    op: impl Fn(T, T) -> T,
+) -> Option<T> {
+    let block_total = block_reduce(value, scratch, &op);
+    let tid = linear_thread_idx();
+    let blocks = grid_blocks();
+
+    let mut is_last = 0;
+    if tid == 0 {
+        write_volatile(partials.add(linear_block_idx() as usize), block_total);
+        // make the partial result visible to the last block before taking a ticket.
+        thread::grid_fence();
+        is_last = (atomic::fetch_add(ticket, 1) == blocks - 1) as u32;
+    }
+    if thread::sync_threads_or(is_last) == 0 {
+        return None;
+    }
+
+    if tid == 0 {
+        thread::grid_fence();
+        let mut total = read_volatile(partials);
+        for i in 1..blocks {
+            total = op(total, read_volatile(partials.add(i as usize)));
+        }
+        *scratch = total;
+        write_volatile(ticket, 0);
+    }
+    thread::sync_threads();
+    let total = *scratch;
+    thread::sync_threads();
+    Some(total)
+}
+
+/// Reduces `map(i)` for every `i` in `0..len` across the grid using `op`, starting from `identity`. Every thread
+/// of the last block to finish gets `Some` with the result, every other thread gets `None`.
+///
+/// Every thread first folds the indices `i`, `i + stride`, `i + 2 * stride` and so on in order, where `i` is the
+/// global index of the thread in a one-dimensional grid and `stride` the number of threads of the grid, then
+/// the threads are combined with [`grid_reduce`]. Like [`grid_reduce`], the result is the same on every run for
+/// the same grid and block size, and any grid size covers the whole range.
+///
+/// ```no_run
+/// #[kernel]
+/// pub unsafe fn dot(x: &[f32], y: &[f32], partials: *mut f32, ticket: *mut u32, out: *mut f32) {
+///     let scratch = shared_array![f32; 32];
+///     let map = |i: usize| x[i] * y[i];
+///     let total = collective::grid_map_reduce(x.len(), 0.0, map, partials, ticket, scratch, |a, b| a + b);
+///     if let Some(total) = total {
+///         if thread::thread_idx_x() == 0 {
+///             *out = total;
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Safety
+///
+/// - Every requirement of [`grid_reduce`].
+/// - The grid and its blocks must be one-dimensional.
+#[gpu_only]
+pub unsafe fn grid_map_reduce<T: Copy>(
+    len: usize,
+    identity: T,
+    map: impl Fn(usize) -> T,
+    partials: *mut T,
+    ticket: *mut u32,
+    scratch: *mut T,
+    op: impl Fn(T, T) -> T,
+) -> Option<T> {
+    let stride = (thread::grid_dim_x() * thread::block_dim_x()) as usize;
+    let mut i = thread::index_1d() as usize;
+    let mut acc = identity;
+    while i < len {
+        acc = op(acc, map(i));
+        i += stride;
+    }
+    grid_reduce(acc, partials, ticket, scratch, op)
+}
+
+/// Computes an inclusive prefix scan of `value` across the threads of the grid using `op`, threads are
+/// ordered by the flattened index of their block, then by their flattened index inside of the block.
+///
+/// Every block is first scanned with [`block_scan`], then each block waits for the prefix of the block before
+/// it and combines it with its own total (a chained scan). Elements are always combined in the same order,
+/// so the result is the same on every run for the same grid and block size.
+///
+/// `prefixes` must point to global memory with room for one `T` per block, and `flags` to global memory
+/// with room for one `u32` per block, which are all `0` when the kernel is launched. `flags` are reset to
+/// `0` by the last block, so the same `prefixes` and `flags` can be used for every launch on a stream.
+///
+/// # Safety
+///
+/// - Every requirement of [`block_scan`].
+/// - Every thread of the grid must call this function with the same `prefixes` and `flags`, and no other
+/// kernel may use them at the same time.
+/// - `prefixes` and `flags` must be valid for reads and writes of one element per block.
+/// - Every block of the grid must be resident on the GPU at the same time (as required by cooperative
+/// launches), since blocks wait on the blocks before them. Otherwise this may never return.
+#[gpu_only]
+pub unsafe fn grid_scan<T: Copy>(
+    value: T,
+    prefixes: *mut T,
+    flags: *mut u32,
+    scratch: *mut T,
+    op: impl Fn(T, T) -> T,
+) -> T {
+    let scanned = block_scan(value, scratch, &op);
+    let tid = linear_thread_idx();
+    let block = linear_block_idx() as usize;
+    let blocks = grid_blocks() as usize;
+
+    // the last thread of the block holds the total of the block.
+    if tid == block_threads() - 1 {
+        let prefix = if block == 0 {
+            scanned
+        } else {
+            while read_volatile(flags.add(block - 1)) == 0 {}
+            thread::grid_fence();
+            let previous = read_volatile(prefixes.add(block - 1));
+            *scratch = previous;
+            op(previous, scanned)
+        };
+        write_volatile(prefixes.add(block), prefix);
+        thread::grid_fence();
+        write_volatile(flags.add(block), 1);
+
+        // every other block already read the flag it waited on, since the last block waited on all of them.
+        if block == blocks - 1 {
+            for i in 0..blocks {
+                write_volatile(flags.add(i), 0);
+            }
+        }
+    }
+    thread::sync_threads();
+    let result = if block > 0 {
+        op(*scratch, scanned)
+    } else {
+        scanned
+    };
+    thread::sync_threads();
+    result
+}
+
+/// Copies the elements of `input` for which `predicate` returns `true` to the start of `output`, keeping
+/// their order (stream compaction), and writes how many there are to `count`.
+///
+/// `predicate` is usually a closure, which is compiled into the kernel calling this function, so a
+/// kernel is generated for every predicate without the cost of an indirect call:
+///
+/// ```no_run
+/// #[kernel]
+/// pub unsafe fn keep_above(
+///     input: &[f32],
+///     threshold: f32,
+///     output: *mut f32,
+///     count: *mut u32,
+///     prefixes: *mut u32,
+///     flags: *mut u32,
+/// ) {
+///     let scratch = shared_array![u32; 32];
+///     collective::compact(input, output, count, prefixes, flags, scratch, |x| *x > threshold);
+/// }
+/// ```
+///
+/// Every thread of the grid takes a contiguous chunk of `input`, counts the selected elements of it, and
+/// the counts are scanned with [`grid_scan`] to find where every thread writes its elements. `predicate`
+/// is called twice for every element, so it should be cheap and must always return the same result.
+///
+/// `prefixes`, `flags` and `scratch` are the buffers of [`grid_scan`].
+///
+/// # Safety
+///
+/// - Every requirement of [`grid_scan`], in particular every block must be resident at the same time,
+/// so the grid should have at most as many blocks as the GPU runs at once (the minimum grid size of
+/// the occupancy API). Larger inputs are fine, threads then take more elements.
+/// - `output` must be valid for writes of as many elements as are selected, at most `input.len()`,
+/// and `count` for a write of one `u32`.
+#[gpu_only]
+pub unsafe fn compact<T: Copy>(
+    input: &[T],
+    output: *mut T,
+    count: *mut u32,
+    prefixes: *mut u32,
+    flags: *mut u32,
+    scratch: *mut u32,
+    predicate: impl Fn(&T) -> bool,
+) {
+    let threads = (grid_blocks() * block_threads()) as usize;
+    let idx = linear_block_idx() as usize * block_threads() as usize + linear_thread_idx() as usize;
+    let per_thread = (input.len() + threads - 1) / threads;
+    let start = (idx * per_thread).min(input.len());
+    let end = (start + per_thread).min(input.len());
+    let chunk = &input[start..end];
+
+    let selected = chunk.iter().filter(|x| predicate(x)).count() as u32;
+    let inclusive = grid_scan(selected, prefixes, flags, scratch, |a, b| a + b);
+    let mut out = output.add((inclusive - selected) as usize);
+    for x in chunk {
+        if predicate(x) {
+            *out = *x;
+            out = out.add(1);
+        }
+    }
+    if idx == threads - 1 {
+        *count = inclusive;
+    }
+}
@@ -0,0 +1,364 @@
+//! Compensated summation and extended precision arithmetic, for computations where the rounding
+//! error of plain `f32` or `f64` operations adds up to too much.
+//!
+//! Long reductions are the usual culprit: adding a million `f32`s one at a time can lose most of the
+//! digits of the result, because every addition rounds and the sum grows much larger than the
+//! elements. [`KahanSum`] keeps track of the rounding error of every addition and adds it back,
+//! which makes the error of the sum independent of the number of elements:
+//!
+//! ```ignore
+//! use cuda_std::compensated::KahanSum;
+//!
+//! let mut sum = KahanSum::new();
+//! for x in values {
+//!     sum += *x;
+//! }
+//! let total: f32 = sum.value();
+//! ```
+//!
+//! `KahanSum`s are `Copy` and can be combined with [`KahanSum::merge`], so they can be reduced across
+//! threads with [`collective`](crate::collective) to keep the extra precision in the final reduction.
+//!
+//! The building blocks are the error-free transformations [`two_sum`] and [`two_product`], which
+//! return the rounded result of an operation together with its exact rounding error. [`DoubleFloat`]
+//! uses them to represent a number as the unevaluated sum of two floats, which has roughly twice the
+//! precision: [`FloatFloat`] has about 48 bits of mantissa for GPUs with slow `f64` support, and
+//! [`DoubleDouble`] has about 106 bits.
+//!
+//! These only work if float operations are not reassociated, which rustc and NVVM never do. Fused
+//! multiply-adds (which NVVM generates by default) are fine.
+
+use crate::float::GpuFloat;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// The float types the functions of this module are implemented for, [`f32`] and [`f64`].
+pub trait Float:
+    GpuFloat
+    + Default
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + private::Sealed
+{
+}
+
+impl Float for f32 {}
+impl Float for f64 {}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Returns `(s, e)` where `s` is `a + b` rounded to the nearest float and `e` is its rounding error,
+/// such that `s + e == a + b` exactly (Knuth's TwoSum).
+#[inline]
+pub fn two_sum<T: Float>(a: T, b: T) -> (T, T) {
+    let s = a + b;
+    let b_virtual = s - a;
+    let a_virtual = s - b_virtual;
+    (s, (a - a_virtual) + (b - b_virtual))
+}
+
+/// The same as [`two_sum`], but only correct if `|a| >= |b|` (Dekker's FastTwoSum). This takes three
+/// operations instead of six.
+#[inline]
+pub fn fast_two_sum<T: Float>(a: T, b: T) -> (T, T) {
+    let s = a + b;
+    (s, b - (s - a))
+}
+
+/// Returns `(p, e)` where `p` is `a * b` rounded to the nearest float and `e` is its rounding error,
+/// such that `p + e == a * b` exactly (TwoProduct), unless the product underflows.
+#[inline]
+pub fn two_product<T: Float>(a: T, b: T) -> (T, T) {
+    let p = a * b;
+    (p, a.mul_add(b, -p))
+}
+
+/// The dot product of `a` and `b` computed as if with twice the precision of `T`, then rounded
+/// (Ogita, Rump and Oishi's Dot2). Extra elements of the longer slice are ignored.
+pub fn dot<T: Float>(a: &[T], b: &[T]) -> T {
+    let mut sum = T::default();
+    let mut err = T::default();
+    for (&x, &y) in a.iter().zip(b) {
+        let (p, p_err) = two_product(x, y);
+        let (s, s_err) = two_sum(sum, p);
+        sum = s;
+        err = err + (p_err + s_err);
+    }
+    sum + err
+}
+
+/// A running sum which compensates for the rounding error of every addition, see the
+/// [module docs](self).
+///
+/// Unlike plain Kahan summation this also stays accurate when the added values are larger than the
+/// sum so far (like Neumaier's variant), and the compensation is folded back into the sum after
+/// every addition so that it never grows large enough to lose precision itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct KahanSum<T> {
+    sum: T,
+    compensation: T,
+}
+
+impl<T: Float> KahanSum<T> {
+    /// A sum of zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Combines two partial sums, for example the sums of two threads in a reduction.
+    #[inline]
+    pub fn merge(self, other: Self) -> Self {
+        let (sum, err) = two_sum(self.sum, other.sum);
+        let (sum, compensation) = fast_two_sum(sum, err + (self.compensation + other.compensation));
+        Self { sum, compensation }
+    }
+
+    /// The sum, with the accumulated rounding errors added back.
+    #[inline]
+    pub fn value(self) -> T {
+        self.sum + self.compensation
+    }
+}
+
+/// Adds `x` to the sum.
+impl<T: Float> AddAssign<T> for KahanSum<T> {
+    #[inline]
+    fn add_assign(&mut self, x: T) {
+        let (sum, err) = two_sum(self.sum, x);
+        let (sum, compensation) = fast_two_sum(sum, self.compensation + err);
+        self.sum = sum;
+        self.compensation = compensation;
+    }
+}
+
+impl<T: Float> Add for KahanSum<T> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, other: Self) -> Self {
+        self.merge(other)
+    }
+}
+
+impl<T: Float> core::iter::Sum<T> for KahanSum<T> {
+    fn sum<I: Iterator<Item = T>>(iter: I) -> Self {
+        let mut sum = Self::new();
+        for x in iter {
+            sum += x;
+        }
+        sum
+    }
+}
+
+/// A number stored as the unevaluated sum `hi + lo` of two floats with `|lo| <= ulp(hi) / 2`, which
+/// has roughly twice the precision of `T`, see the [module docs](self).
+///
+/// The arithmetic operators have a relative error of a few units in the last place of the combined
+/// mantissa. The exponent range is the same as the one of `T`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DoubleFloat<T> {
+    pub hi: T,
+    pub lo: T,
+}
+
+/// Two `f32`s, with about 48 bits of mantissa.
+pub type FloatFloat = DoubleFloat<f32>;
+/// Two `f64`s, with about 106 bits of mantissa.
+pub type DoubleDouble = DoubleFloat<f64>;
+
+impl<T: Float> DoubleFloat<T> {
+    /// Creates a number from a float, exactly.
+    #[inline]
+    pub fn new(x: T) -> Self {
+        Self {
+            hi: x,
+            lo: T::default(),
+        }
+    }
+
+    /// The exact sum of two floats.
+    #[inline]
+    pub fn from_sum(a: T, b: T) -> Self {
+        let (hi, lo) = two_sum(a, b);
+        Self { hi, lo }
+    }
+
+    /// The exact product of two floats.
+    #[inline]
+    pub fn from_product(a: T, b: T) -> Self {
+        let (hi, lo) = two_product(a, b);
+        Self { hi, lo }
+    }
+
+    /// The number rounded to a single float.
+    #[inline]
+    pub fn value(self) -> T {
+        self.hi + self.lo
+    }
+
+    #[inline]
+    fn renormalize(hi: T, lo: T) -> Self {
+        let (hi, lo) = fast_two_sum(hi, lo);
+        Self { hi, lo }
+    }
+
+    /// The square root, `NaN` for negative numbers.
+    #[inline]
+    pub fn sqrt(self) -> Self {
+        let zero = T::default();
+        if self.hi <= zero {
+            return Self::new(self.hi.sqrt());
+        }
+        // one newton step from the square root of `hi`.
+        let root = self.hi.sqrt();
+        let (sq, sq_err) = two_product(root, root);
+        let rest = ((self.hi - sq) - sq_err) + self.lo;
+        Self::renormalize(root, rest / (root + root))
+    }
+
+    #[inline]
+    pub fn abs(self) -> Self {
+        if self.hi < T::default() {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+impl<T: Float> From<T> for DoubleFloat<T> {
+    #[inline]
+    fn from(x: T) -> Self {
+        Self::new(x)
+    }
+}
+
+impl<T: Float> Neg for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn neg(self) -> Self {
+        Self {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl<T: Float> Add for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: Self) -> Self {
+        let (hi, hi_err) = two_sum(self.hi, rhs.hi);
+        let (lo, lo_err) = two_sum(self.lo, rhs.lo);
+        let r = Self::renormalize(hi, hi_err + lo);
+        Self::renormalize(r.hi, r.lo + lo_err)
+    }
+}
+
+impl<T: Float> Add<T> for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn add(self, rhs: T) -> Self {
+        let (hi, err) = two_sum(self.hi, rhs);
+        Self::renormalize(hi, err + self.lo)
+    }
+}
+
+impl<T: Float> Sub for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: Self) -> Self {
+        self + -rhs
+    }
+}
+
+impl<T: Float> Sub<T> for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn sub(self, rhs: T) -> Self {
+        self + -rhs
+    }
+}
+
+impl<T: Float> Mul for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: Self) -> Self {
+        let (hi, err) = two_product(self.hi, rhs.hi);
+        let err = err + (self.hi * rhs.lo + self.lo * rhs.hi);
+        Self::renormalize(hi, err)
+    }
+}
+
+impl<T: Float> Mul<T> for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn mul(self, rhs: T) -> Self {
+        let (hi, err) = two_product(self.hi, rhs);
+        Self::renormalize(hi, err + self.lo * rhs)
+    }
+}
+
+impl<T: Float> Div for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn div(self, rhs: Self) -> Self {
+        // the quotient of the high parts, corrected by the remainder of the whole division.
+        let q = self.hi / rhs.hi;
+        let rem = self - rhs * q;
+        Self::renormalize(q, rem.hi / rhs.hi)
+    }
+}
+
+impl<T: Float> Div<T> for DoubleFloat<T> {
+    type Output = Self;
+
+    #[inline]
+    fn div(self, rhs: T) -> Self {
+        let q = self.hi / rhs;
+        let (p, p_err) = two_product(q, rhs);
+        let rem = ((self.hi - p) - p_err) + self.lo;
+        Self::renormalize(q, rem / rhs)
+    }
+}
+
+macro_rules! assign_ops {
+    ($($trait:ident, $fn:ident, $op:tt;)*) => {
+        $(
+            impl<T: Float> $trait for DoubleFloat<T> {
+                #[inline]
+                fn $fn(&mut self, rhs: Self) {
+                    *self = *self $op rhs;
+                }
+            }
+
+            impl<T: Float> $trait<T> for DoubleFloat<T> {
+                #[inline]
+                fn $fn(&mut self, rhs: T) {
+                    *self = *self $op rhs;
+                }
+            }
+        )*
+    };
+}
+
+assign_ops! {
+    AddAssign, add_assign, +;
+    SubAssign, sub_assign, -;
+    MulAssign, mul_assign, *;
+    DivAssign, div_assign, /;
+}
@@ -0,0 +1,118 @@
+//! Device-side counters for debugging and statistics, collected by the host after launches.
+//!
+//! Printing from kernels is slow and its output is hard to aggregate over millions of threads. Instead,
+//! kernels can bump one of [`SLOTS`] 64-bit counters in a global buffer of the module with [`count`],
+//! [`add`] and [`record_max`], which the host reads after the kernels have run, for example with
+//! `cust::diag::Diagnostics`:
+//!
+//! ```no_run
+//! const RAY_MISSED: u32 = 0;
+//! const MAX_BOUNCES: u32 = 1;
+//!
+//! #[kernel]
+//! pub unsafe fn trace(rays: &[Ray], out: *mut Vec3<f32>) {
+//!     // ...
+//!     if hit.is_none() {
+//!         diag::count(RAY_MISSED);
+//!     }
+//!     diag::record_max(MAX_BOUNCES, bounces as u64);
+//! }
+//! ```
+//!
+//! ```ignore
+//! // host
+//! let mut diagnostics = Diagnostics::new(&module)?;
+//! unsafe { launch!(module.trace<<<grid, block, 0, stream>>>(...))? };
+//! stream.synchronize()?;
+//! let counters = diagnostics.take()?;
+//! println!("{} rays missed, at most {} bounces", counters[0], counters[1]);
+//! ```
+//!
+//! The counters are updated with fire-and-forget atomics (`red` instructions), which are cheap but not free
+//! when many threads hit the same counter, so hot loops should count in a local variable and add it once.
+//! Slots out of range are ignored. The buffer is shared by every kernel of the module, its meaning is
+//! entirely up to the caller.
+//!
+//! On the host, the counters are kept in process-wide atomics instead, so that kernels using them still run
+//! inside of [`host::emulate`](crate::host::emulate). They are read and reset with [`take_host_counters`].
+
+/// The number of counters in the diagnostics buffer.
+pub const SLOTS: usize = 256;
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+mod imp {
+    use super::SLOTS;
+
+    /// The diagnostics buffer, found by the host by its name.
+    #[no_mangle]
+    #[crate::address_space(global)]
+    static mut CUDA_STD_DIAG: [u64; SLOTS] = [0; SLOTS];
+
+    #[inline(always)]
+    fn slot_ptr(slot: u32) -> *mut u64 {
+        unsafe { core::ptr::addr_of_mut!(CUDA_STD_DIAG[slot as usize]) }
+    }
+
+    #[inline(always)]
+    pub(super) fn add(slot: u32, value: u64) {
+        unsafe { asm!("red.add.u64 [{}], {};", in(reg64) slot_ptr(slot), in(reg64) value) }
+    }
+
+    #[inline(always)]
+    pub(super) fn max(slot: u32, value: u64) {
+        unsafe { asm!("red.max.u64 [{}], {};", in(reg64) slot_ptr(slot), in(reg64) value) }
+    }
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+mod imp {
+    use super::SLOTS;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[allow(clippy::declare_interior_mutable_const)]
+    const ZERO: AtomicU64 = AtomicU64::new(0);
+    pub(super) static HOST_COUNTERS: [AtomicU64; SLOTS] = [ZERO; SLOTS];
+
+    pub(super) fn add(slot: u32, value: u64) {
+        HOST_COUNTERS[slot as usize].fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub(super) fn max(slot: u32, value: u64) {
+        HOST_COUNTERS[slot as usize].fetch_max(value, Ordering::Relaxed);
+    }
+}
+
+/// Adds one to the counter `slot`.
+#[inline(always)]
+pub fn count(slot: u32) {
+    add(slot, 1);
+}
+
+/// Adds `value` to the counter `slot`, wrapping around on overflow.
+#[inline(always)]
+pub fn add(slot: u32, value: u64) {
+    if (slot as usize) < SLOTS {
+        imp::add(slot, value);
+    }
+}
+
+/// Sets the counter `slot` to `value` if `value` is larger, for recording the largest value of something
+/// (e.g. the iteration count of a loop) over every thread.
+#[inline(always)]
+pub fn record_max(slot: u32, value: u64) {
+    if (slot as usize) < SLOTS {
+        imp::max(slot, value);
+    }
+}
+
+/// Returns the counters used on the host and resets them to zero.
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+pub fn take_host_counters() -> [u64; SLOTS] {
+    use std::sync::atomic::Ordering;
+
+    let mut counters = [0; SLOTS];
+    for (counter, host) in counters.iter_mut().zip(&imp::HOST_COUNTERS) {
+        *counter = host.swap(0, Ordering::Relaxed);
+    }
+    counters
+}
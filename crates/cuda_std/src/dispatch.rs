@@ -0,0 +1,114 @@
+//! Dispatch over enums with data, such as the objects and materials of a renderer, with the lanes of
+//! a warp grouped by variant.
+//!
+//! A `match` over an enum whose variant differs between the lanes of a warp diverges: every variant
+//! which occurs in the warp runs one after the other with only its lanes active. This cannot be
+//! avoided, but without help the lanes of a variant may not even run it together. On compute_70 and
+//! above threads are scheduled independently, so after nested branches (such as the loops of a
+//! material) the warp can end up running the same arm several times for different subsets of its
+//! lanes, which costs as much as running every lane on its own.
+//!
+//! [`coherent`] runs a closure (usually the `match`) once per variant in the warp, with exactly the
+//! lanes of that variant, one variant after the other. The lanes of a variant stay converged through
+//! the arm and the warp reconverges after every variant. Enums opt into it with
+//! [`#[dispatch]`](crate::dispatch), which implements [`Dispatch`] for them:
+//!
+//! ```ignore
+//! #[dispatch]
+//! #[derive(Clone, Copy)]
+//! pub enum MaterialKind {
+//!     Diffuse(DiffuseMaterial),
+//!     Metallic(MetallicMaterial),
+//!     Dielectric(DielectricMaterial),
+//! }
+//!
+//! let material = &materials[hit.material_id as usize];
+//! let (attenuation, scattered) = dispatch::coherent(material, |material| match material {
+//!     MaterialKind::Diffuse(m) => m.scatter(ray, hit, rng),
+//!     MaterialKind::Metallic(m) => m.scatter(ray, hit, rng),
+//!     MaterialKind::Dielectric(m) => m.scatter(ray, hit, rng),
+//! });
+//! ```
+//!
+//! Matching on a reference to an enum in global memory, like above, is also cheaper than copying it
+//! into a local variable first. The payloads of the variants share the same bytes, and a copy whose
+//! variant is only known at runtime cannot be split into registers, so it is spilled to local memory
+//! and read back by every arm. The codegen removes copies which are only read and come from a shared
+//! reference parameter of the function (such as `let material = materials[i];` with
+//! `materials: &[MaterialKind]`), the arms then read the referenced memory. The `match` itself still
+//! compiles to compares and branches, libnvvm does not emit jump tables.
+//!
+//! On the host, [`coherent`] calls the closure directly.
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::warp;
+
+/// Enums whose variant can be read as a number, implemented by [`#[dispatch]`](crate::dispatch).
+pub trait Dispatch {
+    /// The number of variants of the enum.
+    const VARIANTS: u32;
+
+    /// The index of the variant of `self`, from `0` to `VARIANTS - 1` in declaration order.
+    fn variant(&self) -> u32;
+}
+
+/// The lanes of the warp which are executing this call (`__activemask()` in CUDA C++).
+#[inline(always)]
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+fn active_mask() -> u32 {
+    let mask;
+    unsafe {
+        asm!("activemask.b32 {};", out(reg32) mask);
+    }
+    mask
+}
+
+/// The lanes of `mask` for which `pred` is true (`__ballot_sync` in CUDA C++).
+#[inline(always)]
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+unsafe fn ballot(mask: u32, pred: bool) -> u32 {
+    let out;
+    // inline asm has no predicate registers, see `select`.
+    asm!(
+        "{{ .reg .pred p; setp.ne.b32 p, {}, 0; vote.sync.ballot.b32 {}, p, {}; }}",
+        in(reg32) pred as u32,
+        out(reg32) out,
+        in(reg32) mask,
+    );
+    out
+}
+
+/// Calls `f` with `value`, with the active lanes of the warp grouped by the variant of their
+/// `value`: `f` runs once for every variant which occurs in the warp, with exactly the lanes of that
+/// variant active, and the warp reconverges before this returns.
+///
+/// Every lane which is active when this is called must call it. Warp functions used in `f` must
+/// only include the lanes of the variant, not every lane of the warp.
+#[inline(always)]
+pub fn coherent<T: Dispatch + ?Sized, R>(value: &T, f: impl FnOnce(&T) -> R) -> R {
+    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+    {
+        f(value)
+    }
+    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+    unsafe {
+        let variant = value.variant();
+        let mask = active_mask();
+        let mut pending = mask;
+        let mut f = Some(f);
+        let mut out = None;
+        // every lane of `mask` goes through every iteration, only the lanes of the variant of the
+        // lowest pending lane call `f` in each of them.
+        while pending != 0 {
+            let leader = pending.trailing_zeros();
+            let leader_variant = warp::shuffle(mask, variant, leader);
+            let group = ballot(mask, variant == leader_variant);
+            if variant == leader_variant {
+                out = Some((f.take().unwrap_unchecked())(value));
+            }
+            pending &= !group;
+        }
+        warp::sync_warp(mask);
+        out.unwrap_unchecked()
+    }
+}
@@ -0,0 +1,111 @@
+//! Fast approximate math functions, which map to the approximate hardware instructions (the
+//! `__sinf`, `__expf`, etc. intrinsics of CUDA C++).
+//!
+//! These are much faster than the regular `f32` functions but trade accuracy for speed: the
+//! trigonometric functions lose accuracy quickly outside of `-π..π`, and denormals are flushed to
+//! zero. Unlike compiling a whole module with fast-math they are opted into per call site, so
+//! performance-critical code such as shading can use them while the rest of a kernel stays
+//! precise:
+//!
+//! ```ignore
+//! use cuda_std::fast;
+//!
+//! fn falloff(distance: f32, radius: f32) -> f32 {
+//!     fast::saturate(1.0 - fast::div(distance, radius)) * fast::exp(-distance)
+//! }
+//! ```
+//!
+//! The exact error bounds of every function are listed in the CUDA C Programming Guide, Appendix
+//! "Intrinsic Functions".
+
+use crate::gpu_only;
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::intrinsics as raw;
+
+macro_rules! fast_fns {
+    ($($(#[$attr:meta])* $name:ident($($arg:ident),*) => $raw:ident;)*) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name($($arg: f32),*) -> f32 {
+                unsafe { raw::$raw($($arg),*) }
+            }
+        )*
+    };
+}
+
+fast_fns! {
+    /// Fast approximate sine (`__sinf` in CUDA C++).
+    sin(x) => fast_sinf;
+    /// Fast approximate cosine (`__cosf` in CUDA C++).
+    cos(x) => fast_cosf;
+    /// Fast approximate tangent (`__tanf` in CUDA C++).
+    tan(x) => fast_tanf;
+    /// Fast approximate `e^x` (`__expf` in CUDA C++).
+    exp(x) => fast_expf;
+    /// Fast approximate `10^x` (`__exp10f` in CUDA C++).
+    exp10(x) => fast_exp10f;
+    /// Fast approximate natural logarithm (`__logf` in CUDA C++).
+    log(x) => fast_logf;
+    /// Fast approximate base 2 logarithm (`__log2f` in CUDA C++).
+    log2(x) => fast_log2f;
+    /// Fast approximate base 10 logarithm (`__log10f` in CUDA C++).
+    log10(x) => fast_log10f;
+    /// Fast approximate `x^y`, computed as `2^(y * log2(x))` (`__powf` in CUDA C++).
+    pow(x, y) => fast_powf;
+    /// Fast approximate division (`__fdividef` in CUDA C++), returns 0 if `y` is larger than
+    /// `2^126` and `x` is finite.
+    div(x, y) => fast_fdividef;
+    /// Clamps `x` to `0.0..=1.0`, NaN becomes 0 (`__saturatef` in CUDA C++).
+    saturate(x) => saturatef;
+}
+
+/// Fast approximate sine and cosine, computed together (`__sincosf` in CUDA C++).
+#[gpu_only]
+#[inline(always)]
+pub fn sin_cos(x: f32) -> (f32, f32) {
+    let (mut sin, mut cos) = (0.0, 0.0);
+    unsafe {
+        raw::fast_sincosf(x, &mut sin, &mut cos);
+    }
+    (sin, cos)
+}
+
+macro_rules! approx_instructions {
+    ($($(#[$attr:meta])* $name:ident($($arg:ident),*) => $op:literal;)*) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name($($arg: f32),*) -> f32 {
+                let out;
+                unsafe {
+                    asm!(
+                        concat!($op, " {}", $(", {", stringify!($arg), "}",)* ";"),
+                        out(reg32) out,
+                        $($arg = in(reg32) $arg,)*
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+approx_instructions! {
+    /// Fast approximate reciprocal, `1 / x`.
+    recip(x) => "rcp.approx.ftz.f32";
+    /// Fast approximate square root.
+    sqrt(x) => "sqrt.approx.ftz.f32";
+    /// Fast approximate reciprocal square root, `1 / sqrt(x)` (`rsqrtf` in CUDA C++).
+    rsqrt(x) => "rsqrt.approx.ftz.f32";
+    /// `x + y`, clamped to `0.0..=1.0`, NaN becomes 0.
+    add_sat(x, y) => "add.sat.f32";
+    /// `x - y`, clamped to `0.0..=1.0`, NaN becomes 0.
+    sub_sat(x, y) => "sub.sat.f32";
+    /// `x * y`, clamped to `0.0..=1.0`, NaN becomes 0.
+    mul_sat(x, y) => "mul.sat.f32";
+    /// `x * y + z` with a single rounding, clamped to `0.0..=1.0`, NaN becomes 0.
+    mul_add_sat(x, y, z) => "fma.rn.sat.f32";
+}
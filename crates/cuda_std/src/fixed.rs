@@ -0,0 +1,380 @@
+//! Fixed-point numbers in Q format with saturating arithmetic, for porting DSP code (audio, radio,
+//! sensor processing) which relies on fixed-point semantics.
+//!
+//! [`Fixed32<FRAC>`](Fixed32) and [`Fixed16<FRAC>`](Fixed16) store a number as an `i32` or `i16` with
+//! `FRAC` fractional bits, so the value is `bits / 2^FRAC`. The common formats have aliases: [`Q31`] and
+//! [`Q15`] for values in `-1.0..1.0`, and [`Q16_16`] for values in `-32768.0..32768.0`.
+//!
+//! Every operation saturates instead of wrapping, like DSP instructions do: results which do not fit
+//! are clamped to [`MIN`](Fixed32::MIN) or [`MAX`](Fixed32::MAX). Multiplications and divisions round
+//! to the nearest representable value. The operators (`+`, `-`, `*`, `/`) are the saturating
+//! operations, the `wrapping_*` methods are there for code which relies on wrapping.
+//!
+//! On the GPU, 32-bit additions and subtractions use the saturating `add.sat.s32` and `sub.sat.s32`
+//! instructions and conversions from floats use `cvt.rni.s32.f32`, which rounds and saturates in one
+//! instruction. 16-bit operations are done in 32 bits and clamped, since there are no saturating 16-bit
+//! instructions (except for the packed ones in [`simd`](crate::simd)).
+//!
+//! ```ignore
+//! use cuda_std::fixed::Q15;
+//!
+//! // a FIR filter over Q15 samples, as it would run on a DSP.
+//! pub fn fir(samples: &[Q15], taps: &[Q15]) -> Q15 {
+//!     samples
+//!         .iter()
+//!         .zip(taps)
+//!         .fold(Q15::ZERO, |acc, (&s, &t)| acc.mul_add(s, t))
+//! }
+//! ```
+//!
+//! `FRAC` must be less than the number of bits of the type (`0..=31` and `0..=15`).
+
+use core::{
+    fmt,
+    ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign},
+};
+
+#[inline(always)]
+fn add_sat_i32(a: i32, b: i32) -> i32 {
+    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+    let out = a.saturating_add(b);
+    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+    let out = {
+        let out: i32;
+        unsafe {
+            asm!("add.sat.s32 {}, {}, {};", out(reg32) out, in(reg32) a, in(reg32) b);
+        }
+        out
+    };
+    out
+}
+
+#[inline(always)]
+fn sub_sat_i32(a: i32, b: i32) -> i32 {
+    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+    let out = a.saturating_sub(b);
+    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+    let out = {
+        let out: i32;
+        unsafe {
+            asm!("sub.sat.s32 {}, {}, {};", out(reg32) out, in(reg32) a, in(reg32) b);
+        }
+        out
+    };
+    out
+}
+
+/// Rounds `x` to the nearest integer with ties to even, saturating to the range of `i32`. NaN becomes
+/// `0`.
+#[inline(always)]
+fn round_sat_i32(x: f32) -> i32 {
+    // `round` rounds ties away from zero, `cvt.rni` to even.
+    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+    let out = if (x - x.trunc()).abs() == 0.5 {
+        2.0 * (x / 2.0).round()
+    } else {
+        x.round()
+    } as i32;
+    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+    let out = {
+        let out: i32;
+        unsafe {
+            asm!("cvt.rni.s32.f32 {}, {};", out(reg32) out, in(reg32) x);
+        }
+        out
+    };
+    out
+}
+
+#[inline(always)]
+fn add_sat_i16(a: i16, b: i16) -> i16 {
+    (a as i32 + b as i32).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+#[inline(always)]
+fn sub_sat_i16(a: i16, b: i16) -> i16 {
+    (a as i32 - b as i32).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+#[inline(always)]
+fn round_sat_i16(x: f32) -> i16 {
+    round_sat_i32(x).clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+macro_rules! fixed {
+    ($(
+        $(#[$attr:meta])*
+        $name:ident($bits:ident, $wide:ident, $add:ident, $sub:ident, $round:ident);
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[repr(transparent)]
+            #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+            pub struct $name<const FRAC: u32>($bits);
+
+            #[cfg(all(not(target_os = "cuda"), feature = "cust"))]
+            unsafe impl<const FRAC: u32> cust::memory::DeviceCopy for $name<FRAC> {}
+
+            impl<const FRAC: u32> $name<FRAC> {
+                pub const ZERO: Self = Self(0);
+                /// The largest representable value, `(2^(BITS - 1) - 1) / 2^FRAC`.
+                pub const MAX: Self = Self($bits::MAX);
+                /// The smallest representable value, `-2^(BITS - 1) / 2^FRAC`.
+                pub const MIN: Self = Self($bits::MIN);
+                /// The smallest positive value, `1 / 2^FRAC`.
+                pub const DELTA: Self = Self(1);
+
+                /// Half of the last place, added before shifting to round to nearest.
+                const HALF: $wide = if FRAC == 0 { 0 } else { 1 << (FRAC - 1) };
+
+                /// Creates a number from its raw representation, `bits / 2^FRAC`.
+                #[inline]
+                pub const fn from_bits(bits: $bits) -> Self {
+                    Self(bits)
+                }
+
+                /// The raw representation of the number.
+                #[inline]
+                pub const fn to_bits(self) -> $bits {
+                    self.0
+                }
+
+                /// Converts an integer, saturating if it is out of range.
+                #[inline]
+                pub fn from_int(x: $bits) -> Self {
+                    Self::saturate((x as $wide) << FRAC)
+                }
+
+                /// Converts a float, rounding to the nearest representable value (ties to even) and
+                /// saturating if it is out of range. NaN becomes zero.
+                #[inline]
+                pub fn from_f32(x: f32) -> Self {
+                    Self($round(x * (1u64 << FRAC) as f32))
+                }
+
+                #[inline]
+                pub fn to_f32(self) -> f32 {
+                    self.0 as f32 / (1u64 << FRAC) as f32
+                }
+
+                #[inline]
+                pub fn to_f64(self) -> f64 {
+                    self.0 as f64 / (1u64 << FRAC) as f64
+                }
+
+                /// The integer part, rounded towards negative infinity.
+                #[inline]
+                pub fn floor_int(self) -> $bits {
+                    (self.0 as $wide >> FRAC) as $bits
+                }
+
+                #[inline]
+                fn saturate(x: $wide) -> Self {
+                    Self(x.clamp($bits::MIN as $wide, $bits::MAX as $wide) as $bits)
+                }
+
+                #[inline]
+                pub fn saturating_add(self, rhs: Self) -> Self {
+                    Self($add(self.0, rhs.0))
+                }
+
+                #[inline]
+                pub fn saturating_sub(self, rhs: Self) -> Self {
+                    Self($sub(self.0, rhs.0))
+                }
+
+                /// The product, rounded to nearest.
+                #[inline]
+                pub fn saturating_mul(self, rhs: Self) -> Self {
+                    let product = self.0 as $wide * rhs.0 as $wide;
+                    Self::saturate((product + Self::HALF) >> FRAC)
+                }
+
+                /// The quotient, rounded to nearest with ties away from zero. Dividing by zero gives
+                /// [`MAX`](Self::MAX) or [`MIN`](Self::MIN) depending on the sign of `self`, and zero for `0 / 0`.
+                #[inline]
+                pub fn saturating_div(self, rhs: Self) -> Self {
+                    if rhs.0 == 0 {
+                        return match self.0 {
+                            0 => Self::ZERO,
+                            x if x > 0 => Self::MAX,
+                            _ => Self::MIN,
+                        };
+                    }
+                    let (dividend, divisor) = ((self.0 as $wide) << FRAC, rhs.0 as $wide);
+                    let quotient = dividend / divisor;
+                    // round half away from zero, the remainder has the sign of the dividend.
+                    let remainder = dividend % divisor;
+                    let rounded = if 2 * remainder.abs() < divisor.abs() {
+                        quotient
+                    } else if (dividend < 0) == (divisor < 0) {
+                        quotient + 1
+                    } else {
+                        quotient - 1
+                    };
+                    Self::saturate(rounded)
+                }
+
+                /// `self + a * b` with a single saturation at the end, the multiply-accumulate of DSPs.
+                #[inline]
+                pub fn mul_add(self, a: Self, b: Self) -> Self {
+                    let product = (a.0 as $wide * b.0 as $wide + Self::HALF) >> FRAC;
+                    Self::saturate(self.0 as $wide + product)
+                }
+
+                /// The negation, `-MIN` saturates to `MAX`.
+                #[inline]
+                pub fn saturating_neg(self) -> Self {
+                    Self($sub(0, self.0))
+                }
+
+                /// The absolute value, `MIN.saturating_abs()` saturates to `MAX`.
+                #[inline]
+                pub fn saturating_abs(self) -> Self {
+                    if self.0 < 0 {
+                        self.saturating_neg()
+                    } else {
+                        self
+                    }
+                }
+
+                #[inline]
+                pub fn wrapping_add(self, rhs: Self) -> Self {
+                    Self(self.0.wrapping_add(rhs.0))
+                }
+
+                #[inline]
+                pub fn wrapping_sub(self, rhs: Self) -> Self {
+                    Self(self.0.wrapping_sub(rhs.0))
+                }
+
+                /// The product, rounded to nearest and wrapping if it is out of range.
+                #[inline]
+                pub fn wrapping_mul(self, rhs: Self) -> Self {
+                    let product = self.0 as $wide * rhs.0 as $wide;
+                    Self(((product + Self::HALF) >> FRAC) as $bits)
+                }
+            }
+
+            impl<const FRAC: u32> fmt::Debug for $name<FRAC> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    fmt::Debug::fmt(&self.to_f64(), f)
+                }
+            }
+
+            impl<const FRAC: u32> fmt::Display for $name<FRAC> {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    fmt::Display::fmt(&self.to_f64(), f)
+                }
+            }
+
+            impl<const FRAC: u32> Neg for $name<FRAC> {
+                type Output = Self;
+
+                #[inline]
+                fn neg(self) -> Self {
+                    self.saturating_neg()
+                }
+            }
+
+            fixed_ops! {
+                $name;
+                Add, add, AddAssign, add_assign, saturating_add;
+                Sub, sub, SubAssign, sub_assign, saturating_sub;
+                Mul, mul, MulAssign, mul_assign, saturating_mul;
+                Div, div, DivAssign, div_assign, saturating_div;
+            }
+        )*
+    };
+}
+
+macro_rules! fixed_ops {
+    ($name:ident; $($trait:ident, $fn:ident, $assign_trait:ident, $assign_fn:ident, $method:ident;)*) => {
+        $(
+            impl<const FRAC: u32> $trait for $name<FRAC> {
+                type Output = Self;
+
+                #[inline]
+                fn $fn(self, rhs: Self) -> Self {
+                    self.$method(rhs)
+                }
+            }
+
+            impl<const FRAC: u32> $assign_trait for $name<FRAC> {
+                #[inline]
+                fn $assign_fn(&mut self, rhs: Self) {
+                    *self = self.$method(rhs);
+                }
+            }
+        )*
+    };
+}
+
+fixed! {
+    /// A 32-bit fixed-point number with `FRAC` fractional bits, see the [module docs](self).
+    Fixed32(i32, i64, add_sat_i32, sub_sat_i32, round_sat_i32);
+    /// A 16-bit fixed-point number with `FRAC` fractional bits, see the [module docs](self).
+    Fixed16(i16, i32, add_sat_i16, sub_sat_i16, round_sat_i16);
+}
+
+/// A 32-bit number in `-1.0..1.0`, the usual format of DSP accumulators.
+pub type Q31 = Fixed32<31>;
+/// A 16-bit number in `-1.0..1.0`, the usual format of audio samples and filter coefficients.
+pub type Q15 = Fixed16<15>;
+/// A 32-bit number with 16 integer and 16 fractional bits.
+pub type Q16_16 = Fixed32<16>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_f32_rounds_ties_to_even() {
+        let round = |x| Fixed32::<0>::from_f32(x).to_bits();
+        assert_eq!(round(0.5), 0);
+        assert_eq!(round(1.5), 2);
+        assert_eq!(round(2.5), 2);
+        assert_eq!(round(-2.5), -2);
+        assert_eq!(round(-3.5), -4);
+        assert_eq!(round(2.4), 2);
+        assert_eq!(round(2.6), 3);
+        assert_eq!(Q16_16::from_f32(1.5 / 65536.0).to_bits(), 2);
+        assert_eq!(Fixed16::<0>::from_f32(6.5).to_bits(), 6);
+    }
+
+    #[test]
+    fn from_f32_saturates() {
+        assert_eq!(Fixed32::<0>::from_f32(3.0e9), Fixed32::MAX);
+        assert_eq!(Fixed32::<0>::from_f32(f32::NEG_INFINITY), Fixed32::MIN);
+        assert_eq!(Q31::from_f32(1.0), Q31::MAX);
+        assert_eq!(Q31::from_f32(-1.0), Q31::from_bits(i32::MIN));
+        assert_eq!(Q15::from_f32(2.0), Q15::MAX);
+        assert_eq!(Q15::from_f32(-7.0), Q15::MIN);
+    }
+
+    #[test]
+    fn from_f32_maps_nan_to_zero() {
+        assert_eq!(Q16_16::from_f32(f32::NAN), Q16_16::ZERO);
+        assert_eq!(Q15::from_f32(f32::NAN), Q15::ZERO);
+    }
+
+    #[test]
+    fn operations_saturate() {
+        assert_eq!(Q15::MAX + Q15::DELTA, Q15::MAX);
+        assert_eq!(Q15::MIN - Q15::DELTA, Q15::MIN);
+        assert_eq!(-Q31::MIN, Q31::MAX);
+        assert_eq!(Q15::MIN * Q15::MIN, Q15::MAX);
+        assert_eq!(Q16_16::from_int(30000) * Q16_16::from_int(2), Q16_16::MAX);
+        assert_eq!(Q16_16::from_int(1) / Q16_16::ZERO, Q16_16::MAX);
+        assert_eq!(Q16_16::ZERO / Q16_16::ZERO, Q16_16::ZERO);
+    }
+
+    #[test]
+    fn division_rounds_half_away_from_zero() {
+        let div = |a, b| (Fixed32::<0>::from_int(a) / Fixed32::<0>::from_int(b)).to_bits();
+        assert_eq!(div(5, 2), 3);
+        assert_eq!(div(-5, 2), -3);
+        assert_eq!(div(7, 3), 2);
+        assert_eq!(div(-8, 3), -3);
+    }
+}
@@ -0,0 +1,297 @@
+//! 8-bit floating point types, as used for weights and activations of quantized kernels.
+//!
+//! Two formats are provided:
+//! - [`F8E4M3`], 4 exponent bits and 3 mantissa bits, with a range of ±448 and no infinities.
+//! - [`F8E5M2`], 5 exponent bits and 2 mantissa bits, with a range of ±57344 and infinities.
+//!
+//! Conversions from `f32` round to nearest-even and saturate to the largest finite value, which
+//! matches the `cvt.rn.satfinite` instructions used for them on `sm_89` and newer. On older
+//! architectures and on the host the conversions are done in software, with the same results.
+//! Arithmetic is done by converting to `f32` (or [`f16`](crate::f16)) and back.
+//!
+//! The types can be copied to and from the GPU with cust when the `cust` feature is enabled.
+
+use crate::f16;
+use core::cmp::Ordering;
+use core::fmt;
+
+/// Parameters of an 8-bit float format.
+struct Format {
+    mantissa_bits: u32,
+    bias: i32,
+    /// The bits of the largest finite value.
+    max: u8,
+    /// The bits of the canonical NaN.
+    nan: u8,
+    has_inf: bool,
+}
+
+const E4M3: Format = Format {
+    mantissa_bits: 3,
+    bias: 7,
+    max: 0x7E,
+    nan: 0x7F,
+    has_inf: false,
+};
+
+const E5M2: Format = Format {
+    mantissa_bits: 2,
+    bias: 15,
+    max: 0x7B,
+    nan: 0x7F,
+    has_inf: true,
+};
+
+impl Format {
+    fn is_nan(&self, bits: u8) -> bool {
+        let magnitude = bits & 0x7F;
+        if self.has_inf {
+            magnitude > 0x7F & !((1 << self.mantissa_bits) - 1)
+        } else {
+            magnitude == self.nan
+        }
+    }
+
+    // unused when the conversions are done in hardware.
+    #[allow(dead_code)]
+    fn encode(&self, value: f32) -> u8 {
+        let bits = value.to_bits();
+        let sign = ((bits >> 31) as u8) << 7;
+        let exponent = ((bits >> 23) & 0xFF) as i32;
+        let mantissa = bits & 0x7F_FFFF;
+        if exponent == 0xFF {
+            // infinities saturate like any other overflow.
+            return if mantissa != 0 {
+                sign | self.nan
+            } else {
+                sign | self.max
+            };
+        }
+        if exponent == 0 {
+            // zero, and f32 subnormals are far too small to be anything but zero.
+            return sign;
+        }
+
+        let exponent = exponent - 127;
+        let significand = mantissa | 0x80_0000;
+        let min_exponent = 1 - self.bias;
+        // the number of bits of the f32 significand which do not fit, more for subnormals.
+        let shift = (23 - self.mantissa_bits) as i32 + (min_exponent - exponent).max(0);
+        if shift > 24 {
+            return sign;
+        }
+        let shift = shift as u32;
+        let mut rounded = significand >> shift;
+        let remainder = significand & ((1 << shift) - 1);
+        let half = 1 << (shift - 1);
+        if remainder > half || (remainder == half && rounded & 1 == 1) {
+            rounded += 1;
+        }
+
+        let magnitude = if exponent >= min_exponent {
+            // the implicit bit of `rounded` adds one to the exponent field, rounding up to the next
+            // power of two carries into it.
+            (((exponent + self.bias - 1) as u32) << self.mantissa_bits) + rounded
+        } else {
+            // subnormals, rounding up to the smallest normal carries into the exponent field too.
+            rounded
+        };
+        sign | magnitude.min(self.max as u32) as u8
+    }
+
+    #[allow(dead_code)]
+    fn decode(&self, bits: u8) -> f32 {
+        let sign = ((bits >> 7) as u32) << 31;
+        let magnitude = bits & 0x7F;
+        if self.is_nan(bits) {
+            return f32::NAN;
+        }
+        let exponent = (magnitude >> self.mantissa_bits) as i32;
+        let mantissa = (magnitude & ((1 << self.mantissa_bits) - 1)) as u32;
+        if self.has_inf && exponent == (0x7F >> self.mantissa_bits) {
+            return f32::from_bits(sign | f32::INFINITY.to_bits());
+        }
+        let magnitude = if exponent == 0 {
+            // subnormal, `mantissa * 2^(1 - bias - mantissa_bits)`, which is exact in f32.
+            let scale =
+                f32::from_bits(((128 - self.bias - self.mantissa_bits as i32) as u32) << 23);
+            mantissa as f32 * scale
+        } else {
+            f32::from_bits(
+                (((exponent - self.bias + 127) as u32) << 23)
+                    | (mantissa << (23 - self.mantissa_bits)),
+            )
+        };
+        f32::from_bits(sign | magnitude.to_bits())
+    }
+}
+
+macro_rules! fp8 {
+    ($name:ident, $format:ident, $ptx:literal, $doc:literal) => {
+        #[doc = $doc]
+        #[repr(transparent)]
+        #[derive(Clone, Copy, Default)]
+        pub struct $name(u8);
+
+        impl $name {
+            /// Positive zero.
+            pub const ZERO: Self = Self(0);
+            /// The largest finite value.
+            pub const MAX: Self = Self($format.max);
+            /// The smallest finite value.
+            pub const MIN: Self = Self($format.max | 0x80);
+            /// The smallest positive normal value.
+            pub const MIN_POSITIVE: Self = Self(1 << $format.mantissa_bits);
+            /// Not a number.
+            pub const NAN: Self = Self($format.nan);
+
+            /// Creates a value from its raw bits.
+            #[inline]
+            pub const fn from_bits(bits: u8) -> Self {
+                Self(bits)
+            }
+
+            /// The raw bits of this value.
+            #[inline]
+            pub const fn to_bits(self) -> u8 {
+                self.0
+            }
+
+            /// Whether this value is NaN.
+            #[inline]
+            pub fn is_nan(self) -> bool {
+                $format.is_nan(self.0)
+            }
+
+            /// Converts an `f32`, rounding to nearest-even and saturating to [`Self::MAX`] and
+            /// [`Self::MIN`]. NaN stays NaN.
+            #[cfg(all(
+                any(target_arch = "nvptx", target_arch = "nvptx64"),
+                target_feature = "sm_89"
+            ))]
+            #[inline]
+            pub fn from_f32(value: f32) -> Self {
+                let out: u16;
+                unsafe {
+                    asm!(
+                        concat!("cvt.rn.satfinite.", $ptx, "x2.f32 {}, {}, {};"),
+                        out(reg16) out,
+                        in(reg32) 0.0f32,
+                        in(reg32) value,
+                    );
+                }
+                Self(out as u8)
+            }
+
+            /// Converts an `f32`, rounding to nearest-even and saturating to [`Self::MAX`] and
+            /// [`Self::MIN`]. NaN stays NaN.
+            #[cfg(not(all(
+                any(target_arch = "nvptx", target_arch = "nvptx64"),
+                target_feature = "sm_89"
+            )))]
+            #[inline]
+            pub fn from_f32(value: f32) -> Self {
+                Self($format.encode(value))
+            }
+
+            /// Converts to an `f32`, which is exact.
+            #[cfg(all(
+                any(target_arch = "nvptx", target_arch = "nvptx64"),
+                target_feature = "sm_89"
+            ))]
+            #[inline]
+            pub fn to_f32(self) -> f32 {
+                let out: f32;
+                unsafe {
+                    asm!(
+                        "{{",
+                        ".reg .b32 pair;",
+                        ".reg .b16 lo, hi;",
+                        concat!("cvt.rn.f16x2.", $ptx, "x2 pair, {};"),
+                        "mov.b32 {{lo, hi}}, pair;",
+                        "cvt.f32.f16 {}, lo;",
+                        "}}",
+                        in(reg16) self.0 as u16,
+                        out(reg32) out,
+                    );
+                }
+                out
+            }
+
+            /// Converts to an `f32`, which is exact.
+            #[cfg(not(all(
+                any(target_arch = "nvptx", target_arch = "nvptx64"),
+                target_feature = "sm_89"
+            )))]
+            #[inline]
+            pub fn to_f32(self) -> f32 {
+                $format.decode(self.0)
+            }
+
+            /// Converts an [`f16`], rounding to nearest-even and saturating like
+            /// [`Self::from_f32`].
+            #[inline]
+            pub fn from_f16(value: f16) -> Self {
+                // every f16 is exactly representable as an f32, so this rounds only once.
+                Self::from_f32(value.to_f32())
+            }
+
+            /// Converts to an [`f16`], which is exact.
+            #[inline]
+            pub fn to_f16(self) -> f16 {
+                f16::from_f32(self.to_f32())
+            }
+        }
+
+        #[cfg(all(not(target_os = "cuda"), feature = "cust"))]
+        unsafe impl cust::memory::DeviceCopy for $name {}
+
+        impl From<$name> for f32 {
+            #[inline]
+            fn from(value: $name) -> f32 {
+                value.to_f32()
+            }
+        }
+
+        impl PartialEq for $name {
+            #[inline]
+            fn eq(&self, other: &Self) -> bool {
+                self.to_f32() == other.to_f32()
+            }
+        }
+
+        impl PartialOrd for $name {
+            #[inline]
+            fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+                self.to_f32().partial_cmp(&other.to_f32())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Debug::fmt(&self.to_f32(), f)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                fmt::Display::fmt(&self.to_f32(), f)
+            }
+        }
+    };
+}
+
+fp8!(
+    F8E4M3,
+    E4M3,
+    "e4m3",
+    "An 8-bit float with 4 exponent bits and 3 mantissa bits (`__nv_fp8_e4m3` in CUDA C++), with a \
+     range of ±448, NaN and no infinities. Usually used for weights and activations."
+);
+fp8!(
+    F8E5M2,
+    E5M2,
+    "e5m2",
+    "An 8-bit float with 5 exponent bits and 2 mantissa bits (`__nv_fp8_e5m2` in CUDA C++), with a \
+     range of ±57344, NaN and infinities. Usually used for gradients."
+);
@@ -0,0 +1,76 @@
+//! Fusing chains of elementwise operations into a single kernel.
+//!
+//! Pipelines of tiny elementwise operations (scale, add a bias, clamp, convert) are usually dominated by the
+//! overhead of launching a kernel for every operation and by every intermediate result making a round trip
+//! through global memory. [`fused_kernel!`](crate::fused_kernel) generates one kernel which applies every stage
+//! of such a chain to an element while it stays in registers, the stages being closures or device functions
+//! which are inlined like any other generic code:
+//!
+//! ```no_run
+//! fused_kernel! {
+//!     /// Normalizes the input and clamps it to be positive.
+//!     pub fn normalize(input: f32 => output: f32; mean: f32, inv_std: f32) {
+//!         |x| x - mean,
+//!         |x| x * inv_std,
+//!         |x| x.max(0.0),
+//!     }
+//! }
+//! ```
+//!
+//! This defines a kernel `normalize(input: &[f32], output: *mut f32, mean: f32, inv_std: f32)` which writes
+//! `max((input[i] - mean) * inv_std, 0.0)` to `output[i]` for every element of `input`, launched like any other
+//! kernel. Every stage takes the result of the previous one, and may change its type, the last stage must
+//! return the output type. The threads loop over the elements with [`grid_stride`], so the kernel can be
+//! launched with any grid size.
+
+use crate::thread;
+
+/// Calls `f` with every index below `len` which belongs to the current thread when the indices are split
+/// across the grid in a grid-stride loop: thread `t` of a grid of `n` threads gets `t`, `t + n`, `t + 2n` and so
+/// on. Consecutive threads get consecutive indices, so accesses to arrays indexed with them are coalesced.
+///
+/// The loop only uses the x dimension of the grid and of the blocks.
+#[inline(always)]
+pub fn grid_stride(len: usize, mut f: impl FnMut(usize)) {
+    let stride = (thread::grid_dim_x() * thread::block_dim_x()) as usize;
+    let mut i = thread::index_1d() as usize;
+    while i < len {
+        f(i);
+        i += stride;
+    }
+}
+
+/// Applies one stage of a fused chain, this only exists so that the argument types of closure stages are
+/// inferred from the previous stage.
+#[doc(hidden)]
+#[inline(always)]
+pub fn stage<A, B>(value: A, f: impl FnOnce(A) -> B) -> B {
+    f(value)
+}
+
+/// Defines a kernel which applies a chain of elementwise stages to every element of its input, see the
+/// [`fusion`](crate::fusion) module.
+///
+/// The kernel takes the input as a slice, a pointer to the output, which must have room for as many elements
+/// as the input and must not overlap it, and then the parameters after the `;`, which the stages can use.
+#[macro_export]
+macro_rules! fused_kernel {
+    (
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident($input:ident: $in:ty => $output:ident: $out:ty $(; $($param:ident: $param_ty:ty),* $(,)?)?) {
+            $($stage:expr),+ $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        #[$crate::kernel]
+        #[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+        $vis unsafe fn $name($input: &[$in], $output: *mut $out $($(, $param: $param_ty)*)?) {
+            $crate::fusion::grid_stride($input.len(), |i| {
+                let value = $input[i];
+                $(let value = $crate::fusion::stage(value, $stage);)+
+                let value: $out = value;
+                *$output.add(i) = value;
+            });
+        }
+    };
+}
@@ -0,0 +1,152 @@
+//! Emulation of the CUDA thread model on the CPU, for running kernels and device functions on the
+//! host.
+//!
+//! `cuda_std` builds for host targets, so a single crate of kernels and types can be depended on by
+//! both GPU and CPU code. On the host, most GPU-only functions panic, but the functions in
+//! [`thread`](crate::thread) which query the index and dimensions of the current thread work inside
+//! of [`emulate`], which runs a closure once for every thread of a launch, one thread at a time.
+//! This is mostly useful for testing kernels on the CPU.
+//!
+//! Since threads run one after the other, [`sync_threads`](crate::thread::sync_threads) cannot be
+//! emulated for blocks of more than one thread and panics.
+//!
+//! ```
+//! use cuda_std::{host, thread, vek::Vec3};
+//!
+//! let mut out = [0u32; 8];
+//! host::emulate(Vec3::new(2, 1, 1), Vec3::new(4, 1, 1), || {
+//!     let idx = thread::index_1d();
+//!     out[idx as usize] = idx * 2;
+//! });
+//! assert_eq!(out[5], 10);
+//! ```
+
+use core::cell::Cell;
+use vek::Vec3;
+
+#[derive(Clone, Copy)]
+struct EmulatedThread {
+    thread_idx: Vec3<u32>,
+    block_idx: Vec3<u32>,
+    block_dim: Vec3<u32>,
+    grid_dim: Vec3<u32>,
+}
+
+std::thread_local! {
+    static CURRENT: Cell<Option<EmulatedThread>> = Cell::new(None);
+}
+
+/// Resets the emulated thread when dropped, so that a panicking closure does not leave it set.
+struct Reset(Option<EmulatedThread>);
+
+impl Drop for Reset {
+    fn drop(&mut self) {
+        CURRENT.with(|c| c.set(self.0));
+    }
+}
+
+/// Runs `f` once for every thread of a launch with the given grid and block dimensions, in order of
+/// their [`index`](crate::thread::index), with the thread functions returning the indices of the
+/// emulated thread.
+///
+/// Emulated launches can be nested, the inner launch shadows the outer one until it returns.
+///
+/// # Panics
+///
+/// Panics if any dimension is zero.
+pub fn emulate(grid_dim: Vec3<u32>, block_dim: Vec3<u32>, mut f: impl FnMut()) {
+    assert!(
+        grid_dim.product() != 0 && block_dim.product() != 0,
+        "grid and block dimensions must not be zero"
+    );
+    let _reset = Reset(CURRENT.with(|c| c.get()));
+    for block_idx in indices(grid_dim) {
+        for thread_idx in indices(block_dim) {
+            CURRENT.with(|c| {
+                c.set(Some(EmulatedThread {
+                    thread_idx,
+                    block_idx,
+                    block_dim,
+                    grid_dim,
+                }))
+            });
+            f();
+        }
+    }
+}
+
+/// Whether this is called inside of [`emulate`].
+pub fn is_emulating() -> bool {
+    CURRENT.with(|c| c.get()).is_some()
+}
+
+fn indices(dim: Vec3<u32>) -> impl Iterator<Item = Vec3<u32>> {
+    (0..dim.z).flat_map(move |z| {
+        (0..dim.y).flat_map(move |y| (0..dim.x).map(move |x| Vec3::new(x, y, z)))
+    })
+}
+
+fn current(name: &str) -> EmulatedThread {
+    CURRENT.with(|c| c.get()).unwrap_or_else(|| {
+        panic!(
+            "`{}` can only be used on the GPU or inside of `cuda_std::host::emulate`",
+            name
+        )
+    })
+}
+
+/// Host versions of the intrinsics used by [`thread`](crate::thread), with the same signatures.
+pub(crate) mod intrinsics {
+    use super::current;
+    use std::sync::atomic::{fence, Ordering};
+
+    macro_rules! emulated {
+        ($($name:ident => $field:ident.$axis:ident),* $(,)?) => {
+            $(
+                pub(crate) unsafe fn $name() -> u32 {
+                    current(stringify!($field)).$field.$axis
+                }
+            )*
+        };
+    }
+
+    emulated! {
+        __nvvm_thread_idx_x => thread_idx.x,
+        __nvvm_thread_idx_y => thread_idx.y,
+        __nvvm_thread_idx_z => thread_idx.z,
+        __nvvm_block_idx_x => block_idx.x,
+        __nvvm_block_idx_y => block_idx.y,
+        __nvvm_block_idx_z => block_idx.z,
+        __nvvm_block_dim_x => block_dim.x,
+        __nvvm_block_dim_y => block_dim.y,
+        __nvvm_block_dim_z => block_dim.z,
+        __nvvm_grid_dim_x => grid_dim.x,
+        __nvvm_grid_dim_y => grid_dim.y,
+        __nvvm_grid_dim_z => grid_dim.z,
+    }
+
+    pub(crate) unsafe fn __nvvm_warp_size() -> u32 {
+        32
+    }
+
+    pub(crate) unsafe fn __nvvm_block_barrier() {
+        // every other thread of the block would have to run up to the barrier before this one can
+        // continue, which is impossible when running them one after the other.
+        assert!(
+            current("sync_threads").block_dim.product() == 1,
+            "`sync_threads` cannot be emulated on the host for blocks with more than one thread"
+        );
+    }
+
+    pub(crate) unsafe fn __nvvm_grid_fence() {
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) unsafe fn __nvvm_device_fence() {
+        fence(Ordering::SeqCst);
+    }
+
+    pub(crate) unsafe fn __nvvm_system_fence() {
+        fence(Ordering::SeqCst);
+    }
+}
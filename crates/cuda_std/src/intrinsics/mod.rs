@@ -0,0 +1,186 @@
+//! Raw intrinsics: the libdevice math intrinsics and bit manipulation intrinsics.
+//!
+//! The libdevice intrinsics were autogenerated using crude text analysis from the libdevice PDF
+//! file, therefore many of the descriptions have broken text, especially for math symbols. The
+//! link to the libdevice website is provided for every intrinsic so you can view the non-broken
+//! description.
+//!
+//! Most of the intrinsics here have "proper" functions, corresponding f32/f64 functions
+//! are already codegenned to libdevice intrinsics by the codegen automatically. This module
+//! is mostly for exotic intrinsics that have not been added as proper functions yet.
+//!
+//! The underlying libdevice functions have a prefix of `__nv_`, however this prefix
+//! is stripped from the functions in this module just for convenience.
+//!
+//! The bit manipulation intrinsics ([`popc`], [`clz`], [`brev`], [`ffs`], [`byte_perm`], the
+//! funnel shifts and their 64-bit `ll` variants) are safe functions taking unsigned integers like
+//! their CUDA C++ counterparts. They lower directly to the NVVM intrinsics for the corresponding
+//! PTX instructions on the GPU, and are implemented in software on the host so that they can be
+//! used by code shared with the CPU.
+
+mod libdevice;
+
+pub use libdevice::*;
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+mod nvvm {
+    extern "C" {
+        #[link_name = "llvm.nvvm.popc.i"]
+        pub fn popc(x: u32) -> u32;
+        #[link_name = "llvm.nvvm.popc.ll"]
+        pub fn popcll(x: u64) -> u32;
+        #[link_name = "llvm.nvvm.clz.i"]
+        pub fn clz(x: u32) -> u32;
+        #[link_name = "llvm.nvvm.clz.ll"]
+        pub fn clzll(x: u64) -> u32;
+        #[link_name = "llvm.nvvm.brev32"]
+        pub fn brev(x: u32) -> u32;
+        #[link_name = "llvm.nvvm.brev64"]
+        pub fn brevll(x: u64) -> u64;
+        #[link_name = "llvm.nvvm.prmt"]
+        pub fn prmt(x: u32, y: u32, selector: u32) -> u32;
+        #[link_name = "llvm.fshl.i32"]
+        pub fn fshl(hi: u32, lo: u32, shift: u32) -> u32;
+        #[link_name = "llvm.fshr.i32"]
+        pub fn fshr(hi: u32, lo: u32, shift: u32) -> u32;
+    }
+}
+
+/// Defines a function which calls an NVVM intrinsic on the GPU and is implemented in software on
+/// the host.
+macro_rules! bit_intrinsic {
+    ($(
+        $(#[$attr:meta])*
+        pub fn $name:ident($($arg:ident: $ty:ty),*) -> $ret:ty {
+            gpu: $gpu:expr,
+            host: $host:expr $(,)?
+        }
+    )*) => {
+        $(
+            $(#[$attr])*
+            #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+            #[inline(always)]
+            pub fn $name($($arg: $ty),*) -> $ret {
+                unsafe { $gpu }
+            }
+
+            $(#[$attr])*
+            #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+            #[inline(always)]
+            pub fn $name($($arg: $ty),*) -> $ret {
+                $host
+            }
+        )*
+    };
+}
+
+bit_intrinsic! {
+    /// The number of set bits of `x` (`__popc` in CUDA C++).
+    pub fn popc(x: u32) -> u32 {
+        gpu: nvvm::popc(x),
+        host: x.count_ones(),
+    }
+
+    /// The number of set bits of `x` (`__popcll` in CUDA C++).
+    pub fn popcll(x: u64) -> u32 {
+        gpu: nvvm::popcll(x),
+        host: x.count_ones(),
+    }
+
+    /// The number of leading zero bits of `x`, 32 if `x` is zero (`__clz` in CUDA C++).
+    pub fn clz(x: u32) -> u32 {
+        gpu: nvvm::clz(x),
+        host: x.leading_zeros(),
+    }
+
+    /// The number of leading zero bits of `x`, 64 if `x` is zero (`__clzll` in CUDA C++).
+    pub fn clzll(x: u64) -> u32 {
+        gpu: nvvm::clzll(x),
+        host: x.leading_zeros(),
+    }
+
+    /// Reverses the order of the bits of `x` (`__brev` in CUDA C++).
+    pub fn brev(x: u32) -> u32 {
+        gpu: nvvm::brev(x),
+        host: x.reverse_bits(),
+    }
+
+    /// Reverses the order of the bits of `x` (`__brevll` in CUDA C++).
+    pub fn brevll(x: u64) -> u64 {
+        gpu: nvvm::brevll(x),
+        host: x.reverse_bits(),
+    }
+
+    /// Selects four bytes out of the eight bytes of `x` and `y` (`__byte_perm` in CUDA C++).
+    ///
+    /// The bytes of `x` are numbered 0 to 3 and the bytes of `y` 4 to 7, from least to most
+    /// significant. Byte `n` of the result is the byte numbered by bits `4n..4n + 3` of
+    /// `selector`, the upper 16 bits of `selector` are ignored. For example a selector of `0x0123`
+    /// reverses the bytes of `x`, and `0x5410` combines the lower halves of `x` and `y`.
+    pub fn byte_perm(x: u32, y: u32, selector: u32) -> u32 {
+        gpu: nvvm::prmt(x, y, selector & 0x7777),
+        host: {
+            let bytes = ((y as u64) << 32 | x as u64).to_le_bytes();
+            let select = |n: u32| bytes[(selector >> (4 * n) & 7) as usize];
+            u32::from_le_bytes([select(0), select(1), select(2), select(3)])
+        },
+    }
+
+    /// Shifts the 64-bit concatenation of `hi` and `lo` left by `shift % 32` bits and returns its
+    /// upper 32 bits (`__funnelshift_l` in CUDA C++).
+    pub fn funnelshift_l(lo: u32, hi: u32, shift: u32) -> u32 {
+        gpu: nvvm::fshl(hi, lo, shift),
+        host: (((hi as u64) << 32 | lo as u64) << (shift & 31) >> 32) as u32,
+    }
+
+    /// Shifts the 64-bit concatenation of `hi` and `lo` right by `shift % 32` bits and returns
+    /// its lower 32 bits (`__funnelshift_r` in CUDA C++).
+    pub fn funnelshift_r(lo: u32, hi: u32, shift: u32) -> u32 {
+        gpu: nvvm::fshr(hi, lo, shift),
+        host: (((hi as u64) << 32 | lo as u64) >> (shift & 31)) as u32,
+    }
+}
+
+/// The one-based index of the least significant set bit of `x`, 0 if `x` is zero (`__ffs` in CUDA
+/// C++).
+#[inline(always)]
+pub fn ffs(x: u32) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        x.trailing_zeros() + 1
+    }
+}
+
+/// The one-based index of the least significant set bit of `x`, 0 if `x` is zero (`__ffsll` in
+/// CUDA C++).
+#[inline(always)]
+pub fn ffsll(x: u64) -> u32 {
+    if x == 0 {
+        0
+    } else {
+        x.trailing_zeros() + 1
+    }
+}
+
+/// Shifts the 64-bit concatenation of `hi` and `lo` left by `min(shift, 32)` bits and returns its
+/// upper 32 bits (`__funnelshift_lc` in CUDA C++).
+#[inline(always)]
+pub fn funnelshift_lc(lo: u32, hi: u32, shift: u32) -> u32 {
+    if shift >= 32 {
+        lo
+    } else {
+        funnelshift_l(lo, hi, shift)
+    }
+}
+
+/// Shifts the 64-bit concatenation of `hi` and `lo` right by `min(shift, 32)` bits and returns
+/// its lower 32 bits (`__funnelshift_rc` in CUDA C++).
+#[inline(always)]
+pub fn funnelshift_rc(lo: u32, hi: u32, shift: u32) -> u32 {
+    if shift >= 32 {
+        hi
+    } else {
+        funnelshift_r(lo, hi, shift)
+    }
+}
@@ -3,10 +3,10 @@
 //! The CUDA Standard Library provides a curated set of abstractions for writing performant, reliable, and
 //! understandable GPU kernels using the Rustc NVVM backend.
 //!
-//! This library will build on non-nvptx targets or targets not using the nvvm backend. However, it will not
-//! be usable, and it will throw linker errors if you attempt to use most of the functions in the library.
-//! However, [`kernel`] automatically cfg-gates the function annotated for `nvptx64` or `nvptx`, therefore,
-//! no "actual" functions from this crate should be used when compiling for a non-nvptx target.
+//! This library also builds on non-nvptx targets, where it uses `std`, so that a single crate of kernels and
+//! types shared with the CPU can be depended on by both CPU and GPU code. Most GPU-only functions panic when
+//! called on the host, however, the thread index functions can be emulated with [`host::emulate`], which
+//! allows running kernels on the CPU, for example in tests.
 //!
 //! This crate cannot be used with the llvm ptx backend either, it heavily relies on external functions implicitly
 //! defined by the nvvm backend, as well as internal attributes.
@@ -36,17 +36,39 @@
 
 extern crate alloc;
 
+pub mod atomic;
+pub mod cluster;
+pub mod collections;
+pub mod collective;
+pub mod compensated;
+pub mod diag;
+pub mod dispatch;
+pub mod fast;
+pub mod fixed;
 pub mod float;
+pub mod fp8;
+pub mod fusion;
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+pub mod host;
 #[allow(warnings)]
 pub mod intrinsics;
 pub mod io;
 pub mod mem;
 pub mod misc;
-// WIP
-// pub mod rt;
+pub mod num;
+pub mod pipeline;
 pub mod ptr;
+pub mod rt;
+pub mod segmented;
+pub mod select;
 pub mod shared;
+pub mod simd;
+pub mod spec;
+pub mod sync;
+pub mod texture;
 pub mod thread;
+pub mod tma;
+pub mod uniform;
 pub mod warp;
 
 mod float_ext;
@@ -45,3 +45,17 @@ pub fn clock() -> u64 {
     }
     clock
 }
+
+#[cfg(target_os = "cuda")]
+extern "C" {
+    /// Marks the loop containing the call with an unroll count, used by `#[unroll]` and `#[no_unroll]`.
+    /// The codegen turns it into `llvm.loop` metadata and removes the call.
+    #[doc(hidden)]
+    pub fn __nvvm_loop_unroll(count: u32);
+}
+
+/// Used by `#[unroll]` and `#[no_unroll]`, does nothing on the CPU.
+#[cfg(not(target_os = "cuda"))]
+#[doc(hidden)]
+#[inline(always)]
+pub unsafe fn __nvvm_loop_unroll(_count: u32) {}
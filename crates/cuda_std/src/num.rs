@@ -0,0 +1,360 @@
+//! Numeric types which are not part of core, usable in kernels and on the host.
+//!
+//! # Complex numbers
+//!
+//! [`Complex<f32>`](Complex) and [`Complex<f64>`](Complex) have the same layout as `num_complex::Complex`
+//! and `std::complex` (two floats, real part first), and the same fields as `cuComplex`/`cuDoubleComplex`
+//! (which are more aligned), so buffers of them can be shared with cuFFT and other CUDA libraries. With the
+//! `cust` feature they implement `DeviceCopy`.
+//!
+//! Arithmetic is implemented inline with the plain formulas instead of calling out to
+//! `__mulsc3`/`__divsc3` like C does, so it never produces libcalls (which do not exist on the GPU).
+//! The only difference is that products of infinities and NaNs are not special cased, which does not
+//! matter for FFTs or simulations. Division uses Smith's algorithm, which avoids overflow for large
+//! denominators. Transcendental functions use the [`GpuFloat`](crate::GpuFloat) functions of the
+//! parts, which map to libdevice.
+//!
+//! ```ignore
+//! use cuda_std::num::Complex;
+//!
+//! // multiplies every element of an FFT by the frequency response of a filter.
+//! #[kernel]
+//! pub unsafe fn apply_filter(spectrum: *mut Complex<f32>, response: &[Complex<f32>]) {
+//!     let i = thread::index_1d() as usize;
+//!     if let Some(r) = response.get(i) {
+//!         *spectrum.add(i) *= *r;
+//!     }
+//! }
+//! ```
+
+#[cfg(target_os = "cuda")]
+use crate::float::GpuFloat;
+use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// A complex number `re + im * i`, see the [module docs](self).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Complex<T> {
+    pub re: T,
+    pub im: T,
+}
+
+#[cfg(all(not(target_os = "cuda"), feature = "cust"))]
+unsafe impl<T: cust::memory::DeviceCopy> cust::memory::DeviceCopy for Complex<T> {}
+
+impl<T> Complex<T> {
+    #[inline]
+    pub const fn new(re: T, im: T) -> Self {
+        Self { re, im }
+    }
+}
+
+macro_rules! complex_impl {
+    ($($ty:ident),*) => {
+        $(
+            impl Complex<$ty> {
+                /// The imaginary unit `i`.
+                pub const I: Self = Self::new(0.0, 1.0);
+                pub const ONE: Self = Self::new(1.0, 0.0);
+                pub const ZERO: Self = Self::new(0.0, 0.0);
+
+                /// The number with an absolute value of `r` and an argument of `theta` radians.
+                #[inline]
+                pub fn from_polar(r: $ty, theta: $ty) -> Self {
+                    let (sin, cos) = theta.sin_cos();
+                    Self::new(r * cos, r * sin)
+                }
+
+                /// `e^(i * theta)`, the point of the unit circle at `theta` radians, which is the twiddle
+                /// factor of FFTs.
+                #[inline]
+                pub fn cis(theta: $ty) -> Self {
+                    Self::from_polar(1.0, theta)
+                }
+
+                /// The complex conjugate, `re - im * i`.
+                #[inline]
+                pub fn conj(self) -> Self {
+                    Self::new(self.re, -self.im)
+                }
+
+                /// The squared absolute value, `re^2 + im^2`. This is cheaper than [`abs`](Self::abs).
+                #[inline]
+                pub fn norm_sqr(self) -> $ty {
+                    self.re * self.re + self.im * self.im
+                }
+
+                /// The absolute value (magnitude), computed without overflowing or underflowing for large
+                /// or small parts.
+                #[inline]
+                pub fn abs(self) -> $ty {
+                    self.re.hypot(self.im)
+                }
+
+                /// The argument (phase) in radians, in `-π..=π`.
+                #[inline]
+                pub fn arg(self) -> $ty {
+                    self.im.atan2(self.re)
+                }
+
+                /// Returns `(abs, arg)`.
+                #[inline]
+                pub fn to_polar(self) -> ($ty, $ty) {
+                    (self.abs(), self.arg())
+                }
+
+                /// `1 / self`.
+                #[inline]
+                pub fn recip(self) -> Self {
+                    Self::ONE / self
+                }
+
+                /// `e^self`.
+                #[inline]
+                pub fn exp(self) -> Self {
+                    Self::from_polar(self.re.exp(), self.im)
+                }
+
+                /// The principal natural logarithm, with an imaginary part in `-π..=π`.
+                #[inline]
+                pub fn ln(self) -> Self {
+                    Self::new(self.abs().ln(), self.arg())
+                }
+
+                /// The principal square root, with a non-negative real part.
+                #[inline]
+                pub fn sqrt(self) -> Self {
+                    if self.re == 0.0 && self.im == 0.0 {
+                        return Self::ZERO;
+                    }
+                    let abs = self.abs();
+                    let re = ((abs + self.re) * 0.5).sqrt();
+                    let im = ((abs - self.re) * 0.5).sqrt();
+                    Self::new(re, im.copysign(self.im))
+                }
+
+                /// `self` raised to a real power, `NaN` parts for zero and negative powers.
+                #[inline]
+                pub fn powf(self, exp: $ty) -> Self {
+                    let (r, theta) = self.to_polar();
+                    Self::from_polar(r.powf(exp), theta * exp)
+                }
+
+                /// `self` raised to an integer power by repeated squaring, which is exact for small
+                /// gaussian integers.
+                #[inline]
+                pub fn powi(self, exp: i32) -> Self {
+                    let mut base = if exp < 0 { self.recip() } else { self };
+                    let mut exp = exp.unsigned_abs();
+                    let mut out = Self::ONE;
+                    while exp > 0 {
+                        if exp & 1 == 1 {
+                            out *= base;
+                        }
+                        base *= base;
+                        exp >>= 1;
+                    }
+                    out
+                }
+
+                #[inline]
+                pub fn sin(self) -> Self {
+                    let (sin, cos) = self.re.sin_cos();
+                    Self::new(sin * self.im.cosh(), cos * self.im.sinh())
+                }
+
+                #[inline]
+                pub fn cos(self) -> Self {
+                    let (sin, cos) = self.re.sin_cos();
+                    Self::new(cos * self.im.cosh(), -sin * self.im.sinh())
+                }
+
+                #[inline]
+                pub fn tan(self) -> Self {
+                    self.sin() / self.cos()
+                }
+
+                #[inline]
+                pub fn sinh(self) -> Self {
+                    let (sin, cos) = self.im.sin_cos();
+                    Self::new(self.re.sinh() * cos, self.re.cosh() * sin)
+                }
+
+                #[inline]
+                pub fn cosh(self) -> Self {
+                    let (sin, cos) = self.im.sin_cos();
+                    Self::new(self.re.cosh() * cos, self.re.sinh() * sin)
+                }
+
+                #[inline]
+                pub fn tanh(self) -> Self {
+                    self.sinh() / self.cosh()
+                }
+
+                /// Whether either part is `NaN`.
+                #[inline]
+                pub fn is_nan(self) -> bool {
+                    self.re.is_nan() || self.im.is_nan()
+                }
+            }
+
+            impl From<$ty> for Complex<$ty> {
+                #[inline]
+                fn from(re: $ty) -> Self {
+                    Self::new(re, 0.0)
+                }
+            }
+
+            impl Neg for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn neg(self) -> Self {
+                    Self::new(-self.re, -self.im)
+                }
+            }
+
+            impl Add for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn add(self, rhs: Self) -> Self {
+                    Self::new(self.re + rhs.re, self.im + rhs.im)
+                }
+            }
+
+            impl Sub for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn sub(self, rhs: Self) -> Self {
+                    Self::new(self.re - rhs.re, self.im - rhs.im)
+                }
+            }
+
+            impl Mul for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn mul(self, rhs: Self) -> Self {
+                    Self::new(
+                        self.re * rhs.re - self.im * rhs.im,
+                        self.re * rhs.im + self.im * rhs.re,
+                    )
+                }
+            }
+
+            impl Div for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn div(self, rhs: Self) -> Self {
+                    // Smith's algorithm, divides by the larger part first so the denominator does not
+                    // overflow.
+                    if rhs.re.abs() >= rhs.im.abs() {
+                        let ratio = rhs.im / rhs.re;
+                        let denom = rhs.re + rhs.im * ratio;
+                        Self::new(
+                            (self.re + self.im * ratio) / denom,
+                            (self.im - self.re * ratio) / denom,
+                        )
+                    } else {
+                        let ratio = rhs.re / rhs.im;
+                        let denom = rhs.re * ratio + rhs.im;
+                        Self::new(
+                            (self.re * ratio + self.im) / denom,
+                            (self.im * ratio - self.re) / denom,
+                        )
+                    }
+                }
+            }
+
+            impl Add<$ty> for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn add(self, rhs: $ty) -> Self {
+                    Self::new(self.re + rhs, self.im)
+                }
+            }
+
+            impl Sub<$ty> for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn sub(self, rhs: $ty) -> Self {
+                    Self::new(self.re - rhs, self.im)
+                }
+            }
+
+            impl Mul<$ty> for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn mul(self, rhs: $ty) -> Self {
+                    Self::new(self.re * rhs, self.im * rhs)
+                }
+            }
+
+            impl Div<$ty> for Complex<$ty> {
+                type Output = Self;
+
+                #[inline]
+                fn div(self, rhs: $ty) -> Self {
+                    Self::new(self.re / rhs, self.im / rhs)
+                }
+            }
+
+            impl Mul<Complex<$ty>> for $ty {
+                type Output = Complex<$ty>;
+
+                #[inline]
+                fn mul(self, rhs: Complex<$ty>) -> Complex<$ty> {
+                    rhs * self
+                }
+            }
+
+            complex_assign_ops! {
+                $ty;
+                AddAssign, add_assign, +;
+                SubAssign, sub_assign, -;
+                MulAssign, mul_assign, *;
+                DivAssign, div_assign, /;
+            }
+
+            impl core::iter::Sum for Complex<$ty> {
+                fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+                    iter.fold(Self::ZERO, |a, b| a + b)
+                }
+            }
+
+            impl core::iter::Product for Complex<$ty> {
+                fn product<I: Iterator<Item = Self>>(iter: I) -> Self {
+                    iter.fold(Self::ONE, |a, b| a * b)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! complex_assign_ops {
+    ($ty:ident; $($trait:ident, $fn:ident, $op:tt;)*) => {
+        $(
+            impl $trait for Complex<$ty> {
+                #[inline]
+                fn $fn(&mut self, rhs: Self) {
+                    *self = *self $op rhs;
+                }
+            }
+
+            impl $trait<$ty> for Complex<$ty> {
+                #[inline]
+                fn $fn(&mut self, rhs: $ty) {
+                    *self = *self $op rhs;
+                }
+            }
+        )*
+    };
+}
+
+complex_impl!(f32, f64);
@@ -0,0 +1,114 @@
+//! Asynchronous copies from global to shared memory (`cp.async`), the equivalent of the
+//! `__pipeline_memcpy_async`, `__pipeline_commit` and `__pipeline_wait_prior` primitives of CUDA C++.
+//!
+//! Copies are issued with [`memcpy_async`], grouped with [`commit`] and waited on with [`wait_prior`],
+//! which lets a block load the next tile of data while computing on the current one:
+//!
+//! ```ignore
+//! load_tile(0, buffers[0]);
+//! pipeline::commit();
+//! for t in 0..tiles {
+//!     if t + 1 < tiles {
+//!         load_tile(t + 1, buffers[(t + 1) % 2]);
+//!     }
+//!     pipeline::commit();
+//!     // wait for every group except the one just committed, which is the tile `t`.
+//!     pipeline::wait_prior(1);
+//!     thread::sync_threads();
+//!     compute(buffers[t % 2]);
+//!     thread::sync_threads();
+//! }
+//! ```
+//!
+//! A thread only waits for its own copies, so a block barrier is needed before reading data copied by
+//! other threads. Every function of this module requires compute capability 8.0 or higher, code which
+//! also targets older GPUs can use `#[cfg(target_feature = "sm_80")]` to fall back to regular loads.
+
+use crate::gpu_only;
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::ptr::{convert_generic_to_specific_address_space, AddressSpace};
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use core::mem::size_of;
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+macro_rules! cp_async {
+    ($dst:expr, $src:expr, $src_size:expr, $($size:literal),*) => {
+        match size_of::<T>() {
+            $(
+                $size => asm!(
+                    concat!("cp.async.ca.shared.global [{}], [{}], ", $size, ", {};"),
+                    in(reg64) $dst,
+                    in(reg64) $src,
+                    in(reg32) $src_size,
+                ),
+            )*
+            _ => panic!("memcpy_async only copies values of 4, 8 or 16 bytes"),
+        }
+    };
+}
+
+/// Starts copying `*src` in global memory to `*dst` in shared memory, without waiting for the copy to
+/// complete. The copy is part of the next group [`commit`]ted by this thread.
+///
+/// # Safety
+///
+/// - `T` must be 4, 8 or 16 bytes large, otherwise this panics.
+/// - `dst` must point to shared memory and `src` to global memory, both aligned to the size of `T`.
+/// - `*dst` must not be accessed until the copy was waited on.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn memcpy_async<T>(dst: *mut T, src: *const T) {
+    let dst = convert_generic_to_specific_address_space(dst as *const T, AddressSpace::Shared);
+    let size = size_of::<T>() as u32;
+    cp_async!(dst, src, size, 4, 8, 16);
+}
+
+/// Like [`memcpy_async`], but fills `*dst` with zero bytes instead of reading `src` if `valid` is false,
+/// which is how the edges of tiles are usually padded.
+///
+/// # Safety
+///
+/// The same as [`memcpy_async`], `src` must still be a valid global memory address (which is not read)
+/// if `valid` is false.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn memcpy_async_zfill<T>(dst: *mut T, src: *const T, valid: bool) {
+    let dst = convert_generic_to_specific_address_space(dst as *const T, AddressSpace::Shared);
+    let src_size = if valid { size_of::<T>() as u32 } else { 0 };
+    cp_async!(dst, src, src_size, 4, 8, 16);
+}
+
+/// Commits every copy issued by this thread since the last commit as a group.
+///
+/// # Safety
+///
+/// Requires compute capability 8.0 or higher.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn commit() {
+    asm!("cp.async.commit_group;");
+}
+
+/// Waits until at most `pending` of the most recently committed groups of this thread are still
+/// in progress, `wait_prior(0)` waits for every copy.
+///
+/// # Safety
+///
+/// - Requires compute capability 8.0 or higher.
+/// - `pending` must be at most 7, otherwise this panics. It should be a constant so that the
+/// branch on it is removed.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn wait_prior(pending: u32) {
+    match pending {
+        0 => asm!("cp.async.wait_group 0;"),
+        1 => asm!("cp.async.wait_group 1;"),
+        2 => asm!("cp.async.wait_group 2;"),
+        3 => asm!("cp.async.wait_group 3;"),
+        4 => asm!("cp.async.wait_group 4;"),
+        5 => asm!("cp.async.wait_group 5;"),
+        6 => asm!("cp.async.wait_group 6;"),
+        7 => asm!("cp.async.wait_group 7;"),
+        _ => panic!("wait_prior only supports up to 7 pending groups"),
+    }
+}
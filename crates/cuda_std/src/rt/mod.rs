@@ -1,3 +1,7 @@
+//! Bindings to the CUDA device runtime, used for launching kernels from other kernels (dynamic parallelism).
+//!
+//! Note that this module requires linking the PTX against `libcudadevrt`, the CUDA device runtime library.
+
 pub(crate) mod driver_types_sys;
 mod error;
 pub mod sys;
@@ -48,8 +52,8 @@ impl Stream {
     }
 
     #[doc(hidden)]
-    pub fn launch(&self, param_buf: *mut c_void) -> CudaResult<()> {
-        unsafe { cuda::cudaLaunchDeviceV2(param_buf, self.raw).to_result() }
+    pub unsafe fn launch(&self, param_buf: *mut c_void) -> CudaResult<()> {
+        cuda::cudaLaunchDeviceV2(param_buf, self.raw).to_result()
     }
 }
 
@@ -61,41 +65,87 @@ impl Drop for Stream {
     }
 }
 
+/// Waits until every kernel launched by this thread block (or any of its children) has finished executing.
+///
+/// Note that this is a very heavy operation and should be avoided when possible, it is
+/// deprecated for device code starting with CUDA 11.6.
+pub fn device_synchronize() -> CudaResult<()> {
+    unsafe { cuda::cudaDeviceSynchronize().to_result() }
+}
+
+/// Launches a kernel from device code (dynamic parallelism) on a [`Stream`]. This mirrors
+/// `cust::launch!`, but it is only usable inside of other kernels.
+///
+/// The syntax is `launch_device!(kernel<<<grid, block, shared_mem, stream>>>(params...))`.
+/// The grid and block sizes may be anything which is convertible to [`GridSize`] and [`BlockSize`],
+/// respectively.
+///
+/// Using dynamic parallelism requires the PTX to be linked against the CUDA device runtime
+/// (`libcudadevrt`) when it is loaded, see `CudaBuilder::dynamic_parallelism` in cuda_builder.
+///
+/// # Safety
+///
+/// Kernel launches are asynchronous, the caller must make sure that any memory passed to the
+/// child kernel stays valid until the child kernel finishes executing, and the parameters must
+/// match the parameters of the kernel.
+///
+/// # Examples
+///
+/// ```no_run
+/// #[kernel]
+/// pub unsafe fn child(a: *mut f32) {
+///     *a.add(thread::index_1d() as usize) *= 2.0;
+/// }
+///
+/// #[kernel]
+/// pub unsafe fn parent(a: *mut f32, n: u32) {
+///     if thread::first() {
+///         let stream = rt::Stream::new(rt::StreamFlags::NON_BLOCKING).unwrap();
+///         launch_device!(child<<<n / 32, 32, 0, stream>>>(a)).unwrap();
+///     }
+/// }
+/// ```
 #[macro_export]
-macro_rules! launch {
+macro_rules! launch_device {
     ($func:ident<<<$grid_dim:expr, $block_dim:expr, $smem_size:expr, $stream:ident>>>($($param:expr),* $(,)?)) => {{
-        let grid_dim = ::$crate::rt::GridDim::from($grid_dim);
-        let block_dim = ::$crate::rt::BlockDim::from($block_dim);
-        let mut buf = ::$crate::rt::sys::cudaGetParameterBufferV2(
-            &$func as *const _ as *const ::core::ffi::c_void,
-            ::$crate::rt::sys::dim3 {
+        let grid_dim = $crate::rt::GridSize::from($grid_dim);
+        let block_dim = $crate::rt::BlockSize::from($block_dim);
+        let buf = $crate::rt::sys::cudaGetParameterBufferV2(
+            $func as *const ::core::ffi::c_void,
+            $crate::rt::sys::dim3 {
                 x: grid_dim.x,
                 y: grid_dim.y,
                 z: grid_dim.z
             },
-            ::$crate::rt::sys::dim3 {
+            $crate::rt::sys::dim3 {
                 x: block_dim.x,
                 y: block_dim.y,
                 z: block_dim.z
             },
             $smem_size
         ) as *mut u8;
-        unsafe {
-            let mut offset = 0;
+
+        if buf.is_null() {
+            ::core::result::Result::Err($crate::rt::CudaError::LaunchOutOfResources)
+        } else {
+            // every parameter is placed at the next offset which satisfies its alignment.
+            let mut offset = 0usize;
             $(
                 let param = $param;
-                let size = ::core::mem::size_of_val(&param)
-                let mut buf_idx = (offset as f32 / size as f32).ceil() as usize + 1;
-                offset = buf_idx * size;
-                let ptr = &param as *const _ as *const u8;
-                let dst = buf.add(offset);
-                ::core::ptr::copy_nonoverlapping(&param as *const _ as *const u8, dst, size);
+                let align = ::core::mem::align_of_val(&param);
+                let size = ::core::mem::size_of_val(&param);
+                offset = (offset + align - 1) & !(align - 1);
+                ::core::ptr::copy_nonoverlapping(&param as *const _ as *const u8, buf.add(offset), size);
+                offset += size;
             )*
+            let _ = offset;
+
+            // type check the parameters against the kernel.
+            if false {
+                $func($($param),*);
+            }
+            $stream.launch(buf as *mut ::core::ffi::c_void)
         }
-        if false {
-            $func($($param),*);
-        }
-        $stream.launch(buf as *mut ::core::ffi::c_void).to_result()
     }};
 }
 
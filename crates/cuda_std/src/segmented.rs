@@ -0,0 +1,198 @@
+//! Segmented reductions and sorts, which process many variable-length pieces (segments) of one array
+//! independently, such as the particles of every cell of a grid, the edges of every vertex of a graph
+//! or the sequences of a ragged batch.
+//!
+//! Segments are described by offsets, like the rows of a CSR matrix: segment `i` is
+//! `data[offsets[i]..offsets[i + 1]]` (so there is one more offset than there are segments), see
+//! [`segment`] and [`segment_mut`]. The functions of this module process one segment with a whole
+//! block or warp, so kernels launch one block (or one warp) per segment. Blocks are the better choice
+//! for long segments, warps for short ones since a block per segment would leave most of its threads
+//! idle.
+//!
+//! Like the functions of [`collective`](crate::collective), these must be called by every thread of
+//! the block or warp.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! // the sum of every segment, launched with one block per segment.
+//! #[kernel]
+//! pub unsafe fn segment_sums(data: &[f32], offsets: &[u32], sums: *mut f32) {
+//!     let scratch = shared_array![f32; 32];
+//!     let i = thread::block_idx_x() as usize;
+//!     let sum = segmented::block_segment_reduce(segmented::segment(data, offsets, i), 0.0, scratch, |a, b| a + b);
+//!     if thread::thread_idx_x() == 0 {
+//!         *sums.add(i) = sum;
+//!     }
+//! }
+//!
+//! // sorts every segment in place, launched with one block per segment.
+//! #[kernel]
+//! pub unsafe fn sort_segments(keys: *mut u32, offsets: &[u32]) {
+//!     let i = thread::block_idx_x() as usize;
+//!     segmented::block_sort(segmented::segment_mut(keys, offsets, i));
+//! }
+//! ```
+
+use crate::gpu_only;
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::{
+    collective::{self, block_threads, linear_thread_idx},
+    thread,
+    warp::{self, WARP_SIZE},
+};
+
+/// Segment `i` of `data`, `data[offsets[i]..offsets[i + 1]]`.
+#[inline(always)]
+pub fn segment<'a, T>(data: &'a [T], offsets: &[u32], i: usize) -> &'a [T] {
+    &data[offsets[i] as usize..offsets[i + 1] as usize]
+}
+
+/// Segment `i` of the array at `data`, for outputs of kernels which are passed as pointers.
+///
+/// # Safety
+///
+/// `data` must be valid for reads and writes up to `offsets[i + 1]`, and the segment must not be
+/// accessed through other references while the returned slice is used (the threads of the block
+/// processing it may share it).
+#[inline(always)]
+pub unsafe fn segment_mut<'a, T>(data: *mut T, offsets: &[u32], i: usize) -> &'a mut [T] {
+    let start = offsets[i] as usize;
+    let end = offsets[i + 1] as usize;
+    core::slice::from_raw_parts_mut(data.add(start), end - start)
+}
+
+/// Reduces `segment` with every thread of the block using `op`, every thread gets the result.
+/// `identity` is the result for empty segments, it must not change values it is combined with (such
+/// as `0` for sums).
+///
+/// Every thread combines a strided part of the segment in order before the block combines them, so
+/// the result only depends on the block size, see [the docs of `collective`](crate::collective#determinism).
+///
+/// # Safety
+///
+/// The same as [`collective::block_reduce`](crate::collective::block_reduce), and every thread must
+/// pass the same `segment`.
+#[gpu_only]
+pub unsafe fn block_segment_reduce<T: Copy>(
+    segment: &[T],
+    identity: T,
+    scratch: *mut T,
+    op: impl Fn(T, T) -> T,
+) -> T {
+    let threads = block_threads() as usize;
+    let mut i = linear_thread_idx() as usize;
+    let mut value = identity;
+    while i < segment.len() {
+        value = op(value, segment[i]);
+        i += threads;
+    }
+    collective::block_reduce(value, scratch, op)
+}
+
+/// Reduces `segment` with every thread of the warp using `op`, every thread gets the result. See
+/// [`block_segment_reduce`] for `identity`.
+///
+/// # Safety
+///
+/// The same as [`collective::warp_reduce`](crate::collective::warp_reduce), and every thread must
+/// pass the same `segment`.
+#[gpu_only]
+pub unsafe fn warp_segment_reduce<T: Copy>(
+    segment: &[T],
+    identity: T,
+    op: impl Fn(T, T) -> T,
+) -> T {
+    let mut i = warp::lane_id() as usize;
+    let mut value = identity;
+    while i < segment.len() {
+        value = op(value, segment[i]);
+        i += WARP_SIZE as usize;
+    }
+    collective::warp_reduce(value, op)
+}
+
+/// Runs the compare-and-swap passes of a bitonic sorting network over `len` elements with every thread
+/// of the block, `compare_swap(i, j)` with `i < j` must put the smaller of the two elements at `i`.
+///
+/// This is the variant of the network in which every comparison sorts in the same direction (the
+/// first pass of every merge compares mirrored elements instead of reversing half of the sequence),
+/// so the length can be padded to a power of two with elements that are larger than any other, and
+/// comparisons with them are skipped.
+#[gpu_only]
+unsafe fn bitonic_sort(len: usize, compare_swap: impl FnMut(usize, usize)) {
+    let mut compare_swap = compare_swap;
+    let threads = block_threads() as usize;
+    let tid = linear_thread_idx() as usize;
+    let padded = len.next_power_of_two();
+
+    let mut size = 2;
+    while size <= padded {
+        let mut stride = size / 2;
+        let mut mirrored = true;
+        while stride > 0 {
+            let mut i = tid;
+            while i < padded {
+                let j = if mirrored { i ^ (size - 1) } else { i ^ stride };
+                if j > i && j < len {
+                    compare_swap(i, j);
+                }
+                i += threads;
+            }
+            thread::sync_threads();
+            mirrored = false;
+            stride /= 2;
+        }
+        size *= 2;
+    }
+}
+
+/// Sorts `keys` in place in ascending order with every thread of the block. The sort is not stable.
+///
+/// This sorts `keys` wherever it is, segments which fit in shared memory are sorted faster if they are
+/// copied there first.
+///
+/// # Safety
+///
+/// - Every thread of the block must call this function with the same `keys`, it contains
+/// [`sync_threads`](crate::thread::sync_threads) calls.
+/// - `keys` must not be accessed by other blocks while it is sorted.
+#[gpu_only]
+pub unsafe fn block_sort<K: Copy + PartialOrd>(keys: &mut [K]) {
+    // every thread of the block writes to the slice, so go through a raw pointer.
+    let len = keys.len();
+    let keys = keys.as_mut_ptr();
+    bitonic_sort(len, |i, j| {
+        let (a, b) = (*keys.add(i), *keys.add(j));
+        if b < a {
+            *keys.add(i) = b;
+            *keys.add(j) = a;
+        }
+    });
+}
+
+/// Sorts `keys` in place in ascending order with every thread of the block, and moves the elements of
+/// `values` along with them. The sort is not stable.
+///
+/// # Safety
+///
+/// The same as [`block_sort`], for both `keys` and `values`.
+///
+/// # Panics
+///
+/// Panics if `keys` and `values` have different lengths.
+#[gpu_only]
+pub unsafe fn block_sort_by_key<K: Copy + PartialOrd, V: Copy>(keys: &mut [K], values: &mut [V]) {
+    assert_eq!(keys.len(), values.len());
+    let len = keys.len();
+    let keys = keys.as_mut_ptr();
+    let values = values.as_mut_ptr();
+    bitonic_sort(len, |i, j| {
+        let (a, b) = (*keys.add(i), *keys.add(j));
+        if b < a {
+            *keys.add(i) = b;
+            *keys.add(j) = a;
+            core::ptr::swap(values.add(i), values.add(j));
+        }
+    });
+}
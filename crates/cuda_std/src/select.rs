@@ -0,0 +1,166 @@
+//! Branchless selection, minimum, maximum and clamping, for inner loops where threads of a warp would
+//! otherwise diverge on data-dependent branches.
+//!
+//! NVVM usually turns small `if`s into predicated instructions by itself, but not reliably: it keeps
+//! branches around loads, calls and larger expressions, and `Ord::min` on integers goes through
+//! comparisons it does not always fold. The functions of this module lower to a single instruction on
+//! the GPU, `selp` for [`select`] and `min`/`max` for [`min`], [`max`] and [`clamp`], so both values
+//! are always computed and no thread waits for the others:
+//!
+//! ```ignore
+//! use cuda_std::select::{clamp, select};
+//!
+//! // accumulates the contributions of lights, skipping occluded ones without a branch.
+//! fn shade(lights: &[Light], point: Vec3) -> f32 {
+//!     lights.iter().fold(0.0, |sum, light| {
+//!         let contribution = clamp(light.intensity(point), 0.0, light.max_intensity);
+//!         sum + select(light.occluded(point), 0.0, contribution)
+//!     })
+//! }
+//! ```
+//!
+//! Because both values are computed, this only pays off when they are cheap; expensive alternatives
+//! are better left to a branch, which skips the work when the whole warp takes the same side.
+//!
+//! On the host these are the ordinary `if` and `min`/`max`, so code shared with the CPU can use them.
+
+/// Types which can be selected between without a branch, see [`select`].
+pub trait Select: Copy {
+    /// `if cond { a } else { b }`, computed without a branch.
+    fn select(cond: bool, a: Self, b: Self) -> Self;
+}
+
+/// Types with a minimum and maximum instruction, see [`min`] and [`max`].
+pub trait MinMax: Copy {
+    /// The smaller of `a` and `b`. For floats, if one of them is NaN the other one is returned.
+    fn min(a: Self, b: Self) -> Self;
+    /// The larger of `a` and `b`. For floats, if one of them is NaN the other one is returned.
+    fn max(a: Self, b: Self) -> Self;
+}
+
+macro_rules! select_impls {
+    ($($ty:ty => $reg:ident, $bits:literal, $suffix:literal;)*) => {
+        $(
+            impl Select for $ty {
+                #[inline(always)]
+                fn select(cond: bool, a: Self, b: Self) -> Self {
+                    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+                    let out = if cond { a } else { b };
+                    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+                    let out = {
+                        let out: $ty;
+                        // inline asm has no predicate registers, so the condition is converted to a
+                        // predicate in a scope of its own.
+                        unsafe {
+                            asm!(
+                                concat!(
+                                    "{{ .reg .pred p; setp.ne.b32 p, {}, 0; selp.b", $bits,
+                                    " {}, {}, {}, p; }}"
+                                ),
+                                in(reg32) cond as u32,
+                                out($reg) out,
+                                in($reg) a,
+                                in($reg) b,
+                            );
+                        }
+                        out
+                    };
+                    out
+                }
+            }
+
+            impl MinMax for $ty {
+                #[inline(always)]
+                fn min(a: Self, b: Self) -> Self {
+                    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+                    let out = a.min(b);
+                    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+                    let out = {
+                        let out: $ty;
+                        unsafe {
+                            asm!(
+                                concat!("min.", $suffix, " {}, {}, {};"),
+                                out($reg) out,
+                                in($reg) a,
+                                in($reg) b,
+                            );
+                        }
+                        out
+                    };
+                    out
+                }
+
+                #[inline(always)]
+                fn max(a: Self, b: Self) -> Self {
+                    #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+                    let out = a.max(b);
+                    #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+                    let out = {
+                        let out: $ty;
+                        unsafe {
+                            asm!(
+                                concat!("max.", $suffix, " {}, {}, {};"),
+                                out($reg) out,
+                                in($reg) a,
+                                in($reg) b,
+                            );
+                        }
+                        out
+                    };
+                    out
+                }
+            }
+        )*
+    };
+}
+
+select_impls! {
+    i32 => reg32, "32", "s32";
+    u32 => reg32, "32", "u32";
+    f32 => reg32, "32", "f32";
+    i64 => reg64, "64", "s64";
+    u64 => reg64, "64", "u64";
+    f64 => reg64, "64", "f64";
+}
+
+/// Selects every element on its own, with the same condition.
+impl<T: Select, const N: usize> Select for [T; N] {
+    #[inline(always)]
+    fn select(cond: bool, a: Self, b: Self) -> Self {
+        let mut out = b;
+        for (out, &a) in out.iter_mut().zip(a.iter()) {
+            *out = T::select(cond, a, *out);
+        }
+        out
+    }
+}
+
+/// `if cond { a } else { b }`, computed without a branch (`selp` on the GPU).
+#[inline(always)]
+pub fn select<T: Select>(cond: bool, a: T, b: T) -> T {
+    T::select(cond, a, b)
+}
+
+/// The smaller of `a` and `b`, computed without a branch (`min` on the GPU). For floats, if one of
+/// them is NaN the other one is returned.
+#[inline(always)]
+pub fn min<T: MinMax>(a: T, b: T) -> T {
+    T::min(a, b)
+}
+
+/// The larger of `a` and `b`, computed without a branch (`max` on the GPU). For floats, if one of
+/// them is NaN the other one is returned.
+#[inline(always)]
+pub fn max<T: MinMax>(a: T, b: T) -> T {
+    T::max(a, b)
+}
+
+/// Clamps `x` to `lo..=hi` with a `max` and a `min`, without a branch.
+///
+/// Unlike `Ord::clamp` and `f32::clamp` this does not panic if `lo > hi`, the result is then `hi`.
+/// A NaN `x` becomes `lo`. For clamping floats to `0.0..=1.0`, [`fast::saturate`](crate::fast::saturate)
+/// is a single instruction.
+#[inline(always)]
+pub fn clamp<T: MinMax>(x: T, lo: T, hi: T) -> T {
+    T::min(T::max(x, lo), hi)
+}
@@ -0,0 +1,168 @@
+//! SIMD-within-a-register intrinsics, which operate on several small integers packed into a single
+//! 32-bit integer: four bytes or two 16-bit halves.
+//!
+//! Packed integers are stored in little endian order, the first element is in the lowest bits of
+//! the integer, which is how an array of bytes read as an integer is laid out:
+//!
+//! ```ignore
+//! use cuda_std::simd;
+//!
+//! // the int8 dot product of a row of a quantized matrix and a vector, 4 elements at a time.
+//! pub fn dot_i8(row: &[[i8; 4]], vector: &[[i8; 4]]) -> i32 {
+//!     let pack = |x: [i8; 4]| i32::from_le_bytes(x.map(|b| b as u8));
+//!     row.iter()
+//!         .zip(vector)
+//!         .fold(0, |acc, (a, b)| simd::dp4a(pack(*a), pack(*b), acc))
+//! }
+//! ```
+//!
+//! The per-lane operations (`vadd4`, `vabsdiffu4`, `vmaxu2`, ...) use the SIMD video instructions
+//! and have the same names as their CUDA C++ counterparts. Signed variants end with `s4` or `s2`
+//! and unsigned ones with `u4` or `u2`, saturating additions and subtractions end with `ss` or `us`
+//! (for signed and unsigned saturation) and wrap otherwise.
+
+use crate::gpu_only;
+
+macro_rules! dot_products {
+    ($($(#[$attr:meta])* $name:ident, $ty:ty => $op:literal;)*) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name(a: $ty, b: $ty, c: $ty) -> $ty {
+                let out;
+                unsafe {
+                    asm!(
+                        concat!($op, " {}, {}, {}, {};"),
+                        out(reg32) out,
+                        in(reg32) a,
+                        in(reg32) b,
+                        in(reg32) c,
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+dot_products! {
+    /// The dot product of the four signed bytes of `a` and `b`, plus `c` (`__dp4a` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp4a, i32 => "dp4a.s32.s32";
+    /// The dot product of the four unsigned bytes of `a` and `b`, plus `c` (`__dp4a` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp4a_u32, u32 => "dp4a.u32.u32";
+    /// The dot product of the two signed 16-bit halves of `a` and the two lower signed bytes of `b`,
+    /// plus `c` (`__dp2a_lo` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp2a_lo, i32 => "dp2a.lo.s32.s32";
+    /// The dot product of the two signed 16-bit halves of `a` and the two upper signed bytes of `b`,
+    /// plus `c` (`__dp2a_hi` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp2a_hi, i32 => "dp2a.hi.s32.s32";
+    /// The dot product of the two unsigned 16-bit halves of `a` and the two lower unsigned bytes of
+    /// `b`, plus `c` (`__dp2a_lo` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp2a_lo_u32, u32 => "dp2a.lo.u32.u32";
+    /// The dot product of the two unsigned 16-bit halves of `a` and the two upper unsigned bytes of
+    /// `b`, plus `c` (`__dp2a_hi` in CUDA C++).
+    ///
+    /// Requires compute capability 6.1 or higher.
+    dp2a_hi_u32, u32 => "dp2a.hi.u32.u32";
+}
+
+macro_rules! lane_ops {
+    ($lanes:literal: $($name:ident => $op:literal, $what:literal;)*) => {
+        $(
+            #[doc = concat!("The per-", $lanes, " ", $what, " of `a` and `b` (`__", stringify!($name), "` in CUDA C++).")]
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name(a: u32, b: u32) -> u32 {
+                let out;
+                unsafe {
+                    asm!(
+                        concat!($op, " {}, {}, {}, 0;"),
+                        out(reg32) out,
+                        in(reg32) a,
+                        in(reg32) b,
+                    );
+                }
+                out
+            }
+        )*
+    };
+}
+
+lane_ops! {
+    "byte":
+    vadd4 => "vadd4.u32.u32.u32", "wrapping sum";
+    vaddss4 => "vadd4.s32.s32.s32.sat", "signed saturating sum";
+    vaddus4 => "vadd4.u32.u32.u32.sat", "unsigned saturating sum";
+    vsub4 => "vsub4.u32.u32.u32", "wrapping difference";
+    vsubss4 => "vsub4.s32.s32.s32.sat", "signed saturating difference";
+    vsubus4 => "vsub4.u32.u32.u32.sat", "unsigned saturating difference";
+    vavgu4 => "vavrg4.u32.u32.u32", "unsigned average, rounded up,";
+    vabsdiffu4 => "vabsdiff4.u32.u32.u32", "unsigned absolute difference";
+    vabsdiffs4 => "vabsdiff4.u32.s32.s32", "signed absolute difference";
+    vmaxu4 => "vmax4.u32.u32.u32", "unsigned maximum";
+    vmaxs4 => "vmax4.s32.s32.s32", "signed maximum";
+    vminu4 => "vmin4.u32.u32.u32", "unsigned minimum";
+    vmins4 => "vmin4.s32.s32.s32", "signed minimum";
+}
+
+lane_ops! {
+    "half":
+    vadd2 => "vadd2.u32.u32.u32", "wrapping sum";
+    vaddss2 => "vadd2.s32.s32.s32.sat", "signed saturating sum";
+    vaddus2 => "vadd2.u32.u32.u32.sat", "unsigned saturating sum";
+    vsub2 => "vsub2.u32.u32.u32", "wrapping difference";
+    vsubss2 => "vsub2.s32.s32.s32.sat", "signed saturating difference";
+    vsubus2 => "vsub2.u32.u32.u32.sat", "unsigned saturating difference";
+    vavgu2 => "vavrg2.u32.u32.u32", "unsigned average, rounded up,";
+    vabsdiffu2 => "vabsdiff2.u32.u32.u32", "unsigned absolute difference";
+    vabsdiffs2 => "vabsdiff2.u32.s32.s32", "signed absolute difference";
+    vmaxu2 => "vmax2.u32.u32.u32", "unsigned maximum";
+    vmaxs2 => "vmax2.s32.s32.s32", "signed maximum";
+    vminu2 => "vmin2.u32.u32.u32", "unsigned minimum";
+    vmins2 => "vmin2.s32.s32.s32", "signed minimum";
+}
+
+/// The sum of the absolute differences of the four unsigned bytes of `a` and `b` (`__vsadu4` in
+/// CUDA C++), as used by block matching.
+#[gpu_only]
+#[inline(always)]
+pub fn vsadu4(a: u32, b: u32) -> u32 {
+    let out;
+    unsafe {
+        asm!(
+            "vabsdiff4.u32.u32.u32.add {}, {}, {}, 0;",
+            out(reg32) out,
+            in(reg32) a,
+            in(reg32) b,
+        );
+    }
+    out
+}
+
+/// The sum of the absolute differences of the two unsigned 16-bit halves of `a` and `b`
+/// (`__vsadu2` in CUDA C++).
+#[gpu_only]
+#[inline(always)]
+pub fn vsadu2(a: u32, b: u32) -> u32 {
+    let out;
+    unsafe {
+        asm!(
+            "vabsdiff2.u32.u32.u32.add {}, {}, {}, 0;",
+            out(reg32) out,
+            in(reg32) a,
+            in(reg32) b,
+        );
+    }
+    out
+}
@@ -0,0 +1,70 @@
+//! Specialization constants, values which are fixed when the PTX is loaded instead of when it is built.
+//!
+//! Tuning parameters such as tile sizes or feature flags are usually either constants, which need a rebuild of
+//! the PTX for every value, or kernel parameters, which cost a register and keep the compiler from unrolling
+//! loops over them. A specialization constant is a global in the constant address space with a known name, whose
+//! initializer the host replaces in the PTX text before loading it (with `cust::module::Specialization`), so that
+//! the JIT compiler sees the chosen value:
+//!
+//! ```no_run
+//! spec_constant! {
+//!     /// The number of elements every thread handles.
+//!     pub static ITEMS_PER_THREAD: u32 = 4;
+//! }
+//!
+//! #[kernel]
+//! pub unsafe fn scale(data: *mut f32, len: usize, factor: f32) {
+//!     let items = ITEMS_PER_THREAD.get() as usize;
+//!     let start = thread::index_1d() as usize * items;
+//!     for i in start..(start + items).min(len) {
+//!         *data.add(i) *= factor;
+//!     }
+//! }
+//! ```
+//!
+//! ```ignore
+//! // host
+//! let ptx = Specialization::new().set("ITEMS_PER_THREAD", 8u32).apply(PTX)?;
+//! let module = Module::from_str(ptx)?;
+//! ```
+//!
+//! The value in the source is used if the PTX is loaded without specializing it, so it should be a sensible
+//! default. Specialization constants must have the same layout on the host and the device, which is the case for
+//! integers, floats, `bool` and `#[repr(C)]` structs of them.
+
+/// A value which can be replaced when the PTX is loaded, declared with [`spec_constant!`](crate::spec_constant).
+///
+/// The value is always read from memory, since the initializer of the static is only the default.
+#[repr(transparent)]
+pub struct SpecConstant<T>(T);
+
+impl<T: Copy> SpecConstant<T> {
+    /// Creates a specialization constant with a default value, used through [`spec_constant!`](crate::spec_constant).
+    pub const fn new(default: T) -> Self {
+        Self(default)
+    }
+
+    /// The value of the constant, which is the one set by the host if the PTX was specialized.
+    #[inline(always)]
+    pub fn get(&self) -> T {
+        // the compiler must not fold the initializer into the code, it is replaced after compiling.
+        unsafe { core::ptr::read_volatile(&self.0) }
+    }
+}
+
+/// Declares [`SpecConstant`]s, statics in the constant address space which keep their name in the PTX, so that
+/// the host can set their values when loading it. See the [`spec`](crate::spec) module.
+///
+/// The names are global to the PTX module (like `#[no_mangle]` functions), so they should be unique across the
+/// crate and its dependencies.
+#[macro_export]
+macro_rules! spec_constant {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty = $default:expr;)*) => {
+        $(
+            $(#[$attr])*
+            #[no_mangle]
+            #[$crate::address_space(constant)]
+            $vis static $name: $crate::spec::SpecConstant<$ty> = $crate::spec::SpecConstant::new($default);
+        )*
+    };
+}
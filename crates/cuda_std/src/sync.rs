@@ -0,0 +1,256 @@
+//! One-time initialization in device code, such as filling a lookup table the first time a kernel
+//! needs it.
+//!
+//! [`Once`] runs a closure exactly once no matter how many threads call it, the threads which lose
+//! the race wait until the closure finished and see everything it wrote. [`Lazy`] builds on it to
+//! hold a value which is computed on first use, usually in a `static`:
+//!
+//! ```no_run
+//! use cuda_std::{kernel, sync::Lazy, thread};
+//!
+//! static SAMPLE_PATTERN: Lazy<[f32; 64]> = Lazy::new(|| {
+//!     let mut pattern = [0.0; 64];
+//!     for (i, x) in pattern.iter_mut().enumerate() {
+//!         // the van der Corput sequence.
+//!         *x = (i as u32).reverse_bits() as f32 / 4294967296.0;
+//!     }
+//!     pattern
+//! });
+//!
+//! #[kernel]
+//! pub unsafe fn jitter(out: *mut f32) {
+//!     let idx = thread::index_1d() as usize;
+//!     *out.add(idx) = SAMPLE_PATTERN[idx % 64];
+//! }
+//! ```
+//!
+//! Statics in global memory are zeroed when the module is loaded and keep their value between
+//! launches, so the table is computed by the first launch using it. Shared memory is not
+//! initialized, so a `Once` must not be put there. Shared lookup tables are filled with
+//! [`block_once`] instead, which runs the closure on the first thread of the block and waits for it
+//! with a barrier.
+//!
+//! # Deadlocks
+//!
+//! The threads waiting for the closure spin until it finished. Before compute_70, the threads of a
+//! warp are not scheduled independently, so if a lane waits while another lane of the same warp
+//! runs the closure, the warp may spin forever. On older GPUs, only call [`Once::call_once`] from one
+//! lane of every warp, or make sure the value was initialized by an earlier launch.
+//!
+//! On the host, the state is a regular atomic, so these types also work with
+//! [`host::emulate`](crate::host::emulate) and in CPU code.
+
+use crate::thread;
+use core::{
+    cell::{Cell, UnsafeCell},
+    fmt,
+    mem::MaybeUninit,
+    ops::Deref,
+};
+
+const INCOMPLETE: u32 = 0;
+const RUNNING: u32 = 1;
+const COMPLETE: u32 = 2;
+
+/// Runs a closure exactly once across every thread of every block, like `std::sync::Once`.
+///
+/// A `Once` must be in global memory and start out as [`Once::new`], which is the case for
+/// statics.
+pub struct Once {
+    state: UnsafeCell<u32>,
+}
+
+unsafe impl Sync for Once {}
+unsafe impl Send for Once {}
+
+impl fmt::Debug for Once {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Once")
+            .field("completed", &self.is_completed())
+            .finish()
+    }
+}
+
+impl Default for Once {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Once {
+    /// A `Once` whose closure has not run yet.
+    pub const fn new() -> Self {
+        Self {
+            state: UnsafeCell::new(INCOMPLETE),
+        }
+    }
+
+    /// Whether a closure passed to [`call_once`](Self::call_once) finished. Once this returns true,
+    /// everything the closure wrote is visible to the calling thread.
+    #[inline]
+    pub fn is_completed(&self) -> bool {
+        let completed = load(self.state.get()) == COMPLETE;
+        if completed {
+            // keeps the reads of the initialized data after the read of the state.
+            thread::device_fence();
+        }
+        completed
+    }
+
+    /// Runs `f` if no thread ran a closure of this `Once` yet, otherwise waits until that closure
+    /// finished. When this returns, the closure has finished and its writes are visible to the
+    /// calling thread.
+    ///
+    /// If the closure panics, the threads waiting for it wait forever (on the GPU, the panic
+    /// aborts the kernel).
+    #[inline]
+    pub fn call_once(&self, f: impl FnOnce()) {
+        if self.is_completed() {
+            return;
+        }
+        self.call_once_slow(f);
+    }
+
+    #[cold]
+    fn call_once_slow(&self, f: impl FnOnce()) {
+        let state = self.state.get();
+        if compare_exchange(state, INCOMPLETE, RUNNING).is_ok() {
+            f();
+            // makes the writes of the closure visible before the state.
+            thread::device_fence();
+            store(state, COMPLETE);
+        } else {
+            while load(state) != COMPLETE {
+                core::hint::spin_loop();
+            }
+            thread::device_fence();
+        }
+    }
+}
+
+/// Runs `f` on the first thread of the block, then waits until every thread of the block reached
+/// this point, so that what `f` wrote (usually a table in shared memory) is visible to the whole
+/// block.
+///
+/// Every thread of the block must call this, it contains a
+/// [`sync_threads`](crate::thread::sync_threads).
+#[inline]
+pub fn block_once(f: impl FnOnce()) {
+    if thread::thread_idx() == vek::Vec3::zero() {
+        f();
+    }
+    thread::sync_threads();
+}
+
+/// A value which is computed by the first thread to use it, like `std::lazy::SyncLazy`. See the
+/// [module docs](self).
+pub struct Lazy<T, F = fn() -> T> {
+    once: Once,
+    value: UnsafeCell<MaybeUninit<T>>,
+    init: Cell<Option<F>>,
+}
+
+// the value is only written by the thread running the `Once`, which is also the only one taking
+// `init`.
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T: fmt::Debug, F> fmt::Debug for Lazy<T, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_tuple("Lazy");
+        match Self::get(self) {
+            Some(value) => debug.field(value),
+            None => debug.field(&format_args!("<uninit>")),
+        };
+        debug.finish()
+    }
+}
+
+impl<T, F> Lazy<T, F> {
+    /// A value which is computed with `init` when it is first used.
+    pub const fn new(init: F) -> Self {
+        Self {
+            once: Once::new(),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+            init: Cell::new(Some(init)),
+        }
+    }
+
+    /// The value, `None` if it was not computed yet.
+    pub fn get(this: &Self) -> Option<&T> {
+        if this.once.is_completed() {
+            Some(unsafe { (*this.value.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Lazy<T, F> {
+    /// Computes the value if no thread did yet (waiting if another thread is computing it) and
+    /// returns it. This is the same as dereferencing the `Lazy`.
+    #[inline]
+    pub fn force(this: &Self) -> &T {
+        this.once.call_once(|| {
+            let init = this.init.take().expect("`Lazy` was already initialized");
+            unsafe { (*this.value.get()).as_mut_ptr().write(init()) };
+        });
+        unsafe { (*this.value.get()).assume_init_ref() }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        Self::force(self)
+    }
+}
+
+impl<T, F> Drop for Lazy<T, F> {
+    fn drop(&mut self) {
+        if self.once.is_completed() {
+            unsafe { self.value.get_mut().as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn load(ptr: *mut u32) -> u32 {
+    unsafe { ptr.read_volatile() }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn store(ptr: *mut u32, value: u32) {
+    unsafe { ptr.write_volatile(value) }
+}
+
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+fn compare_exchange(ptr: *mut u32, current: u32, new: u32) -> Result<u32, u32> {
+    unsafe { crate::atomic::compare_exchange(ptr, current, new) }
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn atomic<'a>(ptr: *mut u32) -> &'a core::sync::atomic::AtomicU32 {
+    // `AtomicU32` has the same layout as `u32`.
+    unsafe { &*(ptr as *const core::sync::atomic::AtomicU32) }
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn load(ptr: *mut u32) -> u32 {
+    atomic(ptr).load(core::sync::atomic::Ordering::Acquire)
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn store(ptr: *mut u32, value: u32) {
+    atomic(ptr).store(value, core::sync::atomic::Ordering::Release)
+}
+
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+fn compare_exchange(ptr: *mut u32, current: u32, new: u32) -> Result<u32, u32> {
+    use core::sync::atomic::Ordering;
+    atomic(ptr).compare_exchange(current, new, Ordering::AcqRel, Ordering::Acquire)
+}
@@ -0,0 +1,144 @@
+//! Sampling texture objects created on the host, such as with cust's `Texture`.
+//!
+//! Texture reads go through the texture cache and hardware which filters between texels,
+//! wraps or clamps out of bounds coordinates and converts the texel format to floats, which
+//! makes them the fastest way to sample images with irregular access patterns.
+//!
+//! Kernels take the handle of a texture object (`Texture::handle` in cust) as a `u64` and wrap it
+//! in a [`TextureObject`]:
+//!
+//! ```ignore
+//! use cuda_std::texture::TextureObject;
+//!
+//! #[kernel]
+//! pub unsafe fn resample(texture: u64, out: *mut [f32; 4], width: u32, height: u32) {
+//!     let idx = thread::index_2d();
+//!     if idx.x >= width || idx.y >= height {
+//!         return;
+//!     }
+//!     let texture = TextureObject::from_handle(texture);
+//!     // sample in the middle of the texel, with normalized coordinates.
+//!     let u = (idx.x as f32 + 0.5) / width as f32;
+//!     let v = (idx.y as f32 + 0.5) / height as f32;
+//!     *out.add((idx.y * width + idx.x) as usize) = texture.sample_2d(u, v);
+//! }
+//! ```
+//!
+//! Samples are always four floats, of which only the channels of the texture format are meaningful.
+//! Textures of integers must therefore not be created with `READ_AS_INTEGER`, their values are then
+//! converted to floats in `[0, 1]` (or `[-1, 1]` if signed).
+
+use crate::gpu_only;
+
+/// A texture object, the device side of cust's `Texture`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(transparent)]
+pub struct TextureObject(u64);
+
+impl TextureObject {
+    /// Wraps the handle of a texture object created on the host.
+    pub const fn from_handle(handle: u64) -> Self {
+        Self(handle)
+    }
+
+    /// The handle of the texture object.
+    pub const fn handle(self) -> u64 {
+        self.0
+    }
+
+    /// Samples a one-dimensional texture at `x`.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a texture object which is alive and was created in the current context,
+    /// with an array of one dimension.
+    #[gpu_only]
+    #[inline(always)]
+    pub unsafe fn sample_1d(self, x: f32) -> [f32; 4] {
+        let (r, g, b, a): (f32, f32, f32, f32);
+        asm!(
+            "tex.1d.v4.f32.f32 {{{}, {}, {}, {}}}, [{}, {{{}}}];",
+            out(reg32) r,
+            out(reg32) g,
+            out(reg32) b,
+            out(reg32) a,
+            in(reg64) self.0,
+            in(reg32) x,
+        );
+        [r, g, b, a]
+    }
+
+    /// Samples a two-dimensional texture at `(x, y)`.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a texture object which is alive and was created in the current context,
+    /// with an array of two dimensions.
+    #[gpu_only]
+    #[inline(always)]
+    pub unsafe fn sample_2d(self, x: f32, y: f32) -> [f32; 4] {
+        let (r, g, b, a): (f32, f32, f32, f32);
+        asm!(
+            "tex.2d.v4.f32.f32 {{{}, {}, {}, {}}}, [{}, {{{}, {}}}];",
+            out(reg32) r,
+            out(reg32) g,
+            out(reg32) b,
+            out(reg32) a,
+            in(reg64) self.0,
+            in(reg32) x,
+            in(reg32) y,
+        );
+        [r, g, b, a]
+    }
+
+    /// Samples a two-dimensional texture at `(x, y)` from the mipmap level `level`, which is
+    /// fractional for filtering between two levels.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a texture object which is alive and was created in the current context,
+    /// with a (mipmapped) array of two dimensions.
+    #[gpu_only]
+    #[inline(always)]
+    pub unsafe fn sample_2d_level(self, x: f32, y: f32, level: f32) -> [f32; 4] {
+        let (r, g, b, a): (f32, f32, f32, f32);
+        asm!(
+            "tex.level.2d.v4.f32.f32 {{{}, {}, {}, {}}}, [{}, {{{}, {}}}], {};",
+            out(reg32) r,
+            out(reg32) g,
+            out(reg32) b,
+            out(reg32) a,
+            in(reg64) self.0,
+            in(reg32) x,
+            in(reg32) y,
+            in(reg32) level,
+        );
+        [r, g, b, a]
+    }
+
+    /// Samples a three-dimensional texture at `(x, y, z)`.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be a texture object which is alive and was created in the current context,
+    /// with an array of three dimensions.
+    #[gpu_only]
+    #[inline(always)]
+    pub unsafe fn sample_3d(self, x: f32, y: f32, z: f32) -> [f32; 4] {
+        let (r, g, b, a): (f32, f32, f32, f32);
+        // the coordinates of 3D textures are a vector of four, the last one is ignored.
+        asm!(
+            "tex.3d.v4.f32.f32 {{{}, {}, {}, {}}}, [{}, {{{}, {}, {}, {}}}];",
+            out(reg32) r,
+            out(reg32) g,
+            out(reg32) b,
+            out(reg32) a,
+            in(reg64) self.0,
+            in(reg32) x,
+            in(reg32) y,
+            in(reg32) z,
+            in(reg32) z,
+        );
+        [r, g, b, a]
+    }
+}
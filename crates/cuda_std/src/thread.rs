@@ -21,7 +21,12 @@
 use cuda_std_macros::gpu_only;
 use vek::{Vec2, Vec3};
 
+// on the host these are emulated, see the `host` module.
+#[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+use crate::host::intrinsics::*;
+
 // different calling conventions dont exist in nvptx, so we just use C as a placeholder.
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
 extern "C" {
     // defined in libintrinsics.ll
     fn __nvvm_thread_idx_x() -> u32;
@@ -49,80 +54,67 @@ extern "C" {
     fn __nvvm_system_fence();
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn thread_idx_x() -> u32 {
     unsafe { __nvvm_thread_idx_x() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn thread_idx_y() -> u32 {
     unsafe { __nvvm_thread_idx_y() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn thread_idx_z() -> u32 {
     unsafe { __nvvm_thread_idx_z() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_idx_x() -> u32 {
     unsafe { __nvvm_block_idx_x() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_idx_y() -> u32 {
     unsafe { __nvvm_block_idx_y() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_idx_z() -> u32 {
     unsafe { __nvvm_block_idx_z() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_dim_x() -> u32 {
     unsafe { __nvvm_block_dim_x() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_dim_y() -> u32 {
     unsafe { __nvvm_block_dim_y() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn block_dim_z() -> u32 {
     unsafe { __nvvm_block_dim_z() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn grid_dim_x() -> u32 {
     unsafe { __nvvm_grid_dim_x() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn grid_dim_y() -> u32 {
     unsafe { __nvvm_grid_dim_y() }
 }
 
-#[gpu_only]
 #[inline(always)]
 pub fn grid_dim_z() -> u32 {
     unsafe { __nvvm_grid_dim_z() }
 }
 
 /// Gets the 3d index of the thread currently executing the kernel.
-#[gpu_only]
 #[inline(always)]
 pub fn thread_idx() -> Vec3<u32> {
     unsafe {
@@ -135,7 +127,6 @@ pub fn thread_idx() -> Vec3<u32> {
 }
 
 /// Gets the 3d index of the block that the thread currently executing the kernel is located in.
-#[gpu_only]
 #[inline(always)]
 pub fn block_idx() -> Vec3<u32> {
     unsafe {
@@ -149,7 +140,6 @@ pub fn block_idx() -> Vec3<u32> {
 
 /// Gets the 3d layout of the thread blocks executing this kernel. In other words,
 /// how many threads exist in each thread block in every direction.
-#[gpu_only]
 #[inline(always)]
 pub fn block_dim() -> Vec3<u32> {
     unsafe {
@@ -163,7 +153,6 @@ pub fn block_dim() -> Vec3<u32> {
 
 /// Gets the 3d layout of the block grids executing this kernel. In other words,
 /// how many thread blocks exist in each grid in every direction.
-#[gpu_only]
 #[inline(always)]
 pub fn grid_dim() -> Vec3<u32> {
     unsafe {
@@ -181,7 +170,6 @@ pub fn grid_dim() -> Vec3<u32> {
 /// 
 /// For very simple kernels it may be faster to use a more simple index calculation, however,
 /// it will be unsound if the kernel launches in a 2d/3d configuration.
-#[gpu_only]
 #[rustfmt::skip]
 #[inline(always)]
 pub fn index() -> u32 {
@@ -227,7 +215,6 @@ pub fn first() -> bool {
 }
 
 /// Gets the number of threads inside of a warp. Currently 32 threads on every GPU architecture.
-#[gpu_only]
 #[inline(always)]
 pub fn warp_size() -> u32 {
     unsafe { __nvvm_warp_size() }
@@ -239,7 +226,10 @@ pub fn warp_size() -> u32 {
 /// Be careful when using sync_threads in conditional code. It will be perfectly fine if
 /// all threads evaluate to the same path, but if they dont, execution will halt
 /// or produce odd results (but should not produce undefined behavior).
-#[gpu_only]
+///
+/// This is the equivalent of CUDA C++'s `__syncthreads()`. The codegen marks calls to this
+/// function as `convergent`, so the optimizer will never move it into divergent code (e.g. by sinking
+/// it into a branch), a sync in uniform control flow stays in uniform control flow.
 #[inline(always)]
 pub fn sync_threads() {
     unsafe { __nvvm_block_barrier() }
@@ -289,21 +279,18 @@ pub fn sync_threads_or(predicate: u32) -> u32 {
 ///
 /// Note that this is NOT an execution synchronization like [`sync_threads`]. It is not possible
 /// to sync threads at a grid level. It is simply a memory fence.
-#[gpu_only]
 #[inline(always)]
 pub fn grid_fence() {
     unsafe { __nvvm_grid_fence() }
 }
 
 /// Acts as a memory fence at the device level.
-#[gpu_only]
 #[inline(always)]
 pub fn device_fence() {
     unsafe { __nvvm_device_fence() }
 }
 
 /// Acts as a memory fence at the system level.
-#[gpu_only]
 #[inline(always)]
 pub fn system_fence() {
     unsafe { __nvvm_system_fence() }
@@ -322,3 +309,65 @@ pub fn nanosleep(nanos: u32) {
         )
     }
 }
+
+macro_rules! hardware_register {
+    ($($(#[$attr:meta])* $name:ident -> $ty:ident => $reg:literal),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            #[gpu_only]
+            #[inline(always)]
+            pub fn $name() -> $ty {
+                let out: $ty;
+                // not `pure`, some of these registers change while the thread runs.
+                unsafe {
+                    hardware_register!(@mov $ty, $reg, out);
+                }
+                out
+            }
+        )*
+    };
+    (@mov u32, $reg:literal, $out:ident) => {
+        asm!(concat!("mov.u32 {}, ", $reg, ";"), out(reg32) $out)
+    };
+    (@mov u64, $reg:literal, $out:ident) => {
+        asm!(concat!("mov.u64 {}, ", $reg, ";"), out(reg64) $out)
+    };
+}
+
+hardware_register! {
+    /// The identifier of the streaming multiprocessor (SM) the thread is running on (`%smid`), ranging
+    /// from `0` to [`sm_id_limit`]` - 1`.
+    ///
+    /// SM identifiers are not guaranteed to be contiguous, and a thread block stays on the same SM but
+    /// nothing else is guaranteed about the mapping, so this is only useful for heuristics such as
+    /// spreading persistent blocks over the SMs or picking a per-SM slot of a work queue, never for
+    /// correctness.
+    sm_id -> u32 => "%smid",
+    /// An upper bound of [`sm_id`] (`%nsmid`), which may be larger than the number of SMs of the
+    /// device since SM identifiers are not contiguous. Arrays indexed by [`sm_id`] must have this
+    /// length.
+    sm_id_limit -> u32 => "%nsmid",
+    /// The identifier of the warp slot the thread's warp is running in on its SM (`%warpid`), ranging
+    /// from `0` to [`warp_id_limit`]` - 1`.
+    ///
+    /// This is **not** the index of the warp within its block, the hardware may move a warp to a
+    /// different slot while it runs (e.g. when it is preempted), so the value may already be stale
+    /// when it is used. Compute `linear thread index / 32` for the index of a warp within its block.
+    warp_id -> u32 => "%warpid",
+    /// An upper bound of [`warp_id`] (`%nwarpid`), which is the largest number of warps an SM of the
+    /// device can hold at the same time.
+    warp_id_limit -> u32 => "%nwarpid",
+    /// An identifier of the grid the thread belongs to (`%gridid`), unique among the grids running
+    /// at the same time in a context. This tells apart the launches of a kernel which run
+    /// concurrently, for example with dynamic parallelism.
+    grid_id -> u64 => "%gridid",
+}
+
+pub use crate::warp::lane_id;
+
+/// The number of thread blocks in the grid (the product of the `%nctaid` dimensions also returned by
+/// [`grid_dim`]).
+#[inline(always)]
+pub fn num_blocks() -> u32 {
+    grid_dim().product()
+}
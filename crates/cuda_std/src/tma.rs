@@ -0,0 +1,298 @@
+//! Bulk tensor copies between global and shared memory with the tensor memory accelerator (TMA),
+//! using descriptors encoded on the host with cust's `TensorMap`. Requires compute capability 9.0
+//! or higher.
+//!
+//! Copies into shared memory are asynchronous, their completion is tracked with a [`Barrier`] in
+//! shared memory, which one thread arms with the number of bytes it expects to arrive before
+//! issuing the copy and which every thread then waits on:
+//!
+//! ```ignore
+//! use cuda_std::*;
+//! use cuda_std::tma::{self, Barrier, TensorMap};
+//!
+//! #[kernel]
+//! pub unsafe fn scale_tiles(map: *const TensorMap, factor: f32) {
+//!     let tile = shared_array![f32; 64 * 32];
+//!     let barrier = shared_array![Barrier; 1];
+//!     let coords = [thread::block_idx_x() as i32 * 64, thread::block_idx_y() as i32 * 32];
+//!
+//!     if thread::thread_idx_x() == 0 {
+//!         tma::barrier_init(barrier, 1);
+//!         tma::fence_barrier_init();
+//!     }
+//!     thread::sync_threads();
+//!     if thread::thread_idx_x() == 0 {
+//!         tma::arrive_expect_tx(barrier, 64 * 32 * 4);
+//!         tma::load_2d(tile, map, coords, barrier);
+//!     }
+//!     tma::wait_parity(barrier, 0);
+//!
+//!     // ... work on the tile in shared memory, then write it back.
+//!     tma::fence_proxy_async_shared();
+//!     thread::sync_threads();
+//!     if thread::thread_idx_x() == 0 {
+//!         tma::store_2d(map, coords, tile);
+//!         tma::commit_group();
+//!         tma::wait_group_read();
+//!     }
+//! }
+//! ```
+
+use crate::gpu_only;
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+use crate::ptr::{convert_generic_to_specific_address_space, AddressSpace};
+
+/// An opaque TMA descriptor, the device side of cust's `TensorMap`. Descriptors must be in global
+/// memory and are only ever used through pointers.
+#[repr(C, align(64))]
+pub struct TensorMap {
+    _opaque: [u64; 16],
+}
+
+/// A barrier in shared memory tracking the completion of asynchronous copies (an `mbarrier`).
+///
+/// A barrier completes a phase once the expected number of threads arrived at it and the expected
+/// number of bytes were copied, after which it resets for the next phase. Phases alternate between
+/// parity 0 and 1, starting with 0.
+#[repr(C, align(8))]
+pub struct Barrier {
+    _state: u64,
+}
+
+/// The address of shared memory in the shared state space, as used by the instructions below.
+#[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+#[inline(always)]
+unsafe fn shared<T>(ptr: *const T) -> *const T {
+    convert_generic_to_specific_address_space(ptr, AddressSpace::Shared)
+}
+
+/// Initializes a barrier in shared memory which completes a phase once `count` threads arrived at
+/// it.
+///
+/// # Safety
+///
+/// `barrier` must point to shared memory, and the barrier must not be in use by other threads.
+/// The initialization must be made visible with [`fence_barrier_init`] and a block or cluster
+/// barrier before the barrier is used.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn barrier_init(barrier: *mut Barrier, count: u32) {
+    asm!(
+        "mbarrier.init.shared.b64 [{}], {};",
+        in(reg64) shared(barrier),
+        in(reg32) count,
+    );
+}
+
+/// Makes barrier initializations visible to the TMA unit.
+///
+/// # Safety
+///
+/// Must be called by the thread which initialized the barriers.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn fence_barrier_init() {
+    asm!("fence.mbarrier_init.release.cluster;");
+}
+
+/// Arrives at the barrier and expects `bytes` more bytes to be copied in the current phase, this
+/// is done by the thread issuing a copy, before issuing it.
+///
+/// # Safety
+///
+/// `barrier` must point to an initialized barrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn arrive_expect_tx(barrier: *mut Barrier, bytes: u32) {
+    asm!(
+        "{{",
+        ".reg .b64 state;",
+        "mbarrier.arrive.expect_tx.shared.b64 state, [{}], {};",
+        "}}",
+        in(reg64) shared(barrier),
+        in(reg32) bytes,
+    );
+}
+
+/// Arrives at the barrier without expecting any bytes.
+///
+/// # Safety
+///
+/// `barrier` must point to an initialized barrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn arrive(barrier: *mut Barrier) {
+    asm!(
+        "{{",
+        ".reg .b64 state;",
+        "mbarrier.arrive.shared.b64 state, [{}];",
+        "}}",
+        in(reg64) shared(barrier),
+    );
+}
+
+/// Returns whether the phase of the barrier with the given parity completed, waiting for a
+/// system-dependent time limit before returning `false`.
+///
+/// # Safety
+///
+/// `barrier` must point to an initialized barrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn try_wait_parity(barrier: *mut Barrier, parity: u32) -> bool {
+    let out: u32;
+    asm!(
+        "{{",
+        ".reg .pred p;",
+        "mbarrier.try_wait.parity.shared.b64 p, [{}], {};",
+        "selp.u32 {}, 1, 0, p;",
+        "}}",
+        in(reg64) shared(barrier),
+        in(reg32) parity,
+        out(reg32) out,
+    );
+    out != 0
+}
+
+/// Waits until the phase of the barrier with the given parity completed.
+///
+/// # Safety
+///
+/// `barrier` must point to an initialized barrier in shared memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn wait_parity(barrier: *mut Barrier, parity: u32) {
+    while !try_wait_parity(barrier, parity) {}
+}
+
+/// Makes writes to shared memory made by this thread visible to the TMA unit, which must be done
+/// before storing a tile written with normal stores.
+#[gpu_only]
+#[inline(always)]
+pub fn fence_proxy_async_shared() {
+    unsafe {
+        asm!("fence.proxy.async.shared::cta;");
+    }
+}
+
+/// Prefetches a descriptor into the cache ahead of its first use.
+///
+/// # Safety
+///
+/// `map` must point to a valid descriptor in global memory.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn prefetch_tensor_map(map: *const TensorMap) {
+    asm!("prefetch.tensormap [{}];", in(reg64) map);
+}
+
+/// Commits the stores issued by this thread since the last commit into a bulk async-group.
+#[gpu_only]
+#[inline(always)]
+pub fn commit_group() {
+    unsafe {
+        asm!("cp.async.bulk.commit_group;");
+    }
+}
+
+/// Waits until every committed group of stores of this thread finished reading shared memory, after
+/// which it can be overwritten.
+#[gpu_only]
+#[inline(always)]
+pub fn wait_group_read() {
+    unsafe {
+        asm!("cp.async.bulk.wait_group.read 0;");
+    }
+}
+
+/// Waits until every committed group of stores of this thread completed, after which its writes to
+/// global memory are visible.
+#[gpu_only]
+#[inline(always)]
+pub fn wait_group() {
+    unsafe {
+        asm!("cp.async.bulk.wait_group 0;");
+    }
+}
+
+macro_rules! tensor_copies {
+    ($($dim:literal, $load:ident, $store:ident, [$($coord:ident),*; $rank:literal], $coords:literal;)*) => {
+        $(
+            #[doc = concat!("Asynchronously copies the tile at `coords` of a ", $dim, " tensor into")]
+            /// shared memory at `dst`, completing `barrier` with the size of the tile in bytes.
+            ///
+            /// Coordinates are in elements, innermost dimension first, and may be out of bounds
+            /// (even negative), in which case the out of bounds elements are filled.
+            ///
+            /// # Safety
+            ///
+            /// `map` must point to a valid descriptor in global memory for a tensor of this rank,
+            /// `dst` must point to shared memory aligned to 128 bytes with room for the tile, and
+            /// `barrier` must point to an initialized barrier in shared memory which this thread
+            /// armed with [`arrive_expect_tx`].
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $load<T>(
+                dst: *mut T,
+                map: *const TensorMap,
+                coords: [i32; $rank],
+                barrier: *mut Barrier,
+            ) {
+                let [$($coord),*] = coords;
+                asm!(
+                    concat!(
+                        "cp.async.bulk.tensor.", $dim,
+                        ".shared::cluster.global.mbarrier::complete_tx::bytes [{}], [{}, ",
+                        $coords,
+                        "], [{}];"
+                    ),
+                    in(reg64) shared(dst),
+                    in(reg64) map,
+                    $(in(reg32) $coord,)*
+                    in(reg64) shared(barrier),
+                );
+            }
+
+            #[doc = concat!("Asynchronously copies a tile from shared memory at `src` to `coords` of a ", $dim)]
+            /// tensor, as part of the next group committed with [`commit_group`].
+            ///
+            /// Coordinates are in elements, innermost dimension first, out of bounds elements of
+            /// the tile are not written.
+            ///
+            /// # Safety
+            ///
+            /// `map` must point to a valid descriptor in global memory for a tensor of this rank,
+            /// `src` must point to shared memory aligned to 128 bytes holding the tile, made visible
+            /// with [`fence_proxy_async_shared`], which must not be written until the store has
+            /// been waited on with [`wait_group_read`].
+            #[gpu_only]
+            #[inline(always)]
+            pub unsafe fn $store<T>(
+                map: *const TensorMap,
+                coords: [i32; $rank],
+                src: *const T,
+            ) {
+                let [$($coord),*] = coords;
+                asm!(
+                    concat!(
+                        "cp.async.bulk.tensor.", $dim,
+                        ".global.shared::cta.bulk_group [{}, ",
+                        $coords,
+                        "], [{}];"
+                    ),
+                    in(reg64) map,
+                    $(in(reg32) $coord,)*
+                    in(reg64) shared(src),
+                );
+            }
+        )*
+    };
+}
+
+tensor_copies! {
+    "1d", load_1d, store_1d, [c0; 1], "{{{}}}";
+    "2d", load_2d, store_2d, [c0, c1; 2], "{{{}, {}}}";
+    "3d", load_3d, store_3d, [c0, c1, c2; 3], "{{{}, {}, {}}}";
+    "4d", load_4d, store_4d, [c0, c1, c2, c3; 4], "{{{}, {}, {}, {}}}";
+    "5d", load_5d, store_5d, [c0, c1, c2, c3, c4; 5], "{{{}, {}, {}, {}, {}}}";
+}
@@ -0,0 +1,76 @@
+//! Uniform buffers, parameter structs kept in constant memory and set by the host between launches.
+//!
+//! Kernels usually take their parameters by value, which is best for parameters that change with every
+//! launch (see `cust::function::LaunchParams`). Parameters which stay the same over many launches, such as
+//! the camera and settings of a renderer, can instead be written once to a global in the constant address
+//! space of the module, which every kernel in it can read without taking it as a parameter:
+//!
+//! ```no_run
+//! #[repr(C)]
+//! #[derive(Clone, Copy)]
+//! pub struct Settings {
+//!     pub width: u32,
+//!     pub height: u32,
+//!     pub exposure: f32,
+//! }
+//!
+//! uniform! {
+//!     pub static SETTINGS: Settings;
+//! }
+//!
+//! #[kernel]
+//! pub unsafe fn tonemap(pixels: *mut f32) {
+//!     let settings = SETTINGS.get();
+//!     let idx = thread::index_1d() as usize;
+//!     if idx < (settings.width * settings.height) as usize {
+//!         *pixels.add(idx) *= settings.exposure;
+//!     }
+//! }
+//! ```
+//!
+//! ```ignore
+//! // host, `Settings` must be the same struct as on the device (usually from a shared crate).
+//! let settings = module.get_global::<Settings>(&CString::new("SETTINGS")?)?;
+//! settings.copy_from(&Settings { width, height, exposure: 1.5 })?;
+//! ```
+//!
+//! The copy is synchronous and not ordered with work on other streams, so the host must not change the
+//! buffer while kernels reading it may still be running. Kernels which are launched before the buffer is first
+//! written read uninitialized memory.
+
+use core::mem::MaybeUninit;
+
+/// A struct in constant memory written by the host, declared with [`uniform!`](crate::uniform).
+#[repr(transparent)]
+pub struct Uniform<T>(MaybeUninit<T>);
+
+impl<T: Copy> Uniform<T> {
+    /// Creates an uninitialized uniform buffer, used through [`uniform!`](crate::uniform).
+    pub const fn uninit() -> Self {
+        Self(MaybeUninit::uninit())
+    }
+
+    /// The value of the buffer written by the host.
+    #[inline(always)]
+    pub fn get(&self) -> T {
+        // the static is never initialized on the device side, the compiler must not assume anything about it.
+        unsafe { core::ptr::read_volatile(self.0.as_ptr()) }
+    }
+}
+
+/// Declares [`Uniform`] buffers, statics in the constant address space which keep their name in the PTX so that
+/// the host can find them with `Module::get_global`. See the [`uniform`](crate::uniform) module.
+///
+/// The names are global to the PTX module (like `#[no_mangle]` functions), so they should be unique across the
+/// crate and its dependencies.
+#[macro_export]
+macro_rules! uniform {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $ty:ty;)*) => {
+        $(
+            $(#[$attr])*
+            #[no_mangle]
+            #[$crate::address_space(constant)]
+            $vis static $name: $crate::uniform::Uniform<$ty> = $crate::uniform::Uniform::uninit();
+        )*
+    };
+}
@@ -4,8 +4,25 @@
 //! thread blocks and execute in SIMT fashion.
 
 use crate::gpu_only;
+use core::mem::{size_of, MaybeUninit};
+use core::ptr;
 
-/// Synchronizes all of the threads inside of this warp according to `mask`.
+/// The number of threads inside of a warp, this is 32 on every architecture currently.
+pub const WARP_SIZE: u32 = 32;
+
+/// A mask which includes every lane of a warp.
+pub const FULL_MASK: u32 = 0xFFFF_FFFF;
+
+/// Synchronizes all of the threads inside of this warp according to `mask`. This is the equivalent
+/// of CUDA C++'s `__syncwarp(mask)`.
+///
+/// Each bit of `mask` corresponds to a lane in the warp, every lane in the mask must execute a
+/// `sync_warp` with the same mask before any of them continue. This also acts as a memory barrier
+/// for the participating threads.
+///
+/// On compute_70 and above threads inside of a warp are independently scheduled, therefore you cannot
+/// assume that threads in a warp execute in lockstep (warp-synchronous programming); a `sync_warp` is needed
+/// to reconverge threads and make memory writes visible to the other threads in the warp.
 ///
 /// # Safety
 ///
@@ -26,6 +43,19 @@ pub unsafe fn sync_warp(mask: u32) {
     sync(mask);
 }
 
+/// Synchronizes every thread inside of this warp, this is the same as calling [`sync_warp`] with
+/// [`FULL_MASK`] (`__syncwarp()` in CUDA C++).
+///
+/// # Safety
+///
+/// Every thread in the warp must call this function, if any thread in the warp has exited or does not
+/// reach this call, the behavior is undefined.
+#[gpu_only]
+#[inline(always)]
+pub unsafe fn sync() {
+    sync_warp(FULL_MASK)
+}
+
 /// Returns the thread's lane within its warp. This value ranges from `0` to `WARP_SIZE - 1` (`WARP_SIZE` is 32 on all
 /// architectures currently).
 #[gpu_only]
@@ -40,3 +70,70 @@ pub fn lane_id() -> u32 {
     }
     out
 }
+
+/// Applies `f` to every 32-bit word of `value`, used to shuffle types which are larger or smaller than a
+/// single register.
+#[inline(always)]
+unsafe fn map_words<T: Copy>(value: T, mut f: impl FnMut(u32) -> u32) -> T {
+    let mut out = MaybeUninit::<T>::uninit();
+    let src = &value as *const T as *const u8;
+    let dst = out.as_mut_ptr() as *mut u8;
+    let mut offset = 0;
+    while offset < size_of::<T>() {
+        let len = (size_of::<T>() - offset).min(size_of::<u32>());
+        let mut word = 0u32;
+        ptr::copy_nonoverlapping(src.add(offset), &mut word as *mut u32 as *mut u8, len);
+        let word = f(word);
+        ptr::copy_nonoverlapping(&word as *const u32 as *const u8, dst.add(offset), len);
+        offset += len;
+    }
+    out.assume_init()
+}
+
+macro_rules! shuffle {
+    ($($(#[$attr:meta])* $name:ident, $raw:ident, $mode:literal, $clamp:literal);* $(;)?) => {
+        $(
+            #[gpu_only]
+            #[inline(always)]
+            unsafe fn $raw(mask: u32, value: u32, b: u32) -> u32 {
+                let out;
+                asm!(
+                    concat!("shfl.sync.", $mode, ".b32 {}, {}, {}, ", $clamp, ", {};"),
+                    out(reg32) out,
+                    in(reg32) value,
+                    in(reg32) b,
+                    in(reg32) mask,
+                );
+                out
+            }
+
+            $(#[$attr])*
+            ///
+            /// `T` may be of any size, it is shuffled one 32-bit register at a time.
+            ///
+            /// # Safety
+            ///
+            /// The behavior of this function is undefined if:
+            /// - Any thread inside `mask` has exited or does not execute this shuffle with the same `mask`.
+            /// - The executing thread is not inside of `mask`.
+            #[inline(always)]
+            pub unsafe fn $name<T: Copy>(mask: u32, value: T, b: u32) -> T {
+                map_words(value, |word| $raw(mask, word, b))
+            }
+        )*
+    };
+}
+
+shuffle! {
+    /// Returns `value` from the lane `b` (`__shfl_sync` in CUDA C++).
+    shuffle, shfl_idx, "idx", "0x1f";
+    /// Returns `value` from the lane `lane_id() - b` (`__shfl_up_sync` in CUDA C++). Lanes
+    /// for which `lane_id() - b` would be less than `0` get their own `value` back.
+    shuffle_up, shfl_up, "up", "0";
+    /// Returns `value` from the lane `lane_id() + b` (`__shfl_down_sync` in CUDA C++). Lanes
+    /// for which `lane_id() + b` would be more than `31` get their own `value` back.
+    shuffle_down, shfl_down, "down", "0x1f";
+    /// Returns `value` from the lane `lane_id() ^ b` (`__shfl_xor_sync` in CUDA C++). This is
+    /// the butterfly pattern used for reductions where every lane needs the result.
+    shuffle_xor, shfl_xor, "bfly", "0x1f";
+}
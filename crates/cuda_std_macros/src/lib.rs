@@ -1,9 +1,10 @@
 use proc_macro::TokenStream;
-use proc_macro2::Span;
+use proc_macro2::{Group, Punct, Spacing, Span, TokenTree};
 use quote::{quote_spanned, ToTokens};
 use syn::{
-    parse::Parse, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned, Error,
-    FnArg, Ident, ItemFn, ReturnType, Stmt, Token,
+    parse::Parse, parse_macro_input, parse_quote, punctuated::Punctuated, spanned::Spanned,
+    visit_mut::VisitMut, Error, ExprPath, FnArg, GenericArgument, GenericParam, Ident, ItemFn,
+    LitInt, PathArguments, ReturnType, Stmt, Token,
 };
 
 /// Registers a function as a gpu kernel.
@@ -15,24 +16,93 @@ use syn::{
 /// - Marks the function as `no_mangle`.
 /// - Errors if the function is not unsafe.
 /// - Makes sure function parameters are all [`Copy`].
-/// - Makes sure the function doesn't return anything.
+/// - Embeds a hash of the parameter and return types in the ptx as a `__cuda_abi_<name>` global, which
+///   the launch functions generated by `cuda_builder`'s kernel bindings check when they load the kernel, so
+///   that launching stale ptx with a different signature fails instead of corrupting memory.
+///
+/// A kernel may return a value for every thread, for map-style kernels which would otherwise take an
+/// output slice only to write one element of it. The thread with the index `cuda_std::thread::index_1d()`
+/// returns the `index`th value, so such kernels are launched in one dimension:
+///
+/// ```ignore
+/// #[kernel]
+/// pub unsafe fn square(values: &[f32]) -> f32 {
+///     let x = values[thread::index_1d() as usize];
+///     x * x
+/// }
+/// ```
+///
+/// The values are written to an output slice which the macro appends to the parameters of the kernel, so
+/// it is launched with an additional `&mut DeviceBuffer<T>` argument (or a pointer and a length), which
+/// must be at least as long as the number of outputs. Threads whose index is past the end of the buffer do
+/// not run the function at all, so the grid can be rounded up to full blocks. The launch functions
+/// generated by `cuda_builder`'s kernel bindings allocate and return the output buffer.
+///
+/// `#[kernel(cluster_dim = (x, y, z))]` (or `cluster_dim = x`) declares that the kernel is always
+/// launched with thread block clusters of the given size, which requires compute capability 9.0,
+/// see `cuda_std::cluster`. Such kernels can be launched without specifying a cluster size.
+///
+/// The register usage of a kernel can be limited with the following hints, which ptxas uses to decide how
+/// many registers to allocate (and spill the rest to local memory):
+/// - `max_registers = n`: at most `n` registers per thread, like `-maxrregcount` but for one kernel.
+/// - `max_threads = n`: the kernel is never launched with more than `n` threads per block, the first
+///   argument of `__launch_bounds__` in CUDA C++. Launching it with more threads fails.
+/// - `min_blocks = n`: at least `n` blocks of `max_threads` threads should be able to run on an SM at
+///   once, the second argument of `__launch_bounds__`. Requires `max_threads`.
+///
+/// Kernels may be generic over types, for example over a function object passed as a parameter. A generic
+/// kernel is not a kernel by itself, its instances are declared with [`kernel_instances!`], which makes a
+/// non-generic kernel with the given name for every list of generic arguments.
 ///
 /// Note that this does not cfg the function for nvptx(64), that is explicit so that rust analyzer is able to
 /// offer intellisense by default.
 #[proc_macro_attribute]
 pub fn kernel(input: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
     let cloned = input.clone();
-    let _ = parse_macro_input!(input as KernelHints);
-    let input = parse_macro_input!(cloned as proc_macro2::TokenStream);
+    let hints = parse_macro_input!(input as KernelHints);
     let mut item = parse_macro_input!(item as ItemFn);
+    if !item.sig.generics.params.is_empty() {
+        return generic_kernel(cloned.into(), item).into();
+    }
+    if hints.min_blocks.is_some() && hints.max_threads.is_none() {
+        return Error::new(
+            Span::call_site(),
+            "`min_blocks` requires `max_threads` to be specified",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let input = without_attribute_hints(parse_macro_input!(cloned as proc_macro2::TokenStream));
     let no_mangle = parse_quote!(#[no_mangle]);
     item.attrs.push(no_mangle);
     let internal = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(kernel(#input)))]);
     item.attrs.push(internal);
+    if let Some(dims) = hints.cluster_dim {
+        let [x, y, z] = dims.map(proc_macro2::Literal::u32_unsuffixed);
+        let cluster = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(cluster_dim(#x, #y, #z)))]);
+        item.attrs.push(cluster);
+    }
+    let bounds = [
+        ("max_registers", hints.max_registers),
+        ("max_threads", hints.max_threads),
+        ("min_blocks", hints.min_blocks),
+    ];
+    for (name, value) in bounds {
+        if let Some(value) = value {
+            let name = Ident::new(name, Span::call_site());
+            let value = proc_macro2::Literal::u32_unsuffixed(value);
+            let bound = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(#name(#value)))]);
+            item.attrs.push(bound);
+        }
+    }
 
     // used to guarantee some things about how params are passed in the codegen.
     item.sig.abi = Some(parse_quote!(extern "C"));
 
+    if let Err(err) = hint_loops(&mut item.block) {
+        return err.to_compile_error().into();
+    }
+
     let check_fn = parse_quote! {
         fn assert_kernel_parameter_is_copy<T: Copy>() {}
     };
@@ -51,24 +121,283 @@ pub fn kernel(input: proc_macro::TokenStream, item: proc_macro::TokenStream) ->
         item.block.stmts.insert(0, call);
     }
 
-    let ret = item.sig.output.clone();
-    if let ReturnType::Type(_, _) = ret {
+    if item.sig.unsafety.is_none() {
         let err = quote_spanned! {
-            ret.span() => ::core::compile_err!("Kernel functions should not return anything");
+            item.span() => ::core::compile_error!("Kernel functions must be marked as unsafe");
         }
         .into();
         item.block.stmts.insert(0, parse_macro_input!(err as Stmt));
     }
 
+    let abi_hash = kernel_abi_hash(&item.sig);
+    let abi_name = quote::format_ident!("__cuda_abi_{}", item.sig.ident);
+
+    if let ReturnType::Type(_, ty) = item.sig.output.clone() {
+        // the body becomes a closure, so that `return` still returns the value of the thread.
+        let block = &item.block;
+        item.block = parse_quote!({
+            let __index = ::cuda_std::thread::index_1d() as usize;
+            if __index < __output.len() {
+                let __value = (|| -> #ty #block)();
+                *__output.get_unchecked_mut(__index) = __value;
+            }
+        });
+        item.sig.inputs.push(parse_quote!(__output: &mut [#ty]));
+        item.sig.output = ReturnType::Default;
+        item.attrs
+            .push(parse_quote!(#[allow(improper_ctypes_definitions)]));
+    }
+
+    quote::quote! {
+        #item
+
+        #[cfg(any(target_arch = "nvptx", target_arch = "nvptx64"))]
+        #[no_mangle]
+        #[allow(non_upper_case_globals)]
+        #[doc(hidden)]
+        pub static #abi_name: u64 = #abi_hash;
+    }
+    .into()
+}
+
+/// The hash of the parameter and return types of a kernel, which is embedded in the PTX as a
+/// `__cuda_abi_<kernel>` global so that the bindings generated by `cuda_builder` can check that the
+/// PTX they load was built from the same signature. This must hash the same string as
+/// `cuda_builder` does, the types as written without whitespace (FNV-1a). A test pins both to the same
+/// hash.
+fn kernel_abi_hash(sig: &syn::Signature) -> u64 {
+    let strip = |tokens: proc_macro2::TokenStream| {
+        tokens
+            .to_string()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+    };
+    let params = sig
+        .inputs
+        .iter()
+        .filter_map(|param| match param {
+            FnArg::Typed(ty) => Some(strip(ty.ty.to_token_stream())),
+            FnArg::Receiver(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let output = match &sig.output {
+        ReturnType::Type(_, ty) => strip(ty.to_token_stream()),
+        ReturnType::Default => String::new(),
+    };
+    let signature = format!("{}({})->{}", sig.ident, params.join(","), output);
+    signature.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Expands a generic kernel to the function itself and a `macro_rules!` macro with the same name, which
+/// [`kernel_instances!`] invokes with the name and the generic arguments of an instance. The macro makes a
+/// kernel with the parameters of the generic kernel, where the generic parameters are replaced by the
+/// arguments, that calls the generic kernel.
+fn generic_kernel(hints: proc_macro2::TokenStream, mut item: ItemFn) -> proc_macro2::TokenStream {
+    // the instances call the generic kernel, so its loops are where the hints go.
+    if let Err(err) = hint_loops(&mut item.block) {
+        return err.to_compile_error();
+    }
+    let mut generics = vec![];
+    for param in &item.sig.generics.params {
+        match param {
+            GenericParam::Type(ty) => generics.push((ty.ident.clone(), false)),
+            GenericParam::Const(cnst) => generics.push((cnst.ident.clone(), true)),
+            GenericParam::Lifetime(lifetime) => {
+                return Error::new(
+                    lifetime.span(),
+                    "Generic kernels may not have lifetime parameters",
+                )
+                .to_compile_error()
+            }
+        }
+    }
     if item.sig.unsafety.is_none() {
-        let err = quote_spanned! {
-            item.span() => ::core::compile_error!("Kernel functions must be marked as unsafe");
+        return Error::new(item.sig.span(), "Kernel functions must be marked as unsafe")
+            .to_compile_error();
+    }
+    let mut args = vec![];
+    let mut tys = vec![];
+    for (i, param) in item.sig.inputs.iter().enumerate() {
+        match param {
+            FnArg::Receiver(_) => {
+                return Error::new(param.span(), "Kernel functions may not be struct methods")
+                    .to_compile_error()
+            }
+            FnArg::Typed(ty) => {
+                args.push(Ident::new(&format!("__arg{}", i), Span::call_site()));
+                tys.push(substitute_generics(ty.ty.to_token_stream(), &generics));
+            }
         }
-        .into();
-        item.block.stmts.insert(0, parse_macro_input!(err as Stmt));
     }
 
-    item.to_token_stream().into()
+    // `$name:ident; $T:ty, $N:expr` for the generic parameters `T` and `const N`.
+    let dollar = Punct::new('$', Spacing::Alone);
+    let matchers = generics.iter().map(|(ident, is_const)| {
+        if *is_const {
+            quote::quote!(#dollar #ident:expr)
+        } else {
+            quote::quote!(#dollar #ident:ty)
+        }
+    });
+    let turbofish = generics.iter().map(|(ident, is_const)| {
+        if *is_const {
+            quote::quote!({ #dollar #ident })
+        } else {
+            quote::quote!(#dollar #ident)
+        }
+    });
+
+    let ret = match &item.sig.output {
+        ReturnType::Type(arrow, ty) => {
+            let ty = substitute_generics(ty.to_token_stream(), &generics);
+            quote::quote!(#arrow #ty)
+        }
+        ReturnType::Default => quote::quote!(),
+    };
+
+    let name = &item.sig.ident;
+    let vis = &item.vis;
+    let docs = format!(
+        "An instance of the generic kernel `{}`, see `kernel_instances!`.",
+        name
+    );
+    quote::quote! {
+        #item
+
+        #[allow(unused_macros)]
+        macro_rules! #name {
+            (#dollar name:ident; #(#matchers),*) => {
+                #[doc = #docs]
+                #[::cuda_std::kernel(#hints)]
+                #[allow(improper_ctypes_definitions, clippy::missing_safety_doc, clippy::too_many_arguments)]
+                #vis unsafe fn #dollar name(#(#args: #tys),*) #ret {
+                    #name::<#(#turbofish),*>(#(#args),*)
+                }
+            };
+        }
+    }
+}
+
+/// Replaces the generic parameters of a generic kernel in `tokens` with the metavariables of the same name.
+fn substitute_generics(
+    tokens: proc_macro2::TokenStream,
+    generics: &[(Ident, bool)],
+) -> proc_macro2::TokenStream {
+    let mut out = proc_macro2::TokenStream::new();
+    for token in tokens {
+        match token {
+            TokenTree::Ident(ident) if generics.iter().any(|(generic, _)| *generic == ident) => {
+                out.extend([
+                    TokenTree::Punct(Punct::new('$', Spacing::Alone)),
+                    TokenTree::Ident(ident),
+                ]);
+            }
+            TokenTree::Group(group) => {
+                let mut new = Group::new(
+                    group.delimiter(),
+                    substitute_generics(group.stream(), generics),
+                );
+                new.set_span(group.span());
+                out.extend([TokenTree::Group(new)]);
+            }
+            token => out.extend([token]),
+        }
+    }
+    out
+}
+
+/// One instance of [`kernel_instances!`], `kernel::<Args> as name`.
+struct KernelInstance {
+    kernel: Ident,
+    args: Vec<GenericArgument>,
+    name: Ident,
+}
+
+impl Parse for KernelInstance {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let path = input.parse::<ExprPath>()?;
+        if path.qself.is_some()
+            || path.path.leading_colon.is_some()
+            || path.path.segments.len() != 1
+        {
+            return Err(Error::new(
+                path.span(),
+                "Expected the name of a generic kernel in scope, followed by its generic arguments",
+            ));
+        }
+        let segment = path.path.segments.into_iter().next().unwrap();
+        let args = match segment.arguments {
+            PathArguments::AngleBracketed(args) => args.args.into_iter().collect(),
+            _ => {
+                return Err(Error::new(
+                    segment.ident.span(),
+                    "Expected the generic arguments of the instance, such as `kernel::<T>`",
+                ))
+            }
+        };
+        <Token![as]>::parse(input)?;
+        Ok(Self {
+            kernel: segment.ident,
+            args,
+            name: input.parse()?,
+        })
+    }
+}
+
+/// Declares instances of generic kernels, each of which is a kernel with the given name that calls the
+/// generic kernel with the given generic arguments.
+///
+/// ```ignore
+/// pub trait UnaryOp: Copy {
+///     fn apply(&self, x: f32) -> f32;
+/// }
+///
+/// #[derive(Clone, Copy)]
+/// #[repr(C)]
+/// pub struct Scale(pub f32);
+///
+/// impl UnaryOp for Scale {
+///     fn apply(&self, x: f32) -> f32 {
+///         x * self.0
+///     }
+/// }
+///
+/// #[kernel]
+/// pub unsafe fn map<F: UnaryOp>(input: &[f32], op: F, output: *mut f32) {
+///     let idx = thread::index_1d() as usize;
+///     if idx < input.len() {
+///         *output.add(idx) = op.apply(input[idx]);
+///     }
+/// }
+///
+/// // `Offset` is another `UnaryOp`.
+/// kernel_instances! {
+///     map::<Scale> as map_scale,
+///     map::<Offset> as map_offset,
+/// }
+/// ```
+///
+/// This is the function object pattern of C++ kernel libraries: the generic parameter is usually the type of
+/// a parameter, whose value is passed by the host like any other parameter and carries the state of the
+/// function, and the host picks the instance to launch by its name (`map_scale`, `map_offset`). The
+/// instances are ordinary kernels, so they are kept in the PTX by the codegen, listed by
+/// `cust::include_ptx!` and get launch functions from the kernel bindings of `cuda_builder`.
+///
+/// A generic kernel is only in scope after its definition, like `macro_rules!` macros, so its instances must
+/// be declared after it in the same module (or a module declared after it with `#[macro_use]`). Generic
+/// kernels may have type and const parameters, but not lifetime parameters.
+#[proc_macro]
+pub fn kernel_instances(input: TokenStream) -> TokenStream {
+    let instances =
+        parse_macro_input!(input with Punctuated::<KernelInstance, Token![,]>::parse_terminated);
+    let mut out = proc_macro2::TokenStream::new();
+    for KernelInstance { kernel, args, name } in instances {
+        out.extend(quote::quote!(#kernel! { #name; #(#args),* }));
+    }
+    out.into()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -94,6 +423,76 @@ impl Parse for Dimension {
 enum KernelHint {
     GridDim(Dimension),
     BlockDim(Dimension),
+    ClusterDim([u32; 3]),
+    MaxRegisters(u32),
+    MaxThreads(u32),
+    MinBlocks(u32),
+}
+
+/// Parses a cluster size, either a single integer or a tuple of one to three integers.
+fn parse_cluster_dim(input: syn::parse::ParseStream) -> syn::Result<[u32; 3]> {
+    let dims = if input.peek(syn::token::Paren) {
+        let content;
+        syn::parenthesized!(content in input);
+        Punctuated::<LitInt, Token![,]>::parse_terminated(&content)?
+            .into_iter()
+            .collect::<Vec<_>>()
+    } else {
+        vec![input.parse::<LitInt>()?]
+    };
+    if dims.is_empty() || dims.len() > 3 {
+        return Err(Error::new(
+            input.span(),
+            "Cluster dimensions must have one to three components",
+        ));
+    }
+    let mut out = [1; 3];
+    for (out, dim) in out.iter_mut().zip(&dims) {
+        *out = dim.base10_parse::<u32>()?;
+        if *out == 0 {
+            return Err(Error::new(
+                dim.span(),
+                "Cluster dimensions must not be zero",
+            ));
+        }
+    }
+    Ok(out)
+}
+
+/// Parses a positive integer hint such as `max_registers = 64`.
+fn parse_positive(input: syn::parse::ParseStream) -> syn::Result<u32> {
+    let lit = input.parse::<LitInt>()?;
+    let value = lit.base10_parse::<u32>()?;
+    if value == 0 {
+        return Err(Error::new(lit.span(), "Expected a positive integer"));
+    }
+    Ok(value)
+}
+
+/// The hints which are passed to the codegen as their own attributes instead of through `kernel(...)`.
+const ATTRIBUTE_HINTS: &[&str] = &["cluster_dim", "max_registers", "max_threads", "min_blocks"];
+
+/// Removes the hints which are passed as their own attributes from the kernel hints passed to the codegen.
+fn without_attribute_hints(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let mut hints = vec![vec![]];
+    for token in input {
+        match &token {
+            TokenTree::Punct(punct) if punct.as_char() == ',' => hints.push(vec![]),
+            _ => hints.last_mut().unwrap().push(token),
+        }
+    }
+    let hints = hints.into_iter().filter(|hint| {
+        !hint.is_empty()
+            && !matches!(hint.first(), Some(TokenTree::Ident(ident)) if ATTRIBUTE_HINTS.iter().any(|hint| ident == hint))
+    });
+    let mut out = proc_macro2::TokenStream::new();
+    for (i, hint) in hints.enumerate() {
+        if i != 0 {
+            out.extend(quote::quote!(,));
+        }
+        out.extend(hint);
+    }
+    out
 }
 
 impl Parse for KernelHint {
@@ -110,6 +509,10 @@ impl Parse for KernelHint {
                 let dim = Dimension::parse(input)?;
                 Ok(Self::BlockDim(dim))
             }
+            "cluster_dim" => Ok(Self::ClusterDim(parse_cluster_dim(input)?)),
+            "max_registers" => Ok(Self::MaxRegisters(parse_positive(input)?)),
+            "max_threads" => Ok(Self::MaxThreads(parse_positive(input)?)),
+            "min_blocks" => Ok(Self::MinBlocks(parse_positive(input)?)),
             _ => Err(Error::new(Span::call_site(), "Unrecognized option")),
         }
     }
@@ -119,6 +522,10 @@ impl Parse for KernelHint {
 struct KernelHints {
     grid_dim: Option<Dimension>,
     block_dim: Option<Dimension>,
+    cluster_dim: Option<[u32; 3]>,
+    max_registers: Option<u32>,
+    max_threads: Option<u32>,
+    min_blocks: Option<u32>,
 }
 
 impl Parse for KernelHints {
@@ -135,6 +542,10 @@ impl Parse for KernelHints {
             match hint {
                 KernelHint::GridDim(dim) => out.grid_dim = Some(dim),
                 KernelHint::BlockDim(dim) => out.block_dim = Some(dim),
+                KernelHint::ClusterDim(dim) => out.cluster_dim = Some(dim),
+                KernelHint::MaxRegisters(n) => out.max_registers = Some(n),
+                KernelHint::MaxThreads(n) => out.max_threads = Some(n),
+                KernelHint::MinBlocks(n) => out.min_blocks = Some(n),
             }
         }
 
@@ -185,6 +596,557 @@ pub fn gpu_only(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -
     output.into()
 }
 
+/// Controls how a loop is unrolled on the GPU, like `#pragma unroll` in CUDA C++:
+/// - `#[unroll]` unrolls the loop completely, which requires a trip count known at compile time.
+/// - `#[unroll(n)]` unrolls the loop `n` times.
+/// - [`#[no_unroll]`](macro@no_unroll) keeps the loop from being unrolled.
+///
+/// The hints become `llvm.loop` metadata, which the optimizer of libnvvm follows where it can. They are
+/// hints: a loop whose trip count is not known is not unrolled completely.
+///
+/// Custom attributes on loops are only allowed on nightly with `#![feature(proc_macro_hygiene,
+/// stmt_expr_attributes)]`, so these are usually put on loops in functions with [`#[kernel]`](macro@kernel) or
+/// [`#[loop_hints]`](macro@loop_hints), which handle the attributes of the loops in their body:
+///
+/// ```ignore
+/// #[kernel]
+/// pub unsafe fn convolve(input: &[f32], weights: &[f32; 9], output: *mut f32) {
+///     let idx = thread::index_1d() as usize;
+///     let mut sum = 0.0;
+///     #[unroll]
+///     for i in 0..9 {
+///         sum += input[idx + i] * weights[i];
+///     }
+///     *output.add(idx) = sum;
+/// }
+/// ```
+///
+/// This does nothing on the CPU.
+#[proc_macro_attribute]
+pub fn unroll(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let attr = proc_macro2::TokenStream::from(attr);
+    let attr = if attr.is_empty() {
+        parse_quote!(#[unroll])
+    } else {
+        parse_quote!(#[unroll(#attr)])
+    };
+    hint_loop_expr(attr, item)
+}
+
+/// Keeps a loop from being unrolled on the GPU, like `#pragma unroll 1` in CUDA C++, see
+/// [`#[unroll]`](macro@unroll).
+#[proc_macro_attribute]
+pub fn no_unroll(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    hint_loop_expr(parse_quote!(#[no_unroll]), item)
+}
+
+/// Handles the [`#[unroll]`](macro@unroll) and [`#[no_unroll]`](macro@no_unroll) attributes of the loops in
+/// the body of a device function. [`#[kernel]`](macro@kernel) does this for kernels.
+#[proc_macro_attribute]
+pub fn loop_hints(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let mut func = syn::parse_macro_input!(item as syn::ItemFn);
+    match hint_loops(&mut func.block) {
+        Ok(()) => func.into_token_stream().into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Handles the loop hint attributes of the loops in `block`, see [`LoopHints`].
+fn hint_loops(block: &mut syn::Block) -> syn::Result<()> {
+    let mut hints = LoopHints::default();
+    hints.visit_block_mut(block);
+    match hints.error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
+
+/// Applies a loop attribute used directly on a loop expression.
+fn hint_loop_expr(attr: syn::Attribute, item: proc_macro::TokenStream) -> TokenStream {
+    let mut expr = syn::parse_macro_input!(item as syn::Expr);
+    match &mut expr {
+        syn::Expr::ForLoop(syn::ExprForLoop { attrs, .. })
+        | syn::Expr::While(syn::ExprWhile { attrs, .. })
+        | syn::Expr::Loop(syn::ExprLoop { attrs, .. }) => attrs.insert(0, attr),
+        _ => {
+            return Error::new(expr.span(), "loop hints can only be used on loops")
+                .to_compile_error()
+                .into()
+        }
+    }
+    let mut hints = LoopHints::default();
+    hints.visit_expr_mut(&mut expr);
+    match hints.error {
+        Some(err) => err.to_compile_error().into(),
+        None => expr.into_token_stream().into(),
+    }
+}
+
+/// The unroll count of a loop attribute: `u32::MAX` for `#[unroll]`, `n` for `#[unroll(n)]` and `0` for
+/// `#[no_unroll]`. `None` if the attribute is not a loop hint.
+fn unroll_count(attr: &syn::Attribute) -> Option<syn::Result<u32>> {
+    let name = attr.path.segments.last()?.ident.to_string();
+    match name.as_str() {
+        "no_unroll" => Some(Ok(0)),
+        "unroll" if attr.tokens.is_empty() => Some(Ok(u32::MAX)),
+        "unroll" => {
+            Some(
+                attr.parse_args::<LitInt>()
+                    .and_then(|lit| match lit.base10_parse::<u32>()? {
+                        0 => Err(Error::new(
+                            lit.span(),
+                            "loops must be unrolled at least once",
+                        )),
+                        n => Ok(n),
+                    }),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Turns the loop hint attributes of loops into calls which the codegen turns into `llvm.loop` metadata.
+#[derive(Default)]
+struct LoopHints {
+    error: Option<Error>,
+}
+
+impl LoopHints {
+    fn hint(&mut self, attrs: &mut Vec<syn::Attribute>, body: &mut syn::Block) {
+        let mut count = None;
+        attrs.retain(|attr| match unroll_count(attr) {
+            Some(Ok(n)) => {
+                count = Some(n);
+                false
+            }
+            Some(Err(err)) => {
+                self.error.get_or_insert(err);
+                false
+            }
+            None => true,
+        });
+        if let Some(count) = count {
+            // a call at the start of the body is always inside of the loop.
+            let call = parse_quote! {
+                #[allow(unused_unsafe)]
+                let () = unsafe { ::cuda_std::misc::__nvvm_loop_unroll(#count) };
+            };
+            body.stmts.insert(0, call);
+        }
+    }
+}
+
+impl VisitMut for LoopHints {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        match expr {
+            syn::Expr::ForLoop(l) => self.hint(&mut l.attrs, &mut l.body),
+            syn::Expr::While(l) => self.hint(&mut l.attrs, &mut l.body),
+            syn::Expr::Loop(l) => self.hint(&mut l.attrs, &mut l.body),
+            _ => {}
+        }
+        syn::visit_mut::visit_expr_mut(self, expr);
+    }
+}
+
+/// Makes a GPU and a CPU version of a function from one definition, where the CPU version calls other
+/// functions in place of GPU-only ones.
+///
+/// Functions shared by kernels and host code (such as the CPU renderer of a path tracer) cannot call
+/// intrinsics which only exist on the GPU, like the approximate math of `cuda_std::fast` or texture
+/// sampling. The attribute takes a list of `gpu_path => cpu_expr` swaps: the GPU version of the function
+/// is the function as written, and in the CPU version every use of `gpu_path` is replaced by `cpu_expr`,
+/// which is usually a function path or a closure with the same signature:
+///
+/// ```ignore
+/// #[gpu_or_cpu(sample_albedo => |uv: Vec2| Vec3::new(uv.x, uv.y, 1.0))]
+/// fn direction(phi: f32, scale: f32, uv: Vec2) -> Vec3 {
+///     let (sin, cos) = fast::sin_cos(phi);
+///     Vec3::new(fast::div(cos, scale), sin, 0.0) * sample_albedo(uv)
+/// }
+/// ```
+///
+/// The functions of `cuda_std::fast` are swapped by default for their precise `f32` counterparts, for
+/// example `fast::sin_cos` for `f32::sin_cos` and `fast::saturate` for a clamp to `0.0..=1.0`, whether
+/// they are written as `fast::name`, `cuda_std::fast::name` or `::cuda_std::fast::name`, so most
+/// shared math needs no swaps at all. A swap given to the attribute takes precedence over the default
+/// one for the same path. The functions of `GpuFloat` need no swaps, they call the `std` functions on
+/// the CPU already.
+///
+/// Other paths are compared as written, so `foo::bar` does not swap `crate::foo::bar`. Only
+/// expressions are swapped, method calls and paths in types or patterns are left alone.
+#[proc_macro_attribute]
+pub fn gpu_or_cpu(attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let swaps = parse_macro_input!(attr with Punctuated::<Swap, Token![,]>::parse_terminated);
+    let gpu = parse_macro_input!(item as ItemFn);
+    let mut cpu = gpu.clone();
+    let mut swapper = Swapper {
+        swaps: swaps.into_iter().collect(),
+    };
+    swapper.visit_block_mut(&mut cpu.block);
+
+    let output = quote::quote! {
+        #[cfg(any(target_arch="nvptx", target_arch="nvptx64"))]
+        #gpu
+
+        #[cfg(not(any(target_arch="nvptx", target_arch="nvptx64")))]
+        #cpu
+    };
+    output.into()
+}
+
+/// `gpu_path => cpu_expr` in [`gpu_or_cpu`].
+struct Swap {
+    from: syn::Path,
+    to: syn::Expr,
+}
+
+impl Parse for Swap {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let from = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let to = input.parse()?;
+        Ok(Self { from, to })
+    }
+}
+
+struct Swapper {
+    swaps: Vec<Swap>,
+}
+
+impl VisitMut for Swapper {
+    fn visit_expr_mut(&mut self, expr: &mut syn::Expr) {
+        if let syn::Expr::Path(path) = expr {
+            if path.qself.is_none() {
+                let written = path.path.to_token_stream().to_string();
+                let swap = self
+                    .swaps
+                    .iter()
+                    .find(|swap| swap.from.to_token_stream().to_string() == written);
+                if let Some(swap) = swap {
+                    let to = &swap.to;
+                    *expr = parse_quote!((#to));
+                    return;
+                }
+                if let Some(to) = fast_swap(&path.path) {
+                    *expr = parse_quote!((#to));
+                    return;
+                }
+            }
+        }
+        syn::visit_mut::visit_expr_mut(self, expr);
+    }
+}
+
+/// The CPU versions of the functions of `cuda_std::fast`, used by [`gpu_or_cpu`] unless a swap for the
+/// same path is given.
+const FAST_SWAPS: &[(&str, &str)] = &[
+    ("sin", "f32::sin"),
+    ("cos", "f32::cos"),
+    ("tan", "f32::tan"),
+    ("exp", "f32::exp"),
+    ("exp10", "|x: f32| f32::powf(10.0, x)"),
+    ("log", "f32::ln"),
+    ("log2", "f32::log2"),
+    ("log10", "f32::log10"),
+    ("pow", "f32::powf"),
+    ("div", "|x: f32, y: f32| x / y"),
+    // `max` returns 0 for NaN like the intrinsic, `clamp` would keep it.
+    ("saturate", "|x: f32| x.max(0.0).min(1.0)"),
+    ("sin_cos", "f32::sin_cos"),
+    ("recip", "f32::recip"),
+    ("sqrt", "f32::sqrt"),
+    ("rsqrt", "|x: f32| 1.0 / x.sqrt()"),
+    ("add_sat", "|x: f32, y: f32| (x + y).max(0.0).min(1.0)"),
+    ("sub_sat", "|x: f32, y: f32| (x - y).max(0.0).min(1.0)"),
+    ("mul_sat", "|x: f32, y: f32| (x * y).max(0.0).min(1.0)"),
+    (
+        "mul_add_sat",
+        "|x: f32, y: f32, z: f32| x.mul_add(y, z).max(0.0).min(1.0)",
+    ),
+];
+
+/// The default CPU version of `path` if it names a function of `cuda_std::fast`.
+fn fast_swap(path: &syn::Path) -> Option<syn::Expr> {
+    let segments = path
+        .segments
+        .iter()
+        .map(|segment| match segment.arguments {
+            PathArguments::None => Some(segment.ident.to_string()),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let name = match segments.as_slice() {
+        [module, name] if module == "fast" && path.leading_colon.is_none() => name,
+        [krate, module, name] if krate == "cuda_std" && module == "fast" => name,
+        _ => return None,
+    };
+    let (_, to) = FAST_SWAPS.iter().find(|(fast, _)| fast == name)?;
+    Some(syn::parse_str(to).expect("invalid default swap"))
+}
+
+/// Checks that a device function only uses code which behaves the same on the host, so that it can be
+/// tested on the CPU, for example with proptest, before it runs on the GPU.
+///
+/// Every function of a gpu crate builds for the host too, but functions using the GPU-only parts of
+/// `cuda_std` panic there, depend on the emulated thread (see `cuda_std::host`), or compute something
+/// else, like the approximate math of `cuda_std::fast`. A passing CPU test says nothing about them.
+/// Using any of them in a `#[pure]` function is a compile error, so the tested host version computes the
+/// same as the device version, up to the rounding of libm functions:
+///
+/// ```ignore
+/// #[pure]
+/// pub fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+///     let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+///     t * t * (3.0 - 2.0 * t)
+/// }
+///
+/// #[cfg(test)]
+/// proptest::proptest! {
+///     #[test]
+///     fn smoothstep_stays_in_range(x in -10.0f32..10.0) {
+///         proptest::prop_assert!((0.0..=1.0).contains(&smoothstep(0.0, 1.0, x)));
+///     }
+/// }
+/// ```
+///
+/// Rejected are `unsafe` code, inline assembly, `shared_array!` and paths into the modules of `cuda_std`
+/// which only work on the GPU or depend on the current thread, such as `thread::index_1d` or
+/// `cuda_std::warp::shuffle`. The check works on paths as written (outside of macros), so functions and
+/// methods called by a `#[pure]` function are not checked, mark them `#[pure]` too.
+///
+/// With the `host-stubs` feature of `cuda_std`, usually enabled by a dev-dependency, every `#[pure]`
+/// function also gets a public `<name>_host` function on the host which calls it, so that tests in other
+/// crates, such as the host crate, can call device functions which are private. It is generated next to
+/// the function, so its module must be reachable from the tests and the types of its signature public.
+#[proc_macro_attribute]
+pub fn pure(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let func = parse_macro_input!(item as ItemFn);
+    let mut checker = PurityChecker { error: None };
+    if let Some(unsafety) = &func.sig.unsafety {
+        checker.reject(unsafety.span(), "`#[pure]` functions cannot be unsafe");
+    }
+    syn::visit::visit_block(&mut checker, &func.block);
+
+    let error = checker.error.map(|err| err.to_compile_error());
+    let stub = if cfg!(feature = "host-stubs") {
+        Some(host_stub(&func))
+    } else {
+        None
+    };
+    let output = quote::quote! {
+        #error
+        #func
+        #stub
+    };
+    output.into()
+}
+
+/// The public `<name>_host` function calling `func` of `#[pure]` with the `host-stubs` feature.
+fn host_stub(func: &ItemFn) -> proc_macro2::TokenStream {
+    let name = &func.sig.ident;
+    let mut sig = func.sig.clone();
+    sig.ident = quote::format_ident!("{}_host", name);
+    sig.constness = None;
+    let mut args = Vec::new();
+    for (i, input) in sig.inputs.iter_mut().enumerate() {
+        if let FnArg::Typed(arg) = input {
+            // patterns such as tuples are bound to a name and passed on whole.
+            let ident = match &*arg.pat {
+                syn::Pat::Ident(pat) if pat.by_ref.is_none() && pat.subpat.is_none() => {
+                    pat.ident.clone()
+                }
+                _ => quote::format_ident!("__arg{}", i),
+            };
+            arg.pat = parse_quote!(#ident);
+            args.push(ident);
+        }
+    }
+
+    // type and const parameters are passed explicitly, in case they are only used in the return
+    // type, which `impl Trait` arguments do not allow.
+    let mut finder = ImplTraitFinder(false);
+    syn::visit::visit_signature(&mut finder, &func.sig);
+    let params = func
+        .sig
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match param {
+            GenericParam::Type(ty) => Some(&ty.ident),
+            GenericParam::Const(c) => Some(&c.ident),
+            GenericParam::Lifetime(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let turbofish = if params.is_empty() || finder.0 {
+        None
+    } else {
+        Some(quote::quote!(::<#(#params),*>))
+    };
+
+    // the stub only exists where the function does.
+    let cfgs = func.attrs.iter().filter(|attr| attr.path.is_ident("cfg"));
+    quote::quote! {
+        #(#cfgs)*
+        #[cfg(not(any(target_arch = "nvptx", target_arch = "nvptx64")))]
+        #[doc(hidden)]
+        #[inline]
+        #[allow(clippy::too_many_arguments)]
+        pub #sig {
+            #name #turbofish (#(#args),*)
+        }
+    }
+}
+
+struct ImplTraitFinder(bool);
+
+impl<'ast> syn::visit::Visit<'ast> for ImplTraitFinder {
+    fn visit_type_impl_trait(&mut self, _: &'ast syn::TypeImplTrait) {
+        self.0 = true;
+    }
+}
+
+/// Modules of `cuda_std` which only work on the GPU or depend on the current thread. Their names are
+/// rejected as the first segment of a path too, since they are usually imported with the prelude.
+const IMPURE_MODULES: &[&str] = &[
+    "cluster",
+    "collective",
+    "diag",
+    "dispatch",
+    "fast",
+    "intrinsics",
+    "pipeline",
+    "segmented",
+    "shared",
+    "spec",
+    "texture",
+    "thread",
+    "tma",
+    "uniform",
+    "warp",
+];
+
+/// Like [`IMPURE_MODULES`], but only rejected after `cuda_std::`, they share their name with modules of
+/// `core`.
+const IMPURE_CUDA_STD_MODULES: &[&str] = &["io", "mem", "misc", "ptr", "rt"];
+
+const IMPURE_MACROS: &[&str] = &["asm", "global_asm", "llvm_asm", "shared_array"];
+
+struct PurityChecker {
+    error: Option<Error>,
+}
+
+impl PurityChecker {
+    fn reject(&mut self, span: Span, msg: &str) {
+        let err = Error::new(span, msg);
+        match &mut self.error {
+            Some(error) => error.combine(err),
+            None => self.error = Some(err),
+        }
+    }
+}
+
+impl<'ast> syn::visit::Visit<'ast> for PurityChecker {
+    fn visit_expr_unsafe(&mut self, expr: &'ast syn::ExprUnsafe) {
+        self.reject(
+            expr.unsafe_token.span(),
+            "`#[pure]` functions cannot use unsafe code",
+        );
+    }
+
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if let Some(name) = mac.path.segments.last() {
+            if IMPURE_MACROS.iter().any(|m| name.ident == m) {
+                self.reject(
+                    mac.path.span(),
+                    &format!("`#[pure]` functions cannot use `{}!`", name.ident),
+                );
+            }
+        }
+    }
+
+    fn visit_path(&mut self, path: &'ast syn::Path) {
+        let mut segments = path.segments.iter();
+        let (module, in_cuda_std) = match segments.next() {
+            Some(first) if first.ident == "cuda_std" => (segments.next(), true),
+            first => (first, false),
+        };
+        if let Some(module) = module {
+            let name = module.ident.to_string();
+            let impure = IMPURE_MODULES.contains(&name.as_str())
+                || (in_cuda_std && IMPURE_CUDA_STD_MODULES.contains(&name.as_str()));
+            if impure && segments.next().is_some() {
+                self.reject(
+                    path.span(),
+                    &format!(
+                        "`#[pure]` functions cannot use `cuda_std::{}`, it only works on the GPU or \
+                         depends on the current thread",
+                        name
+                    ),
+                );
+                return;
+            }
+        }
+        syn::visit::visit_path(self, path);
+    }
+}
+
+/// Keeps the function from being inlined into its callers on the GPU, without changing how it is inlined on
+/// the CPU.
+///
+/// The codegen inlines aggressively, which is usually what you want on the GPU, but inlining every helper
+/// into a big kernel can make it need far more registers, which lowers occupancy or causes spills. Large
+/// device functions which are called from several places (or only on rare paths) are good candidates.
+///
+/// `#[inline(always)]` and `#[inline(never)]` are honored by the codegen too, this attribute only exists so
+/// that code shared with the CPU does not lose inlining there. It cannot be combined with `#[inline]`.
+#[proc_macro_attribute]
+pub fn gpu_noinline(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let mut func = syn::parse_macro_input!(item as syn::ItemFn);
+    if let Some(attr) = func.attrs.iter().find(|a| a.path.is_ident("inline")) {
+        return Error::new(
+            attr.span(),
+            "#[gpu_noinline] cannot be combined with #[inline]",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let noinline =
+        parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), inline(never))]);
+    func.attrs.push(noinline);
+
+    func.into_token_stream().into()
+}
+
+/// Fails the build if the kernel uses local memory, either for local variables which could not be kept in
+/// registers or for registers spilled by ptxas.
+///
+/// Local memory is as slow as global memory when it is not cached, so a kernel which starts using it (often after an
+/// unrelated change, such as indexing an array with a runtime value or adding a few live variables) can silently become
+/// several times slower. The error names the kernel, how much local memory it uses, and the local variables which are
+/// indexed with runtime values, the usual reason for variables in local memory. Spills are only found if `ptxas` from
+/// the CUDA toolkit can be run.
+///
+/// ```ignore
+/// #[kernel]
+/// #[forbid_local_memory]
+/// pub unsafe fn blur(input: &[f32], output: *mut f32) {
+///     // ...
+/// }
+/// ```
+///
+/// This does nothing on the CPU.
+#[proc_macro_attribute]
+pub fn forbid_local_memory(
+    _attr: proc_macro::TokenStream,
+    item: proc_macro::TokenStream,
+) -> TokenStream {
+    let mut func = syn::parse_macro_input!(item as syn::ItemFn);
+    let forbid = parse_quote!(#[cfg_attr(any(target_arch="nvptx", target_arch="nvptx64"), nvvm_internal(forbid_local_memory))]);
+    func.attrs.push(forbid);
+    func.into_token_stream().into()
+}
+
 /// Notifies the codegen that this function is externally visible and should not be
 /// removed if it is not used by a kernel. Usually used for linking with other PTX/cubin files.
 ///
@@ -236,3 +1198,115 @@ pub fn address_space(attr: proc_macro::TokenStream, item: proc_macro::TokenStrea
 
     global.into_token_stream().into()
 }
+
+/// Implements `cuda_std::dispatch::Dispatch` for an enum, so that matches over it can be run with the lanes of
+/// a warp grouped by variant with `cuda_std::dispatch::coherent`.
+///
+/// Unless the enum already has a `#[repr]`, this also makes it `#[repr(u32)]`, which puts the variant in a full
+/// 32-bit word at the start of the enum instead of the smallest integer which fits, so reading the variant is a
+/// single load without masking.
+#[proc_macro_attribute]
+pub fn dispatch(_attr: proc_macro::TokenStream, item: proc_macro::TokenStream) -> TokenStream {
+    let mut item = syn::parse_macro_input!(item as syn::ItemEnum);
+    if !item.variants.is_empty() && !item.attrs.iter().any(|a| a.path.is_ident("repr")) {
+        item.attrs.push(parse_quote!(#[repr(u32)]));
+    }
+
+    let name = &item.ident;
+    let (impl_generics, ty_generics, where_clause) = item.generics.split_for_impl();
+    let variants = item.variants.len() as u32;
+    let arms = item.variants.iter().enumerate().map(|(i, variant)| {
+        let ident = &variant.ident;
+        let i = i as u32;
+        quote::quote!(Self::#ident { .. } => #i)
+    });
+
+    quote::quote! {
+        #item
+
+        impl #impl_generics ::cuda_std::dispatch::Dispatch for #name #ty_generics #where_clause {
+            const VARIANTS: u32 = #variants;
+
+            #[inline(always)]
+            fn variant(&self) -> u32 {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    }
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the same signature and hash are pinned in the tests of `cuda_builder`, whose bindings check it.
+    #[test]
+    fn kernel_abi_hash_matches_bindings() {
+        let func: ItemFn =
+            syn::parse_str("pub unsafe fn add(a: &[f32], b: * mut f32, n: usize) {}").unwrap();
+        assert_eq!(kernel_abi_hash(&func.sig), 0xeaa6_1b1f_16b8_326c);
+    }
+
+    #[test]
+    fn swaps_fast_functions_by_default() {
+        let mut swapper = Swapper {
+            swaps: vec![syn::parse_str("fast::div => |x: f32, y: f32| x * y.recip()").unwrap()],
+        };
+        let mut block: syn::Block = syn::parse_str(
+            "{ fast::sin(x) + cuda_std::fast::saturate(x) + ::cuda_std::fast::sqrt(x) \
+             + fast::div(x, y) + other::sin(x) + fast::unknown(x) }",
+        )
+        .unwrap();
+        swapper.visit_block_mut(&mut block);
+        assert_eq!(
+            block.to_token_stream().to_string(),
+            "{ (f32 :: sin) (x) + (| x : f32 | x . max (0.0) . min (1.0)) (x) + (f32 :: sqrt) (x) \
+             + (| x : f32 , y : f32 | x * y . recip ()) (x , y) + other :: sin (x) + fast :: unknown (x) }"
+        );
+    }
+
+    #[test]
+    fn hints_loops_of_generic_kernels() {
+        let func: ItemFn = syn::parse_str(
+            "pub unsafe fn map<F: Fn(f32) -> f32>(f: F, values: &mut [f32; 4]) {\
+             #[unroll] for x in values.iter_mut() { *x = f(*x); } }",
+        )
+        .unwrap();
+        let expanded = generic_kernel(proc_macro2::TokenStream::new(), func).to_string();
+        assert!(expanded.contains(":: cuda_std :: misc :: __nvvm_loop_unroll (4294967295u32)"));
+        assert!(!expanded.contains("# [unroll]"));
+    }
+
+    #[test]
+    fn generates_host_stubs() {
+        let func: ItemFn = syn::parse_str(
+            "#[cfg(feature = \"math\")] #[inline(always)] const fn lerp<'a, T: Copy, const N: usize>(\
+             a: &'a [T; N], (b, c): (T, T), mut t: f32) -> T { a[0] }",
+        )
+        .unwrap();
+        let stub: ItemFn = syn::parse2(host_stub(&func)).unwrap();
+        assert_eq!(stub.sig.ident, "lerp_host");
+        assert!(matches!(stub.vis, syn::Visibility::Public(_)));
+        assert!(stub.sig.constness.is_none());
+        assert!(stub.attrs[0].path.is_ident("cfg"));
+        let args = stub
+            .sig
+            .inputs
+            .iter()
+            .map(|arg| arg.to_token_stream().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(args, ["a : & 'a [T ; N]", "__arg1 : (T , T)", "t : f32"]);
+        assert_eq!(
+            stub.block.to_token_stream().to_string(),
+            "{ lerp :: < T , N > (a , __arg1 , t) }"
+        );
+
+        let func: ItemFn =
+            syn::parse_str("fn sum<T>(values: impl Iterator<Item = T>) -> T {}").unwrap();
+        let stub: ItemFn = syn::parse2(host_stub(&func)).unwrap();
+        assert_eq!(stub.block.to_token_stream().to_string(), "{ sum (values) }");
+    }
+}
@@ -0,0 +1,16 @@
+// GPUDirect Storage is only shipped on linux, and it is an optional part of the toolkit, so
+// we only link to it if we can find it. Without it, every read goes through pinned host memory.
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=CUDA_LIBRARY_PATH");
+    if !cfg!(target_os = "linux") || std::env::var("DOCS_RS").is_ok() {
+        return;
+    }
+
+    let dirs = find_cuda_helper::find_cuda_lib_dirs();
+    if let Some(dir) = dirs.iter().find(|dir| dir.join("libcufile.so").is_file()) {
+        println!("cargo:rustc-link-search=native={}", dir.display());
+        println!("cargo:rustc-link-lib=dylib=cufile");
+        println!("cargo:rustc-cfg=cufile");
+    }
+}
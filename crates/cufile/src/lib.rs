@@ -0,0 +1,323 @@
+//! Bindings to the CUDA GPUDirect Storage (cuFile) API, used to read files directly from
+//! NVMe drives into device memory without a copy through host memory.
+//!
+//! GPUDirect Storage (GDS) is only available on linux with the `nvidia-fs` driver loaded and a
+//! supported filesystem. When it is unavailable (or `libcufile` was not found at build time),
+//! [`CuFile`] transparently falls back to reading the file into pinned host memory and copying
+//! it to the device, so the same code works on every machine, just slower.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let _ctx = cust::quick_init()?;
+//! use cufile::CuFile;
+//! use cust::memory::DeviceBuffer;
+//!
+//! let mut file = CuFile::open("weights.bin")?;
+//! let mut weights = unsafe { DeviceBuffer::<f32>::uninitialized(1 << 20)? };
+//! file.read_exact(&mut weights, 0)?;
+//! println!("read through GDS: {}", file.is_direct());
+//! # Ok(())
+//! # }
+//! ```
+
+#[allow(warnings)]
+pub mod sys;
+
+use cust::error::CudaError;
+use cust::memory::{CopyDestination, DeviceCopy, DevicePointer, DeviceSlice, LockedBuffer};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    mem,
+    path::Path,
+};
+
+/// The size of the pinned staging buffer used when GDS is unavailable.
+const STAGING_LEN: usize = 8 << 20;
+
+pub type CuFileResult<T> = Result<T, CuFileError>;
+
+#[derive(Debug)]
+pub enum CuFileError {
+    /// An error from the filesystem.
+    Io(io::Error),
+    /// An error from the CUDA driver API while copying through host memory.
+    Cuda(CudaError),
+    /// An error from cuFile, with the raw `CUfileOpError` code.
+    CuFile(sys::CUfileOpError),
+    /// cuFile failed because of an error in the CUDA driver, with the raw `CUresult`.
+    CuFileDriver(cust::sys::CUresult),
+}
+
+impl fmt::Display for CuFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Cuda(e) => write!(f, "{}", e),
+            Self::CuFile(code) => write!(f, "cuFile error {}", code),
+            Self::CuFileDriver(res) => write!(f, "cuFile failed with CUDA driver error {:?}", res),
+        }
+    }
+}
+
+impl std::error::Error for CuFileError {}
+
+impl From<io::Error> for CuFileError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<CudaError> for CuFileError {
+    fn from(e: CudaError) -> Self {
+        Self::Cuda(e)
+    }
+}
+
+#[cfg(cufile)]
+trait ToResult {
+    fn to_result(self) -> CuFileResult<()>;
+}
+
+#[cfg(cufile)]
+impl ToResult for sys::CUfileError_t {
+    fn to_result(self) -> CuFileResult<()> {
+        match self.err {
+            sys::CU_FILE_SUCCESS => Ok(()),
+            sys::CU_FILE_CUDA_DRIVER_ERROR => Err(CuFileError::CuFileDriver(self.cu_err)),
+            code => Err(CuFileError::CuFile(code)),
+        }
+    }
+}
+
+/// Opens the cuFile driver once per process, returning whether it is usable.
+#[cfg(cufile)]
+fn driver_open() -> bool {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Once,
+    };
+
+    static INIT: Once = Once::new();
+    static OPEN: AtomicBool = AtomicBool::new(false);
+    INIT.call_once(|| {
+        let res = unsafe { sys::cuFileDriverOpen() }.to_result();
+        OPEN.store(res.is_ok(), Ordering::SeqCst);
+    });
+    OPEN.load(Ordering::SeqCst)
+}
+
+/// Whether GPUDirect Storage can be used on this machine. If this returns `false`, every
+/// [`CuFile`] reads through pinned host memory instead.
+pub fn is_gds_available() -> bool {
+    #[cfg(cufile)]
+    {
+        driver_open()
+    }
+    #[cfg(not(cufile))]
+    {
+        false
+    }
+}
+
+/// A file which can be read directly into device memory.
+///
+/// Reads go through GPUDirect Storage if it is available for this file, otherwise they are
+/// staged through a pinned host buffer, see [`CuFile::is_direct`].
+#[derive(Debug)]
+pub struct CuFile {
+    file: File,
+    #[cfg(cufile)]
+    handle: Option<sys::CUfileHandle_t>,
+    /// Lazily allocated staging buffer for the fallback path.
+    staging: Option<LockedBuffer<u8>>,
+}
+
+impl CuFile {
+    /// Opens a file for reading.
+    ///
+    /// GPUDirect Storage requires the file to be opened with `O_DIRECT`, if that (or registering the
+    /// file with cuFile) fails, the file is opened normally and reads fall back to pinned host memory.
+    pub fn open(path: impl AsRef<Path>) -> CuFileResult<Self> {
+        let path = path.as_ref();
+        #[cfg(cufile)]
+        if driver_open() {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            let direct = std::fs::OpenOptions::new()
+                .read(true)
+                .custom_flags(libc::O_DIRECT)
+                .open(path);
+            if let Ok(file) = direct {
+                if let Ok(handle) = register_handle(&file) {
+                    return Ok(Self {
+                        file,
+                        handle: Some(handle),
+                        staging: None,
+                    });
+                }
+            }
+        }
+        Ok(Self::from_file(File::open(path)?))
+    }
+
+    /// Wraps an already opened file. Reads only go through GPUDirect Storage if the file
+    /// was opened with `O_DIRECT` and cuFile accepts it.
+    pub fn from_file(file: File) -> Self {
+        #[cfg(cufile)]
+        let handle = if driver_open() {
+            register_handle(&file).ok()
+        } else {
+            None
+        };
+        Self {
+            file,
+            #[cfg(cufile)]
+            handle,
+            staging: None,
+        }
+    }
+
+    /// Whether reads from this file go directly from storage to the device through GPUDirect Storage.
+    pub fn is_direct(&self) -> bool {
+        #[cfg(cufile)]
+        {
+            self.handle.is_some()
+        }
+        #[cfg(not(cufile))]
+        {
+            false
+        }
+    }
+
+    /// Reads bytes starting at `offset` in the file into `dst`, returning the number of bytes read.
+    /// This is less than the size of `dst` only if the end of the file was reached.
+    ///
+    /// The bytes are copied as-is, the file must contain valid values of `T`.
+    pub fn read<T: DeviceCopy>(
+        &mut self,
+        dst: &mut DeviceSlice<T>,
+        offset: u64,
+    ) -> CuFileResult<usize> {
+        let size = mem::size_of::<T>() * dst.len();
+        #[cfg(cufile)]
+        if let Some(handle) = self.handle {
+            return read_direct(handle, dst.as_mut_ptr().cast(), size, offset);
+        }
+
+        let staging = match &mut self.staging {
+            Some(staging) => staging,
+            staging => staging.insert(LockedBuffer::new(&0, STAGING_LEN)?),
+        };
+        // SAFETY: the byte view covers exactly the memory of `dst`.
+        let bytes = unsafe {
+            DeviceSlice::from_raw_parts_mut(
+                DevicePointer::wrap(dst.as_mut_ptr().cast::<u8>()),
+                size,
+            )
+        };
+
+        let mut done = 0;
+        while done < size {
+            let len = staging.len().min(size - done);
+            let read = read_at(&self.file, &mut staging[..len], offset + done as u64)?;
+            if read == 0 {
+                break;
+            }
+            bytes[done..done + read].copy_from(&staging[..read])?;
+            done += read;
+        }
+        Ok(done)
+    }
+
+    /// Fills `dst` with bytes starting at `offset` in the file, returning an error of kind
+    /// [`io::ErrorKind::UnexpectedEof`] if the file is too short.
+    pub fn read_exact<T: DeviceCopy>(
+        &mut self,
+        dst: &mut DeviceSlice<T>,
+        offset: u64,
+    ) -> CuFileResult<()> {
+        let size = mem::size_of::<T>() * dst.len();
+        if self.read(dst, offset)? < size {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "failed to fill whole buffer",
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying file.
+    pub fn file(&self) -> &File {
+        &self.file
+    }
+}
+
+impl Drop for CuFile {
+    fn drop(&mut self) {
+        #[cfg(cufile)]
+        if let Some(handle) = self.handle.take() {
+            unsafe { sys::cuFileHandleDeregister(handle) };
+        }
+    }
+}
+
+#[cfg(cufile)]
+fn register_handle(file: &File) -> CuFileResult<sys::CUfileHandle_t> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut descr = sys::CUfileDescr_t {
+        type_: sys::CU_FILE_HANDLE_TYPE_OPAQUE_FD,
+        handle: sys::CUfileDescr_handle {
+            fd: file.as_raw_fd(),
+        },
+        fs_ops: std::ptr::null(),
+    };
+    let mut handle = std::ptr::null_mut();
+    unsafe {
+        sys::cuFileHandleRegister(&mut handle, &mut descr).to_result()?;
+    }
+    Ok(handle)
+}
+
+#[cfg(cufile)]
+fn read_direct(
+    handle: sys::CUfileHandle_t,
+    dst: *mut std::ffi::c_void,
+    size: usize,
+    offset: u64,
+) -> CuFileResult<usize> {
+    let mut done = 0;
+    while done < size {
+        let read = unsafe {
+            sys::cuFileRead(
+                handle,
+                dst,
+                size - done,
+                (offset + done as u64) as _,
+                done as _,
+            )
+        };
+        match read {
+            0 => break,
+            -1 => return Err(io::Error::last_os_error().into()),
+            read if read < 0 => return Err(CuFileError::CuFile(-read as sys::CUfileOpError)),
+            read => done += read as usize,
+        }
+    }
+    Ok(done)
+}
+
+fn read_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    loop {
+        match file.read(buf) {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            res => return res,
+        }
+    }
+}
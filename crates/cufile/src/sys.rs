@@ -0,0 +1,70 @@
+//! Raw bindings to the subset of `cufile.h` used by this crate.
+
+use cust::sys::CUresult;
+use std::os::raw::{c_int, c_long, c_void};
+
+pub type CUfileOpError = c_int;
+
+pub const CU_FILE_SUCCESS: CUfileOpError = 0;
+pub const CU_FILE_DRIVER_NOT_INITIALIZED: CUfileOpError = 5001;
+pub const CU_FILE_CUDA_DRIVER_ERROR: CUfileOpError = 5011;
+
+#[repr(C)]
+#[derive(Debug, Copy, Clone)]
+pub struct CUfileError_t {
+    pub err: CUfileOpError,
+    pub cu_err: CUresult,
+}
+
+pub type CUfileFileHandleType = c_int;
+
+pub const CU_FILE_HANDLE_TYPE_OPAQUE_FD: CUfileFileHandleType = 1;
+pub const CU_FILE_HANDLE_TYPE_OPAQUE_WIN32: CUfileFileHandleType = 2;
+pub const CU_FILE_HANDLE_TYPE_USERSPACE_FS: CUfileFileHandleType = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub union CUfileDescr_handle {
+    pub fd: c_int,
+    pub handle: *mut c_void,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CUfileDescr_t {
+    pub type_: CUfileFileHandleType,
+    pub handle: CUfileDescr_handle,
+    pub fs_ops: *const c_void,
+}
+
+pub type CUfileHandle_t = *mut c_void;
+
+extern "C" {
+    pub fn cuFileDriverOpen() -> CUfileError_t;
+    pub fn cuFileDriverClose() -> CUfileError_t;
+    pub fn cuFileHandleRegister(
+        fh: *mut CUfileHandle_t,
+        descr: *mut CUfileDescr_t,
+    ) -> CUfileError_t;
+    pub fn cuFileHandleDeregister(fh: CUfileHandle_t);
+    pub fn cuFileBufRegister(
+        bufPtr_base: *const c_void,
+        length: usize,
+        flags: c_int,
+    ) -> CUfileError_t;
+    pub fn cuFileBufDeregister(bufPtr_base: *const c_void) -> CUfileError_t;
+    pub fn cuFileRead(
+        fh: CUfileHandle_t,
+        bufPtr_base: *mut c_void,
+        size: usize,
+        file_offset: c_long,
+        bufPtr_offset: c_long,
+    ) -> isize;
+    pub fn cuFileWrite(
+        fh: CUfileHandle_t,
+        bufPtr_base: *const c_void,
+        size: usize,
+        file_offset: c_long,
+        bufPtr_offset: c_long,
+    ) -> isize;
+}
@@ -0,0 +1,846 @@
+//! Recording of driver calls for reproducing driver and codegen bugs, enabled by the `capture`
+//! feature.
+//!
+//! With the feature enabled, [`sys`](crate::sys) becomes a shim over `cust_raw` which, while a
+//! capture is running, writes every driver call cust makes to a log, along with its arguments, its
+//! result, and the values it returned through out-parameters. Host data which is needed to replay
+//! the calls, such as module images and the contents of host to device copies, is saved next to
+//! the log, and the parameters of kernel launches are recorded using the parameter sizes in the
+//! PTX of the module the kernel came from.
+//!
+//! A capture is started with [`start`] or by setting the `CUST_CAPTURE` environment variable to
+//! a directory. The log can then be read with [`Trace`], which can generate a standalone C
+//! program that makes the same calls, which is usually what driver bug reports ask for:
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use cust::capture::{self, Trace};
+//! use std::fs::File;
+//!
+//! capture::start("capture")?;
+//! // load modules, allocate memory and launch kernels...
+//! capture::stop();
+//!
+//! let trace = Trace::open("capture")?;
+//! trace.write_repro(File::create("capture/repro.c")?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Kernel parameters can only be recorded for kernels from PTX modules loaded while the capture
+//! was running, and calls which take pointers to host structures (graphs, arrays, textures, launch
+//! configurations and tensor maps) are recorded but cannot be replayed, they appear as comments in
+//! the generated program.
+//!
+//! The driver functions which cust looks up at runtime because they are newer than `cust_raw`
+//! (`cuLaunchKernelEx`, `cuTensorMapEncodeTiled` and `cuModuleGetLoadingMode`) are recorded too.
+//! Errors of the capture itself stop it and are kept for [`take_error`].
+
+mod repro;
+
+pub use repro::{Arg, Call, Trace};
+
+use crate::sys::{cudaError_enum, CUfunction, CUresult};
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    env,
+    ffi::CStr,
+    fmt::{Debug, Write as _},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    os::raw::{c_char, c_void},
+    path::{Path, PathBuf},
+    slice,
+    sync::Mutex,
+};
+
+/// The name of the log inside of a capture directory.
+const LOG: &str = "calls.txt";
+
+static RECORDER: Lazy<Mutex<Option<Recorder>>> = Lazy::new(|| {
+    let recorder = env::var_os("CUST_CAPTURE").and_then(|dir| match Recorder::new(dir.as_ref()) {
+        Ok(recorder) => Some(recorder),
+        Err(e) => {
+            fail("failed to start capturing driver calls", e);
+            None
+        }
+    });
+    Mutex::new(recorder)
+});
+
+/// The error which stopped the last capture, see [`take_error`].
+static ERROR: Lazy<Mutex<Option<io::Error>>> = Lazy::new(|| Mutex::new(None));
+
+/// Starts recording driver calls to `dir`, which is created if it does not exist. A capture which
+/// is already running is stopped first.
+pub fn start(dir: impl AsRef<Path>) -> io::Result<()> {
+    let recorder = Recorder::new(dir.as_ref())?;
+    *RECORDER.lock().unwrap() = Some(recorder);
+    ERROR.lock().unwrap().take();
+    Ok(())
+}
+
+/// Stops recording driver calls, flushing the log.
+pub fn stop() {
+    RECORDER.lock().unwrap().take();
+}
+
+/// Whether driver calls are currently being recorded.
+pub fn is_capturing() -> bool {
+    RECORDER.lock().unwrap().is_some()
+}
+
+/// Takes the error which stopped the last capture, if starting it from `CUST_CAPTURE` or writing
+/// the log failed. Driver calls cannot return these errors, so the capture stops and keeps the
+/// error here instead (it is also emitted as a warning with the `tracing` feature).
+pub fn take_error() -> Option<io::Error> {
+    Lazy::force(&RECORDER);
+    ERROR.lock().unwrap().take()
+}
+
+fn fail(context: &str, e: io::Error) {
+    trace_warn!("cust: {}: {}", context, e);
+    let e = io::Error::new(e.kind(), format!("{}: {}", context, e));
+    *ERROR.lock().unwrap() = Some(e);
+}
+
+pub(crate) struct Recorder {
+    dir: PathBuf,
+    log: BufWriter<File>,
+    next: usize,
+    /// The images of loaded modules, for finding the parameters of their kernels.
+    modules: HashMap<usize, Vec<u8>>,
+    /// The size of every parameter of kernels retrieved from a PTX module.
+    kernels: HashMap<usize, Vec<usize>>,
+}
+
+impl Recorder {
+    fn new(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut log = BufWriter::new(File::create(dir.join(LOG))?);
+        writeln!(log, "# cust driver call capture")?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            log,
+            next: 0,
+            modules: HashMap::new(),
+            kernels: HashMap::new(),
+        })
+    }
+
+    fn write(&mut self, name: &str, result: CUresult, mut call: Record) -> io::Result<()> {
+        let index = self.next;
+        self.next += 1;
+        for (arg, bytes) in std::mem::take(&mut call.blobs) {
+            let file = format!("{}-{}.bin", index, arg);
+            fs::write(self.dir.join(&file), bytes)?;
+            call.set(arg, format!("@{}", file));
+        }
+
+        write!(self.log, "{}\t{}\t{:?}", index, name, result)?;
+        for (name, ty, value) in &call.args {
+            write!(self.log, "\t{}:{}={}", name, ty, value)?;
+        }
+        for (name, value) in &call.outs {
+            write!(self.log, "\t&{}={}", name, value)?;
+        }
+        writeln!(self.log)?;
+        // flush every call so the log survives the driver taking down the process.
+        self.log.flush()
+    }
+}
+
+/// A driver call being recorded.
+pub(crate) struct Record {
+    success: bool,
+    args: Vec<(&'static str, &'static str, String)>,
+    outs: Vec<(&'static str, String)>,
+    blobs: Vec<(&'static str, Vec<u8>)>,
+}
+
+impl Record {
+    pub(crate) fn arg<T: Debug>(&mut self, name: &'static str, ty: &'static str, val: &T) {
+        self.args.push((name, ty, format!("{:?}", val)));
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        if let Some(arg) = self.args.iter_mut().find(|(arg, ..)| *arg == name) {
+            arg.2 = value;
+        }
+    }
+
+    /// Records the value written to an out-parameter, or `?` if the call failed.
+    pub(crate) unsafe fn out<T: Debug>(&mut self, name: &'static str, ptr: *const T) {
+        if !ptr.is_null() {
+            let value = if self.success {
+                format!("{:?}", *ptr)
+            } else {
+                "?".to_string()
+            };
+            self.outs.push((name, value));
+        }
+    }
+
+    /// Records a nul-terminated string argument by value.
+    unsafe fn string(&mut self, name: &'static str, ptr: *const c_char) {
+        if !ptr.is_null() {
+            self.set(name, format!("{:?}", CStr::from_ptr(ptr)));
+        }
+    }
+
+    /// Records a pointer to a structure by the value of the structure.
+    pub(crate) unsafe fn deref<T: Debug>(&mut self, name: &'static str, ptr: *const T) {
+        if !ptr.is_null() {
+            self.set(name, format!("{:?}", *ptr));
+        }
+    }
+
+    /// Saves the host memory an argument points to next to the log.
+    unsafe fn blob(&mut self, name: &'static str, ptr: *const c_void, len: usize) {
+        if !ptr.is_null() {
+            let bytes = slice::from_raw_parts(ptr as *const u8, len).to_vec();
+            self.blobs.push((name, bytes));
+        }
+    }
+
+    fn params(&mut self, name: &'static str, params: &[&[u8]]) {
+        let mut value = String::from("[");
+        for (i, param) in params.iter().enumerate() {
+            if i != 0 {
+                value.push(',');
+            }
+            for byte in param.iter() {
+                let _ = write!(value, "{:02x}", byte);
+            }
+        }
+        value.push(']');
+        self.set(name, value);
+    }
+
+    /// Records the parameters of a launch of `f`, if the kernel came from a PTX module loaded
+    /// during the capture.
+    pub(crate) unsafe fn kernel_params(
+        &mut self,
+        name: &'static str,
+        recorder: &Recorder,
+        f: CUfunction,
+        params: *mut *mut c_void,
+    ) {
+        if let (false, Some(sizes)) = (params.is_null(), recorder.kernels.get(&(f as usize))) {
+            let params = sizes
+                .iter()
+                .enumerate()
+                .map(|(i, &size)| slice::from_raw_parts(*params.add(i) as *const u8, size))
+                .collect::<Vec<_>>();
+            self.params(name, &params);
+        }
+    }
+}
+
+pub(crate) fn record(
+    name: &'static str,
+    result: CUresult,
+    f: impl FnOnce(&mut Record, &mut Recorder),
+) {
+    let mut guard = RECORDER.lock().unwrap();
+    let recorder = match guard.as_mut() {
+        Some(recorder) => recorder,
+        None => return,
+    };
+    let mut call = Record {
+        success: result == cudaError_enum::CUDA_SUCCESS,
+        args: Vec::new(),
+        outs: Vec::new(),
+        blobs: Vec::new(),
+    };
+    f(&mut call, recorder);
+    if let Err(e) = recorder.write(name, result, call) {
+        fail("failed to record driver call, stopping capture", e);
+        *guard = None;
+    }
+}
+
+/// The length of a module image, which may be PTX, a cubin or a fatbin.
+unsafe fn image_len(image: *const c_void) -> Option<usize> {
+    let bytes = image as *const u8;
+    let read = |offset: usize, len: usize| {
+        let mut val = 0u64;
+        for i in (0..len).rev() {
+            val = (val << 8) | *bytes.add(offset + i) as u64;
+        }
+        val as usize
+    };
+    if image.is_null() {
+        None
+    } else if slice::from_raw_parts(bytes, 4) == b"\x7fELF" {
+        // 64-bit ELF, the section headers come last.
+        Some(read(0x28, 8) + read(0x3A, 2) * read(0x3C, 2))
+    } else if read(0, 4) == 0xBA55_ED50 {
+        // fatbin header: magic, version, header size, size of the rest.
+        Some(read(6, 2) + read(8, 8))
+    } else {
+        Some(CStr::from_ptr(image as *const c_char).to_bytes().len())
+    }
+}
+
+/// The size of every parameter of a kernel, if `image` is PTX containing the kernel.
+fn kernel_params(image: &[u8], name: &str) -> Option<Vec<usize>> {
+    let ptx = std::str::from_utf8(image).ok()?;
+    let mut rest = ptx;
+    let params = loop {
+        let start = rest.find(".entry ")? + ".entry ".len();
+        rest = &rest[start..];
+        let after = rest.strip_prefix(name).map(str::trim_start);
+        if let Some(after) = after.and_then(|after| after.strip_prefix('(')) {
+            break &after[..after.find(')')?];
+        }
+    };
+
+    params
+        .split(',')
+        .filter(|param| !param.trim().is_empty())
+        .map(|param| {
+            let width = param
+                .split_whitespace()
+                .filter_map(|token| token.strip_prefix('.'))
+                .find_map(|ty| match ty.as_bytes().first() {
+                    Some(b'u' | b's' | b'b' | b'f') => ty[1..].parse::<usize>().ok(),
+                    _ => None,
+                })?;
+            let count = match (param.find('['), param.find(']')) {
+                (Some(start), Some(end)) => param[start + 1..end].trim().parse().ok()?,
+                _ => 1,
+            };
+            Some(width / 8 * count)
+        })
+        .collect()
+}
+
+/// Defines recording wrappers for driver functions in `$raw`.
+///
+/// `out` lists out-parameters whose values are recorded, `str` lists nul-terminated string
+/// arguments recorded by value, and `blob` is a host pointer and the length of the data it points
+/// to, which is saved next to the log.
+macro_rules! shim {
+    ($raw:ident => $(
+        fn $name:ident($($arg:ident: $ty:ty),* $(,)?)
+            $(out($($out:ident),*))?
+            $(str($($str:ident),*))?
+            $(blob($blob:ident, $len:ident))?;
+    )*) => {
+        $(
+            pub unsafe fn $name($($arg: $ty),*) -> CUresult {
+                let result = $raw::$name($($arg),*);
+                record(stringify!($name), result, |_call, _| {
+                    $(_call.arg(stringify!($arg), stringify!($ty), &$arg);)*
+                    $($(_call.out(stringify!($out), $out);)*)?
+                    $($(_call.string(stringify!($str), $str);)*)?
+                    $(_call.blob(stringify!($blob), $blob as *const c_void, $len);)?
+                });
+                result
+            }
+        )*
+    };
+}
+
+/// The driver API, recording every call while a capture is running.
+#[allow(non_snake_case, clippy::missing_safety_doc, clippy::too_many_arguments)]
+pub mod sys {
+    use super::{image_len, kernel_params, record};
+    use std::{
+        ffi::CStr,
+        fs,
+        os::raw::{c_char, c_int, c_uchar, c_uint, c_void},
+        slice,
+    };
+
+    pub use cust_raw::*;
+
+    shim! { cust_raw =>
+        fn cuArray3DCreate_v2(
+            pHandle: *mut CUarray,
+            pAllocateArray: *const CUDA_ARRAY3D_DESCRIPTOR,
+        ) out(pHandle);
+        fn cuArray3DGetDescriptor_v2(
+            pArrayDescriptor: *mut CUDA_ARRAY3D_DESCRIPTOR,
+            hArray: CUarray,
+        );
+        fn cuArrayDestroy(hArray: CUarray);
+        fn cuCtxCreate_v2(pctx: *mut CUcontext, flags: c_uint, dev: CUdevice) out(pctx);
+        fn cuCtxCreate_v3(
+            pctx: *mut CUcontext,
+            paramsArray: *mut CUexecAffinityParam,
+            numParams: c_int,
+            flags: c_uint,
+            dev: CUdevice,
+        ) out(pctx);
+        fn cuCtxDestroy_v2(ctx: CUcontext);
+        fn cuCtxGetApiVersion(ctx: CUcontext, version: *mut c_uint) out(version);
+        fn cuCtxGetCacheConfig(pconfig: *mut CUfunc_cache) out(pconfig);
+        fn cuCtxGetCurrent(pctx: *mut CUcontext) out(pctx);
+        fn cuCtxGetDevice(device: *mut CUdevice) out(device);
+        fn cuCtxGetExecAffinity(
+            pExecAffinity: *mut CUexecAffinityParam,
+            type_: CUexecAffinityType,
+        );
+        fn cuCtxGetFlags(flags: *mut c_uint) out(flags);
+        fn cuCtxGetLimit(pvalue: *mut usize, limit: CUlimit) out(pvalue);
+        fn cuCtxGetSharedMemConfig(pConfig: *mut CUsharedconfig) out(pConfig);
+        fn cuCtxGetStreamPriorityRange(
+            leastPriority: *mut c_int,
+            greatestPriority: *mut c_int,
+        ) out(leastPriority, greatestPriority);
+        fn cuCtxPopCurrent_v2(pctx: *mut CUcontext) out(pctx);
+        fn cuCtxPushCurrent_v2(ctx: CUcontext);
+        fn cuCtxResetPersistingL2Cache();
+        fn cuCtxSetCacheConfig(config: CUfunc_cache);
+        fn cuCtxSetCurrent(ctx: CUcontext);
+        fn cuCtxSetLimit(limit: CUlimit, value: usize);
+        fn cuCtxSetSharedMemConfig(config: CUsharedconfig);
+        fn cuCtxSynchronize();
+        fn cuDeviceGet(device: *mut CUdevice, ordinal: c_int) out(device);
+        fn cuDeviceGetAttribute(pi: *mut c_int, attrib: CUdevice_attribute, dev: CUdevice) out(pi);
+        fn cuDeviceGetByPCIBusId(dev: *mut CUdevice, pciBusId: *const c_char)
+            out(dev) str(pciBusId);
+        fn cuDeviceGetCount(count: *mut c_int) out(count);
+        fn cuDeviceGetExecAffinitySupport(
+            pi: *mut c_int,
+            type_: CUexecAffinityType,
+            dev: CUdevice,
+        ) out(pi);
+        fn cuDeviceGetName(name: *mut c_char, len: c_int, dev: CUdevice);
+        fn cuDeviceGetPCIBusId(pciBusId: *mut c_char, len: c_int, dev: CUdevice);
+        fn cuDeviceGetUuid(uuid: *mut CUuuid, dev: CUdevice) out(uuid);
+        fn cuDeviceGetUuid_v2(uuid: *mut CUuuid, dev: CUdevice) out(uuid);
+        fn cuDevicePrimaryCtxReset_v2(dev: CUdevice);
+        fn cuDeviceTotalMem_v2(bytes: *mut usize, dev: CUdevice) out(bytes);
+        fn cuDriverGetVersion(driverVersion: *mut c_int) out(driverVersion);
+        fn cuEventCreate(phEvent: *mut CUevent, Flags: c_uint) out(phEvent);
+        fn cuEventDestroy_v2(hEvent: CUevent);
+        fn cuEventElapsedTime(pMilliseconds: *mut f32, hStart: CUevent, hEnd: CUevent)
+            out(pMilliseconds);
+        fn cuEventQuery(hEvent: CUevent);
+        fn cuEventRecord(hEvent: CUevent, hStream: CUstream);
+        fn cuEventSynchronize(hEvent: CUevent);
+        fn cuFuncGetAttribute(pi: *mut c_int, attrib: CUfunction_attribute, hfunc: CUfunction)
+            out(pi);
+        fn cuFuncSetAttribute(hfunc: CUfunction, attrib: CUfunction_attribute, value: c_int);
+        fn cuFuncSetCacheConfig(hfunc: CUfunction, config: CUfunc_cache);
+        fn cuFuncSetSharedMemConfig(hfunc: CUfunction, config: CUsharedconfig);
+        fn cuGetErrorString(error: CUresult, pStr: *mut *const c_char) out(pStr);
+        fn cuGraphAddEmptyNode(
+            phGraphNode: *mut CUgraphNode,
+            hGraph: CUgraph,
+            dependencies: *const CUgraphNode,
+            numDependencies: usize,
+        ) out(phGraphNode);
+        fn cuGraphAddKernelNode(
+            phGraphNode: *mut CUgraphNode,
+            hGraph: CUgraph,
+            dependencies: *const CUgraphNode,
+            numDependencies: usize,
+            nodeParams: *const CUDA_KERNEL_NODE_PARAMS,
+        ) out(phGraphNode);
+        fn cuGraphAddMemcpyNode(
+            phGraphNode: *mut CUgraphNode,
+            hGraph: CUgraph,
+            dependencies: *const CUgraphNode,
+            numDependencies: usize,
+            copyParams: *const CUDA_MEMCPY3D,
+            ctx: CUcontext,
+        ) out(phGraphNode);
+        fn cuGraphCreate(phGraph: *mut CUgraph, flags: c_uint) out(phGraph);
+        fn cuGraphDebugDotPrint(hGraph: CUgraph, path: *const c_char, flags: c_uint) str(path);
+        fn cuGraphDestroy(hGraph: CUgraph);
+        fn cuGraphExecDestroy(hGraphExec: CUgraphExec);
+        fn cuGraphExecKernelNodeSetParams(
+            hGraphExec: CUgraphExec,
+            hNode: CUgraphNode,
+            nodeParams: *const CUDA_KERNEL_NODE_PARAMS,
+        );
+        fn cuGraphExecUpdate(
+            hGraphExec: CUgraphExec,
+            hGraph: CUgraph,
+            hErrorNode_out: *mut CUgraphNode,
+            updateResult_out: *mut CUgraphExecUpdateResult,
+        ) out(hErrorNode_out, updateResult_out);
+        fn cuGraphGetEdges(
+            hGraph: CUgraph,
+            from: *mut CUgraphNode,
+            to: *mut CUgraphNode,
+            numEdges: *mut usize,
+        ) out(numEdges);
+        fn cuGraphGetNodes(hGraph: CUgraph, nodes: *mut CUgraphNode, numNodes: *mut usize)
+            out(numNodes);
+        fn cuGraphInstantiate_v2(
+            phGraphExec: *mut CUgraphExec,
+            hGraph: CUgraph,
+            phErrorNode: *mut CUgraphNode,
+            logBuffer: *mut c_char,
+            bufferSize: usize,
+        ) out(phGraphExec);
+        fn cuGraphKernelNodeSetParams(
+            hNode: CUgraphNode,
+            nodeParams: *const CUDA_KERNEL_NODE_PARAMS,
+        );
+        fn cuGraphLaunch(hGraphExec: CUgraphExec, hStream: CUstream);
+        fn cuGraphNodeGetType(hNode: CUgraphNode, type_: *mut CUgraphNodeType) out(type_);
+        fn cuInit(Flags: c_uint);
+        fn cuLinkAddData_v2(
+            state: CUlinkState,
+            type_: CUjitInputType,
+            data: *mut c_void,
+            size: usize,
+            name: *const c_char,
+            numOptions: c_uint,
+            options: *mut CUjit_option,
+            optionValues: *mut *mut c_void,
+        ) str(name) blob(data, size);
+        fn cuLinkAddFile_v2(
+            state: CUlinkState,
+            type_: CUjitInputType,
+            path: *const c_char,
+            numOptions: c_uint,
+            options: *mut CUjit_option,
+            optionValues: *mut *mut c_void,
+        ) str(path);
+        fn cuLinkComplete(state: CUlinkState, cubinOut: *mut *mut c_void, sizeOut: *mut usize)
+            out(cubinOut, sizeOut);
+        fn cuLinkCreate_v2(
+            numOptions: c_uint,
+            options: *mut CUjit_option,
+            optionValues: *mut *mut c_void,
+            stateOut: *mut CUlinkState,
+        ) out(stateOut);
+        fn cuLinkDestroy(state: CUlinkState);
+        fn cuMemAdvise(devPtr: CUdeviceptr, count: usize, advice: CUmem_advise, device: CUdevice);
+        fn cuMemAllocHost_v2(pp: *mut *mut c_void, bytesize: usize) out(pp);
+        fn cuMemAllocManaged(dptr: *mut CUdeviceptr, bytesize: usize, flags: c_uint) out(dptr);
+        fn cuMemAllocPitch_v2(
+            dptr: *mut CUdeviceptr,
+            pPitch: *mut usize,
+            WidthInBytes: usize,
+            Height: usize,
+            ElementSizeBytes: c_uint,
+        ) out(dptr, pPitch);
+        fn cuMemAlloc_v2(dptr: *mut CUdeviceptr, bytesize: usize) out(dptr);
+        fn cuMemFreeHost(p: *mut c_void);
+        fn cuMemFree_v2(dptr: CUdeviceptr);
+        fn cuMemHostRegister_v2(p: *mut c_void, bytesize: usize, Flags: c_uint);
+        fn cuMemHostUnregister(p: *mut c_void);
+        fn cuMemPrefetchAsync(
+            devPtr: CUdeviceptr,
+            count: usize,
+            dstDevice: CUdevice,
+            hStream: CUstream,
+        );
+        fn cuMemcpy2D_v2(pCopy: *const CUDA_MEMCPY2D);
+        fn cuMemcpy2DAsync_v2(pCopy: *const CUDA_MEMCPY2D, hStream: CUstream);
+        fn cuMemcpyAtoH_v2(
+            dstHost: *mut c_void,
+            srcArray: CUarray,
+            srcOffset: usize,
+            ByteCount: usize,
+        );
+        fn cuMemcpyDtoDAsync_v2(
+            dstDevice: CUdeviceptr,
+            srcDevice: CUdeviceptr,
+            ByteCount: usize,
+            hStream: CUstream,
+        );
+        fn cuMemcpyDtoD_v2(dstDevice: CUdeviceptr, srcDevice: CUdeviceptr, ByteCount: usize);
+        fn cuMemcpyDtoHAsync_v2(
+            dstHost: *mut c_void,
+            srcDevice: CUdeviceptr,
+            ByteCount: usize,
+            hStream: CUstream,
+        );
+        fn cuMemcpyDtoH_v2(dstHost: *mut c_void, srcDevice: CUdeviceptr, ByteCount: usize);
+        fn cuMemcpyHtoA_v2(
+            dstArray: CUarray,
+            dstOffset: usize,
+            srcHost: *const c_void,
+            ByteCount: usize,
+        ) blob(srcHost, ByteCount);
+        fn cuMemcpyHtoDAsync_v2(
+            dstDevice: CUdeviceptr,
+            srcHost: *const c_void,
+            ByteCount: usize,
+            hStream: CUstream,
+        ) blob(srcHost, ByteCount);
+        fn cuMemcpyHtoD_v2(dstDevice: CUdeviceptr, srcHost: *const c_void, ByteCount: usize)
+            blob(srcHost, ByteCount);
+        fn cuMemsetD8_v2(dstDevice: CUdeviceptr, uc: c_uchar, N: usize);
+        fn cuModuleGetGlobal_v2(
+            dptr: *mut CUdeviceptr,
+            bytes: *mut usize,
+            hmod: CUmodule,
+            name: *const c_char,
+        ) out(dptr, bytes) str(name);
+        fn cuModuleUnload(hmod: CUmodule);
+        fn cuOccupancyAvailableDynamicSMemPerBlock(
+            dynamicSmemSize: *mut usize,
+            func: CUfunction,
+            numBlocks: c_int,
+            blockSize: c_int,
+        ) out(dynamicSmemSize);
+        fn cuOccupancyMaxActiveBlocksPerMultiprocessor(
+            numBlocks: *mut c_int,
+            func: CUfunction,
+            blockSize: c_int,
+            dynamicSMemSize: usize,
+        ) out(numBlocks);
+        fn cuOccupancyMaxPotentialBlockSize(
+            minGridSize: *mut c_int,
+            blockSize: *mut c_int,
+            func: CUfunction,
+            blockSizeToDynamicSMemSize: CUoccupancyB2DSize,
+            dynamicSMemSize: usize,
+            blockSizeLimit: c_int,
+        ) out(minGridSize, blockSize);
+        fn cuStreamAddCallback(
+            hStream: CUstream,
+            callback: CUstreamCallback,
+            userData: *mut c_void,
+            flags: c_uint,
+        );
+        fn cuStreamCreateWithPriority(phStream: *mut CUstream, flags: c_uint, priority: c_int)
+            out(phStream);
+        fn cuStreamDestroy_v2(hStream: CUstream);
+        fn cuStreamGetFlags(hStream: CUstream, flags: *mut c_uint) out(flags);
+        fn cuStreamGetPriority(hStream: CUstream, priority: *mut c_int) out(priority);
+        fn cuStreamQuery(hStream: CUstream);
+        fn cuStreamSetAttribute(
+            hStream: CUstream,
+            attr: CUstreamAttrID,
+            value: *const CUstreamAttrValue,
+        );
+        fn cuStreamSynchronize(hStream: CUstream);
+        fn cuStreamWaitEvent(hStream: CUstream, hEvent: CUevent, Flags: c_uint);
+        fn cuSurfObjectCreate(pSurfObject: *mut CUsurfObject, pResDesc: *const CUDA_RESOURCE_DESC)
+            out(pSurfObject);
+        fn cuSurfObjectDestroy(surfObject: CUsurfObject);
+        fn cuSurfObjectGetResourceDesc(
+            pResDesc: *mut CUDA_RESOURCE_DESC,
+            surfObject: CUsurfObject,
+        );
+        fn cuTexObjectCreate(
+            pTexObject: *mut CUtexObject,
+            pResDesc: *const CUDA_RESOURCE_DESC,
+            pTexDesc: *const CUDA_TEXTURE_DESC,
+            pResViewDesc: *const CUDA_RESOURCE_VIEW_DESC,
+        ) out(pTexObject);
+        fn cuTexObjectDestroy(texObject: CUtexObject);
+        fn cuTexObjectGetResourceDesc(pResDesc: *mut CUDA_RESOURCE_DESC, texObject: CUtexObject);
+        fn cuTexObjectGetResourceViewDesc(
+            pResViewDesc: *mut CUDA_RESOURCE_VIEW_DESC,
+            texObject: CUtexObject,
+        );
+    }
+
+    #[cfg(feature = "per-thread-default-stream")]
+    mod ptds {
+        use super::*;
+
+        extern "C" {
+            pub fn cuMemcpyHtoD_v2_ptds(
+                dst: CUdeviceptr,
+                src: *const c_void,
+                size: usize,
+            ) -> CUresult;
+            pub fn cuMemcpyDtoH_v2_ptds(
+                dst: *mut c_void,
+                src: CUdeviceptr,
+                size: usize,
+            ) -> CUresult;
+            pub fn cuMemcpyDtoD_v2_ptds(
+                dst: CUdeviceptr,
+                src: CUdeviceptr,
+                size: usize,
+            ) -> CUresult;
+            pub fn cuMemsetD8_v2_ptds(dst: CUdeviceptr, value: c_uchar, len: usize) -> CUresult;
+            pub fn cuMemcpy2D_v2_ptds(pCopy: *const CUDA_MEMCPY2D) -> CUresult;
+            pub fn cuMemcpyHtoA_v2_ptds(
+                dst: CUarray,
+                offset: usize,
+                src: *const c_void,
+                size: usize,
+            ) -> CUresult;
+            pub fn cuMemcpyAtoH_v2_ptds(
+                dst: *mut c_void,
+                src: CUarray,
+                offset: usize,
+                size: usize,
+            ) -> CUresult;
+        }
+    }
+
+    #[cfg(feature = "per-thread-default-stream")]
+    shim! { ptds =>
+        fn cuMemcpyHtoD_v2_ptds(dstDevice: CUdeviceptr, srcHost: *const c_void, ByteCount: usize)
+            blob(srcHost, ByteCount);
+        fn cuMemcpyDtoH_v2_ptds(dstHost: *mut c_void, srcDevice: CUdeviceptr, ByteCount: usize);
+        fn cuMemcpyDtoD_v2_ptds(dstDevice: CUdeviceptr, srcDevice: CUdeviceptr, ByteCount: usize);
+        fn cuMemsetD8_v2_ptds(dstDevice: CUdeviceptr, uc: c_uchar, N: usize);
+        fn cuMemcpy2D_v2_ptds(pCopy: *const CUDA_MEMCPY2D);
+        fn cuMemcpyHtoA_v2_ptds(
+            dstArray: CUarray,
+            dstOffset: usize,
+            srcHost: *const c_void,
+            ByteCount: usize,
+        ) blob(srcHost, ByteCount);
+        fn cuMemcpyAtoH_v2_ptds(
+            dstHost: *mut c_void,
+            srcArray: CUarray,
+            srcOffset: usize,
+            ByteCount: usize,
+        );
+    }
+
+    // module loads and launches also keep track of kernel parameters.
+
+    pub unsafe fn cuModuleLoad(module: *mut CUmodule, fname: *const c_char) -> CUresult {
+        let result = cust_raw::cuModuleLoad(module, fname);
+        record("cuModuleLoad", result, |call, recorder| {
+            call.arg("module", "*mut CUmodule", &module);
+            call.arg("fname", "*const c_char", &fname);
+            call.out("module", module);
+            call.string("fname", fname);
+            if fname.is_null() {
+                return;
+            }
+            let path = CStr::from_ptr(fname).to_string_lossy();
+            if let (true, Ok(image)) = (call.success, fs::read(&*path)) {
+                recorder.modules.insert(*module as usize, image);
+            }
+        });
+        result
+    }
+
+    pub unsafe fn cuModuleLoadData(module: *mut CUmodule, image: *const c_void) -> CUresult {
+        let result = cust_raw::cuModuleLoadData(module, image);
+        record("cuModuleLoadData", result, |call, recorder| {
+            call.arg("module", "*mut CUmodule", &module);
+            call.arg("image", "*const c_void", &image);
+            call.out("module", module);
+            if let Some(len) = image_len(image) {
+                call.blob("image", image, len);
+                if call.success {
+                    let image = slice::from_raw_parts(image as *const u8, len).to_vec();
+                    recorder.modules.insert(*module as usize, image);
+                }
+            }
+        });
+        result
+    }
+
+    pub unsafe fn cuModuleGetFunction(
+        hfunc: *mut CUfunction,
+        hmod: CUmodule,
+        name: *const c_char,
+    ) -> CUresult {
+        let result = cust_raw::cuModuleGetFunction(hfunc, hmod, name);
+        record("cuModuleGetFunction", result, |call, recorder| {
+            call.arg("hfunc", "*mut CUfunction", &hfunc);
+            call.arg("hmod", "CUmodule", &hmod);
+            call.arg("name", "*const c_char", &name);
+            call.out("hfunc", hfunc);
+            call.string("name", name);
+            if name.is_null() {
+                return;
+            }
+            let name = CStr::from_ptr(name).to_string_lossy();
+            let params = recorder
+                .modules
+                .get(&(hmod as usize))
+                .and_then(|image| kernel_params(image, &name));
+            if let (true, Some(params)) = (call.success, params) {
+                recorder.kernels.insert(*hfunc as usize, params);
+            }
+        });
+        result
+    }
+
+    pub unsafe fn cuLaunchKernel(
+        f: CUfunction,
+        gridDimX: c_uint,
+        gridDimY: c_uint,
+        gridDimZ: c_uint,
+        blockDimX: c_uint,
+        blockDimY: c_uint,
+        blockDimZ: c_uint,
+        sharedMemBytes: c_uint,
+        hStream: CUstream,
+        kernelParams: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) -> CUresult {
+        let result = cust_raw::cuLaunchKernel(
+            f,
+            gridDimX,
+            gridDimY,
+            gridDimZ,
+            blockDimX,
+            blockDimY,
+            blockDimZ,
+            sharedMemBytes,
+            hStream,
+            kernelParams,
+            extra,
+        );
+        record("cuLaunchKernel", result, |call, recorder| {
+            call.arg("f", "CUfunction", &f);
+            call.arg("gridDimX", "c_uint", &gridDimX);
+            call.arg("gridDimY", "c_uint", &gridDimY);
+            call.arg("gridDimZ", "c_uint", &gridDimZ);
+            call.arg("blockDimX", "c_uint", &blockDimX);
+            call.arg("blockDimY", "c_uint", &blockDimY);
+            call.arg("blockDimZ", "c_uint", &blockDimZ);
+            call.arg("sharedMemBytes", "c_uint", &sharedMemBytes);
+            call.arg("hStream", "CUstream", &hStream);
+            call.arg("kernelParams", "*mut *mut c_void", &kernelParams);
+            call.arg("extra", "*mut *mut c_void", &extra);
+            call.kernel_params("kernelParams", recorder, f, kernelParams);
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finds_kernel_params() {
+        let ptx = b"
+.visible .entry add2(
+    .param .u64 add2_param_0
+)
+{
+    ret;
+}
+
+.visible .entry add(
+    .param .u64 add_param_0,
+    .param .align 8 .b8 add_param_1[24],
+    .param .f32 add_param_2
+)
+{
+    ret;
+}
+";
+        assert_eq!(kernel_params(ptx, "add"), Some(vec![8, 24, 4]));
+        assert_eq!(kernel_params(ptx, "add2"), Some(vec![8]));
+        assert_eq!(kernel_params(ptx, "sub"), None);
+    }
+
+    #[test]
+    fn keeps_errors() {
+        let e = io::Error::new(io::ErrorKind::Other, "disk full");
+        fail("failed to record driver call", e);
+        let e = take_error().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::Other);
+        assert_eq!(e.to_string(), "failed to record driver call: disk full");
+        assert!(take_error().is_none());
+    }
+}
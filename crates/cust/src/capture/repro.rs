@@ -0,0 +1,477 @@
+//! Reading captures and turning them into standalone C programs.
+
+use super::LOG;
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+/// Driver calls which only query state, which are left out of reproductions unless they are the
+/// call that failed.
+const QUERIES: &[&str] = &[
+    "cuArray3DGetDescriptor_v2",
+    "cuCtxGetApiVersion",
+    "cuCtxGetCacheConfig",
+    "cuCtxGetDevice",
+    "cuCtxGetExecAffinity",
+    "cuCtxGetFlags",
+    "cuCtxGetLimit",
+    "cuCtxGetSharedMemConfig",
+    "cuCtxGetStreamPriorityRange",
+    "cuDeviceGetAttribute",
+    "cuDeviceGetCount",
+    "cuDeviceGetExecAffinitySupport",
+    "cuDeviceGetName",
+    "cuDeviceGetPCIBusId",
+    "cuDeviceGetUuid",
+    "cuDeviceGetUuid_v2",
+    "cuDeviceTotalMem_v2",
+    "cuDriverGetVersion",
+    "cuEventElapsedTime",
+    "cuEventQuery",
+    "cuFuncGetAttribute",
+    "cuGetErrorString",
+    "cuGraphDebugDotPrint",
+    "cuGraphGetEdges",
+    "cuGraphGetNodes",
+    "cuGraphNodeGetType",
+    "cuOccupancyAvailableDynamicSMemPerBlock",
+    "cuOccupancyMaxActiveBlocksPerMultiprocessor",
+    "cuOccupancyMaxPotentialBlockSize",
+    "cuStreamGetFlags",
+    "cuStreamGetPriority",
+    "cuStreamQuery",
+    "cuSurfObjectGetResourceDesc",
+    "cuTexObjectGetResourceDesc",
+    "cuTexObjectGetResourceViewDesc",
+];
+
+/// Results which are part of normal operation rather than a failure.
+const NOT_ERRORS: &[&str] = &["CUDA_SUCCESS", "CUDA_ERROR_NOT_READY"];
+
+/// An argument of a recorded driver call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Arg {
+    /// The name of the parameter in the driver API.
+    pub name: String,
+    /// The Rust type of the parameter, like `*mut CUdeviceptr`.
+    pub ty: String,
+    /// The value of the argument, formatted with `Debug`. String arguments are recorded as the
+    /// string itself, arguments whose data was saved to a file as `@file`, and kernel parameters
+    /// as the hex encoded bytes of every parameter, like `[0010,ff]`.
+    pub value: String,
+}
+
+/// A recorded driver call.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    /// The index of the call, in the order calls returned.
+    pub index: usize,
+    /// The name of the driver function, like `cuMemAlloc_v2`.
+    pub name: String,
+    /// The result of the call, like `CUDA_SUCCESS`.
+    pub result: String,
+    pub args: Vec<Arg>,
+    /// The values written to out-parameters by parameter name, `?` if the call failed.
+    pub outs: Vec<(String, String)>,
+}
+
+impl Call {
+    /// Whether the call failed.
+    pub fn failed(&self) -> bool {
+        !NOT_ERRORS.contains(&self.result.as_str())
+    }
+
+    fn arg(&self, name: &str) -> Option<&Arg> {
+        self.args.iter().find(|arg| arg.name == name)
+    }
+}
+
+/// A capture of driver calls, written by [`start`](super::start).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    calls: Vec<Call>,
+}
+
+impl Trace {
+    /// Reads the capture in `dir`.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let log = fs::read_to_string(dir.as_ref().join(LOG))?;
+        Self::parse(&log).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "malformed driver call capture")
+        })
+    }
+
+    fn parse(log: &str) -> Option<Self> {
+        let mut calls = Vec::new();
+        for line in log.lines().filter(|line| !line.starts_with('#')) {
+            let mut fields = line.split('\t');
+            let mut call = Call {
+                index: fields.next()?.parse().ok()?,
+                name: fields.next()?.to_string(),
+                result: fields.next()?.to_string(),
+                args: Vec::new(),
+                outs: Vec::new(),
+            };
+            for field in fields {
+                let (key, value) = field.split_once('=')?;
+                if let Some(out) = key.strip_prefix('&') {
+                    call.outs.push((out.to_string(), value.to_string()));
+                } else {
+                    let (name, ty) = key.split_once(':')?;
+                    call.args.push(Arg {
+                        name: name.to_string(),
+                        ty: ty.to_string(),
+                        value: value.to_string(),
+                    });
+                }
+            }
+            calls.push(call);
+        }
+        Some(Self { calls })
+    }
+
+    /// The recorded calls, in the order they returned.
+    pub fn calls(&self) -> &[Call] {
+        &self.calls
+    }
+
+    /// The first call which failed, if any.
+    pub fn first_error(&self) -> Option<&Call> {
+        self.calls.iter().find(|call| call.failed())
+    }
+
+    /// Writes a C program which makes the calls of this capture up to and including the first
+    /// failed call, leaving out calls which only query state. The program checks that every call
+    /// returns the same result as when it was captured.
+    ///
+    /// The program reads saved data relative to the current directory, so it must be run from the
+    /// capture directory. It can be built with `cc repro.c -I$CUDA_PATH/include -lcuda`.
+    pub fn write_repro<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let end = self
+            .first_error()
+            .map_or(self.calls.len(), |call| call.index + 1);
+        let calls = self.calls.iter().filter(|call| call.index < end);
+
+        let mut repro = Repro::default();
+        let mut body = String::new();
+        for call in calls {
+            if QUERIES.contains(&call.name.as_str()) && !call.failed() {
+                continue;
+            }
+            repro.call(call, &mut body);
+        }
+
+        if repro.ptds {
+            writeln!(out, "#define CUDA_API_PER_THREAD_DEFAULT_STREAM")?;
+        }
+        write!(out, "{}", PRELUDE)?;
+        write!(out, "{}", body)?;
+        writeln!(out, "    return 0;\n}}")
+    }
+}
+
+const PRELUDE: &str = r#"// Reproduction generated from a capture of driver calls made by cust.
+#include <cuda.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define EXPECT(call, expected)                                                            \
+    do {                                                                                  \
+        CUresult res_ = (call);                                                           \
+        if (res_ != (expected)) {                                                         \
+            const char *name_ = NULL;                                                     \
+            cuGetErrorName(res_, &name_);                                                 \
+            fprintf(stderr, "line %d: %s returned %s, expected %s\n", __LINE__, #call,    \
+                    name_ ? name_ : "an unknown error", #expected);                       \
+        }                                                                                 \
+    } while (0)
+
+static void *read_blob(const char *path) {
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        fprintf(stderr, "failed to open %s\n", path);
+        exit(1);
+    }
+    fseek(file, 0, SEEK_END);
+    long len = ftell(file);
+    fseek(file, 0, SEEK_SET);
+    // nul-terminated for PTX images.
+    char *data = calloc(len + 1, 1);
+    if (fread(data, 1, len, file) != (size_t)len) {
+        fprintf(stderr, "failed to read %s\n", path);
+        exit(1);
+    }
+    fclose(file);
+    return data;
+}
+
+int main(void) {
+"#;
+
+#[derive(Default)]
+struct Repro {
+    /// Variables holding values returned by earlier calls, by their recorded value.
+    vars: HashMap<String, String>,
+    /// Device allocations as the base address, size, and variable holding the address.
+    allocs: Vec<(u64, u64, String)>,
+    ptds: bool,
+}
+
+impl Repro {
+    fn call(&mut self, call: &Call, body: &mut String) {
+        let mut setup = String::new();
+        let mut args = Vec::new();
+        for arg in &call.args {
+            match self.arg(call, arg, &mut setup) {
+                Ok(arg) => args.push(arg),
+                Err(reason) => {
+                    // writing to a String cannot fail.
+                    let _ = writeln!(
+                        body,
+                        "    // {}: {} -> {} cannot be replayed, {} `{}`",
+                        call.index, call.name, call.result, reason, arg.name
+                    );
+                    return;
+                }
+            }
+        }
+
+        let name = match call.name.strip_suffix("_ptds") {
+            Some(name) => {
+                self.ptds = true;
+                name
+            }
+            None => &call.name,
+        };
+        let _ = write!(body, "{}", setup);
+        let _ = writeln!(
+            body,
+            "    EXPECT({}({}), {}); // {}",
+            name,
+            args.join(", "),
+            call.result,
+            call.index
+        );
+
+        for (out, value) in &call.outs {
+            if !["0", "0x0", "?"].contains(&value.as_str()) {
+                self.vars.insert(value.clone(), var(call, out));
+            }
+        }
+        let size = match call.name.as_str() {
+            "cuMemAlloc_v2" | "cuMemAllocManaged" => call.arg("bytesize").map(|arg| &arg.value),
+            "cuModuleGetGlobal_v2" => out(call, "bytes"),
+            _ => None,
+        };
+        if let (Some(base), Some(size)) = (out(call, "dptr"), size) {
+            if let (Ok(base), Ok(size)) = (base.parse(), size.parse()) {
+                self.allocs.push((base, size, var(call, "dptr")));
+            }
+        }
+    }
+
+    fn arg(&self, call: &Call, arg: &Arg, setup: &mut String) -> Result<String, &'static str> {
+        let value = arg.value.as_str();
+        let ty = normalize(&arg.ty);
+
+        if out(call, &arg.name).is_some() {
+            let _ = writeln!(
+                setup,
+                "    {} {};",
+                c_type(pointee(&ty).ok_or("unknown out-parameter")?),
+                var(call, &arg.name)
+            );
+            return Ok(format!("&{}", var(call, &arg.name)));
+        }
+        if let Some(file) = value.strip_prefix('@') {
+            let name = var(call, &arg.name);
+            let _ = writeln!(setup, "    void *{} = read_blob(\"{}\");", name, file);
+            return Ok(name);
+        }
+        if let Some(params) = value.strip_prefix('[') {
+            return Ok(self.params(call, params.trim_end_matches(']'), setup));
+        }
+        if value.starts_with('"') {
+            return Ok(value.to_string());
+        }
+        if value == "0x0" || value == "None" {
+            return Ok("NULL".to_string());
+        }
+        if let Some(var) = self.vars.get(value) {
+            return Ok(var.clone());
+        }
+        if ty == "*mut c_void" {
+            // a host buffer which the call writes to.
+            if let Some(len) = call.arg("ByteCount") {
+                let name = var(call, &arg.name);
+                let _ = writeln!(setup, "    void *{} = malloc({});", name, len.value);
+                return Ok(name);
+            }
+        }
+        if ty == "CUdeviceptr" {
+            return value
+                .parse()
+                .ok()
+                .and_then(|ptr| self.device_ptr(ptr))
+                .ok_or("unknown device pointer");
+        }
+        if let Some(handle) = value.strip_prefix("0x") {
+            // special handles like CU_STREAM_PER_THREAD.
+            return match u64::from_str_radix(handle, 16) {
+                Ok(handle) if handle < 0x10 => Ok(format!("({}){}", c_type(&ty), value)),
+                _ => Err("unknown pointer"),
+            };
+        }
+        if ty.starts_with('*') || value.starts_with("Some") {
+            return Err("unknown pointer");
+        }
+        Ok(value.to_string())
+    }
+
+    /// The address of a device allocation (or somewhere in one) as an expression.
+    fn device_ptr(&self, ptr: u64) -> Option<String> {
+        let (base, _, var) = self
+            .allocs
+            .iter()
+            .find(|(base, size, _)| (*base..*base + *size).contains(&ptr))?;
+        Some(match ptr - base {
+            0 => var.clone(),
+            offset => format!("({} + {})", var, offset),
+        })
+    }
+
+    fn params(&self, call: &Call, params: &str, setup: &mut String) -> String {
+        let name = var(call, "params");
+        let mut ptrs = Vec::new();
+        for (i, param) in params.split(',').filter(|p| !p.is_empty()).enumerate() {
+            let bytes = (0..param.len())
+                .step_by(2)
+                .filter_map(|i| u8::from_str_radix(param.get(i..i + 2)?, 16).ok())
+                .collect::<Vec<_>>();
+            let val = bytes
+                .iter()
+                .rev()
+                .fold(0u64, |val, byte| (val << 8) | *byte as u64);
+            match self.device_ptr(val) {
+                Some(ptr) if bytes.len() == 8 => {
+                    let _ = writeln!(setup, "    CUdeviceptr {}_{} = {};", name, i, ptr);
+                    ptrs.push(format!("&{}_{}", name, i));
+                }
+                _ => {
+                    let bytes = bytes
+                        .iter()
+                        .map(|byte| format!("0x{:02x}", byte))
+                        .collect::<Vec<_>>();
+                    let _ = writeln!(
+                        setup,
+                        "    unsigned char {}_{}[] = {{{}}};",
+                        name,
+                        i,
+                        bytes.join(", ")
+                    );
+                    ptrs.push(format!("{}_{}", name, i));
+                }
+            }
+        }
+        let _ = writeln!(setup, "    void *{}[] = {{{}}};", name, ptrs.join(", "));
+        name
+    }
+}
+
+fn out<'a>(call: &'a Call, name: &str) -> Option<&'a String> {
+    call.outs
+        .iter()
+        .find(|(out, _)| out == name)
+        .map(|(_, value)| value)
+}
+
+fn var(call: &Call, name: &str) -> String {
+    format!("{}_{}", name, call.index)
+}
+
+/// Normalizes the spacing of a type, `* mut T` and `*mut T` are both `*mut T`.
+fn normalize(ty: &str) -> String {
+    ty.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("* mut", "*mut")
+        .replace("* const", "*const")
+}
+
+fn pointee(ty: &str) -> Option<&str> {
+    ty.strip_prefix("*mut ")
+        .or_else(|| ty.strip_prefix("*const "))
+}
+
+fn c_type(ty: &str) -> String {
+    if let Some(pointee) = ty.strip_prefix("*mut ") {
+        return format!("{} *", c_type(pointee));
+    }
+    if let Some(pointee) = ty.strip_prefix("*const ") {
+        return format!("const {} *", c_type(pointee));
+    }
+    match ty {
+        "c_char" => "char",
+        "c_int" => "int",
+        "c_uchar" => "unsigned char",
+        "c_uint" => "unsigned int",
+        "c_void" => "void",
+        "f32" => "float",
+        "u32" => "unsigned int",
+        "u64" => "unsigned long long",
+        "usize" => "size_t",
+        ty => ty,
+    }
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRACE: &str = "# cust driver call capture
+0\tcuInit\tCUDA_SUCCESS\tFlags:c_uint=0
+1\tcuDeviceGet\tCUDA_SUCCESS\tdevice:*mut CUdevice=0x7ffc10\tordinal:c_int=0\t&device=0
+2\tcuCtxCreate_v2\tCUDA_SUCCESS\tpctx:*mut CUcontext=0x7ffc18\tflags:c_uint=0\tdev:CUdevice=0\t&pctx=0x55d000
+3\tcuDriverGetVersion\tCUDA_SUCCESS\tdriverVersion:*mut c_int=0x7ffc20\t&driverVersion=11040
+4\tcuModuleLoadData\tCUDA_SUCCESS\tmodule:*mut CUmodule=0x7ffc28\timage:*const c_void=@4-image.bin\t&module=0x55e000
+5\tcuModuleGetFunction\tCUDA_SUCCESS\thfunc:*mut CUfunction=0x7ffc30\thmod:CUmodule=0x55e000\tname:*const c_char=\"add\"\t&hfunc=0x55f000
+6\tcuMemAlloc_v2\tCUDA_SUCCESS\tdptr:*mut CUdeviceptr=0x7ffc38\tbytesize:usize=64\t&dptr=4096
+7\tcuMemcpyHtoD_v2\tCUDA_SUCCESS\tdstDevice:CUdeviceptr=4096\tsrcHost:*const c_void=@7-srcHost.bin\tByteCount:usize=64
+8\tcuLaunchKernel\tCUDA_ERROR_ILLEGAL_ADDRESS\tf:CUfunction=0x55f000\tgridDimX:c_uint=1\tgridDimY:c_uint=1\tgridDimZ:c_uint=1\tblockDimX:c_uint=32\tblockDimY:c_uint=1\tblockDimZ:c_uint=1\tsharedMemBytes:c_uint=0\thStream:CUstream=0x0\tkernelParams:*mut *mut c_void=[1010000000000000,07000000]\textra:*mut *mut c_void=0x0
+9\tcuCtxSynchronize\tCUDA_ERROR_ILLEGAL_ADDRESS
+";
+
+    #[test]
+    fn parses_log() {
+        let trace = Trace::parse(TRACE).unwrap();
+        assert_eq!(trace.calls().len(), 10);
+        assert_eq!(trace.first_error().unwrap().index, 8);
+        assert_eq!(
+            trace.calls()[6].outs,
+            vec![("dptr".to_string(), "4096".to_string())]
+        );
+    }
+
+    #[test]
+    fn writes_repro() {
+        let mut out = Vec::new();
+        Trace::parse(TRACE).unwrap().write_repro(&mut out).unwrap();
+        let repro = String::from_utf8(out).unwrap();
+
+        assert!(repro.contains("EXPECT(cuCtxCreate_v2(&pctx_2, 0, 0), CUDA_SUCCESS); // 2"));
+        assert!(!repro.contains("cuDriverGetVersion"));
+        assert!(repro.contains("void *image_4 = read_blob(\"4-image.bin\");"));
+        assert!(repro.contains("cuModuleGetFunction(&hfunc_5, module_4, \"add\")"));
+        assert!(repro.contains("cuMemcpyHtoD_v2(dptr_6, srcHost_7, 64)"));
+        assert!(repro.contains("CUdeviceptr params_8_0 = (dptr_6 + 16);"));
+        assert!(repro.contains("unsigned char params_8_1[] = {0x07, 0x00, 0x00, 0x00};"));
+        assert!(repro.contains("&params_8_0, params_8_1"));
+        assert!(repro.contains("NULL, params_8, NULL), CUDA_ERROR_ILLEGAL_ADDRESS); // 8"));
+        assert!(!repro.contains("cuCtxSynchronize"));
+    }
+}
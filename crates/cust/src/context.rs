@@ -114,11 +114,13 @@
 
 use crate::device::Device;
 use crate::error::{CudaResult, DropResult, ToResult};
+use crate::module::Module;
 use crate::private::Sealed;
 use crate::sys::{self as cuda, CUcontext};
 use crate::CudaApiVersion;
 use std::mem;
 use std::mem::transmute;
+use std::path::PathBuf;
 use std::ptr;
 
 /// This enumeration represents configuration settings for devices which share hardware resources
@@ -169,6 +171,9 @@ pub enum ResourceLimit {
     DeviceRuntimePendingLaunchCount = 4,
     /// L2 cache fetch granularity
     MaxL2FetchGranularity = 5,
+    /// The size in bytes of L2 cache set aside for persisting accesses, see
+    /// [`Stream::set_access_policy_window`](crate::stream::Stream::set_access_policy_window).
+    PersistingL2CacheSize = 6,
 }
 
 /// This enumeration represents the options for configuring the shared memory bank size.
@@ -223,6 +228,40 @@ bitflags::bitflags! {
     }
 }
 
+impl Default for ContextFlags {
+    /// `MAP_HOST | SCHED_AUTO`, the flags used by [`quick_init`](crate::quick_init).
+    fn default() -> Self {
+        ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO
+    }
+}
+
+/// A limit on the execution resources a context may use, passed to
+/// [`Context::create_with_affinity`].
+///
+/// Execution affinity is currently only supported with MPS (the Multi-Process Service) or on devices
+/// which support it natively, use [`Device::supports_sm_count_affinity`] to check for support.
+#[non_exhaustive]
+#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+pub enum ExecAffinity {
+    /// Limits the context to (at most) the given number of SMs. The driver may round this up
+    /// to a granularity supported by the hardware, use [`CurrentContext::get_sm_count_affinity`]
+    /// to query the actual number of SMs available to the context.
+    SmCount(u32),
+}
+
+impl ExecAffinity {
+    fn to_raw(self) -> cuda::CUexecAffinityParam {
+        match self {
+            ExecAffinity::SmCount(val) => cuda::CUexecAffinityParam {
+                type_: cuda::CUexecAffinityType::CU_EXEC_AFFINITY_TYPE_SM_COUNT,
+                param: cuda::CUexecAffinityParam_st__bindgen_ty_1 {
+                    smCount: cuda::CUexecAffinitySmCount { val },
+                },
+            },
+        }
+    }
+}
+
 /// Owned handle to a CUDA context.
 ///
 /// The context will be destroyed when this goes out of scope. If this is the current context on
@@ -251,6 +290,7 @@ impl Context {
     /// # }
     /// ```
     pub fn create_and_push(flags: ContextFlags, device: Device) -> CudaResult<Context> {
+        let _span = trace_span!("cust::context_create", device = device.as_raw(), flags = ?flags);
         unsafe {
             // CUDA only provides a create-and-push operation, but that makes it hard to provide
             // lifetime guarantees so we create-and-push, then pop, then the programmer has to
@@ -262,6 +302,56 @@ impl Context {
         }
     }
 
+    /// Create a CUDA context for the given device, limited to the given execution resources.
+    ///
+    /// This is useful for services which share a GPU (usually through MPS), limiting every context
+    /// to a subset of the SMs keeps one workload from starving the others.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CudaError::UnsupportedExecAffinity`](crate::error::CudaError::UnsupportedExecAffinity)
+    /// if the device does not support the requested affinity.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use cust::device::Device;
+    /// # use cust::context::{Context, ContextFlags, CurrentContext, ExecAffinity};
+    /// # use std::error::Error;
+    /// #
+    /// # fn main () -> Result<(), Box<dyn Error>> {
+    /// cust::init(cust::CudaFlags::empty())?;
+    /// let device = Device::get_device(0)?;
+    /// let context = Context::create_with_affinity(
+    ///     ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO,
+    ///     device,
+    ///     &[ExecAffinity::SmCount(8)],
+    /// )?;
+    /// println!("running on {} SMs", CurrentContext::get_sm_count_affinity()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn create_with_affinity(
+        flags: ContextFlags,
+        device: Device,
+        affinity: &[ExecAffinity],
+    ) -> CudaResult<Context> {
+        let _span = trace_span!("cust::context_create", device = device.as_raw(), flags = ?flags);
+        let mut params = affinity.iter().map(|a| a.to_raw()).collect::<Vec<_>>();
+        unsafe {
+            let mut ctx: CUcontext = ptr::null_mut();
+            cuda::cuCtxCreate_v3(
+                &mut ctx as *mut CUcontext,
+                params.as_mut_ptr(),
+                params.len() as i32,
+                flags.bits(),
+                device.as_raw(),
+            )
+            .to_result()?;
+            Ok(Context { inner: ctx })
+        }
+    }
+
     /// Get the API version used to create this context.
     ///
     /// This is not necessarily the latest version supported by the driver.
@@ -533,6 +623,37 @@ impl CurrentContext {
         }
     }
 
+    /// Returns the number of SMs the current context is limited to, see
+    /// [`Context::create_with_affinity`]. For contexts without an SM count affinity, this is the
+    /// number of SMs on the device.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::device::Device;
+    /// # use cust::context::{ Context, ContextFlags, CurrentContext };
+    /// # use std::error::Error;
+    /// #
+    /// # fn main () -> Result<(), Box<dyn Error>> {
+    /// # cust::init(cust::CudaFlags::empty())?;
+    /// # let device = Device::get_device(0)?;
+    /// let context = Context::create_and_push(ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO, device)?;
+    /// let sm_count = CurrentContext::get_sm_count_affinity()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_sm_count_affinity() -> CudaResult<u32> {
+        unsafe {
+            let mut param = ExecAffinity::SmCount(0).to_raw();
+            cuda::cuCtxGetExecAffinity(
+                &mut param as *mut _,
+                cuda::CUexecAffinityType::CU_EXEC_AFFINITY_TYPE_SM_COUNT,
+            )
+            .to_result()?;
+            Ok(param.param.smCount.val)
+        }
+    }
+
     /// Return the device ID for the current context.
     ///
     /// # Example
@@ -725,6 +846,8 @@ impl CurrentContext {
     ///    which can no longer be used for device allocations.
     /// * `MaxL2FetchGranularity`: Controls the L2 fetch granularity. This is purely a performance
     ///    hint and it can be ignored or clamped depending on the platform.
+    /// * `PersistingL2CacheSize`: Controls the size of the L2 cache set aside for persisting
+    ///    accesses. It is clamped to [`DeviceAttribute::MaxPersistingL2CacheSize`](crate::device::DeviceAttribute::MaxPersistingL2CacheSize).
     ///
     /// # Example
     ///
@@ -748,6 +871,25 @@ impl CurrentContext {
         }
     }
 
+    /// Resets all persisting lines in the L2 cache of the current context to normal, so that the
+    /// L2 cache set aside for persisting accesses can be used by other data.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::context::CurrentContext;
+    /// CurrentContext::reset_persisting_l2_cache()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn reset_persisting_l2_cache() -> CudaResult<()> {
+        unsafe { cuda::cuCtxResetPersistingL2Cache().to_result() }
+    }
+
     /// Sets the preferred shared memory configuration for the current context.
     ///
     /// On devices with configurable shared memory banks, this function will set the context's
@@ -833,3 +975,203 @@ impl CurrentContext {
         }
     }
 }
+
+/// Where a module managed by a [`ContextManager`] was loaded from, so it can be loaded again.
+#[derive(Debug, Clone)]
+enum ModuleSource {
+    Ptx(String),
+    File(PathBuf),
+}
+
+impl ModuleSource {
+    fn load(&self) -> CudaResult<Module> {
+        match self {
+            ModuleSource::Ptx(ptx) => Module::from_str(ptx),
+            ModuleSource::File(path) => Module::from_file(path),
+        }
+    }
+}
+
+/// A handle to a module loaded through a [`ContextManager`], which stays valid when the
+/// context is recreated.
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+pub struct ModuleHandle(usize);
+
+/// An owned context which can be recreated after a sticky error, for long-running services
+/// which should survive a single bad kernel.
+///
+/// After a sticky error (see [`CudaError::is_sticky`](crate::error::CudaError::is_sticky)), the
+/// context is unusable and every call using it fails. The manager recovers by destroying the
+/// context, creating a new one on the same device, and loading every module it manages again.
+/// Everything else that belonged to the old context (device memory, streams, events, etc.) is gone
+/// and must be recreated by the caller, [`ContextManager::generation`] can be used to detect that
+/// this happened.
+///
+/// # Example
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// use cust::context::{ContextFlags, ContextManager};
+/// use cust::device::Device;
+///
+/// init(CudaFlags::empty())?;
+/// let mut manager = ContextManager::new(ContextFlags::SCHED_AUTO, Device::get_device(0)?)?;
+/// let module = manager.load_module_from_str(include_str!("../resources/add.ptx"))?;
+///
+/// loop {
+///     let func = manager.module(module).get_function("sum")?;
+///     // ... allocate buffers and launch `func` ...
+///     match manager.check() {
+///         Ok(()) => break,
+///         // the context was recreated, try again with fresh buffers.
+///         Err(e) if e.is_sticky() => continue,
+///         Err(e) => return Err(e.into()),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ContextManager {
+    device: Device,
+    flags: ContextFlags,
+    // modules must be dropped before the context they were loaded in.
+    modules: Vec<Module>,
+    sources: Vec<ModuleSource>,
+    context: Context,
+    generation: u64,
+}
+
+impl ContextManager {
+    /// Creates a new context for `device` and makes it current on this thread.
+    pub fn new(flags: ContextFlags, device: Device) -> CudaResult<Self> {
+        Ok(Self {
+            device,
+            flags,
+            modules: Vec::new(),
+            sources: Vec::new(),
+            context: Context::create_and_push(flags, device)?,
+            generation: 0,
+        })
+    }
+
+    /// The current context. This changes every time the context is recreated.
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// The device the context belongs to.
+    pub fn device(&self) -> Device {
+        self.device
+    }
+
+    /// The number of times the context has been recreated.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Loads a module from a PTX string, it will be loaded again every time the context is recreated.
+    pub fn load_module_from_str(&mut self, ptx: impl Into<String>) -> CudaResult<ModuleHandle> {
+        self.load(ModuleSource::Ptx(ptx.into()))
+    }
+
+    /// Loads a module from a file, it will be loaded again (from the same path) every time the
+    /// context is recreated.
+    pub fn load_module_from_file(&mut self, path: impl Into<PathBuf>) -> CudaResult<ModuleHandle> {
+        self.load(ModuleSource::File(path.into()))
+    }
+
+    fn load(&mut self, source: ModuleSource) -> CudaResult<ModuleHandle> {
+        let module = source.load()?;
+        self.modules.push(module);
+        self.sources.push(source);
+        Ok(ModuleHandle(self.modules.len() - 1))
+    }
+
+    /// Returns a module loaded in the current context.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the handle was created by a different manager.
+    pub fn module(&self, handle: ModuleHandle) -> &Module {
+        &self.modules[handle.0]
+    }
+
+    /// Waits for the context to finish its work, recreating it if it failed with a sticky error.
+    ///
+    /// The original error is still returned after the context has been recreated, so that callers
+    /// know their work (and device memory) was lost. If recreating the context fails, that error
+    /// is returned instead.
+    pub fn check(&mut self) -> CudaResult<()> {
+        match CurrentContext::synchronize() {
+            Err(e) if e.is_sticky() => {
+                self.recover()?;
+                Err(e)
+            }
+            res => res,
+        }
+    }
+
+    /// Destroys the context and creates a new one on the same device with the same flags, then
+    /// loads every module again. The new context is made current on this thread, other threads which
+    /// used the old context must make the new context current with [`CurrentContext::set_current`].
+    ///
+    /// If creating the context or loading a module fails, the manager keeps the old context and
+    /// modules (and its generation), so that `recover` can be called again.
+    pub fn recover(&mut self) -> CudaResult<()> {
+        let context = Context::create_and_push(self.flags, self.device)?;
+        let modules = match self
+            .sources
+            .iter()
+            .map(ModuleSource::load)
+            .collect::<CudaResult<Vec<_>>>()
+        {
+            Ok(modules) => modules,
+            Err(e) => {
+                let _ = Context::drop(context);
+                CurrentContext::set_current(&self.context)?;
+                return Err(e);
+            }
+        };
+
+        // unloading modules in a broken context fails, but the context is destroyed right after
+        // so nothing leaks.
+        let _ = CurrentContext::set_current(&self.context);
+        drop(mem::replace(&mut self.modules, modules));
+        let old = mem::replace(&mut self.context, context);
+        let _ = Context::drop(old);
+        // destroying the old context pops it if it was current, make sure the new one is.
+        CurrentContext::set_current(&self.context)?;
+        self.generation += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::error::Error;
+
+    #[test]
+    fn failed_recover_keeps_modules() -> Result<(), Box<dyn Error>> {
+        crate::init(crate::CudaFlags::empty())?;
+        let path = std::env::temp_dir().join(format!("cust_recover_{}.ptx", std::process::id()));
+        std::fs::write(&path, include_str!("../resources/add.ptx"))?;
+        let mut manager = ContextManager::new(ContextFlags::SCHED_AUTO, Device::get_device(0)?)?;
+        let handle = manager.load_module_from_file(&path)?;
+
+        std::fs::remove_file(&path)?;
+        assert!(manager.recover().is_err());
+        assert_eq!(manager.generation(), 0);
+        manager.module(handle).get_function("sum")?;
+
+        std::fs::write(&path, include_str!("../resources/add.ptx"))?;
+        manager.recover()?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(manager.generation(), 1);
+        manager.module(handle).get_function("sum")?;
+        Ok(())
+    }
+}
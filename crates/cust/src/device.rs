@@ -1,9 +1,10 @@
 //! Functions and types for enumerating CUDA devices and retrieving information about them.
 
-use crate::error::{CudaResult, ToResult};
+use crate::error::{CudaError, CudaResult, ToResult};
 use crate::sys::*;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
 use std::ops::Range;
+use std::os::raw::c_char;
 
 /// All supported device attributes for [Device::get_attribute](struct.Device.html#method.get_attribute)
 #[repr(u32)]
@@ -193,6 +194,160 @@ pub enum DeviceAttribute {
     ComputePreemptionSupported = 90,
     /// Device can access host registered memory at the same virtual address as the CPU
     CanUseHostPointerForRegisteredMem = 91,
+    /// Device supports launching cooperative kernels
+    CooperativeLaunch = 95,
+    /// Maximum amount of shared memory per block a kernel can use by opting in
+    MaxSharedMemoryPerBlockOptin = 97,
+    /// Maximum size in bytes of L2 cache that can be set aside for persisting accesses
+    MaxPersistingL2CacheSize = 108,
+    /// Maximum number of bytes of an access policy window
+    MaxAccessPolicyWindowSize = 109,
+}
+
+/// Formats the bytes of a UUID the way NVIDIA tools do (`GPU-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`).
+fn format_uuid(prefix: &str, bytes: &[u8; 16]) -> String {
+    let mut out = String::from(prefix);
+    for (i, byte) in bytes.iter().enumerate() {
+        if matches!(i, 4 | 6 | 8 | 10) {
+            out.push('-');
+        }
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+/// One entry of a [`DeviceFilter`].
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+pub enum DeviceSelector {
+    /// A device ordinal, relative to the devices visible to this process.
+    Ordinal(u32),
+    /// A device UUID in the `GPU-...` or `MIG-...` format, or a unique prefix of one.
+    Uuid(String),
+    /// A PCI bus ID, see [`Device::pci_bus_id`].
+    PciBusId(String),
+}
+
+impl DeviceSelector {
+    fn resolve(&self) -> CudaResult<Device> {
+        match self {
+            DeviceSelector::Ordinal(ordinal) => Device::get_device(*ordinal),
+            DeviceSelector::PciBusId(bus_id) => Device::from_pci_bus_id(bus_id),
+            DeviceSelector::Uuid(prefix) => {
+                let mut found = None;
+                for device in Device::devices()? {
+                    let device = device?;
+                    let uuid = device.uuid_string()?;
+                    let matches = uuid
+                        .get(..prefix.len())
+                        .map_or(false, |start| start.eq_ignore_ascii_case(prefix));
+                    if matches {
+                        // an ambiguous prefix is an error, like with `CUDA_VISIBLE_DEVICES`.
+                        if found.is_some() {
+                            return Err(CudaError::InvalidDevice);
+                        }
+                        found = Some(device);
+                    }
+                }
+                found.ok_or(CudaError::InvalidDevice)
+            }
+        }
+    }
+}
+
+/// Selects a deterministic list of devices, for binding workers to specific GPUs.
+///
+/// A filter is written with the same syntax as `CUDA_VISIBLE_DEVICES`, a comma-separated list of
+/// device ordinals and UUIDs (`GPU-...` or `MIG-...`, a unique prefix is enough), with PCI bus IDs
+/// (`0000:65:00.0`) also being accepted. UUIDs and PCI bus IDs are the most robust, they identify the
+/// same GPU no matter how devices are numbered.
+///
+/// The driver applies `CUDA_VISIBLE_DEVICES` itself when it is initialized, so a filter can only
+/// select devices which are visible to the process, and its ordinals are relative to the visible devices.
+/// Selecting a device which is not visible returns [`CudaError::InvalidDevice`].
+///
+/// # Example
+///
+/// ```
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # init(CudaFlags::empty())?;
+/// use cust::device::DeviceFilter;
+///
+/// // use the GPUs assigned by the scheduler, or every GPU if it did not assign any.
+/// let devices = DeviceFilter::from_env("WORKER_GPUS").devices()?;
+/// assert!(!devices.is_empty());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default, Hash, Eq, PartialEq)]
+pub struct DeviceFilter {
+    /// `None` selects every visible device.
+    selectors: Option<Vec<DeviceSelector>>,
+}
+
+impl DeviceFilter {
+    /// A filter which selects every visible device.
+    pub fn all() -> Self {
+        Self { selectors: None }
+    }
+
+    /// Parses a filter from a `CUDA_VISIBLE_DEVICES`-style list. An empty list selects no devices.
+    pub fn parse(spec: &str) -> Self {
+        let selectors = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                if let Ok(ordinal) = entry.parse() {
+                    DeviceSelector::Ordinal(ordinal)
+                } else if entry.contains(':') {
+                    DeviceSelector::PciBusId(entry.to_string())
+                } else {
+                    DeviceSelector::Uuid(entry.to_string())
+                }
+            })
+            .collect();
+        Self {
+            selectors: Some(selectors),
+        }
+    }
+
+    /// Parses a filter from an environment variable, selecting every visible device if it is not set.
+    pub fn from_env(var: &str) -> Self {
+        match std::env::var(var) {
+            Ok(spec) => Self::parse(&spec),
+            Err(_) => Self::all(),
+        }
+    }
+
+    /// Adds a selector to the filter. Adding a selector to [`DeviceFilter::all`] makes it only select
+    /// the added devices.
+    pub fn select(mut self, selector: DeviceSelector) -> Self {
+        self.selectors.get_or_insert_with(Vec::new).push(selector);
+        self
+    }
+
+    /// The selectors of this filter, `None` if it selects every visible device.
+    pub fn selectors(&self) -> Option<&[DeviceSelector]> {
+        self.selectors.as_deref()
+    }
+
+    /// Returns the selected devices, in the order they were selected and without duplicates.
+    pub fn devices(&self) -> CudaResult<Vec<Device>> {
+        let selectors = match &self.selectors {
+            Some(selectors) => selectors,
+            None => return Device::devices()?.collect(),
+        };
+        let mut devices = Vec::with_capacity(selectors.len());
+        for selector in selectors {
+            let device = selector.resolve()?;
+            if !devices.contains(&device) {
+                devices.push(device);
+            }
+        }
+        Ok(devices)
+    }
 }
 
 /// Opaque handle to a CUDA device.
@@ -327,6 +482,127 @@ impl Device {
         }
     }
 
+    /// Returns the UUID of this device. This is stable across processes and reboots (unlike device
+    /// ordinals, which depend on `CUDA_VISIBLE_DEVICES`), and can be used to match devices with other
+    /// APIs such as NVML.
+    ///
+    /// For MIG (Multi-Instance GPU) devices, this is the UUID of the MIG instance, not of the
+    /// physical GPU, see [`Device::parent_uuid`].
+    ///
+    /// # Example
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # init(CudaFlags::empty())?;
+    /// use cust::device::Device;
+    /// let device = Device::get_device(0)?;
+    /// println!("UUID: {:x?}", device.uuid()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn uuid(self) -> CudaResult<[u8; 16]> {
+        let mut uuid = CUuuid { bytes: [0; 16] };
+        unsafe {
+            cuDeviceGetUuid_v2(&mut uuid as *mut CUuuid, self.device).to_result()?;
+        }
+        Ok(uuid.bytes.map(|b| b as u8))
+    }
+
+    /// Returns the UUID of the physical GPU this device belongs to. This is the same as
+    /// [`Device::uuid`] unless this is a MIG device.
+    pub fn parent_uuid(self) -> CudaResult<[u8; 16]> {
+        let mut uuid = CUuuid { bytes: [0; 16] };
+        unsafe {
+            cuDeviceGetUuid(&mut uuid as *mut CUuuid, self.device).to_result()?;
+        }
+        Ok(uuid.bytes.map(|b| b as u8))
+    }
+
+    /// Returns whether this device is a MIG (Multi-Instance GPU) instance, a partition of a physical
+    /// GPU with its own SMs and memory. The SMs and memory of the partition are reported by
+    /// [`DeviceAttribute::MultiprocessorCount`] and [`Device::total_memory`] like on any other
+    /// device, so launch configurations based on them adapt to the partition automatically.
+    pub fn is_mig(self) -> CudaResult<bool> {
+        Ok(self.uuid()? != self.parent_uuid()?)
+    }
+
+    /// Returns the UUID of this device formatted the same way as `nvidia-smi`, NVML and
+    /// `CUDA_VISIBLE_DEVICES` (`GPU-xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx`, or `MIG-...` for MIG
+    /// devices).
+    pub fn uuid_string(self) -> CudaResult<String> {
+        let prefix = if self.is_mig()? { "MIG-" } else { "GPU-" };
+        Ok(format_uuid(prefix, &self.uuid()?))
+    }
+
+    /// Returns the UUID of the physical GPU this device belongs to, formatted like
+    /// [`Device::uuid_string`].
+    pub fn parent_uuid_string(self) -> CudaResult<String> {
+        Ok(format_uuid("GPU-", &self.parent_uuid()?))
+    }
+
+    /// Returns the device with the given UUID, as returned by [`Device::uuid`].
+    ///
+    /// Returns [`CudaError::InvalidDevice`] if no visible device has this UUID.
+    ///
+    /// # Example
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # init(CudaFlags::empty())?;
+    /// use cust::device::Device;
+    /// let device = Device::get_device(0)?;
+    /// assert_eq!(device, Device::from_uuid(&device.uuid()?)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_uuid(uuid: &[u8; 16]) -> CudaResult<Device> {
+        for device in Device::devices()? {
+            let device = device?;
+            if device.uuid()? == *uuid {
+                return Ok(device);
+            }
+        }
+        Err(CudaError::InvalidDevice)
+    }
+
+    /// Returns the PCI bus ID of this device, in the `domain:bus:device.function` format
+    /// (for example `0000:65:00.0`).
+    ///
+    /// # Example
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # init(CudaFlags::empty())?;
+    /// use cust::device::Device;
+    /// let device = Device::get_device(0)?;
+    /// let bus_id = device.pci_bus_id()?;
+    /// assert_eq!(device, Device::from_pci_bus_id(&bus_id)?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn pci_bus_id(self) -> CudaResult<String> {
+        // the longest format is `dddddddd:bb:dd.f` plus the nul terminator.
+        let mut buf = [0 as c_char; 32];
+        unsafe {
+            cuDeviceGetPCIBusId(buf.as_mut_ptr(), buf.len() as i32, self.device).to_result()?;
+            Ok(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    /// Returns the device with the given PCI bus ID, see [`Device::pci_bus_id`].
+    pub fn from_pci_bus_id(bus_id: &str) -> CudaResult<Device> {
+        let bus_id = CString::new(bus_id).map_err(|_| CudaError::InvalidValue)?;
+        unsafe {
+            let mut device = Device { device: 0 };
+            cuDeviceGetByPCIBusId(&mut device.device as *mut CUdevice, bus_id.as_ptr())
+                .to_result()?;
+            Ok(device)
+        }
+    }
+
     /// Returns information about this device.
     ///
     /// # Example
@@ -356,6 +632,33 @@ impl Device {
         }
     }
 
+    /// Returns whether contexts on this device can be limited to a number of SMs with
+    /// [`ExecAffinity::SmCount`](crate::context::ExecAffinity::SmCount).
+    pub fn supports_sm_count_affinity(self) -> CudaResult<bool> {
+        unsafe {
+            let mut val = 0i32;
+            cuDeviceGetExecAffinitySupport(
+                &mut val as *mut i32,
+                CUexecAffinityType::CU_EXEC_AFFINITY_TYPE_SM_COUNT,
+                self.device,
+            )
+            .to_result()?;
+            Ok(val != 0)
+        }
+    }
+
+    /// Destroys every allocation in the primary context of this device and resets it. This can be
+    /// used to recover the primary context after a sticky error (see
+    /// [`CudaError::is_sticky`](crate::error::CudaError::is_sticky)).
+    ///
+    /// # Safety
+    ///
+    /// Every resource belonging to the primary context (memory, modules, streams, etc) is destroyed,
+    /// they must not be used after this.
+    pub unsafe fn reset_primary_context(self) -> CudaResult<()> {
+        cuDevicePrimaryCtxReset_v2(self.device).to_result()
+    }
+
     /// Returns a raw handle to this device, not handing over ownership, meaning that dropping
     /// this device will try to drop the underlying device.
     pub fn as_raw(&self) -> CUdevice {
@@ -421,4 +724,66 @@ mod test {
         println!("{}", memory);
         Ok(())
     }
+
+    #[test]
+    fn test_sm_count_affinity_support() -> Result<(), Box<dyn Error>> {
+        use crate::context::{Context, ContextFlags, CurrentContext, ExecAffinity};
+
+        test_init()?;
+        let device = Device::get_device(0)?;
+        let supported = device.supports_sm_count_affinity()?;
+        let context = Context::create_with_affinity(
+            ContextFlags::SCHED_AUTO,
+            device,
+            &[ExecAffinity::SmCount(1)],
+        );
+        if supported {
+            // SM count affinity needs Volta or newer.
+            assert!(device.get_attribute(DeviceAttribute::ComputeCapabilityMajor)? >= 7);
+            let _context = context?;
+            let sm_count = CurrentContext::get_sm_count_affinity()?;
+            let max = device.get_attribute(DeviceAttribute::MultiprocessorCount)?;
+            assert!(sm_count >= 1 && sm_count as i32 <= max);
+        } else {
+            assert_eq!(context.err(), Some(CudaError::UnsupportedExecAffinity));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_uuid_formatting() {
+        let bytes = [
+            0x8f, 0x6e, 0x2a, 0x10, 0x4b, 0x3c, 0x1d, 0x2e, 0x9a, 0x0b, 0x11, 0x22, 0x33, 0x44,
+            0x55, 0x66,
+        ];
+        assert_eq!(
+            format_uuid("GPU-", &bytes),
+            "GPU-8f6e2a10-4b3c-1d2e-9a0b-112233445566"
+        );
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        let filter = DeviceFilter::parse("1, GPU-8f6e,0000:65:00.0");
+        assert_eq!(
+            filter.selectors().unwrap(),
+            &[
+                DeviceSelector::Ordinal(1),
+                DeviceSelector::Uuid("GPU-8f6e".to_string()),
+                DeviceSelector::PciBusId("0000:65:00.0".to_string()),
+            ]
+        );
+        assert!(DeviceFilter::all().selectors().is_none());
+    }
+
+    #[test]
+    fn test_device_lookup() -> Result<(), Box<dyn Error>> {
+        test_init()?;
+        let device = Device::get_device(0)?;
+        assert_eq!(device, Device::from_uuid(&device.uuid()?)?);
+        assert_eq!(device, Device::from_pci_bus_id(&device.pci_bus_id()?)?);
+        let filter = DeviceFilter::parse(&device.uuid_string()?);
+        assert_eq!(filter.devices()?, vec![device]);
+        Ok(())
+    }
 }
@@ -0,0 +1,123 @@
+//! Collecting the diagnostics counters kernels update with `cuda_std::diag`.
+//!
+//! Kernels count events and record values in a buffer of [`SLOTS`] 64-bit counters which `cuda_std`
+//! defines in every module it is linked into. [`Diagnostics`] finds that buffer in a [`Module`] and
+//! reads it back after launches, without any printing on the device:
+//!
+//! ```
+//! # use cust::*;
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! # let _ctx = quick_init()?;
+//! use cust::diag::Diagnostics;
+//! use cust::module::Module;
+//!
+//! # let ptx = "
+//! # .version 7.0
+//! # .target sm_61
+//! # .address_size 64
+//! # .visible .global .align 8 .b8 CUDA_STD_DIAG[2048];
+//! # ";
+//! let module = Module::from_str(ptx)?;
+//! let mut diagnostics = Diagnostics::new(&module)?;
+//! // ... launch kernels which use `cuda_std::diag` and wait for them ...
+//! let counters = diagnostics.take()?;
+//! for (slot, value) in counters.nonzero() {
+//!     println!("counter {}: {}", slot, value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The counters are read and written with synchronous copies, which wait for the kernels using them
+//! to finish on the legacy default stream, but kernels on non-blocking streams must be waited for
+//! before collecting their counters. The buffer belongs to the module, so every kernel of the module
+//! adds to the same counters, across every launch until they are [`reset`](Diagnostics::reset).
+
+use crate::error::CudaResult;
+use crate::memory::CopyDestination;
+use crate::module::{Module, Symbol};
+use std::ffi::CStr;
+use std::fmt;
+use std::ops::Deref;
+
+/// The number of counters in the diagnostics buffer, the same as `cuda_std::diag::SLOTS`.
+pub const SLOTS: usize = 256;
+
+/// The name of the diagnostics buffer in the PTX.
+const SYMBOL: &[u8] = b"CUDA_STD_DIAG\0";
+
+/// The diagnostics buffer of a module, see the [module-level documentation](self).
+#[derive(Debug)]
+pub struct Diagnostics<'a> {
+    symbol: Symbol<'a, [u64; SLOTS]>,
+}
+
+impl<'a> Diagnostics<'a> {
+    /// Finds the diagnostics buffer of `module`.
+    ///
+    /// Returns [`CudaError::NotFound`](crate::error::CudaError::NotFound) if the module does not contain it, which happens if it was not
+    /// built with a version of `cuda_std` which has `cuda_std::diag`, or if the buffer was optimized out
+    /// because no kernel uses it.
+    pub fn new(module: &'a Module) -> CudaResult<Self> {
+        let name = CStr::from_bytes_with_nul(SYMBOL).unwrap();
+        let symbol = module.get_global(name)?;
+        Ok(Self { symbol })
+    }
+
+    /// Reads the counters.
+    pub fn collect(&self) -> CudaResult<Counters> {
+        let mut counters = [0; SLOTS];
+        self.symbol.copy_to(&mut counters)?;
+        Ok(Counters(counters))
+    }
+
+    /// Sets every counter to zero.
+    pub fn reset(&mut self) -> CudaResult<()> {
+        self.symbol.copy_from(&[0; SLOTS])
+    }
+
+    /// Reads the counters and sets them to zero, for collecting the counters of every launch
+    /// separately.
+    pub fn take(&mut self) -> CudaResult<Counters> {
+        let counters = self.collect()?;
+        self.reset()?;
+        Ok(counters)
+    }
+}
+
+/// The values of the diagnostics counters, indexed by slot.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Counters([u64; SLOTS]);
+
+impl Counters {
+    /// The slots and values of the counters which are not zero, in order of slot.
+    pub fn nonzero(&self) -> impl Iterator<Item = (usize, u64)> + '_ {
+        self.0
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, value)| value != 0)
+    }
+}
+
+impl Deref for Counters {
+    type Target = [u64; SLOTS];
+
+    fn deref(&self) -> &[u64; SLOTS] {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Counters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // most of the slots are usually unused.
+        f.debug_map().entries(self.nonzero()).finish()
+    }
+}
+
+impl From<Counters> for [u64; SLOTS] {
+    fn from(counters: Counters) -> Self {
+        counters.0
+    }
+}
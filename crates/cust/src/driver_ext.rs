@@ -0,0 +1,115 @@
+//! Driver API functions and types which were added after the CUDA version `cust_raw` was generated
+//! for. The functions are looked up at runtime with `cuGetProcAddress` so that cust still loads
+//! with older drivers, on which they return [`CudaError::NotSupported`].
+//!
+//! Like the functions of `sys`, calls are recorded by `cust::capture` while a capture is running
+//! (calls which fail because the driver does not have the function are not).
+
+#![allow(non_snake_case, non_camel_case_types)]
+
+use crate::error::{CudaError, CudaResult, ToResult};
+use crate::sys::{self as cuda, CUfunction, CUresult, CUstream};
+use once_cell::sync::OnceCell;
+use std::ffi::c_void;
+use std::ptr;
+
+/// Looks up a driver function once, `version` is the CUDA version which introduced it.
+fn proc_address(cell: &OnceCell<usize>, name: &str, version: i32) -> CudaResult<usize> {
+    cell.get_or_try_init(|| unsafe {
+        let mut pfn = ptr::null_mut();
+        match cuda::cuGetProcAddress(name.as_ptr().cast(), &mut pfn, version, 0).to_result() {
+            Ok(()) if !pfn.is_null() => Ok(pfn as usize),
+            Ok(()) | Err(CudaError::NotFound) => Err(CudaError::NotSupported),
+            Err(e) => Err(e),
+        }
+    })
+    .copied()
+}
+
+/// Defines wrappers calling driver functions looked up at runtime, with the same `out` lists as the
+/// recording shims of `cust::capture`. `deref` lists pointers to structures which are recorded by
+/// value, and `launch` the function and parameters of a kernel launch.
+macro_rules! driver_fns {
+    ($(
+        $version:literal => fn $name:ident($($arg:ident: $ty:ty),* $(,)?)
+            $(out($($out:ident),*))?
+            $(deref($($deref:ident),*))?
+            $(launch($f:ident, $params:ident))?;
+    )*) => {
+        $(
+            #[allow(clippy::too_many_arguments)]
+            pub(crate) unsafe fn $name($($arg: $ty),*) -> CudaResult<()> {
+                static PFN: OnceCell<usize> = OnceCell::new();
+                let pfn = proc_address(&PFN, concat!(stringify!($name), "\0"), $version)?;
+                let f = std::mem::transmute::<usize, unsafe extern "C" fn($($ty),*) -> CUresult>(pfn);
+                let result = f($($arg),*);
+                #[cfg(feature = "capture")]
+                crate::capture::record(stringify!($name), result, |_call, _recorder| {
+                    $(_call.arg(stringify!($arg), stringify!($ty), &$arg);)*
+                    $($(_call.out(stringify!($out), $out);)*)?
+                    $($(_call.deref(stringify!($deref), $deref);)*)?
+                    $(_call.kernel_params(stringify!($params), _recorder, $f, $params);)?
+                });
+                result.to_result()
+            }
+        )*
+    };
+}
+
+driver_fns! {
+    11070 => fn cuModuleGetLoadingMode(mode: *mut u32) out(mode);
+    12000 => fn cuLaunchKernelEx(
+        config: *const CUlaunchConfig,
+        f: CUfunction,
+        kernelParams: *mut *mut c_void,
+        extra: *mut *mut c_void,
+    ) deref(config) launch(f, kernelParams);
+    12000 => fn cuTensorMapEncodeTiled(
+        tensorMap: *mut CUtensorMap,
+        tensorDataType: u32,
+        tensorRank: u32,
+        globalAddress: *mut c_void,
+        globalDim: *const u64,
+        globalStrides: *const u64,
+        boxDim: *const u32,
+        elementStrides: *const u32,
+        interleave: u32,
+        swizzle: u32,
+        l2Promotion: u32,
+        oobFill: u32,
+    ) out(tensorMap);
+}
+
+pub(crate) const CU_MODULE_LAZY_LOADING: u32 = 2;
+
+pub(crate) const CU_LAUNCH_ATTRIBUTE_CLUSTER_DIMENSION: u32 = 4;
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct CUlaunchAttribute {
+    pub id: u32,
+    pub pad: [u8; 4],
+    // `CUlaunchAttributeValue` is a 64 byte union, the cluster dimension is its first 3 words.
+    pub value: [u32; 16],
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub(crate) struct CUlaunchConfig {
+    pub gridDimX: u32,
+    pub gridDimY: u32,
+    pub gridDimZ: u32,
+    pub blockDimX: u32,
+    pub blockDimY: u32,
+    pub blockDimZ: u32,
+    pub sharedMemBytes: u32,
+    pub hStream: CUstream,
+    pub attrs: *mut CUlaunchAttribute,
+    pub numAttrs: u32,
+}
+
+#[repr(C, align(64))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct CUtensorMap {
+    pub opaque: [u64; 16],
+}
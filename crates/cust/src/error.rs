@@ -52,6 +52,8 @@ pub enum CudaError {
     InvalidPtx = 218,
     InvalidGraphicsContext = 219,
     NvlinkUncorrectable = 220,
+    UnsupportedPtxVersion = 222,
+    UnsupportedExecAffinity = 224,
     InvalidSouce = 300,
     FileNotFound = 301,
     SharedObjectSymbolNotFound = 302,
@@ -80,17 +82,33 @@ pub enum CudaError {
     LaunchFailed = 719,
     NotPermitted = 800,
     NotSupported = 801,
+    MpsConnectionFailed = 805,
+    MpsRpcFailure = 806,
+    MpsServerNotReady = 807,
+    MpsMaxClientsReached = 808,
+    MpsMaxConnectionsReached = 809,
     UnknownError = 999,
 
     // cust errors
     InvalidMemoryAllocation = 100_100,
     OptixError = 100_101,
+    InvalidLaunchConfiguration = 100_102,
+    KernelAbiMismatch = 100_103,
 }
 impl fmt::Display for CudaError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             CudaError::InvalidMemoryAllocation => write!(f, "Invalid memory allocation"),
             CudaError::OptixError => write!(f, "OptiX error"),
+            CudaError::InvalidLaunchConfiguration => {
+                write!(f, "Launch configuration exceeds the limits of the device")
+            }
+            CudaError::KernelAbiMismatch => {
+                write!(
+                    f,
+                    "Kernel signature does not match the one it was built with"
+                )
+            }
             other if (other as u32) <= 999 => {
                 let value = other as u32;
                 let mut ptr: *const c_char = ptr::null();
@@ -109,6 +127,107 @@ impl fmt::Display for CudaError {
 }
 impl Error for CudaError {}
 
+impl CudaError {
+    /// Whether this error is "sticky", meaning that the context it happened in is corrupted and
+    /// every further call using it will fail. The only way to recover from a sticky error is to destroy
+    /// the context and create a new one, see [`ContextManager`](crate::context::ContextManager).
+    ///
+    /// These errors usually come from a kernel doing something illegal, such as accessing an invalid address.
+    pub fn is_sticky(self) -> bool {
+        matches!(
+            self,
+            CudaError::IllegalAddress
+                | CudaError::LaunchTimeout
+                | CudaError::AssertError
+                | CudaError::HardwareStackError
+                | CudaError::IllegalInstruction
+                | CudaError::MisalignedAddress
+                | CudaError::InvalidAddressSpace
+                | CudaError::InvalidProgramCounter
+                | CudaError::LaunchFailed
+                | CudaError::EccUncorrectable
+                | CudaError::NvlinkUncorrectable
+        )
+    }
+
+    /// A hint on what commonly causes this error and how to fix it, for the errors new users run
+    /// into most, or `None` if there is no hint beyond the description of the error.
+    ///
+    /// ```
+    /// use cust::error::CudaError;
+    ///
+    /// let err = CudaError::InvalidPtx;
+    /// eprintln!("error: {}", err);
+    /// if let Some(hint) = err.explain() {
+    ///     eprintln!("hint: {}", hint);
+    /// }
+    /// ```
+    pub fn explain(self) -> Option<&'static str> {
+        Some(match self {
+            CudaError::InvalidPtx | CudaError::UnsupportedPtxVersion => {
+                "the PTX was compiled for a newer architecture or PTX version than the driver \
+                 supports; update the driver, or rebuild the PTX for an older architecture with \
+                 `CudaBuilder::arch`"
+            }
+            CudaError::NoBinaryForGpu | CudaError::InvalidImage => {
+                "the module has no code which can run on this GPU; rebuild the PTX with \
+                 `CudaBuilder::arch` set to the compute capability of the GPU or lower"
+            }
+            CudaError::InvalidContext => {
+                "there is no current context on this thread, or it was dropped; keep the context \
+                 returned by `quick_init` alive (`let _ctx = quick_init()?`, not `let _ = ...`) and \
+                 make it current on every thread using CUDA"
+            }
+            CudaError::NotInitialized => {
+                "the driver API was not initialized; call `cust::init` or `cust::quick_init` first"
+            }
+            CudaError::NoDevice => {
+                "no CUDA capable GPU was found; check that the NVIDIA driver is installed and that \
+                 `CUDA_VISIBLE_DEVICES` does not hide every GPU"
+            }
+            CudaError::NotFound => {
+                "a kernel or global was not found in the module; check its name and that it is \
+                 `#[kernel]` (or `#[no_mangle]`) in the GPU crate, and that the PTX was rebuilt"
+            }
+            CudaError::InvalidValue => {
+                "an argument was invalid; for launches, check that the parameters match the kernel \
+                 signature (slices are passed as a pointer and a length)"
+            }
+            CudaError::LaunchOutOfResources => {
+                "the kernel needs more registers or shared memory than available for this block \
+                 size; launch with smaller blocks, or limit its registers with \
+                 `#[kernel(max_registers = ...)]`"
+            }
+            CudaError::InvalidLaunchConfiguration => {
+                "the grid, block or shared memory size exceeds the limits of the device; see \
+                 `LaunchConfig::validate`"
+            }
+            CudaError::KernelAbiMismatch => {
+                "the PTX was built from a different version of the kernel than the host code \
+                 launching it; rebuild the PTX or regenerate the kernel bindings"
+            }
+            CudaError::OutOfMemory => {
+                "the GPU ran out of memory; free buffers which are no longer needed, or process the \
+                 data in smaller batches"
+            }
+            CudaError::IllegalAddress | CudaError::MisalignedAddress => {
+                "a kernel accessed memory it must not (out of bounds, freed, or host memory); the \
+                 context is now unusable. Run the program with `compute-sanitizer` to find the \
+                 access"
+            }
+            CudaError::AssertError => {
+                "a kernel panicked or failed an assertion, its message was printed by the GPU; the \
+                 context is now unusable"
+            }
+            CudaError::LaunchTimeout => {
+                "a kernel ran for too long and was stopped by the display watchdog; split the work \
+                 over several launches or use a GPU which does not drive a display"
+            }
+            _ => return None,
+        })
+    }
+}
+
 /// Result type for most CUDA functions.
 pub type CudaResult<T> = Result<T, CudaError>;
 
@@ -161,10 +280,16 @@ impl ToResult for cudaError_enum {
                 Err(CudaError::PeerAccessUnsupported)
             }
             cudaError_enum::CUDA_ERROR_INVALID_PTX => Err(CudaError::InvalidPtx),
+            cudaError_enum::CUDA_ERROR_UNSUPPORTED_PTX_VERSION => {
+                Err(CudaError::UnsupportedPtxVersion)
+            }
             cudaError_enum::CUDA_ERROR_INVALID_GRAPHICS_CONTEXT => {
                 Err(CudaError::InvalidGraphicsContext)
             }
             cudaError_enum::CUDA_ERROR_NVLINK_UNCORRECTABLE => Err(CudaError::NvlinkUncorrectable),
+            cudaError_enum::CUDA_ERROR_UNSUPPORTED_EXEC_AFFINITY => {
+                Err(CudaError::UnsupportedExecAffinity)
+            }
             cudaError_enum::CUDA_ERROR_INVALID_SOURCE => Err(CudaError::InvalidSouce),
             cudaError_enum::CUDA_ERROR_FILE_NOT_FOUND => Err(CudaError::FileNotFound),
             cudaError_enum::CUDA_ERROR_SHARED_OBJECT_SYMBOL_NOT_FOUND => {
@@ -211,6 +336,15 @@ impl ToResult for cudaError_enum {
             cudaError_enum::CUDA_ERROR_LAUNCH_FAILED => Err(CudaError::LaunchFailed),
             cudaError_enum::CUDA_ERROR_NOT_PERMITTED => Err(CudaError::NotPermitted),
             cudaError_enum::CUDA_ERROR_NOT_SUPPORTED => Err(CudaError::NotSupported),
+            cudaError_enum::CUDA_ERROR_MPS_CONNECTION_FAILED => Err(CudaError::MpsConnectionFailed),
+            cudaError_enum::CUDA_ERROR_MPS_RPC_FAILURE => Err(CudaError::MpsRpcFailure),
+            cudaError_enum::CUDA_ERROR_MPS_SERVER_NOT_READY => Err(CudaError::MpsServerNotReady),
+            cudaError_enum::CUDA_ERROR_MPS_MAX_CLIENTS_REACHED => {
+                Err(CudaError::MpsMaxClientsReached)
+            }
+            cudaError_enum::CUDA_ERROR_MPS_MAX_CONNECTIONS_REACHED => {
+                Err(CudaError::MpsMaxConnectionsReached)
+            }
             _ => Err(CudaError::UnknownError),
         }
     }
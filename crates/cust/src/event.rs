@@ -47,6 +47,12 @@ bitflags::bitflags! {
     }
 }
 
+impl Default for EventFlags {
+    fn default() -> Self {
+        EventFlags::DEFAULT
+    }
+}
+
 /// Status enum that represents the current status of an event.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum EventStatus {
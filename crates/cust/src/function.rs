@@ -1,11 +1,15 @@
 //! Functions and types for working with CUDA kernels.
 
 use crate::context::{CacheConfig, SharedMemoryConfig};
-use crate::error::{CudaResult, ToResult};
+use crate::device::{Device, DeviceAttribute};
+use crate::error::{CudaError, CudaResult, ToResult};
+use crate::memory::{DeviceBuffer, DeviceCopy, DeviceSlice};
 use crate::module::Module;
 use crate::sys::{self as cuda, CUfunction};
+use std::ffi::c_void;
 use std::marker::PhantomData;
-use std::mem::{transmute, MaybeUninit};
+use std::mem::{self, transmute, MaybeUninit};
+use std::ops::Deref;
 
 /// Dimensions of a grid, or the number of thread blocks in a kernel launch.
 ///
@@ -24,21 +28,32 @@ pub struct GridSize {
 impl GridSize {
     /// Create a one-dimensional grid of `x` blocks
     #[inline]
-    pub fn x(x: u32) -> GridSize {
+    pub const fn x(x: u32) -> GridSize {
         GridSize { x, y: 1, z: 1 }
     }
 
     /// Create a two-dimensional grid of `x * y` blocks
     #[inline]
-    pub fn xy(x: u32, y: u32) -> GridSize {
+    pub const fn xy(x: u32, y: u32) -> GridSize {
         GridSize { x, y, z: 1 }
     }
 
     /// Create a three-dimensional grid of `x * y * z` blocks
     #[inline]
-    pub fn xyz(x: u32, y: u32, z: u32) -> GridSize {
+    pub const fn xyz(x: u32, y: u32, z: u32) -> GridSize {
         GridSize { x, y, z }
     }
+
+    /// Checks that every dimension of this grid is at least 1 and at most the maximum grid size
+    /// of `device`, returning [`CudaError::InvalidLaunchConfiguration`] otherwise.
+    pub fn validate(&self, device: Device) -> CudaResult<()> {
+        use DeviceAttribute::*;
+        check_dims(
+            [self.x, self.y, self.z],
+            [MaxGridDimX, MaxGridDimY, MaxGridDimZ],
+            device,
+        )
+    }
 }
 impl From<u32> for GridSize {
     fn from(x: u32) -> GridSize {
@@ -60,6 +75,12 @@ impl<'a> From<&'a GridSize> for GridSize {
         *other
     }
 }
+impl Default for GridSize {
+    /// A grid of one block.
+    fn default() -> Self {
+        GridSize::x(1)
+    }
+}
 #[cfg(feature = "vek")]
 impl From<vek::Vec2<u32>> for GridSize {
     fn from(vec: vek::Vec2<u32>) -> Self {
@@ -84,6 +105,30 @@ impl From<vek::Vec3<usize>> for GridSize {
         GridSize::xyz(vec.x as u32, vec.y as u32, vec.z as u32)
     }
 }
+#[cfg(feature = "glam")]
+impl From<glam::UVec2> for GridSize {
+    fn from(vec: glam::UVec2) -> Self {
+        GridSize::xy(vec.x, vec.y)
+    }
+}
+#[cfg(feature = "glam")]
+impl From<glam::UVec3> for GridSize {
+    fn from(vec: glam::UVec3) -> Self {
+        GridSize::xyz(vec.x, vec.y, vec.z)
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Vector2<u32>> for GridSize {
+    fn from(vec: nalgebra::Vector2<u32>) -> Self {
+        GridSize::xy(vec.x, vec.y)
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Vector3<u32>> for GridSize {
+    fn from(vec: nalgebra::Vector3<u32>) -> Self {
+        GridSize::xyz(vec.x, vec.y, vec.z)
+    }
+}
 
 /// Dimensions of a thread block, or the number of threads in a block.
 ///
@@ -104,21 +149,46 @@ pub struct BlockSize {
 impl BlockSize {
     /// Create a one-dimensional block of `x` threads
     #[inline]
-    pub fn x(x: u32) -> BlockSize {
+    pub const fn x(x: u32) -> BlockSize {
         BlockSize { x, y: 1, z: 1 }
     }
 
     /// Create a two-dimensional block of `x * y` threads
     #[inline]
-    pub fn xy(x: u32, y: u32) -> BlockSize {
+    pub const fn xy(x: u32, y: u32) -> BlockSize {
         BlockSize { x, y, z: 1 }
     }
 
     /// Create a three-dimensional block of `x * y * z` threads
     #[inline]
-    pub fn xyz(x: u32, y: u32, z: u32) -> BlockSize {
+    pub const fn xyz(x: u32, y: u32, z: u32) -> BlockSize {
         BlockSize { x, y, z }
     }
+
+    /// The number of threads in a block of this size.
+    #[inline]
+    pub const fn threads(&self) -> u64 {
+        self.x as u64 * self.y as u64 * self.z as u64
+    }
+
+    /// Checks that every dimension of this block is at least 1 and at most the maximum block size
+    /// of `device`, and that it does not have more threads than the device allows per block,
+    /// returning [`CudaError::InvalidLaunchConfiguration`] otherwise.
+    ///
+    /// Kernels may be limited to fewer threads per block than the device because of the resources
+    /// they use, see [`FunctionAttribute::MaxThreadsPerBlock`].
+    pub fn validate(&self, device: Device) -> CudaResult<()> {
+        use DeviceAttribute::*;
+        check_dims(
+            [self.x, self.y, self.z],
+            [MaxBlockDimX, MaxBlockDimY, MaxBlockDimZ],
+            device,
+        )?;
+        if self.threads() > device.get_attribute(MaxThreadsPerBlock)? as u64 {
+            return Err(CudaError::InvalidLaunchConfiguration);
+        }
+        Ok(())
+    }
 }
 impl From<u32> for BlockSize {
     fn from(x: u32) -> BlockSize {
@@ -140,6 +210,12 @@ impl<'a> From<&'a BlockSize> for BlockSize {
         *other
     }
 }
+impl Default for BlockSize {
+    /// A block of one thread.
+    fn default() -> Self {
+        BlockSize::x(1)
+    }
+}
 #[cfg(feature = "vek")]
 impl From<vek::Vec2<u32>> for BlockSize {
     fn from(vec: vek::Vec2<u32>) -> Self {
@@ -164,6 +240,457 @@ impl From<vek::Vec3<usize>> for BlockSize {
         BlockSize::xyz(vec.x as u32, vec.y as u32, vec.z as u32)
     }
 }
+#[cfg(feature = "glam")]
+impl From<glam::UVec2> for BlockSize {
+    fn from(vec: glam::UVec2) -> Self {
+        BlockSize::xy(vec.x, vec.y)
+    }
+}
+#[cfg(feature = "glam")]
+impl From<glam::UVec3> for BlockSize {
+    fn from(vec: glam::UVec3) -> Self {
+        BlockSize::xyz(vec.x, vec.y, vec.z)
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Vector2<u32>> for BlockSize {
+    fn from(vec: nalgebra::Vector2<u32>) -> Self {
+        BlockSize::xy(vec.x, vec.y)
+    }
+}
+#[cfg(feature = "nalgebra")]
+impl From<nalgebra::Vector3<u32>> for BlockSize {
+    fn from(vec: nalgebra::Vector3<u32>) -> Self {
+        BlockSize::xyz(vec.x, vec.y, vec.z)
+    }
+}
+
+/// Dimensions of a thread block cluster, or the number of thread blocks in a cluster.
+///
+/// Clusters are groups of thread blocks which are guaranteed to be co-scheduled on the same GPU
+/// processing cluster, and which can synchronize and access each other's shared memory with
+/// `cuda_std::cluster`. They require compute capability 9.0 or higher. Each dimension of the grid
+/// must be a multiple of the corresponding dimension of the cluster, and clusters of up to 8 blocks
+/// are portable across devices.
+///
+/// Clusters are launched with [`Stream::launch_cluster`](crate::stream::Stream::launch_cluster), or
+/// with a regular launch if the kernel declares its cluster size at compile time with
+/// `#[kernel(cluster_dim = ...)]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterSize {
+    /// X dimension of each cluster, in blocks
+    pub x: u32,
+    /// Y dimension of each cluster, in blocks
+    pub y: u32,
+    /// Z dimension of each cluster, in blocks
+    pub z: u32,
+}
+impl ClusterSize {
+    /// Create a one-dimensional cluster of `x` blocks
+    #[inline]
+    pub const fn x(x: u32) -> ClusterSize {
+        ClusterSize { x, y: 1, z: 1 }
+    }
+
+    /// Create a two-dimensional cluster of `x * y` blocks
+    #[inline]
+    pub const fn xy(x: u32, y: u32) -> ClusterSize {
+        ClusterSize { x, y, z: 1 }
+    }
+
+    /// Create a three-dimensional cluster of `x * y * z` blocks
+    #[inline]
+    pub const fn xyz(x: u32, y: u32, z: u32) -> ClusterSize {
+        ClusterSize { x, y, z }
+    }
+
+    /// The number of blocks in a cluster of this size.
+    #[inline]
+    pub const fn blocks(&self) -> u64 {
+        self.x as u64 * self.y as u64 * self.z as u64
+    }
+
+    /// Whether every dimension of `grid` is a non-zero multiple of the dimensions of this cluster.
+    pub fn divides(&self, grid: GridSize) -> bool {
+        [(self.x, grid.x), (self.y, grid.y), (self.z, grid.z)]
+            .iter()
+            .all(|&(cluster, grid)| cluster != 0 && grid != 0 && grid / cluster * cluster == grid)
+    }
+}
+impl From<u32> for ClusterSize {
+    fn from(x: u32) -> ClusterSize {
+        ClusterSize::x(x)
+    }
+}
+impl From<(u32, u32)> for ClusterSize {
+    fn from((x, y): (u32, u32)) -> ClusterSize {
+        ClusterSize::xy(x, y)
+    }
+}
+impl From<(u32, u32, u32)> for ClusterSize {
+    fn from((x, y, z): (u32, u32, u32)) -> ClusterSize {
+        ClusterSize::xyz(x, y, z)
+    }
+}
+impl<'a> From<&'a ClusterSize> for ClusterSize {
+    fn from(other: &ClusterSize) -> ClusterSize {
+        *other
+    }
+}
+
+fn check_dims(dims: [u32; 3], limits: [DeviceAttribute; 3], device: Device) -> CudaResult<()> {
+    for (dim, limit) in dims.iter().zip(limits.iter()) {
+        if *dim == 0 || *dim as i64 > device.get_attribute(*limit)? as i64 {
+            return Err(CudaError::InvalidLaunchConfiguration);
+        }
+    }
+    Ok(())
+}
+
+/// The grid size, block size and dynamic shared memory of a kernel launch.
+///
+/// Most kernels run one thread per element of their input, which is what [`LaunchConfig::for_len`]
+/// computes the grid for:
+///
+/// ```
+/// use cust::function::{BlockSize, GridSize, LaunchConfig};
+///
+/// const CONFIG: LaunchConfig = LaunchConfig::for_len(1000, 256);
+/// assert_eq!(CONFIG.grid, GridSize::x(4));
+/// assert_eq!(CONFIG.block, BlockSize::x(256));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaunchConfig {
+    pub grid: GridSize,
+    pub block: BlockSize,
+    pub shared_mem_bytes: u32,
+}
+
+impl Default for LaunchConfig {
+    /// A single thread without dynamic shared memory.
+    fn default() -> Self {
+        Self::new(1, 1, 0)
+    }
+}
+
+impl LaunchConfig {
+    pub fn new(
+        grid: impl Into<GridSize>,
+        block: impl Into<BlockSize>,
+        shared_mem_bytes: u32,
+    ) -> Self {
+        Self {
+            grid: grid.into(),
+            block: block.into(),
+            shared_mem_bytes,
+        }
+    }
+
+    /// A one-dimensional launch with blocks of `block` threads and enough blocks for at least
+    /// `len` threads. The grid is rounded up, so the last block may run past `len` and the kernel
+    /// must check its index against it. A `len` of zero still launches a single block, since grids
+    /// cannot be empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block` is zero, or if more than `u32::MAX` blocks are needed.
+    pub const fn for_len(len: usize, block: u32) -> Self {
+        Self {
+            grid: GridSize::x(blocks_for(len, block)),
+            block: BlockSize::x(block),
+            shared_mem_bytes: 0,
+        }
+    }
+
+    /// A two-dimensional launch with blocks of `block_x * block_y` threads and enough blocks to
+    /// cover `width * height` threads, rounding up in both dimensions like
+    /// [`for_len`](Self::for_len).
+    ///
+    /// # Panics
+    ///
+    /// Panics if either block dimension is zero, or if more than `u32::MAX` blocks are needed in
+    /// either dimension.
+    pub const fn for_len_xy(width: usize, height: usize, block_x: u32, block_y: u32) -> Self {
+        Self {
+            grid: GridSize::xy(blocks_for(width, block_x), blocks_for(height, block_y)),
+            block: BlockSize::xy(block_x, block_y),
+            shared_mem_bytes: 0,
+        }
+    }
+
+    /// Sets the amount of dynamic shared memory of the launch.
+    pub const fn with_shared_mem(mut self, shared_mem_bytes: u32) -> Self {
+        self.shared_mem_bytes = shared_mem_bytes;
+        self
+    }
+
+    /// Checks the grid and block sizes against the limits of `device` (see
+    /// [`GridSize::validate`] and [`BlockSize::validate`]), and that the dynamic shared memory
+    /// fits in the shared memory a kernel can opt into using on `device`, returning
+    /// [`CudaError::InvalidLaunchConfiguration`] otherwise.
+    ///
+    /// The driver rejects invalid launches with an unspecific [`CudaError::InvalidValue`], this
+    /// is meant for checking configurations computed at runtime before launching with them.
+    /// Launches ([`launch!`](crate::launch) and the `Stream::launch*` methods) only run this check
+    /// in debug builds.
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::context::CurrentContext;
+    /// use cust::error::CudaError;
+    /// use cust::function::LaunchConfig;
+    ///
+    /// let device = CurrentContext::get_device()?;
+    /// LaunchConfig::for_len(1 << 20, 256).validate(device)?;
+    /// assert_eq!(
+    ///     LaunchConfig::new(1, 4096, 0).validate(device),
+    ///     Err(CudaError::InvalidLaunchConfiguration)
+    /// );
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn validate(&self, device: Device) -> CudaResult<()> {
+        self.grid.validate(device)?;
+        self.block.validate(device)?;
+        let max_shared = device.get_attribute(DeviceAttribute::MaxSharedMemoryPerBlockOptin)?;
+        if self.shared_mem_bytes as i64 > max_shared as i64 {
+            return Err(CudaError::InvalidLaunchConfiguration);
+        }
+        Ok(())
+    }
+}
+
+/// The number of blocks of `block` threads needed for `len` threads, at least 1, the grid size of
+/// [`LaunchConfig::for_len`].
+///
+/// # Panics
+///
+/// Panics if `block` is zero, or if more than `u32::MAX` blocks are needed.
+pub const fn blocks_for(len: usize, block: u32) -> u32 {
+    assert!(block != 0, "block size must not be zero");
+    let block = block as usize;
+    let mut blocks = len / block;
+    if blocks * block < len || blocks == 0 {
+        blocks += 1;
+    }
+    assert!(
+        blocks <= u32::MAX as usize,
+        "too many blocks for a grid dimension"
+    );
+    blocks as u32
+}
+
+/// The maximum size in bytes of the parameters of a kernel.
+pub const MAX_PARAMS_SIZE: usize = 4096;
+
+/// A struct holding all of the parameters of a kernel, which is launched with
+/// [`Stream::launch_params`](crate::stream::Stream::launch_params) and takes the struct by value
+/// as its only parameter.
+///
+/// Long lists of positional kernel arguments are easy to get out of sync between the kernel and
+/// its launches, since the driver only checks their total size. Defining the struct in a crate
+/// shared by the host and the GPU crate instead makes the compiler check every field:
+///
+/// ```ignore
+/// // shared crate
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// #[cfg_attr(not(target_os = "cuda"), derive(cust::LaunchParams))]
+/// pub struct BlurParams {
+///     pub input: *const f32,
+///     pub output: *mut f32,
+///     pub width: u32,
+///     pub height: u32,
+///     pub radius: f32,
+/// }
+///
+/// // GPU crate
+/// #[kernel]
+/// pub unsafe fn blur(params: BlurParams) { ... }
+///
+/// // host
+/// let params = BlurParams {
+///     input: input_gpu.as_device_ptr().as_raw(),
+///     output: output_gpu.as_device_ptr().as_raw_mut(),
+///     width,
+///     height,
+///     radius: 2.5,
+/// };
+/// unsafe { stream.launch_params(&module.get_function("blur")?, grid, block, 0, &params)? };
+/// ```
+///
+/// Parameters which are the same for many launches can be kept in the constant buffer of the
+/// module instead, see `cuda_std::uniform`.
+///
+/// # Safety
+///
+/// The type must have the same layout on the host and the device, which `#[repr(C)]` structs
+/// of `DeviceCopy` fields and raw pointers have. It should be implemented with
+/// `#[derive(LaunchParams)]`, which checks this and that the struct fits in [`MAX_PARAMS_SIZE`].
+pub unsafe trait LaunchParams: Copy {}
+
+/// A value which can be passed as an argument of [`launch!`](crate::launch), as one or more kernel
+/// parameters.
+///
+/// Every [`DeviceCopy`] value is a single parameter. Device slices are two, the pointer and the
+/// length, which is how kernels take `&[T]`, `&mut [T]` and `&str` parameters, so kernels don't
+/// need a pointer and a length parameter for every buffer:
+///
+/// ```ignore
+/// // GPU crate
+/// #[kernel]
+/// pub unsafe fn scale(values: &mut [f32], factor: f32) { ... }
+///
+/// // host
+/// let mut values = DeviceBuffer::from_slice(&[1.0f32; 256])?;
+/// unsafe { launch!(module.scale<<<1, 256, 0, stream>>>(&mut values, 2.0f32))? };
+/// ```
+///
+/// `&str` parameters take a `&DeviceSlice<u8>` of UTF-8 on the host.
+///
+/// # Safety
+///
+/// [`push_values`](KernelParam::push_values) must push references to values with the layout of
+/// the kernel parameters on the device.
+pub unsafe trait KernelParam {
+    /// The values of the parameters, which are kept alive until the kernel is launched.
+    type Values;
+
+    /// Converts the argument to the values of its parameters.
+    fn into_values(self) -> Self::Values;
+
+    /// Pushes the value of every parameter to `params`, in order.
+    fn push_values<const N: usize>(values: &Self::Values, params: &mut KernelParams<N>);
+}
+
+unsafe impl<T: DeviceCopy> KernelParam for T {
+    type Values = T;
+
+    fn into_values(self) -> T {
+        self
+    }
+
+    fn push_values<const N: usize>(values: &T, params: &mut KernelParams<N>) {
+        params.push(values);
+    }
+}
+
+macro_rules! slice_params {
+    ($($ty:ty),*) => {
+        $(
+            unsafe impl<'a, T: DeviceCopy> KernelParam for $ty {
+                type Values = (*const T, usize);
+
+                fn into_values(self) -> Self::Values {
+                    (self.as_ptr(), self.len())
+                }
+
+                fn push_values<const N: usize>(values: &Self::Values, params: &mut KernelParams<N>) {
+                    params.push(&values.0);
+                    params.push(&values.1);
+                }
+            }
+        )*
+    };
+}
+
+slice_params!(
+    &'a DeviceSlice<T>,
+    &'a mut DeviceSlice<T>,
+    &'a DeviceBuffer<T>,
+    &'a mut DeviceBuffer<T>
+);
+
+/// The values of an argument of [`launch!`](crate::launch), used by the macro.
+#[doc(hidden)]
+pub struct KernelArg<P: KernelParam>(P::Values);
+
+impl<P: KernelParam> KernelArg<P> {
+    pub fn new(param: P) -> Self {
+        Self(param.into_values())
+    }
+
+    pub fn push_values<const N: usize>(&self, params: &mut KernelParams<N>) {
+        P::push_values(&self.0, params);
+    }
+}
+
+/// The pointers to the parameters of a launch, and the sizes of the parameters. The first `N`
+/// parameters are stored inline, [`launch!`](crate::launch) reserves two for every argument so
+/// that it never allocates for scalars and slices.
+///
+/// Dereferences to the pointers, which can be passed to [`Stream::launch`](crate::stream::Stream::launch).
+pub struct KernelParams<const N: usize> {
+    len: usize,
+    ptrs: [*mut c_void; N],
+    sizes: [usize; N],
+    // only used once more than `N` parameters are pushed.
+    spilled: Option<(Vec<*mut c_void>, Vec<usize>)>,
+}
+
+impl<const N: usize> KernelParams<N> {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            ptrs: [std::ptr::null_mut(); N],
+            sizes: [0; N],
+            spilled: None,
+        }
+    }
+
+    /// Pushes a pointer to `value` as the next parameter, `value` must stay alive until the
+    /// parameters are used.
+    pub fn push<T>(&mut self, value: &T) {
+        let (ptr, size) = (value as *const T as *mut c_void, mem::size_of::<T>());
+        if self.spilled.is_none() && self.len == N {
+            self.spilled = Some((self.ptrs.to_vec(), self.sizes.to_vec()));
+        }
+        match &mut self.spilled {
+            Some((ptrs, sizes)) => {
+                ptrs.push(ptr);
+                sizes.push(size);
+            }
+            None => {
+                self.ptrs[self.len] = ptr;
+                self.sizes[self.len] = size;
+            }
+        }
+        self.len += 1;
+    }
+
+    /// The pointers to the parameters, in order.
+    pub fn ptrs(&self) -> &[*mut c_void] {
+        match &self.spilled {
+            Some((ptrs, _)) => ptrs,
+            None => &self.ptrs[..self.len],
+        }
+    }
+
+    /// The sizes of the parameters in bytes, in order.
+    pub fn sizes(&self) -> &[usize] {
+        match &self.spilled {
+            Some((_, sizes)) => sizes,
+            None => &self.sizes[..self.len],
+        }
+    }
+}
+
+impl<const N: usize> Default for KernelParams<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for KernelParams<N> {
+    type Target = [*mut c_void];
+
+    fn deref(&self) -> &Self::Target {
+        self.ptrs()
+    }
+}
 
 /// All supported function attributes for [Function::get_attribute](struct.Function.html#method.get_attribute)
 #[repr(u32)]
@@ -197,6 +724,14 @@ pub enum FunctionAttribute {
     /// The attribute to indicate whether the function has been compiled with user specified
     /// option "-Xptxas --dlcm=ca" set.
     CacheModeCa = 7,
+
+    /// The maximum size in bytes of dynamically-allocated shared memory that can be used by this
+    /// function, set with [`Function::set_max_dynamic_shared_memory`].
+    MaxDynamicSharedSizeBytes = 8,
+
+    /// The preferred shared memory carveout in percent of the maximum shared memory, or `-1` if no
+    /// preference was set, set with [`Function::set_shared_memory_carveout`].
+    PreferredSharedMemoryCarveout = 9,
 }
 
 /// Handle to a global kernel function.
@@ -306,6 +841,81 @@ impl<'a> Function<'a> {
         unsafe { cuda::cuFuncSetSharedMemConfig(self.inner, transmute(cfg)).to_result() }
     }
 
+    /// Sets the maximum size in bytes of dynamically-allocated shared memory this function can be
+    /// launched with.
+    ///
+    /// Launches requesting more than 48KB of dynamic shared memory fail unless this was raised
+    /// first. The sum of `bytes` and the function's static shared memory must not exceed
+    /// [`DeviceAttribute::MaxSharedMemoryPerBlockOptin`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// # use cust::module::Module;
+    /// # use std::ffi::CString;
+    /// # let ptx = CString::new(include_str!("../resources/add.ptx"))?;
+    /// # let module = Module::load_from_string(&ptx)?;
+    /// use cust::device::DeviceAttribute;
+    /// let mut function = module.get_function("sum")?;
+    /// let optin = Device::get_device(0)?.get_attribute(DeviceAttribute::MaxSharedMemoryPerBlockOptin)?;
+    /// function.set_max_dynamic_shared_memory(optin as u32)?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_max_dynamic_shared_memory(&mut self, bytes: u32) -> CudaResult<()> {
+        unsafe {
+            cuda::cuFuncSetAttribute(
+                self.inner,
+                cuda::CUfunction_attribute::CU_FUNC_ATTRIBUTE_MAX_DYNAMIC_SHARED_SIZE_BYTES,
+                bytes as i32,
+            )
+            .to_result()
+        }
+    }
+
+    /// Sets the preferred shared memory carveout of this function, in percent of the maximum
+    /// shared memory of a multiprocessor, the rest of the unified L1/shared memory being used as L1
+    /// cache. `None` resets it to the driver's default.
+    ///
+    /// This is only a hint, the driver rounds it to a supported configuration and may choose a
+    /// different one if required to execute the function. Percentages above 100 are clamped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// # use cust::module::Module;
+    /// # use std::ffi::CString;
+    /// # let ptx = CString::new(include_str!("../resources/add.ptx"))?;
+    /// # let module = Module::load_from_string(&ptx)?;
+    /// let mut function = module.get_function("sum")?;
+    /// // prefer as much L1 cache as possible
+    /// function.set_shared_memory_carveout(Some(0))?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_shared_memory_carveout(&mut self, carveout: Option<u32>) -> CudaResult<()> {
+        let value = match carveout {
+            Some(percent) => percent.min(100) as i32,
+            None => -1,
+        };
+        unsafe {
+            cuda::cuFuncSetAttribute(
+                self.inner,
+                cuda::CUfunction_attribute::CU_FUNC_ATTRIBUTE_PREFERRED_SHARED_MEMORY_CARVEOUT,
+                value,
+            )
+            .to_result()
+        }
+    }
+
     /// Retrieves a raw handle to this function.
     pub fn to_raw(&self) -> CUfunction {
         self.inner
@@ -434,6 +1044,15 @@ impl<'a> Function<'a> {
 /// In this variant, the `function` parameter must be a variable. Use this form to avoid looking up
 /// the kernel function for each call.
 ///
+/// The parameters can be any [`KernelParam`], which are [`DeviceCopy`] values and device slices
+/// for kernels taking `&[T]`, `&mut [T]` or `&str` parameters.
+///
+/// In debug builds, the grid, block and shared memory sizes are checked against the limits of the
+/// current device before launching, an invalid launch returns
+/// [`CudaError::InvalidLaunchConfiguration`]. Release builds skip the check and leave it to the driver,
+/// which returns an unspecific [`CudaError::InvalidValue`], call [`LaunchConfig::validate`] to check
+/// configurations computed at runtime in every build.
+///
 /// # Safety
 ///
 /// Launching kernels must be done in an `unsafe` block. Calling a kernel is similar to calling a
@@ -523,21 +1142,140 @@ macro_rules! launch {
         }
     };
     ($function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* $(,)?)) => {
+        $crate::with_kernel_params!(
+            params => $stream.launch(&$function, $grid, $block, $shared, &params);
+            [] $($arg,)*
+        )
+    };
+}
+
+/// The kernel parameters of `Stream::launch` from local variables holding the arguments, an array
+/// of pointers to the variables. Unlike [`launch!`], the arguments are passed as they are, so a
+/// slice parameter takes a pointer and a length variable.
+///
+/// ```no_run
+/// # use cust::prelude::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::function::blocks_for;
+///
+/// let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+/// let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+/// let sum = module.get_function("sum")?;
+/// let x = DeviceBuffer::from_slice(&[1.0f32; 10])?;
+/// let y = DeviceBuffer::from_slice(&[2.0f32; 10])?;
+/// let out = DeviceBuffer::from_slice(&[0.0f32; 10])?;
+///
+/// let (mut x, mut y) = (x.as_device_ptr(), y.as_device_ptr());
+/// let (mut out, mut len) = (out.as_device_ptr(), out.len());
+/// let params = cust::params!(x, y, out, len);
+/// unsafe {
+///     stream.launch(&sum, blocks_for(len, 256), 256, 0, &params)?;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! params {
+    ($($param:ident),* $(,)?) => {
+        [$(&mut $param as *mut _ as *mut ::std::ffi::c_void),*]
+    };
+}
+
+/// Converts the arguments of a launch to kernel parameters with [`KernelParam`], and evaluates
+/// `$body` with `$params` bound to the pointers to the parameters.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! with_kernel_params {
+    (@count) => { 0 };
+    (@count $first:ident $($rest:ident)*) => { 1 + $crate::with_kernel_params!(@count $($rest)*) };
+    ($params:ident => $body:expr; [$($bound:ident)*]) => {
         {
-            fn assert_impl_devicecopy<T: $crate::memory::DeviceCopy>(_val: T) {}
-            if false {
-                $(
-                    assert_impl_devicecopy($arg);
-                )*
-            };
-
-            $stream.launch(&$function, $grid, $block, $shared,
-                &[
-                    $(
-                        &$arg as *const _ as *mut ::std::ffi::c_void,
-                    )*
-                ]
-            )
+            let mut $params = $crate::function::KernelParams::<
+                { 2 * $crate::with_kernel_params!(@count $($bound)*) },
+            >::new();
+            $(
+                $bound.push_values(&mut $params);
+            )*
+            $body
         }
     };
+    // binds every argument to a variable of its own (they are distinct because of hygiene), so
+    // that the values of its parameters live until the launch.
+    ($params:ident => $body:expr; [$($bound:ident)*] $arg:expr, $($rest:expr,)*) => {
+        {
+            let arg = $crate::function::KernelArg::new($arg);
+            $crate::with_kernel_params!($params => $body; [$($bound)* arg] $($rest,)*)
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::DevicePointer;
+
+    #[test]
+    fn cluster_divides_grid() {
+        assert!(ClusterSize::x(2).divides(GridSize::xy(8, 3)));
+        assert!(ClusterSize::xy(2, 4).divides(GridSize::xy(6, 8)));
+        assert!(!ClusterSize::x(4).divides(GridSize::x(6)));
+        assert!(!ClusterSize::xy(1, 2).divides(GridSize::xy(4, 3)));
+        assert!(!ClusterSize::x(0).divides(GridSize::x(4)));
+    }
+
+    #[test]
+    fn launch_config_for_len() {
+        assert_eq!(LaunchConfig::for_len(1024, 256).grid, GridSize::x(4));
+        assert_eq!(LaunchConfig::for_len(1025, 256).grid, GridSize::x(5));
+        assert_eq!(LaunchConfig::for_len(1, 256).grid, GridSize::x(1));
+        assert_eq!(LaunchConfig::for_len(0, 256).grid, GridSize::x(1));
+        assert_eq!(
+            LaunchConfig::for_len_xy(1920, 1080, 16, 16).grid,
+            GridSize::xy(120, 68)
+        );
+    }
+
+    #[test]
+    fn slices_are_two_params() {
+        let ptr = 0x1000 as *mut f32;
+        let slice = unsafe { DeviceSlice::from_raw_parts(DevicePointer::wrap(ptr), 16) };
+        let (scalar, slice) = (KernelArg::new(3u32), KernelArg::new(slice));
+        let mut params = KernelParams::<4>::new();
+        scalar.push_values(&mut params);
+        slice.push_values(&mut params);
+
+        assert_eq!(params.len(), 3);
+        assert_eq!(
+            params.sizes(),
+            &[4, mem::size_of::<*const f32>(), mem::size_of::<usize>()]
+        );
+        unsafe {
+            assert_eq!(*(params[0] as *const u32), 3);
+            assert_eq!(*(params[1] as *const *const f32), ptr as *const f32);
+            assert_eq!(*(params[2] as *const usize), 16);
+        }
+    }
+
+    #[test]
+    fn params_spill_past_capacity() {
+        let values = [1u32, 2, 3];
+        let mut params = KernelParams::<2>::new();
+        for value in &values {
+            params.push(value);
+        }
+
+        assert_eq!(params.len(), 3);
+        assert_eq!(params.sizes(), &[4; 3]);
+        for (ptr, value) in params.iter().zip(&values) {
+            assert_eq!(*ptr as *const u32, value as *const u32);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn launch_config_zero_block() {
+        LaunchConfig::for_len(1024, 0);
+    }
 }
@@ -2,51 +2,55 @@
 
 use std::{
     ffi::c_void,
-    mem::{ManuallyDrop, MaybeUninit},
+    mem::{self, ManuallyDrop, MaybeUninit},
     os::raw::{c_char, c_uint},
     path::Path,
-    ptr,
+    ptr, slice,
 };
 
 use crate::{
+    context::{ContextHandle, CurrentContext},
     error::{CudaResult, ToResult},
-    function::{BlockSize, GridSize},
+    function::{BlockSize, GridSize, KernelParams},
+    memory::{DeviceCopy, DeviceSlice},
+    stream::Stream,
     sys as cuda,
 };
 
-/// Creates a kernel invocation using the same syntax as [`launch`] to be used to insert kernel launches inside graphs.
-/// This returns a Result of a kernel invocation object you can then pass to a graph.
+/// Creates a kernel invocation using the same syntax as [`launch`](crate::launch) to be used to insert
+/// kernel launches inside graphs. This returns a Result of a kernel invocation object you can then pass to a graph.
+///
+/// The stream is only accepted for parity with [`launch`](crate::launch), it is not used because the stream
+/// is chosen when the graph is launched.
+///
+/// The parameters can be any [`KernelParam`](crate::function::KernelParam), like with [`launch`](crate::launch).
+/// They are copied into the invocation, therefore they may be dropped after this macro is invoked.
+/// However, any memory they point to (such as device buffers) must stay valid until the graph is executed.
 #[macro_export]
 macro_rules! kernel_invocation {
-    ($module:ident . $function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* )) => {
+    ($module:ident . $function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* $(,)?)) => {
         {
-            let name = std::ffi::CString::new(stringify!($function)).unwrap();
-            let function = $module.get_function(&name);
+            let function = $module.get_function(stringify!($function));
             match function {
-                Ok(f) => kernel_invocation!(f<<<$grid, $block, $shared, $stream>>>( $($arg),* ) ),
+                Ok(f) => $crate::kernel_invocation!(f<<<$grid, $block, $shared, $stream>>>( $($arg),* ) ),
                 Err(e) => Err(e),
             }
         }
     };
-    ($function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* )) => {
-        {
-            fn assert_impl_devicecopy<T: $crate::memory::DeviceCopy>(_val: T) {}
-            if false {
-                $(
-                    assert_impl_devicecopy($arg);
-                )*
+    ($function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* $(,)?)) => {
+        $crate::with_kernel_params!(
+            params => {
+                let _ = &$stream;
+                $crate::error::CudaResult::Ok($crate::graph::KernelInvocation::_new_internal(
+                    $crate::function::BlockSize::from($block),
+                    $crate::function::GridSize::from($grid),
+                    $shared,
+                    $function.to_raw(),
+                    $crate::graph::KernelInvocation::_param_bytes(&params),
+                ))
             };
-
-            let boxed = vec![$(&$arg as *const _ as *mut ::std::ffi::c_void),*].into_boxed_slice();
-
-            Ok($crate::graph::KernelInvocation::_new_internal(
-                $crate::function::BlockSize::from($block),
-                $crate::function::GridSize::from($grid),
-                $shared,
-                $function.to_raw(),
-                vec![].into_boxed_slice(),
-            ))
-        }
+            [] $($arg,)*
+        )
     };
 }
 
@@ -57,8 +61,9 @@ pub struct KernelInvocation {
     pub grid_dim: GridSize,
     pub shared_mem_bytes: u32,
     func: cuda::CUfunction,
-    params: Box<*mut c_void>,
-    params_len: Option<usize>,
+    // owned copies of every parameter, the driver copies them out of here
+    // when the node is created or its parameters are updated.
+    params: Vec<Box<[u8]>>,
 }
 
 impl KernelInvocation {
@@ -68,8 +73,7 @@ impl KernelInvocation {
         grid_dim: GridSize,
         shared_mem_bytes: u32,
         func: cuda::CUfunction,
-        params: Box<*mut c_void>,
-        params_len: usize,
+        params: Vec<Box<[u8]>>,
     ) -> Self {
         Self {
             block_dim,
@@ -77,12 +81,28 @@ impl KernelInvocation {
             shared_mem_bytes,
             func,
             params,
-            params_len: Some(params_len),
         }
     }
 
-    pub fn to_raw(self) -> cuda::CUDA_KERNEL_NODE_PARAMS {
-        cuda::CUDA_KERNEL_NODE_PARAMS {
+    #[doc(hidden)]
+    pub fn _param_bytes<const N: usize>(params: &KernelParams<N>) -> Vec<Box<[u8]>> {
+        params
+            .iter()
+            .zip(params.sizes())
+            // SAFETY: kernel parameters are allowed to be bitwise copied to the GPU.
+            .map(|(&ptr, &size)| unsafe { slice::from_raw_parts(ptr as *const u8, size) }.into())
+            .collect()
+    }
+
+    /// Runs `f` with the raw parameters of this invocation, the raw parameters borrow from `self`
+    /// so they are only valid inside of `f`.
+    fn with_raw<R>(&self, f: impl FnOnce(&cuda::CUDA_KERNEL_NODE_PARAMS) -> R) -> R {
+        let mut params = self
+            .params
+            .iter()
+            .map(|p| p.as_ptr() as *mut c_void)
+            .collect::<Vec<_>>();
+        let raw = cuda::CUDA_KERNEL_NODE_PARAMS {
             func: self.func,
             gridDimX: self.grid_dim.x,
             gridDimY: self.grid_dim.y,
@@ -90,28 +110,11 @@ impl KernelInvocation {
             blockDimX: self.block_dim.x,
             blockDimY: self.block_dim.y,
             blockDimZ: self.block_dim.z,
-            kernelParams: Box::into_raw(self.params),
+            kernelParams: params.as_mut_ptr(),
             sharedMemBytes: self.shared_mem_bytes,
             extra: ptr::null_mut(),
-        }
-    }
-
-    /// Makes a new invocation from its raw counterpart.
-    ///
-    /// # Safety
-    ///
-    /// The function pointer must be a valid CUfunction pointer and
-    /// params' "ownership" must be able to be transferred to the invocation
-    /// (it will be turned into a Box).
-    pub unsafe fn from_raw(raw: cuda::CUDA_KERNEL_NODE_PARAMS) -> Self {
-        Self {
-            func: raw.func,
-            grid_dim: GridSize::xyz(raw.gridDimX, raw.gridDimY, raw.gridDimZ),
-            block_dim: BlockSize::xyz(raw.blockDimX, raw.gridDimY, raw.gridDimZ),
-            params: Box::from_raw(raw.kernelParams),
-            shared_mem_bytes: raw.sharedMemBytes,
-            params_len: None,
-        }
+        };
+        f(&raw)
     }
 }
 
@@ -366,7 +369,7 @@ impl Graph {
             );
         }
 
-        unsafe { cuGraphDebugDotPrint(self.raw, "./out.dot\0".as_ptr().cast(), 1 << 0).to_result() }
+        unsafe { cuGraphDebugDotPrint(self.raw, buf.as_ptr().cast(), 1 << 0).to_result() }
     }
 
     /// Adds a kernel invocation node to this graph, [`KernelInvocation`] can be created using
@@ -377,26 +380,174 @@ impl Graph {
         invocation: KernelInvocation,
         dependencies: impl AsRef<[GraphNode]>,
     ) -> CudaResult<GraphNode> {
-        let deps = dependencies.as_ref();
-        self.check_deps_are_valid("add_kernel_node", deps)?;
+        self.add_node(
+            "add_kernel_node",
+            NodeKind::Kernel(invocation),
+            dependencies.as_ref(),
+        )
+    }
+
+    fn add_node(
+        &mut self,
+        func_name: &str,
+        kind: NodeKind,
+        deps: &[GraphNode],
+    ) -> CudaResult<GraphNode> {
+        self.check_deps_are_valid(func_name, deps)?;
         // invalidate cache because it will change.
         self.node_cache = None;
         unsafe {
             let deps_ptr = deps.as_ptr().cast();
             let mut node = MaybeUninit::<GraphNode>::uninit();
-            let params = invocation.to_raw();
-            cuda::cuGraphAddKernelNode(
-                node.as_mut_ptr().cast(),
-                self.raw,
-                deps_ptr,
-                deps.len(),
-                &params as *const _,
-            )
-            .to_result()?;
+            match kind {
+                NodeKind::Kernel(invocation) => invocation.with_raw(|params| {
+                    cuda::cuGraphAddKernelNode(
+                        node.as_mut_ptr().cast(),
+                        self.raw,
+                        deps_ptr,
+                        deps.len(),
+                        params as *const _,
+                    )
+                    .to_result()
+                })?,
+                NodeKind::Memcpy(params) => {
+                    let ctx = CurrentContext::get_current()?;
+                    cuda::cuGraphAddMemcpyNode(
+                        node.as_mut_ptr().cast(),
+                        self.raw,
+                        deps_ptr,
+                        deps.len(),
+                        &params as *const _,
+                        ctx.get_inner(),
+                    )
+                    .to_result()?
+                }
+                NodeKind::Empty => cuda::cuGraphAddEmptyNode(
+                    node.as_mut_ptr().cast(),
+                    self.raw,
+                    deps_ptr,
+                    deps.len(),
+                )
+                .to_result()?,
+            }
             Ok(node.assume_init())
         }
     }
 
+    /// Starts adding a kernel invocation node to this graph, [`KernelInvocation`] can be created using
+    /// [`kernel_invocation`]. The node is inserted once [`NodeBuilder::add`] is called.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use cust::*;
+    /// # use cust::graph::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// # let module = module::Module::from_str("")?;
+    /// # let stream = Stream::new(StreamFlags::DEFAULT, None)?;
+    /// let input = [1.0f32; 1024];
+    /// let mut output = [0.0f32; 1024];
+    /// let mut buf = memory::DeviceBuffer::from_slice(&[0.0f32; 1024])?;
+    ///
+    /// let mut graph = Graph::new(GraphCreationFlags::NONE)?;
+    /// let upload = graph.copy_htod(&mut buf, &input).add()?;
+    /// let kernel = graph
+    ///     .kernel(kernel_invocation!(module.double<<<4, 256, 0, stream>>>(buf.as_device_ptr(), buf.len()))?)
+    ///     .after(&upload)
+    ///     .add()?;
+    /// graph.copy_dtoh(&mut output, &buf).after(&kernel).add()?;
+    ///
+    /// let exec = graph.instantiate()?;
+    /// unsafe { exec.launch(&stream)? };
+    /// stream.synchronize()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn kernel(&mut self, invocation: KernelInvocation) -> NodeBuilder<'_> {
+        NodeBuilder::new(self, NodeKind::Kernel(invocation))
+    }
+
+    /// Starts adding a node which copies `src` from the host to `dst` on the device.
+    ///
+    /// `src` is read when the graph is executed, not when the node is added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` and `src` have different lengths.
+    pub fn copy_htod<T: DeviceCopy>(
+        &mut self,
+        dst: &mut DeviceSlice<T>,
+        src: &[T],
+    ) -> NodeBuilder<'_> {
+        assert_eq!(
+            dst.len(),
+            src.len(),
+            "destination and source slices have different lengths"
+        );
+        let mut params = memcpy_params::<T>(src.len());
+        params.srcMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_HOST;
+        params.srcHost = src.as_ptr().cast();
+        params.dstMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        params.dstDevice = dst.as_mut_ptr() as cuda::CUdeviceptr;
+        NodeBuilder::new(self, NodeKind::Memcpy(params))
+    }
+
+    /// Starts adding a node which copies `src` from the device to `dst` on the host.
+    ///
+    /// `dst` is written when the graph is executed, not when the node is added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` and `src` have different lengths.
+    pub fn copy_dtoh<T: DeviceCopy>(
+        &mut self,
+        dst: &mut [T],
+        src: &DeviceSlice<T>,
+    ) -> NodeBuilder<'_> {
+        assert_eq!(
+            dst.len(),
+            src.len(),
+            "destination and source slices have different lengths"
+        );
+        let mut params = memcpy_params::<T>(src.len());
+        params.srcMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        params.srcDevice = src.as_ptr() as cuda::CUdeviceptr;
+        params.dstMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_HOST;
+        params.dstHost = dst.as_mut_ptr().cast();
+        NodeBuilder::new(self, NodeKind::Memcpy(params))
+    }
+
+    /// Starts adding a node which copies `src` to `dst`, both on the device.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dst` and `src` have different lengths.
+    pub fn copy_dtod<T: DeviceCopy>(
+        &mut self,
+        dst: &mut DeviceSlice<T>,
+        src: &DeviceSlice<T>,
+    ) -> NodeBuilder<'_> {
+        assert_eq!(
+            dst.len(),
+            src.len(),
+            "destination and source slices have different lengths"
+        );
+        let mut params = memcpy_params::<T>(src.len());
+        params.srcMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        params.srcDevice = src.as_ptr() as cuda::CUdeviceptr;
+        params.dstMemoryType = cuda::CUmemorytype::CU_MEMORYTYPE_DEVICE;
+        params.dstDevice = dst.as_mut_ptr() as cuda::CUdeviceptr;
+        NodeBuilder::new(self, NodeKind::Memcpy(params))
+    }
+
+    /// Starts adding a node which does nothing, this is useful for joining many dependencies
+    /// into a single node.
+    pub fn empty(&mut self) -> NodeBuilder<'_> {
+        NodeBuilder::new(self, NodeKind::Empty)
+    }
+
     /// The number of edges (dependency edges) inside this graph.
     pub fn num_edges(&mut self) -> CudaResult<usize> {
         unsafe {
@@ -452,22 +603,46 @@ impl Graph {
         }
     }
 
-    /// Retrieves the invocation parameters for a kernel invocation node.
+    /// Replaces the parameters (function, launch dimensions, and arguments) of a kernel invocation node.
+    /// This does not affect any [`GraphExec`] which was already instantiated from this graph, use
+    /// [`GraphExec::set_kernel_params`] or [`GraphExec::update`] for that.
     ///
     /// # Panics
     ///
     /// Panics if the node is invalid or if the node is not a kernel invocation node.
-    pub fn kernel_node_params(&mut self, node: GraphNode) -> CudaResult<KernelInvocation> {
-        self.check_deps_are_valid("kernel_node_params", &[node])?;
+    pub fn set_kernel_params(
+        &mut self,
+        node: GraphNode,
+        invocation: &KernelInvocation,
+    ) -> CudaResult<()> {
+        self.check_deps_are_valid("set_kernel_params", &[node])?;
         assert_eq!(
             self.node_type(node)?,
             GraphNodeType::KernelInvocation,
-            "Node given to `kernel_node_params` was not a kernel invocation node"
+            "Node given to `set_kernel_params` was not a kernel invocation node"
         );
+        invocation.with_raw(|params| unsafe {
+            cuda::cuGraphKernelNodeSetParams(node.to_raw(), params as *const _).to_result()
+        })
+    }
+
+    /// Instantiates this graph into an executable graph which can then be launched on a stream.
+    /// The executable graph is a snapshot, later changes to this graph are not reflected in it unless
+    /// [`GraphExec::update`] is called.
+    pub fn instantiate(&mut self) -> CudaResult<GraphExec> {
+        let mut raw = MaybeUninit::uninit();
         unsafe {
-            let mut params = MaybeUninit::uninit();
-            cuda::cuGraphKernelNodeGetParams(node.to_raw(), params.as_mut_ptr());
-            Ok(KernelInvocation::from_raw(params.assume_init()))
+            cuda::cuGraphInstantiate_v2(
+                raw.as_mut_ptr(),
+                self.raw,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                0,
+            )
+            .to_result()?;
+            Ok(GraphExec {
+                raw: raw.assume_init(),
+            })
         }
     }
 
@@ -501,3 +676,151 @@ impl Drop for Graph {
         }
     }
 }
+
+fn memcpy_params<T>(len: usize) -> cuda::CUDA_MEMCPY3D {
+    // SAFETY: an all-zero CUDA_MEMCPY3D is valid, it is just a bag of integers and pointers.
+    let mut params: cuda::CUDA_MEMCPY3D = unsafe { mem::zeroed() };
+    params.WidthInBytes = len * mem::size_of::<T>();
+    params.Height = 1;
+    params.Depth = 1;
+    params
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Kernel(KernelInvocation),
+    Memcpy(cuda::CUDA_MEMCPY3D),
+    Empty,
+}
+
+/// A node which is about to be added to a [`Graph`], created by functions such as [`Graph::kernel`]
+/// or [`Graph::copy_htod`]. Dependencies are declared with [`after`](Self::after) and the node is
+/// inserted into the graph with [`add`](Self::add).
+#[derive(Debug)]
+#[must_use = "the node is not added to the graph until `add` is called"]
+pub struct NodeBuilder<'g> {
+    graph: &'g mut Graph,
+    kind: NodeKind,
+    deps: Vec<GraphNode>,
+}
+
+impl<'g> NodeBuilder<'g> {
+    fn new(graph: &'g mut Graph, kind: NodeKind) -> Self {
+        Self {
+            graph,
+            kind,
+            deps: vec![],
+        }
+    }
+
+    /// Makes this node execute after `node` has finished executing.
+    pub fn after(mut self, node: &GraphNode) -> Self {
+        if !self.deps.contains(node) {
+            self.deps.push(*node);
+        }
+        self
+    }
+
+    /// Makes this node execute after every node in `nodes` has finished executing.
+    pub fn after_all<'a>(mut self, nodes: impl IntoIterator<Item = &'a GraphNode>) -> Self {
+        for node in nodes {
+            self = self.after(node);
+        }
+        self
+    }
+
+    /// Adds the node to the graph, returning a handle to it which can be used as a dependency
+    /// for other nodes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any of the dependencies are invalid (dropped or from another graph).
+    pub fn add(self) -> CudaResult<GraphNode> {
+        self.graph
+            .add_node("NodeBuilder::add", self.kind, &self.deps)
+    }
+}
+
+/// An executable graph, created from a [`Graph`] with [`Graph::instantiate`].
+///
+/// Executable graphs can be launched on a stream any number of times, launching a graph is much
+/// cheaper than launching every one of its nodes separately.
+#[derive(Debug)]
+pub struct GraphExec {
+    raw: cuda::CUgraphExec,
+}
+
+// SAFETY: same reasoning as Graph, every method that mutates the executable graph takes `&mut self`.
+unsafe impl Send for GraphExec {}
+unsafe impl Sync for GraphExec {}
+
+impl GraphExec {
+    /// Launches this graph on a stream. The graph executes after any previous work on the stream
+    /// and any work queued afterwards waits for the graph to finish.
+    ///
+    /// # Safety
+    ///
+    /// Launching a graph has the same invariants as launching every kernel inside of it, additionally, every
+    /// buffer referenced by the graph (by copy nodes or kernel parameters) must still be alive.
+    pub unsafe fn launch(&self, stream: &Stream) -> CudaResult<()> {
+        let _span = trace_span!("cust::graph_launch", stream = stream.as_inner() as usize);
+        cuda::cuGraphLaunch(self.raw, stream.as_inner()).to_result()
+    }
+
+    /// Replaces the parameters of a kernel invocation node in this executable graph without having to
+    /// instantiate the graph again. `node` is the node in the [`Graph`] this was instantiated from.
+    ///
+    /// The new invocation must use a function from the same context as the original one.
+    pub fn set_kernel_params(
+        &mut self,
+        node: GraphNode,
+        invocation: &KernelInvocation,
+    ) -> CudaResult<()> {
+        invocation.with_raw(|params| unsafe {
+            cuda::cuGraphExecKernelNodeSetParams(self.raw, node.to_raw(), params as *const _)
+                .to_result()
+        })
+    }
+
+    /// Updates the parameters of every node in this executable graph to match `graph`, which must be the
+    /// graph this was instantiated from (or a graph with identical topology).
+    ///
+    /// Returns an error if the graph's topology changed, in which case the graph must be instantiated again.
+    pub fn update(&mut self, graph: &Graph) -> CudaResult<()> {
+        let mut error_node = MaybeUninit::uninit();
+        let mut result = MaybeUninit::uninit();
+        unsafe {
+            cuda::cuGraphExecUpdate(
+                self.raw,
+                graph.raw,
+                error_node.as_mut_ptr(),
+                result.as_mut_ptr(),
+            )
+            .to_result()
+        }
+    }
+
+    /// Creates a new [`GraphExec`] from a raw handle.
+    ///
+    /// # Safety
+    ///
+    /// The handle must be valid and exclusive, nothing else may use or destroy it.
+    pub unsafe fn from_raw(raw: cuda::CUgraphExec) -> Self {
+        Self { raw }
+    }
+
+    /// Consumes this [`GraphExec`], turning it into a raw handle. The handle will not be destroyed,
+    /// it is up to the caller to ensure the executable graph is destroyed.
+    pub fn into_raw(self) -> cuda::CUgraphExec {
+        let me = ManuallyDrop::new(self);
+        me.raw
+    }
+}
+
+impl Drop for GraphExec {
+    fn drop(&mut self) {
+        unsafe {
+            cuda::cuGraphExecDestroy(self.raw);
+        }
+    }
+}
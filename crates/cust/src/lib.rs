@@ -54,37 +54,62 @@
 //! Cust will try to find the CUDA libraries automatically, if it is unable to find it, you can set
 //! `CUDA_LIBRARY_PATH` to some path manually.
 
+#[macro_use]
+mod trace;
+
+#[cfg(feature = "capture")]
+pub mod capture;
 pub mod context;
 pub mod device;
+pub mod diag;
+mod driver_ext;
 pub mod error;
 pub mod event;
 pub mod function;
-// WIP
-#[allow(warnings)]
-mod graph;
+pub mod graph;
 pub mod link;
 pub mod memory;
 pub mod module;
+pub mod pipeline;
 pub mod prelude;
 pub mod stream;
+pub mod strict;
+pub mod tensor_map;
 // WIP
 mod surface;
 mod texture;
+pub mod tune;
 pub mod util;
+pub mod watchdog;
 
+#[cfg(feature = "capture")]
+pub use capture::sys;
+#[cfg(not(feature = "capture"))]
 pub use cust_raw as sys;
 
-pub use cust_derive::DeviceCopy;
+pub use memory::snapshot;
+
+pub use cust_derive::{include_ptx, DeviceCopy, LaunchParams};
 
 use crate::context::{Context, ContextFlags};
 use crate::device::Device;
 use crate::error::{CudaResult, ToResult};
+use crate::stream::{Stream, StreamFlags};
 use bitflags::bitflags;
 use sys::{cuDriverGetVersion, cuInit};
 
 #[cfg(feature = "vek")]
 pub use vek;
 
+#[cfg(feature = "glam")]
+pub use glam;
+
+#[cfg(feature = "nalgebra")]
+pub use nalgebra;
+
+#[cfg(feature = "half")]
+pub use half;
+
 bitflags! {
     /// Bit flags for initializing the CUDA driver. Currently, no flags are defined,
     /// so `CudaFlags::empty()` is the only valid value.
@@ -95,6 +120,12 @@ bitflags! {
     }
 }
 
+impl Default for CudaFlags {
+    fn default() -> Self {
+        CudaFlags::empty()
+    }
+}
+
 /// Initialize the CUDA Driver API.
 ///
 /// This must be called before any other custa function is called. Typically, this
@@ -118,9 +149,33 @@ pub fn init(flags: CudaFlags) -> CudaResult<()> {
 /// context.
 #[must_use = "The CUDA Context must be kept alive or errors will be issued for any CUDA function that is run"]
 pub fn quick_init() -> CudaResult<Context> {
-    init(CudaFlags::empty())?;
+    init(CudaFlags::default())?;
+    let device = Device::get_device(0)?;
+    Context::create_and_push(ContextFlags::default(), device)
+}
+
+/// Like [`quick_init`], but also returns the device of the context and a new stream on it, which is
+/// everything most programs need to start launching kernels:
+///
+/// ```no_run
+/// # fn main() -> cust::error::CudaResult<()> {
+/// use cust::prelude::*;
+///
+/// let (device, _context, stream) = cust::init_default()?;
+/// println!("running on {}", device.name()?);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// The context must outlive the stream, which it does if the tuple is destructured into named
+/// bindings as above (bindings are dropped in reverse order). Binding the context to `_` drops it
+/// right away.
+#[must_use = "The CUDA Context must be kept alive or errors will be issued for any CUDA function that is run"]
+pub fn init_default() -> CudaResult<(Device, Context, Stream)> {
+    let context = quick_init()?;
     let device = Device::get_device(0)?;
-    Context::create_and_push(ContextFlags::MAP_HOST | ContextFlags::SCHED_AUTO, device)
+    let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+    Ok((device, context, stream))
 }
 
 /// Struct representing the CUDA API version number.
@@ -1,10 +1,10 @@
 //! Functions for linking together multiple PTX files into a module.
 
-use std::{mem::MaybeUninit, time::Duration};
+use std::{ffi::CString, mem::MaybeUninit, path::Path, time::Duration};
 
 use crate::sys as cuda;
 
-use crate::error::{CudaResult, ToResult};
+use crate::error::{CudaError, CudaResult, ToResult};
 
 static UNNAMED: &str = "\0";
 
@@ -119,6 +119,30 @@ impl Linker {
         }
     }
 
+    /// Add a library (`.a` or `.lib` archive of cubins) from a file to be linked in. This is most commonly
+    /// used to link in `libcudadevrt`, the CUDA device runtime, which is required for launching kernels
+    /// from other kernels (dynamic parallelism).
+    ///
+    /// # Returns
+    ///
+    /// Returns an error if the file could not be read, the library is invalid, or CUDA is out of memory.
+    pub fn add_library(&mut self, path: impl AsRef<Path>) -> CudaResult<()> {
+        let path = CString::new(path.as_ref().to_string_lossy().into_owned())
+            .map_err(|_| CudaError::InvalidValue)?;
+
+        unsafe {
+            cuda::cuLinkAddFile_v2(
+                self.raw,
+                cuda::CUjitInputType::CU_JIT_INPUT_LIBRARY,
+                path.as_ptr(),
+                0,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+            .to_result()
+        }
+    }
+
     /// Runs the linker to generate the final cubin bytes. Also returns a duration
     /// for how long it took to run the linker.
     pub fn complete(self) -> CudaResult<(Vec<u8>, Duration)> {
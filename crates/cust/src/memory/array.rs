@@ -6,9 +6,8 @@
 use crate::context::CurrentContext;
 use crate::device::DeviceAttribute;
 use crate::error::*;
-use crate::sys::cuMemcpy2D_v2;
-use crate::sys::cuMemcpyAtoH_v2;
-use crate::sys::cuMemcpyHtoA_v2;
+use crate::memory::default_stream;
+use crate::memory::hooks::{self, AllocationKind};
 use crate::sys::CUDA_MEMCPY2D;
 use crate::sys::{self as cuda, CUarray, CUarray_format, CUarray_format_enum};
 use std::ffi::c_void;
@@ -472,9 +471,15 @@ impl ArrayObject {
 
         let mut handle = MaybeUninit::uninit();
         unsafe { cuda::cuArray3DCreate_v2(handle.as_mut_ptr(), &descriptor.desc) }.to_result()?;
-        Ok(Self {
-            handle: unsafe { handle.assume_init() },
-        })
+        let handle = unsafe { handle.assume_init() };
+        // the size of the elements, the driver may allocate more for the layout of the array.
+        let size = descriptor.width()
+            * descriptor.height().max(1)
+            * descriptor.depth().max(1)
+            * descriptor.num_channels() as usize
+            * descriptor.format().mem_size();
+        hooks::allocated(handle as usize, size, AllocationKind::Array);
+        Ok(Self { handle })
     }
 
     /// Allocates a new CUDA Array that is up to 3-dimensions.
@@ -733,9 +738,11 @@ impl ArrayObject {
     /// Try to destroy an `ArrayObject`. Can fail - if it does, returns the CUDA error and the
     /// un-destroyed array object
     pub fn drop(array: ArrayObject) -> DropResult<ArrayObject> {
+        let array = ManuallyDrop::new(array);
+        hooks::freed(array.handle as usize);
         match unsafe { cuda::cuArrayDestroy(array.handle) }.to_result() {
             Ok(()) => Ok(()),
-            Err(e) => Err((e, array)),
+            Err(e) => Err((e, ManuallyDrop::into_inner(array))),
         }
     }
 
@@ -755,8 +762,13 @@ impl ArrayObject {
         assert_eq!(self_size, other_size, "Array and value sizes don't match");
         unsafe {
             if desc.height() == 0 && desc.depth() == 0 {
-                cuMemcpyHtoA_v2(self.handle, 0, val.as_ptr() as *const c_void, self_size)
-                    .to_result()
+                default_stream::memcpy_htoa(
+                    self.handle,
+                    0,
+                    val.as_ptr() as *const c_void,
+                    self_size,
+                )
+                .to_result()
             } else if desc.depth() == 0 {
                 let desc = CUDA_MEMCPY2D {
                     Height: desc.height(),
@@ -778,7 +790,7 @@ impl ArrayObject {
                     srcXInBytes: 0,
                     srcY: 0,
                 };
-                cuMemcpy2D_v2(&desc as *const _).to_result()
+                default_stream::memcpy_2d(&desc).to_result()
             } else {
                 panic!();
             }
@@ -801,8 +813,13 @@ impl ArrayObject {
         assert_eq!(self_size, other_size, "Array and value sizes don't match");
         unsafe {
             if desc.height() == 0 && desc.depth() == 0 {
-                cuMemcpyAtoH_v2(val.as_mut_ptr() as *mut c_void, self.handle, 0, self_size)
-                    .to_result()
+                default_stream::memcpy_atoh(
+                    val.as_mut_ptr() as *mut c_void,
+                    self.handle,
+                    0,
+                    self_size,
+                )
+                .to_result()
             } else if desc.depth() == 0 {
                 let width = desc.width() * desc.num_channels() as usize * desc.format().mem_size();
                 let desc = CUDA_MEMCPY2D {
@@ -823,7 +840,7 @@ impl ArrayObject {
                     srcXInBytes: 0,
                     srcY: 0,
                 };
-                cuMemcpy2D_v2(&desc as *const _).to_result()?;
+                default_stream::memcpy_2d(&desc).to_result()?;
                 Ok(())
             } else {
                 panic!();
@@ -860,6 +877,7 @@ impl std::fmt::Debug for ArrayObject {
 
 impl Drop for ArrayObject {
     fn drop(&mut self) {
+        hooks::freed(self.handle as usize);
         unsafe { cuda::cuArrayDestroy(self.handle) };
     }
 }
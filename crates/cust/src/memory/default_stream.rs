@@ -0,0 +1,96 @@
+//! Synchronous memory operations, which implicitly run on the default stream.
+//!
+//! By default these use the legacy NULL stream, which synchronizes with every other (blocking) stream
+//! in the context. With the `per-thread-default-stream` feature they use the calling thread's
+//! default stream instead, the same as compiling CUDA C++ with `--default-stream per-thread`.
+
+use crate::sys::{CUarray, CUdeviceptr, CUresult, CUDA_MEMCPY2D};
+use std::os::raw::c_void;
+
+#[cfg(not(feature = "per-thread-default-stream"))]
+use crate::sys::{
+    cuMemcpy2D_v2, cuMemcpyAtoH_v2, cuMemcpyDtoD_v2, cuMemcpyDtoH_v2, cuMemcpyHtoA_v2,
+    cuMemcpyHtoD_v2, cuMemsetD8_v2,
+};
+
+#[cfg(all(feature = "per-thread-default-stream", not(feature = "capture")))]
+extern "C" {
+    #[link_name = "cuMemcpyHtoD_v2_ptds"]
+    fn cuMemcpyHtoD_v2(dst: CUdeviceptr, src: *const c_void, size: usize) -> CUresult;
+    #[link_name = "cuMemcpyDtoH_v2_ptds"]
+    fn cuMemcpyDtoH_v2(dst: *mut c_void, src: CUdeviceptr, size: usize) -> CUresult;
+    #[link_name = "cuMemcpyDtoD_v2_ptds"]
+    fn cuMemcpyDtoD_v2(dst: CUdeviceptr, src: CUdeviceptr, size: usize) -> CUresult;
+    #[link_name = "cuMemsetD8_v2_ptds"]
+    fn cuMemsetD8_v2(dst: CUdeviceptr, value: u8, len: usize) -> CUresult;
+    #[link_name = "cuMemcpy2D_v2_ptds"]
+    fn cuMemcpy2D_v2(copy: *const CUDA_MEMCPY2D) -> CUresult;
+    #[link_name = "cuMemcpyHtoA_v2_ptds"]
+    fn cuMemcpyHtoA_v2(dst: CUarray, offset: usize, src: *const c_void, size: usize) -> CUresult;
+    #[link_name = "cuMemcpyAtoH_v2_ptds"]
+    fn cuMemcpyAtoH_v2(dst: *mut c_void, src: CUarray, offset: usize, size: usize) -> CUresult;
+}
+
+#[cfg(all(feature = "per-thread-default-stream", feature = "capture"))]
+use crate::sys::{
+    cuMemcpy2D_v2_ptds as cuMemcpy2D_v2, cuMemcpyAtoH_v2_ptds as cuMemcpyAtoH_v2,
+    cuMemcpyDtoD_v2_ptds as cuMemcpyDtoD_v2, cuMemcpyDtoH_v2_ptds as cuMemcpyDtoH_v2,
+    cuMemcpyHtoA_v2_ptds as cuMemcpyHtoA_v2, cuMemcpyHtoD_v2_ptds as cuMemcpyHtoD_v2,
+    cuMemsetD8_v2_ptds as cuMemsetD8_v2,
+};
+
+pub(crate) unsafe fn memcpy_htod(dst: CUdeviceptr, src: *const c_void, size: usize) -> CUresult {
+    crate::strict::implicit_sync("synchronous host to device copy on the default stream");
+    let _span = trace_span!("cust::memcpy_htod", bytes = size, stream = "default");
+    cuMemcpyHtoD_v2(dst, src, size)
+}
+
+pub(crate) unsafe fn memcpy_dtoh(dst: *mut c_void, src: CUdeviceptr, size: usize) -> CUresult {
+    crate::strict::implicit_sync("synchronous device to host copy on the default stream");
+    let _span = trace_span!("cust::memcpy_dtoh", bytes = size, stream = "default");
+    cuMemcpyDtoH_v2(dst, src, size)
+}
+
+pub(crate) unsafe fn memcpy_dtod(dst: CUdeviceptr, src: CUdeviceptr, size: usize) -> CUresult {
+    crate::strict::implicit_sync("synchronous device to device copy on the default stream");
+    let _span = trace_span!("cust::memcpy_dtod", bytes = size, stream = "default");
+    cuMemcpyDtoD_v2(dst, src, size)
+}
+
+pub(crate) unsafe fn memset_d8(dst: CUdeviceptr, value: u8, len: usize) -> CUresult {
+    crate::strict::implicit_sync("synchronous memset on the default stream");
+    let _span = trace_span!("cust::memset", bytes = len, stream = "default");
+    cuMemsetD8_v2(dst, value, len)
+}
+
+pub(crate) unsafe fn memcpy_2d(copy: &CUDA_MEMCPY2D) -> CUresult {
+    crate::strict::implicit_sync("synchronous 2D copy on the default stream");
+    let _span = trace_span!(
+        "cust::memcpy_2d",
+        bytes = copy.WidthInBytes * copy.Height,
+        stream = "default"
+    );
+    cuMemcpy2D_v2(copy)
+}
+
+pub(crate) unsafe fn memcpy_htoa(
+    dst: CUarray,
+    offset: usize,
+    src: *const c_void,
+    size: usize,
+) -> CUresult {
+    crate::strict::implicit_sync("synchronous host to array copy on the default stream");
+    let _span = trace_span!("cust::memcpy_htoa", bytes = size, stream = "default");
+    cuMemcpyHtoA_v2(dst, offset, src, size)
+}
+
+pub(crate) unsafe fn memcpy_atoh(
+    dst: *mut c_void,
+    src: CUarray,
+    offset: usize,
+    size: usize,
+) -> CUresult {
+    crate::strict::implicit_sync("synchronous array to host copy on the default stream");
+    let _span = trace_span!("cust::memcpy_atoh", bytes = size, stream = "default");
+    cuMemcpyAtoH_v2(dst, src, offset, size)
+}
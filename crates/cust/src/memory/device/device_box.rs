@@ -1,4 +1,5 @@
 use crate::error::{CudaResult, DropResult, ToResult};
+use crate::memory::default_stream;
 use crate::memory::device::AsyncCopyDestination;
 use crate::memory::device::CopyDestination;
 use crate::memory::malloc::{cuda_free, cuda_malloc};
@@ -103,7 +104,7 @@ impl<T> DeviceBox<T> {
     pub unsafe fn zeroed() -> CudaResult<Self> {
         let mut new_box = DeviceBox::uninitialized()?;
         if mem::size_of::<T>() != 0 {
-            cuda::cuMemsetD8_v2(
+            default_stream::memset_d8(
                 new_box.as_device_ptr().as_raw_mut() as u64,
                 0,
                 mem::size_of::<T>(),
@@ -210,6 +211,12 @@ impl<T> DeviceBox<T> {
         self.ptr
     }
 
+    /// Returns a raw device pointer to the contained value, for passing the box to a kernel launch
+    /// without borrowing it mutably.
+    pub fn as_ptr(&self) -> *const T {
+        self.ptr.as_raw()
+    }
+
     /// Destroy a `DeviceBox`, returning an error.
     ///
     /// Deallocating device memory can return errors from previous asynchronous work. This function
@@ -269,7 +276,7 @@ impl<T: DeviceCopy> CopyDestination<T> for DeviceBox<T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyHtoD_v2(
+                default_stream::memcpy_htod(
                     self.ptr.as_raw_mut() as u64,
                     val as *const T as *const c_void,
                     size,
@@ -284,7 +291,7 @@ impl<T: DeviceCopy> CopyDestination<T> for DeviceBox<T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoH_v2(
+                default_stream::memcpy_dtoh(
                     val as *const T as *mut c_void,
                     self.ptr.as_raw() as u64,
                     size,
@@ -300,8 +307,12 @@ impl<T: DeviceCopy> CopyDestination<DeviceBox<T>> for DeviceBox<T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoD_v2(self.ptr.as_raw_mut() as u64, val.ptr.as_raw() as u64, size)
-                    .to_result()?
+                default_stream::memcpy_dtod(
+                    self.ptr.as_raw_mut() as u64,
+                    val.ptr.as_raw() as u64,
+                    size,
+                )
+                .to_result()?
             }
         }
         Ok(())
@@ -311,8 +322,12 @@ impl<T: DeviceCopy> CopyDestination<DeviceBox<T>> for DeviceBox<T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoD_v2(val.ptr.as_raw_mut() as u64, self.ptr.as_raw() as u64, size)
-                    .to_result()?
+                default_stream::memcpy_dtod(
+                    val.ptr.as_raw_mut() as u64,
+                    self.ptr.as_raw() as u64,
+                    size,
+                )
+                .to_result()?
             }
         }
         Ok(())
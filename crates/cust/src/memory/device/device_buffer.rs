@@ -1,10 +1,10 @@
 use crate::error::{CudaResult, DropResult, ToResult};
+use crate::memory::default_stream;
 use crate::memory::device::{AsyncCopyDestination, CopyDestination, DeviceSlice};
 use crate::memory::malloc::{cuda_free, cuda_malloc};
 use crate::memory::DeviceCopy;
 use crate::memory::DevicePointer;
 use crate::stream::Stream;
-use crate::sys as cuda;
 use std::mem;
 use std::ops::{Deref, DerefMut};
 
@@ -77,7 +77,7 @@ impl<T> DeviceBuffer<T> {
     pub unsafe fn zeroed(size: usize) -> CudaResult<Self> {
         let ptr = if size > 0 && mem::size_of::<T>() > 0 {
             let mut ptr = cuda_malloc(size)?;
-            cuda::cuMemsetD8_v2(ptr.as_raw_mut() as u64, 0, size * mem::size_of::<T>())
+            default_stream::memset_d8(ptr.as_raw_mut() as u64, 0, size * mem::size_of::<T>())
                 .to_result()?;
             ptr
         } else {
@@ -0,0 +1,322 @@
+use crate::error::{CudaError, CudaResult, ToResult};
+use crate::memory::default_stream;
+use crate::memory::hooks::{self, AllocationKind};
+use crate::memory::malloc::cuda_free;
+use crate::memory::DeviceCopy;
+use crate::memory::DevicePointer;
+use crate::stream::Stream;
+use crate::sys::{self as cuda, CUDA_MEMCPY2D};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+
+/// A two-dimensional device buffer whose rows are padded to a pitch chosen by the driver, as
+/// allocated by `cudaMallocPitch` in CUDA C++.
+///
+/// Padding every row so that it starts at a well-aligned address makes accesses to columns and
+/// rows of images coalesce better than with a tightly packed buffer. Kernels must therefore index
+/// the buffer with the [`pitch`](Self::pitch) instead of the width: element `(x, y)` is at byte
+/// offset `y * pitch + x * size_of::<T>()` from [`as_device_ptr`](Self::as_device_ptr).
+///
+/// Copies to and from the host use tightly packed host slices of `width * height` elements in row
+/// major order, the padding is skipped by the driver.
+///
+/// # Examples
+///
+/// ```
+/// # let _context = cust::quick_init().unwrap();
+/// use cust::memory::*;
+///
+/// let mut image = unsafe { DevicePitchedBuffer::<[f32; 4]>::uninitialized(100, 50).unwrap() };
+/// assert!(image.pitch() >= 100 * 16);
+/// image.copy_from(&vec![[1.0, 0.5, 0.25, 1.0]; 100 * 50]).unwrap();
+///
+/// let pixels = image.as_host_vec().unwrap();
+/// assert_eq!(pixels.len(), 100 * 50);
+/// assert_eq!(pixels[4999], [1.0, 0.5, 0.25, 1.0]);
+/// ```
+#[derive(Debug)]
+pub struct DevicePitchedBuffer<T> {
+    buf: DevicePointer<T>,
+    width: usize,
+    height: usize,
+    pitch: usize,
+}
+
+impl<T: DeviceCopy> DevicePitchedBuffer<T> {
+    /// Allocates a buffer of `height` rows of `width` elements each, without initializing it.
+    ///
+    /// # Errors
+    ///
+    /// If the allocation fails, returns the error from CUDA. The driver limits the width of a row to
+    /// a few hundred thousand bytes and fails with `InvalidValue` for wider rows.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the contents of the buffer are initialized before reading from
+    /// the buffer.
+    pub unsafe fn uninitialized(width: usize, height: usize) -> CudaResult<Self> {
+        let row_bytes = width
+            .checked_mul(mem::size_of::<T>())
+            .ok_or(CudaError::InvalidMemoryAllocation)?;
+        if row_bytes == 0 || height == 0 {
+            return Ok(Self {
+                buf: DevicePointer::wrap(ptr::NonNull::dangling().as_ptr()),
+                width,
+                height,
+                pitch: row_bytes,
+            });
+        }
+
+        // the element size only decides how the driver aligns the rows, it must be 4, 8 or 16.
+        let element_size = match mem::size_of::<T>() {
+            size if size % 16 == 0 => 16,
+            size if size % 8 == 0 => 8,
+            _ => 4,
+        };
+        let _span = trace_span!("cust::malloc_pitch", width = row_bytes, height = height);
+        let mut ptr: cuda::CUdeviceptr = 0;
+        let mut pitch = 0;
+        cuda::cuMemAllocPitch_v2(&mut ptr, &mut pitch, row_bytes, height, element_size)
+            .to_result()?;
+        hooks::allocated(ptr as usize, pitch * height, AllocationKind::Pitched);
+        Ok(Self {
+            buf: DevicePointer::wrap(ptr as *mut T),
+            width,
+            height,
+            pitch,
+        })
+    }
+
+    /// Allocates a buffer of `height` rows of `width` elements each and fills it (including the
+    /// padding) with zeroes.
+    ///
+    /// # Errors
+    ///
+    /// If the allocation fails, returns the error from CUDA.
+    ///
+    /// # Safety
+    ///
+    /// The backing memory is zeroed, which may not be a valid bit-pattern for type `T`. The caller
+    /// must ensure either that all-zeroes is a valid bit-pattern for type `T` or that the backing
+    /// memory is set to a valid value before it is read.
+    pub unsafe fn zeroed(width: usize, height: usize) -> CudaResult<Self> {
+        let mut buffer = Self::uninitialized(width, height)?;
+        if buffer.size_in_bytes() != 0 {
+            default_stream::memset_d8(buffer.buf.as_raw_mut() as u64, 0, buffer.size_in_bytes())
+                .to_result()?;
+        }
+        Ok(buffer)
+    }
+
+    /// The number of elements in every row.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The number of rows.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The distance between the starts of two consecutive rows, **in bytes**. This is at least
+    /// `width * size_of::<T>()`.
+    pub fn pitch(&self) -> usize {
+        self.pitch
+    }
+
+    /// The size of the allocation in bytes, including the padding of every row.
+    pub fn size_in_bytes(&self) -> usize {
+        self.pitch * self.height
+    }
+
+    /// A pointer to the first element of the first row.
+    pub fn as_device_ptr(&mut self) -> DevicePointer<T> {
+        self.buf
+    }
+
+    /// A pointer to the first element of the first row, for kernels which only read the buffer.
+    pub fn as_ptr(&self) -> *const T {
+        self.buf.as_raw()
+    }
+
+    /// Copies a tightly packed image of `width * height` elements from the host into the buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` does not have exactly `width * height` elements.
+    ///
+    /// # Errors
+    ///
+    /// If a CUDA error occurs, returns the error.
+    pub fn copy_from(&mut self, source: &[T]) -> CudaResult<()> {
+        let copy = self.host_copy(source.as_ptr() as *mut T, source.len(), true);
+        if let Some(copy) = copy {
+            unsafe { default_stream::memcpy_2d(&copy).to_result()? }
+        }
+        Ok(())
+    }
+
+    /// Copies the buffer into a tightly packed image of `width * height` elements on the host.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dest` does not have exactly `width * height` elements.
+    ///
+    /// # Errors
+    ///
+    /// If a CUDA error occurs, returns the error.
+    pub fn copy_to(&self, dest: &mut [T]) -> CudaResult<()> {
+        let copy = self.host_copy(dest.as_mut_ptr(), dest.len(), false);
+        if let Some(copy) = copy {
+            unsafe { default_stream::memcpy_2d(&copy).to_result()? }
+        }
+        Ok(())
+    }
+
+    /// Copies the buffer into a new tightly packed `Vec` of `width * height` elements.
+    ///
+    /// # Errors
+    ///
+    /// If a CUDA error occurs, returns the error.
+    pub fn as_host_vec(&self) -> CudaResult<Vec<T>> {
+        let len = self.width * self.height;
+        let mut vec = Vec::with_capacity(len);
+        let copy = self.host_copy(vec.as_mut_ptr(), len, false);
+        if let Some(copy) = copy {
+            unsafe { default_stream::memcpy_2d(&copy).to_result()? }
+        }
+        // the copy initialized every element.
+        unsafe { vec.set_len(len) };
+        Ok(vec)
+    }
+
+    /// Asynchronously copies a tightly packed image of `width * height` elements from the host into
+    /// the buffer on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `source` does not have exactly `width * height` elements.
+    ///
+    /// # Safety
+    ///
+    /// `source` must not be modified or freed until the copy is done, see
+    /// [`AsyncCopyDestination`](crate::memory::AsyncCopyDestination). It should be page-locked,
+    /// otherwise the copy is synchronous.
+    pub unsafe fn async_copy_from(&mut self, source: &[T], stream: &Stream) -> CudaResult<()> {
+        crate::strict::check_pinned(source.as_ptr() as *const c_void, "asynchronous 2D copy");
+        let copy = self.host_copy(source.as_ptr() as *mut T, source.len(), true);
+        if let Some(copy) = copy {
+            cuda::cuMemcpy2DAsync_v2(&copy, stream.as_inner()).to_result()?;
+        }
+        Ok(())
+    }
+
+    /// Asynchronously copies the buffer into a tightly packed image of `width * height` elements on
+    /// the host on `stream`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `dest` does not have exactly `width * height` elements.
+    ///
+    /// # Safety
+    ///
+    /// `dest` must not be read, modified or freed until the copy is done, see
+    /// [`AsyncCopyDestination`](crate::memory::AsyncCopyDestination). It should be page-locked,
+    /// otherwise the copy is synchronous.
+    pub unsafe fn async_copy_to(&self, dest: &mut [T], stream: &Stream) -> CudaResult<()> {
+        crate::strict::check_pinned(dest.as_ptr() as *const c_void, "asynchronous 2D copy");
+        let copy = self.host_copy(dest.as_mut_ptr(), dest.len(), false);
+        if let Some(copy) = copy {
+            cuda::cuMemcpy2DAsync_v2(&copy, stream.as_inner()).to_result()?;
+        }
+        Ok(())
+    }
+
+    /// The description of a copy between the buffer and a tightly packed host image at `host`, `None`
+    /// if there is nothing to copy.
+    fn host_copy(&self, host: *mut T, len: usize, to_device: bool) -> Option<CUDA_MEMCPY2D> {
+        assert_eq!(
+            len,
+            self.width * self.height,
+            "host image and pitched buffer sizes do not match"
+        );
+        let row_bytes = self.width * mem::size_of::<T>();
+        if row_bytes == 0 || self.height == 0 {
+            return None;
+        }
+
+        let host_type = cuda::CUmemorytype_enum::CU_MEMORYTYPE_HOST;
+        let device_type = cuda::CUmemorytype_enum::CU_MEMORYTYPE_DEVICE;
+        let device = self.buf.as_raw() as cuda::CUdeviceptr;
+        let mut copy = CUDA_MEMCPY2D {
+            srcXInBytes: 0,
+            srcY: 0,
+            srcMemoryType: host_type,
+            srcHost: host as *const c_void,
+            srcDevice: 0,
+            srcArray: ptr::null_mut(),
+            srcPitch: row_bytes,
+            dstXInBytes: 0,
+            dstY: 0,
+            dstMemoryType: device_type,
+            dstHost: ptr::null_mut(),
+            dstDevice: device,
+            dstArray: ptr::null_mut(),
+            dstPitch: self.pitch,
+            WidthInBytes: row_bytes,
+            Height: self.height,
+        };
+        if !to_device {
+            copy.srcMemoryType = device_type;
+            copy.srcHost = ptr::null();
+            copy.srcDevice = device;
+            copy.srcPitch = self.pitch;
+            copy.dstMemoryType = host_type;
+            copy.dstHost = host as *mut c_void;
+            copy.dstDevice = 0;
+            copy.dstPitch = row_bytes;
+        }
+        Some(copy)
+    }
+}
+
+impl<T> Drop for DevicePitchedBuffer<T> {
+    fn drop(&mut self) {
+        if self.pitch * self.height == 0 || self.buf.is_null() {
+            return;
+        }
+        let ptr = mem::replace(&mut self.buf, DevicePointer::null());
+        unsafe {
+            let _ = cuda_free(ptr);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_device_pitched_buffer {
+    use super::*;
+
+    #[test]
+    fn test_pitched_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let _context = crate::quick_init()?;
+        let image: Vec<u32> = (0..37 * 11).collect();
+        let mut buffer = unsafe { DevicePitchedBuffer::<u32>::uninitialized(37, 11)? };
+        assert!(buffer.pitch() >= 37 * 4);
+        buffer.copy_from(&image)?;
+
+        let mut out = vec![0; 37 * 11];
+        buffer.copy_to(&mut out)?;
+        assert_eq!(out, image);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pitched_empty() -> Result<(), Box<dyn std::error::Error>> {
+        let _context = crate::quick_init()?;
+        let buffer = unsafe { DevicePitchedBuffer::<f32>::zeroed(0, 8)? };
+        assert_eq!(buffer.size_in_bytes(), 0);
+        assert!(buffer.as_host_vec()?.is_empty());
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use crate::error::{CudaResult, ToResult};
+use crate::memory::default_stream;
 use crate::memory::device::AsyncCopyDestination;
 use crate::memory::device::{CopyDestination, DeviceBuffer};
 use crate::memory::DeviceCopy;
@@ -27,6 +28,38 @@ impl<T: DeviceCopy + Default + Clone> DeviceSlice<T> {
     }
 }
 
+impl<T: DeviceCopy> DeviceSlice<T> {
+    /// Copies the contents of the slice into a newly allocated host vector. Unlike
+    /// [`as_host_vec`](Self::as_host_vec), this does not initialize the vector before copying into
+    /// it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let _context = cust::quick_init().unwrap();
+    /// use cust::memory::*;
+    /// let a = DeviceBuffer::from_slice(&[1, 2, 3]).unwrap();
+    /// assert_eq!(a.to_host_vec().unwrap(), vec![1, 2, 3]);
+    /// ```
+    pub fn to_host_vec(&self) -> CudaResult<Vec<T>> {
+        let mut vec = Vec::with_capacity(self.len());
+        let size = mem::size_of::<T>() * self.len();
+        if size != 0 {
+            unsafe {
+                default_stream::memcpy_dtoh(
+                    vec.as_mut_ptr() as *mut c_void,
+                    self.as_ptr() as u64,
+                    size,
+                )
+                .to_result()?;
+            }
+        }
+        // SAFETY: every element was initialized by the copy.
+        unsafe { vec.set_len(self.len()) };
+        Ok(vec)
+    }
+}
+
 // This works by faking a regular slice out of the device raw-pointer and the length and transmuting
 // I have no idea if this is safe or not. Probably not, though I can't imagine how the compiler
 // could possibly know that the pointer is not de-referenceable. I'm banking that we get proper
@@ -456,7 +489,7 @@ impl<T: DeviceCopy, I: AsRef<[T]> + AsMut<[T]> + ?Sized> CopyDestination<I> for
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyHtoD_v2(
+                default_stream::memcpy_htod(
                     self.0.as_mut_ptr() as u64,
                     val.as_ptr() as *const c_void,
                     size,
@@ -476,8 +509,12 @@ impl<T: DeviceCopy, I: AsRef<[T]> + AsMut<[T]> + ?Sized> CopyDestination<I> for
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoH_v2(val.as_mut_ptr() as *mut c_void, self.as_ptr() as u64, size)
-                    .to_result()?
+                default_stream::memcpy_dtoh(
+                    val.as_mut_ptr() as *mut c_void,
+                    self.as_ptr() as u64,
+                    size,
+                )
+                .to_result()?
             }
         }
         Ok(())
@@ -492,7 +529,7 @@ impl<T: DeviceCopy> CopyDestination<DeviceSlice<T>> for DeviceSlice<T> {
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoD_v2(self.0.as_mut_ptr() as u64, val.as_ptr() as u64, size)
+                default_stream::memcpy_dtod(self.0.as_mut_ptr() as u64, val.as_ptr() as u64, size)
                     .to_result()?
             }
         }
@@ -507,7 +544,7 @@ impl<T: DeviceCopy> CopyDestination<DeviceSlice<T>> for DeviceSlice<T> {
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoD_v2(val.as_mut_ptr() as u64, self.as_ptr() as u64, size)
+                default_stream::memcpy_dtod(val.as_mut_ptr() as u64, self.as_ptr() as u64, size)
                     .to_result()?
             }
         }
@@ -534,6 +571,12 @@ impl<T: DeviceCopy, I: AsRef<[T]> + AsMut<[T]> + ?Sized> AsyncCopyDestination<I>
         );
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
+            let _span = trace_span!(
+                "cust::memcpy_htod_async",
+                bytes = size,
+                stream = stream.as_inner() as usize,
+            );
+            crate::strict::check_pinned(val.as_ptr() as *const c_void, "async host to device copy");
             cuda::cuMemcpyHtoDAsync_v2(
                 self.0.as_mut_ptr() as u64,
                 val.as_ptr() as *const c_void,
@@ -553,6 +596,12 @@ impl<T: DeviceCopy, I: AsRef<[T]> + AsMut<[T]> + ?Sized> AsyncCopyDestination<I>
         );
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
+            let _span = trace_span!(
+                "cust::memcpy_dtoh_async",
+                bytes = size,
+                stream = stream.as_inner() as usize,
+            );
+            crate::strict::check_pinned(val.as_ptr() as *const c_void, "async device to host copy");
             cuda::cuMemcpyDtoHAsync_v2(
                 val.as_mut_ptr() as *mut c_void,
                 self.as_ptr() as u64,
@@ -572,6 +621,11 @@ impl<T: DeviceCopy> AsyncCopyDestination<DeviceSlice<T>> for DeviceSlice<T> {
         );
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
+            let _span = trace_span!(
+                "cust::memcpy_dtod_async",
+                bytes = size,
+                stream = stream.as_inner() as usize,
+            );
             cuda::cuMemcpyDtoDAsync_v2(
                 self.0.as_mut_ptr() as u64,
                 val.as_ptr() as u64,
@@ -590,6 +644,11 @@ impl<T: DeviceCopy> AsyncCopyDestination<DeviceSlice<T>> for DeviceSlice<T> {
         );
         let size = mem::size_of::<T>() * self.len();
         if size != 0 {
+            let _span = trace_span!(
+                "cust::memcpy_dtod_async",
+                bytes = size,
+                stream = stream.as_inner() as usize,
+            );
             cuda::cuMemcpyDtoDAsync_v2(
                 val.as_mut_ptr() as u64,
                 self.as_ptr() as u64,
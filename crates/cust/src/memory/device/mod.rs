@@ -3,10 +3,12 @@ use crate::stream::Stream;
 
 mod device_box;
 mod device_buffer;
+mod device_pitched_buffer;
 mod device_slice;
 
 pub use self::device_box::*;
 pub use self::device_buffer::*;
+pub use self::device_pitched_buffer::*;
 pub use self::device_slice::*;
 
 /// Sealed trait implemented by types which can be the source or destination when copying data
@@ -0,0 +1,211 @@
+//! Hooks called by cust when it allocates and frees memory, for keeping track of the memory used
+//! by a program in frameworks built on cust.
+//!
+//! Every allocation and free made through cust calls the registered hooks, including those made by
+//! the buffers, by [`MemoryPool`](super::MemoryPool), by the scratch memory of streams and by
+//! [`ArrayObject`](super::array::ArrayObject)s. Memory
+//! allocated outside of cust (for example by a library sharing the context) is not reported.
+//!
+//! ```
+//! use cust::memory::hooks::{self, Allocation, AllocationHook};
+//! use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+//!
+//! #[derive(Default)]
+//! struct Usage(AtomicUsize);
+//!
+//! impl AllocationHook for Usage {
+//!     fn allocated(&self, alloc: &Allocation) {
+//!         self.0.fetch_add(alloc.size, Ordering::Relaxed);
+//!     }
+//!
+//!     fn freed(&self, alloc: &Allocation) {
+//!         self.0.fetch_sub(alloc.size, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let usage = Arc::new(Usage::default());
+//! let id = hooks::register(usage.clone());
+//! // ... the bytes allocated through cust are now in `usage` ...
+//! hooks::unregister(id);
+//! ```
+//!
+//! Frees are only reported for memory which was allocated while a hook was registered, so that hooks
+//! registered while memory is allocated do not see frees of memory they never saw allocated.
+
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// The kind of memory of an [`Allocation`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AllocationKind {
+    /// Device memory, such as the memory of a `DeviceBuffer`.
+    Device,
+    /// Device memory with padded rows, of a `DevicePitchedBuffer`.
+    Pitched,
+    /// Unified memory.
+    Unified,
+    /// Page-locked host memory.
+    Locked,
+    /// Device memory from a `MemoryPool`.
+    Pool,
+    /// A CUDA array, of an `ArrayObject`. Its address is the handle of the array.
+    Array,
+}
+
+/// An allocation reported to the hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    /// The address of the allocation, in device memory for device memory.
+    pub address: usize,
+    /// The size of the allocation in bytes.
+    pub size: usize,
+    pub kind: AllocationKind,
+}
+
+/// A hook called when cust allocates and frees memory.
+///
+/// The hooks are called from the thread which allocates or frees the memory, right after the
+/// allocation succeeded and right before the memory is freed, so they should be cheap.
+pub trait AllocationHook: Send + Sync {
+    fn allocated(&self, alloc: &Allocation);
+    fn freed(&self, alloc: &Allocation);
+}
+
+/// The handle of a registered hook, for unregistering it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HookId(u64);
+
+#[derive(Default)]
+struct Hooks {
+    hooks: Vec<(HookId, Arc<dyn AllocationHook>)>,
+    next_id: u64,
+    /// The allocations reported to the hooks which have not been freed.
+    live: HashMap<usize, Allocation>,
+}
+
+static HOOKS: Lazy<Mutex<Hooks>> = Lazy::new(Default::default);
+/// Whether any hook is registered, so that allocating does not lock anything without hooks.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Registers a hook which is called for every allocation and free from now on.
+pub fn register(hook: Arc<dyn AllocationHook>) -> HookId {
+    let mut hooks = HOOKS.lock().unwrap();
+    let id = HookId(hooks.next_id);
+    hooks.next_id += 1;
+    hooks.hooks.push((id, hook));
+    ACTIVE.store(true, Ordering::Release);
+    id
+}
+
+/// Unregisters a hook, returning whether it was registered.
+pub fn unregister(id: HookId) -> bool {
+    let mut hooks = HOOKS.lock().unwrap();
+    let len = hooks.hooks.len();
+    hooks.hooks.retain(|(hook, _)| *hook != id);
+    if hooks.hooks.is_empty() {
+        hooks.live.clear();
+        ACTIVE.store(false, Ordering::Release);
+    }
+    hooks.hooks.len() != len
+}
+
+/// Reports a new allocation to the hooks.
+pub(crate) fn allocated(address: usize, size: usize, kind: AllocationKind) {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return;
+    }
+    let alloc = Allocation {
+        address,
+        size,
+        kind,
+    };
+    // the hooks are called without holding the lock, so that they can (un)register hooks.
+    let hooks = {
+        let mut hooks = HOOKS.lock().unwrap();
+        hooks.live.insert(address, alloc);
+        hooks
+            .hooks
+            .iter()
+            .map(|(_, hook)| hook.clone())
+            .collect::<Vec<_>>()
+    };
+    for hook in hooks {
+        hook.allocated(&alloc);
+    }
+}
+
+/// Reports that the allocation at `address` is about to be freed to the hooks.
+pub(crate) fn freed(address: usize) {
+    if !ACTIVE.load(Ordering::Acquire) {
+        return;
+    }
+    let (alloc, hooks) = {
+        let mut hooks = HOOKS.lock().unwrap();
+        match hooks.live.remove(&address) {
+            Some(alloc) => (
+                alloc,
+                hooks
+                    .hooks
+                    .iter()
+                    .map(|(_, hook)| hook.clone())
+                    .collect::<Vec<_>>(),
+            ),
+            None => return,
+        }
+    };
+    for hook in hooks {
+        hook.freed(&alloc);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Log(Mutex<Vec<(bool, Allocation)>>);
+
+    impl AllocationHook for Log {
+        fn allocated(&self, alloc: &Allocation) {
+            self.0.lock().unwrap().push((true, *alloc));
+        }
+
+        fn freed(&self, alloc: &Allocation) {
+            self.0.lock().unwrap().push((false, *alloc));
+        }
+    }
+
+    #[test]
+    fn reports_frees_with_size() {
+        // addresses no real allocation has, since other tests may allocate meanwhile.
+        const ADDRESS: usize = usize::MAX - 4096;
+        allocated(ADDRESS - 4096, 16, AllocationKind::Device);
+
+        let log = Arc::new(Log::default());
+        let id = register(log.clone());
+        allocated(ADDRESS, 256, AllocationKind::Locked);
+        freed(ADDRESS);
+        // allocated before the hook was registered.
+        freed(ADDRESS - 4096);
+        assert!(unregister(id));
+        assert!(!unregister(id));
+
+        let alloc = Allocation {
+            address: ADDRESS,
+            size: 256,
+            kind: AllocationKind::Locked,
+        };
+        let log = log.0.lock().unwrap();
+        let ours = log
+            .iter()
+            .filter(|(_, a)| a.address >= ADDRESS - 4096)
+            .collect::<Vec<_>>();
+        assert_eq!(ours, [&(true, alloc), &(false, alloc)]);
+    }
+}
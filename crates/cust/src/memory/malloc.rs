@@ -1,3 +1,4 @@
+use super::hooks::{self, AllocationKind};
 use super::DeviceCopy;
 use crate::error::*;
 use crate::memory::DevicePointer;
@@ -44,8 +45,10 @@ pub unsafe fn cuda_malloc<T>(count: usize) -> CudaResult<DevicePointer<T>> {
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    let _span = trace_span!("cust::malloc", bytes = size);
     let mut ptr: *mut c_void = ptr::null_mut();
     cuda::cuMemAlloc_v2(&mut ptr as *mut *mut c_void as *mut u64, size).to_result()?;
+    hooks::allocated(ptr as usize, size, AllocationKind::Device);
     let ptr = ptr as *mut T;
     Ok(DevicePointer::wrap(ptr as *mut T))
 }
@@ -89,6 +92,7 @@ pub unsafe fn cuda_malloc_unified<T: DeviceCopy>(count: usize) -> CudaResult<Uni
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    let _span = trace_span!("cust::malloc_unified", bytes = size);
     let mut ptr: *mut c_void = ptr::null_mut();
     cuda::cuMemAllocManaged(
         &mut ptr as *mut *mut c_void as *mut u64,
@@ -96,6 +100,7 @@ pub unsafe fn cuda_malloc_unified<T: DeviceCopy>(count: usize) -> CudaResult<Uni
         cuda::CUmemAttach_flags_enum::CU_MEM_ATTACH_GLOBAL as u32,
     )
     .to_result()?;
+    hooks::allocated(ptr as usize, size, AllocationKind::Unified);
     let ptr = ptr as *mut T;
     Ok(UnifiedPointer::wrap(ptr as *mut T))
 }
@@ -129,6 +134,8 @@ pub unsafe fn cuda_free<T>(mut p: DevicePointer<T>) -> CudaResult<()> {
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    trace_event!(ptr = ptr as usize, "cust::free");
+    hooks::freed(ptr as usize);
     cuda::cuMemFree_v2(ptr as u64).to_result()?;
     Ok(())
 }
@@ -162,6 +169,8 @@ pub unsafe fn cuda_free_unified<T: DeviceCopy>(mut p: UnifiedPointer<T>) -> Cuda
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    trace_event!(ptr = ptr as usize, "cust::free");
+    hooks::freed(ptr as usize);
     cuda::cuMemFree_v2(ptr as u64).to_result()?;
     Ok(())
 }
@@ -203,8 +212,10 @@ pub unsafe fn cuda_malloc_locked<T>(count: usize) -> CudaResult<*mut T> {
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    let _span = trace_span!("cust::malloc_locked", bytes = size);
     let mut ptr: *mut c_void = ptr::null_mut();
     cuda::cuMemAllocHost_v2(&mut ptr as *mut *mut c_void, size).to_result()?;
+    hooks::allocated(ptr as usize, size, AllocationKind::Locked);
     let ptr = ptr as *mut T;
     Ok(ptr as *mut T)
 }
@@ -237,6 +248,8 @@ pub unsafe fn cuda_free_locked<T>(ptr: *mut T) -> CudaResult<()> {
         return Err(CudaError::InvalidMemoryAllocation);
     }
 
+    trace_event!(ptr = ptr as usize, "cust::free_locked");
+    hooks::freed(ptr as usize);
     cuda::cuMemFreeHost(ptr as *mut c_void).to_result()?;
     Ok(())
 }
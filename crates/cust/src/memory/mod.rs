@@ -75,16 +75,25 @@
 
 pub mod array;
 
+pub(crate) mod default_stream;
 mod device;
+pub mod hooks;
 mod locked;
 mod malloc;
 mod pointer;
+mod pool;
+mod registered;
+pub(crate) mod scratch;
+pub mod snapshot;
 mod unified;
 
 pub use self::device::*;
 pub use self::locked::*;
 pub use self::malloc::*;
 pub use self::pointer::*;
+pub use self::pool::MemoryPool;
+pub use self::registered::*;
+pub use self::scratch::ScratchBuffer;
 pub use self::unified::*;
 
 use core::marker::PhantomData;
@@ -286,5 +295,55 @@ impl_device_copy_vek! {
     Quaternion,
 }
 
+#[cfg(feature = "glam")]
+macro_rules! impl_device_copy_glam {
+    ($($strukt:ident),* $(,)?) => {
+        $(
+            unsafe impl DeviceCopy for glam::$strukt {}
+        )*
+    }
+}
+
+#[cfg(feature = "glam")]
+impl_device_copy_glam! {
+    Vec2, Vec3, Vec3A, Vec4,
+    DVec2, DVec3, DVec4,
+    IVec2, IVec3, IVec4,
+    UVec2, UVec3, UVec4,
+    BVec2, BVec3, BVec4,
+    Mat2, Mat3, Mat3A, Mat4,
+    DMat2, DMat3, DMat4,
+    Affine2, Affine3A, DAffine2, DAffine3,
+    Quat, DQuat,
+}
+
+// vectors and matrices (and their aliases like Vector3 and Matrix4) are all SMatrix.
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy, const R: usize, const C: usize> DeviceCopy
+    for nalgebra::SMatrix<T, R, C>
+{
+}
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy> DeviceCopy for nalgebra::Unit<T> {}
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy + nalgebra::Scalar, const D: usize> DeviceCopy for nalgebra::Point<T, D> {}
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy> DeviceCopy for nalgebra::Quaternion<T> {}
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy + nalgebra::Scalar, const D: usize> DeviceCopy
+    for nalgebra::Translation<T, D>
+{
+}
+#[cfg(feature = "nalgebra")]
+unsafe impl<T: DeviceCopy + nalgebra::Scalar, const D: usize> DeviceCopy
+    for nalgebra::Rotation<T, D>
+{
+}
+
 #[cfg(feature = "num-complex")]
 unsafe impl<T: DeviceCopy> DeviceCopy for num_complex::Complex<T> {}
+
+#[cfg(feature = "half")]
+unsafe impl DeviceCopy for half::f16 {}
+#[cfg(feature = "half")]
+unsafe impl DeviceCopy for half::bf16 {}
@@ -0,0 +1,258 @@
+//! Stream-ordered memory pools.
+
+use crate::device::Device;
+use crate::error::*;
+use crate::memory::hooks::{self, AllocationKind};
+use crate::memory::DevicePointer;
+use crate::stream::Stream;
+use crate::sys::{self as cuda, CUmemPool_attribute, CUmemoryPool};
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr;
+
+/// A pool of device memory for allocations which are ordered with the work of a stream
+/// (`cuMemAllocFromPoolAsync`), requires CUDA 11.2.
+///
+/// Freed allocations go back to the pool and are reused by later allocations instead of being
+/// returned to the device, which makes allocating in loops cheap. Memory is only released to the
+/// device when a stream, event or context is synchronized and the pool holds more than its
+/// [release threshold](Self::set_release_threshold), which is 0 by default, or when the pool is
+/// [trimmed](Self::trim_to). Long-running services usually set the threshold to the memory they
+/// expect to need and watch [`used_bytes`](Self::used_bytes) and
+/// [`reserved_bytes`](Self::reserved_bytes):
+///
+/// ```no_run
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let _ctx = cust::quick_init()?;
+/// use cust::device::Device;
+/// use cust::memory::MemoryPool;
+///
+/// let pool = MemoryPool::default_for(Device::get_device(0)?)?;
+/// pool.set_release_threshold(1 << 30)?;
+/// // ... allocate and free memory in the pool ...
+/// println!("{} bytes in use, {} reserved", pool.used_bytes()?, pool.reserved_bytes()?);
+/// // give back what is not used after a burst of work.
+/// pool.trim_to(0)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct MemoryPool {
+    inner: CUmemoryPool,
+    /// Whether the pool was created by us, the default pool of a device is never destroyed.
+    owned: bool,
+}
+
+unsafe impl Send for MemoryPool {}
+unsafe impl Sync for MemoryPool {}
+
+impl MemoryPool {
+    /// Creates a new pool of memory on `device`.
+    pub fn new(device: Device) -> CudaResult<Self> {
+        unsafe {
+            let mut props: cuda::CUmemPoolProps = mem::zeroed();
+            props.allocType = cuda::CUmemAllocationType::CU_MEM_ALLOCATION_TYPE_PINNED;
+            props.handleTypes = cuda::CUmemAllocationHandleType::CU_MEM_HANDLE_TYPE_NONE;
+            props.location.type_ = cuda::CUmemLocationType::CU_MEM_LOCATION_TYPE_DEVICE;
+            props.location.id = device.as_raw();
+            let mut inner = ptr::null_mut();
+            cuda::cuMemPoolCreate(&mut inner, &props).to_result()?;
+            Ok(Self { inner, owned: true })
+        }
+    }
+
+    /// The default pool of `device`, which `cuMemAllocAsync` allocates from unless another pool
+    /// was made the current pool of the device.
+    pub fn default_for(device: Device) -> CudaResult<Self> {
+        unsafe {
+            let mut inner = ptr::null_mut();
+            cuda::cuDeviceGetDefaultMemPool(&mut inner, device.as_raw()).to_result()?;
+            Ok(Self {
+                inner,
+                owned: false,
+            })
+        }
+    }
+
+    /// Sets the number of bytes the pool keeps reserved when it releases memory to the device
+    /// on synchronization. `u64::MAX` keeps all of the memory.
+    pub fn set_release_threshold(&self, bytes: u64) -> CudaResult<()> {
+        let mut value = bytes;
+        unsafe {
+            cuda::cuMemPoolSetAttribute(
+                self.inner,
+                CUmemPool_attribute::CU_MEMPOOL_ATTR_RELEASE_THRESHOLD,
+                &mut value as *mut u64 as *mut c_void,
+            )
+            .to_result()
+        }
+    }
+
+    /// The number of bytes the pool keeps reserved when it releases memory to the device.
+    pub fn release_threshold(&self) -> CudaResult<u64> {
+        self.get_u64(CUmemPool_attribute::CU_MEMPOOL_ATTR_RELEASE_THRESHOLD)
+    }
+
+    /// The number of bytes of the allocations from this pool which have not been freed.
+    pub fn used_bytes(&self) -> CudaResult<u64> {
+        self.get_u64(CUmemPool_attribute::CU_MEMPOOL_ATTR_USED_MEM_CURRENT)
+    }
+
+    /// The number of bytes of device memory the pool currently holds, used or not.
+    pub fn reserved_bytes(&self) -> CudaResult<u64> {
+        self.get_u64(CUmemPool_attribute::CU_MEMPOOL_ATTR_RESERVED_MEM_CURRENT)
+    }
+
+    /// Releases memory of the pool to the device until it holds at most `bytes` bytes which are
+    /// not in use, or no unused memory is left. Memory of allocations which have not been freed
+    /// is never released.
+    pub fn trim_to(&self, bytes: usize) -> CudaResult<()> {
+        unsafe { cuda::cuMemPoolTrimTo(self.inner, bytes).to_result() }
+    }
+
+    /// Allocates memory for `count` values of `T` from this pool, once the work enqueued on
+    /// `stream` before has finished. The memory is not cleared.
+    ///
+    /// # Errors
+    ///
+    /// If the number of bytes to allocate is zero or would overflow a usize, returns
+    /// `InvalidMemoryAllocation`.
+    ///
+    /// # Safety
+    ///
+    /// The memory may only be used by work ordered after the allocation on `stream`, and must be
+    /// freed with [`free_async`](Self::free_async). It is not initialized.
+    pub unsafe fn malloc_async<T>(
+        &self,
+        count: usize,
+        stream: &Stream,
+    ) -> CudaResult<DevicePointer<T>> {
+        let size = count.checked_mul(mem::size_of::<T>()).unwrap_or(0);
+        if size == 0 {
+            return Err(CudaError::InvalidMemoryAllocation);
+        }
+
+        let _span = trace_span!("cust::malloc_async", bytes = size);
+        let mut ptr = 0;
+        cuda::cuMemAllocFromPoolAsync(&mut ptr, size, self.inner, stream.as_inner()).to_result()?;
+        hooks::allocated(ptr as usize, size, AllocationKind::Pool);
+        Ok(DevicePointer::wrap(ptr as *mut T))
+    }
+
+    /// Frees memory allocated with [`malloc_async`](Self::malloc_async) once the work enqueued
+    /// on `stream` before has finished, returning it to its pool.
+    ///
+    /// # Safety
+    ///
+    /// The memory must not be used by work which is not ordered before the free on `stream`.
+    pub unsafe fn free_async<T>(mut ptr: DevicePointer<T>, stream: &Stream) -> CudaResult<()> {
+        let ptr = ptr.as_raw_mut();
+        if ptr.is_null() {
+            return Err(CudaError::InvalidMemoryAllocation);
+        }
+
+        trace_event!(ptr = ptr as usize, "cust::free_async");
+        hooks::freed(ptr as usize);
+        cuda::cuMemFreeAsync(ptr as u64, stream.as_inner()).to_result()
+    }
+
+    /// The raw handle of the pool.
+    pub fn as_raw(&self) -> CUmemoryPool {
+        self.inner
+    }
+
+    fn get_u64(&self, attr: CUmemPool_attribute) -> CudaResult<u64> {
+        let mut value = 0u64;
+        unsafe {
+            cuda::cuMemPoolGetAttribute(self.inner, attr, &mut value as *mut u64 as *mut c_void)
+                .to_result()?;
+        }
+        Ok(value)
+    }
+
+    /// Destroys the pool, returning it with the error if destroying it fails. Destroying the
+    /// default pool of a device does nothing.
+    ///
+    /// Memory of allocations which have not been freed is released once they are freed.
+    pub fn drop(mut pool: MemoryPool) -> DropResult<MemoryPool> {
+        if !pool.owned || pool.inner.is_null() {
+            return Ok(());
+        }
+
+        unsafe {
+            let inner = mem::replace(&mut pool.inner, ptr::null_mut());
+            match cuda::cuMemPoolDestroy(inner).to_result() {
+                Ok(()) => {
+                    mem::forget(pool);
+                    Ok(())
+                }
+                Err(e) => Err((e, MemoryPool { inner, owned: true })),
+            }
+        }
+    }
+}
+
+impl Drop for MemoryPool {
+    fn drop(&mut self) {
+        if self.owned && !self.inner.is_null() {
+            unsafe { cuda::cuMemPoolDestroy(self.inner) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stream::StreamFlags;
+
+    fn pool_and_stream() -> (MemoryPool, Stream) {
+        let pool = MemoryPool::new(Device::get_device(0).unwrap()).unwrap();
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None).unwrap();
+        (pool, stream)
+    }
+
+    #[test]
+    fn test_allocate_and_free_async() {
+        let _context = crate::quick_init().unwrap();
+        let (pool, stream) = pool_and_stream();
+        unsafe {
+            let ptr = pool.malloc_async::<u32>(1024, &stream).unwrap();
+            assert!(!ptr.is_null());
+            stream.synchronize().unwrap();
+            assert!(pool.used_bytes().unwrap() >= 1024 * 4);
+
+            MemoryPool::free_async(ptr, &stream).unwrap();
+        }
+        stream.synchronize().unwrap();
+        assert_eq!(pool.used_bytes().unwrap(), 0);
+        MemoryPool::drop(pool).unwrap();
+    }
+
+    #[test]
+    fn test_allocate_zero_sized() {
+        let _context = crate::quick_init().unwrap();
+        let (pool, stream) = pool_and_stream();
+        let result = unsafe { pool.malloc_async::<u32>(0, &stream) };
+        assert_eq!(result.unwrap_err(), CudaError::InvalidMemoryAllocation);
+    }
+
+    #[test]
+    fn test_release_threshold_and_trim() {
+        let _context = crate::quick_init().unwrap();
+        let (pool, stream) = pool_and_stream();
+        pool.set_release_threshold(u64::MAX).unwrap();
+        assert_eq!(pool.release_threshold().unwrap(), u64::MAX);
+
+        unsafe {
+            let ptr = pool.malloc_async::<u8>(1 << 20, &stream).unwrap();
+            MemoryPool::free_async(ptr, &stream).unwrap();
+        }
+        stream.synchronize().unwrap();
+        // the threshold keeps the freed memory in the pool until it is trimmed.
+        assert_eq!(pool.used_bytes().unwrap(), 0);
+        assert!(pool.reserved_bytes().unwrap() >= 1 << 20);
+
+        pool.trim_to(0).unwrap();
+        assert_eq!(pool.reserved_bytes().unwrap(), 0);
+    }
+}
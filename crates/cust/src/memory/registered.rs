@@ -0,0 +1,176 @@
+use super::DeviceCopy;
+use crate::error::*;
+use crate::sys as cuda;
+use std::ffi::c_void;
+use std::mem;
+use std::ops;
+
+bitflags::bitflags! {
+    /// Flags for registering existing host memory with [`register_host_memory_with_flags`].
+    pub struct HostRegisterFlags: u32 {
+        /// No flags set.
+        const DEFAULT = 0x00;
+
+        /// The memory is considered page-locked by every CUDA context, not just the
+        /// context which was current when registering it.
+        const PORTABLE = cuda::CU_MEMHOSTREGISTER_PORTABLE;
+
+        /// Maps the memory into the CUDA address space, so that kernels can access it directly.
+        const DEVICE_MAP = cuda::CU_MEMHOSTREGISTER_DEVICEMAP;
+
+        /// The memory is I/O memory (for example, memory belonging to a third-party PCIe device),
+        /// it is always mapped into the CUDA address space.
+        const IO_MEMORY = cuda::CU_MEMHOSTREGISTER_IOMEMORY;
+
+        /// The memory will only be read by the device. This is required for registering
+        /// memory which the process can only read, such as read-only memory-mapped files.
+        const READ_ONLY = cuda::CU_MEMHOSTREGISTER_READ_ONLY;
+    }
+}
+
+/// A slice of existing host memory which has been page-locked for fast (and asynchronous) transfers.
+/// The memory is unregistered when this is dropped.
+///
+/// This is created with [`register_host_memory`] and is useful for memory which was not allocated
+/// by CUDA, such as memory-mapped files, where copying into a [`LockedBuffer`](super::LockedBuffer)
+/// first would defeat the purpose.
+///
+/// See the [`module-level documentation`](../memory/index.html) for more details on page-locked
+/// memory.
+#[derive(Debug)]
+pub struct RegisteredSlice<'a, T: DeviceCopy> {
+    slice: &'a mut [T],
+}
+
+/// Page-locks an existing slice of host memory, returning a guard which unregisters it when dropped.
+///
+/// Registering memory is an expensive operation, it is intended for large buffers which are reused
+/// or transferred in chunks, not for small temporary buffers.
+///
+/// # Errors
+///
+/// If the registration fails, returns the error from CUDA. Notably, returns
+/// [`CudaError::HostMemoryAlreadyRegistered`] if any part of the slice is already registered.
+///
+/// # Examples
+///
+/// ```
+/// # let _context = cust::quick_init().unwrap();
+/// use cust::memory::*;
+/// let mut values = vec![0u64; 1024];
+/// let mut registered = register_host_memory(&mut values).unwrap();
+/// let buffer = DeviceBuffer::from_slice(&[1u64; 1024]).unwrap();
+/// # let stream = cust::stream::Stream::new(cust::stream::StreamFlags::DEFAULT, None).unwrap();
+/// unsafe {
+///     buffer.async_copy_to(&mut registered, &stream).unwrap();
+/// }
+/// stream.synchronize().unwrap();
+/// drop(registered);
+/// assert!(values.iter().all(|x| *x == 1));
+/// ```
+pub fn register_host_memory<T: DeviceCopy>(slice: &mut [T]) -> CudaResult<RegisteredSlice<'_, T>> {
+    register_host_memory_with_flags(slice, HostRegisterFlags::DEFAULT)
+}
+
+/// Same as [`register_host_memory`] but with custom flags, see [`HostRegisterFlags`].
+pub fn register_host_memory_with_flags<T: DeviceCopy>(
+    slice: &mut [T],
+    flags: HostRegisterFlags,
+) -> CudaResult<RegisteredSlice<'_, T>> {
+    let size = mem::size_of_val(slice);
+    if size > 0 {
+        unsafe {
+            cuda::cuMemHostRegister_v2(slice.as_mut_ptr() as *mut c_void, size, flags.bits())
+                .to_result()?;
+        }
+    }
+    Ok(RegisteredSlice { slice })
+}
+
+impl<'a, T: DeviceCopy> RegisteredSlice<'a, T> {
+    /// Unregisters the memory, returning the original slice. Unlike dropping the guard, this
+    /// reports any error that occurs.
+    ///
+    /// # Errors
+    ///
+    /// If unregistering fails, returns the error from CUDA along with the guard.
+    pub fn unregister(mut reg: RegisteredSlice<'a, T>) -> Result<&'a mut [T], (CudaError, Self)> {
+        let slice = mem::take(&mut reg.slice);
+        if mem::size_of_val(slice) > 0 {
+            unsafe {
+                if let Err(e) =
+                    cuda::cuMemHostUnregister(slice.as_mut_ptr() as *mut c_void).to_result()
+                {
+                    return Err((e, RegisteredSlice { slice }));
+                }
+            }
+        }
+        Ok(slice)
+    }
+}
+
+impl<T: DeviceCopy> AsRef<[T]> for RegisteredSlice<'_, T> {
+    fn as_ref(&self) -> &[T] {
+        self.slice
+    }
+}
+impl<T: DeviceCopy> AsMut<[T]> for RegisteredSlice<'_, T> {
+    fn as_mut(&mut self) -> &mut [T] {
+        self.slice
+    }
+}
+impl<T: DeviceCopy> ops::Deref for RegisteredSlice<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        self.slice
+    }
+}
+impl<T: DeviceCopy> ops::DerefMut for RegisteredSlice<'_, T> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.slice
+    }
+}
+impl<T: DeviceCopy> Drop for RegisteredSlice<'_, T> {
+    fn drop(&mut self) {
+        if mem::size_of_val(self.slice) > 0 {
+            unsafe {
+                let _ = cuda::cuMemHostUnregister(self.slice.as_mut_ptr() as *mut c_void);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn register_and_unregister() {
+        let _context = crate::quick_init().unwrap();
+        let mut values = vec![0u64; 4096];
+        let reg = register_host_memory(&mut values).unwrap();
+        let slice = RegisteredSlice::unregister(reg).unwrap();
+        assert_eq!(slice.len(), 4096);
+    }
+
+    #[test]
+    fn registering_twice_fails() {
+        let _context = crate::quick_init().unwrap();
+        let mut values = vec![0u64; 4096];
+        let reg = register_host_memory(&mut values).unwrap();
+        let ptr = reg.as_ptr() as *mut c_void;
+        let err =
+            unsafe { cuda::cuMemHostRegister_v2(ptr, 4096 * mem::size_of::<u64>(), 0).to_result() }
+                .unwrap_err();
+        assert_eq!(CudaError::HostMemoryAlreadyRegistered, err);
+    }
+
+    #[test]
+    fn zero_length_slice() {
+        let _context = crate::quick_init().unwrap();
+        let mut values: Vec<u64> = vec![];
+        let reg = register_host_memory(&mut values).unwrap();
+        drop(reg);
+    }
+}
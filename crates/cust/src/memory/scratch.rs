@@ -0,0 +1,229 @@
+//! Temporary device memory recycled by a stream, see [`Stream::alloc_scratch`].
+
+use crate::error::CudaResult;
+use crate::event::{Event, EventFlags, EventStatus};
+use crate::memory::{DeviceBuffer, DeviceCopy, DevicePointer, DeviceSlice};
+use crate::stream::Stream;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+
+/// The smallest block the arena allocates, smaller requests share the size class.
+const MIN_BLOCK_SIZE: usize = 256;
+
+#[derive(Debug)]
+struct Block {
+    buffer: DeviceBuffer<u8>,
+    in_use: bool,
+    // recorded on the stream when the block was last released, the block can be freed once it completes.
+    released: Option<Event>,
+}
+
+/// The blocks of device memory a stream hands out as scratch buffers. Blocks are never shared between
+/// buffers, so every buffer is aligned like a fresh allocation.
+#[derive(Debug, Default)]
+pub(crate) struct ScratchArena {
+    // freed blocks leave a hole, so that borrowed blocks keep their index.
+    blocks: Vec<Option<Block>>,
+}
+
+impl ScratchArena {
+    /// The index of a free block of at least `size` bytes, allocating a new one if there is none.
+    fn acquire(&mut self, size: usize) -> CudaResult<usize> {
+        let free = self
+            .blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(index, block)| Some((index, block.as_ref()?)))
+            .filter(|(_, block)| !block.in_use && block.buffer.len() >= size)
+            .min_by_key(|(_, block)| block.buffer.len())
+            .map(|(index, _)| index);
+        let index = match free {
+            Some(index) => index,
+            None => {
+                // round up to a power of two, so that workspaces which grow a little between calls
+                // still fit in the block of the previous call.
+                let size = size.max(MIN_BLOCK_SIZE).next_power_of_two();
+                let block = Block {
+                    buffer: unsafe { DeviceBuffer::uninitialized(size)? },
+                    in_use: false,
+                    released: None,
+                };
+                match self.blocks.iter().position(Option::is_none) {
+                    Some(hole) => {
+                        self.blocks[hole] = Some(block);
+                        hole
+                    }
+                    None => {
+                        self.blocks.push(Some(block));
+                        self.blocks.len() - 1
+                    }
+                }
+            }
+        };
+        self.block(index).in_use = true;
+        Ok(index)
+    }
+
+    fn block(&mut self, index: usize) -> &mut Block {
+        self.blocks[index]
+            .as_mut()
+            .expect("borrowed scratch block was freed")
+    }
+
+    fn release(&mut self, index: usize, stream: &Stream) {
+        let block = self.block(index);
+        block.in_use = false;
+        let event = match block.released.take() {
+            Some(event) => Ok(event),
+            None => Event::new(EventFlags::DISABLE_TIMING),
+        };
+        // without an event the block is only freed when the stream is dropped.
+        block.released = event.and_then(|e| e.record(stream).map(|_| e)).ok();
+    }
+
+    /// Frees every released block whose work on the stream has completed.
+    fn trim(&mut self) -> CudaResult<()> {
+        for slot in &mut self.blocks {
+            let done = match slot {
+                Some(Block {
+                    in_use: false,
+                    released: Some(event),
+                    ..
+                }) => event.query()? == EventStatus::Ready,
+                _ => false,
+            };
+            if done {
+                *slot = None;
+            }
+        }
+        while matches!(self.blocks.last(), Some(None)) {
+            self.blocks.pop();
+        }
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.blocks
+            .iter()
+            .flatten()
+            .map(|block| block.buffer.len())
+            .sum()
+    }
+}
+
+/// A temporary buffer of device memory borrowed from the arena of a stream, created by
+/// [`Stream::alloc_scratch`]. It dereferences to a [`DeviceSlice`] and returns its memory to the
+/// stream when dropped.
+#[derive(Debug)]
+pub struct ScratchBuffer<'a, T: DeviceCopy> {
+    stream: &'a Stream,
+    block: usize,
+    ptr: DevicePointer<T>,
+    len: usize,
+}
+
+impl<'a, T: DeviceCopy> Deref for ScratchBuffer<'a, T> {
+    type Target = DeviceSlice<T>;
+
+    fn deref(&self) -> &DeviceSlice<T> {
+        unsafe { DeviceSlice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl<'a, T: DeviceCopy> DerefMut for ScratchBuffer<'a, T> {
+    fn deref_mut(&mut self) -> &mut DeviceSlice<T> {
+        unsafe { DeviceSlice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl<'a, T: DeviceCopy> Drop for ScratchBuffer<'a, T> {
+    fn drop(&mut self) {
+        self.stream
+            .scratch_arena()
+            .borrow_mut()
+            .release(self.block, self.stream);
+    }
+}
+
+impl Stream {
+    /// Borrows an uninitialized buffer of `len` elements from the scratch arena of this stream,
+    /// for temporary workspaces of algorithms such as sorts and scans.
+    ///
+    /// The arena keeps the memory of dropped scratch buffers and hands it out again, so code which
+    /// needs a workspace on every call does not allocate and free device memory every time. Since
+    /// work on a stream runs in order, memory can be handed out again as soon as the buffer is
+    /// dropped, even if kernels using it are still running, later work on the stream only starts
+    /// after them. For the same reason, **scratch buffers must only be used by work on the stream
+    /// they were borrowed from**.
+    ///
+    /// The arena only grows, [`trim_scratch`](Self::trim_scratch) returns its unused memory to the
+    /// driver. Every stream handle has its own arena, which is freed with it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let _context = cust::quick_init().unwrap();
+    /// use cust::stream::{Stream, StreamFlags};
+    ///
+    /// let stream = Stream::new(StreamFlags::NON_BLOCKING, None).unwrap();
+    /// let first = stream.alloc_scratch::<u32>(1000).unwrap().as_ptr();
+    /// // the first buffer was dropped, so its memory is reused.
+    /// let second = stream.alloc_scratch::<u32>(800).unwrap();
+    /// assert_eq!(second.as_ptr(), first);
+    /// assert_eq!(second.len(), 800);
+    /// ```
+    pub fn alloc_scratch<T: DeviceCopy>(&self, len: usize) -> CudaResult<ScratchBuffer<'_, T>> {
+        let size = len
+            .checked_mul(mem::size_of::<T>())
+            .expect("scratch buffer size overflows usize");
+        let mut arena = self.scratch_arena().borrow_mut();
+        let block = arena.acquire(size)?;
+        let ptr = unsafe {
+            DevicePointer::wrap(arena.block(block).buffer.as_device_ptr().as_raw_mut() as *mut T)
+        };
+        Ok(ScratchBuffer {
+            stream: self,
+            block,
+            ptr,
+            len,
+        })
+    }
+
+    /// Frees the memory of the scratch arena of this stream which is not borrowed and not used by
+    /// work still running on the stream.
+    pub fn trim_scratch(&self) -> CudaResult<()> {
+        self.scratch_arena().borrow_mut().trim()
+    }
+
+    /// The number of bytes of device memory held by the scratch arena of this stream, borrowed or
+    /// not.
+    pub fn scratch_capacity(&self) -> usize {
+        self.scratch_arena().borrow().capacity()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::stream::StreamFlags;
+
+    #[test]
+    fn test_scratch_reuse() -> Result<(), Box<dyn std::error::Error>> {
+        let _context = crate::quick_init()?;
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        let a = stream.alloc_scratch::<f32>(100)?;
+        let b = stream.alloc_scratch::<f32>(100)?;
+        assert_ne!(a.as_ptr(), b.as_ptr());
+        let a_ptr = a.as_ptr();
+        drop(a);
+        let c = stream.alloc_scratch::<u8>(400)?;
+        assert_eq!(c.as_ptr() as usize, a_ptr as usize);
+        assert_eq!(stream.scratch_capacity(), 1024);
+        drop((b, c));
+
+        stream.synchronize()?;
+        stream.trim_scratch()?;
+        assert_eq!(stream.scratch_capacity(), 0);
+        Ok(())
+    }
+}
@@ -0,0 +1,418 @@
+//! Snapshots of device memory, for checkpointing and restoring GPU state.
+//!
+//! A snapshot of a [`DeviceSlice`] can be taken synchronously with
+//! [`DeviceSlice::to_host_vec`], or asynchronously with [`DeviceSlice::snapshot_async`], which
+//! copies the slice into page-locked memory on a stream without blocking the calling thread.
+//!
+//! For checkpointing many buffers at once, for example to roll back the state of an iterative
+//! solver, [`SnapshotSet`] copies every registered buffer to or from a single page-locked staging
+//! buffer with one graph launch.
+//!
+//! With the `serde` feature, this module can also be used to serialize and deserialize device
+//! buffers directly with `#[serde(with = "cust::memory::snapshot")]`. Serializing copies the
+//! buffer to the host and deserializing allocates a new buffer, so a context must be current in
+//! both cases.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Checkpoint {
+//!     step: u64,
+//!     #[serde(with = "cust::memory::snapshot")]
+//!     particles: DeviceBuffer<Particle>,
+//! }
+//! ```
+
+use crate::error::{CudaResult, ToResult};
+use crate::event::{Event, EventFlags, EventStatus};
+use crate::graph::{Graph, GraphCreationFlags, GraphExec};
+use crate::memory::{DeviceCopy, DevicePointer, DeviceSlice, LockedBuffer};
+use crate::stream::Stream;
+use crate::sys as cuda;
+use std::ffi::c_void;
+use std::marker::PhantomData;
+use std::mem;
+
+/// A copy of a [`DeviceSlice`] into page-locked host memory which may still be in progress,
+/// created by [`DeviceSlice::snapshot_async`].
+///
+/// The slice stays borrowed until the snapshot is finished. Dropping an unfinished snapshot
+/// blocks until the copy completes.
+#[derive(Debug)]
+pub struct PendingSnapshot<'a, T: DeviceCopy> {
+    // always Some until the snapshot is taken out of it.
+    buffer: Option<LockedBuffer<T>>,
+    done: Event,
+    _slice: PhantomData<&'a DeviceSlice<T>>,
+}
+
+impl<'a, T: DeviceCopy> PendingSnapshot<'a, T> {
+    /// Whether the copy has finished, in which case [`wait`](Self::wait) does not block.
+    pub fn is_ready(&self) -> CudaResult<bool> {
+        Ok(self.done.query()? == EventStatus::Ready)
+    }
+
+    /// Blocks until the copy has finished and returns the snapshot.
+    pub fn wait(mut self) -> CudaResult<LockedBuffer<T>> {
+        self.done.synchronize()?;
+        Ok(self.buffer.take().unwrap())
+    }
+}
+
+impl<'a, T: DeviceCopy> Drop for PendingSnapshot<'a, T> {
+    fn drop(&mut self) {
+        if self.buffer.is_some() {
+            // the buffer must not be freed while the copy is still writing into it.
+            let _ = self.done.synchronize();
+        }
+    }
+}
+
+impl<T: DeviceCopy> DeviceSlice<T> {
+    /// Starts copying the slice into a newly allocated page-locked buffer on `stream`, returning
+    /// a [`PendingSnapshot`] which can be waited on for the copy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # let _context = cust::quick_init().unwrap();
+    /// use cust::memory::*;
+    /// use cust::stream::{Stream, StreamFlags};
+    ///
+    /// let stream = Stream::new(StreamFlags::NON_BLOCKING, None).unwrap();
+    /// let buf = DeviceBuffer::from_slice(&[1u32, 2, 3]).unwrap();
+    /// let snapshot = buf.snapshot_async(&stream).unwrap();
+    /// // ... queue more work on the stream ...
+    /// assert_eq!(snapshot.wait().unwrap().as_slice(), &[1, 2, 3]);
+    /// ```
+    pub fn snapshot_async(&self, stream: &Stream) -> CudaResult<PendingSnapshot<'_, T>> {
+        let mut buffer = unsafe { LockedBuffer::uninitialized(self.len())? };
+        let size = mem::size_of::<T>() * self.len();
+        if size != 0 {
+            unsafe {
+                cuda::cuMemcpyDtoHAsync_v2(
+                    buffer.as_mut_ptr() as *mut c_void,
+                    self.as_ptr() as u64,
+                    size,
+                    stream.as_inner(),
+                )
+                .to_result()?;
+            }
+        }
+        let done = Event::new(EventFlags::DISABLE_TIMING)?;
+        done.record(stream)?;
+        Ok(PendingSnapshot {
+            buffer: Some(buffer),
+            done,
+            _slice: PhantomData,
+        })
+    }
+}
+
+/// A set of device buffers which are snapshotted and restored together, see the
+/// [module docs](self).
+///
+/// Saving and restoring are done by launching a graph of copies between the buffers and a
+/// page-locked staging buffer, both graphs are built the first time they are used and rebuilt
+/// after registering more buffers. The staging buffer always holds the most recently saved or
+/// imported snapshot, [`export`](Self::export) and [`import`](Self::import) move snapshots in and
+/// out of it so that several generations can be kept (and optionally compressed) on the host.
+///
+/// # Examples
+///
+/// ```
+/// # let _context = cust::quick_init().unwrap();
+/// use cust::memory::*;
+/// use cust::snapshot::SnapshotSet;
+/// use cust::stream::{Stream, StreamFlags};
+///
+/// let stream = Stream::new(StreamFlags::NON_BLOCKING, None).unwrap();
+/// let mut state = DeviceBuffer::from_slice(&[1.0f32, 2.0, 3.0]).unwrap();
+/// let mut set = SnapshotSet::new();
+/// unsafe { set.register(&state) };
+///
+/// set.save(&stream).unwrap();
+/// let checkpoint = set.export().unwrap();
+///
+/// state.copy_from(&[0.0, 0.0, 0.0]).unwrap();
+/// set.import(&checkpoint).unwrap();
+/// set.restore(&stream).unwrap();
+/// stream.synchronize().unwrap();
+/// assert_eq!(state.to_host_vec().unwrap(), vec![1.0, 2.0, 3.0]);
+/// ```
+#[derive(Debug, Default)]
+pub struct SnapshotSet {
+    // (device pointer, size in bytes) of every registered buffer.
+    entries: Vec<(cuda::CUdeviceptr, usize)>,
+    staging: Option<LockedBuffer<u8>>,
+    save: Option<GraphExec>,
+    restore: Option<GraphExec>,
+    // recorded after the last save or restore, the staging buffer may not be touched before it
+    // completes.
+    pending: Option<Event>,
+}
+
+/// A snapshot exported from a [`SnapshotSet`], holding the contents of every registered buffer,
+/// possibly compressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    data: Vec<u8>,
+    len: usize,
+    compressed: bool,
+}
+
+impl Snapshot {
+    /// The (possibly compressed) contents of the snapshot.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Whether the snapshot was exported with [`SnapshotSet::export_with`].
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// The size of the snapshot in bytes once decompressed.
+    pub fn uncompressed_len(&self) -> usize {
+        self.len
+    }
+}
+
+impl SnapshotSet {
+    /// Creates an empty set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a buffer to the set.
+    ///
+    /// # Safety
+    ///
+    /// The buffer is not borrowed, it must stay alive (and not be reallocated) for as long as the
+    /// set is used to save or restore it. Restoring writes to the buffer regardless of any other
+    /// references to it.
+    pub unsafe fn register<T: DeviceCopy>(&mut self, buffer: &DeviceSlice<T>) {
+        let size = mem::size_of::<T>() * buffer.len();
+        if size != 0 {
+            self.entries
+                .push((buffer.as_ptr() as cuda::CUdeviceptr, size));
+        }
+        // the staging buffer is reallocated, so any copies still using it must finish first.
+        let _ = self.wait();
+        self.staging = None;
+        self.save = None;
+        self.restore = None;
+    }
+
+    /// Blocks until the last save or restore has finished.
+    pub fn wait(&mut self) -> CudaResult<()> {
+        if let Some(pending) = self.pending.take() {
+            pending.synchronize()?;
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, stream: &Stream) -> CudaResult<()> {
+        let event = Event::new(EventFlags::DISABLE_TIMING)?;
+        event.record(stream)?;
+        self.pending = Some(event);
+        Ok(())
+    }
+
+    /// The total size of the registered buffers in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.entries.iter().map(|(_, size)| size).sum()
+    }
+
+    fn staging(&mut self) -> CudaResult<&mut LockedBuffer<u8>> {
+        if self.staging.is_none() {
+            self.staging = Some(LockedBuffer::new(&0, self.size_in_bytes())?);
+        }
+        Ok(self.staging.as_mut().unwrap())
+    }
+
+    fn build(&mut self, save: bool) -> CudaResult<GraphExec> {
+        let entries = self.entries.clone();
+        let staging = self.staging()?.as_mut_slice();
+        let mut graph = Graph::new(GraphCreationFlags::NONE)?;
+        let mut offset = 0;
+        for (ptr, size) in entries {
+            // SAFETY: register requires the buffer to be alive.
+            let device = unsafe {
+                DeviceSlice::from_raw_parts_mut(DevicePointer::wrap(ptr as *mut u8), size)
+            };
+            let host = &mut staging[offset..offset + size];
+            if save {
+                graph.copy_dtoh(host, device).add()?;
+            } else {
+                graph.copy_htod(device, host).add()?;
+            }
+            offset += size;
+        }
+        graph.instantiate()
+    }
+
+    /// Copies every registered buffer into the staging buffer on `stream`, the copies run
+    /// asynchronously.
+    pub fn save(&mut self, stream: &Stream) -> CudaResult<()> {
+        if self.save.is_none() {
+            self.save = Some(self.build(true)?);
+        }
+        // SAFETY: the graph only contains copies between registered buffers and the staging
+        // buffer, which are all alive.
+        unsafe { self.save.as_ref().unwrap().launch(stream)? };
+        self.record(stream)
+    }
+
+    /// Copies the staging buffer back into every registered buffer on `stream`, the copies run
+    /// asynchronously.
+    ///
+    /// The registered buffers must not be used by other work while they are restored, if that
+    /// work is on another stream it must be ordered after the restore, for example with events.
+    pub fn restore(&mut self, stream: &Stream) -> CudaResult<()> {
+        if self.restore.is_none() {
+            self.restore = Some(self.build(false)?);
+        }
+        unsafe { self.restore.as_ref().unwrap().launch(stream)? };
+        self.record(stream)
+    }
+
+    /// Waits for the last [`save`](Self::save) and copies the staging buffer into a
+    /// [`Snapshot`].
+    pub fn export(&mut self) -> CudaResult<Snapshot> {
+        self.export_with(|bytes| bytes.to_vec())
+            .map(|snapshot| Snapshot {
+                compressed: false,
+                ..snapshot
+            })
+    }
+
+    /// Like [`export`](Self::export), but passes the staging buffer through `compress`, which
+    /// can be any compression algorithm.
+    pub fn export_with(&mut self, compress: impl FnOnce(&[u8]) -> Vec<u8>) -> CudaResult<Snapshot> {
+        self.wait()?;
+        let staging = self.staging()?;
+        Ok(Snapshot {
+            data: compress(staging.as_slice()),
+            len: staging.len(),
+            compressed: true,
+        })
+    }
+
+    /// Loads an uncompressed snapshot into the staging buffer, to be copied back into the
+    /// registered buffers with [`restore`](Self::restore).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the snapshot is compressed or was exported from a set with a different size.
+    pub fn import(&mut self, snapshot: &Snapshot) -> CudaResult<()> {
+        assert!(
+            !snapshot.compressed,
+            "compressed snapshots must be imported with `import_with`"
+        );
+        self.import_with(snapshot, |bytes| bytes.to_vec())
+    }
+
+    /// Like [`import`](Self::import), but passes the snapshot through `decompress` first.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the decompressed snapshot has a different size than the registered buffers.
+    pub fn import_with(
+        &mut self,
+        snapshot: &Snapshot,
+        decompress: impl FnOnce(&[u8]) -> Vec<u8>,
+    ) -> CudaResult<()> {
+        let data = decompress(&snapshot.data);
+        self.wait()?;
+        let staging = self.staging()?;
+        assert_eq!(
+            data.len(),
+            staging.len(),
+            "snapshot does not match the size of the registered buffers"
+        );
+        staging.copy_from_slice(&data);
+        Ok(())
+    }
+}
+
+impl Drop for SnapshotSet {
+    fn drop(&mut self) {
+        let _ = self.wait();
+    }
+}
+
+/// Serializes a device buffer (or slice) by copying it to the host, for use with
+/// `#[serde(serialize_with = "cust::memory::snapshot::serialize")]`.
+#[cfg(feature = "serde")]
+pub fn serialize<S, T, B>(buffer: &B, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+    T: DeviceCopy + serde::Serialize,
+    B: std::ops::Deref<Target = DeviceSlice<T>>,
+{
+    use serde::ser::Error;
+
+    let host = buffer.to_host_vec().map_err(S::Error::custom)?;
+    serde::Serialize::serialize(&host, serializer)
+}
+
+/// Deserializes a device buffer by allocating it and copying the deserialized elements to it,
+/// for use with `#[serde(deserialize_with = "cust::memory::snapshot::deserialize")]`.
+#[cfg(feature = "serde")]
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<crate::memory::DeviceBuffer<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: DeviceCopy + serde::Deserialize<'de>,
+{
+    use serde::de::Error;
+
+    let host = <Vec<T> as serde::Deserialize>::deserialize(deserializer)?;
+    crate::memory::DeviceBuffer::from_slice(&host).map_err(D::Error::custom)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::DeviceBuffer;
+    use crate::stream::StreamFlags;
+
+    #[test]
+    fn test_snapshot_async() -> Result<(), Box<dyn std::error::Error>> {
+        let _context = crate::quick_init()?;
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        let buf = DeviceBuffer::from_slice(&[1u64, 2, 3, 4])?;
+        let snapshot = buf.snapshot_async(&stream)?;
+        assert_eq!(snapshot.wait()?.as_slice(), &[1, 2, 3, 4]);
+        assert_eq!(buf.to_host_vec()?, vec![1, 2, 3, 4]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_set_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::memory::CopyDestination;
+
+        let _context = crate::quick_init()?;
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        let mut a = DeviceBuffer::from_slice(&[1u32, 2, 3])?;
+        let mut b = DeviceBuffer::from_slice(&[4.0f64, 5.0])?;
+        let mut set = SnapshotSet::new();
+        unsafe {
+            set.register(&a);
+            set.register(&b);
+        }
+        assert_eq!(set.size_in_bytes(), 3 * 4 + 2 * 8);
+
+        set.save(&stream)?;
+        let snapshot = set.export_with(|bytes| bytes.iter().rev().copied().collect())?;
+        assert!(snapshot.is_compressed());
+
+        a.copy_from(&[0, 0, 0])?;
+        b.copy_from(&[0.0, 0.0])?;
+        set.import_with(&snapshot, |bytes| bytes.iter().rev().copied().collect())?;
+        set.restore(&stream)?;
+        stream.synchronize()?;
+        assert_eq!(a.to_host_vec()?, vec![1, 2, 3]);
+        assert_eq!(b.to_host_vec()?, vec![4.0, 5.0]);
+        Ok(())
+    }
+}
@@ -1,20 +1,76 @@
 //! Functions and types for working with CUDA modules.
 
-use crate::error::{CudaResult, DropResult, ToResult};
+use crate::context::CurrentContext;
+use crate::device::{Device, DeviceAttribute};
+use crate::driver_ext;
+use crate::error::{CudaError, CudaResult, DropResult, ToResult};
 use crate::function::Function;
-use crate::memory::{CopyDestination, DeviceCopy, DevicePointer};
+use crate::memory::{default_stream, CopyDestination, DeviceCopy, DevicePointer};
 use crate::sys as cuda;
+use std::collections::HashMap;
 use std::ffi::{c_void, CStr, CString};
 use std::fmt;
 use std::marker::PhantomData;
 use std::mem;
-use std::path::Path;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::ptr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+
+use once_cell::sync::OnceCell;
 
 /// A compiled CUDA module, loaded into a context.
 #[derive(Debug)]
 pub struct Module {
     inner: cuda::CUmodule,
+    /// The `__cuda_abi_<name>` hashes read by [`Module::get_function_checked`], or `None` for kernels
+    /// without one, so that they are only copied from the device once.
+    abi_hashes: Mutex<HashMap<String, Option<u64>>>,
+}
+
+/// How the driver loads the kernels of modules, see [`loading_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ModuleLoadingMode {
+    /// Every kernel of a module is loaded onto the device when the module is loaded.
+    Eager,
+    /// Kernels are only loaded onto the device when they are first retrieved from a module with
+    /// [`Module::get_function`], usually right before their first launch.
+    Lazy,
+}
+
+/// The mode in which the driver loads modules in this process (`cuModuleGetLoadingMode`), which
+/// requires CUDA 11.7 and returns [`CudaError::NotSupported`] on older drivers.
+///
+/// Lazy loading is enabled with the `CUDA_MODULE_LOADING=LAZY` environment variable (the default
+/// from CUDA 12.2), which must be set before [`crate::init`], for example with
+/// [`request_loading_mode`]. It makes loading modules with hundreds of kernels, and fatbins with
+/// images for many architectures, much faster and uses less device memory, because only the
+/// kernels which are used are ever loaded and only for the architecture of the device. The cost
+/// is moved to the first [`Module::get_function`] of every kernel, which can be paid up front for
+/// the kernels on a hot path with [`Module::preload`].
+pub fn loading_mode() -> CudaResult<ModuleLoadingMode> {
+    let mut mode = 0;
+    unsafe { driver_ext::cuModuleGetLoadingMode(&mut mode)? };
+    Ok(if mode == driver_ext::CU_MODULE_LAZY_LOADING {
+        ModuleLoadingMode::Lazy
+    } else {
+        ModuleLoadingMode::Eager
+    })
+}
+
+/// Asks the driver to load modules in `mode` by setting `CUDA_MODULE_LOADING`, unless the variable
+/// was already set by the user. This only has an effect before [`crate::init`] is first called, and
+/// drivers older than CUDA 11.7 always load eagerly.
+pub fn request_loading_mode(mode: ModuleLoadingMode) {
+    const VAR: &str = "CUDA_MODULE_LOADING";
+    if std::env::var_os(VAR).is_none() {
+        let value = match mode {
+            ModuleLoadingMode::Eager => "EAGER",
+            ModuleLoadingMode::Lazy => "LAZY",
+        };
+        std::env::set_var(VAR, value);
+    }
 }
 
 #[cfg(unix)]
@@ -29,6 +85,13 @@ fn path_to_bytes<P: AsRef<Path>>(path: P) -> Vec<u8> {
 }
 
 impl Module {
+    fn null() -> Module {
+        Module {
+            inner: ptr::null_mut(),
+            abi_hashes: Mutex::new(HashMap::new()),
+        }
+    }
+
     /// Load a module from the given path into the current context.
     ///
     /// The given path should be either a cubin file, a ptx file, or a fatbin file such as
@@ -49,14 +112,13 @@ impl Module {
     /// # }
     /// ```
     pub fn from_file<P: AsRef<Path>>(path: P) -> CudaResult<Module> {
+        let _span = trace_span!("cust::module_load", path = %path.as_ref().display());
         unsafe {
             let mut bytes = path_to_bytes(path);
             if !bytes.contains(&0) {
                 bytes.push(0);
             }
-            let mut module = Module {
-                inner: ptr::null_mut(),
-            };
+            let mut module = Module::null();
             cuda::cuModuleLoad(
                 &mut module.inner as *mut cuda::CUmodule,
                 bytes.as_ptr() as *const _,
@@ -99,10 +161,14 @@ impl Module {
     /// # }
     /// ```
     pub fn load_from_string(image: &CStr) -> CudaResult<Module> {
+        Module::load_data(image.to_bytes_with_nul())
+    }
+
+    /// Loads a module from the bytes of a cubin, a fatbin or nul-terminated PTX.
+    fn load_data(image: &[u8]) -> CudaResult<Module> {
+        let _span = trace_span!("cust::module_load", bytes = image.len());
         unsafe {
-            let mut module = Module {
-                inner: ptr::null_mut(),
-            };
+            let mut module = Module::null();
             cuda::cuModuleLoadData(
                 &mut module.inner as *mut cuda::CUmodule,
                 image.as_ptr() as *const c_void,
@@ -193,6 +259,93 @@ impl Module {
         }
     }
 
+    /// Gets a kernel function like [`get_function`](Self::get_function), and checks that it was
+    /// built from a kernel whose parameter and return types hash to `abi_hash`. This is used by the
+    /// kernel bindings generated by `cuda_builder` to catch PTX built from an older version of a
+    /// kernel, which would otherwise be launched with the wrong parameters.
+    ///
+    /// `#[kernel]` embeds the hash as the `__cuda_abi_<name>` global. Kernels without it, such as
+    /// ones from PTX not built by `rustc_codegen_nvvm`, are not checked. Returns
+    /// [`CudaError::KernelAbiMismatch`] if the hashes differ.
+    ///
+    /// The hash is copied from the device the first time a kernel is checked, which synchronizes
+    /// (outside of [`strict-async`](crate::strict) checks), and cached in the module afterwards, so
+    /// that launching through the bindings does not block the host.
+    pub fn get_function_checked<T: AsRef<str>>(
+        &'_ self,
+        name: T,
+        abi_hash: u64,
+    ) -> CudaResult<Function<'_>> {
+        let name = name.as_ref();
+        let function = self.get_function(name)?;
+        match self.abi_hash(name)? {
+            Some(hash) if hash != abi_hash => Err(CudaError::KernelAbiMismatch),
+            _ => Ok(function),
+        }
+    }
+
+    /// The ABI hash of the kernel `name`, read from the device the first time it is asked for.
+    fn abi_hash(&self, name: &str) -> CudaResult<Option<u64>> {
+        let mut hashes = self.abi_hashes.lock().unwrap();
+        if let Some(&hash) = hashes.get(name) {
+            return Ok(hash);
+        }
+        let symbol = CString::new(format!("__cuda_abi_{}", name))
+            .expect("Argument to get_function_checked had a nul");
+        let hash = match self.get_global::<u64>(&symbol) {
+            Ok(global) => {
+                let mut hash = 0u64;
+                // a synchronous copy, but only once per kernel, not once per launch.
+                crate::strict::allow_implicit_sync(|| global.copy_to(&mut hash))?;
+                Some(hash)
+            }
+            Err(CudaError::NotFound) => None,
+            Err(e) => return Err(e),
+        };
+        hashes.insert(name.to_string(), hash);
+        Ok(hash)
+    }
+
+    /// A kernel function which is only retrieved from the module when it is first used, so that
+    /// creating handles for all of the kernels of a module up front does not load them with
+    /// [lazy loading](loading_mode).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::module::Module;
+    ///
+    /// let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+    /// let sum = module.lazy_function("sum");
+    /// // `sum` is looked up (and loaded) here.
+    /// let function = sum.get()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn lazy_function<T: AsRef<str>>(&self, name: T) -> LazyFunction<'_> {
+        let name = CString::new(name.as_ref()).expect("Argument to lazy_function had a nul");
+        LazyFunction {
+            module: self,
+            name,
+            inner: OnceCell::new(),
+        }
+    }
+
+    /// Loads the kernels called `names` onto the device now, so that their first launch does not
+    /// wait for them to be loaded when the driver [loads modules lazily](loading_mode). With eager
+    /// loading this only checks that the kernels exist.
+    pub fn preload<T: AsRef<str>>(&self, names: &[T]) -> CudaResult<()> {
+        let _span = trace_span!("cust::module_preload", kernels = names.len());
+        for name in names {
+            self.get_function(name)?;
+        }
+        Ok(())
+    }
+
     /// Destroy a `Module`, returning an error.
     ///
     /// Destroying a module can return errors from previous asynchronous work. This function
@@ -228,11 +381,12 @@ impl Module {
         unsafe {
             let inner = mem::replace(&mut module.inner, ptr::null_mut());
             match cuda::cuModuleUnload(inner).to_result() {
-                Ok(()) => {
-                    mem::forget(module);
-                    Ok(())
+                // `inner` is null now, so dropping the module does nothing else.
+                Ok(()) => Ok(()),
+                Err(e) => {
+                    module.inner = inner;
+                    Err((e, module))
                 }
-                Err(e) => Err((e, Module { inner })),
             }
         }
     }
@@ -250,6 +404,671 @@ impl Drop for Module {
     }
 }
 
+/// A kernel function of a module which is looked up the first time it is used, created by
+/// [`Module::lazy_function`].
+#[derive(Debug)]
+pub struct LazyFunction<'a> {
+    module: &'a Module,
+    name: CString,
+    // the function, stored as an integer because raw pointers are not Send.
+    inner: OnceCell<usize>,
+}
+
+impl<'a> LazyFunction<'a> {
+    /// The function, which is looked up in the module (and loaded with lazy loading) the first
+    /// time this is called.
+    pub fn get(&self) -> CudaResult<Function<'a>> {
+        let inner = self.inner.get_or_try_init(|| unsafe {
+            let mut func: cuda::CUfunction = ptr::null_mut();
+            cuda::cuModuleGetFunction(&mut func, self.module.inner, self.name.as_ptr())
+                .to_result()?;
+            Ok::<_, CudaError>(func as usize)
+        })?;
+        Ok(Function::new(*inner as cuda::CUfunction, self.module))
+    }
+
+    /// Whether the function was looked up already.
+    pub fn is_loaded(&self) -> bool {
+        self.inner.get().is_some()
+    }
+
+    /// The name of the function.
+    pub fn name(&self) -> &CStr {
+        &self.name
+    }
+}
+
+/// A module which is loaded from an embedded image the first time it is used in every context,
+/// usually created through [`include_ptx!`](crate::include_ptx).
+///
+/// Loaded modules are kept until the process exits, so they can be handed out as `&'static`.
+/// The driver may reuse the handle of a destroyed context for a new context, therefore
+/// [`LazyModule::clear`] must be called after destroying a context the module was loaded into.
+///
+/// # Example
+///
+/// ```
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::module::LazyModule;
+///
+/// static MODULE: LazyModule = LazyModule::new(include_str!("../resources/add.ptx"));
+///
+/// let function = MODULE.get()?.get_function("sum")?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct LazyModule {
+    image: &'static str,
+    // (context, leaked module) pairs, stored as integers because raw pointers are not Send.
+    modules: OnceCell<Mutex<Vec<(usize, usize)>>>,
+}
+
+impl LazyModule {
+    /// Creates a module which is loaded from `image` (PTX) when it is first used.
+    pub const fn new(image: &'static str) -> Self {
+        Self {
+            image,
+            modules: OnceCell::new(),
+        }
+    }
+
+    /// Returns the module for the current context, loading it if it was not loaded into the
+    /// context yet.
+    pub fn get(&self) -> CudaResult<&'static Module> {
+        let mut ctx: cuda::CUcontext = ptr::null_mut();
+        unsafe { cuda::cuCtxGetCurrent(&mut ctx).to_result()? };
+        if ctx.is_null() {
+            return Err(CudaError::InvalidContext);
+        }
+        let mut modules = self
+            .modules
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(&(_, module)) = modules.iter().find(|(c, _)| *c == ctx as usize) {
+            return Ok(unsafe { &*(module as *const Module) });
+        }
+        let module: &'static Module = Box::leak(Box::new(Module::from_str(self.image)?));
+        modules.push((ctx as usize, module as *const Module as usize));
+        Ok(module)
+    }
+
+    /// Forgets every loaded module, without unloading them. Modules are loaded again when they are
+    /// next used.
+    pub fn clear(&self) {
+        if let Some(modules) = self.modules.get() {
+            modules.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        }
+    }
+}
+
+/// A module which can be unloaded at runtime while handles to its functions are still around, for
+/// plugin-style applications which load and unload GPU code as they run.
+///
+/// Functions retrieved from a [`Module`] borrow it, so the compiler makes sure that no function
+/// outlives its module, but this makes it hard to keep the functions of a module which is loaded
+/// and unloaded at runtime. The functions of an `UnloadableModule` are owned and instead checked at
+/// runtime: once the module is [unloaded](Self::unload), [`UnloadableFunction::with`] returns
+/// [`CudaError::InvalidHandle`] instead of a function which could be launched.
+///
+/// Work which was already launched is not waited for, streams running kernels of the module should
+/// be synchronized before unloading it.
+///
+/// # Example
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::error::CudaError;
+/// use cust::module::{Module, UnloadableModule};
+/// use cust::stream::{Stream, StreamFlags};
+///
+/// let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+/// let plugin = UnloadableModule::new(Module::from_file("./plugin.ptx")?);
+/// let sum = plugin.get_function("sum")?;
+/// // ... launch `sum` through `sum.with(|function| ...)` ...
+/// stream.synchronize()?;
+/// plugin.unload()?;
+/// assert_eq!(sum.with(|_| ()).unwrap_err(), CudaError::InvalidHandle);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnloadableModule {
+    slot: Arc<ModuleSlot>,
+}
+
+/// A function of an [`UnloadableModule`], which can only be used while the module is loaded.
+#[derive(Debug, Clone)]
+pub struct UnloadableFunction {
+    slot: Arc<ModuleSlot>,
+    // stored as an integer because raw pointers are not Send.
+    inner: usize,
+}
+
+#[derive(Debug)]
+struct ModuleSlot(RwLock<Option<Module>>);
+
+// modules and functions can be used from any thread the context is current on, the lock makes sure
+// that the module is not unloaded while it is used.
+unsafe impl Send for ModuleSlot {}
+unsafe impl Sync for ModuleSlot {}
+
+impl UnloadableModule {
+    /// Wraps a loaded module.
+    pub fn new(module: Module) -> Self {
+        Self {
+            slot: Arc::new(ModuleSlot(RwLock::new(Some(module)))),
+        }
+    }
+
+    /// Gets an owned handle to a kernel function of the module. Returns
+    /// [`CudaError::InvalidHandle`] if the module was unloaded.
+    pub fn get_function<T: AsRef<str>>(&self, name: T) -> CudaResult<UnloadableFunction> {
+        let module = self.slot.0.read().unwrap_or_else(|e| e.into_inner());
+        let module = module.as_ref().ok_or(CudaError::InvalidHandle)?;
+        let inner = module.get_function(name)?.to_raw() as usize;
+        Ok(UnloadableFunction {
+            slot: self.slot.clone(),
+            inner,
+        })
+    }
+
+    /// Calls `f` with the module, or returns [`CudaError::InvalidHandle`] if it was unloaded. The
+    /// module is not unloaded while `f` runs.
+    pub fn with<R>(&self, f: impl FnOnce(&Module) -> R) -> CudaResult<R> {
+        let module = self.slot.0.read().unwrap_or_else(|e| e.into_inner());
+        module.as_ref().map(f).ok_or(CudaError::InvalidHandle)
+    }
+
+    /// Unloads the module from its context, which frees the memory of its code and globals. Every
+    /// function of the module (and every clone of this handle) becomes unusable. This waits for
+    /// calls of [`with`](Self::with) on other threads to return, and does nothing if the module was
+    /// unloaded already.
+    ///
+    /// If unloading fails the module stays loaded and the error is returned.
+    pub fn unload(&self) -> CudaResult<()> {
+        let mut slot = self.slot.0.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(module) = slot.take() {
+            let _span = trace_span!("cust::module_unload");
+            if let Err((e, module)) = Module::drop(module) {
+                *slot = Some(module);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the module is still loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.slot
+            .0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+}
+
+impl UnloadableFunction {
+    /// Calls `f` with the function, usually to launch it, or returns [`CudaError::InvalidHandle`]
+    /// if its module was unloaded. The module is not unloaded while `f` runs.
+    pub fn with<R>(&self, f: impl FnOnce(&Function<'_>) -> R) -> CudaResult<R> {
+        let module = self.slot.0.read().unwrap_or_else(|e| e.into_inner());
+        let module = module.as_ref().ok_or(CudaError::InvalidHandle)?;
+        Ok(f(&Function::new(self.inner as cuda::CUfunction, module)))
+    }
+
+    /// Whether the module of the function is still loaded.
+    pub fn is_loaded(&self) -> bool {
+        self.slot
+            .0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_some()
+    }
+}
+
+/// A module loaded from a file which is loaded again when the file changes, so that kernels can
+/// be iterated on without restarting the application. Usually the file is rebuilt by
+/// `cuda_builder::CudaBuilder::watch`.
+///
+/// The module is only replaced by [`HotReloadModule::reload_if_changed`], which should be called
+/// between launches. Functions borrow the module, so no function of an old module can be used
+/// after it was replaced.
+///
+/// # Example
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::module::HotReloadModule;
+///
+/// let mut module = HotReloadModule::new("./resources/add.ptx")?;
+/// loop {
+///     if let Err(e) = module.reload_if_changed() {
+///         // keep using the previous version until the file is fixed.
+///         eprintln!("failed to reload the module: {}", e);
+///     }
+///     let function = module.get_function("sum")?;
+///     // ... launch `function` ...
+/// #   break;
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HotReloadModule {
+    path: PathBuf,
+    module: Module,
+    modified: Option<SystemTime>,
+    generation: u64,
+}
+
+impl HotReloadModule {
+    /// Loads the module at `path` into the current context, like [`Module::from_file`].
+    pub fn new<P: AsRef<Path>>(path: P) -> CudaResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+        let module = Module::from_file(&path)?;
+        Ok(Self {
+            path,
+            module,
+            modified,
+            generation: 0,
+        })
+    }
+
+    /// Loads the module again if its file was modified since it was last loaded, returning
+    /// whether it was reloaded. The new module is loaded into the current context.
+    ///
+    /// If loading the new module fails, the previous module is kept and the error is returned.
+    /// The same file is not tried again until it is modified again.
+    pub fn reload_if_changed(&mut self) -> CudaResult<bool> {
+        let modified = modified_time(&self.path);
+        if modified == self.modified {
+            return Ok(false);
+        }
+        self.modified = modified;
+        self.module = Module::from_file(&self.path)?;
+        self.generation += 1;
+        Ok(true)
+    }
+
+    /// The currently loaded module.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// The path the module is loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of times the module was reloaded, which can be used to know when state derived
+    /// from the module (such as launch configurations) must be recomputed.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+impl Deref for HotReloadModule {
+    type Target = Module;
+
+    fn deref(&self) -> &Module {
+        &self.module
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Values for the specialization constants of a PTX module, which are written into the PTX before it is
+/// loaded, so that the JIT compiler specializes the kernels for them. Specialization constants are declared
+/// with `cuda_std::spec_constant!`.
+///
+/// # Example
+///
+/// ```
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::module::{Module, Specialization};
+///
+/// // the constants as `spec_constant!` declares them.
+/// const PTX: &str = "\
+/// .version 7.0
+/// .target sm_61
+/// .address_size 64
+///
+/// .visible .const .align 4 .u32 TILE_SIZE = 16;
+/// .visible .const .align 1 .u8 USE_FAST_MATH = 0;
+/// ";
+///
+/// let ptx = Specialization::new()
+///     .set("TILE_SIZE", 32u32)
+///     .set("USE_FAST_MATH", true)
+///     .apply(PTX)?;
+/// let module = Module::from_str(ptx)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Specialization {
+    values: Vec<(String, Vec<u8>)>,
+}
+
+impl Specialization {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of the specialization constant `name`, which must have the type `T` on the device.
+    pub fn set<T: DeviceCopy>(mut self, name: &str, value: T) -> Self {
+        let bytes = unsafe {
+            std::slice::from_raw_parts(&value as *const T as *const u8, mem::size_of::<T>())
+        };
+        self.values.retain(|(set, _)| set != name);
+        self.values.push((name.to_string(), bytes.to_vec()));
+        self
+    }
+
+    /// Returns `ptx` with the initializers of the specialization constants replaced by their values.
+    ///
+    /// Returns [`CudaError::NotFound`] if a constant is not defined in `ptx`, and [`CudaError::InvalidValue`]
+    /// if its size is not the size of its value.
+    pub fn apply(&self, ptx: &str) -> CudaResult<String> {
+        let mut lines = ptx.lines().map(str::to_string).collect::<Vec<_>>();
+        for (name, bytes) in &self.values {
+            let line = lines
+                .iter_mut()
+                .find(|line| global_name(line) == Some(name.as_str()))
+                .ok_or(CudaError::NotFound)?;
+            *line = specialize_global(line, bytes)?;
+        }
+        let mut out = lines.join("\n");
+        if ptx.ends_with('\n') {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+}
+
+/// The name of the global defined by a line of PTX, if it defines a global in the global or constant state space.
+fn global_name(line: &str) -> Option<&str> {
+    let declaration = line
+        .split('=')
+        .next()?
+        .trim()
+        .trim_end_matches(';')
+        .trim_end();
+    let mut words = declaration.split_whitespace();
+    if !words.any(|word| word == ".const" || word == ".global") || declaration.contains(".extern") {
+        return None;
+    }
+    let declarator = declaration.split_whitespace().last()?;
+    Some(declarator.split('[').next().unwrap_or(declarator))
+}
+
+/// Replaces the definition of a global by a byte array of the same size initialized to `bytes`, for example
+/// `.visible .const .align 4 .u32 TILE = 16;` by `.visible .const .align 4 .b8 TILE[4] = {32, 0, 0, 0};`.
+fn specialize_global(line: &str, bytes: &[u8]) -> CudaResult<String> {
+    let indent = &line[..line.len() - line.trim_start().len()];
+    let declaration = line.split('=').next().unwrap_or(line);
+    let declaration = declaration.trim().trim_end_matches(';').trim_end();
+    let mut words = declaration.split_whitespace().collect::<Vec<_>>();
+    let declarator = words.pop().ok_or(CudaError::InvalidPtx)?;
+    let (name, count) = match declarator.split_once('[') {
+        Some((name, count)) => (
+            name,
+            count
+                .trim_end_matches(']')
+                .parse::<usize>()
+                .map_err(|_| CudaError::InvalidPtx)?,
+        ),
+        None => (declarator, 1),
+    };
+    let ty = words
+        .iter()
+        .position(|word| scalar_size(word).is_some())
+        .ok_or(CudaError::InvalidPtx)?;
+    if scalar_size(words[ty]).unwrap() * count != bytes.len() {
+        return Err(CudaError::InvalidValue);
+    }
+    words[ty] = ".b8";
+    let bytes = bytes.iter().map(u8::to_string).collect::<Vec<_>>();
+    Ok(format!(
+        "{}{} {}[{}] = {{{}}};",
+        indent,
+        words.join(" "),
+        name,
+        bytes.len(),
+        bytes.join(", ")
+    ))
+}
+
+/// The size in bytes of a PTX fundamental type such as `.u32`.
+fn scalar_size(ty: &str) -> Option<usize> {
+    let bits = ty
+        .strip_prefix(".b")
+        .or_else(|| ty.strip_prefix(".u"))
+        .or_else(|| ty.strip_prefix(".s"))
+        .or_else(|| ty.strip_prefix(".f"))?;
+    match bits {
+        "8" | "16" | "32" | "64" => Some(bits.parse::<usize>().unwrap() / 8),
+        _ => None,
+    }
+}
+
+/// Several variants of the same kernels, such as PTX or cubins compiled for different architectures
+/// or hand-tuned for some devices, of which the best one for a device is loaded.
+///
+/// Fatbins let the driver pick between builds of the same source for different architectures, a kernel
+/// set also picks between different sources. Variants can be limited to devices with some properties
+/// with [`KernelVariant::when`]. The variant loaded is the one with the highest architecture the device
+/// supports, of those the ones whose condition holds before the unconditional ones, then cubins before
+/// PTX (which would have to be JIT compiled), and otherwise the variant added first. Here `large_tiles`
+/// is loaded on devices before sm_80 with enough shared memory:
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::device::DeviceAttribute;
+/// use cust::module::{KernelSet, KernelVariant};
+///
+/// let set = KernelSet::new()
+///     // the target architecture is read from the PTX.
+///     .variant(KernelVariant::ptx("generic", include_str!("../resources/add.ptx")))
+///     .variant(KernelVariant::cubin("sm_80", std::fs::read("add_sm80.cubin")?, (8, 0)))
+///     .variant(
+///         KernelVariant::ptx("large_tiles", include_str!("../resources/add.ptx")).when(|device| {
+///             device
+///                 .get_attribute(DeviceAttribute::MaxSharedMemoryPerBlockOptin)
+///                 .map_or(false, |bytes| bytes >= 96 * 1024)
+///         }),
+///     );
+/// let (module, variant) = set.load()?;
+/// println!("loaded the {} kernels", variant.name());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct KernelSet {
+    variants: Vec<KernelVariant>,
+}
+
+impl fmt::Debug for KernelSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(&self.variants).finish()
+    }
+}
+
+/// A variant of the kernels of a [`KernelSet`].
+pub struct KernelVariant {
+    name: String,
+    image: Vec<u8>,
+    arch: (u32, u32),
+    cubin: bool,
+    condition: Option<Box<dyn Fn(Device) -> bool + Send + Sync>>,
+}
+
+impl fmt::Debug for KernelVariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KernelVariant")
+            .field("name", &self.name)
+            .field("arch", &self.arch)
+            .field("cubin", &self.cubin)
+            .field("conditional", &self.condition.is_some())
+            .finish()
+    }
+}
+
+impl KernelVariant {
+    /// PTX kernels, which run on devices of the architecture of its `.target` directive and newer ones.
+    pub fn ptx(name: impl Into<String>, ptx: impl Into<String>) -> Self {
+        let ptx = ptx.into();
+        let arch = ptx_target(&ptx).unwrap_or((0, 0));
+        let mut image = ptx.into_bytes();
+        image.push(0);
+        Self {
+            name: name.into(),
+            image,
+            arch,
+            cubin: false,
+            condition: None,
+        }
+    }
+
+    /// Kernels compiled to a cubin for the architecture `arch` (the compute capability as
+    /// `(major, minor)`), which only run on devices of the same major version.
+    pub fn cubin(name: impl Into<String>, cubin: impl Into<Vec<u8>>, arch: (u32, u32)) -> Self {
+        Self {
+            name: name.into(),
+            image: cubin.into(),
+            arch,
+            cubin: true,
+            condition: None,
+        }
+    }
+
+    /// Only uses this variant on devices for which `condition` returns true, such as devices with a lot
+    /// of shared memory or SMs.
+    pub fn when(mut self, condition: impl Fn(Device) -> bool + Send + Sync + 'static) -> Self {
+        self.condition = Some(Box::new(condition));
+        self
+    }
+
+    /// The name of the variant, for logging which one was loaded.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The architecture the variant requires, as `(major, minor)`.
+    pub fn arch(&self) -> (u32, u32) {
+        self.arch
+    }
+
+    /// Whether the variant runs on a device of compute capability `cc`, without its condition.
+    fn runs_on(&self, cc: (u32, u32)) -> bool {
+        if self.cubin {
+            cc.0 == self.arch.0 && cc.1 >= self.arch.1
+        } else {
+            cc >= self.arch
+        }
+    }
+}
+
+impl KernelSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a variant to the set.
+    pub fn variant(mut self, variant: KernelVariant) -> Self {
+        self.variants.push(variant);
+        self
+    }
+
+    /// The variants of the set, in the order they were added.
+    pub fn variants(&self) -> &[KernelVariant] {
+        &self.variants
+    }
+
+    /// The variant which would be loaded on `device`, if any of them runs on it.
+    pub fn select(&self, device: Device) -> CudaResult<Option<&KernelVariant>> {
+        let cc = (
+            device.get_attribute(DeviceAttribute::ComputeCapabilityMajor)? as u32,
+            device.get_attribute(DeviceAttribute::ComputeCapabilityMinor)? as u32,
+        );
+        let index = self.pick(cc, |variant| match &variant.condition {
+            Some(condition) => condition(device),
+            None => true,
+        });
+        Ok(index.map(|index| &self.variants[index]))
+    }
+
+    /// Loads the best variant for the device of the current context into the context, returning the
+    /// module and the variant. Returns [`CudaError::NoBinaryForGpu`] if no variant runs on the device.
+    pub fn load(&self) -> CudaResult<(Module, &KernelVariant)> {
+        let device = CurrentContext::get_device()?;
+        let variant = self.select(device)?.ok_or(CudaError::NoBinaryForGpu)?;
+        let _span = trace_span!("cust::kernel_set_load", variant = %variant.name);
+        let module = Module::load_data(&variant.image)?;
+        Ok((module, variant))
+    }
+
+    /// The index of the best variant for a device of compute capability `cc` whose condition holds.
+    fn pick(
+        &self,
+        cc: (u32, u32),
+        mut condition: impl FnMut(&KernelVariant) -> bool,
+    ) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for (index, variant) in self.variants.iter().enumerate() {
+            if !variant.runs_on(cc) || !condition(variant) {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some(best) => {
+                    let best = &self.variants[best];
+                    let key = |v: &KernelVariant| (v.arch, v.condition.is_some(), v.cubin);
+                    key(variant) > key(best)
+                }
+            };
+            if better {
+                best = Some(index);
+            }
+        }
+        best
+    }
+}
+
+/// The compute capability of the `.target` directive of PTX, such as `(8, 6)` for `sm_86`.
+fn ptx_target(ptx: &str) -> Option<(u32, u32)> {
+    let target = ptx
+        .lines()
+        .find_map(|line| line.trim().strip_prefix(".target"))?;
+    let arch = target
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .find_map(|word| word.strip_prefix("sm_"))?;
+    // `sm_90a` and such have a suffix after the number.
+    let digits = arch.trim_end_matches(|c: char| !c.is_ascii_digit());
+    let number = digits.parse::<u32>().ok()?;
+    Some((number / 10, number % 10))
+}
+
 /// Handle to a symbol defined within a CUDA module.
 #[derive(Debug)]
 pub struct Symbol<'a, T: DeviceCopy> {
@@ -267,7 +1086,7 @@ impl<'a, T: DeviceCopy> CopyDestination<T> for Symbol<'a, T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyHtoD_v2(
+                default_stream::memcpy_htod(
                     self.ptr.as_raw_mut() as u64,
                     val as *const T as *const c_void,
                     size,
@@ -282,7 +1101,7 @@ impl<'a, T: DeviceCopy> CopyDestination<T> for Symbol<'a, T> {
         let size = mem::size_of::<T>();
         if size != 0 {
             unsafe {
-                cuda::cuMemcpyDtoH_v2(
+                default_stream::memcpy_dtoh(
                     val as *const T as *mut c_void,
                     self.ptr.as_raw() as u64,
                     size,
@@ -293,3 +1112,103 @@ impl<'a, T: DeviceCopy> CopyDestination<T> for Symbol<'a, T> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PTX: &str = "\
+.version 7.0
+.target sm_61
+.address_size 64
+
+.extern .const .align 4 .u32 OTHER;
+.visible .const .align 4 .u32 TILE_SIZE = 16;
+.visible .const .align 8 .b8 SCALE[8] = {0, 0, 0, 0, 0, 0, 240, 63};
+.visible .global .align 1 .u8 ENABLED;
+";
+
+    #[test]
+    fn reads_ptx_target() {
+        assert_eq!(ptx_target(PTX), Some((6, 1)));
+        assert_eq!(
+            ptx_target(".version 8.0\n.target sm_90a, debug\n"),
+            Some((9, 0))
+        );
+        assert_eq!(ptx_target(".version 8.0\n"), None);
+    }
+
+    #[test]
+    fn picks_highest_supported_arch() {
+        let set = KernelSet::new()
+            .variant(KernelVariant::ptx("generic", PTX))
+            .variant(KernelVariant::cubin("sm_80", [0u8], (8, 0)))
+            .variant(KernelVariant::ptx("sm_70", ".target sm_70\n"))
+            .variant(KernelVariant::ptx("sm_80_ptx", ".target sm_80\n"));
+        let name = |cc| set.pick(cc, |_| true).map(|i| set.variants[i].name());
+        assert_eq!(name((5, 2)), None);
+        assert_eq!(name((6, 1)), Some("generic"));
+        assert_eq!(name((7, 5)), Some("sm_70"));
+        // the cubin needs no JIT compilation.
+        assert_eq!(name((8, 6)), Some("sm_80"));
+        // the cubin does not run on other major versions.
+        assert_eq!(name((9, 0)), Some("sm_80_ptx"));
+        assert_eq!(
+            set.pick((8, 6), |v| v.name() != "sm_80")
+                .map(|i| set.variants[i].name()),
+            Some("sm_80_ptx")
+        );
+    }
+
+    #[test]
+    fn picks_conditional_variants_first() {
+        let set = KernelSet::new()
+            .variant(KernelVariant::ptx("generic", PTX))
+            .variant(KernelVariant::cubin("sm_61", [0u8], (6, 1)))
+            .variant(KernelVariant::ptx("tuned", PTX).when(|_| true))
+            .variant(KernelVariant::ptx("sm_70", ".target sm_70\n"));
+        let name = |cc, holds: bool| {
+            set.pick(cc, |v| holds || v.condition.is_none())
+                .map(|i| set.variants[i].name())
+        };
+        assert_eq!(name((6, 1), true), Some("tuned"));
+        assert_eq!(name((6, 1), false), Some("sm_61"));
+        // the architecture still comes first.
+        assert_eq!(name((7, 5), true), Some("sm_70"));
+    }
+
+    #[test]
+    fn specializes_scalars_and_arrays() {
+        let ptx = Specialization::new()
+            .set("TILE_SIZE", 32u32)
+            .set("SCALE", 2.0f64)
+            .set("ENABLED", true)
+            .apply(PTX)
+            .unwrap();
+        assert!(ptx.contains(".visible .const .align 4 .b8 TILE_SIZE[4] = {32, 0, 0, 0};\n"));
+        assert!(
+            ptx.contains(".visible .const .align 8 .b8 SCALE[8] = {0, 0, 0, 0, 0, 0, 0, 64};\n")
+        );
+        assert!(ptx.contains(".visible .global .align 1 .b8 ENABLED[1] = {1};\n"));
+        assert!(ptx.contains(".extern .const .align 4 .u32 OTHER;\n"));
+        assert!(ptx.ends_with('\n'));
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let ptx = Specialization::new()
+            .set("TILE_SIZE", 32u32)
+            .set("TILE_SIZE", 8u32)
+            .apply(PTX)
+            .unwrap();
+        assert!(ptx.contains("TILE_SIZE[4] = {8, 0, 0, 0};"));
+    }
+
+    #[test]
+    fn rejects_missing_and_mismatched_constants() {
+        let missing = Specialization::new().set("OTHER", 1u32).apply(PTX);
+        assert_eq!(missing, Err(CudaError::NotFound));
+        let mismatched = Specialization::new().set("TILE_SIZE", 1u64).apply(PTX);
+        assert_eq!(mismatched, Err(CudaError::InvalidValue));
+    }
+}
@@ -0,0 +1,208 @@
+//! Stream-ordered pipelines for overlapping uploads, computation, and downloads.
+//!
+//! Processing a large amount of data on the GPU usually follows the same pattern: upload a chunk
+//! of data, run some kernels on it, then download the results. Doing this sequentially leaves the GPU
+//! idle while copying, and the copy engines idle while computing. A [`Pipeline`] splits the data into
+//! chunks and distributes them over multiple stages, each with its own stream and page-locked staging
+//! buffers, so that the upload of one chunk overlaps with the computation and download of the
+//! previous chunks. Two stages give double buffering, three stages give triple buffering, etc.
+
+use crate::error::CudaResult;
+use crate::event::{Event, EventFlags};
+use crate::memory::{AsyncCopyDestination, DeviceBuffer, DeviceCopy, DeviceSlice, LockedBuffer};
+use crate::stream::{Stream, StreamFlags};
+use std::ops::Range;
+
+/// A single stage of a pipeline, owning a stream and every buffer a chunk needs.
+#[derive(Debug)]
+struct Stage<I: DeviceCopy, O: DeviceCopy> {
+    stream: Stream,
+    done: Event,
+    host_input: LockedBuffer<I>,
+    device_input: DeviceBuffer<I>,
+    device_output: DeviceBuffer<O>,
+    host_output: LockedBuffer<O>,
+    /// The range of the output which this stage is currently computing, if any.
+    pending: Option<Range<usize>>,
+}
+
+impl<I: DeviceCopy, O: DeviceCopy> Stage<I, O> {
+    fn new(chunk_len: usize) -> CudaResult<Self> {
+        // SAFETY: the buffers are always written to before being read from.
+        unsafe {
+            Ok(Self {
+                stream: Stream::new(StreamFlags::NON_BLOCKING, None)?,
+                done: Event::new(EventFlags::DISABLE_TIMING)?,
+                host_input: LockedBuffer::uninitialized(chunk_len)?,
+                device_input: DeviceBuffer::uninitialized(chunk_len)?,
+                device_output: DeviceBuffer::uninitialized(chunk_len)?,
+                host_output: LockedBuffer::uninitialized(chunk_len)?,
+                pending: None,
+            })
+        }
+    }
+
+    /// Waits for the chunk this stage is working on (if any) and copies its results to `output`.
+    fn finish(&mut self, output: &mut [O]) -> CudaResult<()> {
+        if let Some(range) = self.pending.take() {
+            self.done.synchronize()?;
+            let len = range.len();
+            output[range].copy_from_slice(&self.host_output[..len]);
+        }
+        Ok(())
+    }
+}
+
+/// Processes data in chunks across multiple streams, overlapping the upload of a chunk
+/// with the computation and download of previous chunks.
+///
+/// Every element of the input maps to one element of the output, the computation for a chunk
+/// is provided as a closure which enqueues work (usually kernel launches) on the stage's stream.
+///
+/// # Example
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::pipeline::Pipeline;
+///
+/// let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+/// let func = module.get_function("sum")?;
+/// let input = vec![1.0f32; 1 << 24];
+/// let mut output = vec![0.0f32; 1 << 24];
+///
+/// // triple buffering with chunks of 1M elements.
+/// let mut pipeline = Pipeline::new(3, 1 << 20)?;
+/// pipeline.run(&input, &mut output, |stream, input, output| unsafe {
+///     let len = input.len();
+///     let x = input.as_device_ptr();
+///     // out = x + x
+///     launch!(func<<<(len as u32 + 255) / 256, 256, 0, stream>>>(
+///         x,
+///         x,
+///         output.as_device_ptr(),
+///         len as i32
+///     ))
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Pipeline<I: DeviceCopy, O: DeviceCopy> {
+    stages: Vec<Stage<I, O>>,
+    chunk_len: usize,
+}
+
+impl<I: DeviceCopy, O: DeviceCopy> Pipeline<I, O> {
+    /// Creates a new pipeline with `stages` stages (2 for double buffering, 3 for triple buffering, etc),
+    /// processing at most `chunk_len` elements at a time per stage.
+    ///
+    /// This allocates `stages` page-locked host buffers and device buffers of `chunk_len` elements for both
+    /// the input and the output.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `stages` or `chunk_len` is zero.
+    pub fn new(stages: usize, chunk_len: usize) -> CudaResult<Self> {
+        assert!(stages > 0, "a pipeline must have at least one stage");
+        assert!(
+            chunk_len > 0,
+            "the chunk length of a pipeline must not be zero"
+        );
+        let stages = (0..stages)
+            .map(|_| Stage::new(chunk_len))
+            .collect::<CudaResult<Vec<_>>>()?;
+        Ok(Self { stages, chunk_len })
+    }
+
+    /// The number of stages (and therefore streams) in this pipeline.
+    pub fn num_stages(&self) -> usize {
+        self.stages.len()
+    }
+
+    /// The maximum number of elements processed at once by a single stage.
+    pub fn chunk_len(&self) -> usize {
+        self.chunk_len
+    }
+
+    /// Processes `input` into `output` chunk by chunk. For every chunk, the input is uploaded,
+    /// `compute` is called with the stage's stream, the uploaded input, and the device output for the chunk,
+    /// then the output is downloaded. Chunks are processed in order but may overlap with each other.
+    ///
+    /// `compute` must only enqueue work on the given stream, it may also overwrite the uploaded input.
+    /// The input and output slices have the same length, which is at most [`chunk_len`](Self::chunk_len)
+    /// elements. This function returns once every chunk has been written to `output`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `input` and `output` have different lengths.
+    pub fn run<F>(&mut self, input: &[I], output: &mut [O], compute: F) -> CudaResult<()>
+    where
+        F: FnMut(&Stream, &mut DeviceSlice<I>, &mut DeviceSlice<O>) -> CudaResult<()>,
+    {
+        assert_eq!(
+            input.len(),
+            output.len(),
+            "input and output have different lengths"
+        );
+
+        let res = self.run_chunks(input, output, compute);
+        if res.is_err() {
+            // make sure no stage is left with work that would be copied into a different output later.
+            for stage in &mut self.stages {
+                stage.pending = None;
+                let _ = stage.stream.synchronize();
+            }
+        }
+        res
+    }
+
+    fn run_chunks<F>(&mut self, input: &[I], output: &mut [O], mut compute: F) -> CudaResult<()>
+    where
+        F: FnMut(&Stream, &mut DeviceSlice<I>, &mut DeviceSlice<O>) -> CudaResult<()>,
+    {
+        let num_stages = self.stages.len();
+        for (i, chunk) in input.chunks(self.chunk_len).enumerate() {
+            let start = i * self.chunk_len;
+            let len = chunk.len();
+            let stage = &mut self.stages[i % num_stages];
+            // the stage's buffers are about to be reused, so wait for its previous chunk first.
+            stage.finish(output)?;
+
+            stage.host_input[..len].copy_from_slice(chunk);
+            // SAFETY: the staging buffers are owned by the stage and are not touched by the host
+            // again until the `done` event has been waited on.
+            unsafe {
+                stage.device_input[..len]
+                    .async_copy_from(&stage.host_input[..len], &stage.stream)?;
+            }
+            compute(
+                &stage.stream,
+                &mut stage.device_input[..len],
+                &mut stage.device_output[..len],
+            )?;
+            unsafe {
+                stage.device_output[..len]
+                    .async_copy_to(&mut stage.host_output[..len], &stage.stream)?;
+            }
+            stage.done.record(&stage.stream)?;
+            stage.pending = Some(start..start + len);
+        }
+
+        for stage in &mut self.stages {
+            stage.finish(output)?;
+        }
+        Ok(())
+    }
+}
+
+impl<I: DeviceCopy, O: DeviceCopy> Drop for Pipeline<I, O> {
+    fn drop(&mut self) {
+        // copies may still be in flight if `compute` panicked, the buffers must outlive them.
+        for stage in &self.stages {
+            let _ = stage.stream.synchronize();
+        }
+    }
+}
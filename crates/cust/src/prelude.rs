@@ -5,9 +5,16 @@
 
 pub use crate::context::{Context, ContextFlags};
 pub use crate::device::Device;
+pub use crate::error::{CudaError, CudaResult};
+pub use crate::event::{Event, EventFlags};
+pub use crate::function::{BlockSize, Function, GridSize, LaunchConfig};
 pub use crate::launch;
-pub use crate::memory::{CopyDestination, DeviceBuffer, UnifiedBuffer};
+pub use crate::launch_with_timeout;
+pub use crate::memory::{
+    AsyncCopyDestination, CopyDestination, DeviceBox, DeviceBuffer, DeviceCopy, DeviceSlice,
+    LockedBuffer, UnifiedBuffer,
+};
 pub use crate::module::Module;
 pub use crate::stream::{Stream, StreamFlags};
 pub use crate::util::*;
-pub use crate::CudaFlags;
+pub use crate::{init_default, quick_init, CudaFlags, DeviceCopy};
@@ -9,11 +9,27 @@
 //! execute concurrently. Sequencing between multiple streams can be achieved using events, which
 //! are not currently supported by cust. Finally, the host can wait for all work scheduled in
 //! a stream to be completed.
+//!
+//! # Default streams
+//!
+//! CUDA also has two implicit streams, the legacy NULL stream ([`Stream::legacy_default`]) which
+//! synchronizes with every other blocking stream in the context, and a per-thread default stream
+//! ([`Stream::per_thread_default`]) which behaves like a regular stream owned by the calling thread.
+//! Synchronous memory operations (such as [`CopyDestination::copy_from`](crate::memory::CopyDestination::copy_from))
+//! use the legacy NULL stream, so multi-threaded programs may accidentally serialize on it. Enabling
+//! the `per-thread-default-stream` feature makes them use the per-thread default stream instead.
 
-use crate::error::{CudaResult, DropResult, ToResult};
+use crate::context::CurrentContext;
+use crate::driver_ext;
+use crate::error::{CudaError, CudaResult, DropResult, ToResult};
 use crate::event::Event;
-use crate::function::{BlockSize, Function, GridSize};
+use crate::function::{
+    BlockSize, ClusterSize, Function, GridSize, LaunchConfig, LaunchParams, MAX_PARAMS_SIZE,
+};
+use crate::memory::scratch::ScratchArena;
+use crate::memory::DeviceSlice;
 use crate::sys::{self as cuda, cudaError_enum, CUstream};
+use std::cell::RefCell;
 use std::ffi::c_void;
 use std::mem;
 use std::panic;
@@ -42,6 +58,12 @@ bitflags::bitflags! {
     }
 }
 
+impl Default for StreamFlags {
+    fn default() -> Self {
+        StreamFlags::DEFAULT
+    }
+}
+
 bitflags::bitflags! {
     /// Bit flags for configuring a CUDA Stream waiting on an CUDA Event.
     ///
@@ -52,12 +74,64 @@ bitflags::bitflags! {
     }
 }
 
+/// How memory accesses which hit or miss an [`AccessPolicyWindow`] are treated by the L2 cache.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum AccessProperty {
+    /// Normal cache persistence.
+    Normal = 0,
+    /// Accesses are less likely to persist in the L2 cache.
+    Streaming = 1,
+    /// Accesses are more likely to persist in the L2 cache, in the part of it set aside with
+    /// [`ResourceLimit::PersistingL2CacheSize`](crate::context::ResourceLimit::PersistingL2CacheSize).
+    Persisting = 2,
+}
+
+/// A range of device memory and the L2 cache policy used for accesses to it by kernels launched
+/// on a stream, see [`Stream::set_access_policy_window`].
+///
+/// A fraction `hit_ratio` of the accesses to the window are given the `hit_prop` property and the
+/// rest the `miss_prop` property. The window must not be larger than
+/// [`DeviceAttribute::MaxAccessPolicyWindowSize`](crate::device::DeviceAttribute::MaxAccessPolicyWindowSize).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AccessPolicyWindow {
+    /// The start of the window.
+    pub base_ptr: *mut c_void,
+    /// The size of the window in bytes.
+    pub num_bytes: usize,
+    /// The fraction of accesses given `hit_prop`, between `0.0` and `1.0`.
+    pub hit_ratio: f32,
+    /// The property of accesses in the `hit_ratio` fraction.
+    pub hit_prop: AccessProperty,
+    /// The property of the other accesses.
+    pub miss_prop: AccessProperty,
+}
+
+impl AccessPolicyWindow {
+    /// A window over `slice` whose accesses persist in the L2 cache with the given hit ratio, the
+    /// rest being streaming.
+    pub fn persisting<T>(slice: &DeviceSlice<T>, hit_ratio: f32) -> Self {
+        AccessPolicyWindow {
+            base_ptr: slice.as_ptr() as *mut c_void,
+            num_bytes: slice.len() * mem::size_of::<T>(),
+            hit_ratio,
+            hit_prop: AccessProperty::Persisting,
+            miss_prop: AccessProperty::Streaming,
+        }
+    }
+}
+
+// Special stream handles from cuda.h, these are not real streams and must never be destroyed.
+const CU_STREAM_LEGACY: CUstream = 0x1 as CUstream;
+const CU_STREAM_PER_THREAD: CUstream = 0x2 as CUstream;
+
 /// A stream of work for the device to perform.
 ///
 /// See the module-level documentation for more information.
 #[derive(Debug)]
 pub struct Stream {
     inner: CUstream,
+    scratch: RefCell<ScratchArena>,
 }
 impl Stream {
     /// Create a new stream with the given flags and optional priority.
@@ -91,6 +165,7 @@ impl Stream {
         unsafe {
             let mut stream = Stream {
                 inner: ptr::null_mut(),
+                scratch: Default::default(),
             };
             cuda::cuStreamCreateWithPriority(
                 &mut stream.inner as *mut CUstream,
@@ -102,6 +177,52 @@ impl Stream {
         }
     }
 
+    /// Returns a handle to the per-thread default stream of the calling thread.
+    ///
+    /// Work on this stream does not implicitly synchronize with other streams (except the legacy NULL stream),
+    /// and every host thread gets its own stream. The handle refers to whichever thread uses it, so
+    /// it should not be sent to other threads. Dropping it does nothing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::stream::Stream;
+    ///
+    /// let stream = Stream::per_thread_default();
+    /// stream.synchronize()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn per_thread_default() -> Self {
+        Stream {
+            inner: CU_STREAM_PER_THREAD,
+            scratch: Default::default(),
+        }
+    }
+
+    /// Returns a handle to the legacy NULL stream, which synchronizes with every other blocking stream
+    /// in the context. Dropping it does nothing.
+    pub fn legacy_default() -> Self {
+        Stream {
+            inner: CU_STREAM_LEGACY,
+            scratch: Default::default(),
+        }
+    }
+
+    /// Whether this is one of the implicit default streams, see [`Stream::per_thread_default`] and
+    /// [`Stream::legacy_default`].
+    pub fn is_default(&self) -> bool {
+        self.inner == CU_STREAM_LEGACY || self.inner == CU_STREAM_PER_THREAD
+    }
+
+    pub(crate) fn scratch_arena(&self) -> &RefCell<ScratchArena> {
+        &self.scratch
+    }
+
     /// Return the flags which were used to create this stream.
     ///
     /// # Examples
@@ -260,6 +381,94 @@ impl Stream {
         unsafe { cuda::cuStreamWaitEvent(self.inner, event.as_inner(), flags.bits()).to_result() }
     }
 
+    /// Returns whether every piece of work submitted to this stream has completed, without blocking.
+    ///
+    /// Errors from previous asynchronous work (such as a kernel accessing an invalid address) are
+    /// returned by this function.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::stream::{Stream, StreamFlags};
+    ///
+    /// let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+    /// stream.synchronize()?;
+    /// assert!(stream.is_complete()?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn is_complete(&self) -> CudaResult<bool> {
+        match unsafe { cuda::cuStreamQuery(self.inner).to_result() } {
+            Ok(()) => Ok(true),
+            Err(CudaError::NotReady) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the L2 cache policy used for accesses to a window of device memory by kernels
+    /// subsequently launched on this stream, replacing any previously set window.
+    ///
+    /// Persisting accesses only have an effect if some of the L2 cache was set aside with
+    /// [`ResourceLimit::PersistingL2CacheSize`](crate::context::ResourceLimit::PersistingL2CacheSize).
+    /// Lines which persist can be reset to normal with
+    /// [`CurrentContext::reset_persisting_l2_cache`](crate::context::CurrentContext::reset_persisting_l2_cache).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use cust::*;
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    /// # let _ctx = quick_init()?;
+    /// use cust::context::{CurrentContext, ResourceLimit};
+    /// use cust::memory::DeviceBuffer;
+    /// use cust::stream::{AccessPolicyWindow, Stream, StreamFlags};
+    ///
+    /// let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+    /// let lookup = DeviceBuffer::from_slice(&[0u32; 1024])?;
+    /// CurrentContext::set_resource_limit(ResourceLimit::PersistingL2CacheSize, 4096)?;
+    /// stream.set_access_policy_window(&AccessPolicyWindow::persisting(&lookup, 1.0))?;
+    /// // launch kernels reading `lookup` ...
+    /// stream.clear_access_policy_window()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn set_access_policy_window(&self, window: &AccessPolicyWindow) -> CudaResult<()> {
+        let value = cuda::CUstreamAttrValue {
+            accessPolicyWindow: cuda::CUaccessPolicyWindow {
+                base_ptr: window.base_ptr,
+                num_bytes: window.num_bytes,
+                hitRatio: window.hit_ratio,
+                hitProp: unsafe { mem::transmute(window.hit_prop) },
+                missProp: unsafe { mem::transmute(window.miss_prop) },
+            },
+        };
+        unsafe {
+            cuda::cuStreamSetAttribute(
+                self.inner,
+                cuda::CUstreamAttrID::CU_STREAM_ATTRIBUTE_ACCESS_POLICY_WINDOW,
+                &value,
+            )
+            .to_result()
+        }
+    }
+
+    /// Removes the access policy window of this stream, so that accesses by kernels subsequently
+    /// launched on it are treated normally.
+    pub fn clear_access_policy_window(&self) -> CudaResult<()> {
+        self.set_access_policy_window(&AccessPolicyWindow {
+            base_ptr: ptr::null_mut(),
+            num_bytes: 0,
+            hit_ratio: 0.0,
+            hit_prop: AccessProperty::Normal,
+            miss_prop: AccessProperty::Normal,
+        })
+    }
+
     // Hidden implementation detail function. Highly unsafe. Use the `launch!` macro instead.
     #[doc(hidden)]
     pub unsafe fn launch<G, B>(
@@ -276,6 +485,14 @@ impl Stream {
     {
         let grid_size: GridSize = grid_size.into();
         let block_size: BlockSize = block_size.into();
+        let _span = trace_span!(
+            "cust::launch",
+            stream = self.inner as usize,
+            grid = ?(grid_size.x, grid_size.y, grid_size.z),
+            block = ?(block_size.x, block_size.y, block_size.z),
+            shared_mem_bytes,
+        );
+        debug_validate(grid_size, block_size, shared_mem_bytes)?;
 
         cuda::cuLaunchKernel(
             func.to_raw(),
@@ -293,6 +510,109 @@ impl Stream {
         .to_result()
     }
 
+    /// Launches a kernel which takes a single [`LaunchParams`] struct by value on this stream.
+    /// The parameters are copied when the launch is enqueued, so `params` may be reused right
+    /// away.
+    ///
+    /// # Safety
+    ///
+    /// The only parameter of `func` must be of type `P`, and the launch must otherwise be safe in
+    /// the same way as with the `launch!` macro (the pointers in `params` must be valid for the
+    /// way the kernel uses them).
+    pub unsafe fn launch_params<G, B, P>(
+        &self,
+        func: &Function,
+        grid_size: G,
+        block_size: B,
+        shared_mem_bytes: u32,
+        params: &P,
+    ) -> CudaResult<()>
+    where
+        G: Into<GridSize>,
+        B: Into<BlockSize>,
+        P: LaunchParams,
+    {
+        // generic structs are only checked here, the derive checks the others at compile time.
+        if mem::size_of::<P>() > MAX_PARAMS_SIZE {
+            return Err(CudaError::InvalidValue);
+        }
+        self.launch(
+            func,
+            grid_size,
+            block_size,
+            shared_mem_bytes,
+            &[params as *const P as *mut c_void],
+        )
+    }
+
+    /// Launches a kernel on this stream with the grid split into thread block clusters of
+    /// `cluster_size` blocks. This is the launch equivalent of CUDA C++'s `cudaLaunchKernelEx` with
+    /// a cluster dimension attribute, kernels which declare their cluster size at compile time
+    /// can be launched normally instead.
+    ///
+    /// Clusters require compute capability 9.0 and a driver supporting CUDA 12.0 or newer, on older
+    /// drivers this returns [`CudaError::NotSupported`]. Returns
+    /// [`CudaError::InvalidLaunchConfiguration`] if the grid is not a multiple of the cluster size.
+    ///
+    /// # Safety
+    ///
+    /// The same as [`Stream::launch`], the arguments must match the kernel's parameters.
+    pub unsafe fn launch_cluster<G, B, C>(
+        &self,
+        func: &Function,
+        grid_size: G,
+        block_size: B,
+        cluster_size: C,
+        shared_mem_bytes: u32,
+        args: &[*mut c_void],
+    ) -> CudaResult<()>
+    where
+        G: Into<GridSize>,
+        B: Into<BlockSize>,
+        C: Into<ClusterSize>,
+    {
+        let grid_size: GridSize = grid_size.into();
+        let block_size: BlockSize = block_size.into();
+        let cluster_size: ClusterSize = cluster_size.into();
+        let _span = trace_span!(
+            "cust::launch",
+            stream = self.inner as usize,
+            grid = ?(grid_size.x, grid_size.y, grid_size.z),
+            block = ?(block_size.x, block_size.y, block_size.z),
+            cluster = ?(cluster_size.x, cluster_size.y, cluster_size.z),
+            shared_mem_bytes,
+        );
+
+        if !cluster_size.divides(grid_size) {
+            return Err(CudaError::InvalidLaunchConfiguration);
+        }
+        debug_validate(grid_size, block_size, shared_mem_bytes)?;
+        let mut attr = driver_ext::CUlaunchAttribute {
+            id: driver_ext::CU_LAUNCH_ATTRIBUTE_CLUSTER_DIMENSION,
+            pad: [0; 4],
+            value: [0; 16],
+        };
+        attr.value[..3].copy_from_slice(&[cluster_size.x, cluster_size.y, cluster_size.z]);
+        let config = driver_ext::CUlaunchConfig {
+            gridDimX: grid_size.x,
+            gridDimY: grid_size.y,
+            gridDimZ: grid_size.z,
+            blockDimX: block_size.x,
+            blockDimY: block_size.y,
+            blockDimZ: block_size.z,
+            sharedMemBytes: shared_mem_bytes,
+            hStream: self.inner,
+            attrs: &mut attr,
+            numAttrs: 1,
+        };
+        driver_ext::cuLaunchKernelEx(
+            &config,
+            func.to_raw(),
+            args.as_ptr() as *mut _,
+            ptr::null_mut(),
+        )
+    }
+
     // Get the inner `CUstream` from the `Stream`. If you use this handle elsewhere,
     // make sure not to use it after the stream has been dropped. Or ManuallyDrop the struct to be safe.
     pub fn as_inner(&self) -> CUstream {
@@ -325,7 +645,7 @@ impl Stream {
     /// # }
     /// ```
     pub fn drop(mut stream: Stream) -> DropResult<Stream> {
-        if stream.inner.is_null() {
+        if stream.inner.is_null() || stream.is_default() {
             return Ok(());
         }
 
@@ -333,17 +653,35 @@ impl Stream {
             let inner = mem::replace(&mut stream.inner, ptr::null_mut());
             match cuda::cuStreamDestroy_v2(inner).to_result() {
                 Ok(()) => {
+                    drop(stream.scratch.take());
                     mem::forget(stream);
                     Ok(())
                 }
-                Err(e) => Err((e, Stream { inner })),
+                Err(e) => Err((
+                    e,
+                    Stream {
+                        inner,
+                        scratch: mem::take(&mut stream.scratch),
+                    },
+                )),
             }
         }
     }
 }
+
+/// Checks a launch against the limits of the current device in debug builds, which is worth a few
+/// attribute queries there to get [`CudaError::InvalidLaunchConfiguration`] instead of the unspecific
+/// error of the driver. See [`LaunchConfig::validate`].
+fn debug_validate(grid: GridSize, block: BlockSize, shared_mem_bytes: u32) -> CudaResult<()> {
+    if cfg!(debug_assertions) {
+        LaunchConfig::new(grid, block, shared_mem_bytes).validate(CurrentContext::get_device()?)?;
+    }
+    Ok(())
+}
+
 impl Drop for Stream {
     fn drop(&mut self) {
-        if self.inner.is_null() {
+        if self.inner.is_null() || self.is_default() {
             return;
         }
 
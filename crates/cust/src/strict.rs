@@ -0,0 +1,115 @@
+//! Detection of implicitly synchronizing operations, enabled by the `strict-async` feature.
+//!
+//! Some operations block the calling thread or serialize the GPU without it being obvious from the
+//! code, which shows up as unexplained stalls in pipelines that are meant to be asynchronous:
+//!
+//! - Synchronous memory operations such as [`CopyDestination`](crate::memory::CopyDestination)
+//!   copies and [`DeviceBuffer::from_slice`](crate::memory::DeviceBuffer::from_slice), which run on
+//!   the default stream and wait for it (and with the legacy NULL stream, for every other blocking
+//!   stream).
+//! - Asynchronous copies from or to pageable (not page-locked) host memory, which the driver
+//!   stages through a pinned buffer, blocking the calling thread until the copy is done. Use a
+//!   [`LockedBuffer`](crate::memory::LockedBuffer) or
+//!   [`RegisteredBuffer`](crate::memory::RegisteredBuffer) for asynchronous copies.
+//!
+//! With the `strict-async` feature, cust panics when it performs one of these operations, naming
+//! it, so that the backtrace points at the culprit. Setting the `CUST_STRICT_ASYNC` environment
+//! variable to `log` prints a message to stderr instead. Setup and teardown code which
+//! synchronizes on purpose can be wrapped in [`allow_implicit_sync`].
+//!
+//! Without the feature, none of the checks are done and [`allow_implicit_sync`] just calls its
+//! closure.
+
+#[cfg(feature = "strict-async")]
+use std::cell::Cell;
+use std::ffi::c_void;
+
+#[cfg(feature = "strict-async")]
+std::thread_local! {
+    static ALLOWED: Cell<u32> = Cell::new(0);
+}
+
+/// Runs `f` without reporting the implicitly synchronizing operations it does on this thread.
+///
+/// ```
+/// # let _context = cust::quick_init().unwrap();
+/// use cust::memory::DeviceBuffer;
+///
+/// // uploading the inputs once at startup is fine.
+/// let input = cust::strict::allow_implicit_sync(|| DeviceBuffer::from_slice(&[1.0f32; 1024]))
+///     .unwrap();
+/// ```
+pub fn allow_implicit_sync<R>(f: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "strict-async")]
+    {
+        struct Restore;
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                ALLOWED.with(|allowed| allowed.set(allowed.get() - 1));
+            }
+        }
+
+        ALLOWED.with(|allowed| allowed.set(allowed.get() + 1));
+        let _restore = Restore;
+        f()
+    }
+    #[cfg(not(feature = "strict-async"))]
+    f()
+}
+
+/// Reports an implicitly synchronizing operation, `operation` describes it for the message.
+#[cfg(feature = "strict-async")]
+pub(crate) fn implicit_sync(operation: &str) {
+    use once_cell::sync::Lazy;
+
+    static LOG: Lazy<bool> = Lazy::new(|| {
+        std::env::var("CUST_STRICT_ASYNC")
+            .map(|mode| mode.eq_ignore_ascii_case("log"))
+            .unwrap_or(false)
+    });
+
+    if ALLOWED.with(|allowed| allowed.get()) > 0 {
+        return;
+    }
+    if *LOG {
+        eprintln!(
+            "cust: implicitly synchronizing operation: {} (strict-async)",
+            operation
+        );
+    } else {
+        panic!(
+            "implicitly synchronizing operation: {} (reported because of the `strict-async` \
+             feature, wrap it in `cust::strict::allow_implicit_sync` if it is intended)",
+            operation
+        );
+    }
+}
+
+#[cfg(not(feature = "strict-async"))]
+#[inline(always)]
+pub(crate) fn implicit_sync(_operation: &str) {}
+
+/// Reports an asynchronous copy from or to `host` if it is pageable memory, which makes the copy
+/// synchronous.
+#[cfg(feature = "strict-async")]
+pub(crate) fn check_pinned(host: *const c_void, operation: &str) {
+    use crate::sys::{self as cuda, CUpointer_attribute};
+
+    let mut memory_type = 0u32;
+    // the driver only knows about page-locked and device memory, it rejects pageable pointers.
+    let known = unsafe {
+        cuda::cuPointerGetAttribute(
+            &mut memory_type as *mut u32 as *mut c_void,
+            CUpointer_attribute::CU_POINTER_ATTRIBUTE_MEMORY_TYPE,
+            host as cuda::CUdeviceptr,
+        )
+    };
+    if known != cuda::cudaError_enum::CUDA_SUCCESS {
+        implicit_sync(&format!("{} with pageable host memory", operation));
+    }
+}
+
+#[cfg(not(feature = "strict-async"))]
+#[inline(always)]
+pub(crate) fn check_pinned(_host: *const c_void, _operation: &str) {}
@@ -0,0 +1,316 @@
+//! Tensor memory accelerator (TMA) descriptors, which describe a tiled view of a tensor in global
+//! memory that kernels can copy tiles of to and from shared memory in a single instruction, with
+//! `cuda_std::tma`. Requires compute capability 9.0 and a driver supporting CUDA 12.0 or newer.
+//!
+//! A [`TensorMap`] is encoded on the host then copied to device memory, from where kernels use it:
+//!
+//! ```no_run
+//! # use cust::*;
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! # let _ctx = quick_init()?;
+//! use cust::memory::{DeviceBox, DeviceBuffer};
+//! use cust::tensor_map::{TensorMap, TensorMapSwizzle};
+//!
+//! let (width, height) = (1024, 768);
+//! let mut image = DeviceBuffer::from_slice(&vec![0.0f32; width * height])?;
+//! // 64x32 tiles of a row-major `width * height` image.
+//! let map = TensorMap::tiled(image.as_device_ptr(), &[width as u64, height as u64], &[64, 32])
+//!     .swizzle(TensorMapSwizzle::B128)
+//!     .encode()?;
+//! let map = DeviceBox::new(&map)?;
+//! // pass `map.as_device_ptr()` to the kernel ...
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::driver_ext::{self, CUtensorMap};
+use crate::error::{CudaError, CudaResult};
+use crate::memory::{DeviceCopy, DevicePointer};
+use std::ffi::c_void;
+use std::mem;
+
+/// An encoded TMA descriptor, an opaque 128 byte structure which must be in device memory (such as
+/// in a [`DeviceBox`](crate::memory::DeviceBox)) when used by a kernel.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TensorMap(CUtensorMap);
+
+unsafe impl DeviceCopy for TensorMap {}
+
+/// The type of the elements of a tensor, see [`TensorMapElement`].
+#[repr(u32)]
+#[non_exhaustive]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TensorMapDataType {
+    U8 = 0,
+    U16 = 1,
+    U32 = 2,
+    I32 = 3,
+    U64 = 4,
+    I64 = 5,
+    F16 = 6,
+    F32 = 7,
+    F64 = 8,
+    Bf16 = 9,
+    /// `f32` with denormals flushed to zero.
+    F32Ftz = 10,
+    /// `f32` rounded to TF32 when copied.
+    Tf32 = 11,
+    /// `f32` rounded to TF32 with denormals flushed to zero when copied.
+    Tf32Ftz = 12,
+}
+
+/// Types which can be the elements of a tensor described by a [`TensorMap`].
+pub trait TensorMapElement: DeviceCopy {
+    /// The data type used for tensors of this type, can be overridden with
+    /// [`TiledTensorMap::data_type`], for example for half-precision floats stored as `u16`.
+    const DATA_TYPE: TensorMapDataType;
+}
+
+macro_rules! impl_tensor_map_element {
+    ($($ty:ty => $data_type:ident),* $(,)?) => {
+        $(
+            impl TensorMapElement for $ty {
+                const DATA_TYPE: TensorMapDataType = TensorMapDataType::$data_type;
+            }
+        )*
+    };
+}
+
+impl_tensor_map_element! {
+    u8 => U8,
+    u16 => U16,
+    u32 => U32,
+    i32 => I32,
+    u64 => U64,
+    i64 => I64,
+    f32 => F32,
+    f64 => F64,
+}
+
+/// Interleaving of the innermost dimension for tensors in `NC/8HWC8`-like layouts.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TensorMapInterleave {
+    None = 0,
+    B16 = 1,
+    B32 = 2,
+}
+
+/// How tiles are swizzled in shared memory to avoid bank conflicts, the innermost dimension of
+/// the tile must be at most the swizzle span in bytes.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TensorMapSwizzle {
+    None = 0,
+    B32 = 1,
+    B64 = 2,
+    B128 = 3,
+}
+
+/// The size of the L2 cache requests made when copying tiles.
+#[repr(u32)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum TensorMapL2Promotion {
+    None = 0,
+    B64 = 1,
+    B128 = 2,
+    B256 = 3,
+}
+
+impl TensorMap {
+    /// The maximum number of dimensions of a tensor.
+    pub const MAX_RANK: usize = 5;
+
+    /// Starts building a descriptor for copying tiles of `box_dims` elements out of a tensor of
+    /// `dims` elements starting at `ptr`, both innermost dimension first. The tensor is packed by
+    /// default, see [`TiledTensorMap::strides`].
+    pub fn tiled<T: TensorMapElement>(
+        ptr: DevicePointer<T>,
+        dims: &[u64],
+        box_dims: &[u32],
+    ) -> TiledTensorMap {
+        TiledTensorMap {
+            ptr: ptr.as_raw() as *mut c_void,
+            data_type: T::DATA_TYPE,
+            element_size: mem::size_of::<T>() as u64,
+            dims: dims.to_vec(),
+            strides: None,
+            box_dims: box_dims.to_vec(),
+            element_strides: None,
+            interleave: TensorMapInterleave::None,
+            swizzle: TensorMapSwizzle::None,
+            l2_promotion: TensorMapL2Promotion::None,
+            oob_nan: false,
+        }
+    }
+}
+
+/// Builder for a [`TensorMap`] of a tiled tensor, created with [`TensorMap::tiled`].
+#[derive(Debug, Clone)]
+pub struct TiledTensorMap {
+    ptr: *mut c_void,
+    data_type: TensorMapDataType,
+    element_size: u64,
+    dims: Vec<u64>,
+    strides: Option<Vec<u64>>,
+    box_dims: Vec<u32>,
+    element_strides: Option<Vec<u32>>,
+    interleave: TensorMapInterleave,
+    swizzle: TensorMapSwizzle,
+    l2_promotion: TensorMapL2Promotion,
+    oob_nan: bool,
+}
+
+impl TiledTensorMap {
+    /// Sets the strides in bytes of every dimension except the innermost one, which must be
+    /// multiples of 16.
+    pub fn strides(mut self, strides: &[u64]) -> Self {
+        self.strides = Some(strides.to_vec());
+        self
+    }
+
+    /// Sets the number of elements to step over in every dimension when copying a tile, which
+    /// defaults to 1 for every dimension.
+    pub fn element_strides(mut self, element_strides: &[u32]) -> Self {
+        self.element_strides = Some(element_strides.to_vec());
+        self
+    }
+
+    /// Overrides the data type of the elements, which must have the same size as the type of the
+    /// pointer.
+    pub fn data_type(mut self, data_type: TensorMapDataType) -> Self {
+        self.data_type = data_type;
+        self
+    }
+
+    pub fn interleave(mut self, interleave: TensorMapInterleave) -> Self {
+        self.interleave = interleave;
+        self
+    }
+
+    pub fn swizzle(mut self, swizzle: TensorMapSwizzle) -> Self {
+        self.swizzle = swizzle;
+        self
+    }
+
+    pub fn l2_promotion(mut self, l2_promotion: TensorMapL2Promotion) -> Self {
+        self.l2_promotion = l2_promotion;
+        self
+    }
+
+    /// Fills out of bounds elements of floating point tensors with NaN instead of zero.
+    pub fn oob_fill_nan(mut self, nan: bool) -> Self {
+        self.oob_nan = nan;
+        self
+    }
+
+    /// The strides of the tensor, the explicit ones or the ones of a packed tensor.
+    fn global_strides(&self) -> Vec<u64> {
+        match &self.strides {
+            Some(strides) => strides.clone(),
+            None => self
+                .dims
+                .iter()
+                .scan(self.element_size, |stride, dim| {
+                    *stride *= dim;
+                    Some(*stride)
+                })
+                .take(self.dims.len().saturating_sub(1))
+                .collect(),
+        }
+    }
+
+    /// Checks the parts of the descriptor which would otherwise make the driver read out of
+    /// bounds, the driver checks the rest.
+    fn check(&self, strides: &[u64], element_strides: &[u32]) -> CudaResult<()> {
+        let rank = self.dims.len();
+        if rank == 0
+            || rank > TensorMap::MAX_RANK
+            || self.box_dims.len() != rank
+            || strides.len() != rank - 1
+            || element_strides.len() != rank
+        {
+            return Err(CudaError::InvalidValue);
+        }
+        Ok(())
+    }
+
+    /// Encodes the descriptor, returning [`CudaError::InvalidValue`] if the dimensions, strides or
+    /// alignment are invalid and [`CudaError::NotSupported`] if the driver does not support TMA.
+    pub fn encode(&self) -> CudaResult<TensorMap> {
+        let strides = self.global_strides();
+        let element_strides = match &self.element_strides {
+            Some(element_strides) => element_strides.clone(),
+            None => vec![1; self.dims.len()],
+        };
+        self.check(&strides, &element_strides)?;
+
+        let mut map = CUtensorMap { opaque: [0; 16] };
+        unsafe {
+            driver_ext::cuTensorMapEncodeTiled(
+                &mut map,
+                self.data_type as u32,
+                self.dims.len() as u32,
+                self.ptr,
+                self.dims.as_ptr(),
+                strides.as_ptr(),
+                self.box_dims.as_ptr(),
+                element_strides.as_ptr(),
+                self.interleave as u32,
+                self.swizzle as u32,
+                self.l2_promotion as u32,
+                self.oob_nan as u32,
+            )?;
+        }
+        Ok(TensorMap(map))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn builder(dims: &[u64], box_dims: &[u32]) -> TiledTensorMap {
+        TensorMap::tiled(
+            unsafe { DevicePointer::wrap(0x1000 as *mut f32) },
+            dims,
+            box_dims,
+        )
+    }
+
+    #[test]
+    fn packed_strides() {
+        assert_eq!(builder(&[100], &[8]).global_strides(), Vec::<u64>::new());
+        assert_eq!(
+            builder(&[100, 20, 3], &[8, 8, 1]).global_strides(),
+            vec![400, 8000]
+        );
+        let explicit = builder(&[100, 20], &[8, 8]).strides(&[512]);
+        assert_eq!(explicit.global_strides(), vec![512]);
+    }
+
+    #[test]
+    fn rejects_mismatched_ranks() {
+        assert_eq!(
+            builder(&[], &[]).encode().unwrap_err(),
+            CudaError::InvalidValue
+        );
+        assert_eq!(
+            builder(&[100, 20], &[8]).encode().unwrap_err(),
+            CudaError::InvalidValue
+        );
+        assert_eq!(
+            builder(&[1; 6], &[1; 6]).encode().unwrap_err(),
+            CudaError::InvalidValue
+        );
+        assert_eq!(
+            builder(&[100, 20], &[8, 8])
+                .element_strides(&[1])
+                .encode()
+                .unwrap_err(),
+            CudaError::InvalidValue
+        );
+    }
+}
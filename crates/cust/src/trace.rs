@@ -0,0 +1,54 @@
+//! Optional instrumentation with [`tracing`](https://docs.rs/tracing), enabled by the `tracing`
+//! feature.
+//!
+//! Context creation, module loads, allocations, copies and launches are wrapped in `DEBUG` spans
+//! (named after the operation, like `cust::launch`) which record sizes in bytes, launch
+//! dimensions, and the handle of the stream the operation is queued on (`0` for the legacy
+//! default stream). Without the feature, the macros below expand to nothing.
+
+/// Enters a span for the rest of the enclosing scope, used as `let _span = trace_span!(...);`.
+#[cfg(feature = "tracing")]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        ::tracing::debug_span!($($args)*).entered()
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_span {
+    ($($args:tt)*) => {
+        $crate::trace::NoSpan
+    };
+}
+
+/// Emits a `DEBUG` event.
+#[cfg(feature = "tracing")]
+macro_rules! trace_event {
+    ($($args:tt)*) => {
+        ::tracing::debug!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! trace_event {
+    ($($args:tt)*) => {};
+}
+
+/// Emits a `WARN` event, only used by `capture` for now.
+#[cfg(feature = "tracing")]
+#[allow(unused_macros)]
+macro_rules! trace_warn {
+    ($($args:tt)*) => {
+        ::tracing::warn!($($args)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+#[allow(unused_macros)]
+macro_rules! trace_warn {
+    ($($args:tt)*) => {};
+}
+
+/// Stand-in for an entered span when tracing is disabled.
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct NoSpan;
@@ -0,0 +1,448 @@
+//! Automatic tuning of launch configurations.
+//!
+//! The fastest block size for a kernel depends on the kernel, the device, and often the size of
+//! the problem, so it is usually found by trying them. A [`Tuner`] benchmarks a kernel with every
+//! combination of a set of block sizes, shared memory carveouts and kernel variants, and caches
+//! the fastest one per device in a file so that later runs can skip the benchmark.
+//!
+//! Variants are kernels in the same module which compute the same thing in different ways,
+//! usually instantiations of the same generic function with different unroll factors or tile
+//! sizes, generated with a macro on the GPU side:
+//!
+//! ```ignore
+//! fn sum_impl<const UNROLL: usize>(x: &[f32], out: *mut f32) { /* ... */ }
+//!
+//! macro_rules! sum_variants {
+//!     ($($name:ident = $unroll:literal),*) => {$(
+//!         #[kernel]
+//!         pub unsafe fn $name(x: &[f32], out: *mut f32) {
+//!             sum_impl::<$unroll>(x, out)
+//!         }
+//!     )*};
+//! }
+//!
+//! sum_variants!(sum_1 = 1, sum_2 = 2, sum_4 = 4);
+//! ```
+//!
+//! The launch closure given to [`Tuner::tune`] receives the function and the [`Candidate`] being
+//! benchmarked and must launch it once, computing the grid for the candidate's block size (and
+//! unroll factor, if it matters):
+//!
+//! ```
+//! # use cust::*;
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//! # let _ctx = quick_init()?;
+//! use cust::function::LaunchConfig;
+//! use cust::memory::*;
+//! use cust::module::Module;
+//! use cust::stream::{Stream, StreamFlags};
+//! use cust::tune::Tuner;
+//!
+//! let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+//! let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+//! let len = 1 << 16;
+//! let mut x = DeviceBuffer::from_slice(&vec![1.0f32; len])?;
+//! let mut y = DeviceBuffer::from_slice(&vec![2.0f32; len])?;
+//! let mut out = DeviceBuffer::from_slice(&vec![0.0f32; len])?;
+//!
+//! let tuned = Tuner::new(format!("sum/{}", len), "sum")
+//!     .block_sizes([64, 128, 256, 512])
+//!     .tune(&module, &stream, |function, candidate, stream| unsafe {
+//!         let config = LaunchConfig::for_len(len, candidate.block.x);
+//!         launch!(function<<<config.grid, config.block, 0, stream>>>(
+//!             x.as_device_ptr(),
+//!             y.as_device_ptr(),
+//!             out.as_device_ptr(),
+//!             len
+//!         ))
+//!     })?;
+//! println!("{} is fastest with blocks of {}", tuned.variant, tuned.block.x);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::context::CurrentContext;
+use crate::error::{CudaError, CudaResult};
+use crate::event::{Event, EventFlags};
+use crate::function::{BlockSize, Function, FunctionAttribute};
+use crate::module::Module;
+use crate::stream::Stream;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// One point of the space a [`Tuner`] searches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate<'a> {
+    /// The name of the kernel variant.
+    pub variant: &'a str,
+    pub block: BlockSize,
+    /// The preferred shared memory carveout in percent of the maximum shared memory, or `None`
+    /// for the default.
+    pub carveout: Option<u32>,
+}
+
+/// The fastest configuration found by a [`Tuner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tuned {
+    /// The name of the kernel variant.
+    pub variant: String,
+    pub block: BlockSize,
+    /// The preferred shared memory carveout in percent of the maximum shared memory, or `None`
+    /// for the default. It is already set on the variant's function.
+    pub carveout: Option<u32>,
+    /// The average time of a launch.
+    pub time: Duration,
+    /// Whether this configuration was read from the cache instead of being benchmarked.
+    pub cached: bool,
+}
+
+impl Tuned {
+    /// Gets the function of the fastest variant from `module`.
+    pub fn function<'a>(&self, module: &'a Module) -> CudaResult<Function<'a>> {
+        module.get_function(&self.variant)
+    }
+}
+
+/// Benchmarks a kernel across a space of launch configurations, see the [module
+/// documentation](self).
+#[derive(Debug, Clone)]
+pub struct Tuner {
+    key: String,
+    variants: Vec<String>,
+    block_sizes: Vec<BlockSize>,
+    carveouts: Vec<Option<u32>>,
+    warmup: u32,
+    iterations: u32,
+    cache: Option<PathBuf>,
+    profile: Option<PathBuf>,
+}
+
+impl Tuner {
+    /// Creates a tuner for the kernel `variant`, trying one-dimensional blocks of 64 to 1024
+    /// threads by default.
+    ///
+    /// `key` identifies the tuned configuration in the cache, it should include anything the
+    /// best configuration depends on, such as the size of the problem.
+    pub fn new(key: impl Into<String>, variant: impl Into<String>) -> Self {
+        Self {
+            key: key.into(),
+            variants: vec![variant.into()],
+            block_sizes: [64, 128, 256, 512, 1024]
+                .iter()
+                .map(|&x| BlockSize::x(x))
+                .collect(),
+            carveouts: vec![None],
+            warmup: 1,
+            iterations: 5,
+            cache: None,
+            profile: None,
+        }
+    }
+
+    /// Sets the kernel variants to try, replacing the one given to [`Tuner::new`].
+    pub fn variants<S: Into<String>>(mut self, variants: impl IntoIterator<Item = S>) -> Self {
+        self.variants = variants.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the block sizes to try. Block sizes which are too large for a variant are skipped.
+    pub fn block_sizes<B: Into<BlockSize>>(mut self, sizes: impl IntoIterator<Item = B>) -> Self {
+        self.block_sizes = sizes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the preferred shared memory carveouts to try, in percent of the maximum shared memory
+    /// of the device. By default only the driver's default carveout is used.
+    pub fn carveouts(mut self, percents: impl IntoIterator<Item = u32>) -> Self {
+        self.carveouts = percents.into_iter().map(Some).collect();
+        self
+    }
+
+    /// Sets how many untimed launches are made before timing a candidate, and how many timed
+    /// launches are averaged. Defaults to 1 and 5.
+    pub fn iterations(mut self, warmup: u32, iterations: u32) -> Self {
+        self.warmup = warmup;
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    /// Caches the fastest configuration of every device in `path`. Configurations in the cache
+    /// are used without benchmarking as long as their variant is still one of the variants being
+    /// tuned. Failing to read or write the cache is not an error.
+    pub fn cache_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Records the block size of the fastest configuration as the `max_threads` of its variant in the
+    /// tuning profile at `path`, a TOML file which `cuda_builder::CudaBuilder::tuning_profile` applies
+    /// to the next build of the kernels. ptxas then allocates registers for that block size instead of
+    /// the largest possible one, which can remove spills or raise occupancy, and tuning again after
+    /// rebuilding checks that the configuration is still the fastest.
+    ///
+    /// Only configurations which were benchmarked are recorded, other entries of the profile are kept.
+    /// Failing to read or write the profile is not an error.
+    pub fn profile_file(mut self, path: impl AsRef<Path>) -> Self {
+        self.profile = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Benchmarks every candidate on `stream` and returns the fastest one, or the cached one for
+    /// the current device.
+    ///
+    /// `launch` must launch the function once for the given candidate. Candidates which fail to
+    /// launch because the configuration is invalid or needs too many resources are skipped, other
+    /// errors are returned, and [`CudaError::InvalidLaunchConfiguration`] is returned if no
+    /// candidate could be launched. Every candidate is launched several times, so the kernel must
+    /// be fine with running repeatedly on the same data.
+    pub fn tune<F>(&self, module: &Module, stream: &Stream, mut launch: F) -> CudaResult<Tuned>
+    where
+        F: FnMut(&Function, &Candidate, &Stream) -> CudaResult<()>,
+    {
+        let device = CurrentContext::get_device()?.uuid_string()?;
+        if let Some(tuned) = self.read_cache(&device) {
+            let mut function = tuned.function(module)?;
+            function.set_shared_memory_carveout(tuned.carveout)?;
+            return Ok(tuned);
+        }
+
+        let start = Event::new(EventFlags::DEFAULT)?;
+        let end = Event::new(EventFlags::DEFAULT)?;
+        let mut best: Option<Tuned> = None;
+        for variant in &self.variants {
+            let mut function = module.get_function(variant)?;
+            let max_threads = function.get_attribute(FunctionAttribute::MaxThreadsPerBlock)?;
+            for &block in &self.block_sizes {
+                if block.threads() > max_threads as u64 {
+                    continue;
+                }
+                for &carveout in &self.carveouts {
+                    function.set_shared_memory_carveout(carveout)?;
+                    let candidate = Candidate {
+                        variant,
+                        block,
+                        carveout,
+                    };
+                    let time =
+                        match self.time(&function, &candidate, stream, &start, &end, &mut launch) {
+                            Ok(time) => time,
+                            Err(CudaError::InvalidValue)
+                            | Err(CudaError::LaunchOutOfResources)
+                            | Err(CudaError::InvalidLaunchConfiguration) => continue,
+                            Err(e) => return Err(e),
+                        };
+                    let faster = match &best {
+                        Some(best) => time < best.time,
+                        None => true,
+                    };
+                    if faster {
+                        best = Some(Tuned {
+                            variant: variant.clone(),
+                            block,
+                            carveout,
+                            time,
+                            cached: false,
+                        });
+                    }
+                }
+            }
+            // leave the variant with the default carveout unless it is the best one.
+            function.set_shared_memory_carveout(None)?;
+        }
+
+        let best = best.ok_or(CudaError::InvalidLaunchConfiguration)?;
+        best.function(module)?
+            .set_shared_memory_carveout(best.carveout)?;
+        self.write_cache(&device, &best);
+        if let Some(path) = &self.profile {
+            let existing = fs::read_to_string(path).unwrap_or_default();
+            let threads = best.block.threads() as u32;
+            let profile = set_profile_value(&existing, &best.variant, "max_threads", threads);
+            let _ = fs::write(path, profile);
+        }
+        Ok(best)
+    }
+
+    fn time<F>(
+        &self,
+        function: &Function,
+        candidate: &Candidate,
+        stream: &Stream,
+        start: &Event,
+        end: &Event,
+        launch: &mut F,
+    ) -> CudaResult<Duration>
+    where
+        F: FnMut(&Function, &Candidate, &Stream) -> CudaResult<()>,
+    {
+        for _ in 0..self.warmup {
+            launch(function, candidate, stream)?;
+        }
+        start.record(stream)?;
+        for _ in 0..self.iterations {
+            launch(function, candidate, stream)?;
+        }
+        end.record(stream)?;
+        end.synchronize()?;
+        Ok(end.elapsed(start)? / self.iterations)
+    }
+
+    fn read_cache(&self, device: &str) -> Option<Tuned> {
+        let cache = fs::read_to_string(self.cache.as_ref()?).ok()?;
+        cache.lines().find_map(|line| {
+            let fields = line.split('\t').collect::<Vec<_>>();
+            match fields[..] {
+                [entry_device, key, variant, block, carveout, nanos]
+                    if entry_device == device
+                        && key == self.key
+                        && self.variants.iter().any(|v| v == variant) =>
+                {
+                    let block = block
+                        .split(',')
+                        .map(|dim| dim.parse().ok())
+                        .collect::<Option<Vec<u32>>>()?;
+                    let block = match block[..] {
+                        [x, y, z] => BlockSize::xyz(x, y, z),
+                        _ => return None,
+                    };
+                    Some(Tuned {
+                        variant: variant.to_string(),
+                        block,
+                        carveout: carveout.parse().ok(),
+                        time: Duration::from_nanos(nanos.parse().ok()?),
+                        cached: true,
+                    })
+                }
+                _ => None,
+            }
+        })
+    }
+
+    fn write_cache(&self, device: &str, tuned: &Tuned) {
+        let path = match &self.cache {
+            Some(path) => path,
+            None => return,
+        };
+        let existing = fs::read_to_string(path).unwrap_or_default();
+        let mut lines = existing
+            .lines()
+            .filter(|line| {
+                let mut fields = line.split('\t');
+                !(fields.next() == Some(device) && fields.next() == Some(self.key.as_str()))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let carveout = tuned.carveout.map_or("-".to_string(), |c| c.to_string());
+        lines.push(format!(
+            "{}\t{}\t{}\t{},{},{}\t{}\t{}",
+            device,
+            self.key,
+            tuned.variant,
+            tuned.block.x,
+            tuned.block.y,
+            tuned.block.z,
+            carveout,
+            tuned.time.as_nanos()
+        ));
+        let _ = fs::write(path, lines.join("\n") + "\n");
+    }
+}
+
+/// Sets `key = value` in the `[kernels.<kernel>]` table of a tuning profile, adding the table if needed.
+fn set_profile_value(profile: &str, kernel: &str, key: &str, value: u32) -> String {
+    let header = format!("[kernels.{}]", kernel);
+    let entry = format!("{} = {}", key, value);
+    let mut lines = profile.lines().map(str::to_string).collect::<Vec<_>>();
+    match lines.iter().position(|line| line.trim() == header) {
+        Some(start) => {
+            let end = lines[start + 1..]
+                .iter()
+                .position(|line| line.trim_start().starts_with('['))
+                .map_or(lines.len(), |i| start + 1 + i);
+            let existing = lines[start + 1..end]
+                .iter()
+                .position(|line| line.split('=').next().map(str::trim) == Some(key));
+            match existing {
+                Some(i) => lines[start + 1 + i] = entry,
+                None => lines.insert(start + 1, entry),
+            }
+        }
+        None => {
+            if lines.last().map_or(false, |line| !line.trim().is_empty()) {
+                lines.push(String::new());
+            }
+            lines.push(header);
+            lines.push(entry);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::function::LaunchConfig;
+    use crate::memory::DeviceBuffer;
+    use crate::stream::StreamFlags;
+    use std::error::Error;
+
+    #[test]
+    fn test_set_profile_value() {
+        let profile = set_profile_value("", "sum_4", "max_threads", 256);
+        assert_eq!(profile, "[kernels.sum_4]\nmax_threads = 256\n");
+        let profile = set_profile_value(&profile, "scan", "max_threads", 128);
+        assert_eq!(
+            profile,
+            "[kernels.sum_4]\nmax_threads = 256\n\n[kernels.scan]\nmax_threads = 128\n"
+        );
+        let edited = "[kernels.sum_4]\nmax_registers = 32\nmax_threads = 256\n\n[kernels.scan]\n";
+        let profile = set_profile_value(edited, "sum_4", "max_threads", 512);
+        assert_eq!(
+            profile,
+            "[kernels.sum_4]\nmax_registers = 32\nmax_threads = 512\n\n[kernels.scan]\n"
+        );
+        let profile = set_profile_value(&profile, "scan", "max_threads", 64);
+        assert_eq!(
+            profile,
+            "[kernels.sum_4]\nmax_registers = 32\nmax_threads = 512\n\n[kernels.scan]\nmax_threads = 64\n"
+        );
+    }
+
+    #[test]
+    fn test_tune_and_cache() -> Result<(), Box<dyn Error>> {
+        let _context = crate::quick_init()?;
+        let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        let len = 4096;
+        let mut x = DeviceBuffer::from_slice(&vec![1.0f32; len])?;
+        let mut out = DeviceBuffer::from_slice(&vec![0.0f32; len])?;
+
+        let cache = std::env::temp_dir().join(format!("cust-tune-{}.txt", std::process::id()));
+        let tuner = Tuner::new("test", "sum")
+            .block_sizes([32, 128, 4096])
+            .iterations(0, 2)
+            .cache_file(&cache);
+        let mut tune = || {
+            tuner.tune(&module, &stream, |function, candidate, stream| unsafe {
+                let config = LaunchConfig::for_len(len, candidate.block.x);
+                crate::launch!(function<<<config.grid, config.block, 0, stream>>>(
+                    x.as_device_ptr(),
+                    x.as_device_ptr(),
+                    out.as_device_ptr(),
+                    len
+                ))
+            })
+        };
+
+        let tuned = tune()?;
+        assert!(!tuned.cached);
+        assert!(tuned.block.x == 32 || tuned.block.x == 128);
+        let cached = tune()?;
+        fs::remove_file(&cache)?;
+        assert!(cached.cached);
+        assert_eq!(cached.block, tuned.block);
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+//! Launching kernels with a timeout, for detecting hung kernels.
+//!
+//! A kernel which never finishes (for example, because of an infinite loop or a deadlock between
+//! threads) makes every synchronous wait on its stream block forever. [`launch_with_timeout`] instead
+//! waits for the kernel by polling it with `cuStreamQuery` (see [`Stream::is_complete`]), which never
+//! blocks, and gives up once the timeout expires, returning a [`HangReport`] with information about
+//! the launch.
+//!
+//! Note that CUDA has no way of killing a single kernel. After a timeout the kernel is still running
+//! and keeps its stream (and any memory it uses) busy. The only way to stop it is to destroy its
+//! context, usually by recreating it through a [`ContextManager`](crate::context::ContextManager).
+//! On devices which drive a display, the OS watchdog may also kill long-running kernels on its own,
+//! in which case the launch fails with [`CudaError::LaunchTimeout`].
+
+use crate::error::CudaError;
+use crate::event::{Event, EventFlags, EventStatus};
+pub use crate::function::LaunchConfig;
+
+use crate::function::Function;
+use crate::stream::Stream;
+use std::error::Error;
+use std::ffi::c_void;
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The longest time the watchdog sleeps between two queries of the stream.
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Information about a kernel launch which did not finish in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HangReport {
+    /// The configuration the kernel was launched with.
+    pub config: LaunchConfig,
+    /// The timeout which expired.
+    pub timeout: Duration,
+    /// Whether the kernel actually started running. If this is `false`, the kernel was still waiting
+    /// for previous work on the stream to finish, so the hang is not necessarily in this kernel.
+    pub started: bool,
+    /// Roughly how long the kernel has been running for, if it started.
+    pub running_for: Option<Duration>,
+}
+
+impl fmt::Display for HangReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LaunchConfig {
+            grid,
+            block,
+            shared_mem_bytes,
+        } = self.config;
+        write!(
+            f,
+            "kernel launched with grid ({}, {}, {}), block ({}, {}, {}) and {} bytes of shared memory ",
+            grid.x, grid.y, grid.z, block.x, block.y, block.z, shared_mem_bytes
+        )?;
+        if self.started {
+            write!(f, "did not finish within {:?}", self.timeout)
+        } else {
+            write!(
+                f,
+                "did not start within {:?}, previous work on the stream is still running",
+                self.timeout
+            )
+        }
+    }
+}
+
+/// The error returned by [`launch_with_timeout`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum LaunchTimeoutError {
+    /// The launch or the kernel failed.
+    Cuda(CudaError),
+    /// The kernel did not finish in time, it may still be running.
+    TimedOut(HangReport),
+}
+
+impl fmt::Display for LaunchTimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LaunchTimeoutError::Cuda(e) => write!(f, "{}", e),
+            LaunchTimeoutError::TimedOut(report) => write!(f, "{}", report),
+        }
+    }
+}
+
+impl Error for LaunchTimeoutError {}
+
+impl From<CudaError> for LaunchTimeoutError {
+    fn from(e: CudaError) -> Self {
+        LaunchTimeoutError::Cuda(e)
+    }
+}
+
+/// Launches a kernel on `stream` and waits for at most `timeout` for it (and every previous piece of
+/// work on the stream) to finish, returning how long the kernel took on the device.
+///
+/// Prefer the [`launch_with_timeout!`](crate::launch_with_timeout) macro, which checks the arguments
+/// like [`launch!`](crate::launch).
+///
+/// # Safety
+///
+/// The same as [`launch!`](crate::launch), `args` must match the parameters of the kernel.
+pub unsafe fn launch_with_timeout(
+    stream: &Stream,
+    func: &Function,
+    config: LaunchConfig,
+    args: &[*mut c_void],
+    timeout: Duration,
+) -> Result<Duration, LaunchTimeoutError> {
+    let start = Event::new(EventFlags::DEFAULT)?;
+    let end = Event::new(EventFlags::DEFAULT)?;
+    start.record(stream)?;
+    stream.launch(
+        func,
+        config.grid,
+        config.block,
+        config.shared_mem_bytes,
+        args,
+    )?;
+    end.record(stream)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut interval = Duration::from_micros(10);
+    // when the kernel was first seen running, only accurate to the polling interval.
+    let mut started_at = None;
+    // the stream is queried instead of the end event so that errors from the kernel are reported.
+    while !stream.is_complete()? {
+        let now = Instant::now();
+        if started_at.is_none() && start.query()? == EventStatus::Ready {
+            started_at = Some(now);
+        }
+        if now >= deadline {
+            return Err(LaunchTimeoutError::TimedOut(HangReport {
+                config,
+                timeout,
+                started: started_at.is_some(),
+                running_for: started_at.map(|t| now - t),
+            }));
+        }
+        thread::sleep(interval.min(deadline - now));
+        interval = (interval * 2).min(MAX_POLL_INTERVAL);
+    }
+    Ok(end.elapsed(&start)?)
+}
+
+/// Launches a kernel with a timeout, see [`watchdog::launch_with_timeout`](crate::watchdog::launch_with_timeout).
+///
+/// The syntax is the same as [`launch!`](crate::launch) followed by the timeout.
+///
+/// # Example
+///
+/// ```no_run
+/// # use cust::*;
+/// # use std::error::Error;
+/// # use std::time::Duration;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # let _ctx = quick_init()?;
+/// use cust::watchdog::LaunchTimeoutError;
+///
+/// let module = Module::from_str(include_str!("../resources/add.ptx"))?;
+/// let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+/// let mut x = DeviceBuffer::from_slice(&[10.0f32; 256])?;
+/// let mut result = DeviceBuffer::from_slice(&[0.0f32; 256])?;
+///
+/// let res = unsafe {
+///     launch_with_timeout!(module.sum<<<1, 256, 0, stream>>>(
+///         x.as_device_ptr(),
+///         x.as_device_ptr(),
+///         result.as_device_ptr(),
+///         256i32
+///     ), Duration::from_secs(1))
+/// };
+/// match res {
+///     Ok(time) => println!("kernel took {:?}", time),
+///     Err(LaunchTimeoutError::TimedOut(report)) => eprintln!("{}", report),
+///     Err(e) => return Err(e.into()),
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[macro_export]
+macro_rules! launch_with_timeout {
+    ($module:ident . $function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* $(,)?), $timeout:expr) => {
+        {
+            let function = $module.get_function(stringify!($function));
+            match function {
+                Ok(f) => $crate::launch_with_timeout!(f<<<$grid, $block, $shared, $stream>>>( $($arg),* ), $timeout),
+                Err(e) => Err($crate::watchdog::LaunchTimeoutError::Cuda(e)),
+            }
+        }
+    };
+    ($function:ident <<<$grid:expr, $block:expr, $shared:expr, $stream:ident>>>( $( $arg:expr),* $(,)?), $timeout:expr) => {
+        $crate::with_kernel_params!(
+            params => $crate::watchdog::launch_with_timeout(
+                &$stream,
+                &$function,
+                $crate::watchdog::LaunchConfig::new($grid, $block, $shared),
+                &params,
+                $timeout,
+            );
+            [] $($arg,)*
+        )
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hang_report_mentions_config() {
+        let report = HangReport {
+            config: LaunchConfig::new(4, 128, 0),
+            timeout: Duration::from_millis(5),
+            started: true,
+            running_for: None,
+        };
+        let msg = report.to_string();
+        assert!(msg.contains("grid (4, 1, 1)"));
+        assert!(msg.contains("block (128, 1, 1)"));
+    }
+}
@@ -0,0 +1,93 @@
+use proc_macro2::{Ident, TokenStream};
+use std::env;
+use std::path::PathBuf;
+use syn::LitStr;
+
+/// Items of the generated module which kernel getters must not shadow.
+const RESERVED: &[&str] = &["PTX", "MODULE", "module"];
+
+pub(crate) fn expand(name: LitStr) -> TokenStream {
+    let value = name.value();
+    // a bare name refers to a PTX file in OUT_DIR (written there by cuda_builder), anything else
+    // is a path relative to the crate root, like include_str! but without depending on the
+    // location of the invoking file.
+    let path =
+        if value.ends_with(".ptx") || value.contains('/') {
+            match env::var("CARGO_MANIFEST_DIR") {
+                Ok(dir) => PathBuf::from(dir).join(&value),
+                Err(_) => return error(&name, "CARGO_MANIFEST_DIR is not set"),
+            }
+        } else {
+            match env::var("OUT_DIR") {
+                Ok(dir) => PathBuf::from(dir).join(format!("{}.ptx", value)),
+                Err(_) => return error(
+                    &name,
+                    "OUT_DIR is not set, the crate must have a build script which builds the PTX",
+                ),
+            }
+        };
+    let ptx = match std::fs::read_to_string(&path) {
+        Ok(ptx) => ptx,
+        Err(e) => return error(&name, &format!("failed to read {}: {}", path.display(), e)),
+    };
+
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().replace('-', "_"))
+        .unwrap_or_default();
+    let module_name = match syn::parse_str::<Ident>(&stem) {
+        Ok(ident) => ident,
+        Err(_) => return error(&name, &format!("`{}` is not a valid module name", stem)),
+    };
+
+    let getters = entries(&ptx).into_iter().filter_map(|entry| {
+        if RESERVED.contains(&entry.as_str()) {
+            return None;
+        }
+        let ident = syn::parse_str::<Ident>(&entry).ok()?;
+        let doc = format!("Returns the `{}` kernel from the module.", entry);
+        Some(quote! {
+            #[doc = #doc]
+            pub fn #ident() -> ::cust::error::CudaResult<::cust::function::Function<'static>> {
+                MODULE.get()?.get_function(#entry)
+            }
+        })
+    });
+
+    let path = path.to_string_lossy();
+    let doc = format!("Kernels from `{}`.", value);
+    quote! {
+        #[doc = #doc]
+        pub mod #module_name {
+            /// The embedded PTX.
+            pub const PTX: &str = include_str!(#path);
+
+            static MODULE: ::cust::module::LazyModule = ::cust::module::LazyModule::new(PTX);
+
+            /// Returns the module, loading it into the current context if it was not loaded into it
+            /// yet.
+            pub fn module() -> ::cust::error::CudaResult<&'static ::cust::module::Module> {
+                MODULE.get()
+            }
+
+            #(#getters)*
+        }
+    }
+}
+
+/// Names of the kernels (`.entry` directives) defined in some PTX.
+fn entries(ptx: &str) -> Vec<String> {
+    ptx.lines()
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            tokens.find(|&t| t == ".entry")?;
+            let name = tokens.next()?;
+            let name = name.split('(').next()?;
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .collect()
+}
+
+fn error(name: &LitStr, msg: &str) -> TokenStream {
+    syn::Error::new(name.span(), msg).to_compile_error()
+}
@@ -6,12 +6,14 @@ extern crate syn;
 
 use proc_macro2::{Ident, Span, TokenStream};
 use syn::{
-    parse_str, Data, DataEnum, DataStruct, DataUnion, DeriveInput, Field, Fields, Generics,
-    TypeParamBound,
+    parse_str, Data, DataEnum, DataStruct, DataUnion, DeriveInput, Field, Fields, Generics, Meta,
+    NestedMeta, Type, TypeParamBound,
 };
 
 use proc_macro::TokenStream as BaseTokenStream;
 
+mod include_ptx;
+
 #[proc_macro_derive(DeviceCopy)]
 pub fn derive_device_copy(input: BaseTokenStream) -> BaseTokenStream {
     let ast = syn::parse(input).unwrap();
@@ -19,6 +21,54 @@ pub fn derive_device_copy(input: BaseTokenStream) -> BaseTokenStream {
     BaseTokenStream::from(gen)
 }
 
+/// Implements `cust::function::LaunchParams` for a `#[repr(C)]` struct, so that it can be passed to
+/// a kernel as its only parameter with `Stream::launch_params`. Its fields must be raw pointers
+/// (to device memory) or `DeviceCopy`, and it is checked at compile time to fit in the kernel
+/// parameter space.
+///
+/// ```ignore
+/// #[repr(C)]
+/// #[derive(Clone, Copy)]
+/// #[cfg_attr(not(target_os = "cuda"), derive(cust::LaunchParams))]
+/// pub struct BlurParams {
+///     pub input: *const f32,
+///     pub output: *mut f32,
+///     pub width: u32,
+///     pub height: u32,
+///     pub radius: f32,
+/// }
+/// ```
+#[proc_macro_derive(LaunchParams)]
+pub fn derive_launch_params(input: BaseTokenStream) -> BaseTokenStream {
+    let ast = syn::parse(input).unwrap();
+    let gen = impl_launch_params(&ast);
+    BaseTokenStream::from(gen)
+}
+
+/// Embeds a PTX file and generates a module with getters for its kernels, which load the PTX into
+/// the current context the first time they are used (see `cust::module::LazyModule`).
+///
+/// A bare name refers to `$OUT_DIR/<name>.ptx`, which can be written by the build script with
+/// `CudaBuilder::copy_to`. Anything else is a path to a `.ptx` file relative to the crate root.
+/// The generated module is named after the file.
+///
+/// ```ignore
+/// // build.rs
+/// let out = std::path::Path::new(&std::env::var("OUT_DIR").unwrap()).join("kernels.ptx");
+/// CudaBuilder::new("../gpu/my_gpu_crate").copy_to(out).build().unwrap();
+///
+/// // main.rs
+/// cust::include_ptx!("kernels");
+///
+/// let _ctx = cust::quick_init()?;
+/// let render = kernels::render()?;
+/// ```
+#[proc_macro]
+pub fn include_ptx(input: BaseTokenStream) -> BaseTokenStream {
+    let name = syn::parse_macro_input!(input as syn::LitStr);
+    BaseTokenStream::from(include_ptx::expand(name))
+}
+
 fn impl_device_copy(input: &DeriveInput) -> TokenStream {
     let input_type = &input.ident;
 
@@ -60,6 +110,86 @@ fn impl_device_copy(input: &DeriveInput) -> TokenStream {
     generated_code
 }
 
+fn impl_launch_params(input: &DeriveInput) -> TokenStream {
+    let input_type = &input.ident;
+
+    let data_struct = match input.data {
+        Data::Struct(ref data_struct) => data_struct,
+        _ => {
+            return syn::Error::new_spanned(
+                input_type,
+                "LaunchParams can only be derived for structs",
+            )
+            .to_compile_error()
+        }
+    };
+    // the kernel reads the parameters with the layout it was compiled with, which is only the same
+    // as the host's for repr(C) (or a transparent wrapper of something which is).
+    let has_stable_layout = input.attrs.iter().any(|attr| match attr.parse_meta() {
+        Ok(Meta::List(list)) if list.path.is_ident("repr") => list.nested.iter().any(|nested| {
+            matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident("C") || path.is_ident("transparent"))
+        }),
+        _ => false,
+    });
+    if !has_stable_layout {
+        return syn::Error::new_spanned(
+            input_type,
+            "LaunchParams structs must be #[repr(C)] so the host and the kernel agree on their layout",
+        )
+        .to_compile_error();
+    }
+
+    // raw pointers are allowed in parameters (they are how kernels get at their buffers), every
+    // other field must be DeviceCopy, which is checked like in the DeviceCopy derive.
+    let checks = data_struct
+        .fields
+        .iter()
+        .filter(|field| !matches!(field.ty, Type::Ptr(_)))
+        .map(|field| {
+            let field_type = &field.ty;
+            quote! {assert_impl::<#field_type>();}
+        });
+    let type_test_func_ident = Ident::new(
+        &format!(
+            "__verify_{}_can_implement_launchparams",
+            input_type.to_string().to_lowercase()
+        ),
+        Span::call_site(),
+    );
+
+    let generics = add_bound_to_generics(&input.generics);
+    let (impl_generics, type_generics, where_clause) = generics.split_for_impl();
+
+    // the size of a generic struct depends on its parameters, it is checked when launching instead.
+    let size_check = if input.generics.params.is_empty() {
+        let message = format!(
+            "`{}` does not fit in the kernel parameter space",
+            input_type
+        );
+        quote! {
+            const _: () = ::core::assert!(
+                ::core::mem::size_of::<#input_type>() <= ::cust::function::MAX_PARAMS_SIZE,
+                #message
+            );
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        unsafe impl#impl_generics ::cust::function::LaunchParams for #input_type#type_generics #where_clause {}
+
+        #[doc(hidden)]
+        #[allow(all)]
+        fn #type_test_func_ident#impl_generics(value: &#input_type#type_generics) #where_clause {
+            fn assert_impl<T: ::cust::memory::DeviceCopy>() {}
+            #(#checks)*
+        }
+
+        #size_check
+    }
+}
+
 fn add_bound_to_generics(generics: &Generics) -> Generics {
     let mut new_generics = generics.clone();
     let bound: TypeParamBound =
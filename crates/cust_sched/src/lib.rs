@@ -0,0 +1,280 @@
+//! Splitting work across multiple CUDA devices.
+//!
+//! A [`Scheduler`] owns a context and a stream for every device it uses. Work over an index range
+//! (pixels, rows of an image, elements of an array, ...) is split into one contiguous part per
+//! device, proportionally to the number of multiprocessors of every device so that a mix of fast
+//! and slow GPUs finishes at about the same time.
+//!
+//! Every device needs its own modules and buffers, which are created once with
+//! [`Scheduler::per_device`] and then given back to the closure of [`Scheduler::run`], which
+//! enqueues the work of a single device on its stream. The work of all devices then runs
+//! concurrently, and [`Scheduler::gather`] copies the parts of the result back into a single host
+//! buffer.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use cust::prelude::*;
+//! use cust_sched::Scheduler;
+//!
+//! cust::init(CudaFlags::empty())?;
+//! let sched = Scheduler::all_devices()?;
+//! let len = 1 << 24;
+//!
+//! // allocate room for the largest part on every device.
+//! let largest = sched.split(len).iter().map(|r| r.len()).max().unwrap_or(0);
+//! let mut states = sched.per_device(|_| {
+//!     let module = Module::from_str(include_str!("../../cust/resources/add.ptx"))?;
+//!     let out = unsafe { DeviceBuffer::<f32>::zeroed(largest)? };
+//!     Ok((module, out))
+//! })?;
+//!
+//! sched.run(len, &mut states, |worker, (module, out), range| {
+//!     let stream = worker.stream();
+//!     // ... launch kernels of `module` computing `range` into `out` on `stream` ...
+//!     Ok(())
+//! })?;
+//!
+//! let mut result = vec![0.0f32; len];
+//! sched.gather(&states, &mut result, |(_, out)| out)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use cust::{
+    context::{Context, ContextFlags, ContextStack},
+    device::DeviceAttribute,
+    error::CudaResult,
+    memory::{CopyDestination, DeviceCopy, DeviceSlice},
+    prelude::{Device, Stream, StreamFlags},
+};
+use std::ops::Range;
+
+/// A device used by a [`Scheduler`], with its own context and stream.
+#[derive(Debug)]
+pub struct Worker {
+    index: usize,
+    device: Device,
+    weight: u32,
+    // the stream must be destroyed before its context.
+    stream: Stream,
+    context: Context,
+}
+
+impl Worker {
+    /// The index of this worker in [`Scheduler::workers`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn device(&self) -> Device {
+        self.device
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// The stream the work of this device should be enqueued on.
+    pub fn stream(&self) -> &Stream {
+        &self.stream
+    }
+
+    /// The relative speed of this device, its number of multiprocessors.
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    /// Runs `f` with the context of this worker current, then restores the previous context.
+    pub fn with_context<R>(&self, f: impl FnOnce() -> CudaResult<R>) -> CudaResult<R> {
+        ContextStack::push(&self.context)?;
+        let res = f();
+        ContextStack::pop()?;
+        res
+    }
+}
+
+/// Splits work across multiple devices, see the [crate docs](crate).
+#[derive(Debug)]
+pub struct Scheduler {
+    workers: Vec<Worker>,
+}
+
+impl Scheduler {
+    /// Creates a context and a non-blocking stream for every device. The context current on this
+    /// thread is left unchanged.
+    ///
+    /// CUDA must have been initialized with [`cust::init`] already.
+    pub fn new(devices: &[Device]) -> CudaResult<Self> {
+        let workers = devices
+            .iter()
+            .enumerate()
+            .map(|(index, &device)| {
+                let context = Context::create_and_push(ContextFlags::SCHED_AUTO, device)?;
+                let stream = Stream::new(StreamFlags::NON_BLOCKING, None);
+                ContextStack::pop()?;
+                Ok(Worker {
+                    index,
+                    device,
+                    weight: device
+                        .get_attribute(DeviceAttribute::MultiprocessorCount)?
+                        .max(1) as u32,
+                    stream: stream?,
+                    context,
+                })
+            })
+            .collect::<CudaResult<Vec<_>>>()?;
+        Ok(Self { workers })
+    }
+
+    /// Creates a scheduler using every device in the system.
+    pub fn all_devices() -> CudaResult<Self> {
+        let devices = Device::devices()?.collect::<CudaResult<Vec<_>>>()?;
+        Self::new(&devices)
+    }
+
+    pub fn workers(&self) -> &[Worker] {
+        &self.workers
+    }
+
+    /// Splits `0..len` into one contiguous range per worker, proportional to the weight of every
+    /// worker. Some ranges may be empty if `len` is smaller than the number of workers.
+    pub fn split(&self, len: usize) -> Vec<Range<usize>> {
+        let weights = self.workers.iter().map(|w| w.weight).collect::<Vec<_>>();
+        split_weighted(len, &weights)
+    }
+
+    /// Creates per-device state, such as modules and buffers, by calling `f` for every worker
+    /// with its context current.
+    pub fn per_device<S>(&self, mut f: impl FnMut(&Worker) -> CudaResult<S>) -> CudaResult<Vec<S>> {
+        self.workers
+            .iter()
+            .map(|w| w.with_context(|| f(w)))
+            .collect()
+    }
+
+    /// Splits `0..len` with [`Scheduler::split`] and calls `f` for every worker with a non-empty
+    /// range, its state and its range, with the context of the worker current. Then waits for the
+    /// streams of all workers and returns the results of `f`, in the order of the workers.
+    ///
+    /// `f` should only enqueue asynchronous work on the stream of the worker, otherwise the devices
+    /// do not run concurrently.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one state per worker.
+    pub fn run<S, R>(
+        &self,
+        len: usize,
+        states: &mut [S],
+        mut f: impl FnMut(&Worker, &mut S, Range<usize>) -> CudaResult<R>,
+    ) -> CudaResult<Vec<R>> {
+        assert_eq!(
+            states.len(),
+            self.workers.len(),
+            "there must be one state per worker"
+        );
+        let ranges = self.split(len);
+        let mut results = Vec::with_capacity(self.workers.len());
+        for ((worker, state), range) in self.workers.iter().zip(states).zip(ranges) {
+            if !range.is_empty() {
+                results.push(worker.with_context(|| f(worker, state, range))?);
+            }
+        }
+        for worker in &self.workers {
+            worker.stream.synchronize()?;
+        }
+        Ok(results)
+    }
+
+    /// Copies the part of every worker back into `out`, after a [`Scheduler::run`] over
+    /// `out.len()` indices. The part of a worker is at the start of the buffer returned by `f` for
+    /// its state.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is not one state per worker or if a buffer is smaller than its part.
+    pub fn gather<S, T: DeviceCopy>(
+        &self,
+        states: &[S],
+        out: &mut [T],
+        f: impl FnMut(&S) -> &DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        let len = out.len();
+        self.gather_strided(len, states, out, f)
+    }
+
+    /// Like [`Scheduler::gather`], for work over `len` indices where every index produced
+    /// `out.len() / len` consecutive elements of `out`, such as the rows of an image.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `out.len()` is not a multiple of `len`, if there is not one state per worker, or
+    /// if a buffer is smaller than its part.
+    pub fn gather_strided<S, T: DeviceCopy>(
+        &self,
+        len: usize,
+        states: &[S],
+        out: &mut [T],
+        mut f: impl FnMut(&S) -> &DeviceSlice<T>,
+    ) -> CudaResult<()> {
+        assert_eq!(
+            states.len(),
+            self.workers.len(),
+            "there must be one state per worker"
+        );
+        if len == 0 {
+            return Ok(());
+        }
+        assert_eq!(
+            out.len() % len,
+            0,
+            "the output must have the same number of elements for every index"
+        );
+        let stride = out.len() / len;
+        for ((worker, state), range) in self.workers.iter().zip(states).zip(self.split(len)) {
+            if range.is_empty() {
+                continue;
+            }
+            let part = &mut out[range.start * stride..range.end * stride];
+            let buf = f(state);
+            worker.with_context(|| buf[..part.len()].copy_to(part))?;
+        }
+        Ok(())
+    }
+}
+
+/// Splits `0..len` into contiguous ranges with lengths proportional to `weights`.
+fn split_weighted(len: usize, weights: &[u32]) -> Vec<Range<usize>> {
+    let total = weights.iter().map(|&w| w as u128).sum::<u128>();
+    if total == 0 {
+        return weights.iter().map(|_| 0..0).collect();
+    }
+    let mut cumulative = 0u128;
+    let mut start = 0;
+    weights
+        .iter()
+        .map(|&w| {
+            cumulative += w as u128;
+            let end = (len as u128 * cumulative / total) as usize;
+            let range = start..end;
+            start = end;
+            range
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn split_covers_the_range() {
+        assert_eq!(split_weighted(10, &[1, 1]), vec![0..5, 5..10]);
+        assert_eq!(split_weighted(10, &[1, 3]), vec![0..2, 2..10]);
+        assert_eq!(split_weighted(7, &[80, 80, 40]), vec![0..2, 2..5, 5..7]);
+        assert_eq!(split_weighted(1, &[1, 1]), vec![0..0, 0..1]);
+        assert_eq!(split_weighted(5, &[]), vec![]);
+    }
+}
@@ -55,6 +55,36 @@ pub fn find_cuda_root() -> Option<PathBuf> {
     None
 }
 
+// Returns true if the given path contains libnvvm and libdevice, like the root of a CUDA
+// installation or an extracted `cuda_nvcc` redistributable archive.
+fn is_nvvm_root_path<P: AsRef<Path>>(path: P) -> bool {
+    path.as_ref().join("nvvm").join("libdevice").is_dir()
+}
+
+/// Finds the directory containing `nvvm/` (libnvvm and libdevice), which is all that is needed to
+/// build GPU crates, so that PTX can be built on machines without a full CUDA installation or a
+/// GPU, such as CI runners.
+///
+/// `CUDA_NVVM_ROOT` is checked first, it can point to a full toolkit or to an extracted `cuda_nvcc`
+/// archive from <https://developer.download.nvidia.com/compute/cuda/redist/>, which has the same
+/// layout. Otherwise the CUDA installation is used, as found by [`find_cuda_root`] or through the
+/// usual environment variables even if they point to a toolkit without headers.
+pub fn find_nvvm_root() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("CUDA_NVVM_ROOT") {
+        if is_nvvm_root_path(&path) {
+            return Some(path.into());
+        }
+    }
+
+    find_cuda_root().or_else(|| {
+        ["CUDA_PATH", "CUDA_ROOT", "CUDA_TOOLKIT_ROOT_DIR"]
+            .iter()
+            .filter_map(|name| env::var_os(name))
+            .find(|path| is_nvvm_root_path(path))
+            .map(PathBuf::from)
+    })
+}
+
 #[cfg(target_os = "windows")]
 pub fn find_cuda_lib_dirs() -> Vec<PathBuf> {
     if let Some(root_path) = find_cuda_root() {
@@ -179,8 +209,8 @@ pub fn find_libnvvm_bin_dir() -> String {
     if env::var("DOCS_RS").is_ok() {
         return String::new();
     }
-    find_cuda_root()
-        .expect("Failed to find CUDA ROOT, make sure the CUDA SDK is installed and CUDA_PATH or CUDA_ROOT are set!")
+    find_nvvm_root()
+        .expect("Failed to find libnvvm, make sure the CUDA SDK is installed and CUDA_PATH or CUDA_ROOT are set, or set CUDA_NVVM_ROOT!")
         .join("nvvm")
         .join("lib")
         .join("x64")
@@ -193,8 +223,8 @@ pub fn find_libnvvm_bin_dir() -> String {
     if env::var("DOCS_RS").is_ok() {
         return String::new();
     }
-    find_cuda_root()
-        .expect("Failed to find CUDA ROOT, make sure the CUDA SDK is installed and CUDA_PATH or CUDA_ROOT are set!")
+    find_nvvm_root()
+        .expect("Failed to find libnvvm, make sure the CUDA SDK is installed and CUDA_PATH or CUDA_ROOT are set, or set CUDA_NVVM_ROOT!")
         .join("nvvm")
         .join("lib64")
         .to_string_lossy()
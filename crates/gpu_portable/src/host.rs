@@ -0,0 +1,309 @@
+use bytemuck::Pod;
+use cust::{
+    context::{Context, ContextFlags},
+    error::CudaError,
+    function::Function,
+    memory::{CopyDestination, DeviceBuffer},
+    module::Module,
+    prelude::{Device, Stream, StreamFlags},
+    CudaFlags,
+};
+use std::{ffi::c_void, fmt};
+
+/// The API kernels are launched with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// CUDA through cust, running the PTX version of kernels.
+    Cuda,
+    /// wgpu, running the SPIR-V version of kernels on Vulkan, Metal or DX12.
+    #[cfg(feature = "wgpu-fallback")]
+    Wgpu,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Cuda(CudaError),
+    /// No CUDA device is available and no wgpu adapter was found (or the `wgpu-fallback` feature
+    /// is disabled).
+    NoDevice,
+    /// The SPIR-V module is not valid SPIR-V, its length is not a multiple of 4.
+    InvalidSpirv,
+    #[cfg(feature = "wgpu-fallback")]
+    Wgpu(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Cuda(err) => write!(f, "CUDA error: {}", err),
+            Error::NoDevice => f.write_str("No CUDA device or wgpu adapter is available"),
+            Error::InvalidSpirv => f.write_str("The SPIR-V module is not valid SPIR-V"),
+            #[cfg(feature = "wgpu-fallback")]
+            Error::Wgpu(err) => write!(f, "wgpu error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<CudaError> for Error {
+    fn from(err: CudaError) -> Self {
+        Error::Cuda(err)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// A buffer given to [`Gpu::launch`].
+pub enum Arg<'a> {
+    /// A buffer which is uploaded before the launch, a `&[T]` parameter of the kernel.
+    Input(&'a [u8]),
+    /// A buffer which is uploaded before the launch and copied back after it, a `&mut [T]`
+    /// parameter of the kernel.
+    Output(&'a mut [u8]),
+}
+
+impl<'a> Arg<'a> {
+    pub fn input<T: Pod>(data: &'a [T]) -> Self {
+        Arg::Input(bytemuck::cast_slice(data))
+    }
+
+    pub fn output<T: Pod>(data: &'a mut [T]) -> Self {
+        Arg::Output(bytemuck::cast_slice_mut(data))
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Arg::Input(data) => data,
+            Arg::Output(data) => data,
+        }
+    }
+}
+
+enum Inner {
+    Cuda {
+        // the module and stream must be dropped before the context.
+        module: Module,
+        stream: Stream,
+        _context: Context,
+    },
+    #[cfg(feature = "wgpu-fallback")]
+    Wgpu(wgpu_backend::WgpuBackend),
+}
+
+/// A GPU running portable kernels, see the [crate docs](crate).
+pub struct Gpu {
+    inner: Inner,
+}
+
+impl Gpu {
+    /// Uses the first CUDA device if there is one, otherwise the default wgpu adapter. `ptx` and
+    /// `spirv` are the same gpu crate compiled to PTX and to SPIR-V.
+    pub fn new(ptx: &str, spirv: &[u8]) -> Result<Self> {
+        match Self::cuda(ptx) {
+            Ok(gpu) => Ok(gpu),
+            #[cfg(feature = "wgpu-fallback")]
+            Err(_) => Self::wgpu(spirv),
+            #[cfg(not(feature = "wgpu-fallback"))]
+            Err(err) => {
+                let _ = spirv;
+                Err(err)
+            }
+        }
+    }
+
+    /// Uses the first CUDA device, failing if there is none.
+    pub fn cuda(ptx: &str) -> Result<Self> {
+        cust::init(CudaFlags::empty())?;
+        if Device::num_devices()? == 0 {
+            return Err(Error::NoDevice);
+        }
+        let device = Device::get_device(0)?;
+        let context = Context::create_and_push(ContextFlags::SCHED_AUTO, device)?;
+        let module = Module::from_str(ptx)?;
+        let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+        Ok(Self {
+            inner: Inner::Cuda {
+                module,
+                stream,
+                _context: context,
+            },
+        })
+    }
+
+    /// Uses the default wgpu adapter, even if there is a CUDA device.
+    #[cfg(feature = "wgpu-fallback")]
+    pub fn wgpu(spirv: &[u8]) -> Result<Self> {
+        Ok(Self {
+            inner: Inner::Wgpu(wgpu_backend::WgpuBackend::new(spirv)?),
+        })
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.inner {
+            Inner::Cuda { .. } => Backend::Cuda,
+            #[cfg(feature = "wgpu-fallback")]
+            Inner::Wgpu(_) => Backend::Wgpu,
+        }
+    }
+
+    /// Runs the kernel `name` over `len` indices and waits for it to finish, then copies every
+    /// [`Arg::Output`] back. The arguments must be in the order of the parameters of the kernel and
+    /// hold exactly `len` elements each.
+    pub fn launch(&self, name: &str, len: usize, args: &mut [Arg<'_>]) -> Result<()> {
+        if len == 0 {
+            return Ok(());
+        }
+        match &self.inner {
+            Inner::Cuda { module, stream, .. } => {
+                launch_cuda(module.get_function(name)?, stream, len, args)
+            }
+            #[cfg(feature = "wgpu-fallback")]
+            Inner::Wgpu(backend) => backend.launch(name, len, args),
+        }
+    }
+}
+
+fn launch_cuda(func: Function, stream: &Stream, len: usize, args: &mut [Arg<'_>]) -> Result<()> {
+    let mut buffers = args
+        .iter()
+        .map(|arg| DeviceBuffer::from_slice(arg.bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let (_, block_size) = func.suggested_launch_configuration(0, 0.into())?;
+    let grid_size = (len as u32 + block_size - 1) / block_size;
+
+    let mut len = len;
+    let mut ptrs = buffers
+        .iter_mut()
+        .map(|buf| buf.as_device_ptr())
+        .collect::<Vec<_>>();
+    let mut params = vec![&mut len as *mut usize as *mut c_void];
+    params.extend(ptrs.iter_mut().map(|ptr| ptr as *mut _ as *mut c_void));
+    unsafe {
+        stream.launch(&func, grid_size, block_size, 0, &params)?;
+    }
+    stream.synchronize()?;
+
+    for (arg, buf) in args.iter_mut().zip(&buffers) {
+        if let Arg::Output(data) = arg {
+            buf.copy_to(&mut **data)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "wgpu-fallback")]
+mod wgpu_backend {
+    use super::{Arg, Error, Result};
+    use wgpu::util::DeviceExt;
+
+    pub struct WgpuBackend {
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        module: wgpu::ShaderModule,
+    }
+
+    impl WgpuBackend {
+        pub fn new(spirv: &[u8]) -> Result<Self> {
+            if spirv.len() % 4 != 0 {
+                return Err(Error::InvalidSpirv);
+            }
+            let instance = wgpu::Instance::new(wgpu::Backends::all());
+            let adapter = pollster::block_on(
+                instance.request_adapter(&wgpu::RequestAdapterOptions::default()),
+            )
+            .ok_or(Error::NoDevice)?;
+            let (device, queue) = pollster::block_on(
+                adapter.request_device(&wgpu::DeviceDescriptor::default(), None),
+            )
+            .map_err(|e| Error::Wgpu(e.to_string()))?;
+            let module = device.create_shader_module(&wgpu::ShaderModuleDescriptor {
+                label: None,
+                source: wgpu::util::make_spirv(spirv),
+            });
+            Ok(Self {
+                device,
+                queue,
+                module,
+            })
+        }
+
+        pub fn launch(&self, name: &str, len: usize, args: &mut [Arg<'_>]) -> Result<()> {
+            let pipeline = self
+                .device
+                .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                    label: Some(name),
+                    layout: None,
+                    module: &self.module,
+                    entry_point: name,
+                });
+            let buffers = args
+                .iter()
+                .map(|arg| {
+                    self.device
+                        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                            label: None,
+                            contents: arg.bytes(),
+                            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                        })
+                })
+                .collect::<Vec<_>>();
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout: &pipeline.get_bind_group_layout(0),
+                entries: &buffers
+                    .iter()
+                    .enumerate()
+                    .map(|(i, buf)| wgpu::BindGroupEntry {
+                        binding: i as u32,
+                        resource: buf.as_entire_binding(),
+                    })
+                    .collect::<Vec<_>>(),
+            });
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                let groups = (len as u32 + crate::WORKGROUP_SIZE - 1) / crate::WORKGROUP_SIZE;
+                pass.dispatch(groups, 1, 1);
+            }
+            // storage buffers cannot be mapped, copy the outputs to buffers which can.
+            let staging = args
+                .iter()
+                .zip(&buffers)
+                .filter(|(arg, _)| matches!(arg, Arg::Output(_)))
+                .map(|(arg, buf)| {
+                    let size = arg.bytes().len() as u64;
+                    let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+                        label: None,
+                        size,
+                        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                        mapped_at_creation: false,
+                    });
+                    encoder.copy_buffer_to_buffer(buf, 0, &staging, 0, size);
+                    staging
+                })
+                .collect::<Vec<_>>();
+            self.queue.submit(Some(encoder.finish()));
+
+            let outputs = args.iter_mut().filter_map(|arg| match arg {
+                Arg::Output(data) => Some(data),
+                Arg::Input(_) => None,
+            });
+            for (data, staging) in outputs.zip(&staging) {
+                let slice = staging.slice(..);
+                let mapped = slice.map_async(wgpu::MapMode::Read);
+                self.device.poll(wgpu::Maintain::Wait);
+                pollster::block_on(mapped).map_err(|e| Error::Wgpu(e.to_string()))?;
+                data.copy_from_slice(&slice.get_mapped_range());
+                staging.unmap();
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,167 @@
+//! Running simple kernels on any GPU from a single kernel source.
+//!
+//! Kernels written with [`portable_kernel!`] can be compiled both to PTX with `rustc_codegen_nvvm`
+//! (through `CudaBuilder`) and to SPIR-V with [rust-gpu](https://github.com/EmbarkStudios/rust-gpu)
+//! (through `spirv-builder`). On the host, [`Gpu`] launches them with cust if there is a CUDA
+//! device, and otherwise dispatches the SPIR-V version through wgpu (Vulkan, Metal or DX12), so that
+//! applications have a fallback for machines without an NVIDIA GPU.
+//!
+//! # Kernels
+//!
+//! Only simple kernels are portable: every invocation handles one index `i` of buffers which all
+//! have the same number of elements (scalars must be passed as buffers of repeated values).
+//! `&[T]` parameters are read-only and `&mut [T]` parameters are read-write. Element types must be
+//! plain data which is laid out the same way on the host, in PTX and in SPIR-V, such as `u32`,
+//! `i32`, `f32` and `#[repr(C)]` structs of them.
+//!
+//! ```ignore
+//! // in the gpu crate, which is built by both CudaBuilder and spirv-builder.
+//! #![cfg_attr(any(target_os = "cuda", target_arch = "spirv"), no_std)]
+//! #![cfg_attr(target_os = "cuda", feature(register_attr), register_attr(nvvm_internal))]
+//!
+//! gpu_portable::portable_kernel! {
+//!     pub fn saxpy(i, a: &[f32], x: &[f32], y: &mut [f32]) {
+//!         y[i] += a[i] * x[i];
+//!     }
+//! }
+//! ```
+//!
+//! For CUDA this is a `#[kernel]` taking the number of elements and a pointer for every buffer,
+//! for SPIR-V a compute shader with a workgroup size of [`WORKGROUP_SIZE`] and one storage buffer
+//! per parameter in descriptor set 0, bound in order. Invocations past the end of the first buffer
+//! return immediately.
+//!
+//! # Launching
+//!
+//! ```ignore
+//! use gpu_portable::{Arg, Gpu};
+//!
+//! let gpu = Gpu::new(PTX, SPIRV)?;
+//! println!("running on {:?}", gpu.backend());
+//! let a = vec![2.0f32; 1024];
+//! let x = (0..1024).map(|i| i as f32).collect::<Vec<_>>();
+//! let mut y = vec![1.0f32; 1024];
+//! gpu.launch("saxpy", y.len(), &mut [Arg::input(&a), Arg::input(&x), Arg::output(&mut y)])?;
+//! ```
+//!
+//! Buffers are uploaded for every launch and `output`s are copied back after it, this crate is
+//! meant for kernels which do enough work per launch for that not to matter. Code which needs
+//! control over memory, streams or anything CUDA-specific should use cust directly.
+//!
+//! # Limitations
+//!
+//! - cust links to the CUDA driver library at load time, so an application using this crate does
+//!   not start on machines where the NVIDIA driver is not installed at all. Machines with the
+//!   driver but without a usable device (or with a driver which fails to initialize) fall back to
+//!   wgpu.
+//! - rust-gpu pins its own nightly toolchain, which differs from the one of `rustc_codegen_nvvm`,
+//!   so the gpu crate must build on both and the SPIR-V is usually built from a separate build
+//!   script or ahead of time.
+//! - Only the intersection of what both codegens and both APIs support is available: no shared
+//!   memory, atomics, warp intrinsics, `cuda_std` or `spirv_std` functions in portable kernels.
+//!   Everything else must be written per backend with `#[cfg(target_os = "cuda")]` and
+//!   `#[cfg(target_arch = "spirv")]`.
+//! - wgpu limits buffers to 128MiB by default and requires buffer sizes to be a multiple of 4
+//!   bytes, so element types must be at least 4 bytes large.
+
+#![cfg_attr(any(target_os = "cuda", target_arch = "spirv"), no_std)]
+
+#[cfg(not(any(target_os = "cuda", target_arch = "spirv")))]
+mod host;
+
+#[cfg(not(any(target_os = "cuda", target_arch = "spirv")))]
+pub use host::*;
+
+/// The number of invocations in every workgroup of SPIR-V kernels, the number of threads in a
+/// block for CUDA is chosen by the driver.
+pub const WORKGROUP_SIZE: u32 = 64;
+
+#[doc(hidden)]
+pub mod __private {
+    #[cfg(target_os = "cuda")]
+    pub use cuda_std;
+    #[cfg(target_arch = "spirv")]
+    pub use spirv_std;
+}
+
+/// Defines a kernel which can be compiled to both PTX and SPIR-V, see the [crate docs](crate).
+///
+/// The first parameter is the name of the index of the current invocation, the others are the
+/// buffers, which are all `&[T]` or `&mut [T]`. At most 16 buffers are supported.
+#[macro_export]
+macro_rules! portable_kernel {
+    (
+        $(#[$attr:meta])*
+        pub fn $name:ident(
+            $idx:ident,
+            $first:ident : &$($first_mut:ident)? [$first_ty:ty]
+            $(, $arg:ident : &$($arg_mut:ident)? [$arg_ty:ty])* $(,)?
+        ) $body:block
+    ) => {
+        #[cfg(target_os = "cuda")]
+        $(#[$attr])*
+        #[$crate::__private::cuda_std::kernel]
+        #[allow(clippy::missing_safety_doc)]
+        pub unsafe fn $name(len: usize, $first: *mut $first_ty $(, $arg: *mut $arg_ty)*) {
+            let $idx = $crate::__private::cuda_std::thread::index_1d() as usize;
+            if $idx >= len {
+                return;
+            }
+            // every buffer is a separate allocation made by `Gpu::launch`, so they never alias.
+            let $first = &$($first_mut)? *::core::ptr::slice_from_raw_parts_mut($first, len);
+            $(let $arg = &$($arg_mut)? *::core::ptr::slice_from_raw_parts_mut($arg, len);)*
+            $body
+        }
+
+        #[cfg(target_arch = "spirv")]
+        $crate::__spirv_kernel! {
+            [$(#[$attr])*] $name $idx $first
+            []
+            [0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15]
+            $first: &$($first_mut)? [$first_ty], $($arg: &$($arg_mut)? [$arg_ty],)*
+            => $body
+        }
+    };
+}
+
+/// Assigns consecutive bindings to the buffers of a SPIR-V kernel, then emits the kernel.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __spirv_kernel {
+    (
+        [$($attr:tt)*] $name:ident $idx:ident $first:ident
+        [$($params:tt)*]
+        [$binding:tt $($bindings:tt)*]
+        $arg:ident : &$($arg_mut:ident)? [$arg_ty:ty], $($rest:tt)*
+    ) => {
+        $crate::__spirv_kernel! {
+            [$($attr)*] $name $idx $first
+            [
+                $($params)*
+                #[spirv(storage_buffer, descriptor_set = 0, binding = $binding)]
+                $arg: &$($arg_mut)? [$arg_ty],
+            ]
+            [$($bindings)*]
+            $($rest)*
+        }
+    };
+    (
+        [$($attr:tt)*] $name:ident $idx:ident $first:ident
+        [$($params:tt)*]
+        [$($bindings:tt)*]
+        => $body:block
+    ) => {
+        $($attr)*
+        #[$crate::__private::spirv_std::spirv(compute(threads(64)))]
+        pub fn $name(
+            #[spirv(global_invocation_id)] id: $crate::__private::spirv_std::glam::UVec3,
+            $($params)*
+        ) {
+            let $idx = id.x as usize;
+            if $idx >= $first.len() {
+                return;
+            }
+            $body
+        }
+    };
+}
@@ -13,6 +13,18 @@ pub struct DefaultRand {
 }
 
 impl DefaultRand {
+    /// Jumps ahead in the sequence of the generator, equivalently to `2^64` calls to `next_u64()`
+    /// (based on the current default generator).
+    pub fn jump(&mut self) {
+        self.inner.jump();
+    }
+
+    /// Jumps ahead in the sequence of the generator, equivalently to `2^96` calls to `next_u64()`
+    /// (based on the current default generator).
+    pub fn long_jump(&mut self) {
+        self.inner.long_jump();
+    }
+
     /// Initializes many states such that each state is offset in the main sequence by at least
     /// `2**64` elements (based on the current default generator). Such that every state is independent
     /// from the others as long as no state requests more than `2**64` random numbers.
@@ -15,10 +15,15 @@
 #![cfg_attr(target_os = "cuda", no_std)]
 #![feature(doc_cfg)]
 
+pub mod philox;
+pub mod sampling;
 pub mod xoroshiro;
 
 mod default;
 mod gpurng;
+mod streams;
 
 pub use default::*;
 pub use gpurng::*;
+pub use rand_core::{self, RngCore, SeedableRng};
+pub use streams::*;
@@ -0,0 +1,234 @@
+//! The Philox4x32-10 counter-based generator.
+//!
+//! A counter-based generator has no state to carry between kernel launches: its numbers are a
+//! keyed hash of a counter, so every thread can create its generator from what it is working on,
+//! like the pixel and sample it traces, without storing states in device memory. The same pixel and
+//! sample always give the same numbers, which makes renders reproducible no matter how the work is
+//! split between threads, launches or GPUs, and different pixels and samples give independent
+//! numbers, without the correlation of seeding a sequential generator with neighbouring seeds.
+//!
+//! ```
+//! use gpu_rand::{philox, GpuRand};
+//!
+//! # let (x, y, frame) = (3, 4, 0);
+//! let mut rng = philox::for_pixel(x, y, frame);
+//! let offset = rng.normal_f32_2();
+//! ```
+//!
+//! The algorithm is the one of [Parallel Random Numbers: As Easy as 1, 2,
+//! 3](https://www.thesalmons.org/john/random123/papers/random123sc11.pdf) by Salmon et al., the
+//! same as `curandStatePhilox4_32_10_t`.
+
+use rand_core::impls::{fill_bytes_via_next, next_u64_via_u32};
+use rand_core::le::read_u32_into;
+use rand_core::{RngCore, SeedableRng};
+
+const MUL_0: u32 = 0xD2511F53;
+const MUL_1: u32 = 0xCD9E8D57;
+const WEYL_0: u32 = 0x9E3779B9;
+const WEYL_1: u32 = 0xBB67AE85;
+
+/// The key used by [`for_pixel`].
+pub const DEFAULT_KEY: u64 = 0x2545F4914F6CDD1D;
+
+#[inline]
+fn mul_hi_lo(a: u32, b: u32) -> (u32, u32) {
+    let product = a as u64 * b as u64;
+    ((product >> 32) as u32, product as u32)
+}
+
+/// Hashes `counter` with `key` using ten rounds of Philox4x32. This is a bijection of the counter
+/// for every key.
+pub fn philox4x32_10(counter: [u32; 4], key: [u32; 2]) -> [u32; 4] {
+    let [mut c0, mut c1, mut c2, mut c3] = counter;
+    let [mut k0, mut k1] = key;
+    for round in 0..10 {
+        if round != 0 {
+            k0 = k0.wrapping_add(WEYL_0);
+            k1 = k1.wrapping_add(WEYL_1);
+        }
+        let (hi0, lo0) = mul_hi_lo(MUL_0, c0);
+        let (hi1, lo1) = mul_hi_lo(MUL_1, c2);
+        c0 = hi1 ^ c1 ^ k0;
+        c1 = lo1;
+        c2 = hi0 ^ c3 ^ k1;
+        c3 = lo0;
+    }
+    [c0, c1, c2, c3]
+}
+
+/// A Philox4x32-10 random number generator, which returns the hashes of consecutive counters.
+///
+/// Its period is `2^130` for every key. Moving the generator to any place in its sequence with
+/// [`advance`](Self::advance) or [`set_counter`](Self::set_counter) is as fast as generating a
+/// number.
+#[allow(missing_copy_implementations)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[repr(C)]
+#[cfg_attr(not(target_os = "cuda"), derive(Copy, cust::DeviceCopy))]
+pub struct Philox4x32 {
+    counter: [u32; 4],
+    key: [u32; 2],
+    block: [u32; 4],
+    /// The index of the next number of `block`, 4 if the block of `counter` was not generated yet.
+    index: u32,
+}
+
+impl Philox4x32 {
+    /// A generator with `key` which starts at `counter`.
+    pub fn new(key: u64, counter: [u32; 4]) -> Self {
+        Self {
+            counter,
+            key: [key as u32, (key >> 32) as u32],
+            block: [0; 4],
+            index: 4,
+        }
+    }
+
+    /// The counter of the next block of four `u32`s.
+    pub fn counter(&self) -> [u32; 4] {
+        self.counter
+    }
+
+    /// Moves the generator to the start of the block of `counter`.
+    pub fn set_counter(&mut self, counter: [u32; 4]) {
+        self.counter = counter;
+        self.index = 4;
+    }
+
+    /// Skips the rest of the current block and the next `blocks` blocks of four `u32`s. The
+    /// counter is a 128 bit integer with its first word as the lowest bits, so this carries into
+    /// the higher words.
+    pub fn advance(&mut self, blocks: u64) {
+        let mut carry = blocks;
+        for word in &mut self.counter {
+            if carry == 0 {
+                break;
+            }
+            let sum = *word as u64 + (carry & 0xFFFF_FFFF);
+            *word = sum as u32;
+            carry = (carry >> 32) + (sum >> 32);
+        }
+        self.index = 4;
+    }
+}
+
+impl RngCore for Philox4x32 {
+    #[inline]
+    fn next_u32(&mut self) -> u32 {
+        if self.index >= 4 {
+            self.block = philox4x32_10(self.counter, self.key);
+            self.advance(1);
+            self.index = 0;
+        }
+        let r = self.block[self.index as usize];
+        self.index += 1;
+        r
+    }
+
+    #[inline]
+    fn next_u64(&mut self) -> u64 {
+        next_u64_via_u32(self)
+    }
+
+    #[inline]
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        fill_bytes_via_next(self, dest);
+    }
+
+    #[inline]
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for Philox4x32 {
+    type Seed = [u8; 8];
+
+    /// Create a new `Philox4x32` with the key read from `seed`, starting at a counter of zero.
+    fn from_seed(seed: [u8; 8]) -> Philox4x32 {
+        let mut key = [0; 2];
+        read_u32_into(&seed, &mut key);
+        Self::new(key[0] as u64 | (key[1] as u64) << 32, [0; 4])
+    }
+
+    /// Create a new `Philox4x32` with `seed` as the key, starting at a counter of zero. Every key
+    /// gives good numbers, so the seed is not scrambled.
+    fn seed_from_u64(seed: u64) -> Philox4x32 {
+        Self::new(seed, [0; 4])
+    }
+}
+
+/// The generator of sample `sample` of pixel `(x, y)`, with the [`DEFAULT_KEY`]. Use the index of
+/// the frame as the sample to get new numbers every frame.
+///
+/// The pixel and sample are the three upper words of the counter, so the generator returns `2^34`
+/// numbers before it overlaps with the next sample.
+pub fn for_pixel(x: u32, y: u32, sample: u32) -> Philox4x32 {
+    for_pixel_seeded(DEFAULT_KEY, x, y, sample)
+}
+
+/// Like [`for_pixel`], but with `seed` as the key, for renders which should use different numbers.
+pub fn for_pixel_seeded(seed: u64, x: u32, y: u32, sample: u32) -> Philox4x32 {
+    Philox4x32::new(seed, [0, sample, x, y])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reference() {
+        // These values are the known answers of the Random123 library.
+        assert_eq!(
+            philox4x32_10([0; 4], [0; 2]),
+            [0x6627e8d5, 0xe169c58d, 0xbc57ac4c, 0x9b00dbd8]
+        );
+        assert_eq!(
+            philox4x32_10([u32::MAX; 4], [u32::MAX; 2]),
+            [0x408f276d, 0x41c83b0e, 0xa20bc7c6, 0x6d5451fd]
+        );
+        assert_eq!(
+            philox4x32_10(
+                [0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344],
+                [0xa4093822, 0x299f31d0]
+            ),
+            [0xd16cfe09, 0x94fdcceb, 0x5001e420, 0x24126ea1]
+        );
+    }
+
+    #[test]
+    fn advance_carries() {
+        let mut rng = Philox4x32::new(0, [u32::MAX, u32::MAX, 0, 0]);
+        rng.advance(2);
+        assert_eq!(rng.counter(), [1, 0, 1, 0]);
+        rng.advance(u64::MAX);
+        assert_eq!(rng.counter(), [0, 0, 2, 0]);
+    }
+
+    #[test]
+    fn blocks_are_consecutive() {
+        let mut rng = for_pixel(7, 9, 3);
+        let first = (0..8).map(|_| rng.next_u32()).collect::<Vec<_>>();
+        assert_eq!(
+            first[..4],
+            philox4x32_10(
+                [0, 3, 7, 9],
+                [DEFAULT_KEY as u32, (DEFAULT_KEY >> 32) as u32]
+            )
+        );
+        assert_eq!(
+            first[4..],
+            philox4x32_10(
+                [1, 3, 7, 9],
+                [DEFAULT_KEY as u32, (DEFAULT_KEY >> 32) as u32]
+            )
+        );
+
+        let mut skipped = for_pixel(7, 9, 3);
+        skipped.advance(1);
+        assert_eq!(skipped.next_u32(), first[4]);
+        assert_ne!(for_pixel(7, 9, 4).next_u32(), first[0]);
+    }
+}
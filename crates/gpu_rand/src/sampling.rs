@@ -0,0 +1,395 @@
+//! Shuffling and sampling with a random generator, on device slices or on the host.
+//!
+//! - [`shuffle`] and [`partial_shuffle`] permute a slice with the Fisher–Yates shuffle, for
+//!   example to pick a random subset of a batch of training data without replacement.
+//! - [`sample_into`] picks `k` items uniformly from an iterator of unknown length in one pass
+//!   (reservoir sampling).
+//! - [`Reservoir`] picks one item from a stream of weighted items in one pass, with a probability
+//!   proportional to its weight, as used for light sampling with resampled importance sampling.
+//! - [`alias_table`] builds a table on the host from which [`sample_alias`] picks indices with
+//!   probabilities proportional to a list of weights in constant time, for example to pick one of
+//!   many lights by its power.
+
+use crate::GpuRand;
+use rand_core::RngCore;
+
+/// A random integer in `[0, n)`, without the bias of `next_u32() % n`, using Lemire's method.
+///
+/// # Panics
+///
+/// Panics if `n` is zero.
+pub fn below_u32<R: RngCore>(rng: &mut R, n: u32) -> u32 {
+    assert!(n != 0, "cannot sample from an empty range");
+    let mut m = rng.next_u32() as u64 * n as u64;
+    if (m as u32) < n {
+        // values below `2^32 % n` would make the low values more likely.
+        let threshold = n.wrapping_neg() % n;
+        while (m as u32) < threshold {
+            m = rng.next_u32() as u64 * n as u64;
+        }
+    }
+    (m >> 32) as u32
+}
+
+/// A random index in `[0, n)`, like [`below_u32`]. Lengths which fit into a `u32` only use 32 bit
+/// arithmetic, which is much faster on the GPU.
+///
+/// # Panics
+///
+/// Panics if `n` is zero.
+pub fn below<R: RngCore>(rng: &mut R, n: usize) -> usize {
+    if n <= u32::MAX as usize {
+        return below_u32(rng, n as u32) as usize;
+    }
+    let n = n as u64;
+    let mut m = rng.next_u64() as u128 * n as u128;
+    if (m as u64) < n {
+        let threshold = n.wrapping_neg() % n;
+        while (m as u64) < threshold {
+            m = rng.next_u64() as u128 * n as u128;
+        }
+    }
+    (m >> 64) as usize
+}
+
+/// Shuffles `slice` in place, every permutation being equally likely.
+pub fn shuffle<T, R: RngCore>(slice: &mut [T], rng: &mut R) {
+    for i in (1..slice.len()).rev() {
+        slice.swap(i, below(rng, i + 1));
+    }
+}
+
+/// Moves `amount` randomly chosen elements of `slice` to its front, in random order, and returns
+/// them. This is as fast as shuffling only `amount` elements, and the same as [`shuffle`] if
+/// `amount` is the length of the slice.
+///
+/// # Panics
+///
+/// Panics if `amount` is larger than the length of `slice`.
+pub fn partial_shuffle<'a, T, R: RngCore>(
+    slice: &'a mut [T],
+    amount: usize,
+    rng: &mut R,
+) -> &'a mut [T] {
+    assert!(
+        amount <= slice.len(),
+        "cannot pick more elements than the slice has"
+    );
+    for i in 0..amount {
+        let j = i + below(rng, slice.len() - i);
+        slice.swap(i, j);
+    }
+    &mut slice[..amount]
+}
+
+/// Fills `out` with items of `items` chosen uniformly without replacement, in one pass over them
+/// (Algorithm R). Returns how many items were written, which is less than the length of `out` if
+/// `items` has fewer items.
+pub fn sample_into<T, I, R>(items: I, out: &mut [T], rng: &mut R) -> usize
+where
+    I: IntoIterator<Item = T>,
+    R: RngCore,
+{
+    let mut seen = 0;
+    for item in items {
+        if seen < out.len() {
+            out[seen] = item;
+        } else {
+            let j = below(rng, seen + 1);
+            if j < out.len() {
+                out[j] = item;
+            }
+        }
+        seen += 1;
+    }
+    seen.min(out.len())
+}
+
+/// Picks one item out of a stream of weighted items, with a probability proportional to its
+/// weight, without storing the other items (weighted reservoir sampling).
+///
+/// Reservoirs of separate streams can be combined with [`merge`](Self::merge), so every thread can
+/// sample part of the items and the results combined afterwards.
+///
+/// ```
+/// use gpu_rand::{sampling::Reservoir, DefaultRand, SeedableRng};
+///
+/// let mut rng = DefaultRand::seed_from_u64(0);
+/// let powers = [1.0, 10.0, 0.5];
+/// let mut reservoir = Reservoir::new();
+/// for (light, &power) in powers.iter().enumerate() {
+///     reservoir.update(light, power, &mut rng);
+/// }
+/// let light = reservoir.sample().unwrap();
+/// let probability = powers[light] / reservoir.weight_sum();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reservoir<T> {
+    sample: Option<T>,
+    weight_sum: f32,
+    count: u32,
+}
+
+impl<T> Default for Reservoir<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Reservoir<T> {
+    /// An empty reservoir.
+    pub const fn new() -> Self {
+        Self {
+            sample: None,
+            weight_sum: 0.0,
+            count: 0,
+        }
+    }
+
+    /// Offers `item` with `weight` to the reservoir, returning whether it replaced the current
+    /// sample. Items with a weight which is not positive are never picked.
+    pub fn update<R: RngCore>(&mut self, item: T, weight: f32, rng: &mut R) -> bool {
+        self.count += 1;
+        if weight.is_nan() || weight <= 0.0 {
+            return false;
+        }
+        self.weight_sum += weight;
+        // the first item is always picked, `uniform_f32` can round up to exactly one.
+        if self.sample.is_none() || rng.uniform_f32() * self.weight_sum < weight {
+            self.sample = Some(item);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Combines `other` into `self`, as if the items offered to `other` had been offered to
+    /// `self`.
+    pub fn merge<R: RngCore>(&mut self, other: Reservoir<T>, rng: &mut R) {
+        let count = self.count + other.count;
+        if let Some(sample) = other.sample {
+            self.update(sample, other.weight_sum, rng);
+        }
+        self.count = count;
+    }
+
+    /// The picked item, `None` if no item with a positive weight was offered.
+    pub fn sample(self) -> Option<T> {
+        self.sample
+    }
+
+    /// The picked item, `None` if no item with a positive weight was offered.
+    pub fn sample_ref(&self) -> Option<&T> {
+        self.sample.as_ref()
+    }
+
+    /// The sum of the weights of the offered items. An item was picked with a probability of its
+    /// weight divided by this.
+    pub fn weight_sum(&self) -> f32 {
+        self.weight_sum
+    }
+
+    /// The number of items offered to the reservoir.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+}
+
+/// An entry of a table built by [`alias_table`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+pub struct AliasEntry {
+    /// The probability of keeping this index instead of taking `alias`, once this entry is picked.
+    pub threshold: f32,
+    /// The index taken instead of this one.
+    pub alias: u32,
+    /// The probability of [`sample_alias`] returning this index, its weight divided by the sum of
+    /// the weights.
+    pub pdf: f32,
+}
+
+/// Builds an alias table for sampling indices into `weights` with [`sample_alias`], with
+/// probabilities proportional to the weights (Vose's method). Negative and NaN weights are
+/// treated as zero. The table can be copied to the GPU, for example in a `DeviceBuffer`.
+///
+/// If every weight is zero, indices are sampled uniformly.
+///
+/// # Panics
+///
+/// Panics if `weights` is empty or has more than `u32::MAX` elements.
+#[cfg_attr(docsrs, doc(cfg(not(target_os = "cuda"))))]
+#[cfg(not(target_os = "cuda"))]
+pub fn alias_table(weights: &[f32]) -> Vec<AliasEntry> {
+    assert!(!weights.is_empty(), "cannot sample from no weights");
+    assert!(weights.len() <= u32::MAX as usize, "too many weights");
+    let n = weights.len();
+    let weights = weights
+        .iter()
+        .map(|&w| if w > 0.0 { w as f64 } else { 0.0 })
+        .collect::<Vec<_>>();
+    let sum = weights.iter().sum::<f64>();
+    // the weights scaled so that their average is 1.
+    let mut scaled = if sum > 0.0 {
+        weights
+            .iter()
+            .map(|w| w * n as f64 / sum)
+            .collect::<Vec<_>>()
+    } else {
+        vec![1.0; n]
+    };
+
+    let mut table = (0..n)
+        .map(|i| AliasEntry {
+            threshold: 1.0,
+            alias: i as u32,
+            pdf: (scaled[i] / n as f64) as f32,
+        })
+        .collect::<Vec<_>>();
+    let (mut small, mut large): (Vec<_>, Vec<_>) = (0..n).partition(|&i| scaled[i] < 1.0);
+    while let (Some(s), Some(&l)) = (small.pop(), large.last()) {
+        table[s].threshold = scaled[s] as f32;
+        table[s].alias = l as u32;
+        scaled[l] -= 1.0 - scaled[s];
+        if scaled[l] < 1.0 {
+            large.pop();
+            small.push(l);
+        }
+    }
+    // whatever is left is 1 up to rounding errors and keeps its own index.
+    table
+}
+
+/// Samples an index into the weights `table` was built from with [`alias_table`], in constant
+/// time. The probability of the index is the `pdf` of its entry.
+///
+/// # Panics
+///
+/// Panics if `table` is empty.
+pub fn sample_alias<R: RngCore>(table: &[AliasEntry], rng: &mut R) -> usize {
+    let i = below(rng, table.len());
+    let entry = &table[i];
+    if rng.uniform_f32() < entry.threshold {
+        i
+    } else {
+        entry.alias as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DefaultRand;
+    use rand_core::SeedableRng;
+
+    #[test]
+    fn shuffle_is_permutation() {
+        let mut rng = DefaultRand::seed_from_u64(0);
+        let mut values = (0..100).collect::<Vec<_>>();
+        shuffle(&mut values, &mut rng);
+        assert_ne!(values, (0..100).collect::<Vec<_>>());
+        values.sort_unstable();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+
+        let picked = partial_shuffle(&mut values, 10, &mut rng).to_vec();
+        assert_eq!(picked.len(), 10);
+        values.sort_unstable();
+        assert_eq!(values, (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn sample_into_short_input() {
+        let mut rng = DefaultRand::seed_from_u64(0);
+        let mut out = [0; 8];
+        assert_eq!(sample_into(0..5, &mut out, &mut rng), 5);
+        assert_eq!(out[..5], [0, 1, 2, 3, 4]);
+        assert_eq!(sample_into(0..1000, &mut out, &mut rng), 8);
+    }
+
+    #[test]
+    fn alias_table_frequencies() {
+        let weights = [1.0, 0.0, 3.0, 4.0];
+        let table = alias_table(&weights);
+        let mut rng = DefaultRand::seed_from_u64(1);
+        let mut counts = [0u32; 4];
+        for _ in 0..80_000 {
+            counts[sample_alias(&table, &mut rng)] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        for (i, &count) in counts.iter().enumerate() {
+            let expected = weights[i] / 8.0;
+            assert!((count as f32 / 80_000.0 - expected).abs() < 0.01);
+            assert!((table[i].pdf - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn reservoir_frequencies() {
+        let mut rng = DefaultRand::seed_from_u64(2);
+        let mut counts = [0u32; 3];
+        for _ in 0..30_000 {
+            let mut a = Reservoir::new();
+            a.update(0, 1.0, &mut rng);
+            a.update(1, 0.0, &mut rng);
+            let mut b = Reservoir::new();
+            b.update(2, 2.0, &mut rng);
+            a.merge(b, &mut rng);
+            assert_eq!(a.count(), 3);
+            counts[a.sample().unwrap()] += 1;
+        }
+        assert_eq!(counts[1], 0);
+        assert!((counts[2] as f32 / 30_000.0 - 2.0 / 3.0).abs() < 0.02);
+    }
+
+    /// Always returns the largest value, so that `uniform_f32` rounds up to one.
+    struct MaxRng;
+
+    impl RngCore for MaxRng {
+        fn next_u32(&mut self) -> u32 {
+            u32::MAX
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            u64::MAX
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.fill(u8::MAX);
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn reservoir_picks_first_item() {
+        let mut rng = MaxRng;
+        assert_eq!(rng.uniform_f32(), 1.0);
+        let mut reservoir = Reservoir::new();
+        assert!(!reservoir.update(0, 0.0, &mut rng));
+        assert!(reservoir.update(1, 1.0, &mut rng));
+        assert!(!reservoir.update(2, 1.0, &mut rng));
+        assert_eq!(reservoir.sample(), Some(1));
+        assert_eq!(reservoir.count(), 3);
+
+        let mut reservoir = Reservoir::new();
+        assert!(reservoir.update(0, f32::MIN_POSITIVE, &mut rng));
+        assert_eq!(reservoir.sample(), Some(0));
+    }
+
+    #[test]
+    fn reservoir_skips_invalid_weights() {
+        let mut rng = DefaultRand::seed_from_u64(3);
+        let mut reservoir = Reservoir::new();
+        for weight in [f32::NAN, -1.0, -0.0, 0.0, f32::NEG_INFINITY] {
+            assert!(!reservoir.update(0, weight, &mut rng));
+        }
+        assert_eq!(reservoir.sample(), None);
+        assert_eq!(reservoir.weight_sum(), 0.0);
+        assert_eq!(reservoir.count(), 5);
+        assert!(reservoir.update(1, 2.0, &mut rng));
+        assert_eq!(reservoir.weight_sum(), 2.0);
+    }
+}
@@ -0,0 +1,139 @@
+//! Giving every thread its own stream of random numbers.
+//!
+//! Generators must not be shared between threads, and seeding the generator of every thread with
+//! `seed + thread_id` gives streams which are visibly correlated for most generators. There are two
+//! ways to get independent streams instead:
+//!
+//! - [`Streams::fork`] derives the generator of a stream from a parent generator and the index of
+//!   the stream (usually the index of the thread) by hashing them into a new seed. It is cheap
+//!   enough to call at the start of every kernel, so the states do not need to be stored in device
+//!   memory:
+//!
+//!   ```
+//!   use gpu_rand::{DefaultRand, GpuRand, Streams};
+//!
+//!   # let thread_id = 3;
+//!   let mut rng = DefaultRand::seed_stream(1234, thread_id);
+//!   let x = rng.uniform_f32();
+//!   ```
+//!
+//! - [`Jump`] moves a generator ahead by a fixed, very large number of steps, which guarantees that
+//!   the streams never overlap. A jump takes as long as generating a few hundred numbers, so the
+//!   states are best created on the host, like with `DefaultRand::initialize_states`.
+//!
+//! Forked streams are not guaranteed to never overlap, but the seeds are spread over the whole state
+//! space, so for the 128 bit states and larger an overlap is as unlikely as for separately seeded
+//! generators.
+
+use crate::xoroshiro::*;
+use crate::DefaultRand;
+use rand_core::{RngCore, SeedableRng};
+
+/// Deriving independent generators from a seed or a generator and the index of a stream.
+///
+/// This is implemented for every seedable generator.
+pub trait Streams: Sized {
+    /// The generator of stream `stream` of `self`. The same generator and stream always give the
+    /// same generator, different streams give independent ones. `self` is not advanced.
+    fn fork(&self, stream: u64) -> Self;
+
+    /// The generator of stream `stream` of the generator seeded with
+    /// [`seed_from_u64(seed)`](SeedableRng::seed_from_u64).
+    fn seed_stream(seed: u64, stream: u64) -> Self;
+}
+
+impl<T: SeedableRng + RngCore + Clone> Streams for T {
+    fn fork(&self, stream: u64) -> Self {
+        let key = self.clone().next_u64();
+        // mixing the stream makes neighbouring streams start far away from each other in the
+        // sequence of SplitMix64.
+        let mut mixer = SplitMix64::seed_from_u64(stream);
+        Self::seed_from_u64(key ^ mixer.next_u64())
+    }
+
+    fn seed_stream(seed: u64, stream: u64) -> Self {
+        Self::seed_from_u64(seed).fork(stream)
+    }
+}
+
+/// Generators which can jump ahead in their sequence by a fixed number of steps, as many steps as
+/// the square root of their period (for example `2^64` for 128 bit states).
+pub trait Jump {
+    /// Jumps ahead by the jump distance of the generator.
+    fn jump(&mut self);
+
+    /// Jumps ahead by `n` times the jump distance of the generator. This takes `n` times as long as
+    /// [`jump`](Self::jump).
+    fn jump_by(&mut self, n: u64) {
+        for _ in 0..n {
+            self.jump();
+        }
+    }
+}
+
+macro_rules! impl_jump_trait {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Jump for $ty {
+                fn jump(&mut self) {
+                    <$ty>::jump(self);
+                }
+            }
+        )*
+    };
+}
+
+impl_jump_trait!(
+    Xoroshiro128Plus,
+    Xoroshiro128PlusPlus,
+    Xoroshiro128StarStar,
+    Xoshiro128Plus,
+    Xoshiro128PlusPlus,
+    Xoshiro128StarStar,
+    Xoshiro256Plus,
+    Xoshiro256PlusPlus,
+    Xoshiro256StarStar,
+    Xoshiro512Plus,
+    Xoshiro512PlusPlus,
+    Xoshiro512StarStar,
+    DefaultRand,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fork_is_deterministic() {
+        let rng = Xoshiro256StarStar::seed_from_u64(7);
+        assert_eq!(rng.fork(3), rng.fork(3));
+        assert_eq!(
+            Xoshiro256StarStar::seed_stream(7, 3),
+            Xoshiro256StarStar::seed_from_u64(7).fork(3)
+        );
+    }
+
+    #[test]
+    fn forked_streams_differ() {
+        let rng = DefaultRand::seed_from_u64(0);
+        let mut firsts = (0..1000)
+            .map(|stream| rng.fork(stream).next_u64())
+            .collect::<Vec<_>>();
+        firsts.sort_unstable();
+        firsts.dedup();
+        assert_eq!(firsts.len(), 1000);
+        // forking does not advance the parent.
+        assert_eq!(rng, DefaultRand::seed_from_u64(0));
+    }
+
+    #[test]
+    fn jump_by() {
+        let mut a = Xoroshiro128StarStar::seed_from_u64(1);
+        let mut b = a;
+        a.jump_by(3);
+        for _ in 0..3 {
+            b.jump();
+        }
+        assert_eq!(a, b);
+    }
+}
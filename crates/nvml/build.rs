@@ -0,0 +1,17 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=CUDA_LIBRARY_PATH");
+    if std::env::var("DOCS_RS").is_ok() {
+        return;
+    }
+
+    // NVML ships with the driver, the toolkit only has stubs to link against.
+    for dir in find_cuda_helper::find_cuda_lib_dirs() {
+        println!("cargo:rustc-link-search=native={}", dir.display());
+    }
+    if cfg!(target_os = "windows") {
+        println!("cargo:rustc-link-lib=dylib=nvml");
+    } else {
+        println!("cargo:rustc-link-lib=dylib=nvidia-ml");
+    }
+}
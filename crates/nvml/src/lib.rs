@@ -0,0 +1,449 @@
+//! Bindings to NVML (the NVIDIA Management Library) for querying the health, utilization, power
+//! and clocks of GPUs.
+//!
+//! NVML numbers devices differently from CUDA (NVML follows PCI bus order, CUDA puts the fastest
+//! device first by default and respects `CUDA_VISIBLE_DEVICES`), so devices should always be paired
+//! through their UUID, which [`Nvml::device_for`] does. This also works for MIG devices, which
+//! CUDA exposes as separate devices.
+//!
+//! # Example
+//!
+//! ```no_run
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use cust::device::Device;
+//! use nvml::Nvml;
+//!
+//! cust::init(cust::CudaFlags::empty())?;
+//! let nvml = Nvml::init()?;
+//! for device in Device::devices()? {
+//!     let device = device?;
+//!     let gpu = nvml.device_for(&device)?;
+//!     let util = gpu.utilization()?;
+//!     println!(
+//!         "{}: {}°C, {}% busy, {} MB free",
+//!         gpu.name()?,
+//!         gpu.temperature()?,
+//!         util.gpu,
+//!         gpu.memory_info()?.free / (1 << 20)
+//!     );
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#[allow(warnings)]
+pub mod sys;
+
+use cust::device::Device;
+use std::{
+    ffi::{CStr, CString},
+    fmt,
+    marker::PhantomData,
+    os::raw::c_char,
+};
+
+pub type NvmlResult<T> = Result<T, NvmlError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NvmlError {
+    Uninitialized,
+    InvalidArgument,
+    /// The device does not support this query, for example ECC queries on consumer GPUs.
+    NotSupported,
+    NoPermission,
+    NotFound,
+    DriverNotLoaded,
+    /// The GPU fell off the bus or is otherwise inaccessible.
+    GpuIsLost,
+    /// Any other error, with the raw `nvmlReturn_t` code.
+    Other(u32),
+}
+
+impl fmt::Display for NvmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let code = match *self {
+            NvmlError::Uninitialized => sys::NVML_ERROR_UNINITIALIZED,
+            NvmlError::InvalidArgument => sys::NVML_ERROR_INVALID_ARGUMENT,
+            NvmlError::NotSupported => sys::NVML_ERROR_NOT_SUPPORTED,
+            NvmlError::NoPermission => sys::NVML_ERROR_NO_PERMISSION,
+            NvmlError::NotFound => sys::NVML_ERROR_NOT_FOUND,
+            NvmlError::DriverNotLoaded => sys::NVML_ERROR_DRIVER_NOT_LOADED,
+            NvmlError::GpuIsLost => sys::NVML_ERROR_GPU_IS_LOST,
+            NvmlError::Other(code) => code,
+        };
+        let msg = unsafe { CStr::from_ptr(sys::nvmlErrorString(code)) };
+        write!(f, "{}", msg.to_string_lossy())
+    }
+}
+
+impl std::error::Error for NvmlError {}
+
+trait ToResult {
+    fn to_result(self) -> NvmlResult<()>;
+}
+
+impl ToResult for sys::nvmlReturn_t {
+    fn to_result(self) -> NvmlResult<()> {
+        match self {
+            sys::NVML_SUCCESS => Ok(()),
+            sys::NVML_ERROR_UNINITIALIZED => Err(NvmlError::Uninitialized),
+            sys::NVML_ERROR_INVALID_ARGUMENT => Err(NvmlError::InvalidArgument),
+            sys::NVML_ERROR_NOT_SUPPORTED => Err(NvmlError::NotSupported),
+            sys::NVML_ERROR_NO_PERMISSION => Err(NvmlError::NoPermission),
+            sys::NVML_ERROR_NOT_FOUND => Err(NvmlError::NotFound),
+            sys::NVML_ERROR_DRIVER_NOT_LOADED => Err(NvmlError::DriverNotLoaded),
+            sys::NVML_ERROR_GPU_IS_LOST => Err(NvmlError::GpuIsLost),
+            code => Err(NvmlError::Other(code)),
+        }
+    }
+}
+
+/// A handle to an initialized NVML library, NVML is shut down when every handle is dropped.
+#[derive(Debug)]
+pub struct Nvml {
+    _private: (),
+}
+
+impl Nvml {
+    /// Initializes NVML. This may be called multiple times, NVML is reference counted.
+    pub fn init() -> NvmlResult<Self> {
+        unsafe { sys::nvmlInit_v2().to_result()? };
+        Ok(Self { _private: () })
+    }
+
+    /// The number of devices visible to NVML, this ignores `CUDA_VISIBLE_DEVICES`.
+    pub fn device_count(&self) -> NvmlResult<u32> {
+        let mut count = 0;
+        unsafe { sys::nvmlDeviceGetCount_v2(&mut count).to_result()? };
+        Ok(count)
+    }
+
+    /// Returns the device with the given NVML index. Note that this is **not** the same as the
+    /// CUDA ordinal, use [`Nvml::device_for`] to get the NVML device for a cust [`Device`].
+    pub fn device_by_index(&self, index: u32) -> NvmlResult<NvmlDevice<'_>> {
+        let mut raw = std::ptr::null_mut();
+        unsafe { sys::nvmlDeviceGetHandleByIndex_v2(index, &mut raw).to_result()? };
+        Ok(NvmlDevice {
+            raw,
+            _nvml: PhantomData,
+        })
+    }
+
+    /// Returns the device with the given UUID, in the format NVML uses (`GPU-...`).
+    pub fn device_by_uuid(&self, uuid: &str) -> NvmlResult<NvmlDevice<'_>> {
+        let uuid = CString::new(uuid).map_err(|_| NvmlError::InvalidArgument)?;
+        let mut raw = std::ptr::null_mut();
+        unsafe { sys::nvmlDeviceGetHandleByUUID(uuid.as_ptr(), &mut raw).to_result()? };
+        Ok(NvmlDevice {
+            raw,
+            _nvml: PhantomData,
+        })
+    }
+
+    /// Returns the NVML device corresponding to a cust [`Device`], matching them by UUID.
+    pub fn device_for(&self, device: &Device) -> NvmlResult<NvmlDevice<'_>> {
+        let uuid = device
+            .uuid_string()
+            .map_err(|_| NvmlError::InvalidArgument)?;
+        self.device_by_uuid(&uuid)
+    }
+}
+
+impl Drop for Nvml {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = sys::nvmlShutdown();
+        }
+    }
+}
+
+/// GPU and memory utilization over the last sample period, in percent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Utilization {
+    /// The percentage of time a kernel was running on the GPU.
+    pub gpu: u32,
+    /// The percentage of time device memory was being read or written.
+    pub memory: u32,
+}
+
+/// Device memory usage, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryInfo {
+    pub total: u64,
+    pub free: u64,
+    pub used: u64,
+}
+
+/// The clock domains which can be queried with [`NvmlDevice::clock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClockType {
+    Graphics,
+    Sm,
+    Memory,
+    Video,
+}
+
+impl ClockType {
+    fn to_raw(self) -> sys::nvmlClockType_t {
+        match self {
+            ClockType::Graphics => sys::NVML_CLOCK_GRAPHICS,
+            ClockType::Sm => sys::NVML_CLOCK_SM,
+            ClockType::Memory => sys::NVML_CLOCK_MEM,
+            ClockType::Video => sys::NVML_CLOCK_VIDEO,
+        }
+    }
+}
+
+/// Counts of ECC errors on a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EccErrors {
+    /// Single bit errors which were corrected.
+    pub corrected: u64,
+    /// Double bit errors which could not be corrected, any of these means data was corrupted.
+    pub uncorrected: u64,
+}
+
+/// The resources of a device, mostly useful for MIG devices, see [`NvmlDevice::attributes`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeviceAttributes {
+    /// The number of SMs available to the device.
+    pub multiprocessor_count: u32,
+    /// The number of GPU instance slices (the unit memory and SMs are partitioned in) the device
+    /// has.
+    pub gpu_instance_slice_count: u32,
+    /// The number of compute instance slices (the unit SMs are partitioned in) the device has.
+    pub compute_instance_slice_count: u32,
+    /// The amount of device memory, in bytes.
+    pub memory_size: u64,
+    /// The number of copy engines, which may be shared with other MIG devices.
+    pub shared_copy_engine_count: u32,
+    pub shared_decoder_count: u32,
+    pub shared_encoder_count: u32,
+}
+
+/// A device as seen by NVML.
+///
+/// This is either a physical GPU or, if the GPU is partitioned with MIG (Multi-Instance GPU), one of
+/// its MIG devices, see [`NvmlDevice::is_mig_device`].
+#[derive(Debug, Clone, Copy)]
+pub struct NvmlDevice<'a> {
+    raw: sys::nvmlDevice_t,
+    _nvml: PhantomData<&'a Nvml>,
+}
+
+impl NvmlDevice<'_> {
+    fn get_string(
+        &self,
+        len: usize,
+        f: unsafe extern "C" fn(sys::nvmlDevice_t, *mut c_char, u32) -> sys::nvmlReturn_t,
+    ) -> NvmlResult<String> {
+        let mut buf = vec![0 as c_char; len];
+        unsafe {
+            f(self.raw, buf.as_mut_ptr(), len as u32).to_result()?;
+            Ok(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+
+    /// The name of the device.
+    pub fn name(&self) -> NvmlResult<String> {
+        self.get_string(sys::NVML_DEVICE_NAME_BUFFER_SIZE, sys::nvmlDeviceGetName)
+    }
+
+    /// The UUID of the device, in the `GPU-...` format (or `MIG-...` for MIG devices).
+    pub fn uuid(&self) -> NvmlResult<String> {
+        self.get_string(sys::NVML_DEVICE_UUID_BUFFER_SIZE, sys::nvmlDeviceGetUUID)
+    }
+
+    /// The temperature of the GPU die, in degrees Celsius.
+    pub fn temperature(&self) -> NvmlResult<u32> {
+        let mut temp = 0;
+        unsafe {
+            sys::nvmlDeviceGetTemperature(self.raw, sys::NVML_TEMPERATURE_GPU, &mut temp)
+                .to_result()?
+        };
+        Ok(temp)
+    }
+
+    /// The GPU and memory utilization.
+    pub fn utilization(&self) -> NvmlResult<Utilization> {
+        let mut raw = sys::nvmlUtilization_t::default();
+        unsafe { sys::nvmlDeviceGetUtilizationRates(self.raw, &mut raw).to_result()? };
+        Ok(Utilization {
+            gpu: raw.gpu,
+            memory: raw.memory,
+        })
+    }
+
+    /// How much device memory is used and free, across every process.
+    pub fn memory_info(&self) -> NvmlResult<MemoryInfo> {
+        let mut raw = sys::nvmlMemory_t::default();
+        unsafe { sys::nvmlDeviceGetMemoryInfo(self.raw, &mut raw).to_result()? };
+        Ok(MemoryInfo {
+            total: raw.total,
+            free: raw.free,
+            used: raw.used,
+        })
+    }
+
+    /// The current power draw of the device, in milliwatts.
+    pub fn power_usage(&self) -> NvmlResult<u32> {
+        let mut power = 0;
+        unsafe { sys::nvmlDeviceGetPowerUsage(self.raw, &mut power).to_result()? };
+        Ok(power)
+    }
+
+    /// The power limit enforced on the device, in milliwatts.
+    pub fn power_limit(&self) -> NvmlResult<u32> {
+        let mut limit = 0;
+        unsafe { sys::nvmlDeviceGetEnforcedPowerLimit(self.raw, &mut limit).to_result()? };
+        Ok(limit)
+    }
+
+    /// The current clock speed of a clock domain, in MHz.
+    pub fn clock(&self, clock: ClockType) -> NvmlResult<u32> {
+        let mut mhz = 0;
+        unsafe { sys::nvmlDeviceGetClockInfo(self.raw, clock.to_raw(), &mut mhz).to_result()? };
+        Ok(mhz)
+    }
+
+    /// The maximum clock speed of a clock domain, in MHz.
+    pub fn max_clock(&self, clock: ClockType) -> NvmlResult<u32> {
+        let mut mhz = 0;
+        unsafe { sys::nvmlDeviceGetMaxClockInfo(self.raw, clock.to_raw(), &mut mhz).to_result()? };
+        Ok(mhz)
+    }
+
+    /// Whether ECC is currently enabled on the device. Returns [`NvmlError::NotSupported`]
+    /// on devices without ECC memory.
+    pub fn ecc_enabled(&self) -> NvmlResult<bool> {
+        let mut current = 0;
+        let mut pending = 0;
+        unsafe { sys::nvmlDeviceGetEccMode(self.raw, &mut current, &mut pending).to_result()? };
+        Ok(current == sys::NVML_FEATURE_ENABLED)
+    }
+
+    /// The number of ECC errors on the device. If `since_reboot` is `true`, only errors since the
+    /// driver was last loaded are counted, otherwise errors over the lifetime of the device are counted.
+    pub fn ecc_errors(&self, since_reboot: bool) -> NvmlResult<EccErrors> {
+        let counter = if since_reboot {
+            sys::NVML_VOLATILE_ECC
+        } else {
+            sys::NVML_AGGREGATE_ECC
+        };
+        let mut corrected = 0;
+        let mut uncorrected = 0;
+        unsafe {
+            sys::nvmlDeviceGetTotalEccErrors(
+                self.raw,
+                sys::NVML_MEMORY_ERROR_TYPE_CORRECTED,
+                counter,
+                &mut corrected,
+            )
+            .to_result()?;
+            sys::nvmlDeviceGetTotalEccErrors(
+                self.raw,
+                sys::NVML_MEMORY_ERROR_TYPE_UNCORRECTED,
+                counter,
+                &mut uncorrected,
+            )
+            .to_result()?;
+        }
+        Ok(EccErrors {
+            corrected,
+            uncorrected,
+        })
+    }
+
+    /// Whether this is a MIG device, a partition of a physical GPU.
+    pub fn is_mig_device(&self) -> NvmlResult<bool> {
+        let mut is_mig = 0;
+        unsafe { sys::nvmlDeviceIsMigDeviceHandle(self.raw, &mut is_mig).to_result()? };
+        Ok(is_mig != 0)
+    }
+
+    /// The physical GPU of a MIG device, or the device itself if it is not a MIG device.
+    pub fn parent(&self) -> NvmlResult<Self> {
+        if !self.is_mig_device()? {
+            return Ok(*self);
+        }
+        let mut raw = std::ptr::null_mut();
+        unsafe {
+            sys::nvmlDeviceGetDeviceHandleFromMigDeviceHandle(self.raw, &mut raw).to_result()?
+        };
+        Ok(Self {
+            raw,
+            _nvml: PhantomData,
+        })
+    }
+
+    /// Whether MIG mode is currently enabled on this physical GPU. Returns [`NvmlError::NotSupported`]
+    /// on GPUs which do not support MIG.
+    pub fn mig_mode_enabled(&self) -> NvmlResult<bool> {
+        let mut current = 0;
+        let mut pending = 0;
+        unsafe { sys::nvmlDeviceGetMigMode(self.raw, &mut current, &mut pending).to_result()? };
+        Ok(current == sys::NVML_DEVICE_MIG_ENABLE)
+    }
+
+    /// The MIG devices this physical GPU is partitioned into, empty if MIG is disabled.
+    pub fn mig_devices(&self) -> NvmlResult<Vec<Self>> {
+        match self.mig_mode_enabled() {
+            Ok(true) => {}
+            Ok(false) | Err(NvmlError::NotSupported) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        }
+        let mut max = 0;
+        unsafe { sys::nvmlDeviceGetMaxMigDeviceCount(self.raw, &mut max).to_result()? };
+        let mut devices = Vec::new();
+        for index in 0..max {
+            let mut raw = std::ptr::null_mut();
+            let res =
+                unsafe { sys::nvmlDeviceGetMigDeviceHandleByIndex(self.raw, index, &mut raw) };
+            // slots without a MIG device in them are reported as not found.
+            match res.to_result() {
+                Ok(()) => devices.push(Self {
+                    raw,
+                    _nvml: PhantomData,
+                }),
+                Err(NvmlError::NotFound) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(devices)
+    }
+
+    /// The ID of the GPU instance of a MIG device.
+    pub fn gpu_instance_id(&self) -> NvmlResult<u32> {
+        let mut id = 0;
+        unsafe { sys::nvmlDeviceGetGpuInstanceId(self.raw, &mut id).to_result()? };
+        Ok(id)
+    }
+
+    /// The ID of the compute instance of a MIG device.
+    pub fn compute_instance_id(&self) -> NvmlResult<u32> {
+        let mut id = 0;
+        unsafe { sys::nvmlDeviceGetComputeInstanceId(self.raw, &mut id).to_result()? };
+        Ok(id)
+    }
+
+    /// The SMs, memory, and engines available to this device. For MIG devices, these are the
+    /// resources of the partition.
+    pub fn attributes(&self) -> NvmlResult<DeviceAttributes> {
+        let mut raw = sys::nvmlDeviceAttributes_t::default();
+        unsafe { sys::nvmlDeviceGetAttributes_v2(self.raw, &mut raw).to_result()? };
+        Ok(DeviceAttributes {
+            multiprocessor_count: raw.multiprocessorCount,
+            gpu_instance_slice_count: raw.gpuInstanceSliceCount,
+            compute_instance_slice_count: raw.computeInstanceSliceCount,
+            memory_size: raw.memorySizeMB * 1024 * 1024,
+            shared_copy_engine_count: raw.sharedCopyEngineCount,
+            shared_decoder_count: raw.sharedDecoderCount,
+            shared_encoder_count: raw.sharedEncoderCount,
+        })
+    }
+
+    /// Returns the raw NVML handle to this device.
+    pub fn as_raw(&self) -> sys::nvmlDevice_t {
+        self.raw
+    }
+}
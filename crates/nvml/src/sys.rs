@@ -0,0 +1,158 @@
+//! Raw bindings to the subset of `nvml.h` used by this crate.
+
+use std::os::raw::{c_char, c_uint, c_ulonglong, c_void};
+
+pub type nvmlReturn_t = c_uint;
+
+pub const NVML_SUCCESS: nvmlReturn_t = 0;
+pub const NVML_ERROR_UNINITIALIZED: nvmlReturn_t = 1;
+pub const NVML_ERROR_INVALID_ARGUMENT: nvmlReturn_t = 2;
+pub const NVML_ERROR_NOT_SUPPORTED: nvmlReturn_t = 3;
+pub const NVML_ERROR_NO_PERMISSION: nvmlReturn_t = 4;
+pub const NVML_ERROR_NOT_FOUND: nvmlReturn_t = 6;
+pub const NVML_ERROR_INSUFFICIENT_SIZE: nvmlReturn_t = 7;
+pub const NVML_ERROR_DRIVER_NOT_LOADED: nvmlReturn_t = 9;
+pub const NVML_ERROR_GPU_IS_LOST: nvmlReturn_t = 15;
+
+pub type nvmlDevice_t = *mut c_void;
+
+pub type nvmlTemperatureSensors_t = c_uint;
+pub const NVML_TEMPERATURE_GPU: nvmlTemperatureSensors_t = 0;
+
+pub type nvmlClockType_t = c_uint;
+pub const NVML_CLOCK_GRAPHICS: nvmlClockType_t = 0;
+pub const NVML_CLOCK_SM: nvmlClockType_t = 1;
+pub const NVML_CLOCK_MEM: nvmlClockType_t = 2;
+pub const NVML_CLOCK_VIDEO: nvmlClockType_t = 3;
+
+pub type nvmlEnableState_t = c_uint;
+pub const NVML_FEATURE_DISABLED: nvmlEnableState_t = 0;
+pub const NVML_FEATURE_ENABLED: nvmlEnableState_t = 1;
+
+pub type nvmlMemoryErrorType_t = c_uint;
+pub const NVML_MEMORY_ERROR_TYPE_CORRECTED: nvmlMemoryErrorType_t = 0;
+pub const NVML_MEMORY_ERROR_TYPE_UNCORRECTED: nvmlMemoryErrorType_t = 1;
+
+pub type nvmlEccCounterType_t = c_uint;
+pub const NVML_VOLATILE_ECC: nvmlEccCounterType_t = 0;
+pub const NVML_AGGREGATE_ECC: nvmlEccCounterType_t = 1;
+
+pub const NVML_DEVICE_NAME_BUFFER_SIZE: usize = 64;
+pub const NVML_DEVICE_UUID_BUFFER_SIZE: usize = 80;
+
+pub const NVML_DEVICE_MIG_DISABLE: c_uint = 0;
+pub const NVML_DEVICE_MIG_ENABLE: c_uint = 1;
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct nvmlDeviceAttributes_t {
+    pub multiprocessorCount: c_uint,
+    pub sharedCopyEngineCount: c_uint,
+    pub sharedDecoderCount: c_uint,
+    pub sharedEncoderCount: c_uint,
+    pub sharedJpegCount: c_uint,
+    pub sharedOfaCount: c_uint,
+    pub gpuInstanceSliceCount: c_uint,
+    pub computeInstanceSliceCount: c_uint,
+    pub memorySizeMB: c_ulonglong,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct nvmlUtilization_t {
+    pub gpu: c_uint,
+    pub memory: c_uint,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct nvmlMemory_t {
+    pub total: c_ulonglong,
+    pub free: c_ulonglong,
+    pub used: c_ulonglong,
+}
+
+extern "C" {
+    pub fn nvmlInit_v2() -> nvmlReturn_t;
+    pub fn nvmlShutdown() -> nvmlReturn_t;
+    pub fn nvmlErrorString(result: nvmlReturn_t) -> *const c_char;
+    pub fn nvmlDeviceGetCount_v2(deviceCount: *mut c_uint) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetHandleByIndex_v2(index: c_uint, device: *mut nvmlDevice_t) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetHandleByUUID(
+        uuid: *const c_char,
+        device: *mut nvmlDevice_t,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetName(
+        device: nvmlDevice_t,
+        name: *mut c_char,
+        length: c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetUUID(
+        device: nvmlDevice_t,
+        uuid: *mut c_char,
+        length: c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetTemperature(
+        device: nvmlDevice_t,
+        sensorType: nvmlTemperatureSensors_t,
+        temp: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetUtilizationRates(
+        device: nvmlDevice_t,
+        utilization: *mut nvmlUtilization_t,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetMemoryInfo(device: nvmlDevice_t, memory: *mut nvmlMemory_t)
+        -> nvmlReturn_t;
+    pub fn nvmlDeviceGetPowerUsage(device: nvmlDevice_t, power: *mut c_uint) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetEnforcedPowerLimit(
+        device: nvmlDevice_t,
+        limit: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetClockInfo(
+        device: nvmlDevice_t,
+        type_: nvmlClockType_t,
+        clock: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetMaxClockInfo(
+        device: nvmlDevice_t,
+        type_: nvmlClockType_t,
+        clock: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetEccMode(
+        device: nvmlDevice_t,
+        current: *mut nvmlEnableState_t,
+        pending: *mut nvmlEnableState_t,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetTotalEccErrors(
+        device: nvmlDevice_t,
+        errorType: nvmlMemoryErrorType_t,
+        counterType: nvmlEccCounterType_t,
+        eccCounts: *mut c_ulonglong,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceIsMigDeviceHandle(
+        device: nvmlDevice_t,
+        isMigDevice: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetMigMode(
+        device: nvmlDevice_t,
+        currentMode: *mut c_uint,
+        pendingMode: *mut c_uint,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetMaxMigDeviceCount(device: nvmlDevice_t, count: *mut c_uint)
+        -> nvmlReturn_t;
+    pub fn nvmlDeviceGetMigDeviceHandleByIndex(
+        device: nvmlDevice_t,
+        index: c_uint,
+        migDevice: *mut nvmlDevice_t,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetDeviceHandleFromMigDeviceHandle(
+        migDevice: nvmlDevice_t,
+        device: *mut nvmlDevice_t,
+    ) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetGpuInstanceId(device: nvmlDevice_t, id: *mut c_uint) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetComputeInstanceId(device: nvmlDevice_t, id: *mut c_uint) -> nvmlReturn_t;
+    pub fn nvmlDeviceGetAttributes_v2(
+        device: nvmlDevice_t,
+        attributes: *mut nvmlDeviceAttributes_t,
+    ) -> nvmlReturn_t;
+}
@@ -3,4 +3,5 @@ use find_cuda_helper::find_libnvvm_bin_dir;
 fn main() {
     println!("cargo:rustc-link-search={}", find_libnvvm_bin_dir());
     println!("cargo:rustc-link-lib=dylib=nvvm");
+    println!("cargo:rerun-if-env-changed=CUDA_NVVM_ROOT");
 }
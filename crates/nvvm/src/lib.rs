@@ -182,7 +182,8 @@ impl FromStr for NvvmOption {
         let s = s.trim();
         Ok(match s {
             "-g" => Self::GenDebugInfo,
-            "-generate-line-info" => Self::GenLineInfo,
+            // the spelling of nvcc.
+            "-generate-line-info" | "-lineinfo" => Self::GenLineInfo,
             _ if s.starts_with("-opt=") => {
                 let slice = &s[5..];
                 if slice == "0" {
@@ -254,6 +255,8 @@ impl FromStr for NvvmOption {
                     "72" => NvvmArch::Compute72,
                     "75" => NvvmArch::Compute75,
                     "80" => NvvmArch::Compute80,
+                    "89" => NvvmArch::Compute89,
+                    "90" => NvvmArch::Compute90,
                     _ => return Err("unknown arch"),
                 };
                 Self::Arch(arch)
@@ -264,7 +267,9 @@ impl FromStr for NvvmOption {
 }
 
 /// Nvvm architecture, default is `Compute52`
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+///
+/// Architectures are ordered by their compute capability.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum NvvmArch {
     Compute35,
     Compute37,
@@ -278,6 +283,11 @@ pub enum NvvmArch {
     Compute72,
     Compute75,
     Compute80,
+    /// Ada Lovelace, required for the FP8 conversion instructions. Only supported by the NVVM of
+    /// CUDA 11.8 or newer.
+    Compute89,
+    /// Hopper, required for thread block clusters. Only supported by the NVVM of CUDA 12.0 or newer.
+    Compute90,
 }
 
 impl Display for NvvmArch {
@@ -288,6 +298,47 @@ impl Display for NvvmArch {
     }
 }
 
+impl NvvmArch {
+    /// Every architecture, from oldest to newest.
+    pub const ALL: &'static [NvvmArch] = &[
+        NvvmArch::Compute35,
+        NvvmArch::Compute37,
+        NvvmArch::Compute50,
+        NvvmArch::Compute52,
+        NvvmArch::Compute53,
+        NvvmArch::Compute60,
+        NvvmArch::Compute61,
+        NvvmArch::Compute62,
+        NvvmArch::Compute70,
+        NvvmArch::Compute72,
+        NvvmArch::Compute75,
+        NvvmArch::Compute80,
+        NvvmArch::Compute89,
+        NvvmArch::Compute90,
+    ];
+
+    /// The compute capability of this architecture as `major * 10 + minor`, for example `61` for
+    /// `Compute61`.
+    pub fn capability(&self) -> u32 {
+        match self {
+            Self::Compute35 => 35,
+            Self::Compute37 => 37,
+            Self::Compute50 => 50,
+            Self::Compute52 => 52,
+            Self::Compute53 => 53,
+            Self::Compute60 => 60,
+            Self::Compute61 => 61,
+            Self::Compute62 => 62,
+            Self::Compute70 => 70,
+            Self::Compute72 => 72,
+            Self::Compute75 => 75,
+            Self::Compute80 => 80,
+            Self::Compute89 => 89,
+            Self::Compute90 => 90,
+        }
+    }
+}
+
 impl Default for NvvmArch {
     fn default() -> Self {
         Self::Compute52
@@ -411,6 +462,19 @@ impl NvvmProgram {
 mod tests {
     use std::str::FromStr;
 
+    #[test]
+    fn arch_capabilities_are_ordered() {
+        use crate::NvvmArch;
+
+        for pair in NvvmArch::ALL.windows(2) {
+            assert!(pair[0] < pair[1]);
+            assert!(pair[0].capability() < pair[1].capability());
+        }
+        for arch in NvvmArch::ALL {
+            assert_eq!(arch.to_string(), format!("compute_{}", arch.capability()));
+        }
+    }
+
     #[test]
     fn options_parse_correctly() {
         use crate::NvvmArch::*;
@@ -432,6 +496,8 @@ mod tests {
             "-arch=compute_72",
             "-arch=compute_75",
             "-arch=compute_80",
+            "-arch=compute_89",
+            "-arch=compute_90",
             "-ftz=1",
             "-prec-sqrt=0",
             "-prec-div=0",
@@ -453,6 +519,8 @@ mod tests {
             Arch(Compute72),
             Arch(Compute75),
             Arch(Compute80),
+            Arch(Compute89),
+            Arch(Compute90),
             Ftz,
             FastSqrt,
             FastDiv,
@@ -465,5 +533,6 @@ mod tests {
             .collect::<Vec<_>>();
 
         assert_eq!(found, expected);
+        assert_eq!(NvvmOption::from_str("-lineinfo"), Ok(GenLineInfo));
     }
 }
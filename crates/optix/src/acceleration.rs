@@ -0,0 +1,1015 @@
+//! Acceleration structures, the bounding volume hierarchies OptiX traverses to find the
+//! primitives a ray intersects.
+//!
+//! A geometry acceleration structure (GAS) is built out of one or more build inputs of the same
+//! kind:
+//! - [`TriangleArray`], triangle meshes, intersected in hardware.
+//! - [`CurveArray`], round curves such as hair and fibers, intersected by the built-in curve
+//!   intersection programs.
+//! - [`CustomPrimitiveArray`], arbitrary primitives given by their bounding boxes, intersected by a
+//!   user intersection program (see [`device`](crate::device)). This is also how spheres and other
+//!   implicit surfaces are rendered, OptiX 7.3 does not have built-in spheres.
+//!
+//! ```no_run
+//! # use cust::prelude::*;
+//! # use optix::{context::OptixContext, acceleration::*};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let ctx = cust::quick_init()?;
+//! # optix::init()?;
+//! # let optix_ctx = OptixContext::new(&ctx)?;
+//! # let stream = Stream::new(StreamFlags::DEFAULT, None)?;
+//! let spheres = [([0.0, 0.0, -1.0], 0.5), ([0.0, -100.5, -1.0], 100.0)];
+//! let aabbs = spheres
+//!     .iter()
+//!     .map(|&(center, radius)| Aabb::sphere(center, radius))
+//!     .collect::<Vec<_>>();
+//! let aabbs = DeviceBuffer::from_slice(&aabbs)?;
+//!
+//! let input = CustomPrimitiveArray::new(&aabbs, &[GeometryFlags::DISABLE_ANYHIT]);
+//! let gas = Accel::build(
+//!     &optix_ctx,
+//!     &stream,
+//!     &AccelBuildOptions::new(BuildFlags::PREFER_FAST_TRACE | BuildFlags::ALLOW_COMPACTION),
+//!     &[input],
+//!     true,
+//! )?;
+//! // pass `gas.handle()` to the raygen program in the launch parameters ...
+//! # Ok(())
+//! # }
+//! ```
+
+use std::marker::PhantomData;
+
+use bitflags::bitflags;
+use cust::{
+    memory::{CopyDestination, DeviceBox, DeviceBuffer, DeviceCopy, DeviceSlice},
+    prelude::Stream,
+};
+
+use crate::{
+    context::OptixContext,
+    error::{OptixError, OptixResult},
+    optix_call, sys,
+};
+
+/// An opaque handle to a traversable object (an acceleration structure or a transform), which is
+/// passed to `optixTrace` on the GPU or referenced by instances.
+#[repr(transparent)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TraversableHandle(pub sys::OptixTraversableHandle);
+
+unsafe impl DeviceCopy for TraversableHandle {}
+
+bitflags! {
+    /// Flags controlling how an acceleration structure is built.
+    #[derive(Default)]
+    pub struct BuildFlags: u32 {
+        /// Allow the acceleration structure to be updated (refit) after being built.
+        const ALLOW_UPDATE = 1;
+        /// Allow the acceleration structure to be compacted.
+        const ALLOW_COMPACTION = 2;
+        /// Spend more time building to trace rays faster.
+        const PREFER_FAST_TRACE = 4;
+        /// Build faster at the cost of slower traversal.
+        const PREFER_FAST_BUILD = 8;
+        /// Allow access to the vertices of triangles and curves on the GPU.
+        const ALLOW_RANDOM_VERTEX_ACCESS = 16;
+        /// Allow access to the instances of an instance acceleration structure on the GPU.
+        const ALLOW_RANDOM_INSTANCE_ACCESS = 32;
+    }
+}
+
+bitflags! {
+    /// Flags for the primitives of a build input, one per SBT record.
+    #[derive(Default)]
+    pub struct GeometryFlags: u32 {
+        /// Never call the any-hit program for these primitives.
+        const DISABLE_ANYHIT = 1;
+        /// Call the any-hit program exactly once per primitive and ray.
+        const REQUIRE_SINGLE_ANYHIT_CALL = 2;
+    }
+}
+
+bitflags! {
+    /// Flags controlling what happens to an object outside of the time range of its motion.
+    #[derive(Default)]
+    pub struct MotionFlags: u16 {
+        /// The object disappears before the beginning of the time range instead of staying at
+        /// its first key.
+        const START_VANISH = 1;
+        /// The object disappears after the end of the time range instead of staying at its last
+        /// key.
+        const END_VANISH = 2;
+    }
+}
+
+/// The motion of an acceleration structure or motion transform: `num_keys` keys evenly spaced
+/// over the time range `time_begin..=time_end`, which is compared to the time of rays.
+///
+/// The default is a single key, which means no motion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MotionOptions {
+    pub num_keys: u16,
+    pub flags: MotionFlags,
+    pub time_begin: f32,
+    pub time_end: f32,
+}
+
+impl Default for MotionOptions {
+    fn default() -> Self {
+        Self {
+            num_keys: 1,
+            flags: MotionFlags::empty(),
+            time_begin: 0.0,
+            time_end: 0.0,
+        }
+    }
+}
+
+impl MotionOptions {
+    pub fn new(num_keys: u16, time_begin: f32, time_end: f32) -> Self {
+        Self {
+            num_keys,
+            flags: MotionFlags::empty(),
+            time_begin,
+            time_end,
+        }
+    }
+
+    pub fn flags(mut self, flags: MotionFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    pub fn to_raw(self) -> sys::OptixMotionOptions {
+        sys::OptixMotionOptions {
+            numKeys: self.num_keys,
+            flags: self.flags.bits(),
+            timeBegin: self.time_begin,
+            timeEnd: self.time_end,
+        }
+    }
+}
+
+/// Options for building an acceleration structure.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct AccelBuildOptions {
+    pub build_flags: BuildFlags,
+    pub motion_options: MotionOptions,
+}
+
+impl AccelBuildOptions {
+    pub fn new(build_flags: BuildFlags) -> Self {
+        Self {
+            build_flags,
+            motion_options: MotionOptions::default(),
+        }
+    }
+
+    /// Makes the acceleration structure interpolate between several keys over time. For an
+    /// instance acceleration structure, the instances are interpolated between the keys, which
+    /// lets motion transforms below it be traversed efficiently.
+    pub fn motion_options(mut self, motion_options: MotionOptions) -> Self {
+        self.motion_options = motion_options;
+        self
+    }
+
+    pub fn to_raw(self) -> sys::OptixAccelBuildOptions {
+        sys::OptixAccelBuildOptions {
+            buildFlags: self.build_flags.bits(),
+            operation: sys::OptixBuildOperation::OPTIX_BUILD_OPERATION_BUILD,
+            motionOptions: self.motion_options.to_raw(),
+        }
+    }
+}
+
+/// The memory needed to build an acceleration structure out of some build inputs.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AccelBufferSizes {
+    pub output_size_in_bytes: usize,
+    pub temp_size_in_bytes: usize,
+    pub temp_update_size_in_bytes: usize,
+}
+
+impl AccelBufferSizes {
+    pub fn from_raw(raw: sys::OptixAccelBufferSizes) -> Self {
+        Self {
+            output_size_in_bytes: raw.outputSizeInBytes,
+            temp_size_in_bytes: raw.tempSizeInBytes,
+            temp_update_size_in_bytes: raw.tempUpdateSizeInBytes,
+        }
+    }
+}
+
+/// An input an acceleration structure is built out of. Every input of an acceleration structure
+/// must be of the same kind, which is why they are given as a slice of a single type.
+pub trait BuildInput {
+    /// The raw build input, which may point into `self` and must not outlive it.
+    fn to_raw(&self) -> sys::OptixBuildInput;
+}
+
+/// The SBT records used by the primitives of a build input, shared by triangles and custom
+/// primitives.
+#[derive(Debug, Clone)]
+struct SbtRecords {
+    flags: Vec<u32>,
+    index_offsets: u64,
+    primitive_index_offset: u32,
+}
+
+impl SbtRecords {
+    fn new(flags: &[GeometryFlags]) -> Self {
+        assert!(
+            !flags.is_empty(),
+            "build inputs need the flags of at least one SBT record"
+        );
+        Self {
+            flags: flags.iter().map(|f| f.bits()).collect(),
+            index_offsets: 0,
+            primitive_index_offset: 0,
+        }
+    }
+}
+
+/// The vertex formats of triangles.
+pub trait Vertex: DeviceCopy {
+    const FORMAT: sys::OptixVertexFormat;
+}
+
+impl Vertex for [f32; 3] {
+    const FORMAT: sys::OptixVertexFormat = sys::OptixVertexFormat::OPTIX_VERTEX_FORMAT_FLOAT3;
+}
+
+impl Vertex for [f32; 2] {
+    const FORMAT: sys::OptixVertexFormat = sys::OptixVertexFormat::OPTIX_VERTEX_FORMAT_FLOAT2;
+}
+
+/// The index formats of triangles, three indices into the vertices per triangle.
+pub trait IndexTriple: DeviceCopy {
+    const FORMAT: sys::OptixIndicesFormat;
+}
+
+impl IndexTriple for [u32; 3] {
+    const FORMAT: sys::OptixIndicesFormat =
+        sys::OptixIndicesFormat::OPTIX_INDICES_FORMAT_UNSIGNED_INT3;
+}
+
+impl IndexTriple for [u16; 3] {
+    const FORMAT: sys::OptixIndicesFormat =
+        sys::OptixIndicesFormat::OPTIX_INDICES_FORMAT_UNSIGNED_SHORT3;
+}
+
+/// A triangle mesh, either a list of triangles (every three vertices) or indexed triangles.
+#[derive(Debug, Clone)]
+pub struct TriangleArray<'a> {
+    vertices: [u64; 1],
+    num_vertices: u32,
+    vertex_format: sys::OptixVertexFormat,
+    vertex_stride: u32,
+    indices: Option<(u64, u32, sys::OptixIndicesFormat, u32)>,
+    sbt: SbtRecords,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> TriangleArray<'a> {
+    /// A list of triangles made of every three vertices, with one SBT record per element of
+    /// `flags`.
+    pub fn new<V: Vertex>(vertices: &'a DeviceSlice<V>, flags: &[GeometryFlags]) -> Self {
+        Self {
+            vertices: [vertices.as_ptr() as u64],
+            num_vertices: vertices.len() as u32,
+            vertex_format: V::FORMAT,
+            vertex_stride: std::mem::size_of::<V>() as u32,
+            indices: None,
+            sbt: SbtRecords::new(flags),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Uses indexed triangles instead, each triangle is given by three indices into the vertices.
+    pub fn index_buffer<I: IndexTriple>(mut self, indices: &'a DeviceSlice<I>) -> Self {
+        self.indices = Some((
+            indices.as_ptr() as u64,
+            indices.len() as u32,
+            I::FORMAT,
+            std::mem::size_of::<I>() as u32,
+        ));
+        self
+    }
+
+    /// Sets the SBT record of every triangle, required if there is more than one SBT record.
+    pub fn sbt_index_offsets(mut self, offsets: &'a DeviceSlice<u32>) -> Self {
+        self.sbt.index_offsets = offsets.as_ptr() as u64;
+        self
+    }
+
+    /// Sets the offset added to the primitive indices of this input, as seen by programs.
+    pub fn primitive_index_offset(mut self, offset: u32) -> Self {
+        self.sbt.primitive_index_offset = offset;
+        self
+    }
+}
+
+impl BuildInput for TriangleArray<'_> {
+    fn to_raw(&self) -> sys::OptixBuildInput {
+        let (index_buffer, num_triplets, index_format, index_stride) =
+            self.indices
+                .unwrap_or((0, 0, sys::OptixIndicesFormat::OPTIX_INDICES_FORMAT_NONE, 0));
+        let mut raw = sys::OptixBuildInput {
+            type_: sys::OptixBuildInputType::OPTIX_BUILD_INPUT_TYPE_TRIANGLES,
+            ..Default::default()
+        };
+        unsafe {
+            *raw.__bindgen_anon_1.triangleArray.as_mut() = sys::OptixBuildInputTriangleArray {
+                vertexBuffers: self.vertices.as_ptr(),
+                numVertices: self.num_vertices,
+                vertexFormat: self.vertex_format,
+                vertexStrideInBytes: self.vertex_stride,
+                indexBuffer: index_buffer,
+                numIndexTriplets: num_triplets,
+                indexFormat: index_format,
+                indexStrideInBytes: index_stride,
+                flags: self.sbt.flags.as_ptr(),
+                numSbtRecords: self.sbt.flags.len() as u32,
+                sbtIndexOffsetBuffer: self.sbt.index_offsets,
+                sbtIndexOffsetSizeInBytes: if self.sbt.index_offsets == 0 { 0 } else { 4 },
+                primitiveIndexOffset: self.sbt.primitive_index_offset,
+                ..Default::default()
+            };
+        }
+        raw
+    }
+}
+
+/// The kind of the segments of curves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CurveType {
+    /// Straight segments between two control points.
+    Linear,
+    /// Quadratic B-splines, with three control points per segment.
+    QuadraticBSpline,
+    /// Cubic B-splines, with four control points per segment.
+    CubicBSpline,
+}
+
+impl CurveType {
+    pub fn to_raw(self) -> sys::OptixPrimitiveType {
+        match self {
+            Self::Linear => sys::OptixPrimitiveType::OPTIX_PRIMITIVE_TYPE_ROUND_LINEAR,
+            Self::QuadraticBSpline => {
+                sys::OptixPrimitiveType::OPTIX_PRIMITIVE_TYPE_ROUND_QUADRATIC_BSPLINE
+            }
+            Self::CubicBSpline => sys::OptixPrimitiveType::OPTIX_PRIMITIVE_TYPE_ROUND_CUBIC_BSPLINE,
+        }
+    }
+
+    /// The number of control points of a single segment.
+    pub fn control_points(self) -> usize {
+        match self {
+            Self::Linear => 2,
+            Self::QuadraticBSpline => 3,
+            Self::CubicBSpline => 4,
+        }
+    }
+}
+
+/// Round curves of varying width, such as hair or fibers, made of segments of control points.
+///
+/// The pipeline must be created with the primitive type of the curves enabled and use the
+/// built-in intersection program for the curve type (`optixBuiltinISModuleGet`).
+#[derive(Debug, Clone)]
+pub struct CurveArray<'a> {
+    curve_type: CurveType,
+    vertices: [u64; 1],
+    widths: [u64; 1],
+    num_vertices: u32,
+    segments: u64,
+    num_segments: u32,
+    flags: GeometryFlags,
+    primitive_index_offset: u32,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> CurveArray<'a> {
+    /// Curves made of `vertices` control points with the given `widths` (the radius of the curve
+    /// at every control point). Every element of `segments` is the index of the first control
+    /// point of a segment, whose control points are consecutive.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `widths` and `vertices` have different lengths.
+    pub fn new(
+        curve_type: CurveType,
+        vertices: &'a DeviceSlice<[f32; 3]>,
+        widths: &'a DeviceSlice<f32>,
+        segments: &'a DeviceSlice<u32>,
+        flags: GeometryFlags,
+    ) -> Self {
+        assert_eq!(
+            vertices.len(),
+            widths.len(),
+            "curves need one width per control point"
+        );
+        Self {
+            curve_type,
+            vertices: [vertices.as_ptr() as u64],
+            widths: [widths.as_ptr() as u64],
+            num_vertices: vertices.len() as u32,
+            segments: segments.as_ptr() as u64,
+            num_segments: segments.len() as u32,
+            flags,
+            primitive_index_offset: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the offset added to the primitive indices of this input, as seen by programs.
+    pub fn primitive_index_offset(mut self, offset: u32) -> Self {
+        self.primitive_index_offset = offset;
+        self
+    }
+}
+
+impl BuildInput for CurveArray<'_> {
+    fn to_raw(&self) -> sys::OptixBuildInput {
+        let mut raw = sys::OptixBuildInput {
+            type_: sys::OptixBuildInputType::OPTIX_BUILD_INPUT_TYPE_CURVES,
+            ..Default::default()
+        };
+        unsafe {
+            *raw.__bindgen_anon_1.curveArray.as_mut() = sys::OptixBuildInputCurveArray {
+                curveType: self.curve_type.to_raw(),
+                numPrimitives: self.num_segments,
+                vertexBuffers: self.vertices.as_ptr(),
+                numVertices: self.num_vertices,
+                vertexStrideInBytes: 12,
+                widthBuffers: self.widths.as_ptr(),
+                widthStrideInBytes: 4,
+                indexBuffer: self.segments,
+                indexStrideInBytes: 4,
+                flag: self.flags.bits(),
+                primitiveIndexOffset: self.primitive_index_offset,
+                ..Default::default()
+            };
+        }
+        raw
+    }
+}
+
+/// An axis-aligned bounding box of a custom primitive.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+unsafe impl DeviceCopy for Aabb {}
+
+impl Aabb {
+    pub fn new(min: [f32; 3], max: [f32; 3]) -> Self {
+        Self { min, max }
+    }
+
+    /// The bounding box of a sphere.
+    pub fn sphere(center: [f32; 3], radius: f32) -> Self {
+        let radius = radius.abs();
+        Self {
+            min: center.map(|c| c - radius),
+            max: center.map(|c| c + radius),
+        }
+    }
+}
+
+/// Custom primitives given by their bounding boxes, whose intersections with rays are computed by
+/// the intersection program of their SBT record.
+#[derive(Debug, Clone)]
+pub struct CustomPrimitiveArray<'a> {
+    aabbs: [u64; 1],
+    num_primitives: u32,
+    sbt: SbtRecords,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> CustomPrimitiveArray<'a> {
+    /// Primitives with the given bounding boxes, with one SBT record per element of `flags`.
+    pub fn new(aabbs: &'a DeviceSlice<Aabb>, flags: &[GeometryFlags]) -> Self {
+        Self {
+            aabbs: [aabbs.as_ptr() as u64],
+            num_primitives: aabbs.len() as u32,
+            sbt: SbtRecords::new(flags),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Sets the SBT record of every primitive, required if there is more than one SBT record.
+    pub fn sbt_index_offsets(mut self, offsets: &'a DeviceSlice<u32>) -> Self {
+        self.sbt.index_offsets = offsets.as_ptr() as u64;
+        self
+    }
+
+    /// Sets the offset added to the primitive indices of this input, as seen by programs.
+    pub fn primitive_index_offset(mut self, offset: u32) -> Self {
+        self.sbt.primitive_index_offset = offset;
+        self
+    }
+}
+
+impl BuildInput for CustomPrimitiveArray<'_> {
+    fn to_raw(&self) -> sys::OptixBuildInput {
+        let mut raw = sys::OptixBuildInput {
+            type_: sys::OptixBuildInputType::OPTIX_BUILD_INPUT_TYPE_CUSTOM_PRIMITIVES,
+            ..Default::default()
+        };
+        unsafe {
+            *raw.__bindgen_anon_1.customPrimitiveArray.as_mut() =
+                sys::OptixBuildInputCustomPrimitiveArray {
+                    aabbBuffers: self.aabbs.as_ptr(),
+                    numPrimitives: self.num_primitives,
+                    strideInBytes: std::mem::size_of::<Aabb>() as u32,
+                    flags: self.sbt.flags.as_ptr(),
+                    numSbtRecords: self.sbt.flags.len() as u32,
+                    sbtIndexOffsetBuffer: self.sbt.index_offsets,
+                    sbtIndexOffsetSizeInBytes: if self.sbt.index_offsets == 0 { 0 } else { 4 },
+                    sbtIndexOffsetStrideInBytes: 0,
+                    primitiveIndexOffset: self.sbt.primitive_index_offset,
+                };
+        }
+        raw
+    }
+}
+
+bitflags! {
+    /// Flags for an instance.
+    #[derive(Default)]
+    pub struct InstanceFlags: u32 {
+        /// Hit both sides of the triangles of the instance.
+        const DISABLE_TRIANGLE_FACE_CULLING = 1;
+        /// Swap the front and back faces of the triangles of the instance.
+        const FLIP_TRIANGLE_FACING = 2;
+        /// Never call the any-hit programs of the instance, overriding the geometry flags.
+        const DISABLE_ANYHIT = 4;
+        /// Always call the any-hit programs of the instance, overriding the geometry flags.
+        const ENFORCE_ANYHIT = 8;
+        /// Ignore the transform of the instance, which is treated as the identity.
+        const DISABLE_TRANSFORM = 64;
+    }
+}
+
+/// The identity transform of an instance.
+pub const IDENTITY_TRANSFORM: [f32; 12] =
+    [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+
+/// An instance of an acceleration structure or transform in an instance acceleration structure,
+/// laid out like `OptixInstance` so that instances can be written on the GPU as well.
+#[repr(C, align(16))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instance {
+    transform: [f32; 12],
+    instance_id: u32,
+    sbt_offset: u32,
+    visibility_mask: u32,
+    flags: u32,
+    traversable_handle: TraversableHandle,
+    pad: [u32; 2],
+}
+
+unsafe impl DeviceCopy for Instance {}
+
+impl Instance {
+    /// An instance of `handle` with the identity transform, which is visible to every ray.
+    pub fn new(handle: TraversableHandle) -> Self {
+        Self {
+            transform: IDENTITY_TRANSFORM,
+            instance_id: 0,
+            sbt_offset: 0,
+            visibility_mask: 255,
+            flags: 0,
+            traversable_handle: handle,
+            pad: [0; 2],
+        }
+    }
+
+    /// Sets the transform from object space to world space, the first three rows of a row-major
+    /// 4x4 affine matrix.
+    pub fn transform(mut self, transform: [f32; 12]) -> Self {
+        self.transform = transform;
+        self
+    }
+
+    /// Sets the user ID of the instance, as seen by programs.
+    pub fn instance_id(mut self, id: u32) -> Self {
+        self.instance_id = id;
+        self
+    }
+
+    /// Sets the offset of the SBT records of the instance.
+    pub fn sbt_offset(mut self, offset: u32) -> Self {
+        self.sbt_offset = offset;
+        self
+    }
+
+    /// Sets the visibility mask of the instance, only rays whose mask has a bit in common with it
+    /// intersect the instance. Only the lower 8 bits are used.
+    pub fn visibility_mask(mut self, mask: u8) -> Self {
+        self.visibility_mask = mask as u32;
+        self
+    }
+
+    pub fn flags(mut self, flags: InstanceFlags) -> Self {
+        self.flags = flags.bits();
+        self
+    }
+}
+
+/// The instances of an instance acceleration structure (IAS), which places acceleration structures
+/// and transforms in the scene. An IAS takes a single instance array.
+#[derive(Debug, Clone)]
+pub struct InstanceArray<'a> {
+    instances: u64,
+    num_instances: u32,
+    _marker: PhantomData<&'a ()>,
+}
+
+impl<'a> InstanceArray<'a> {
+    pub fn new(instances: &'a DeviceSlice<Instance>) -> Self {
+        Self {
+            instances: instances.as_ptr() as u64,
+            num_instances: instances.len() as u32,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl BuildInput for InstanceArray<'_> {
+    fn to_raw(&self) -> sys::OptixBuildInput {
+        let mut raw = sys::OptixBuildInput {
+            type_: sys::OptixBuildInputType::OPTIX_BUILD_INPUT_TYPE_INSTANCES,
+            ..Default::default()
+        };
+        unsafe {
+            *raw.__bindgen_anon_1.instanceArray.as_mut() = sys::OptixBuildInputInstanceArray {
+                instances: self.instances,
+                numInstances: self.num_instances,
+            };
+        }
+        raw
+    }
+}
+
+/// A built acceleration structure, which owns the device memory it is stored in.
+#[derive(Debug)]
+pub struct Accel {
+    buffer: DeviceBuffer<u8>,
+    handle: TraversableHandle,
+}
+
+impl Accel {
+    /// The memory needed to build an acceleration structure out of `inputs`.
+    pub fn compute_memory_usage<I: BuildInput>(
+        ctx: &OptixContext,
+        options: &AccelBuildOptions,
+        inputs: &[I],
+    ) -> OptixResult<AccelBufferSizes> {
+        let raw_inputs = inputs.iter().map(|i| i.to_raw()).collect::<Vec<_>>();
+        let mut sizes = sys::OptixAccelBufferSizes::default();
+        unsafe {
+            optix_call!(optixAccelComputeMemoryUsage(
+                ctx.raw,
+                &options.to_raw(),
+                raw_inputs.as_ptr(),
+                raw_inputs.len() as u32,
+                &mut sizes,
+            ))?;
+        }
+        Ok(AccelBufferSizes::from_raw(sizes))
+    }
+
+    /// Builds an acceleration structure out of `inputs` on `stream`, then compacts it if
+    /// `compact` is true, which requires [`BuildFlags::ALLOW_COMPACTION`].
+    ///
+    /// Compaction usually halves the memory used by the acceleration structure, but needs to wait
+    /// for the build to finish, this function then synchronizes the stream. The build inputs
+    /// must not be freed or modified until the build finished.
+    pub fn build<I: BuildInput>(
+        ctx: &OptixContext,
+        stream: &Stream,
+        options: &AccelBuildOptions,
+        inputs: &[I],
+        compact: bool,
+    ) -> OptixResult<Self> {
+        if compact && !options.build_flags.contains(BuildFlags::ALLOW_COMPACTION) {
+            return Err(OptixError::InvalidValue);
+        }
+        let raw_options = options.to_raw();
+        let raw_inputs = inputs.iter().map(|i| i.to_raw()).collect::<Vec<_>>();
+        let sizes = Self::compute_memory_usage(ctx, options, inputs)?;
+
+        let temp = unsafe { DeviceBuffer::<u8>::uninitialized(sizes.temp_size_in_bytes) }?;
+        let buffer = unsafe { DeviceBuffer::<u8>::uninitialized(sizes.output_size_in_bytes) }?;
+        let mut compacted_size = DeviceBox::new(&0u64)?;
+        let emitted = sys::OptixAccelEmitDesc {
+            result: compacted_size.as_device_ptr().as_raw() as u64,
+            type_: sys::OptixAccelPropertyType::OPTIX_PROPERTY_TYPE_COMPACTED_SIZE,
+        };
+
+        let mut handle = 0;
+        unsafe {
+            optix_call!(optixAccelBuild(
+                ctx.raw,
+                stream.as_inner(),
+                &raw_options,
+                raw_inputs.as_ptr(),
+                raw_inputs.len() as u32,
+                temp.as_ptr() as u64,
+                temp.len(),
+                buffer.as_ptr() as u64,
+                buffer.len(),
+                &mut handle,
+                if compact { &emitted } else { std::ptr::null() },
+                compact as u32,
+            ))?;
+        }
+        // the temporary buffer and the build inputs are in use until the build finished.
+        stream.synchronize()?;
+
+        let accel = Self {
+            buffer,
+            handle: TraversableHandle(handle),
+        };
+        if compact {
+            let mut size = 0u64;
+            compacted_size.copy_to(&mut size)?;
+            if (size as usize) < accel.buffer.len() {
+                return accel.compact(ctx, stream, size as usize);
+            }
+        }
+        Ok(accel)
+    }
+
+    /// Compacts this acceleration structure into a new buffer of `size` bytes.
+    fn compact(&self, ctx: &OptixContext, stream: &Stream, size: usize) -> OptixResult<Self> {
+        let buffer = unsafe { DeviceBuffer::<u8>::uninitialized(size) }?;
+        let mut handle = 0;
+        unsafe {
+            optix_call!(optixAccelCompact(
+                ctx.raw,
+                stream.as_inner(),
+                self.handle.0,
+                buffer.as_ptr() as u64,
+                buffer.len(),
+                &mut handle,
+            ))?;
+        }
+        // the old acceleration structure is freed when returning.
+        stream.synchronize()?;
+        Ok(Self {
+            buffer,
+            handle: TraversableHandle(handle),
+        })
+    }
+
+    /// The handle to this acceleration structure, to be traced against or instanced.
+    pub fn handle(&self) -> TraversableHandle {
+        self.handle
+    }
+
+    /// The device memory this acceleration structure is stored in.
+    pub fn buffer(&self) -> &DeviceSlice<u8> {
+        &self.buffer
+    }
+}
+
+/// A scale, rotation and translation, the key of an SRT motion transform. Unlike matrices, SRT
+/// keys are interpolated with a spherical interpolation of the rotation, which keeps rotating
+/// objects rigid.
+///
+/// The transform is `translation * rotation * S`, where `S` is the upper triangular matrix of the
+/// scale and shear, with `pivot` as its translation. To rotate around a point `p`, set the pivot to
+/// `-p` and add `p` to the translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SrtData {
+    pub scale: [f32; 3],
+    /// The `a`, `b` and `c` (xy, xz and yz) shear factors.
+    pub shear: [f32; 3],
+    pub pivot: [f32; 3],
+    /// A unit quaternion, `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub translation: [f32; 3],
+}
+
+impl Default for SrtData {
+    fn default() -> Self {
+        Self {
+            scale: [1.0; 3],
+            shear: [0.0; 3],
+            pivot: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            translation: [0.0; 3],
+        }
+    }
+}
+
+impl SrtData {
+    /// The key in the order of the fields of `OptixSRTData`.
+    fn to_raw(self) -> [f32; 16] {
+        let [sx, sy, sz] = self.scale;
+        let [a, b, c] = self.shear;
+        let [pvx, pvy, pvz] = self.pivot;
+        let [qx, qy, qz, qw] = self.rotation;
+        let [tx, ty, tz] = self.translation;
+        [
+            sx, a, b, pvx, sy, c, pvy, sz, pvz, qx, qy, qz, qw, tx, ty, tz,
+        ]
+    }
+}
+
+/// A transform node, which transforms the acceleration structure or transform below it. Transforms
+/// are instanced like acceleration structures, and are needed for motion blur of rigid objects:
+/// the motion of an instance is given by placing a motion transform between the instance and the
+/// acceleration structure.
+///
+/// ```no_run
+/// # use optix::{context::OptixContext, acceleration::*};
+/// # fn f(ctx: &OptixContext, gas: &Accel) -> optix::error::OptixResult<()> {
+/// // a rigid object moving along x during the shutter interval 0..=1.
+/// let moving = Transform::srt_motion(
+///     ctx,
+///     gas.handle(),
+///     MotionOptions::new(2, 0.0, 1.0),
+///     &[
+///         SrtData::default(),
+///         SrtData { translation: [1.0, 0.0, 0.0], ..Default::default() },
+///     ],
+/// )?;
+/// let instance = Instance::new(moving.handle());
+/// // build the IAS with `AccelBuildOptions::motion_options`, and trace rays with a time ...
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct Transform {
+    buffer: DeviceBuffer<u8>,
+    handle: TraversableHandle,
+}
+
+impl Transform {
+    /// A static transform of `child` from object space to world space, the first three rows of a
+    /// row-major 4x4 affine matrix. Returns [`OptixError::InvalidValue`] if the matrix is not
+    /// invertible.
+    pub fn new_static(
+        ctx: &OptixContext,
+        child: TraversableHandle,
+        transform: [f32; 12],
+    ) -> OptixResult<Self> {
+        let inverse = invert_affine(&transform).ok_or(OptixError::InvalidValue)?;
+        let mut bytes = Vec::with_capacity(112);
+        bytes.extend_from_slice(&child.0.to_ne_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        extend_f32(&mut bytes, &transform);
+        extend_f32(&mut bytes, &inverse);
+        Self::upload(
+            ctx,
+            &bytes,
+            sys::OptixTraversableType::OPTIX_TRAVERSABLE_TYPE_STATIC_TRANSFORM,
+        )
+    }
+
+    /// A transform of `child` interpolated linearly between matrices, the first three rows of
+    /// row-major 4x4 affine matrices, one per motion key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are less than 2 keys, or if the number of keys of `motion` is not the
+    /// number of matrices.
+    pub fn matrix_motion(
+        ctx: &OptixContext,
+        child: TraversableHandle,
+        motion: MotionOptions,
+        keys: &[[f32; 12]],
+    ) -> OptixResult<Self> {
+        let bytes = motion_transform_bytes(child, motion, keys);
+        Self::upload(
+            ctx,
+            &bytes,
+            sys::OptixTraversableType::OPTIX_TRAVERSABLE_TYPE_MATRIX_MOTION_TRANSFORM,
+        )
+    }
+
+    /// A transform of `child` interpolated between scale, rotation and translation keys, one per
+    /// motion key.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are less than 2 keys, or if the number of keys of `motion` is not the
+    /// number of SRT keys.
+    pub fn srt_motion(
+        ctx: &OptixContext,
+        child: TraversableHandle,
+        motion: MotionOptions,
+        keys: &[SrtData],
+    ) -> OptixResult<Self> {
+        let keys = keys.iter().map(|k| k.to_raw()).collect::<Vec<_>>();
+        let bytes = motion_transform_bytes(child, motion, &keys);
+        Self::upload(
+            ctx,
+            &bytes,
+            sys::OptixTraversableType::OPTIX_TRAVERSABLE_TYPE_SRT_MOTION_TRANSFORM,
+        )
+    }
+
+    fn upload(
+        ctx: &OptixContext,
+        bytes: &[u8],
+        ty: sys::OptixTraversableType,
+    ) -> OptixResult<Self> {
+        // device allocations are aligned to at least 256 bytes, which satisfies the 64 byte
+        // alignment OptiX requires for transforms.
+        let buffer = DeviceBuffer::from_slice(bytes)?;
+        let mut handle = 0;
+        unsafe {
+            optix_call!(optixConvertPointerToTraversableHandle(
+                ctx.raw,
+                buffer.as_ptr() as u64,
+                ty,
+                &mut handle,
+            ))?;
+        }
+        Ok(Self {
+            buffer,
+            handle: TraversableHandle(handle),
+        })
+    }
+
+    /// The handle to this transform, to be instanced.
+    pub fn handle(&self) -> TraversableHandle {
+        self.handle
+    }
+
+    /// The device memory this transform is stored in.
+    pub fn buffer(&self) -> &DeviceSlice<u8> {
+        &self.buffer
+    }
+}
+
+fn extend_f32(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_ne_bytes());
+    }
+}
+
+/// The bytes of an `OptixMatrixMotionTransform` or `OptixSRTMotionTransform`, which are declared
+/// with two keys but followed by the remaining keys in memory.
+fn motion_transform_bytes<const N: usize>(
+    child: TraversableHandle,
+    motion: MotionOptions,
+    keys: &[[f32; N]],
+) -> Vec<u8> {
+    assert!(keys.len() >= 2, "motion transforms need at least 2 keys");
+    assert_eq!(
+        motion.num_keys as usize,
+        keys.len(),
+        "the motion options must have one key per transform key"
+    );
+    let raw = motion.to_raw();
+    let mut bytes = Vec::with_capacity(32 + keys.len() * N * 4);
+    bytes.extend_from_slice(&child.0.to_ne_bytes());
+    bytes.extend_from_slice(&raw.numKeys.to_ne_bytes());
+    bytes.extend_from_slice(&raw.flags.to_ne_bytes());
+    extend_f32(&mut bytes, &[raw.timeBegin, raw.timeEnd]);
+    bytes.extend_from_slice(&[0; 12]);
+    for key in keys {
+        extend_f32(&mut bytes, key);
+    }
+    bytes
+}
+
+/// The inverse of an affine transform given by the first three rows of a row-major 4x4 matrix.
+fn invert_affine(m: &[f32; 12]) -> Option<[f32; 12]> {
+    let [a, b, c, tx, d, e, f, ty, g, h, i, tz] = *m;
+    let det = a * (e * i - f * h) - b * (d * i - f * g) + c * (d * h - e * g);
+    if det == 0.0 || !det.is_finite() {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let r = [
+        (e * i - f * h) * inv_det,
+        (c * h - b * i) * inv_det,
+        (b * f - c * e) * inv_det,
+        (f * g - d * i) * inv_det,
+        (a * i - c * g) * inv_det,
+        (c * d - a * f) * inv_det,
+        (d * h - e * g) * inv_det,
+        (b * g - a * h) * inv_det,
+        (a * e - b * d) * inv_det,
+    ];
+    // the inverse translation is -R^-1 * t.
+    let t = [
+        -(r[0] * tx + r[1] * ty + r[2] * tz),
+        -(r[3] * tx + r[4] * ty + r[5] * tz),
+        -(r[6] * tx + r[7] * ty + r[8] * tz),
+    ];
+    Some([
+        r[0], r[1], r[2], t[0], r[3], r[4], r[5], t[1], r[6], r[7], r[8], t[2],
+    ])
+}
@@ -0,0 +1,248 @@
+//! Functions for OptiX programs running on the GPU, the Rust equivalent of the device functions of
+//! `optix_device.h`. Only available when compiling for the GPU (with `CudaBuilder::optix`).
+//!
+//! Custom primitives (see [`CustomPrimitiveArray`](crate::acceleration)) are intersected by an
+//! intersection program, which computes where the current ray hits the primitive and reports
+//! the hit with [`report_intersection`]. For example for spheres whose centers and radii are in
+//! the SBT record:
+//!
+//! ```ignore
+//! use optix::device::*;
+//!
+//! #[repr(C)]
+//! pub struct Sphere {
+//!     center: [f32; 3],
+//!     radius: f32,
+//! }
+//!
+//! #[no_mangle]
+//! pub unsafe extern "C" fn __intersection__sphere() {
+//!     let spheres = sbt_data_pointer() as *const Sphere;
+//!     let sphere = &*spheres.add(primitive_index() as usize);
+//!     let [ox, oy, oz] = object_ray_origin();
+//!     let [dx, dy, dz] = object_ray_direction();
+//!     let o = [ox - sphere.center[0], oy - sphere.center[1], oz - sphere.center[2]];
+//!
+//!     let a = dx * dx + dy * dy + dz * dz;
+//!     let half_b = o[0] * dx + o[1] * dy + o[2] * dz;
+//!     let c = o[0] * o[0] + o[1] * o[1] + o[2] * o[2] - sphere.radius * sphere.radius;
+//!     let discriminant = half_b * half_b - a * c;
+//!     if discriminant >= 0.0 {
+//!         let t = (-half_b - discriminant.sqrt()) / a;
+//!         if t > ray_tmin() && t < ray_tmax() {
+//!             report_intersection(t, 0, []);
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! OptiX provides the entry points of programs with the name prefixes `__raygen__`,
+//! `__intersection__`, `__anyhit__`, `__closesthit__`, `__miss__` and so on, which must be
+//! `#[no_mangle]`.
+
+/// Reads an OptiX-provided value of a single register.
+macro_rules! read {
+    ($reg:ident, $name:literal) => {{
+        let out;
+        asm!(concat!("call ({}), ", $name, ", ();"), out($reg) out);
+        out
+    }};
+}
+
+macro_rules! read_vec3 {
+    ($name:ident, $op:literal, $doc:literal) => {
+        #[doc = $doc]
+        #[inline(always)]
+        pub fn $name() -> [f32; 3] {
+            unsafe {
+                [
+                    read!(reg32, concat!($op, "_x")),
+                    read!(reg32, concat!($op, "_y")),
+                    read!(reg32, concat!($op, "_z")),
+                ]
+            }
+        }
+    };
+}
+
+read_vec3!(
+    world_ray_origin,
+    "_optix_get_world_ray_origin",
+    "The origin of the current ray in world space."
+);
+read_vec3!(
+    world_ray_direction,
+    "_optix_get_world_ray_direction",
+    "The direction of the current ray in world space."
+);
+read_vec3!(
+    object_ray_origin,
+    "_optix_get_object_ray_origin",
+    "The origin of the current ray in the object space of the current primitive, only available \
+     in intersection and any-hit programs."
+);
+read_vec3!(
+    object_ray_direction,
+    "_optix_get_object_ray_direction",
+    "The direction of the current ray in the object space of the current primitive, only \
+     available in intersection and any-hit programs."
+);
+
+/// The index of the current launch, in `0..launch_dimensions()`.
+#[inline(always)]
+pub fn launch_index() -> [u32; 3] {
+    unsafe {
+        [
+            read!(reg32, "_optix_get_launch_index_x"),
+            read!(reg32, "_optix_get_launch_index_y"),
+            read!(reg32, "_optix_get_launch_index_z"),
+        ]
+    }
+}
+
+/// The dimensions of the current launch.
+#[inline(always)]
+pub fn launch_dimensions() -> [u32; 3] {
+    unsafe {
+        [
+            read!(reg32, "_optix_get_launch_dimension_x"),
+            read!(reg32, "_optix_get_launch_dimension_y"),
+            read!(reg32, "_optix_get_launch_dimension_z"),
+        ]
+    }
+}
+
+/// The minimum distance along the current ray at which hits are reported.
+#[inline(always)]
+pub fn ray_tmin() -> f32 {
+    unsafe { read!(reg32, "_optix_get_ray_tmin") }
+}
+
+/// The maximum distance along the current ray at which hits are reported, which is the distance
+/// of the closest hit so far in intersection and any-hit programs.
+#[inline(always)]
+pub fn ray_tmax() -> f32 {
+    unsafe { read!(reg32, "_optix_get_ray_tmax") }
+}
+
+/// The time of the current ray, used for motion blur.
+#[inline(always)]
+pub fn ray_time() -> f32 {
+    unsafe { read!(reg32, "_optix_get_ray_time") }
+}
+
+/// The index of the current primitive within its build input, plus the primitive index offset of
+/// the build input.
+#[inline(always)]
+pub fn primitive_index() -> u32 {
+    unsafe { read!(reg32, "_optix_read_primitive_idx") }
+}
+
+/// The user ID of the instance of the current primitive.
+#[inline(always)]
+pub fn instance_id() -> u32 {
+    unsafe { read!(reg32, "_optix_read_instance_id") }
+}
+
+/// The index of the instance of the current primitive within its instance acceleration structure.
+#[inline(always)]
+pub fn instance_index() -> u32 {
+    unsafe { read!(reg32, "_optix_read_instance_idx") }
+}
+
+/// The hit kind of the current hit, as given to [`report_intersection`] or one of the built-in hit
+/// kinds for triangles and curves.
+#[inline(always)]
+pub fn hit_kind() -> u32 {
+    unsafe { read!(reg32, "_optix_get_hit_kind") }
+}
+
+/// The parameter along the curve segment of the current hit, in `0..=1`, only available for hits
+/// on curves.
+#[inline(always)]
+pub fn curve_parameter() -> f32 {
+    unsafe { read!(reg32, "_optix_get_curve_parameter") }
+}
+
+/// A pointer to the data of the SBT record of the current program, after its header.
+#[inline(always)]
+pub fn sbt_data_pointer() -> *const u8 {
+    unsafe { read!(reg64, "_optix_get_sbt_data_ptr_64") }
+}
+
+/// Reports a hit at distance `t` along the current ray from an intersection program, with a
+/// `hit_kind` of at most 127 and up to 8 attributes which can be read by the hit programs with
+/// [`attribute`]. Returns whether the hit was accepted, which is the case if `t` is within the
+/// ray's interval and the any-hit program did not ignore it.
+#[inline(always)]
+pub fn report_intersection<const N: usize>(t: f32, hit_kind: u32, attributes: [u32; N]) -> bool {
+    assert!(N <= 8, "at most 8 attributes can be reported");
+    let out: u32;
+    if N == 0 {
+        unsafe {
+            asm!(
+                "call ({}), _optix_report_intersection_0, ({}, {});",
+                out(reg32) out,
+                in(reg32) t,
+                in(reg32) hit_kind,
+            );
+        }
+    } else {
+        // always pass all 8 attribute registers, reading the ones which were not reported is
+        // undefined anyway.
+        let mut a = [0u32; 8];
+        a[..N].copy_from_slice(&attributes);
+        unsafe {
+            asm!(
+                "call ({}), _optix_report_intersection_8, ({}, {}, {}, {}, {}, {}, {}, {}, {}, {});",
+                out(reg32) out,
+                in(reg32) t,
+                in(reg32) hit_kind,
+                in(reg32) a[0],
+                in(reg32) a[1],
+                in(reg32) a[2],
+                in(reg32) a[3],
+                in(reg32) a[4],
+                in(reg32) a[5],
+                in(reg32) a[6],
+                in(reg32) a[7],
+            );
+        }
+    }
+    out != 0
+}
+
+/// Reads attribute `index` (less than 8) of the current hit, as reported with
+/// [`report_intersection`].
+#[inline(always)]
+pub fn attribute(index: u32) -> u32 {
+    unsafe {
+        match index {
+            0 => read!(reg32, "_optix_get_attribute_0"),
+            1 => read!(reg32, "_optix_get_attribute_1"),
+            2 => read!(reg32, "_optix_get_attribute_2"),
+            3 => read!(reg32, "_optix_get_attribute_3"),
+            4 => read!(reg32, "_optix_get_attribute_4"),
+            5 => read!(reg32, "_optix_get_attribute_5"),
+            6 => read!(reg32, "_optix_get_attribute_6"),
+            7 => read!(reg32, "_optix_get_attribute_7"),
+            _ => 0,
+        }
+    }
+}
+
+/// Discards the current hit from an any-hit program, traversal continues as if it never happened.
+#[inline(always)]
+pub fn ignore_intersection() {
+    unsafe {
+        asm!("call _optix_ignore_intersection, ();");
+    }
+}
+
+/// Stops traversal from an any-hit program, accepting the current hit as the closest one.
+#[inline(always)]
+pub fn terminate_ray() {
+    unsafe {
+        asm!("call _optix_terminate_ray, ();");
+    }
+}
@@ -1,13 +1,26 @@
+#![cfg_attr(target_os = "cuda", no_std, feature(asm))]
+
+#[cfg(not(target_os = "cuda"))]
+pub mod acceleration;
+#[cfg(not(target_os = "cuda"))]
 pub mod context;
+#[cfg(not(target_os = "cuda"))]
 pub mod denoiser;
+#[cfg(target_os = "cuda")]
+pub mod device;
+#[cfg(not(target_os = "cuda"))]
 pub mod error;
 
+#[cfg(not(target_os = "cuda"))]
 pub use cust;
+#[cfg(not(target_os = "cuda"))]
 use error::{OptixResult, ToResult};
+#[cfg(not(target_os = "cuda"))]
 pub use optix_sys as sys;
 
 /// Initializes the OptiX library. This must be called before using any OptiX function. It may
 /// be called before or after initializing CUDA.
+#[cfg(not(target_os = "cuda"))]
 pub fn init() -> OptixResult<()> {
     // avoid initializing multiple times because that will try to load the dll every time.
     if !optix_is_initialized() {
@@ -17,6 +30,7 @@ pub fn init() -> OptixResult<()> {
     }
 }
 
+#[cfg(not(target_os = "cuda"))]
 #[cold]
 #[inline(never)]
 fn init_cold() -> OptixResult<()> {
@@ -26,6 +40,7 @@ fn init_cold() -> OptixResult<()> {
 /// Whether OptiX is initialized. If you are calling raw [`sys`] functions you must make sure
 /// this is true, otherwise OptiX will segfault. In the safe wrapper it is done automatically and optix not
 /// being initialized will return an error result.
+#[cfg(not(target_os = "cuda"))]
 pub fn optix_is_initialized() -> bool {
     // SAFETY: C globals are explicitly defined to be zero-initialized, and the sys version uses
     // Option for each field, and None is explicitly defined to be represented as a nullptr for Option<fn()>,
@@ -34,12 +49,14 @@ pub fn optix_is_initialized() -> bool {
     unsafe { g_optixFunctionTable != sys::OptixFunctionTable::default() }
 }
 
+#[cfg(not(target_os = "cuda"))]
 extern "C" {
     pub(crate) static g_optixFunctionTable: sys::OptixFunctionTable;
 }
 
 /// Call a raw OptiX sys function, making sure that OptiX is initialized. Returning
 /// an OptixNotInitialized error if it is not initialized. See [`optix_is_initialized`].
+#[cfg(not(target_os = "cuda"))]
 #[macro_export]
 macro_rules! optix_call {
     ($name:ident($($param:expr),* $(,)?)) => {{
@@ -9,15 +9,23 @@
 // except according to those terms.
 
 #include "rustllvm.h"
+#include "llvm/Analysis/LoopInfo.h"
+#include "llvm/Analysis/ValueTracking.h"
 #include "llvm/IR/DebugInfoMetadata.h"
 #include "llvm/IR/DiagnosticInfo.h"
 #include "llvm/IR/DiagnosticPrinter.h"
+#include "llvm/IR/Dominators.h"
 #include "llvm/IR/Instructions.h"
+#include "llvm/IR/IntrinsicInst.h"
 #include "llvm/Object/Archive.h"
 #include "llvm/Object/ObjectFile.h"
 #include "llvm/Bitcode/BitcodeWriterPass.h"
 #include "llvm/IR/CallSite.h"
 #include "llvm/Support/Casting.h"
+#include <algorithm>
+#include <map>
+#include <memory>
+#include <vector>
 
 #if LLVM_VERSION_GE(5, 0)
 #include "llvm/ADT/Optional.h"
@@ -262,6 +270,8 @@ static Attribute::AttrKind fromRust(LLVMRustAttribute Kind)
     return Attribute::SanitizeMemory;
   case ReadNone:
     return Attribute::ReadNone;
+  case Convergent:
+    return Attribute::Convergent;
   }
   report_fatal_error("bad AttributeKind");
 }
@@ -550,6 +560,190 @@ extern "C" void LLVMRustAppendModuleInlineAsm(LLVMModuleRef M, const char *Asm)
   unwrap(M)->appendModuleInlineAsm(StringRef(Asm));
 }
 
+// Turns the calls to `__nvvm_loop_unroll(count)` which `#[unroll]` and `#[no_unroll]` put at the
+// start of loop bodies into `llvm.loop` metadata on the innermost loop containing them, and removes
+// the calls. A count of 0 disables unrolling and UINT32_MAX unrolls the loop completely.
+extern "C" void LLVMRustApplyLoopUnrollHints(LLVMModuleRef M)
+{
+  Function *Marker = unwrap(M)->getFunction("__nvvm_loop_unroll");
+  if (!Marker)
+    return;
+
+  // the loops of a function are computed once for all of its calls.
+  std::map<Function *, std::vector<CallInst *>> Calls;
+  for (User *U : Marker->users())
+    if (CallInst *CI = dyn_cast<CallInst>(U))
+      Calls[CI->getFunction()].push_back(CI);
+
+  LLVMContext &Ctx = unwrap(M)->getContext();
+  for (auto &FnCalls : Calls) {
+    DominatorTree DT(*FnCalls.first);
+    LoopInfo LI(DT);
+    for (CallInst *CI : FnCalls.second) {
+      Loop *L = LI.getLoopFor(CI->getParent());
+      ConstantInt *Count = dyn_cast<ConstantInt>(CI->getArgOperand(0));
+      if (L && Count) {
+        uint64_t N = Count->getZExtValue();
+        MDNode *Hint;
+        if (N == 0) {
+          Hint = MDNode::get(Ctx, MDString::get(Ctx, "llvm.loop.unroll.disable"));
+        } else if (N == UINT32_MAX) {
+          Hint = MDNode::get(Ctx, MDString::get(Ctx, "llvm.loop.unroll.full"));
+        } else {
+          Metadata *Ops[] = {
+              MDString::get(Ctx, "llvm.loop.unroll.count"),
+              ConstantAsMetadata::get(ConstantInt::get(Type::getInt32Ty(Ctx), N))};
+          Hint = MDNode::get(Ctx, Ops);
+        }
+        // loop IDs refer to themselves, so that they are never merged with other loops.
+        Metadata *Ops[] = {nullptr, Hint};
+        MDNode *LoopID = MDNode::getDistinct(Ctx, Ops);
+        LoopID->replaceOperandWith(0, LoopID);
+        L->setLoopID(LoopID);
+      }
+      CI->eraseFromParent();
+    }
+  }
+  if (Marker->use_empty())
+    Marker->eraseFromParent();
+}
+
+// Collects the uses of the alloca `AI` which read it, returns the only memcpy writing all of it, or
+// null if it is written any other way or escapes. `Dead` gets the lifetime markers and the casts which
+// are only used by them or by the copy, in an order in which they can be erased.
+static MemCpyInst *findOnlyCopy(AllocaInst *AI, std::vector<LoadInst *> &Loads,
+                                std::vector<MemCpyInst *> &CopiesOut,
+                                std::vector<Instruction *> &Live,
+                                std::vector<Instruction *> &Dead)
+{
+  MemCpyInst *Copy = nullptr;
+  std::vector<Instruction *> Pointers{AI};
+  for (size_t i = 0; i < Pointers.size(); i++) {
+    for (User *U : Pointers[i]->users()) {
+      if (isa<BitCastInst>(U) || isa<GetElementPtrInst>(U)) {
+        Pointers.push_back(cast<Instruction>(U));
+      } else if (LoadInst *LI = dyn_cast<LoadInst>(U)) {
+        if (LI->isVolatile())
+          return nullptr;
+        Loads.push_back(LI);
+      } else if (MemCpyInst *MC = dyn_cast<MemCpyInst>(U)) {
+        if (MC->isVolatile())
+          return nullptr;
+        if (MC->getRawDest() == Pointers[i]) {
+          if (Copy)
+            return nullptr;
+          Copy = MC;
+        } else {
+          CopiesOut.push_back(MC);
+        }
+      } else if (IntrinsicInst *II = dyn_cast<IntrinsicInst>(U)) {
+        if (II->getIntrinsicID() != Intrinsic::lifetime_start &&
+            II->getIntrinsicID() != Intrinsic::lifetime_end)
+          return nullptr;
+        Dead.push_back(II);
+      } else {
+        return nullptr;
+      }
+    }
+  }
+  // a copy from the alloca to itself both reads and writes it.
+  if (!Copy || std::find(CopiesOut.begin(), CopiesOut.end(), Copy) != CopiesOut.end())
+    return nullptr;
+
+  // the casts were pushed after their operands, so the ones only used by dead instructions are found
+  // by walking them backwards.
+  std::vector<Instruction *> DeadCasts;
+  for (size_t i = Pointers.size(); i-- > 1;) {
+    Instruction *I = Pointers[i];
+    bool IsDead = std::all_of(I->user_begin(), I->user_end(), [&](User *U) {
+      return U == Copy || std::find(Dead.begin(), Dead.end(), U) != Dead.end() ||
+             std::find(DeadCasts.begin(), DeadCasts.end(), U) != DeadCasts.end();
+    });
+    if (IsDead)
+      DeadCasts.push_back(I);
+    else
+      Live.push_back(I);
+  }
+  Dead.insert(Dead.end(), DeadCasts.begin(), DeadCasts.end());
+  Live.insert(Live.end(), Loads.begin(), Loads.end());
+  Live.insert(Live.end(), CopiesOut.begin(), CopiesOut.end());
+  return Copy;
+}
+
+// Replaces the allocas which are only written by a copy from a `noalias readonly` argument (a shared
+// reference without interior mutability), and are then only read, with the memory they were copied
+// from. The memory cannot change while the function runs, so the reads get the same values.
+//
+// These are the copies of `let value = slice[i];`, which stay in local memory when SROA cannot split
+// them into registers, such as enums with data whose variants read the same bytes as different types.
+extern "C" void LLVMRustForwardReadOnlyCopies(LLVMModuleRef M)
+{
+  const DataLayout &DL = unwrap(M)->getDataLayout();
+  for (Function &F : *unwrap(M)) {
+    if (F.isDeclaration())
+      continue;
+    std::vector<AllocaInst *> Allocas;
+    for (Instruction &I : F.getEntryBlock())
+      if (AllocaInst *AI = dyn_cast<AllocaInst>(&I))
+        if (AI->isStaticAlloca() && !AI->isArrayAllocation())
+          Allocas.push_back(AI);
+
+    std::unique_ptr<DominatorTree> DT;
+    for (AllocaInst *AI : Allocas) {
+      std::vector<LoadInst *> Loads;
+      std::vector<MemCpyInst *> CopiesOut;
+      std::vector<Instruction *> Live, Dead;
+      MemCpyInst *Copy = findOnlyCopy(AI, Loads, CopiesOut, Live, Dead);
+      if (!Copy || Copy->getDest() != AI)
+        continue;
+      ConstantInt *Len = dyn_cast<ConstantInt>(Copy->getLength());
+      if (!Len || Len->getZExtValue() != DL.getTypeAllocSize(AI->getAllocatedType()))
+        continue;
+      Argument *Arg = dyn_cast<Argument>(GetUnderlyingObject(Copy->getSource(), DL, 0));
+      if (!Arg || !Arg->hasNoAliasAttr() || !Arg->onlyReadsMemory())
+        continue;
+
+      // the reads must come after the address of the source is computed.
+      Value *Src = Copy->getRawSource();
+      Instruction *InsertBefore;
+      if (Instruction *SrcI = dyn_cast<Instruction>(Src)) {
+        if (!DT)
+          DT.reset(new DominatorTree(F));
+        if (!std::all_of(Live.begin(), Live.end(),
+                         [&](Instruction *I) { return DT->dominates(SrcI, I); }))
+          continue;
+        InsertBefore = isa<PHINode>(SrcI) ? SrcI->getParent()->getFirstNonPHI()
+                                          : SrcI->getNextNode();
+      } else if (isa<Argument>(Src)) {
+        InsertBefore = AI;
+      } else {
+        continue;
+      }
+
+      // the source may be less aligned than the alloca, the reads keep the alignment of their
+      // offset in it up to the alignment of the source.
+      unsigned SrcAlign = std::max(Copy->getSourceAlignment(), 1u);
+      for (LoadInst *LI : Loads) {
+        unsigned Align = LI->getAlignment();
+        if (Align == 0)
+          Align = DL.getABITypeAlignment(LI->getType());
+        LI->setAlignment(std::min(Align, SrcAlign));
+      }
+      for (MemCpyInst *MC : CopiesOut)
+        MC->setSourceAlignment(std::min(std::max(MC->getSourceAlignment(), 1u), SrcAlign));
+
+      // the instruction after the source may be one of the dead casts.
+      Value *Replacement =
+          CastInst::CreatePointerBitCastOrAddrSpaceCast(Src, AI->getType(), "", InsertBefore);
+      Copy->eraseFromParent();
+      for (Instruction *I : Dead)
+        I->eraseFromParent();
+      AI->replaceAllUsesWith(Replacement);
+      AI->eraseFromParent();
+    }
+  }
+}
+
 typedef DIBuilder *LLVMRustDIBuilderRef;
 
 #if LLVM_VERSION_LT(5, 0)
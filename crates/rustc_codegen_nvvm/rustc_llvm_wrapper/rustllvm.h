@@ -101,7 +101,8 @@ enum LLVMRustAttribute
   SanitizeThread = 21,
   SanitizeAddress = 22,
   SanitizeMemory = 23,
-  ReadNone = 24
+  ReadNone = 24,
+  Convergent = 25
 };
 
 typedef struct OpaqueRustString *RustStringRef;
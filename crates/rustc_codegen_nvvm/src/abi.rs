@@ -39,7 +39,7 @@ pub(crate) fn readjust_fn_abi<'tcx>(
         }
 
         if let TyKind::Ref(_, ty, _) = arg.layout.ty.kind() {
-            if matches!(ty.kind(), TyKind::Slice(_)) {
+            if matches!(ty.kind(), TyKind::Slice(_) | TyKind::Str) {
                 let mut ptr_attrs = ArgAttributes::new();
                 if let PassMode::Indirect { attrs, .. } = arg.mode {
                     ptr_attrs.regular = attrs.regular;
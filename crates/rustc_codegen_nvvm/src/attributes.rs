@@ -1,5 +1,5 @@
 use crate::llvm::{self, AttributePlace::*, Value};
-use rustc_ast::{Attribute, Lit, LitKind};
+use rustc_ast::{Attribute, Lit, LitKind, NestedMetaItem};
 use rustc_attr::{InlineAttr, OptimizeAttr};
 use rustc_middle::{middle::codegen_fn_attrs::CodegenFnAttrFlags, ty};
 use rustc_session::{config::OptLevel, Session};
@@ -39,6 +39,32 @@ pub(crate) fn default_optimisation_attrs(sess: &Session, llfn: &'_ Value) {
     }
 }
 
+/// Functions which are barriers across threads. Calls to these must never be made
+/// control-dependent on additional values (e.g. by being sunk into a branch or by
+/// unswitching a loop around them), otherwise threads which diverged may wait forever
+/// or skip the barrier entirely.
+const CONVERGENT_FNS: &[&str] = &[
+    "__nvvm_block_barrier",
+    "llvm.nvvm.barrier0",
+    "llvm.nvvm.barrier0.popc",
+    "llvm.nvvm.barrier0.and",
+    "llvm.nvvm.barrier0.or",
+    "llvm.nvvm.bar.warp.sync",
+    "llvm.nvvm.barrier.sync",
+];
+
+/// Whether a function with this name is a thread barrier and calls to it must be marked as convergent.
+pub(crate) fn is_convergent_fn(name: &[u8]) -> bool {
+    CONVERGENT_FNS.iter().any(|f| f.as_bytes() == name)
+}
+
+/// Marks a function as `convergent`. This is done for every function we declare, just like
+/// clang does for CUDA, because any function may transitively call a barrier like `sync_threads`.
+/// The optimizer will remove the attribute from functions it can prove do not need it.
+pub(crate) fn convergent(llfn: &'_ Value) {
+    llvm::Attribute::Convergent.apply_llfn(Function, llfn);
+}
+
 /// Composite function which sets LLVM attributes for function depending on its AST (`#[attribute]`)
 /// attributes.
 pub(crate) fn from_fn_attrs<'ll, 'tcx>(
@@ -94,6 +120,11 @@ pub struct Symbols {
     pub nvvm_internal: Symbol,
     pub kernel: Symbol,
     pub addrspace: Symbol,
+    pub cluster_dim: Symbol,
+    pub max_registers: Symbol,
+    pub max_threads: Symbol,
+    pub min_blocks: Symbol,
+    pub forbid_local_memory: Symbol,
 }
 
 // inspired by rust-gpu's attribute handling
@@ -102,6 +133,11 @@ pub(crate) struct NvvmAttributes {
     pub kernel: bool,
     pub used: bool,
     pub addrspace: Option<u8>,
+    pub cluster_dim: Option<[u32; 3]>,
+    pub max_registers: Option<u32>,
+    pub max_threads: Option<u32>,
+    pub min_blocks: Option<u32>,
+    pub forbid_local_memory: bool,
 }
 
 impl NvvmAttributes {
@@ -133,6 +169,34 @@ impl NvvmAttributes {
                             }
                         }
                     }
+                    if arg.has_name(cx.symbols.cluster_dim) {
+                        let mut dims = [1; 3];
+                        let args = arg.meta_item_list().unwrap_or_default();
+                        for (dim, arg) in dims.iter_mut().zip(args) {
+                            if let Some(Lit {
+                                kind: LitKind::Int(val, _),
+                                ..
+                            }) = arg.literal()
+                            {
+                                *dim = *val as u32;
+                            } else {
+                                panic!();
+                            }
+                        }
+                        nvvm_attrs.cluster_dim = Some(dims);
+                    }
+                    if arg.has_name(cx.symbols.max_registers) {
+                        nvvm_attrs.max_registers = Some(int_arg(arg));
+                    }
+                    if arg.has_name(cx.symbols.max_threads) {
+                        nvvm_attrs.max_threads = Some(int_arg(arg));
+                    }
+                    if arg.has_name(cx.symbols.min_blocks) {
+                        nvvm_attrs.min_blocks = Some(int_arg(arg));
+                    }
+                    if arg.has_name(cx.symbols.forbid_local_memory) {
+                        nvvm_attrs.forbid_local_memory = true;
+                    }
                 }
             }
         }
@@ -140,3 +204,97 @@ impl NvvmAttributes {
         nvvm_attrs
     }
 }
+
+/// The register and launch bound hints of a kernel given with `--kernel-hints=name:key=value,...`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KernelHintsArg {
+    pub name: String,
+    pub max_registers: Option<u32>,
+    pub max_threads: Option<u32>,
+    pub min_blocks: Option<u32>,
+}
+
+impl KernelHintsArg {
+    /// Parses `name:max_registers=64,max_threads=256`, returning `None` if it is invalid.
+    pub fn parse(arg: &str) -> Option<Self> {
+        let (name, hints) = arg.split_once(':')?;
+        let mut out = Self {
+            name: name.to_string(),
+            ..Self::default()
+        };
+        for hint in hints.split(',') {
+            let (key, value) = hint.split_once('=')?;
+            let value = Some(value.parse::<u32>().ok().filter(|&v| v > 0)?);
+            match key {
+                "max_registers" => out.max_registers = value,
+                "max_threads" => out.max_threads = value,
+                "min_blocks" => out.min_blocks = value,
+                _ => return None,
+            }
+        }
+        Some(out)
+    }
+}
+
+impl NvvmAttributes {
+    /// Overrides the hints of the kernel `name` with the ones given on the command line for it, hint
+    /// by hint: a hint given on the command line replaces the same hint of `#[kernel]`, the others
+    /// keep their value. If the kernel is named more than once, later arguments win.
+    pub(crate) fn apply_kernel_hints(&mut self, hints: &[KernelHintsArg], name: &str) {
+        for hints in hints.iter().filter(|hints| hints.name == name) {
+            self.max_registers = hints.max_registers.or(self.max_registers);
+            self.max_threads = hints.max_threads.or(self.max_threads);
+            self.min_blocks = hints.min_blocks.or(self.min_blocks);
+        }
+    }
+}
+
+/// The integer argument of an attribute like `max_registers(64)`.
+fn int_arg(arg: &NestedMetaItem) -> u32 {
+    let args = arg.meta_item_list().unwrap_or_default();
+    if let Some(Lit {
+        kind: LitKind::Int(val, _),
+        ..
+    }) = args.first().and_then(|arg| arg.literal())
+    {
+        *val as u32
+    } else {
+        panic!();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_kernel_hints() {
+        let hints = KernelHintsArg::parse("render:max_threads=256,min_blocks=2").unwrap();
+        assert_eq!(hints.name, "render");
+        assert_eq!(hints.max_registers, None);
+        assert_eq!(hints.max_threads, Some(256));
+        assert_eq!(hints.min_blocks, Some(2));
+        assert_eq!(KernelHintsArg::parse("render:max_threads=0"), None);
+        assert_eq!(KernelHintsArg::parse("render:max_blocks=2"), None);
+        assert_eq!(KernelHintsArg::parse("render"), None);
+    }
+
+    #[test]
+    fn overrides_hints_one_by_one() {
+        let mut attrs = NvvmAttributes {
+            kernel: true,
+            max_registers: Some(64),
+            max_threads: Some(128),
+            ..NvvmAttributes::default()
+        };
+        let hints = [
+            KernelHintsArg::parse("render:max_threads=256,min_blocks=2").unwrap(),
+            KernelHintsArg::parse("denoise:max_registers=32").unwrap(),
+            KernelHintsArg::parse("render:min_blocks=4").unwrap(),
+        ];
+        attrs.apply_kernel_hints(&hints, "render");
+        assert_eq!(attrs.max_registers, Some(64));
+        assert_eq!(attrs.max_threads, Some(256));
+        assert_eq!(attrs.min_blocks, Some(4));
+    }
+}
@@ -327,6 +327,9 @@ pub(crate) unsafe fn optimize(
 
     let llmod = &*module.module_llvm.llmod;
 
+    // the hints must be on the loops before any pass unrolls or rotates them.
+    llvm::LLVMRustApplyLoopUnrollHints(llmod);
+
     let module_name = module.name.clone();
     let module_name = Some(&module_name[..]);
 
@@ -345,6 +348,9 @@ pub(crate) unsafe fn optimize(
     let tm = (cgcx.tm_factory)(tm_factory_config).expect("failed to create target machine");
 
     if config.opt_level.is_some() {
+        // before SROA, which would otherwise leave the copies it cannot split in local memory.
+        llvm::LLVMRustForwardReadOnlyCopies(llmod);
+
         let fpm = llvm::LLVMCreateFunctionPassManagerForModule(llmod);
         let mpm = llvm::LLVMCreatePassManager();
 
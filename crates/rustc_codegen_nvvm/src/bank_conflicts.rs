@@ -0,0 +1,324 @@
+//! Warnings about shared memory accesses which likely cause bank conflicts, enabled with
+//! `-Cllvm-args=--warn-bank-conflicts`.
+//!
+//! Shared memory is split into 32 banks of 4 bytes, consecutive words being in consecutive banks.
+//! The accesses of a warp are served in one pass if no bank is asked for two different words,
+//! otherwise the pass is repeated once for every word a bank is asked for. This only shows up in a
+//! profiler, a tile of `[[f32; 32]; 32]` read by column is 32 times slower than read by row.
+//!
+//! The check goes through the loads and stores of shared statics and works out how many bytes
+//! apart the addresses of neighbouring lanes are, from how `thread_idx_x()` flows into the index.
+//! Everything else the index is computed from (constants, loop counters, block indices and sizes,
+//! `thread_idx_y()`, kernel parameters) is assumed to be the same for every lane of a warp, which
+//! holds as long as the x dimension of blocks is a multiple of 32. Indices computed from anything
+//! else, such as values loaded from global memory, are skipped, so conflicts can be missed.
+
+use crate::llvm::*;
+use rustc_session::Session;
+use std::fs;
+use std::path::Path;
+
+/// The opcodes of `LLVMOpcode` the lane strides are computed through.
+const ADD: u32 = 8;
+const SUB: u32 = 10;
+const MUL: u32 = 12;
+const SHL: u32 = 20;
+const ALLOCA: u32 = 26;
+const LOAD: u32 = 27;
+const GEP: u32 = 29;
+const TRUNC: u32 = 30;
+const ZEXT: u32 = 31;
+const SEXT: u32 = 32;
+const BIT_CAST: u32 = 41;
+const ADDR_SPACE_CAST: u32 = 60;
+
+const SHARED_ADDRSPACE: u32 = 3;
+const BANKS: i64 = 32;
+const WARP_SIZE: i64 = 32;
+/// How deep index expressions are followed.
+const MAX_DEPTH: u32 = 24;
+/// How many nested phi nodes are followed, every one of them is followed twice.
+const MAX_PHIS: usize = 4;
+
+/// The functions reading the thread index in x, from libintrinsics or as NVVM intrinsics.
+const THREAD_IDX_X: &[&[u8]] = &[b"__nvvm_thread_idx_x", b"llvm.nvvm.read.ptx.sreg.tid.x"];
+
+/// Prefixes of the functions reading special registers which are the same for every lane of a
+/// warp (as far as this check is concerned).
+const UNIFORM_PREFIXES: &[&[u8]] = &[
+    b"__nvvm_thread_idx_",
+    b"__nvvm_block_dim_",
+    b"__nvvm_block_idx_",
+    b"__nvvm_grid_dim_",
+    b"__nvvm_warp_size",
+    b"llvm.nvvm.read.ptx.sreg.tid.",
+    b"llvm.nvvm.read.ptx.sreg.ntid.",
+    b"llvm.nvvm.read.ptx.sreg.ctaid.",
+    b"llvm.nvvm.read.ptx.sreg.nctaid.",
+    b"llvm.nvvm.read.ptx.sreg.warpsize",
+];
+
+/// A warning for every load and store of a shared static in `module` which likely causes bank
+/// conflicts.
+pub(crate) unsafe fn collect(module: &Module) -> Vec<String> {
+    let td = LLVMGetModuleDataLayout(module);
+    let mut warnings = Vec::new();
+    let mut next_fn = LLVMGetFirstFunction(module);
+    while let Some(llfn) = next_fn {
+        next_fn = LLVMGetNextFunction(llfn);
+        if LLVMIsDeclaration(llfn) != 0 {
+            continue;
+        }
+        let mut block = Some(LLVMGetFirstBasicBlock(llfn));
+        while let Some(bb) = block {
+            let mut inst = LLVMGetFirstInstruction(bb);
+            while let Some(i) = inst {
+                if let Some(warning) = check_access(td, i) {
+                    if !warnings.contains(&warning) {
+                        warnings.push(warning);
+                    }
+                }
+                inst = LLVMGetNextInstruction(i);
+            }
+            block = LLVMGetNextBasicBlock(bb);
+        }
+    }
+    warnings
+}
+
+/// A warning for `inst` if it is a load or store of a shared static which likely causes bank
+/// conflicts.
+unsafe fn check_access(td: &TargetData, inst: &Value) -> Option<String> {
+    let (kind, ptr, ty) = if LLVMIsALoadInst(inst).is_some() {
+        ("load", LLVMGetOperand(inst, 0), LLVMTypeOf(inst))
+    } else if LLVMIsAStoreInst(inst).is_some() {
+        let value = LLVMGetOperand(inst, 0);
+        ("store", LLVMGetOperand(inst, 1), LLVMTypeOf(value))
+    } else {
+        return None;
+    };
+    let global = shared_static(ptr)?;
+    let width = LLVMStoreSizeOfType(td, ty) as i64;
+    let stride = lane_stride(td, ptr, &mut Vec::new(), 0)?;
+    let ways = conflict_ways(stride, width);
+    if ways <= 1 {
+        return None;
+    }
+
+    let global = demangle(global);
+    let llfn = LLVMGetBasicBlockParent(LLVMGetInstructionParent(inst));
+    let mut len = 0;
+    let file = LLVMGetDebugLocFilename(inst, &mut len);
+    let location = if file.is_null() || len == 0 {
+        String::new()
+    } else {
+        let file = std::slice::from_raw_parts(file.cast(), len as usize);
+        format!(
+            " ({}:{})",
+            String::from_utf8_lossy(file),
+            LLVMGetDebugLocLine(inst)
+        )
+    };
+    Some(format!(
+        "likely {}-way shared memory bank conflict in `{}`{}: neighbouring lanes of a warp {} \
+         {} bytes of `{}` {} bytes apart, consider padding the rows of `{}` or changing its layout",
+        ways,
+        demangle(llfn),
+        location,
+        kind,
+        width,
+        global,
+        stride.abs(),
+        global,
+    ))
+}
+
+/// The shared static `ptr` points into, if any.
+unsafe fn shared_static(mut ptr: &Value) -> Option<&Value> {
+    for _ in 0..MAX_DEPTH {
+        if LLVMIsAGlobalVariable(ptr).is_some() {
+            let addrspace = LLVMGetPointerAddressSpace(LLVMTypeOf(ptr));
+            return (addrspace == SHARED_ADDRSPACE).then(|| ptr);
+        }
+        match opcode(ptr)? {
+            GEP | BIT_CAST | ADDR_SPACE_CAST => ptr = LLVMGetOperand(ptr, 0),
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// The opcode of an instruction or constant expression.
+unsafe fn opcode(value: &Value) -> Option<u32> {
+    if LLVMIsAInstruction(value).is_some() {
+        Some(LLVMGetInstructionOpcode(value))
+    } else if LLVMIsAConstantExpr(value).is_some() {
+        Some(LLVMGetConstOpcode(value))
+    } else {
+        None
+    }
+}
+
+unsafe fn const_int(value: &Value) -> Option<i64> {
+    LLVMIsAConstantInt(value).map(|c| LLVMConstIntGetSExtValue(c))
+}
+
+/// How much `value` (an integer or a pointer, in bytes) grows from one lane of a warp to the next,
+/// `None` if it is not known. `phis` are the phi nodes `value` is computed from, with the stride
+/// they are assumed to have while following their incoming values.
+unsafe fn lane_stride<'ll>(
+    td: &TargetData,
+    value: &'ll Value,
+    phis: &mut Vec<(&'ll Value, Option<i64>)>,
+    depth: u32,
+) -> Option<i64> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+    if LLVMIsAConstant(value).is_some() && LLVMIsAConstantExpr(value).is_none() {
+        return Some(0);
+    }
+    if LLVMIsAArgument(value).is_some() {
+        return Some(0);
+    }
+    if LLVMIsACallInst(value).is_some() {
+        let name = get_value_name(LLVMGetCalledValue(value));
+        return if THREAD_IDX_X.iter().any(|n| *n == name) {
+            Some(1)
+        } else if UNIFORM_PREFIXES.iter().any(|p| name.starts_with(p)) {
+            Some(0)
+        } else {
+            None
+        };
+    }
+    if LLVMIsAPHINode(value).is_some() {
+        return phi_stride(td, value, phis, depth);
+    }
+
+    let mut stride = |v| lane_stride(td, v, phis, depth + 1);
+    let op = |i| LLVMGetOperand(value, i);
+    match opcode(value)? {
+        TRUNC | ZEXT | SEXT | BIT_CAST | ADDR_SPACE_CAST => stride(op(0)),
+        ADD => stride(op(0))?.checked_add(stride(op(1))?),
+        SUB => stride(op(0))?.checked_sub(stride(op(1))?),
+        MUL => match (const_int(op(0)), const_int(op(1))) {
+            (Some(c), _) => stride(op(1))?.checked_mul(c),
+            (_, Some(c)) => stride(op(0))?.checked_mul(c),
+            _ => (stride(op(0))? == 0 && stride(op(1))? == 0).then(|| 0),
+        },
+        SHL => {
+            let shift = const_int(op(1)).filter(|s| (0..32).contains(s))?;
+            stride(op(0))?.checked_mul(1 << shift)
+        }
+        // every lane has its own stack.
+        ALLOCA => None,
+        // a load reads the same value in every lane if they all read the same address.
+        LOAD => (stride(op(0))? == 0).then(|| 0),
+        GEP => {
+            let mut total = stride(op(0))?;
+            let mut ty = LLVMTypeOf(op(0));
+            for i in 1..LLVMGetNumOperands(value) as u32 {
+                let index = op(i);
+                // the first index steps over the pointee, the others into it.
+                ty = if LLVMRustGetTypeKind(ty) == TypeKind::Struct {
+                    LLVMStructGetTypeAtIndex(ty, const_int(index)? as u32)
+                } else {
+                    let elem = LLVMGetElementType(ty);
+                    let size = LLVMABISizeOfType(td, elem) as i64;
+                    total = total.checked_add(stride(index)?.checked_mul(size)?)?;
+                    elem
+                };
+            }
+            Some(total)
+        }
+        // anything else computed only from values which are the same in every lane is too.
+        _ => {
+            for i in 0..LLVMGetNumOperands(value) as u32 {
+                if stride(op(i))? != 0 {
+                    return None;
+                }
+            }
+            Some(0)
+        }
+    }
+}
+
+/// The stride of a phi node, if every incoming value has the same one. Loop counters depend on
+/// themselves, so their incoming values are followed once to find the stride of the values which
+/// do not depend on the phi, and then again assuming the phi has that stride.
+unsafe fn phi_stride<'ll>(
+    td: &TargetData,
+    phi: &'ll Value,
+    phis: &mut Vec<(&'ll Value, Option<i64>)>,
+    depth: u32,
+) -> Option<i64> {
+    if let Some((_, assumed)) = phis.iter().find(|(p, _)| *p == phi) {
+        return *assumed;
+    }
+    if phis.len() >= MAX_PHIS {
+        return None;
+    }
+    let incoming = (0..LLVMCountIncoming(phi))
+        .map(|i| LLVMGetIncomingValue(phi, i))
+        .collect::<Vec<_>>();
+
+    phis.push((phi, None));
+    let assumed = incoming
+        .iter()
+        .find_map(|&v| lane_stride(td, v, phis, depth + 1));
+    phis.last_mut().unwrap().1 = assumed;
+    let stride = assumed.filter(|&assumed| {
+        incoming
+            .iter()
+            .all(|&v| lane_stride(td, v, phis, depth + 1) == Some(assumed))
+    });
+    phis.pop();
+    stride
+}
+
+/// How many times slower an access of `width` bytes per lane is than an access without
+/// conflicts, if the addresses of neighbouring lanes are `stride` bytes apart.
+fn conflict_ways(stride: i64, width: i64) -> i64 {
+    let words_per_lane = ((width + 3) / 4).max(1);
+    let mut banks = vec![Vec::new(); BANKS as usize];
+    for lane in 0..WARP_SIZE {
+        let first_word = (lane * stride).div_euclid(4);
+        for word in first_word..first_word + words_per_lane {
+            let bank = &mut banks[word.rem_euclid(BANKS) as usize];
+            if !bank.contains(&word) {
+                bank.push(word);
+            }
+        }
+    }
+    let passes = banks.iter().map(Vec::len).max().unwrap_or(0) as i64;
+    // a warp accessing more than 128 bytes needs several passes even without conflicts.
+    let min_passes = (words_per_lane * WARP_SIZE + BANKS - 1) / BANKS;
+    passes / min_passes
+}
+
+fn demangle(value: &Value) -> String {
+    let name = String::from_utf8_lossy(get_value_name(value));
+    match rustc_demangle::try_demangle(&name) {
+        Ok(demangled) => format!("{:#}", demangled),
+        Err(_) => name.into_owned(),
+    }
+}
+
+/// Emits the warnings of [`collect`] and writes them next to the PTX file at `ptx_path`, with a
+/// `.bank-conflicts` extension, for cuda_builder to show them.
+pub(crate) fn report(sess: &Session, warnings: &[String], ptx_path: &Path) {
+    for warning in warnings {
+        sess.warn(warning);
+    }
+    let mut out = String::new();
+    for warning in warnings {
+        out.push_str(warning);
+        out.push('\n');
+    }
+    if let Err(e) = fs::write(ptx_path.with_extension("bank-conflicts"), out) {
+        sess.warn(&format!(
+            "failed to write the bank conflict warnings: {}",
+            e
+        ));
+    }
+}
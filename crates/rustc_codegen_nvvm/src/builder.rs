@@ -1,5 +1,6 @@
 #![allow(clippy::unnecessary_mut_passed)]
 
+use crate::attributes;
 use crate::context::CodegenCx;
 use crate::int_replace::{get_transformed_type, transmute_llval};
 use crate::llvm::{self, BasicBlock, LLVMRustGetValueType, Type, Value};
@@ -972,8 +973,13 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
         unsafe { llvm::LLVMBuildExtractElement(self.llbuilder, vec, idx, unnamed()) }
     }
 
-    fn vector_splat(&mut self, _num_elts: usize, _elt: &'ll Value) -> &'ll Value {
-        self.unsupported("vector splats");
+    fn vector_splat(&mut self, num_elts: usize, elt: &'ll Value) -> &'ll Value {
+        trace!("vector splat {:?} x {}", elt, num_elts);
+        let undef = self.const_undef(self.type_vector(self.val_ty(elt), num_elts as u64));
+        (0..num_elts).fold(undef, |vec, i| {
+            let idx = self.const_i32(i as i32);
+            self.insert_element(vec, elt, idx)
+        })
     }
 
     fn extract_value(&mut self, agg_val: &'ll Value, idx: u64) -> &'ll Value {
@@ -1111,6 +1117,12 @@ impl<'ll, 'tcx, 'a> BuilderMethods<'a, 'tcx> for Builder<'a, 'll, 'tcx> {
             )
         };
 
+        // the definitions in libintrinsics replace our declarations when linking, so mark the call itself
+        // as convergent too, that way barriers are never moved into divergent code.
+        if attributes::is_convergent_fn(llvm::get_value_name(llfn)) {
+            llvm::Attribute::Convergent.apply_callsite(llvm::AttributePlace::Function, ret);
+        }
+
         // bitcast return type if the type was remapped
         let map = self.cx.remapped_integer_args.borrow();
         let mut fn_ty = unsafe { LLVMRustGetValueType(llfn) };
@@ -1166,6 +1178,16 @@ impl<'a, 'll, 'tcx> Builder<'a, 'll, 'tcx> {
         unsafe { llvm::LLVMConstVector(vals.as_ptr(), 2) }
     }
 
+    pub(crate) fn insert_element(
+        &mut self,
+        vec: &'ll Value,
+        elt: &'ll Value,
+        idx: &'ll Value,
+    ) -> &'ll Value {
+        trace!("insert element {:?}, {:?}, {:?}", vec, elt, idx);
+        unsafe { llvm::LLVMBuildInsertElement(self.llbuilder, vec, elt, idx, unnamed()) }
+    }
+
     fn with_cx(cx: &'a CodegenCx<'ll, 'tcx>) -> Self {
         // Create a fresh builder from the crate context.
         let llbuilder = unsafe { llvm::LLVMCreateBuilderInContext(cx.llcx) };
@@ -1,5 +1,5 @@
 use crate::abi::FnAbiLlvmExt;
-use crate::attributes::{self, NvvmAttributes, Symbols};
+use crate::attributes::{self, KernelHintsArg, NvvmAttributes, Symbols};
 use crate::debug_info::{self, compile_unit_metadata, CrateDebugContext};
 use crate::llvm::{self, BasicBlock, Type, Value};
 use crate::{target, LlvmMod};
@@ -30,9 +30,10 @@ use rustc_target::spec::{HasTargetSpec, Target};
 use std::cell::{Cell, RefCell};
 use std::ffi::CStr;
 use std::hash::BuildHasherDefault;
+use std::path::PathBuf;
 use std::ptr::null;
 use std::str::FromStr;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 pub(crate) struct CodegenCx<'ll, 'tcx> {
     pub tcx: TyCtxt<'tcx>,
@@ -159,6 +160,11 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
                 nvvm_internal: Symbol::intern("nvvm_internal"),
                 kernel: Symbol::intern("kernel"),
                 addrspace: Symbol::intern("addrspace"),
+                cluster_dim: Symbol::intern("cluster_dim"),
+                max_registers: Symbol::intern("max_registers"),
+                max_threads: Symbol::intern("max_threads"),
+                min_blocks: Symbol::intern("min_blocks"),
+                forbid_local_memory: Symbol::intern("forbid_local_memory"),
             },
             dbg_cx,
             codegen_args: CodegenArgs::from_session(tcx.sess()),
@@ -323,6 +329,7 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
             abi.apply_attrs_llfn(self, llfn);
         }
         attributes::default_optimisation_attrs(self.tcx.sess, llfn);
+        attributes::convergent(llfn);
         llfn
     }
 
@@ -522,6 +529,27 @@ impl<'ll, 'tcx> CodegenCx<'ll, 'tcx> {
 pub struct CodegenArgs {
     pub nvvm_options: Vec<NvvmOption>,
     pub override_libm: bool,
+    /// Whether to write a resource usage report of every kernel next to the PTX file
+    /// (`--emit=gpu-report`).
+    pub emit_gpu_report: bool,
+    /// Whether to generate PTX with LLVM's NVPTX backend instead of libnvvm
+    /// (`--nvptx-backend`).
+    pub nvptx_backend: bool,
+    /// Register and launch bound hints of kernels by name (`--kernel-hints=name:max_threads=256`), which
+    /// override the same hints given to `#[kernel]`. Usually passed by cuda_builder from a tuning profile.
+    pub kernel_hints: Vec<KernelHintsArg>,
+    /// Hand-written PTX files whose functions are spliced into the final PTX (`--link-ptx=<path>`).
+    pub link_ptx: Vec<PathBuf>,
+    /// Hand-written LLVM IR files linked into the final module before it is optimized
+    /// (`--link-llvm-ir=<path>`).
+    pub link_llvm_ir: Vec<PathBuf>,
+    /// Whether to warn about shared memory accesses which likely cause bank conflicts
+    /// (`--warn-bank-conflicts`).
+    pub warn_bank_conflicts: bool,
+    /// Whether to generate relocatable device code (`--rdc`), which may call functions defined in other
+    /// PTX or libraries (like the CUDA device runtime) and keeps the symbols exported by the crate visible.
+    /// The PTX must be linked, for example with `cust::link::Linker`, before it is loaded.
+    pub rdc: bool,
 }
 
 impl CodegenArgs {
@@ -539,6 +567,23 @@ impl CodegenArgs {
                 cg_args.nvvm_options.push(flag);
             } else if arg == "--override-libm" {
                 cg_args.override_libm = true;
+            } else if arg == "--emit=gpu-report" {
+                cg_args.emit_gpu_report = true;
+            } else if arg == "--nvptx-backend" {
+                cg_args.nvptx_backend = true;
+            } else if arg == "--warn-bank-conflicts" {
+                cg_args.warn_bank_conflicts = true;
+            } else if arg == "--rdc" {
+                cg_args.rdc = true;
+            } else if let Some(hints) = arg.strip_prefix("--kernel-hints=") {
+                match KernelHintsArg::parse(hints) {
+                    Some(hints) => cg_args.kernel_hints.push(hints),
+                    None => warn!("Ignoring invalid kernel hints `{}`", hints),
+                }
+            } else if let Some(path) = arg.strip_prefix("--link-ptx=") {
+                cg_args.link_ptx.push(PathBuf::from(path));
+            } else if let Some(path) = arg.strip_prefix("--link-llvm-ir=") {
+                cg_args.link_llvm_ir.push(PathBuf::from(path));
             }
         }
 
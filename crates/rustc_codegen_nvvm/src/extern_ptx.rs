@@ -0,0 +1,275 @@
+//! Linking hand-written PTX into the final PTX file, from `global_asm!` in the gpu crate and from
+//! files given with `-Cllvm-args=--link-ptx=<path>` (`CudaBuilder::link_ptx`).
+//!
+//! libnvvm cannot link PTX, so the functions are spliced into the generated PTX as text: the header
+//! directives of every source are dropped, the rest is inserted after the header of the generated
+//! PTX, and the `.extern .func` declarations the generated PTX has for functions defined by the
+//! sources (from `extern "C"` blocks in the gpu crate) are removed. Before that, every declaration
+//! is checked against the definition, so that a Rust declaration which does not match the PTX is a
+//! build error instead of a launch which reads garbage parameters.
+
+/// The header directives of a PTX module, which can only appear once.
+const HEADER_DIRECTIVES: &[&str] = &[".version", ".target", ".address_size"];
+
+/// The signature of a PTX function, the sizes in bits of its return values and parameters.
+#[derive(Debug, PartialEq, Eq)]
+struct Signature {
+    name: String,
+    returns: Vec<u64>,
+    params: Vec<u64>,
+}
+
+/// Inserts the PTX of `sources` (a name for errors and the PTX) into `ptx`, returning an error if
+/// a function declared in `ptx` does not match its definition in one of the sources.
+pub(crate) fn splice(ptx: &str, sources: &[(String, String)]) -> Result<String, String> {
+    let mut defined = Vec::new();
+    let mut bodies = String::new();
+    for (name, source) in sources {
+        for signature in functions(source) {
+            defined.push((name, signature));
+        }
+        bodies.push_str(&format!("\n// linked from {}\n", name));
+        for line in source.lines() {
+            let trimmed = line.trim_start();
+            if !HEADER_DIRECTIVES.iter().any(|d| trimmed.starts_with(d)) {
+                bodies.push_str(line);
+                bodies.push('\n');
+            }
+        }
+    }
+
+    // remove the declarations of the functions which are now defined, checking them first.
+    let mut out = String::with_capacity(ptx.len() + bodies.len());
+    let mut rest = ptx;
+    while let Some(start) = rest.find(".extern .func") {
+        let end = match rest[start..].find(';') {
+            Some(end) => start + end + 1,
+            None => break,
+        };
+        let decl = &rest[start..end];
+        let signature = parse_function(&decl[".extern .func".len()..]);
+        let definition = signature
+            .as_ref()
+            .and_then(|sig| defined.iter().find(|(_, def)| def.name == sig.name));
+        match (signature, definition) {
+            (Some(sig), Some((source, def))) => {
+                if sig.returns != def.returns || sig.params != def.params {
+                    return Err(format!(
+                        "the declaration of `{}` does not match its definition in {}: declared with \
+                         {} bit parameters and {} bit return values, defined with {} bit parameters \
+                         and {} bit return values",
+                        sig.name,
+                        source,
+                        sizes(&sig.params),
+                        sizes(&sig.returns),
+                        sizes(&def.params),
+                        sizes(&def.returns),
+                    ));
+                }
+                out.push_str(&rest[..start]);
+            }
+            _ => out.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+
+    // insert the functions after the header, before anything can use them.
+    let insert_at = header_end(&out);
+    out.insert_str(insert_at, &bodies);
+    Ok(out)
+}
+
+fn sizes(sizes: &[u64]) -> String {
+    if sizes.is_empty() {
+        return "no".to_string();
+    }
+    let sizes = sizes.iter().map(u64::to_string).collect::<Vec<_>>();
+    format!("({})", sizes.join(", "))
+}
+
+/// The offset right after the line of the last header directive of `ptx`.
+fn header_end(ptx: &str) -> usize {
+    let mut end = 0;
+    let mut offset = 0;
+    for line in ptx.split_inclusive('\n') {
+        offset += line.len();
+        let trimmed = line.trim_start();
+        if HEADER_DIRECTIVES.iter().any(|d| trimmed.starts_with(d)) {
+            end = offset;
+        }
+    }
+    end
+}
+
+/// The signatures of the functions defined in `ptx`.
+fn functions(ptx: &str) -> Vec<Signature> {
+    let mut functions = Vec::new();
+    let mut rest = ptx;
+    while let Some(start) = rest.find(".func") {
+        let line_start = rest[..start].rfind('\n').map_or(0, |i| i + 1);
+        let is_declaration = rest[line_start..start].contains(".extern");
+        rest = &rest[start + ".func".len()..];
+        if is_declaration {
+            continue;
+        }
+        if let Some(signature) = parse_function(rest) {
+            functions.push(signature);
+        }
+    }
+    functions
+}
+
+/// Parses `(.param .b32 ret) name(.param .b64 a, .param .b32 b)` at the start of `src`.
+fn parse_function(src: &str) -> Option<Signature> {
+    let mut src = src.trim_start();
+    let mut returns = Vec::new();
+    if src.starts_with('(') {
+        let (group, rest) = group(src)?;
+        returns = params(group)?;
+        src = rest.trim_start();
+    }
+    let name_len = src
+        .find(|c: char| c.is_whitespace() || c == '(' || c == ';' || c == '{')
+        .unwrap_or(src.len());
+    let name = &src[..name_len];
+    if name.is_empty() {
+        return None;
+    }
+    src = src[name_len..].trim_start();
+    let params = if src.starts_with('(') {
+        params(group(src)?.0)?
+    } else {
+        Vec::new()
+    };
+    Some(Signature {
+        name: name.to_string(),
+        returns,
+        params,
+    })
+}
+
+/// Splits `(...)rest` into the contents of the parentheses and the rest.
+fn group(src: &str) -> Option<(&str, &str)> {
+    let end = src.find(')')?;
+    Some((&src[1..end], &src[end + 1..]))
+}
+
+/// The sizes in bits of a list of `.param` declarations.
+fn params(list: &str) -> Option<Vec<u64>> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|param| !param.is_empty())
+        .map(param_size)
+        .collect()
+}
+
+/// The size in bits of a `.param .b32 name` or `.param .align 8 .b8 name[16]` declaration.
+fn param_size(param: &str) -> Option<u64> {
+    let ty = param.split_whitespace().find_map(|word| {
+        let word = word.strip_prefix('.')?;
+        let bits = word.get(1..)?.parse::<u64>().ok()?;
+        matches!(word.as_bytes()[0], b'b' | b'u' | b's' | b'f').then(|| bits)
+    })?;
+    let count = match (param.find('['), param.find(']')) {
+        (Some(open), Some(close)) if open < close => param[open + 1..close].trim().parse().ok()?,
+        _ => 1,
+    };
+    Some(ty * count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = ".version 7.0\n.target sm_61\n.address_size 64\n";
+
+    fn signature(name: &str, returns: &[u64], params: &[u64]) -> Signature {
+        Signature {
+            name: name.to_string(),
+            returns: returns.to_vec(),
+            params: params.to_vec(),
+        }
+    }
+
+    #[test]
+    fn param_sizes() {
+        assert_eq!(param_size(".param .b32 a"), Some(32));
+        assert_eq!(param_size(".param .u64 ptr"), Some(64));
+        assert_eq!(param_size(".param .f16 h"), Some(16));
+        assert_eq!(param_size(".param .align 8 .b8 s[16]"), Some(128));
+        assert_eq!(param_size(".param .align 4 .b8 s[ 3 ]"), Some(24));
+        assert_eq!(param_size(".param .pred p"), None);
+        assert_eq!(param_size(".param .b8 s[n]"), None);
+    }
+
+    #[test]
+    fn parses_functions() {
+        assert_eq!(
+            parse_function(" (.param .b32 ret) add(.param .b64 a, .param .b32 b)\n{"),
+            Some(signature("add", &[32], &[64, 32]))
+        );
+        assert_eq!(
+            parse_function(" noop()\n{"),
+            Some(signature("noop", &[], &[]))
+        );
+        assert_eq!(parse_function(" noop;"), Some(signature("noop", &[], &[])));
+        assert_eq!(
+            parse_function(" (.param .align 4 .b8 ret[12]) pack\n(\n.param .f32 x\n)\n{"),
+            Some(signature("pack", &[96], &[32]))
+        );
+        assert_eq!(parse_function(" (.param .b32 ret)"), None);
+        assert_eq!(parse_function(" f(.param .pred p)"), None);
+    }
+
+    #[test]
+    fn finds_definitions_only() {
+        let ptx = ".extern .func (.param .b32 r) ext(.param .b32 a);\n\
+                   .visible .func (.param .b32 r) twice(.param .b32 a)\n{\n\tret;\n}\n\
+                   .func halve(.param .b64 a)\n{\n\tret;\n}\n";
+        assert_eq!(
+            functions(ptx),
+            vec![
+                signature("twice", &[32], &[32]),
+                signature("halve", &[], &[64])
+            ]
+        );
+    }
+
+    #[test]
+    fn splices_after_the_header() {
+        let ptx = format!(
+            "{}\n.extern .func (.param .b32 r) twice(.param .b32 a);\n.extern .func other();\n\
+             .visible .entry kernel()\n{{\n\tret;\n}}\n",
+            HEADER
+        );
+        let source = format!(
+            "{}.visible .func (.param .b32 r) twice(.param .b32 a)\n{{\n\tret;\n}}\n",
+            HEADER
+        );
+        let out = splice(&ptx, &[("twice.ptx".to_string(), source)]).unwrap();
+
+        // one header, the declaration of `twice` is gone, the unrelated one stays.
+        assert_eq!(out.matches(".version").count(), 1);
+        assert!(!out.contains(".extern .func (.param .b32 r) twice"));
+        assert!(out.contains(".extern .func other();"));
+        // the definition comes before its use in the kernel.
+        let definition = out.find(".visible .func (.param .b32 r) twice").unwrap();
+        assert!(out.find("// linked from twice.ptx").unwrap() < definition);
+        assert!(out.find(".address_size").unwrap() < definition);
+        assert!(definition < out.find(".entry kernel").unwrap());
+    }
+
+    #[test]
+    fn rejects_mismatched_declarations() {
+        let ptx = format!(
+            "{}.extern .func (.param .b32 r) twice(.param .b64 a);\n",
+            HEADER
+        );
+        let source = ".func (.param .b32 r) twice(.param .b32 a)\n{\n\tret;\n}\n".to_string();
+        let err = splice(&ptx, &[("twice.ptx".to_string(), source)]).unwrap_err();
+        assert!(err.contains("`twice`"), "{}", err);
+        assert!(err.contains("twice.ptx"), "{}", err);
+        assert!(err.contains("declared with (64) bit parameters"), "{}", err);
+    }
+}
@@ -11,7 +11,9 @@ use std::str;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Once;
 
+use crate::context::CodegenArgs;
 use crate::llvm;
+use nvvm::NvvmOption;
 
 static POISONED: AtomicBool = AtomicBool::new(false);
 static INIT: Once = Once::new();
@@ -107,6 +109,19 @@ unsafe fn configure_llvm(sess: &Session) {
         // Use non-zero `import-instr-limit` multiplier for cold callsites.
         add("-import-cold-multiplier=0.1", false);
 
+        // libnvvm takes these as options, the NVPTX backend of LLVM as LLVM options.
+        let cg_args = CodegenArgs::from_session(sess);
+        if cg_args.nvptx_backend {
+            for opt in &cg_args.nvvm_options {
+                match opt {
+                    NvvmOption::FastSqrt => add("-nvptx-prec-sqrtf32=0", false),
+                    NvvmOption::FastDiv => add("-nvptx-prec-divf32=0", false),
+                    NvvmOption::NoFmaContraction => add("-nvptx-fma-level=0", false),
+                    _ => {}
+                }
+            }
+        }
+
         // for arg in sess_args {
         //     add(&(*arg), true);
         // }
@@ -1,5 +1,6 @@
 use crate::abi::LlvmType;
 use crate::llvm::{self, Value};
+use crate::simd;
 use crate::target;
 use crate::ty::LayoutLlvmExt;
 use crate::{builder::Builder, context::CodegenCx};
@@ -25,12 +26,13 @@ fn handle_128_bit_intrinsic<'a, 'll, 'tcx>(b: &mut Builder<'a, 'll, 'tcx>) -> &'
 
 // llvm 7 does not have saturating intrinsics, so we reimplement them right here.
 // This is derived from what rustc used to do before the intrinsics. It should map to the same assembly.
-fn saturating_intrinsic_impl<'a, 'll, 'tcx>(
+pub(crate) fn saturating_intrinsic_impl<'a, 'll, 'tcx>(
     b: &mut Builder<'a, 'll, 'tcx>,
     width: u32,
     signed: bool,
     is_add: bool,
-    args: &[OperandRef<'tcx, &'ll Value>],
+    lhs: &'ll Value,
+    rhs: &'ll Value,
 ) -> &'ll Value {
     use rustc_middle::ty::IntTy::*;
     use rustc_middle::ty::UintTy::*;
@@ -70,8 +72,6 @@ fn saturating_intrinsic_impl<'a, 'll, 'tcx>(
         OverflowOp::Sub
     };
     let llty = b.type_ix(width as u64);
-    let lhs = args[0].immediate();
-    let rhs = args[1].immediate();
 
     let (val, overflowed) = b.checked_binop(overflow_op, ty, lhs, rhs);
 
@@ -357,7 +357,8 @@ impl<'a, 'll, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'll, 'tcx> {
                         width as u32,
                         signed,
                         name == sym::saturating_add,
-                        args,
+                        args[0].immediate(),
+                        args[1].immediate(),
                     )
                 } else if width == 128 {
                     handle_128_bit_intrinsic(self)
@@ -425,8 +426,12 @@ impl<'a, 'll, 'tcx> IntrinsicCallMethods<'tcx> for Builder<'a, 'll, 'tcx> {
                     }
                 }
             }
-            // is this even supported by nvvm? i did not find a definitive answer
-            _ if name_str.starts_with("simd_") => todo!("simd intrinsics"),
+            _ if name_str.starts_with("simd_") => {
+                match simd::generic_simd_intrinsic(self, name, args, ret_ty, llret_ty, span) {
+                    Ok(llval) => llval,
+                    Err(()) => return,
+                }
+            }
             _ => bug!("unknown intrinsic '{}'", name),
         };
         trace!("Finish intrinsic call: `{:?}`", llval);
@@ -30,27 +30,33 @@ mod allocator;
 mod asm;
 mod attributes;
 mod back;
+mod bank_conflicts;
 mod builder;
 mod const_ty;
 mod consts;
 mod context;
 mod ctx_intrinsics;
 mod debug_info;
+mod extern_ptx;
 mod init;
 mod int_replace;
 mod intrinsic;
 mod link;
 mod llvm;
+mod local_memory;
 mod lto;
 mod mono_item;
 mod nvvm;
 mod override_fns;
+mod report;
+mod simd;
 mod target;
 mod ty;
 
 use abi::readjust_fn_abi;
 use back::target_machine_factory;
 use lto::ThinBuffer;
+use nvvm::{NvvmArch, NvvmOption};
 use rustc_codegen_ssa::{
     back::{
         lto::{LtoModuleCodegen, SerializedModule, ThinModule},
@@ -68,6 +74,7 @@ use rustc_middle::{
     ty::TyCtxt,
 };
 use rustc_session::{cstore::MetadataLoaderDyn, Session};
+use rustc_span::Symbol;
 use tracing::debug;
 
 use std::ffi::CString;
@@ -96,6 +103,25 @@ impl CodegenBackend for NvvmCodegenBackend {
         tracing::subscriber::set_global_default(subscriber).expect("no default subscriber");
         init::init(sess);
     }
+    // every architecture up to the one being compiled for is enabled as an `sm_XX` target feature,
+    // so that libraries can use newer instructions with `#[cfg(target_feature = "sm_89")]` and
+    // fall back to older ones otherwise.
+    fn target_features(&self, sess: &Session) -> Vec<Symbol> {
+        let args = context::CodegenArgs::from_session(sess);
+        let arch = args
+            .nvvm_options
+            .iter()
+            .find_map(|opt| match opt {
+                NvvmOption::Arch(arch) => Some(*arch),
+                _ => None,
+            })
+            .unwrap_or_default();
+        NvvmArch::ALL
+            .iter()
+            .filter(|supported| **supported <= arch)
+            .map(|supported| Symbol::intern(&format!("sm_{}", supported.capability())))
+            .collect()
+    }
     fn metadata_loader(&self) -> Box<MetadataLoaderDyn> {
         Box::new(link::NvvmMetadataLoader)
     }
@@ -191,7 +191,13 @@ fn link_exe(
         std::fs::create_dir_all(&out_dir)?;
     }
 
-    codegen_into_ptx_file(allocator, sess, &objects, &rlibs, out_filename)
+    let exported = codegen_results
+        .crate_info
+        .exported_symbols
+        .get(&crate_type)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    codegen_into_ptx_file(allocator, sess, &objects, &rlibs, exported, out_filename)
 }
 
 /// This is the meat of the codegen, taking all of the llvm bitcode modules we have, and giving them to
@@ -201,6 +207,7 @@ fn codegen_into_ptx_file(
     sess: &Session,
     objects: &[PathBuf],
     rlibs: &[PathBuf],
+    exported: &[String],
     out_filename: &Path,
 ) -> io::Result<()> {
     debug!("Codegenning crate into PTX, allocator: {}, objects:\n{:#?}, rlibs:\n{:#?}, out_filename:\n{:#?}",
@@ -216,9 +223,15 @@ fn codegen_into_ptx_file(
 
     let mut modules = Vec::with_capacity(objects.len() + rlibs.len());
 
+    // the order modules are linked in decides the order of functions and globals in the PTX, but
+    // the order in which rustc finishes codegen units is not deterministic. Sorting them by their
+    // (deterministic) names makes the PTX byte-identical across builds.
+    let mut objects = objects.to_vec();
+    objects.sort();
+
     // object files (theyre not object files, they are impostors ඞ) are the bitcode modules produced by this codegen session
     // they *should* be the final crate.
-    for obj in objects {
+    for obj in &objects {
         let bitcode = std::fs::read(obj)?;
         modules.push(bitcode);
     }
@@ -229,17 +242,19 @@ fn codegen_into_ptx_file(
         let mut cgus = Vec::with_capacity(16);
         for entry in Archive::new(File::open(rlib)?).entries()? {
             let mut entry = entry?;
+            let path = entry.path()?.into_owned();
             // metadata is where rustc puts rlib metadata, so its not a cgu we are interested in.
-            if entry.path().unwrap() != Path::new(".metadata") {
+            if path != Path::new(".metadata") {
                 // std::fs::read adds 1 to the size, so do the same here - see comment:
                 // https://github.com/rust-lang/rust/blob/72868e017bdade60603a25889e253f556305f996/library/std/src/fs.rs#L200-L202
                 let mut bitcode = Vec::with_capacity(entry.size() as usize + 1);
                 entry.read_to_end(&mut bitcode).unwrap();
-                cgus.push(bitcode);
+                cgus.push((path, bitcode));
             }
         }
 
-        modules.extend(cgus);
+        cgus.sort_by(|(a, _), (b, _)| a.cmp(b));
+        modules.extend(cgus.into_iter().map(|(_, bitcode)| bitcode));
     }
 
     if let Some(alloc) = allocator {
@@ -256,17 +271,93 @@ fn codegen_into_ptx_file(
     // modules to nvvm to make a final ptx file
 
     // we need to actually parse the codegen args again, because codegencx is not available at link time.
-    let nvvm_opts = CodegenArgs::from_session(sess).nvvm_options;
-
-    let ptx_bytes = match crate::nvvm::codegen_bitcode_modules(&nvvm_opts, sess, modules, cx.llcx) {
-        Ok(bytes) => bytes,
-        Err(err) => {
-            // TODO(RDambrosio016): maybe include the nvvm log with this fatal error
-            sess.fatal(&err.to_string())
+    let args = CodegenArgs::from_session(sess);
+    let nvvm_opts = args.nvvm_options;
+    // relocatable device code keeps the `#[no_mangle]` items of the crate visible to the PTX it is linked with.
+    let exported = if args.rdc { exported } else { &[] };
+
+    let mut forbid_local_memory = Vec::new();
+    let mut bank_conflicts = Vec::new();
+    if args.nvptx_backend {
+        if let Err(err) = crate::nvvm::codegen_bitcode_modules_nvptx(
+            &nvvm_opts,
+            sess,
+            modules,
+            cx.llcx,
+            exported,
+            out_filename,
+            &mut forbid_local_memory,
+            &mut bank_conflicts,
+        ) {
+            sess.fatal(&err)
         }
-    };
+    } else {
+        let ptx_bytes = match crate::nvvm::codegen_bitcode_modules(
+            &nvvm_opts,
+            sess,
+            modules,
+            cx.llcx,
+            exported,
+            &mut forbid_local_memory,
+            &mut bank_conflicts,
+        ) {
+            Ok(bytes) => bytes,
+            Err(err) => {
+                // TODO(RDambrosio016): maybe include the nvvm log with this fatal error
+                sess.fatal(&err.to_string())
+            }
+        };
 
-    std::fs::write(out_filename, ptx_bytes)
+        std::fs::write(out_filename, strip_file_timestamps(&ptx_bytes))?;
+    }
+    if !args.link_ptx.is_empty() {
+        link_ptx_files(sess, &args.link_ptx, out_filename)?;
+    }
+    crate::local_memory::check(sess, &nvvm_opts, &forbid_local_memory, out_filename);
+    if args.warn_bank_conflicts {
+        crate::bank_conflicts::report(sess, &bank_conflicts, out_filename);
+    }
+    if args.emit_gpu_report {
+        crate::report::emit_report(sess, &nvvm_opts, out_filename);
+    }
+    Ok(())
+}
+
+/// Splices the functions of the hand-written PTX files given with `--link-ptx` into the PTX file
+/// at `out_filename`, checking the declarations which use them.
+fn link_ptx_files(sess: &Session, files: &[PathBuf], out_filename: &Path) -> io::Result<()> {
+    let mut sources = Vec::with_capacity(files.len());
+    for file in files {
+        let ptx = std::fs::read_to_string(file)
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", file.display(), err)))?;
+        sources.push((file.display().to_string(), ptx));
+    }
+    let ptx = std::fs::read_to_string(out_filename)?;
+    match crate::extern_ptx::splice(&ptx, &sources) {
+        Ok(ptx) => std::fs::write(out_filename, ptx),
+        Err(err) => sess.fatal(&err),
+    }
+}
+
+/// Removes the modification times and sizes of source files which can follow the paths of `.file`
+/// directives, which would make the PTX differ between checkouts of the same sources. Paths are
+/// remapped with `--remap-path-prefix` instead.
+fn strip_file_timestamps(ptx: &[u8]) -> Vec<u8> {
+    let ptx = String::from_utf8_lossy(ptx);
+    let mut out = String::with_capacity(ptx.len());
+    for line in ptx.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match trimmed.rfind('"') {
+            Some(end)
+                if trimmed.starts_with(".file") && trimmed[end + 1..].trim().starts_with(',') =>
+            {
+                out.push_str(&line[..line.len() - trimmed.len() + end + 1]);
+                out.push('\n');
+            }
+            _ => out.push_str(line),
+        }
+    }
+    out.into_bytes()
 }
 
 fn create_archive(sess: &Session, files: &[&Path], metadata: &[u8], out_filename: &Path) {
@@ -293,7 +384,13 @@ fn try_create_archive(files: &[&Path], metadata: &[u8], out_filename: &Path) ->
             "Duplicate filename in archive: {:?}",
             file.file_name().unwrap()
         );
-        builder.append_path_with_name(file, file.file_name().unwrap())?;
+        // without the modification time of the file, so that rlibs are reproducible too.
+        let data = std::fs::read(file)?;
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(0);
+        builder.append_data(&mut header, file.file_name().unwrap(), &*data)?;
     }
     builder.into_inner()?;
     Ok(())
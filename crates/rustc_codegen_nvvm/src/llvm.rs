@@ -18,7 +18,7 @@
 #![allow(dead_code)]
 
 use libc::{c_char, c_uint, size_t};
-use libc::{c_int, c_ulonglong};
+use libc::{c_int, c_longlong, c_ulonglong};
 use std::ffi::{CStr, CString};
 use std::fmt;
 use std::hash::{Hash, Hasher};
@@ -248,6 +248,7 @@ pub(crate) enum Attribute {
     ZExt = 19,
     InReg = 20,
     ReadNone = 24,
+    Convergent = 25,
 }
 
 /// LLVMIntPredicate
@@ -465,6 +466,12 @@ extern "C" {
 extern "C" {
     pub(crate) type BasicBlock;
 }
+extern "C" {
+    pub(crate) type TargetData;
+}
+extern "C" {
+    pub(crate) type Use;
+}
 #[repr(C)]
 pub(crate) struct Builder<'a> {
     _inv: InvariantOpaque<'a>,
@@ -621,6 +628,16 @@ pub enum CodeModel {
     None,
 }
 
+/// LLVMRustFileType
+#[derive(Copy, Clone)]
+#[repr(C)]
+#[allow(dead_code)]
+pub enum FileType {
+    Other,
+    AssemblyFile,
+    ObjectFile,
+}
+
 extern "C" {
     #[link_name = "LLVMRustBuildCall"]
     pub(crate) fn __LLVMRustBuildCall<'a>(
@@ -1018,6 +1035,18 @@ extern "C" {
         Singlethread: bool,
     ) -> Option<&'static mut TargetMachine>;
 
+    pub(crate) fn LLVMRustDisposeTargetMachine(T: &'static mut TargetMachine);
+
+    /// Runs the codegen passes of a target machine on a module and writes the result to the
+    /// specified path. Disposes the pass manager.
+    pub(crate) fn LLVMRustWriteOutputFile<'a>(
+        T: &'a TargetMachine,
+        PM: &'a mut PassManager<'a>,
+        M: &'a Module,
+        Output: *const c_char,
+        FileType: FileType,
+    ) -> LLVMRustResult;
+
     pub(crate) fn LLVMRustAddAnalysisPasses<'a>(
         T: &'a TargetMachine,
         PM: &'a PassManager,
@@ -1101,6 +1130,16 @@ extern "C" {
     pub(crate) fn LLVMSetDataLayout(M: &Module, Triple: *const c_char);
 
     pub(crate) fn LLVMRustAppendModuleInlineAsm(M: &Module, Asm: *const c_char, AsmLen: size_t);
+    pub(crate) fn LLVMGetModuleInlineAsm(M: &Module, Len: *mut size_t) -> *const c_char;
+    pub(crate) fn LLVMSetModuleInlineAsm2(M: &Module, Asm: *const c_char, Len: size_t);
+
+    /// Turns the `__nvvm_loop_unroll` calls of `#[unroll]` and `#[no_unroll]` into `llvm.loop`
+    /// metadata.
+    pub(crate) fn LLVMRustApplyLoopUnrollHints(M: &Module);
+
+    /// Replaces local copies of memory behind `noalias readonly` arguments, which are only read,
+    /// with the memory they were copied from.
+    pub(crate) fn LLVMRustForwardReadOnlyCopies(M: &Module);
 
     /// See llvm::LLVMTypeKind::getTypeID.
     pub(crate) fn LLVMRustGetTypeKind(Ty: &Type) -> TypeKind;
@@ -1148,6 +1187,7 @@ extern "C" {
     pub(crate) fn LLVMVectorType(ElementType: &Type, ElementCount: c_uint) -> &Type;
 
     pub(crate) fn LLVMGetElementType(Ty: &Type) -> &Type;
+    pub(crate) fn LLVMStructGetTypeAtIndex(StructTy: &Type, i: c_uint) -> &Type;
     pub(crate) fn LLVMGetVectorSize(VectorTy: &Type) -> c_uint;
     pub(crate) fn LLVMRustGetValueType(V: &Value) -> &Type;
 
@@ -1189,6 +1229,7 @@ extern "C" {
     ) -> &Value;
     pub(crate) fn LLVMConstReal(RealTy: &Type, N: f64) -> &Value;
     pub(crate) fn LLVMConstIntGetZExtValue(ConstantVal: &ConstantInt) -> c_ulonglong;
+    pub(crate) fn LLVMConstIntGetSExtValue(ConstantVal: &ConstantInt) -> c_longlong;
     pub(crate) fn LLVMRustConstInt128Get(
         ConstantVal: &ConstantInt,
         SExt: bool,
@@ -1294,6 +1335,37 @@ extern "C" {
     // Operations on instructions
     pub(crate) fn LLVMIsAInstruction(Val: &Value) -> Option<&Value>;
     pub(crate) fn LLVMGetFirstBasicBlock(Fn: &Value) -> &BasicBlock;
+    pub(crate) fn LLVMGetNextBasicBlock(BB: &BasicBlock) -> Option<&BasicBlock>;
+    pub(crate) fn LLVMGetFirstInstruction(BB: &BasicBlock) -> Option<&Value>;
+    pub(crate) fn LLVMGetNextInstruction(Inst: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAAllocaInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAGetElementPtrInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsACallInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAConstant(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAFunction(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetCalledValue(Instr: &Value) -> &Value;
+    pub(crate) fn LLVMGetNumOperands(Val: &Value) -> c_int;
+    pub(crate) fn LLVMIsALoadInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAStoreInst(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAPHINode(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMIsAConstantExpr(Val: &Value) -> Option<&Value>;
+    pub(crate) fn LLVMGetInstructionOpcode(Inst: &Value) -> c_uint;
+    pub(crate) fn LLVMGetConstOpcode(ConstantVal: &Value) -> c_uint;
+    pub(crate) fn LLVMGetInstructionParent(Inst: &Value) -> &BasicBlock;
+    pub(crate) fn LLVMCountIncoming(PhiNode: &Value) -> c_uint;
+    pub(crate) fn LLVMGetIncomingValue(PhiNode: &Value, Index: c_uint) -> &Value;
+    pub(crate) fn LLVMGetDebugLocFilename(Val: &Value, Length: *mut c_uint) -> *const c_char;
+    pub(crate) fn LLVMGetDebugLocLine(Val: &Value) -> c_uint;
+
+    // Operations on target data
+    pub(crate) fn LLVMGetModuleDataLayout(M: &Module) -> &TargetData;
+    pub(crate) fn LLVMABISizeOfType(TD: &TargetData, Ty: &Type) -> c_ulonglong;
+    pub(crate) fn LLVMStoreSizeOfType(TD: &TargetData, Ty: &Type) -> c_ulonglong;
+
+    // Operations on uses
+    pub(crate) fn LLVMGetFirstUse(Val: &Value) -> Option<&Use>;
+    pub(crate) fn LLVMGetNextUse(U: &Use) -> Option<&Use>;
+    pub(crate) fn LLVMGetUser(U: &Use) -> &Value;
 
     // Operations on call sites
     pub(crate) fn LLVMRustAddCallSiteAttribute(Instr: &Value, index: c_uint, attr: Attribute);
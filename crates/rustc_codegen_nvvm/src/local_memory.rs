@@ -0,0 +1,213 @@
+//! Checks for kernels marked with `#[forbid_local_memory]`, which fail the build if the kernel uses
+//! local memory.
+//!
+//! Local memory is per-thread memory in device memory, which is as slow as global memory unless it
+//! is cached. NVVM puts variables there which it cannot keep in registers, usually arrays indexed
+//! with values only known at runtime, and ptxas spills registers there when a kernel needs more
+//! registers than it may use. Neither shows up anywhere unless the PTX is read, so kernels which
+//! must not use it can opt into this check.
+//!
+//! Variables in local memory show up as a `__local_depot` in the PTX of the kernel, spills only in
+//! the output of ptxas, which is run if it is found. To help finding the variables, the error lists
+//! the local variables of the kernel and the functions it calls which are indexed with runtime
+//! values. rustc only names them in the IR if it keeps value names (`-Zfewer-names=no`, or when
+//! emitting LLVM IR), otherwise only their types are listed.
+
+use crate::llvm::*;
+use crate::report::{self, LMEM, SPILL_LOADS, SPILL_STORES};
+use nvvm::NvvmOption;
+use rustc_session::Session;
+use std::ffi::CStr;
+use std::fs;
+use std::path::Path;
+
+/// A kernel with `#[forbid_local_memory]`.
+pub struct ForbidLocalMemory {
+    kernel: String,
+    /// The variables which likely live in local memory.
+    suspects: Vec<String>,
+}
+
+/// The kernels of `module` with `#[forbid_local_memory]`.
+pub(crate) unsafe fn collect(module: &Module) -> Vec<ForbidLocalMemory> {
+    let name = "cg_nvvm_forbid_local_memory\0".as_ptr().cast();
+    let num_operands = LLVMGetNamedMetadataNumOperands(module, name) as usize;
+    let mut operands = Vec::with_capacity(num_operands);
+    LLVMGetNamedMetadataOperands(module, name, operands.as_mut_ptr());
+    operands.set_len(num_operands);
+
+    let mut kernels = Vec::with_capacity(num_operands);
+    for mdnode in operands {
+        let num_operands = LLVMGetMDNodeNumOperands(mdnode) as usize;
+        let mut operands = Vec::with_capacity(num_operands);
+        LLVMGetMDNodeOperands(mdnode, operands.as_mut_ptr());
+        operands.set_len(num_operands);
+
+        if let Some(llfn) = operands.first() {
+            kernels.push(ForbidLocalMemory {
+                kernel: String::from_utf8_lossy(get_value_name(llfn)).into_owned(),
+                suspects: suspects(llfn),
+            });
+        }
+    }
+    kernels
+}
+
+/// The local variables of `kernel` and the functions it calls which are indexed with runtime values.
+unsafe fn suspects(kernel: &Value) -> Vec<String> {
+    let mut suspects = Vec::new();
+    let mut visited = vec![kernel];
+    let mut stack = vec![kernel];
+    while let Some(llfn) = stack.pop() {
+        if LLVMIsDeclaration(llfn) != 0 {
+            continue;
+        }
+        let mut block = Some(LLVMGetFirstBasicBlock(llfn));
+        while let Some(bb) = block {
+            let mut inst = LLVMGetFirstInstruction(bb);
+            while let Some(i) = inst {
+                if LLVMIsAAllocaInst(i).is_some() && dynamically_indexed(i) {
+                    suspects.push(describe(i));
+                } else if LLVMIsACallInst(i).is_some() {
+                    let callee = LLVMGetCalledValue(i);
+                    if LLVMIsAFunction(callee).is_some() && !visited.contains(&callee) {
+                        visited.push(callee);
+                        stack.push(callee);
+                    }
+                }
+                inst = LLVMGetNextInstruction(i);
+            }
+            block = LLVMGetNextBasicBlock(bb);
+        }
+    }
+    suspects
+}
+
+/// Whether a pointer is indexed with a runtime value, directly or through casts and field accesses.
+unsafe fn dynamically_indexed(ptr: &Value) -> bool {
+    let mut next_use = LLVMGetFirstUse(ptr);
+    while let Some(u) = next_use {
+        let user = LLVMGetUser(u);
+        if LLVMIsAGetElementPtrInst(user).is_some() {
+            // operand 0 is the pointer, the rest are the indices.
+            let dynamic = (1..LLVMGetNumOperands(user) as u32)
+                .any(|i| LLVMIsAConstant(LLVMGetOperand(user, i)).is_none());
+            if dynamic || dynamically_indexed(user) {
+                return true;
+            }
+        } else if LLVMIsABitCastInst(user).is_some() && dynamically_indexed(user) {
+            return true;
+        }
+        next_use = LLVMGetNextUse(u);
+    }
+    false
+}
+
+unsafe fn describe(alloca: &Value) -> String {
+    let ty = LLVMPrintTypeToString(LLVMGetElementType(LLVMTypeOf(alloca)));
+    let ty_str = CStr::from_ptr(ty).to_string_lossy().into_owned();
+    LLVMDisposeMessage(ty);
+    let name = get_value_name(alloca);
+    if name.is_empty() {
+        format!("a temporary of type `{}`", ty_str)
+    } else {
+        format!("`{}` of type `{}`", String::from_utf8_lossy(name), ty_str)
+    }
+}
+
+/// Emits an error for every kernel in `kernels` which uses local memory in the PTX file at
+/// `ptx_path`.
+pub(crate) fn check(
+    sess: &Session,
+    nvvm_opts: &[NvvmOption],
+    kernels: &[ForbidLocalMemory],
+    ptx_path: &Path,
+) {
+    if kernels.is_empty() {
+        return;
+    }
+    let ptx = match fs::read_to_string(ptx_path) {
+        Ok(ptx) => ptx,
+        Err(e) => {
+            sess.warn(&format!(
+                "failed to read PTX for checking #[forbid_local_memory]: {}",
+                e
+            ));
+            return;
+        }
+    };
+    let (resources, ptxas_err) =
+        report::kernel_resources(&ptx, ptx_path, report::target_arch(nvvm_opts));
+    if let Some(e) = ptxas_err {
+        sess.warn(&format!(
+            "could not run ptxas, #[forbid_local_memory] only checks for local variables, not for spills: {}",
+            e
+        ));
+    }
+
+    for ForbidLocalMemory { kernel, suspects } in kernels {
+        let mut uses = Vec::new();
+        let res = resources.get(kernel);
+        let lmem = res.and_then(|res| res[LMEM]);
+        match lmem {
+            Some(lmem) if lmem > 0 => uses.push(format!("{} bytes of local memory", lmem)),
+            None if uses_local_depot(&ptx, kernel) => uses.push("local memory".to_string()),
+            _ => {}
+        }
+        let spills = res.map_or(0, |res| {
+            res[SPILL_STORES].unwrap_or(0) + res[SPILL_LOADS].unwrap_or(0)
+        });
+        if spills > 0 {
+            uses.push(format!("{} bytes of register spills", spills));
+        }
+        if uses.is_empty() {
+            continue;
+        }
+
+        let mut msg = format!(
+            "kernel `{}` is marked #[forbid_local_memory] but uses {}",
+            kernel,
+            uses.join(" and ")
+        );
+        if !suspects.is_empty() {
+            msg.push_str(
+                "\nthese variables are indexed with runtime values and likely live in local memory:",
+            );
+            for suspect in suspects {
+                msg.push_str("\n    ");
+                msg.push_str(suspect);
+            }
+            if sess.fewer_names() {
+                msg.push_str("\nbuild with `-Zfewer-names=no` to see the names of the variables");
+            }
+        }
+        if spills > 0 {
+            msg.push_str(
+                "\nspills happen when the kernel needs more registers than it may use, \
+                 see the `max_registers` hint of #[kernel]",
+            );
+        }
+        sess.err(&msg);
+    }
+    sess.abort_if_errors();
+}
+
+/// Whether the body of the kernel `name` declares a local depot, the local memory of its frame.
+fn uses_local_depot(ptx: &str, name: &str) -> bool {
+    let mut in_kernel = false;
+    for line in ptx.lines() {
+        if !in_kernel {
+            if let Some(idx) = line.find(".entry ") {
+                in_kernel = line[idx + ".entry ".len()..]
+                    .split(|c: char| c == '(' || c.is_whitespace())
+                    .next()
+                    == Some(name);
+            }
+        } else if line.contains("__local_depot") {
+            return true;
+        } else if line == "}" {
+            in_kernel = false;
+        }
+    }
+    false
+}
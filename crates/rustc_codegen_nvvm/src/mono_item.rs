@@ -95,7 +95,10 @@ impl<'ll, 'tcx> PreDefineMethods<'tcx> for CodegenCx<'ll, 'tcx> {
 
         let def_id = instance.def_id();
         let attrs = self.tcx.get_attrs(def_id);
-        let nvvm_attrs = NvvmAttributes::parse(self, attrs);
+        let mut nvvm_attrs = NvvmAttributes::parse(self, attrs);
+        if nvvm_attrs.kernel {
+            nvvm_attrs.apply_kernel_hints(&self.codegen_args.kernel_hints, symbol_name);
+        }
 
         unsafe {
             // if this function is marked as being a kernel, add it
@@ -112,6 +115,69 @@ impl<'ll, 'tcx> PreDefineMethods<'tcx> for CodegenCx<'ll, 'tcx> {
                     node,
                 );
             }
+            // kernels with a fixed cluster size, nvvm turns these into `.reqnctapercluster` and
+            // `.explicitcluster` directives.
+            if let Some(dims) = nvvm_attrs.cluster_dim {
+                trace!(
+                    "Marking kernel `{:?}` with cluster size {:?}",
+                    symbol_name,
+                    dims
+                );
+                for (name, dim) in ["cluster_dim_x", "cluster_dim_y", "cluster_dim_z"]
+                    .iter()
+                    .zip(dims)
+                {
+                    let name = llvm::LLVMMDStringInContext(
+                        self.llcx,
+                        name.as_ptr().cast(),
+                        name.len() as u32,
+                    );
+                    let mdvals = &[lldecl, name, self.const_i32(dim as i32)];
+                    let node =
+                        llvm::LLVMMDNodeInContext(self.llcx, mdvals.as_ptr(), mdvals.len() as u32);
+                    llvm::LLVMAddNamedMetadataOperand(
+                        self.llmod,
+                        "nvvm.annotations\0".as_ptr().cast(),
+                        node,
+                    );
+                }
+            }
+            // register and launch bound hints, nvvm turns these into `.maxnreg`, `.maxntid` and
+            // `.minnctapersm` directives, which limit the registers ptxas allocates for the kernel.
+            let hints = [
+                ("maxnreg", nvvm_attrs.max_registers),
+                ("maxntidx", nvvm_attrs.max_threads),
+                ("minctasm", nvvm_attrs.min_blocks),
+            ];
+            for (name, value) in hints {
+                if let Some(value) = value {
+                    trace!("Marking kernel `{:?}` with {} {}", symbol_name, name, value);
+                    let name = llvm::LLVMMDStringInContext(
+                        self.llcx,
+                        name.as_ptr().cast(),
+                        name.len() as u32,
+                    );
+                    let mdvals = &[lldecl, name, self.const_i32(value as i32)];
+                    let node =
+                        llvm::LLVMMDNodeInContext(self.llcx, mdvals.as_ptr(), mdvals.len() as u32);
+                    llvm::LLVMAddNamedMetadataOperand(
+                        self.llmod,
+                        "nvvm.annotations\0".as_ptr().cast(),
+                        node,
+                    );
+                }
+            }
+            if nvvm_attrs.forbid_local_memory {
+                trace!("Forbidding local memory in `{:?}`", symbol_name);
+                let mdvals = &[lldecl];
+                let node =
+                    llvm::LLVMMDNodeInContext(self.llcx, mdvals.as_ptr(), mdvals.len() as u32);
+                llvm::LLVMAddNamedMetadataOperand(
+                    self.llmod,
+                    "cg_nvvm_forbid_local_memory\0".as_ptr().cast(),
+                    node,
+                );
+            }
             if nvvm_attrs.used {
                 trace!("Marking function `{:?}` as used", symbol_name);
                 let mdvals = &[lldecl];
@@ -1,17 +1,24 @@
-//! Final steps in codegen, coalescing modules and feeding them to libnvvm.
+//! Final steps in codegen, coalescing modules and feeding them to libnvvm (or to LLVM's NVPTX
+//! backend, see [`codegen_bitcode_modules_nvptx`]).
 
 use crate::builder::unnamed;
+use crate::context::CodegenArgs;
 use crate::llvm::*;
+use crate::local_memory::{self, ForbidLocalMemory};
 use crate::lto::ThinBuffer;
-use find_cuda_helper::find_cuda_root;
+use find_cuda_helper::find_nvvm_root;
 use nvvm::*;
 use rustc_codegen_ssa::traits::ThinBufferMethods;
+use rustc_session::config::DebugInfo;
 use rustc_session::Session;
-use std::ffi::OsStr;
+use std::collections::HashSet;
+use std::ffi::{CStr, CString, OsStr};
 use std::fmt::Display;
 use std::fs;
 use std::marker::PhantomData;
+use std::mem::MaybeUninit;
 use std::path::Path;
+use std::ptr;
 use tracing::debug;
 
 // see libintrinsics.ll on what this is.
@@ -49,11 +56,19 @@ impl Display for CodegenErr {
 ///
 /// Note that this will implicitly try to find libdevice and add it, so don't do that
 /// step before this. It will fatal error if it cannot find it.
+///
+/// The kernels with `#[forbid_local_memory]` are added to `forbid_local_memory` and the bank conflict
+/// warnings of `--warn-bank-conflicts` to `bank_conflicts`, to be reported once the PTX is written.
+/// The functions and statics named in `exported` keep their external linkage instead of being internalized,
+/// which is how relocatable device code (`--rdc`) exposes them to the PTX it is linked with.
 pub fn codegen_bitcode_modules(
     opts: &[NvvmOption],
     sess: &Session,
     modules: Vec<Vec<u8>>,
     llcx: &Context,
+    exported: &[String],
+    forbid_local_memory: &mut Vec<ForbidLocalMemory>,
+    bank_conflicts: &mut Vec<String>,
 ) -> Result<Vec<u8>, CodegenErr> {
     debug!("Codegenning bitcode to PTX");
 
@@ -68,9 +83,18 @@ pub fn codegen_bitcode_modules(
     let prog = NvvmProgram::new()?;
 
     let module = merge_llvm_modules(modules, llcx);
+    link_llvm_ir_files(sess, module, llcx);
+    // libnvvm does not check `global_asm!` against the declarations which use it, so it is taken
+    // out of the module and spliced into the PTX like the files given with `--link-ptx`.
+    let global_asm = unsafe { take_module_inline_asm(module) };
     unsafe {
-        internalize_pass(module, llcx);
+        forbid_local_memory.extend(local_memory::collect(module));
+        if CodegenArgs::from_session(sess).warn_bank_conflicts {
+            bank_conflicts.extend(crate::bank_conflicts::collect(module));
+        }
+        internalize_pass(module, llcx, exported);
         dce_pass(module);
+        check_device_runtime_calls(sess, module);
     }
     let buf = ThinBuffer::new(module);
 
@@ -104,7 +128,24 @@ pub fn codegen_bitcode_modules(
         );
     }
 
-    let res = match prog.compile(opts) {
+    // rustc's debuginfo only ends up in the PTX if nvvm is told to use it. Even without full
+    // debug info (`-g`, which requires disabling optimizations), `-generate-line-info` makes nvvm
+    // emit `.file` and `.loc` directives, which lets profilers such as Nsight Compute map
+    // instructions back to the Rust source lines they came from.
+    let mut opts = opts.to_vec();
+    if sess.opts.debuginfo != DebugInfo::None
+        && !opts
+            .iter()
+            .any(|opt| matches!(opt, NvvmOption::GenDebugInfo | NvvmOption::GenLineInfo))
+    {
+        opts.push(NvvmOption::GenLineInfo);
+    }
+    // nvvm rejects `-g` unless optimizations are disabled.
+    if opts.contains(&NvvmOption::GenDebugInfo) && !opts.contains(&NvvmOption::NoOpts) {
+        opts.push(NvvmOption::NoOpts);
+    }
+
+    let res = match prog.compile(&opts) {
         Ok(b) => b,
         Err(_) => {
             // this should never happen, if it does, something went really bad or its a bug on libnvvm's end
@@ -112,13 +153,167 @@ pub fn codegen_bitcode_modules(
         }
     };
 
-    Ok(res)
+    if global_asm.trim().is_empty() {
+        return Ok(res);
+    }
+    let ptx = String::from_utf8_lossy(&res);
+    match crate::extern_ptx::splice(&ptx, &[("global_asm!".to_string(), global_asm)]) {
+        Ok(ptx) => Ok(ptx.into_bytes()),
+        Err(err) => sess.fatal(&err),
+    }
+}
+
+/// Like [`codegen_bitcode_modules`], but generates PTX with LLVM's own NVPTX backend instead of
+/// libnvvm and writes it to `out`. Used with `-Cllvm-args=--nvptx-backend`.
+///
+/// The modules are linked together with libintrinsics and libdevice, then optimized with LLVM's
+/// regular optimization pipeline unless `-opt=0` is given. libdevice is optional in this mode, but
+/// any calls to it (for example libm functions overriden with `--override-libm`) will fail to link
+/// without it.
+#[allow(clippy::too_many_arguments)]
+pub fn codegen_bitcode_modules_nvptx(
+    opts: &[NvvmOption],
+    sess: &Session,
+    modules: Vec<Vec<u8>>,
+    llcx: &Context,
+    exported: &[String],
+    out: &Path,
+    forbid_local_memory: &mut Vec<ForbidLocalMemory>,
+    bank_conflicts: &mut Vec<String>,
+) -> Result<(), String> {
+    debug!("Codegenning bitcode to PTX with the LLVM NVPTX backend");
+
+    let module = merge_llvm_modules(modules, llcx);
+    link_llvm_ir_files(sess, module, llcx);
+    let mut libraries = vec![LIBINTRINSICS.to_vec()];
+    match find_libdevice() {
+        Some(libdevice) => libraries.push(libdevice),
+        None => {
+            sess.warn("Could not find libdevice, calls to libdevice functions will fail to link")
+        }
+    }
+    unsafe {
+        for bc in &libraries {
+            let lib = LLVMRustParseBitcodeForLTO(llcx, bc.as_ptr(), bc.len(), unnamed())
+                .ok_or_else(|| "Failed to parse library bitcode".to_string())?;
+            LLVMLinkModules2(module, lib);
+        }
+        forbid_local_memory.extend(local_memory::collect(module));
+        if CodegenArgs::from_session(sess).warn_bank_conflicts {
+            bank_conflicts.extend(crate::bank_conflicts::collect(module));
+        }
+        internalize_pass(module, llcx, exported);
+        dce_pass(module);
+        check_device_runtime_calls(sess, module);
+    }
+
+    let arch = opts
+        .iter()
+        .find_map(|opt| match opt {
+            NvvmOption::Arch(arch) => Some(*arch),
+            _ => None,
+        })
+        .unwrap_or_default();
+    let optimize = !opts.contains(&NvvmOption::NoOpts);
+
+    if opts.contains(&NvvmOption::Ftz) {
+        unsafe {
+            // read by libdevice through `__nvvm_reflect("__CUDA_FTZ")`.
+            LLVMRustAddModuleFlag(module, "nvvm-reflect-ftz\0".as_ptr().cast(), 1);
+            for func in FunctionIter::new(&module) {
+                LLVMRustAddFunctionAttrStringValue(
+                    func,
+                    AttributePlace::Function.as_uint(),
+                    "nvptx-f32ftz\0".as_ptr().cast(),
+                    "true\0".as_ptr().cast(),
+                );
+            }
+        }
+    }
+
+    let triple = CString::new(&*sess.target.llvm_target).unwrap();
+    let cpu = CString::new(format!("sm_{}", arch.capability())).unwrap();
+    let opt_level = if optimize {
+        CodeGenOptLevel::Aggressive
+    } else {
+        CodeGenOptLevel::None
+    };
+    let tm = unsafe {
+        LLVMRustCreateTargetMachine(
+            triple.as_ptr(),
+            cpu.as_ptr(),
+            "\0".as_ptr().cast(),
+            CodeModel::None,
+            RelocMode::Default,
+            opt_level,
+            false,
+            false,
+            false,
+            false,
+            false,
+            false,
+        )
+    }
+    .ok_or_else(|| {
+        last_error().unwrap_or_else(|| "Could not create the NVPTX target machine".to_string())
+    })?;
+
+    let res = unsafe {
+        if optimize {
+            let pm = LLVMCreatePassManager();
+            LLVMRustAddAnalysisPasses(tm, pm, module);
+            let builder = LLVMPassManagerBuilderCreate();
+            LLVMRustConfigurePassManagerBuilder(
+                builder,
+                opt_level,
+                false,
+                false,
+                false,
+                false,
+                std::ptr::null(),
+                std::ptr::null(),
+            );
+            LLVMPassManagerBuilderUseInlinerWithThreshold(builder, 275);
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, pm);
+            LLVMPassManagerBuilderDispose(builder);
+            LLVMRunPassManager(pm, module);
+            LLVMDisposePassManager(pm);
+        } else {
+            // `#[inline(always)]` must be honored without optimizations too, libnvvm does the same.
+            let pm = LLVMCreatePassManager();
+            let builder = LLVMPassManagerBuilderCreate();
+            LLVMRustConfigurePassManagerBuilder(
+                builder,
+                CodeGenOptLevel::None,
+                false,
+                false,
+                false,
+                false,
+                std::ptr::null(),
+                std::ptr::null(),
+            );
+            LLVMRustAddAlwaysInlinePass(builder, false);
+            LLVMPassManagerBuilderPopulateModulePassManager(builder, pm);
+            LLVMPassManagerBuilderDispose(builder);
+            LLVMRunPassManager(pm, module);
+            LLVMDisposePassManager(pm);
+        }
+
+        let out = CString::new(out.to_string_lossy().as_bytes()).unwrap();
+        let pm = LLVMCreatePassManager();
+        LLVMRustAddAnalysisPasses(tm, pm, module);
+        let res = LLVMRustWriteOutputFile(tm, pm, module, out.as_ptr(), FileType::AssemblyFile);
+        LLVMRustDisposeTargetMachine(tm);
+        res
+    };
+    res.into_result()
+        .map_err(|_| last_error().unwrap_or_else(|| "Failed to write the PTX file".to_string()))
 }
 
 /// Find the libdevice bitcode library which contains math intrinsics and is
 /// linked when building the nvvm program.
 pub fn find_libdevice() -> Option<Vec<u8>> {
-    if let Some(base_path) = find_cuda_root() {
+    if let Some(base_path) = find_nvvm_root() {
         let libdevice_file = fs::read_dir(Path::new(&base_path).join("nvvm").join("libdevice"))
             .ok()?
             .filter_map(Result::ok)
@@ -140,6 +335,8 @@ pub fn find_libdevice() -> Option<Vec<u8>> {
 //      - If it is not a kernel and it is not a declaration (i.e. an extern fn) then mark its linkage as internal and its visiblity as default
 // - Iterate over every global in the module and:
 //      - Same as functions, if it is not an external declaration, mark it as internal.
+// - With relocatable device code (`--rdc`), the symbols exported by the crate (`#[no_mangle]` items) are not internalized, so
+// that the PTX linked with this one can use them.
 // - run LLVM's global DCE pass, this will remove any functions and globals that are not directly or indirectly used by kernels.
 
 fn merge_llvm_modules(modules: Vec<Vec<u8>>, llcx: &Context) -> &Module {
@@ -159,6 +356,49 @@ fn merge_llvm_modules(modules: Vec<Vec<u8>>, llcx: &Context) -> &Module {
     module
 }
 
+/// Links the LLVM IR files given with `--link-llvm-ir` into `module`.
+fn link_llvm_ir_files(sess: &Session, module: &Module, llcx: &Context) {
+    for path in CodegenArgs::from_session(sess).link_llvm_ir {
+        let mut ir = fs::read(&path).unwrap_or_else(|err| {
+            sess.fatal(&format!("Failed to read {}: {}", path.display(), err))
+        });
+        // the lexer of textual IR reads until the nul after the end of the buffer.
+        ir.push(0);
+        let name = CString::new(path.display().to_string()).unwrap_or_default();
+        unsafe {
+            // LLVMParseIRInContext takes ownership of the buffer, which borrows `ir`.
+            let buf = LLVMCreateMemoryBufferWithMemoryRange(
+                ir.as_ptr().cast(),
+                ir.len() - 1,
+                name.as_ptr(),
+                True,
+            );
+            let mut parsed = MaybeUninit::uninit();
+            let mut message = ptr::null_mut();
+            if LLVMParseIRInContext(llcx, buf, parsed.as_mut_ptr(), &mut message) == True {
+                let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+                sess.fatal(&format!("Failed to parse {}: {}", path.display(), message));
+            }
+            if LLVMLinkModules2(module, parsed.assume_init()) == True {
+                sess.fatal(&format!("Failed to link {}", path.display()));
+            }
+        }
+    }
+}
+
+/// Removes the module-level inline assembly (from `global_asm!`) of `module`, returning it.
+unsafe fn take_module_inline_asm(module: &Module) -> String {
+    let mut len = 0;
+    let asm = LLVMGetModuleInlineAsm(module, &mut len);
+    if asm.is_null() || len == 0 {
+        return String::new();
+    }
+    let bytes = std::slice::from_raw_parts(asm.cast::<u8>(), len);
+    let asm = String::from_utf8_lossy(bytes).into_owned();
+    LLVMSetModuleInlineAsm2(module, ptr::null(), 0);
+    asm
+}
+
 struct FunctionIter<'a, 'll> {
     module: PhantomData<&'a &'ll Module>,
     next: Option<&'ll Value>,
@@ -217,7 +457,7 @@ impl<'a, 'll> Iterator for GlobalIter<'a, 'll> {
     }
 }
 
-unsafe fn internalize_pass(module: &Module, cx: &Context) {
+unsafe fn internalize_pass(module: &Module, cx: &Context, exported: &[String]) {
     // collect the values of all the declared kernels
     let num_operands =
         LLVMGetNamedMetadataNumOperands(module, "nvvm.annotations\0".as_ptr().cast()) as usize;
@@ -263,11 +503,16 @@ unsafe fn internalize_pass(module: &Module, cx: &Context) {
         used_funcs.push(operands[0]);
     }
 
+    let exported = exported
+        .iter()
+        .map(|name| name.as_bytes())
+        .collect::<HashSet<_>>();
+
     let iter = FunctionIter::new(&module);
     for func in iter {
         let is_kernel = kernels.contains(&func);
         let is_decl = LLVMIsDeclaration(func) == True;
-        let is_used = used_funcs.contains(&func);
+        let is_used = used_funcs.contains(&func) || exported.contains(get_value_name(func));
 
         if !is_decl && !is_kernel {
             LLVMRustSetLinkage(func, Linkage::InternalLinkage);
@@ -284,14 +529,40 @@ unsafe fn internalize_pass(module: &Module, cx: &Context) {
     let iter = GlobalIter::new(&module);
     for func in iter {
         let is_decl = LLVMIsDeclaration(func) == True;
+        // the ABI hashes of kernels are never used on the device, but are read by the host.
+        let is_abi_hash = get_value_name(func).starts_with(b"__cuda_abi_");
+        let is_exported = exported.contains(get_value_name(func));
 
-        if !is_decl {
+        if !is_decl && !is_abi_hash && !is_exported {
             LLVMRustSetLinkage(func, Linkage::InternalLinkage);
             LLVMRustSetVisibility(func, Visibility::Default);
         }
     }
 }
 
+/// Stops the compilation if a kernel calls a function of the CUDA device runtime (`cudaLaunchDeviceV2`,
+/// `cudaDeviceSynchronize`, etc) without `--rdc`. The device runtime is a library which the PTX must be
+/// linked against, which only works for relocatable device code, loading the PTX directly fails with
+/// an unhelpful "named symbol not found" instead.
+unsafe fn check_device_runtime_calls(sess: &Session, module: &Module) {
+    if CodegenArgs::from_session(sess).rdc {
+        return;
+    }
+    let names = FunctionIter::new(&module)
+        .filter(|&func| LLVMIsDeclaration(func) == True)
+        .map(get_value_name)
+        .filter(|name| name.starts_with(b"cuda"))
+        .map(|name| format!("`{}`", String::from_utf8_lossy(name)))
+        .collect::<Vec<_>>();
+    if !names.is_empty() {
+        sess.fatal(&format!(
+            "calling {} of the CUDA device runtime requires relocatable device code, build with \
+             `-Cllvm-args=--rdc` (`CudaBuilder::dynamic_parallelism`)",
+            names.join(", ")
+        ));
+    }
+}
+
 unsafe fn dce_pass(module: &Module) {
     let pass_manager = LLVMCreatePassManager();
 
@@ -0,0 +1,275 @@
+//! Per-kernel resource usage reports, emitted with `-Cllvm-args=--emit=gpu-report`.
+//!
+//! The report lists the PTX size of every kernel and, if `ptxas` from the CUDA toolkit is found,
+//! the registers, shared/constant/local memory and spills of the kernel when compiled for the
+//! target architecture. It is written next to the PTX file with a `.gpu-report` extension, and
+//! every value which grew since the report of the previous build is added to the report as a
+//! `# regression:` line and emitted as a warning.
+
+use find_cuda_helper::find_nvvm_root;
+use nvvm::{NvvmArch, NvvmOption};
+use rustc_session::Session;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The prefix of the lines listing regressions in the report.
+const REGRESSION_PREFIX: &str = "# regression: ";
+
+const COLUMNS: [&str; 7] = [
+    "registers",
+    "smem",
+    "cmem",
+    "lmem",
+    "spill_stores",
+    "spill_loads",
+    "ptx_bytes",
+];
+
+/// The resources of a single kernel, in the order of [`COLUMNS`]. The values reported by ptxas
+/// are `None` if ptxas could not be run.
+pub(crate) type Resources = [Option<u64>; 7];
+
+pub(crate) const LMEM: usize = 3;
+pub(crate) const SPILL_STORES: usize = 4;
+pub(crate) const SPILL_LOADS: usize = 5;
+const PTX_BYTES: usize = 6;
+
+/// Writes the report for the PTX file at `ptx_path` and warns about any regressions.
+pub(crate) fn emit_report(sess: &Session, nvvm_opts: &[NvvmOption], ptx_path: &Path) {
+    let ptx = match fs::read_to_string(ptx_path) {
+        Ok(ptx) => ptx,
+        Err(e) => {
+            sess.warn(&format!("failed to read PTX for the gpu report: {}", e));
+            return;
+        }
+    };
+    let arch = target_arch(nvvm_opts);
+    let (kernels, ptxas_err) = kernel_resources(&ptx, ptx_path, arch);
+    if let Some(e) = ptxas_err {
+        sess.warn(&format!(
+            "could not run ptxas, the gpu report only contains PTX sizes: {}",
+            e
+        ));
+    }
+
+    let report_path = ptx_path.with_extension("gpu-report");
+    let regressions = fs::read_to_string(&report_path)
+        .map(|old| regressions(&parse_report(&old), &kernels))
+        .unwrap_or_default();
+    for regression in &regressions {
+        sess.warn(regression);
+    }
+
+    let mut report = format!(
+        "# gpu resource report for sm_{}\n# kernel {}\n",
+        arch.capability(),
+        COLUMNS.join(" ")
+    );
+    for regression in &regressions {
+        writeln!(report, "{}{}", REGRESSION_PREFIX, regression).unwrap();
+    }
+    for (name, resources) in &kernels {
+        report.push_str(name);
+        for value in resources {
+            match value {
+                Some(value) => write!(report, " {}", value).unwrap(),
+                None => report.push_str(" -"),
+            }
+        }
+        report.push('\n');
+    }
+    if let Err(e) = fs::write(&report_path, report) {
+        sess.warn(&format!("failed to write the gpu report: {}", e));
+    }
+}
+
+/// The architecture the PTX is compiled for.
+pub(crate) fn target_arch(nvvm_opts: &[NvvmOption]) -> NvvmArch {
+    nvvm_opts
+        .iter()
+        .find_map(|opt| match opt {
+            NvvmOption::Arch(arch) => Some(*arch),
+            _ => None,
+        })
+        .unwrap_or_default()
+}
+
+/// The resources of every kernel of the PTX file at `ptx_path` with the contents `ptx`, with the
+/// error of running ptxas if it failed.
+pub(crate) fn kernel_resources(
+    ptx: &str,
+    ptx_path: &Path,
+    arch: NvvmArch,
+) -> (BTreeMap<String, Resources>, Option<String>) {
+    let mut kernels = ptx_sizes(ptx);
+    match run_ptxas(ptx_path, arch) {
+        Ok(log) => {
+            parse_ptxas_log(&log, &mut kernels);
+            (kernels, None)
+        }
+        Err(e) => (kernels, Some(e)),
+    }
+}
+
+/// Finds every `.entry` in the PTX and its size in bytes, up to its closing brace.
+fn ptx_sizes(ptx: &str) -> BTreeMap<String, Resources> {
+    let mut kernels = BTreeMap::new();
+    let mut current: Option<(String, u64)> = None;
+    for line in ptx.lines() {
+        if current.is_none() {
+            if let Some(idx) = line.find(".entry ") {
+                let name = line[idx + ".entry ".len()..]
+                    .split(|c: char| c == '(' || c.is_whitespace())
+                    .next()
+                    .unwrap_or_default();
+                current = Some((name.to_string(), 0));
+            }
+        }
+        if let Some((name, size)) = &mut current {
+            *size += line.len() as u64 + 1;
+            if line == "}" {
+                let mut resources = [None; 7];
+                resources[PTX_BYTES] = Some(*size);
+                kernels.insert(std::mem::take(name), resources);
+                current = None;
+            }
+        }
+    }
+    kernels
+}
+
+fn run_ptxas(ptx_path: &Path, arch: NvvmArch) -> Result<String, String> {
+    let root = find_nvvm_root().ok_or("the CUDA toolkit was not found")?;
+    let ptxas = root
+        .join("bin")
+        .join(if cfg!(windows) { "ptxas.exe" } else { "ptxas" });
+    let cubin: PathBuf = ptx_path.with_extension("gpu-report.cubin");
+    let output = Command::new(&ptxas)
+        .arg("-v")
+        .arg(format!("--gpu-name=sm_{}", arch.capability()))
+        .arg("-o")
+        .arg(&cubin)
+        .arg(ptx_path)
+        .output()
+        .map_err(|e| format!("{}: {}", ptxas.display(), e))?;
+    let _ = fs::remove_file(&cubin);
+    let log = String::from_utf8_lossy(&output.stderr).into_owned();
+    if output.status.success() {
+        Ok(log)
+    } else {
+        Err(log)
+    }
+}
+
+/// Reads the resources of every kernel from the output of `ptxas -v`, which looks like this:
+///
+/// ```text
+/// ptxas info    : Compiling entry function 'render' for 'sm_61'
+/// ptxas info    : Function properties for render
+///     16 bytes stack frame, 8 bytes spill stores, 8 bytes spill loads
+/// ptxas info    : Used 40 registers, 368 bytes cmem[0], 4 bytes cmem[2], 16 bytes lmem
+/// ```
+fn parse_ptxas_log(log: &str, kernels: &mut BTreeMap<String, Resources>) {
+    let mut entry: Option<String> = None;
+    let mut properties_of: Option<String> = None;
+    for line in log.lines() {
+        let line = line.trim();
+        if let Some(rest) = line.split("Compiling entry function '").nth(1) {
+            entry = rest.split('\'').next().map(str::to_string);
+        } else if let Some(name) = line.split("Function properties for ").nth(1) {
+            properties_of = Some(name.trim().to_string());
+        } else if line.contains("bytes spill stores") {
+            let res = match properties_of.take().and_then(|name| kernels.get_mut(&name)) {
+                Some(res) => res,
+                None => continue,
+            };
+            for item in line.split(", ") {
+                let value = leading_number(item);
+                if item.ends_with("spill stores") {
+                    res[SPILL_STORES] = value;
+                } else if item.ends_with("spill loads") {
+                    res[SPILL_LOADS] = value;
+                }
+            }
+        } else if let Some(used) = line.split("Used ").nth(1) {
+            let res = match entry.as_ref().and_then(|name| kernels.get_mut(name)) {
+                Some(res) => res,
+                None => continue,
+            };
+            res[..4].copy_from_slice(&[Some(0); 4]);
+            for item in used.split(", ") {
+                let value = leading_number(item).unwrap_or(0);
+                let idx = if item.ends_with("registers") {
+                    0
+                } else if item.ends_with("smem") {
+                    1
+                } else if item.contains("cmem") {
+                    2
+                } else if item.ends_with("lmem") {
+                    3
+                } else {
+                    continue;
+                };
+                // constant memory is reported per bank, add all of them up.
+                res[idx] = Some(res[idx].unwrap_or(0) + value);
+            }
+        }
+    }
+    // spills are only reported in the function properties, kernels without any did not spill.
+    for res in kernels.values_mut() {
+        if res[0].is_some() {
+            res[SPILL_STORES] = res[SPILL_STORES].or(Some(0));
+            res[SPILL_LOADS] = res[SPILL_LOADS].or(Some(0));
+        }
+    }
+}
+
+fn leading_number(s: &str) -> Option<u64> {
+    s.split_whitespace().next()?.parse().ok()
+}
+
+fn parse_report(report: &str) -> BTreeMap<String, Resources> {
+    report
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?.to_string();
+            let mut resources = [None; 7];
+            for (res, part) in resources.iter_mut().zip(parts) {
+                *res = part.parse().ok();
+            }
+            Some((name, resources))
+        })
+        .collect()
+}
+
+/// Describes every value which grew between the `old` and the `new` report.
+fn regressions(
+    old: &BTreeMap<String, Resources>,
+    new: &BTreeMap<String, Resources>,
+) -> Vec<String> {
+    let mut out = Vec::new();
+    for (name, new_res) in new {
+        let old_res = match old.get(name) {
+            Some(res) => res,
+            None => continue,
+        };
+        for ((column, old), new) in COLUMNS.iter().zip(old_res).zip(new_res) {
+            if let (Some(old), Some(new)) = (old, new) {
+                if new > old {
+                    out.push(format!(
+                        "kernel `{}` {} grew from {} to {}",
+                        name, column, old, new
+                    ));
+                }
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,503 @@
+//! Codegen for the `simd_*` platform intrinsics, which `core::simd` and `#[repr(simd)]` types are
+//! built on.
+//!
+//! SIMD types are LLVM vectors, so that loads and stores of vectors of up to 128 bits become the
+//! `ld.v2`/`ld.v4` instructions of PTX. GPUs do not have vector ALUs though (the SIMD of a GPU is the
+//! warp), and libnvvm only reliably accepts loads, stores, `extractelement` and `insertelement` on
+//! vectors, so every operation is unrolled into one scalar operation per lane. Floating point math
+//! like `simd_fsqrt` calls the libdevice function of every lane.
+
+use crate::builder::Builder;
+use crate::intrinsic::saturating_intrinsic_impl;
+use crate::llvm::{self, Type, Value};
+use crate::target;
+use rustc_codegen_ssa::common::{span_invalid_monomorphization_error, IntPredicate, RealPredicate};
+use rustc_codegen_ssa::mir::operand::OperandRef;
+use rustc_codegen_ssa::traits::{BaseTypeMethods, BuilderMethods, ConstMethods};
+use rustc_middle::ty::{self, Ty};
+use rustc_span::{Span, Symbol};
+
+/// The kind of the elements of a SIMD type.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Elem {
+    Int { width: u64, signed: bool },
+    Float { width: u64 },
+}
+
+impl Elem {
+    fn of(ty: Ty<'_>) -> Option<Self> {
+        let pointer_width = target::pointer_size() as u64;
+        match ty.kind() {
+            ty::Int(t) => Some(Elem::Int {
+                width: t.bit_width().unwrap_or(pointer_width),
+                signed: true,
+            }),
+            ty::Uint(t) => Some(Elem::Int {
+                width: t.bit_width().unwrap_or(pointer_width),
+                signed: false,
+            }),
+            ty::Float(t) => Some(Elem::Float {
+                width: t.bit_width(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts every lane of `vec`.
+fn lanes<'a, 'll, 'tcx>(
+    bx: &mut Builder<'a, 'll, 'tcx>,
+    vec: &'ll Value,
+    len: u64,
+) -> Vec<&'ll Value> {
+    (0..len)
+        .map(|i| {
+            let idx = bx.const_i32(i as i32);
+            bx.extract_element(vec, idx)
+        })
+        .collect()
+}
+
+/// Builds a vector of type `ty` out of its lanes.
+fn vector<'a, 'll, 'tcx>(
+    bx: &mut Builder<'a, 'll, 'tcx>,
+    ty: &'ll Type,
+    lanes: impl IntoIterator<Item = &'ll Value>,
+) -> &'ll Value {
+    let undef = bx.const_undef(ty);
+    lanes.into_iter().enumerate().fold(undef, |vec, (i, lane)| {
+        let idx = bx.const_i32(i as i32);
+        bx.insert_element(vec, lane, idx)
+    })
+}
+
+/// The libdevice function of a float math intrinsic, for elements of `width` bits.
+fn libdevice_fn(name: &str, width: u64) -> Option<String> {
+    let base = match name {
+        "simd_fsqrt" => "sqrt",
+        "simd_fabs" => "fabs",
+        "simd_floor" => "floor",
+        "simd_ceil" => "ceil",
+        "simd_round" => "round",
+        "simd_trunc" => "trunc",
+        "simd_fsin" => "sin",
+        "simd_fcos" => "cos",
+        "simd_fexp" => "exp",
+        "simd_fexp2" => "exp2",
+        "simd_flog" => "log",
+        "simd_flog2" => "log2",
+        "simd_flog10" => "log10",
+        "simd_fpow" => "pow",
+        "simd_fpowi" => "powi",
+        "simd_fmin" => "fmin",
+        "simd_fmax" => "fmax",
+        "simd_fma" => "fma",
+        _ => return None,
+    };
+    match width {
+        32 => Some(format!("__nv_{}f", base)),
+        64 => Some(format!("__nv_{}", base)),
+        _ => None,
+    }
+}
+
+pub(crate) fn generic_simd_intrinsic<'a, 'll, 'tcx>(
+    bx: &mut Builder<'a, 'll, 'tcx>,
+    name: Symbol,
+    args: &[OperandRef<'tcx, &'ll Value>],
+    ret_ty: Ty<'tcx>,
+    llret_ty: &'ll Type,
+    span: Span,
+) -> Result<&'ll Value, ()> {
+    let name_str = &*name.as_str();
+    let tcx = bx.tcx;
+
+    macro_rules! return_error {
+        ($($fmt:tt)*) => {{
+            span_invalid_monomorphization_error(
+                tcx.sess,
+                span,
+                &format!(
+                    "invalid monomorphization of `{}` intrinsic: {}",
+                    name,
+                    format!($($fmt)*)
+                ),
+            );
+            return Err(());
+        }};
+    }
+
+    macro_rules! require {
+        ($cond:expr, $($fmt:tt)*) => {
+            if !$cond {
+                return_error!($($fmt)*);
+            }
+        };
+    }
+
+    macro_rules! require_simd {
+        ($ty:expr, $position:expr) => {
+            require!(
+                $ty.is_simd(),
+                "expected SIMD {} type, found non-SIMD `{}`",
+                $position,
+                $ty
+            )
+        };
+    }
+
+    if name_str == "simd_select_bitmask" {
+        // the mask is an integer with a bit per lane, starting with the lowest bit.
+        let vec_ty = args[1].layout.ty;
+        require_simd!(vec_ty, "argument");
+        let (len, _) = vec_ty.simd_size_and_type(tcx);
+        require!(
+            matches!(Elem::of(args[0].layout.ty), Some(Elem::Int { width, .. }) if width >= len),
+            "expected an integer of at least {} bits as the mask, found `{}`",
+            len,
+            args[0].layout.ty
+        );
+        let mask = args[0].immediate();
+        let mask_ty = bx.val_ty(mask);
+        let then_lanes = lanes(bx, args[1].immediate(), len);
+        let else_lanes = lanes(bx, args[2].immediate(), len);
+        let mut out = Vec::with_capacity(len as usize);
+        for (i, (then_val, else_val)) in then_lanes.into_iter().zip(else_lanes).enumerate() {
+            let bit = bx.lshr(mask, bx.const_uint(mask_ty, i as u64));
+            let bit = bx.trunc(bit, bx.type_i1());
+            out.push(bx.select(bit, then_val, else_val));
+        }
+        return Ok(vector(bx, llret_ty, out));
+    }
+
+    let in_ty = args[0].layout.ty;
+    require_simd!(in_ty, "input");
+    let (in_len, in_elem) = in_ty.simd_size_and_type(tcx);
+    let in_kind = Elem::of(in_elem);
+    require!(
+        in_kind.is_some(),
+        "unsupported element type `{}` of `{}`, only integers and floats are supported on the GPU",
+        in_elem,
+        in_ty
+    );
+    let in_kind = in_kind.unwrap();
+    let a = args[0].immediate();
+
+    // the intrinsics which do not return a vector of the same length.
+    match name_str {
+        "simd_extract" => {
+            require!(
+                ret_ty == in_elem,
+                "expected return type `{}` (element of input `{}`), found `{}`",
+                in_elem,
+                in_ty,
+                ret_ty
+            );
+            return Ok(bx.extract_element(a, args[1].immediate()));
+        }
+        "simd_insert" => {
+            require!(
+                args[2].layout.ty == in_elem,
+                "expected inserted type `{}` (element of input `{}`), found `{}`",
+                in_elem,
+                in_ty,
+                args[2].layout.ty
+            );
+            return Ok(bx.insert_element(a, args[2].immediate(), args[1].immediate()));
+        }
+        "simd_bitmask" => {
+            require!(
+                matches!(Elem::of(ret_ty), Some(Elem::Int { width, .. }) if width >= in_len),
+                "expected an integer of at least {} bits for the bitmask of `{}`, found `{}`",
+                in_len,
+                in_ty,
+                ret_ty
+            );
+            require!(
+                matches!(in_kind, Elem::Int { .. }),
+                "expected a mask of integers, found `{}`",
+                in_ty
+            );
+            // the lanes of masks are all ones or all zeroes, so their sign is the bit of the lane.
+            let lanes = lanes(bx, a, in_len);
+            let zero = bx.const_int(bx.val_ty(lanes[0]), 0);
+            let mut mask = bx.const_uint(llret_ty, 0);
+            for (i, lane) in lanes.into_iter().enumerate() {
+                let set = bx.icmp(IntPredicate::IntSLT, lane, zero);
+                let bit = bx.zext(set, llret_ty);
+                let bit = bx.shl(bit, bx.const_uint(llret_ty, i as u64));
+                mask = bx.or(mask, bit);
+            }
+            return Ok(mask);
+        }
+        _ => {}
+    }
+
+    if let Some(reduction) = name_str.strip_prefix("simd_reduce_") {
+        let lanes = lanes(bx, a, in_len);
+        let boolean = matches!(reduction, "all" | "any");
+        if boolean {
+            require!(
+                matches!(in_kind, Elem::Int { .. }) && ret_ty.is_bool(),
+                "expected a mask of integers reduced to a `bool`, found `{}` -> `{}`",
+                in_ty,
+                ret_ty
+            );
+        } else {
+            require!(
+                ret_ty == in_elem,
+                "expected return type `{}` (element of input `{}`), found `{}`",
+                in_elem,
+                in_ty,
+                ret_ty
+            );
+        }
+
+        let (first, rest) = match reduction {
+            // the ordered reductions start with the accumulator argument.
+            "add_ordered" | "mul_ordered" => (args[1].immediate(), &lanes[..]),
+            _ => (lanes[0], &lanes[1..]),
+        };
+        let mut acc = first;
+        if boolean {
+            let zero = bx.const_int(bx.val_ty(acc), 0);
+            acc = bx.icmp(IntPredicate::IntNE, acc, zero);
+        }
+        for &lane in rest {
+            acc = match (reduction, in_kind) {
+                ("add_ordered" | "add_unordered", Elem::Int { .. }) => bx.add(acc, lane),
+                ("add_ordered" | "add_unordered", Elem::Float { .. }) => bx.fadd(acc, lane),
+                ("mul_ordered" | "mul_unordered", Elem::Int { .. }) => bx.mul(acc, lane),
+                ("mul_ordered" | "mul_unordered", Elem::Float { .. }) => bx.fmul(acc, lane),
+                ("and", Elem::Int { .. }) => bx.and(acc, lane),
+                ("or", Elem::Int { .. }) => bx.or(acc, lane),
+                ("xor", Elem::Int { .. }) => bx.xor(acc, lane),
+                ("all" | "any", _) => {
+                    let zero = bx.const_int(bx.val_ty(lane), 0);
+                    let lane = bx.icmp(IntPredicate::IntNE, lane, zero);
+                    if reduction == "all" {
+                        bx.and(acc, lane)
+                    } else {
+                        bx.or(acc, lane)
+                    }
+                }
+                ("min" | "min_nanless" | "max" | "max_nanless", Elem::Int { signed, .. }) => {
+                    let predicate = match (reduction.starts_with("min"), signed) {
+                        (true, true) => IntPredicate::IntSLT,
+                        (true, false) => IntPredicate::IntULT,
+                        (false, true) => IntPredicate::IntSGT,
+                        (false, false) => IntPredicate::IntUGT,
+                    };
+                    let keep = bx.icmp(predicate, acc, lane);
+                    bx.select(keep, acc, lane)
+                }
+                ("min" | "min_nanless" | "max" | "max_nanless", Elem::Float { width }) => {
+                    let op = if reduction.starts_with("min") {
+                        "simd_fmin"
+                    } else {
+                        "simd_fmax"
+                    };
+                    let llfn = bx.get_intrinsic(&libdevice_fn(op, width).unwrap());
+                    bx.call(bx.type_i1(), llfn, &[acc, lane], None)
+                }
+                _ => return_error!("unsupported reduction `{}` of `{}`", reduction, in_ty),
+            };
+        }
+        return Ok(if boolean {
+            bx.zext(acc, bx.type_i8())
+        } else {
+            acc
+        });
+    }
+
+    require_simd!(ret_ty, "return");
+    let (out_len, out_elem) = ret_ty.simd_size_and_type(tcx);
+
+    if let Some(suffix) = name_str.strip_prefix("simd_shuffle") {
+        // `simd_shuffleN`, or `simd_shuffle` with the length of the index array.
+        let n = if suffix.is_empty() {
+            match args[2].layout.ty.kind() {
+                ty::Array(_, len) => len.eval_usize(tcx, ty::ParamEnv::reveal_all()),
+                _ => return_error!("expected an array of indices"),
+            }
+        } else {
+            suffix.parse().unwrap()
+        };
+        require!(
+            out_len == n && out_elem == in_elem,
+            "expected return type `[{}; {}]`, found `{}`",
+            in_elem,
+            n,
+            ret_ty
+        );
+
+        // the indices are a constant struct created by codegen_ssa.
+        let indices = args[2].immediate();
+        let mut inputs = lanes(bx, a, in_len);
+        inputs.extend(lanes(bx, args[1].immediate(), in_len));
+        let mut out = Vec::with_capacity(n as usize);
+        for i in 0..n {
+            let idx = [i as u32];
+            let idx = unsafe { llvm::LLVMConstExtractValue(indices, idx.as_ptr(), 1) };
+            let idx = bx.const_to_opt_u128(idx, true).map(|idx| idx as usize);
+            require!(
+                matches!(idx, Some(idx) if idx < inputs.len()),
+                "shuffle index #{} is not a constant less than {}",
+                i,
+                inputs.len()
+            );
+            out.push(inputs[idx.unwrap()]);
+        }
+        return Ok(vector(bx, llret_ty, out));
+    }
+
+    require!(
+        out_len == in_len,
+        "expected return type with length {} (same as input type `{}`), found `{}` with length {}",
+        in_len,
+        in_ty,
+        ret_ty,
+        out_len
+    );
+    let out_kind = Elem::of(out_elem);
+    require!(
+        out_kind.is_some(),
+        "unsupported element type `{}` of `{}`",
+        out_elem,
+        ret_ty
+    );
+    let out_kind = out_kind.unwrap();
+    let out_lane_ty = bx.element_type(llret_ty);
+
+    // every other intrinsic works lane by lane on vectors of the same length, with one scalar
+    // argument for `simd_fpowi` and the mask first for `simd_select`.
+    let vector_args = args
+        .iter()
+        .filter(|arg| arg.layout.ty.is_simd())
+        .map(|arg| lanes(bx, arg.immediate(), in_len))
+        .collect::<Vec<_>>();
+    let lane_args = |i: usize| vector_args.iter().map(|lanes| lanes[i]).collect::<Vec<_>>();
+
+    if name_str == "simd_select" {
+        require!(
+            matches!(in_kind, Elem::Int { .. }),
+            "expected a mask of integers, found `{}`",
+            in_ty
+        );
+        let mut out = Vec::with_capacity(in_len as usize);
+        for i in 0..in_len as usize {
+            let lanes = lane_args(i);
+            let zero = bx.const_int(bx.val_ty(lanes[0]), 0);
+            let mask = bx.icmp(IntPredicate::IntNE, lanes[0], zero);
+            out.push(bx.select(mask, lanes[1], lanes[2]));
+        }
+        return Ok(vector(bx, llret_ty, out));
+    }
+
+    let mut out = Vec::with_capacity(in_len as usize);
+    for i in 0..in_len as usize {
+        let lanes = lane_args(i);
+        let lane = match (name_str, in_kind) {
+            ("simd_add", Elem::Int { .. }) => bx.add(lanes[0], lanes[1]),
+            ("simd_add", Elem::Float { .. }) => bx.fadd(lanes[0], lanes[1]),
+            ("simd_sub", Elem::Int { .. }) => bx.sub(lanes[0], lanes[1]),
+            ("simd_sub", Elem::Float { .. }) => bx.fsub(lanes[0], lanes[1]),
+            ("simd_mul", Elem::Int { .. }) => bx.mul(lanes[0], lanes[1]),
+            ("simd_mul", Elem::Float { .. }) => bx.fmul(lanes[0], lanes[1]),
+            ("simd_div", Elem::Int { signed: true, .. }) => bx.sdiv(lanes[0], lanes[1]),
+            ("simd_div", Elem::Int { signed: false, .. }) => bx.udiv(lanes[0], lanes[1]),
+            ("simd_div", Elem::Float { .. }) => bx.fdiv(lanes[0], lanes[1]),
+            ("simd_rem", Elem::Int { signed: true, .. }) => bx.srem(lanes[0], lanes[1]),
+            ("simd_rem", Elem::Int { signed: false, .. }) => bx.urem(lanes[0], lanes[1]),
+            ("simd_rem", Elem::Float { .. }) => bx.frem(lanes[0], lanes[1]),
+            ("simd_shl", Elem::Int { .. }) => bx.shl(lanes[0], lanes[1]),
+            ("simd_shr", Elem::Int { signed: true, .. }) => bx.ashr(lanes[0], lanes[1]),
+            ("simd_shr", Elem::Int { signed: false, .. }) => bx.lshr(lanes[0], lanes[1]),
+            ("simd_and", Elem::Int { .. }) => bx.and(lanes[0], lanes[1]),
+            ("simd_or", Elem::Int { .. }) => bx.or(lanes[0], lanes[1]),
+            ("simd_xor", Elem::Int { .. }) => bx.xor(lanes[0], lanes[1]),
+            ("simd_neg", Elem::Int { .. }) => bx.neg(lanes[0]),
+            ("simd_neg", Elem::Float { .. }) => bx.fneg(lanes[0]),
+            ("simd_saturating_add" | "simd_saturating_sub", Elem::Int { width, signed }) => {
+                let is_add = name_str == "simd_saturating_add";
+                saturating_intrinsic_impl(bx, width as u32, signed, is_add, lanes[0], lanes[1])
+            }
+            ("simd_eq" | "simd_ne" | "simd_lt" | "simd_le" | "simd_gt" | "simd_ge", in_kind) => {
+                require!(
+                    matches!(out_kind, Elem::Int { .. }),
+                    "expected a mask of integers as the result of comparing `{}`, found `{}`",
+                    in_ty,
+                    ret_ty
+                );
+                let cmp = match in_kind {
+                    Elem::Int { signed, .. } => {
+                        let predicate = match (name_str, signed) {
+                            ("simd_eq", _) => IntPredicate::IntEQ,
+                            ("simd_ne", _) => IntPredicate::IntNE,
+                            ("simd_lt", true) => IntPredicate::IntSLT,
+                            ("simd_lt", false) => IntPredicate::IntULT,
+                            ("simd_le", true) => IntPredicate::IntSLE,
+                            ("simd_le", false) => IntPredicate::IntULE,
+                            ("simd_gt", true) => IntPredicate::IntSGT,
+                            ("simd_gt", false) => IntPredicate::IntUGT,
+                            ("simd_ge", true) => IntPredicate::IntSGE,
+                            _ => IntPredicate::IntUGE,
+                        };
+                        bx.icmp(predicate, lanes[0], lanes[1])
+                    }
+                    Elem::Float { .. } => {
+                        // ordered comparisons, except for `!=` which is true for NaNs.
+                        let predicate = match name_str {
+                            "simd_eq" => RealPredicate::RealOEQ,
+                            "simd_ne" => RealPredicate::RealUNE,
+                            "simd_lt" => RealPredicate::RealOLT,
+                            "simd_le" => RealPredicate::RealOLE,
+                            "simd_gt" => RealPredicate::RealOGT,
+                            _ => RealPredicate::RealOGE,
+                        };
+                        bx.fcmp(predicate, lanes[0], lanes[1])
+                    }
+                };
+                // true lanes of masks are all ones.
+                bx.sext(cmp, out_lane_ty)
+            }
+            ("simd_cast", in_kind) => match (in_kind, out_kind) {
+                (Elem::Int { signed, .. }, Elem::Int { .. }) => {
+                    bx.intcast(lanes[0], out_lane_ty, signed)
+                }
+                (Elem::Int { signed: true, .. }, Elem::Float { .. }) => {
+                    bx.sitofp(lanes[0], out_lane_ty)
+                }
+                (Elem::Int { signed: false, .. }, Elem::Float { .. }) => {
+                    bx.uitofp(lanes[0], out_lane_ty)
+                }
+                (Elem::Float { .. }, Elem::Int { signed: true, .. }) => {
+                    bx.fptosi(lanes[0], out_lane_ty)
+                }
+                (Elem::Float { .. }, Elem::Int { signed: false, .. }) => {
+                    bx.fptoui(lanes[0], out_lane_ty)
+                }
+                (Elem::Float { width: from }, Elem::Float { width: to }) => {
+                    if from < to {
+                        bx.fpext(lanes[0], out_lane_ty)
+                    } else if from > to {
+                        bx.fptrunc(lanes[0], out_lane_ty)
+                    } else {
+                        lanes[0]
+                    }
+                }
+            },
+            (_, Elem::Float { width }) if libdevice_fn(name_str, width).is_some() => {
+                let llfn = bx.get_intrinsic(&libdevice_fn(name_str, width).unwrap());
+                let mut call_args = lanes;
+                if name_str == "simd_fpowi" {
+                    call_args.push(args[1].immediate());
+                }
+                bx.call(bx.type_i1(), llfn, &call_args, None)
+            }
+            _ => return_error!("not supported for `{}` on the GPU", in_ty),
+        };
+        out.push(lane);
+    }
+    Ok(vector(bx, llret_ty, out))
+}
@@ -1,3 +1,4 @@
+use cust::function::LaunchConfig;
 use cust::prelude::*;
 use nanorand::{Rng, WyRand};
 use std::error::Error;
@@ -5,7 +6,8 @@ use std::error::Error;
 /// How many numbers to generate and add together.
 const NUMBERS_LEN: usize = 100_000;
 
-static PTX: &str = include_str!("../../../resources/add.ptx");
+// embed the PTX built by build.rs, this generates an `add` module with a getter for every kernel.
+cust::include_ptx!("../../resources/add.ptx");
 
 fn main() -> Result<(), Box<dyn Error>> {
     // generate our random vectors.
@@ -15,18 +17,10 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut rhs = vec![0.0f32; NUMBERS_LEN];
     wyrand.fill(&mut rhs);
 
-    // initialize CUDA, this will pick the first available device and will
-    // make a CUDA context from it.
-    // We don't need the context for anything but it must be kept alive.
-    let _ctx = cust::quick_init()?;
-
-    // Make the CUDA module, modules just house the GPU code for the kernels we created.
-    // they can be made from PTX code, cubins, or fatbins.
-    let module = Module::from_str(PTX)?;
-
-    // make a CUDA stream to issue calls to. You can think of this as an OS thread but for dispatching
-    // GPU calls.
-    let stream = Stream::new(StreamFlags::NON_BLOCKING, None)?;
+    // initialize CUDA, this will pick the first available device, make a CUDA context from it and
+    // a CUDA stream to issue calls to. You can think of the stream as an OS thread but for dispatching
+    // GPU calls. We don't need the device or the context for anything but the context must be kept alive.
+    let (_device, _ctx, stream) = cust::init_default()?;
 
     // allocate the GPU memory needed to house our numbers and copy them over.
     let mut lhs_gpu = lhs.as_slice().as_dbuf()?;
@@ -37,19 +31,21 @@ fn main() -> Result<(), Box<dyn Error>> {
     let mut out = vec![0.0f32; NUMBERS_LEN];
     let mut out_buf = out.as_slice().as_dbuf()?;
 
-    // retrieve the add kernel from the module so we can calculate the right launch config.
-    let func = module.get_function("add")?;
+    // retrieve the add kernel so we can calculate the right launch config. This loads the
+    // CUDA module, which houses the GPU code for the kernels we created, the first time it is used.
+    let func = add::add()?;
 
     // use the CUDA occupancy API to find an optimal launch configuration for the grid and block size.
     // This will try to maximize how much of the GPU is used by finding the best launch configuration for the
     // current CUDA device/architecture.
     let (_, block_size) = func.suggested_launch_configuration(0, 0.into())?;
 
-    let grid_size = (NUMBERS_LEN as u32 + block_size - 1) / block_size;
+    // one thread per number, with the number of blocks rounded up so that every number is covered.
+    let config = LaunchConfig::for_len(NUMBERS_LEN, block_size);
 
     println!(
         "using {} blocks and {} threads per block",
-        grid_size, block_size
+        config.grid.x, block_size
     );
 
     // Actually launch the GPU kernel. This will queue up the launch on the stream, it will
@@ -57,7 +53,7 @@ fn main() -> Result<(), Box<dyn Error>> {
     unsafe {
         launch!(
             // slices are passed as two parameters, the pointer and the length.
-            func<<<grid_size, block_size, 0, stream>>>(
+            func<<<config.grid, config.block, 0, stream>>>(
                 lhs_gpu.as_device_ptr(),
                 lhs_gpu.len(),
                 rhs_gpu.as_device_ptr(),
@@ -0,0 +1,8 @@
+use cuda_builder::CudaBuilder;
+
+fn main() {
+    CudaBuilder::new("../../gpu/compact_gpu")
+        .copy_to("../../resources/compact.ptx")
+        .build()
+        .unwrap();
+}
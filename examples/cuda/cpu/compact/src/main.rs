@@ -0,0 +1,87 @@
+use cust::prelude::*;
+use nanorand::{Rng, WyRand};
+use std::error::Error;
+
+/// How many numbers to generate and filter.
+const NUMBERS_LEN: usize = 1_000_000;
+
+// embed the PTX built by build.rs, this generates a `compact` module with a getter for every kernel.
+cust::include_ptx!("../../resources/compact.ptx");
+
+fn main() -> Result<(), Box<dyn Error>> {
+    // generate our random numbers.
+    let mut wyrand = WyRand::new();
+    let mut floats = vec![0.0f32; NUMBERS_LEN];
+    wyrand.fill(&mut floats);
+    let mut ints = vec![0u32; NUMBERS_LEN];
+    wyrand.fill(&mut ints);
+
+    let (_device, _ctx, stream) = cust::init_default()?;
+
+    // both kernels call the same `collective::compact`, each with its own closure as the predicate.
+    let above = run(&stream, &compact::keep_above()?, &floats, 0.75f32)?;
+    let expected = floats
+        .iter()
+        .copied()
+        .filter(|x| *x > 0.75)
+        .collect::<Vec<_>>();
+    assert_eq!(above, expected);
+    println!("{} of {} numbers are above 0.75", above.len(), NUMBERS_LEN);
+
+    let multiples = run(&stream, &compact::keep_multiples()?, &ints, 7u32)?;
+    let expected = ints
+        .iter()
+        .copied()
+        .filter(|x| x % 7 == 0)
+        .collect::<Vec<_>>();
+    assert_eq!(multiples, expected);
+    println!(
+        "{} of {} numbers are multiples of 7",
+        multiples.len(),
+        NUMBERS_LEN
+    );
+
+    Ok(())
+}
+
+/// Runs one of the compaction kernels over `input` and returns the selected elements.
+fn run<T: DeviceCopy + Default, A: DeviceCopy>(
+    stream: &Stream,
+    func: &Function,
+    input: &[T],
+    arg: A,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    // the blocks of a compaction wait on each other, so they must all run at the same time. The minimum
+    // grid size of the occupancy API is how many blocks of the suggested size the GPU runs at once,
+    // every thread then takes a chunk of the input.
+    let (grid_size, block_size) = func.suggested_launch_configuration(0, 0.into())?;
+
+    let mut input_gpu = input.as_dbuf()?;
+    let mut output_gpu = unsafe { DeviceBuffer::<T>::uninitialized(input.len())? };
+    let mut count_gpu = DeviceBox::new(&0u32)?;
+    // the prefixes and flags of the grid scan, one per block. The flags are reset by the kernel, so they
+    // could be kept around for the next launch.
+    let mut prefixes = unsafe { DeviceBuffer::<u32>::zeroed(grid_size as usize)? };
+    let mut flags = unsafe { DeviceBuffer::<u32>::zeroed(grid_size as usize)? };
+
+    unsafe {
+        launch!(
+            func<<<grid_size, block_size, 0, stream>>>(
+                input_gpu.as_device_ptr(),
+                input_gpu.len(),
+                arg,
+                output_gpu.as_device_ptr(),
+                count_gpu.as_device_ptr(),
+                prefixes.as_device_ptr(),
+                flags.as_device_ptr(),
+            )
+        )?;
+    }
+    stream.synchronize()?;
+
+    let mut count = 0u32;
+    count_gpu.copy_to(&mut count)?;
+    let mut output = vec![T::default(); count as usize];
+    output_gpu[..count as usize].copy_to(&mut output)?;
+    Ok(output)
+}
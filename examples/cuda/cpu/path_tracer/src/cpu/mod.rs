@@ -1,33 +1,30 @@
 use std::time::Duration;
 
-use cust::vek::{Clamp, Vec2, Vec3};
-use gpu_rand::{DefaultRand, GpuRand};
+use cust::vek::{Vec2, Vec3};
+use gpu_rand::{philox, GpuRand};
 use imgui::Ui;
 use path_tracer_gpu::{
-    material::MaterialKind, render::generate_ray, scene::Scene, Object, Viewport,
+    material::MaterialKind, render::generate_ray, rgba16f, scene::Scene, Object, Rgba16F, Viewport,
 };
 use rayon::prelude::*;
 use sysinfo::{ProcessorExt, System, SystemExt};
 
-use crate::{common::Camera, cuda::SEED};
+use crate::common::Camera;
 
 pub struct CpuRenderer {
     // this is basically the cuda buffers but not gpu buffers.
     accumulated_buffer: Vec<Vec3<f32>>,
-    out_buffer: Vec<Vec3<u8>>,
+    out_buffer: Vec<Rgba16F>,
 
     viewport: Viewport,
     objects: Vec<Object>,
     materials: Vec<MaterialKind>,
-    rand_states: Vec<DefaultRand>,
 }
 
 impl CpuRenderer {
     pub fn new(dimensions: Vec2<usize>, camera: &Camera, scene: &Scene) -> Self {
         let accumulated_buffer = vec![Vec3::zero(); dimensions.product()];
-        let out_buffer = vec![Vec3::zero(); dimensions.product()];
-
-        let rand_states = DefaultRand::initialize_states(SEED, dimensions.product());
+        let out_buffer = vec![Rgba16F::default(); dimensions.product()];
 
         let mut viewport = Viewport::default();
         camera.as_viewport(&mut viewport);
@@ -39,7 +36,6 @@ impl CpuRenderer {
             viewport,
             objects: scene.objects.to_vec(),
             materials: scene.materials.to_vec(),
-            rand_states,
         }
     }
 
@@ -70,7 +66,8 @@ impl CpuRenderer {
     pub fn resize(&mut self, dimensions: Vec2<usize>) {
         self.accumulated_buffer
             .resize(dimensions.product(), Vec3::zero());
-        self.out_buffer.resize(dimensions.product(), Vec3::zero());
+        self.out_buffer
+            .resize(dimensions.product(), Rgba16F::default());
         self.viewport.bounds = dimensions;
     }
 
@@ -84,7 +81,7 @@ impl CpuRenderer {
         self.objects[idx] = new;
     }
 
-    pub fn final_image(&mut self, cur_sample: usize) -> (&[Vec3<u8>], Duration) {
+    pub fn final_image(&mut self, cur_sample: usize) -> (&[Rgba16F], Duration) {
         let start = std::time::Instant::now();
 
         let Self {
@@ -97,19 +94,14 @@ impl CpuRenderer {
             .par_iter_mut()
             .zip(accumulated_buffer.par_iter())
             .for_each(|(px, acc)| {
-                let scaled = acc / cur_sample as f32;
-                let gamma_corrected = scaled.sqrt();
-
-                *px = (gamma_corrected * 255.0)
-                    .clamped(Vec3::zero(), Vec3::broadcast(255.0))
-                    .numcast()
-                    .unwrap();
+                *px = rgba16f(acc / cur_sample as f32);
             });
 
         (&self.out_buffer, start.elapsed())
     }
 
-    pub fn render(&mut self) -> Duration {
+    /// Renders sample `sample` of the image, with the same random numbers as the CUDA renderer.
+    pub fn render(&mut self, sample: usize) -> Duration {
         // rustc has some problems with borrows even though it should be fine in this case,
         // so we just destructure to tell it its disjoint.
         let Self {
@@ -117,7 +109,6 @@ impl CpuRenderer {
             viewport,
             objects,
             materials,
-            rand_states,
             ..
         } = self;
         let start = std::time::Instant::now();
@@ -126,12 +117,12 @@ impl CpuRenderer {
 
         accumulated_buffer
             .par_iter_mut()
-            .zip(rand_states.par_iter_mut())
             .enumerate()
-            .for_each(|(idx, (px, rng))| {
+            .for_each(|(idx, px)| {
                 let x = idx % viewport.bounds.x;
                 let y = idx / viewport.bounds.x;
                 let idx = Vec2::new(x as u32, y as u32);
+                let rng = &mut philox::for_pixel(idx.x, idx.y, sample as u32);
 
                 let offset = Vec2::from(rng.normal_f32_2());
 
@@ -1,14 +1,27 @@
 use crate::common::Camera;
+use cuda_image::DeviceImage;
 use cust::{
     error::CudaResult,
-    memory::{DeviceBuffer, DeviceCopy, UnifiedBuffer},
+    memory::{
+        array::{ArrayFormat, ArrayObject},
+        DeviceBuffer, DeviceCopy, UnifiedBuffer,
+    },
+    texture::{
+        ResourceDescriptor, ResourceDescriptorFlags, ResourceType, Texture, TextureAdressingMode,
+        TextureDescriptor, TextureDescriptorFlags, TextureFilterMode,
+    },
     util::SliceExt,
     vek::{num_traits::Zero, Vec2, Vec3},
 };
-use gpu_rand::DefaultRand;
-use path_tracer_gpu::{material::MaterialKind, scene::Scene, Object, Viewport};
-
-use super::SEED;
+use path_tracer_gpu::{
+    aov::AovBuffers,
+    hittable::HitRecord,
+    material::{DiffuseMaterial, MaterialKind},
+    scene::Scene,
+    texture::TextureKind,
+    wavefront::PathState,
+    Object, Rgba16F, Viewport,
+};
 
 /// The various buffers held by the CUDA renderer.
 ///
@@ -18,10 +31,16 @@ pub struct CudaRendererBuffers {
     pub accumulated_buffer: DeviceBuffer<Vec3<f32>>,
     /// The scaled buffer of colors, this is just the accumulated colors divided by sample count.
     pub scaled_buffer: DeviceBuffer<Vec3<f32>>,
-    /// The final image buffer after denoising and postprocessing.
-    pub out_buffer: DeviceBuffer<Vec3<u8>>,
+    /// The final half-precision image after denoising, with padded rows.
+    pub out_buffer: DeviceImage<Rgba16F>,
     /// The scaled buffer but denoised. In the future we will use the same buffer for this.
     pub denoised_buffer: DeviceBuffer<Vec3<f32>>,
+    /// The accumulated albedo of the first hit of every sample.
+    pub albedo_buffer: DeviceBuffer<Vec3<f32>>,
+    /// The accumulated normal of the first hit of every sample.
+    pub normal_buffer: DeviceBuffer<Vec3<f32>>,
+    /// The accumulated distance to the first hit of every sample.
+    pub depth_buffer: DeviceBuffer<f32>,
 
     /// The viewport used by the render kernel to emit rays.
     pub viewport: Viewport,
@@ -29,37 +48,42 @@ pub struct CudaRendererBuffers {
     pub objects: UnifiedBuffer<Object>,
     /// Allocated buffer of the materials in the scene.
     pub materials: UnifiedBuffer<MaterialKind>,
-    /// Per-thread randomness states.
-    pub rand_states: UnifiedBuffer<DefaultRand>,
+    /// The texture every image texture of the scene samples.
+    pub image_texture: Texture,
 }
 
 impl CudaRendererBuffers {
     pub fn new(dimensions: Vec2<usize>, camera: &Camera, scene: &Scene) -> CudaResult<Self> {
         let accumulated_buffer = Self::image_buffer(dimensions)?;
-        let out_buffer = Self::image_buffer(dimensions)?;
+        let out_buffer = DeviceImage::zeroed(dimensions.x, dimensions.y)?;
         let denoised_buffer = Self::image_buffer(dimensions)?;
         let scaled_buffer = Self::image_buffer(dimensions)?;
+        let albedo_buffer = Self::image_buffer(dimensions)?;
+        let normal_buffer = Self::image_buffer(dimensions)?;
+        let depth_buffer = unsafe { DeviceBuffer::zeroed(dimensions.product())? };
 
+        let image_texture = Self::grid_texture(512, 256)?;
         let objects = scene.objects.as_unified_buf()?;
-        let materials = scene.materials.as_unified_buf()?;
+        let materials = Self::bind_textures(scene.materials, &image_texture)
+            .as_slice()
+            .as_unified_buf()?;
 
         let mut viewport = Viewport::default();
         camera.as_viewport(&mut viewport);
         viewport.bounds = dimensions;
 
-        let rand_states = DefaultRand::initialize_states(SEED, dimensions.product())
-            .as_slice()
-            .as_unified_buf()?;
-
         Ok(Self {
             accumulated_buffer,
             scaled_buffer,
             out_buffer,
             denoised_buffer,
+            albedo_buffer,
+            normal_buffer,
+            depth_buffer,
             viewport,
             objects,
             materials,
-            rand_states,
+            image_texture,
         })
     }
 
@@ -67,7 +91,9 @@ impl CudaRendererBuffers {
     /// all of the GPU scene buffers.
     pub fn reset_scene(&mut self, scene: &Scene) -> CudaResult<()> {
         self.objects = scene.objects.as_unified_buf()?;
-        self.materials = scene.materials.as_unified_buf()?;
+        self.materials = Self::bind_textures(scene.materials, &self.image_texture)
+            .as_slice()
+            .as_unified_buf()?;
 
         Ok(())
     }
@@ -75,7 +101,13 @@ impl CudaRendererBuffers {
     /// Reset the renderer's view, in the buffer's case this means clearing accumulated buffers from previous samples.
     /// As well as changing the viewport.
     pub fn update_camera(&mut self, new_camera: &Camera) -> CudaResult<()> {
-        self.accumulated_buffer = unsafe { DeviceBuffer::zeroed(self.accumulated_buffer.len())? };
+        let len = self.accumulated_buffer.len();
+        unsafe {
+            self.accumulated_buffer = DeviceBuffer::zeroed(len)?;
+            self.albedo_buffer = DeviceBuffer::zeroed(len)?;
+            self.normal_buffer = DeviceBuffer::zeroed(len)?;
+            self.depth_buffer = DeviceBuffer::zeroed(len)?;
+        }
         new_camera.as_viewport(&mut self.viewport);
         Ok(())
     }
@@ -84,18 +116,18 @@ impl CudaRendererBuffers {
     pub fn resize(&mut self, new: Vec2<usize>) -> CudaResult<()> {
         self.viewport.bounds = new;
         self.accumulated_buffer = Self::image_buffer(new)?;
-        self.out_buffer = Self::image_buffer(new)?;
+        self.out_buffer = DeviceImage::zeroed(new.x, new.y)?;
         self.denoised_buffer = Self::image_buffer(new)?;
         self.scaled_buffer = Self::image_buffer(new)?;
-        self.rand_states = DefaultRand::initialize_states(SEED, new.product())
-            .as_slice()
-            .as_unified_buf()?;
+        self.albedo_buffer = Self::image_buffer(new)?;
+        self.normal_buffer = Self::image_buffer(new)?;
+        self.depth_buffer = unsafe { DeviceBuffer::zeroed(new.product())? };
         Ok(())
     }
 
     /// Swaps out a material at a specific index.
     pub fn update_material(&mut self, idx: usize, new: MaterialKind) {
-        self.materials[idx] = new;
+        self.materials[idx] = Self::bind_textures(&[new], &self.image_texture)[0];
     }
 
     /// Swaps out an object at a specific index.
@@ -103,6 +135,69 @@ impl CudaRendererBuffers {
         self.objects[idx] = new;
     }
 
+    /// The pointers to the AOV buffers which are passed to the kernels.
+    pub fn aov_buffers(&mut self) -> AovBuffers {
+        AovBuffers {
+            albedo: self.albedo_buffer.as_device_ptr().as_raw_mut(),
+            normal: self.normal_buffer.as_device_ptr().as_raw_mut(),
+            depth: self.depth_buffer.as_device_ptr().as_raw_mut(),
+        }
+    }
+
+    /// Points the image textures of `materials` to `texture`, they have no texture object in the
+    /// scene because texture objects belong to a context.
+    fn bind_textures(materials: &[MaterialKind], texture: &Texture) -> Vec<MaterialKind> {
+        materials
+            .iter()
+            .map(|material| match *material {
+                MaterialKind::Diffuse(DiffuseMaterial {
+                    texture: TextureKind::Image { fallback, .. },
+                }) => MaterialKind::Diffuse(DiffuseMaterial {
+                    texture: TextureKind::Image {
+                        handle: texture.handle(),
+                        fallback,
+                    },
+                }),
+                other => other,
+            })
+            .collect()
+    }
+
+    /// Creates a texture of `width` by `height` texels showing a grid of colored cells, which makes
+    /// the texture coordinates of a surface easy to see. It wraps around and is filtered linearly.
+    fn grid_texture(width: usize, height: usize) -> CudaResult<Texture> {
+        const CELL: usize = 32;
+        let mut texels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x / CELL, y / CELL);
+                let color = if x % CELL == 0 || y % CELL == 0 {
+                    [0.05, 0.05, 0.05]
+                } else {
+                    let u = cx as f32 / (width / CELL) as f32;
+                    let v = cy as f32 / (height / CELL) as f32;
+                    [u, v, if (cx + cy) % 2 == 0 { 0.9 } else { 0.4 }]
+                };
+                texels.extend_from_slice(&color);
+                texels.push(1.0f32);
+            }
+        }
+
+        let mut array = ArrayObject::new_2d([width, height], ArrayFormat::F32, 4)?;
+        array.copy_from(&texels)?;
+        let resource = ResourceDescriptor {
+            flags: ResourceDescriptorFlags::empty(),
+            ty: ResourceType::Array { array },
+        };
+        let descriptor = TextureDescriptor {
+            adress_modes: [TextureAdressingMode::Wrap; 3],
+            filter_mode: TextureFilterMode::Linear,
+            flags: TextureDescriptorFlags::NORMALIZED_COORDINATES,
+            ..Default::default()
+        };
+        Texture::new(resource, descriptor, None)
+    }
+
     // could also use the convenience method on optix::denoiser::Image for this
     fn image_buffer<T: DeviceCopy + Zero>(
         dimensions: Vec2<usize>,
@@ -110,3 +205,54 @@ impl CudaRendererBuffers {
         unsafe { DeviceBuffer::zeroed(dimensions.product()) }
     }
 }
+
+/// The path queues of the wavefront renderer, see `path_tracer_gpu::wavefront`.
+pub struct WavefrontBuffers {
+    /// The two queues of paths, every bounce reads one and compacts the live paths into the other.
+    pub paths: [DeviceBuffer<PathState>; 2],
+    /// The lengths of the two queues, only ever read on the device.
+    pub lens: DeviceBuffer<u32>,
+    /// The closest hit of every path of the current queue.
+    pub hits: DeviceBuffer<Option<HitRecord>>,
+    /// The per-block prefixes of the compaction.
+    pub prefixes: DeviceBuffer<u32>,
+    /// The per-block flags of the compaction, reset by the kernel after every launch.
+    pub flags: DeviceBuffer<u32>,
+    /// The grid and block size of the compaction kernel.
+    pub compact_launch: (u32, u32),
+}
+
+impl WavefrontBuffers {
+    /// Allocates queues for an image of `dimensions`, for compactions launched with the grid and
+    /// block size `compact_launch`.
+    pub fn new(dimensions: Vec2<usize>, compact_launch: (u32, u32)) -> CudaResult<Self> {
+        let len = dimensions.product();
+        let compact_blocks = compact_launch.0 as usize;
+        unsafe {
+            Ok(Self {
+                paths: [
+                    DeviceBuffer::uninitialized(len)?,
+                    DeviceBuffer::uninitialized(len)?,
+                ],
+                lens: DeviceBuffer::zeroed(2)?,
+                hits: DeviceBuffer::uninitialized(len)?,
+                prefixes: DeviceBuffer::zeroed(compact_blocks)?,
+                flags: DeviceBuffer::zeroed(compact_blocks)?,
+                compact_launch,
+            })
+        }
+    }
+
+    /// Reallocates the queues for a new image size.
+    pub fn resize(&mut self, new: Vec2<usize>) -> CudaResult<()> {
+        let len = new.product();
+        unsafe {
+            self.paths = [
+                DeviceBuffer::uninitialized(len)?,
+                DeviceBuffer::uninitialized(len)?,
+            ];
+            self.hits = DeviceBuffer::uninitialized(len)?;
+        }
+        Ok(())
+    }
+}
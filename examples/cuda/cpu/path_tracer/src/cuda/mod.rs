@@ -1,7 +1,9 @@
 mod data;
+mod multi_gpu;
 
 pub use data::*;
 use imgui::Ui;
+pub use multi_gpu::MultiGpuRenderer;
 
 use std::time::Duration;
 
@@ -10,6 +12,7 @@ use cust::{
     error::CudaResult,
     event::{Event, EventFlags},
     function::{BlockSize, GridSize},
+    memory::DevicePointer,
     prelude::*,
     vek::{Vec2, Vec3},
 };
@@ -17,16 +20,20 @@ use optix::{
     context::OptixContext,
     denoiser::{Denoiser, DenoiserModelKind, Image, ImageFormat},
 };
-use path_tracer_gpu::scene::Scene;
-
-/// Seed for the random states
-pub const SEED: u64 = 932174513921034;
+use path_tracer_gpu::{
+    aov::{AovBuffers, AovKind},
+    scene::{Scene, MAX_BOUNCES},
+    Rgba16F, Viewport,
+};
 
 /// How many pixels a single thread block should process, in each axis.
 /// That is to say, 8 will dispatch 8x8 threads (in a 2d config) per block.
 /// This should always be a multiple of warp size (32) to maximize occupancy.
 const THREAD_BLOCK_AXIS_LENGTH: usize = 16;
 
+/// How many threads a block of the 1D wavefront kernels has.
+const WAVEFRONT_BLOCK_SIZE: u32 = 256;
+
 pub(crate) static PTX: &str = include_str!("../../../../resources/path_tracer.ptx");
 
 pub struct CudaRenderer {
@@ -37,7 +44,8 @@ pub struct CudaRenderer {
     _context: Context,
 
     buffers: CudaRendererBuffers,
-    cpu_image: Vec<Vec3<u8>>,
+    wavefront: WavefrontBuffers,
+    cpu_image: Vec<Rgba16F>,
 }
 
 impl CudaRenderer {
@@ -57,7 +65,13 @@ impl CudaRenderer {
             .unwrap();
 
         let buffers = CudaRendererBuffers::new(dimensions, camera, scene)?;
-        let cpu_image = vec![Vec3::zero(); dimensions.product()];
+        // the blocks of a compaction wait on each other, so there must be no more of them than the GPU
+        // runs at once, which is the minimum grid size of the occupancy API.
+        let compact_launch = module
+            .get_function("compact_paths")?
+            .suggested_launch_configuration(0, 0.into())?;
+        let wavefront = WavefrontBuffers::new(dimensions, compact_launch)?;
+        let cpu_image = vec![Rgba16F::default(); dimensions.product()];
 
         Ok(Self {
             _context: context,
@@ -66,6 +80,7 @@ impl CudaRenderer {
             module,
             stream,
             buffers,
+            wavefront,
             cpu_image,
         })
     }
@@ -89,7 +104,9 @@ impl CudaRenderer {
     /// Resize the image-specific data for a new size
     pub fn resize(&mut self, new_size: Vec2<usize>) -> CudaResult<()> {
         self.buffers.resize(new_size)?;
-        self.cpu_image.resize(new_size.product(), Vec3::zero());
+        self.wavefront.resize(new_size)?;
+        self.cpu_image
+            .resize(new_size.product(), Rgba16F::default());
 
         Ok(self
             .denoiser
@@ -98,20 +115,20 @@ impl CudaRenderer {
 
     /// calculate an optimal launch configuration for an image kernel
     fn launch_dimensions(&self) -> (GridSize, BlockSize) {
-        let threads = Vec2::broadcast(THREAD_BLOCK_AXIS_LENGTH);
-        let blocks = (self.buffers.viewport.bounds / threads) + 1;
-        (blocks.into(), threads.into())
+        image_launch_dimensions(self.buffers.viewport.bounds)
     }
 
-    /// Run postprocessing on the accumulated buffer, color correct it, and divide it by the total
-    /// samples to yield a final image that can be displayed.
+    /// Divide the accumulated buffer by the total samples, denoise it, and convert it to the
+    /// half-precision image that can be displayed or exported. With `aov`, the average of that AOV
+    /// is converted instead, without denoising.
     ///
     /// Also returns the denoising time and postprocessing time.
     pub fn final_image(
         &mut self,
         cur_sample: usize,
         denoise: bool,
-    ) -> CudaResult<(&[Vec3<u8>], Duration, Duration)> {
+        aov: Option<AovKind>,
+    ) -> CudaResult<(&[Rgba16F], Duration, Duration)> {
         let module = &self.module;
         let stream = &self.stream;
 
@@ -125,6 +142,30 @@ impl CudaRenderer {
 
         start.record(stream)?;
 
+        if let Some(aov) = aov {
+            denoising_stop.record(stream)?;
+            unsafe {
+                launch!(
+                    module.present_aov<<<blocks, threads, 0, stream>>>(
+                        self.buffers.aov_buffers(),
+                        aov,
+                        cur_sample as u32,
+                        self.buffers.out_buffer.view(),
+                        self.buffers.viewport
+                    )
+                )?;
+            }
+            postprocessing_stop.record(stream)?;
+            postprocessing_stop.synchronize()?;
+
+            self.buffers.out_buffer.copy_to(&mut self.cpu_image)?;
+            return Ok((
+                &self.cpu_image,
+                Duration::ZERO,
+                postprocessing_stop.elapsed(&denoising_stop)?,
+            ));
+        }
+
         unsafe {
             launch!(
                 module.scale_buffer<<<blocks, threads, 0, stream>>>(
@@ -161,9 +202,9 @@ impl CudaRenderer {
 
         unsafe {
             launch!(
-                module.postprocess<<<blocks, threads, 0, stream>>>(
+                module.present<<<blocks, threads, 0, stream>>>(
                     input_buf,
-                    self.buffers.out_buffer.as_device_ptr(),
+                    self.buffers.out_buffer.view(),
                     self.buffers.viewport
                 )
             )?;
@@ -175,17 +216,24 @@ impl CudaRenderer {
         let denoising_time = denoising_stop.elapsed(&start)?;
         let postprocessing_time = postprocessing_stop.elapsed(&denoising_stop)?;
 
+        // the copy drops the padding of the rows.
         self.buffers.out_buffer.copy_to(&mut self.cpu_image)?;
 
         Ok((&self.cpu_image, denoising_time, postprocessing_time))
     }
 
-    /// Render another sample of the image, adding it on top of the already accumulated buffer.
-    pub fn render(&mut self) -> CudaResult<Duration> {
+    /// Render sample `sample` of the image, adding it on top of the already accumulated buffer. The
+    /// random numbers of every pixel are derived from the sample, so rendering the same samples
+    /// always gives the same image.
+    ///
+    /// With `wavefront`, the sample is traced by the kernels of `path_tracer_gpu::wavefront` instead
+    /// of the single `render` kernel.
+    pub fn render(&mut self, sample: usize, wavefront: bool) -> CudaResult<Duration> {
         let module = &self.module;
         let stream = &self.stream;
 
         let (blocks, threads) = self.launch_dimensions();
+        let aovs = self.buffers.aov_buffers();
 
         let mut scene = Scene {
             objects: &self.buffers.objects,
@@ -199,15 +247,29 @@ impl CudaRenderer {
 
         start.record(stream)?;
 
-        unsafe {
-            launch!(
-                module.render<<<blocks, threads, 0, stream>>>(
-                    self.buffers.accumulated_buffer.as_device_ptr(),
-                    self.buffers.viewport,
-                    scene.as_device_ptr(),
-                    self.buffers.rand_states.as_unified_ptr()
-                )
+        if wavefront {
+            launch_wavefront(
+                module,
+                stream,
+                &mut self.wavefront,
+                self.buffers.viewport,
+                scene.as_device_ptr(),
+                self.buffers.accumulated_buffer.as_device_ptr(),
+                aovs,
+                sample as u32,
             )?;
+        } else {
+            unsafe {
+                launch!(
+                    module.render<<<blocks, threads, 0, stream>>>(
+                        self.buffers.accumulated_buffer.as_device_ptr(),
+                        aovs,
+                        self.buffers.viewport,
+                        scene.as_device_ptr(),
+                        sample as u32
+                    )
+                )?;
+            }
         }
 
         stop.record(stream)?;
@@ -216,3 +278,90 @@ impl CudaRenderer {
         stop.elapsed(&start)
     }
 }
+
+/// Launches the kernels tracing sample `sample` of every pixel with a queue of paths per bounce, adding
+/// the colors to `fb` and the AOVs to `aovs`.
+///
+/// The queue lengths stay on the GPU, so every kernel is launched for a full queue and the threads
+/// past the end of the queue exit right away.
+#[allow(clippy::too_many_arguments)]
+fn launch_wavefront(
+    module: &Module,
+    stream: &Stream,
+    wavefront: &mut WavefrontBuffers,
+    viewport: Viewport,
+    scene: DevicePointer<Scene>,
+    fb: DevicePointer<Vec3<f32>>,
+    aovs: AovBuffers,
+    sample: u32,
+) -> CudaResult<()> {
+    let (blocks, threads) = image_launch_dimensions(viewport.bounds);
+    let len = viewport.bounds.product() as u32;
+    let queue_blocks = (len + WAVEFRONT_BLOCK_SIZE - 1) / WAVEFRONT_BLOCK_SIZE;
+    let (compact_blocks, compact_threads) = wavefront.compact_launch;
+
+    let paths = [
+        wavefront.paths[0].as_device_ptr(),
+        wavefront.paths[1].as_device_ptr(),
+    ];
+    let lens = {
+        let lens = wavefront.lens.as_device_ptr();
+        [lens, unsafe { lens.add(1) }]
+    };
+    let hits = wavefront.hits.as_device_ptr();
+
+    unsafe {
+        launch!(
+            module.generate_paths<<<blocks, threads, 0, stream>>>(
+                paths[0],
+                lens[0],
+                viewport,
+                sample
+            )
+        )?;
+
+        // every bounce shades the paths of one queue and compacts the paths still alive into the
+        // other one.
+        for bounce in 0..MAX_BOUNCES as usize {
+            let (current, next) = (bounce % 2, (bounce + 1) % 2);
+            launch!(
+                module.intersect_paths<<<queue_blocks, WAVEFRONT_BLOCK_SIZE, 0, stream>>>(
+                    paths[current],
+                    lens[current],
+                    scene,
+                    hits
+                )
+            )?;
+            launch!(
+                module.shade_paths<<<queue_blocks, WAVEFRONT_BLOCK_SIZE, 0, stream>>>(
+                    paths[current],
+                    lens[current],
+                    hits,
+                    scene,
+                    fb,
+                    aovs,
+                    viewport,
+                    sample
+                )
+            )?;
+            launch!(
+                module.compact_paths<<<compact_blocks, compact_threads, 0, stream>>>(
+                    paths[current],
+                    lens[current],
+                    paths[next],
+                    lens[next],
+                    wavefront.prefixes.as_device_ptr(),
+                    wavefront.flags.as_device_ptr()
+                )
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// calculate an optimal launch configuration for an image kernel
+fn image_launch_dimensions(bounds: Vec2<usize>) -> (GridSize, BlockSize) {
+    let threads = Vec2::broadcast(THREAD_BLOCK_AXIS_LENGTH);
+    let blocks = (bounds / threads) + 1;
+    (blocks.into(), threads.into())
+}
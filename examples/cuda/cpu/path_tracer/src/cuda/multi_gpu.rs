@@ -0,0 +1,217 @@
+//! Rendering on every GPU in the system at once, every GPU renders a tile of consecutive rows of
+//! the image. There is no denoising because the OptiX denoiser needs the whole image.
+
+use std::{
+    mem,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+use crate::common::Camera;
+use cuda_image::{Format, ImageView};
+use cust::{
+    error::CudaResult,
+    function::{BlockSize, GridSize},
+    memory::{DeviceBuffer, UnifiedBuffer},
+    prelude::*,
+    util::SliceExt,
+    vek::{Vec2, Vec3},
+};
+use cust_sched::Scheduler;
+use path_tracer_gpu::{material::MaterialKind, scene::Scene, Object, Rgba16F, Viewport};
+
+use super::{PTX, THREAD_BLOCK_AXIS_LENGTH};
+
+/// The module and buffers of a single GPU, which only cover the rows of its tile.
+struct TileState {
+    module: Module,
+    accumulated_buffer: DeviceBuffer<Vec3<f32>>,
+    scaled_buffer: DeviceBuffer<Vec3<f32>>,
+    out_buffer: DeviceBuffer<Rgba16F>,
+    objects: UnifiedBuffer<Object>,
+    materials: UnifiedBuffer<MaterialKind>,
+}
+
+impl TileState {
+    fn new(rows: Range<usize>, width: usize, scene: &Scene) -> CudaResult<Self> {
+        let len = rows.len() * width;
+        Ok(Self {
+            module: Module::from_str(PTX)?,
+            accumulated_buffer: unsafe { DeviceBuffer::zeroed(len)? },
+            scaled_buffer: unsafe { DeviceBuffer::zeroed(len)? },
+            out_buffer: unsafe { DeviceBuffer::zeroed(len)? },
+            objects: scene.objects.as_unified_buf()?,
+            materials: scene.materials.as_unified_buf()?,
+        })
+    }
+}
+
+pub struct MultiGpuRenderer {
+    // the buffers must be freed before the contexts of the scheduler are destroyed.
+    tiles: Vec<TileState>,
+    scheduler: Scheduler,
+    viewport: Viewport,
+    cpu_image: Vec<Rgba16F>,
+    // kept to upload the scene again when the tiles are reallocated.
+    objects: Vec<Object>,
+    materials: Vec<MaterialKind>,
+}
+
+impl MultiGpuRenderer {
+    /// Creates a renderer using every GPU, CUDA must have been initialized already.
+    pub fn new(dimensions: Vec2<usize>, camera: &Camera, scene: &Scene) -> CudaResult<Self> {
+        let scheduler = Scheduler::all_devices()?;
+
+        let mut viewport = Viewport::default();
+        camera.as_viewport(&mut viewport);
+        viewport.bounds = dimensions;
+
+        let tiles = Self::tiles(&scheduler, dimensions, scene)?;
+        Ok(Self {
+            tiles,
+            scheduler,
+            viewport,
+            cpu_image: vec![Rgba16F::default(); dimensions.product()],
+            objects: scene.objects.to_vec(),
+            materials: scene.materials.to_vec(),
+        })
+    }
+
+    fn tiles(
+        scheduler: &Scheduler,
+        dimensions: Vec2<usize>,
+        scene: &Scene,
+    ) -> CudaResult<Vec<TileState>> {
+        let mut rows = scheduler.split(dimensions.y).into_iter();
+        scheduler.per_device(|_| TileState::new(rows.next().unwrap(), dimensions.x, scene))
+    }
+
+    pub fn num_gpus(&self) -> usize {
+        self.scheduler.workers().len()
+    }
+
+    /// Update the camera of the renderer and reset any accumulated buffers.
+    pub fn update_camera(&mut self, camera: &Camera) -> CudaResult<()> {
+        camera.as_viewport(&mut self.viewport);
+        for (worker, tile) in self.scheduler.workers().iter().zip(&mut self.tiles) {
+            let len = tile.accumulated_buffer.len();
+            tile.accumulated_buffer =
+                worker.with_context(|| unsafe { DeviceBuffer::zeroed(len) })?;
+        }
+        Ok(())
+    }
+
+    /// Resize the image, which reallocates the tiles of every GPU.
+    pub fn resize(&mut self, new_size: Vec2<usize>) -> CudaResult<()> {
+        self.viewport.bounds = new_size;
+        // free the old tiles first, the GPUs may not have room for both.
+        self.tiles.clear();
+        let scene = Scene {
+            objects: &self.objects,
+            materials: &self.materials,
+        };
+        self.tiles = Self::tiles(&self.scheduler, new_size, &scene)?;
+        self.cpu_image
+            .resize(new_size.product(), Rgba16F::default());
+        Ok(())
+    }
+
+    /// calculate an optimal launch configuration for a tile
+    fn launch_dimensions(tile: Vec2<usize>) -> (GridSize, BlockSize) {
+        let threads = Vec2::broadcast(THREAD_BLOCK_AXIS_LENGTH);
+        let blocks = (tile / threads) + 1;
+        (blocks.into(), threads.into())
+    }
+
+    /// Render sample `sample` of the image on every GPU, returning the time until all of them
+    /// finished. The random numbers only depend on the pixel and the sample, so the image is the
+    /// same as with a single GPU.
+    pub fn render(&mut self, sample: usize) -> CudaResult<Duration> {
+        let start = Instant::now();
+        let view = self.viewport;
+
+        self.scheduler
+            .run(view.bounds.y, &mut self.tiles, |worker, tile, rows| {
+                let module = &tile.module;
+                let stream = worker.stream();
+                let (blocks, threads) =
+                    Self::launch_dimensions(Vec2::new(view.bounds.x, rows.len()));
+
+                unsafe {
+                    launch!(
+                        module.render_tile<<<blocks, threads, 0, stream>>>(
+                            tile.accumulated_buffer.as_device_ptr(),
+                            view,
+                            rows.start,
+                            rows.len(),
+                            tile.objects.as_unified_ptr(),
+                            tile.objects.len(),
+                            tile.materials.as_unified_ptr(),
+                            tile.materials.len(),
+                            sample as u32
+                        )
+                    )?;
+                }
+                Ok(())
+            })?;
+
+        Ok(start.elapsed())
+    }
+
+    /// Scale and postprocess the tile of every GPU, then copy the tiles into a single image.
+    ///
+    /// Also returns the postprocessing time.
+    pub fn final_image(&mut self, cur_sample: usize) -> CudaResult<(&[Rgba16F], Duration)> {
+        let start = Instant::now();
+        let width = self.viewport.bounds.x;
+
+        self.scheduler.run(
+            self.viewport.bounds.y,
+            &mut self.tiles,
+            |worker, tile, rows| {
+                let module = &tile.module;
+                let stream = worker.stream();
+                // the postprocessing kernels only use the bounds, which are the bounds of the tile.
+                let mut view = Viewport::default();
+                view.bounds = Vec2::new(width, rows.len());
+                let (blocks, threads) = Self::launch_dimensions(view.bounds);
+
+                unsafe {
+                    launch!(
+                        module.scale_buffer<<<blocks, threads, 0, stream>>>(
+                            tile.accumulated_buffer.as_device_ptr(),
+                            tile.scaled_buffer.as_device_ptr(),
+                            cur_sample,
+                            view
+                        )
+                    )?;
+                    // the tiles are gathered into one image, so their rows are not padded.
+                    let out = ImageView::from_raw_parts(
+                        tile.out_buffer.as_mut_ptr() as *mut u8,
+                        width as u32,
+                        rows.len() as u32,
+                        width * mem::size_of::<Rgba16F>(),
+                        Format::Rgba16F,
+                    );
+                    launch!(
+                        module.present<<<blocks, threads, 0, stream>>>(
+                            tile.scaled_buffer.as_device_ptr(),
+                            out,
+                            view
+                        )
+                    )?;
+                }
+                Ok(())
+            },
+        )?;
+
+        self.scheduler.gather_strided(
+            self.viewport.bounds.y,
+            &self.tiles,
+            &mut self.cpu_image,
+            |tile| &tile.out_buffer,
+        )?;
+
+        Ok((&self.cpu_image, start.elapsed()))
+    }
+}
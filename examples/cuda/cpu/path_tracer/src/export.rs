@@ -0,0 +1,47 @@
+//! Saving the rendered image without losing its dynamic range, as OpenEXR or Radiance HDR, or as
+//! an ordinary PNG.
+
+use cust::vek::Vec2;
+use image::{codecs::hdr::HdrEncoder, Rgb};
+use path_tracer_gpu::Rgba16F;
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+/// Saves `pixels`, an image of `dimensions` with its first row at the bottom (like the textures of
+/// the viewer), to `path`. The format is chosen by the extension, `exr`, `hdr` or `png`. PNGs are
+/// sRGB encoded, so colors brighter than white are clipped.
+pub fn save(
+    path: &Path,
+    dimensions: Vec2<usize>,
+    pixels: &[Rgba16F],
+) -> Result<(), Box<dyn Error>> {
+    assert_eq!(pixels.len(), dimensions.product());
+    // image files start with the top row.
+    let flipped = pixels
+        .chunks_exact(dimensions.x.max(1))
+        .rev()
+        .flatten()
+        .copied()
+        .collect::<Vec<_>>();
+
+    let is_hdr = path
+        .extension()
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("hdr"));
+    if !is_hdr {
+        cuda_image::save(path, dimensions.x, dimensions.y, &flipped)?;
+        return Ok(());
+    }
+
+    let data = flipped
+        .iter()
+        .map(|px| Rgb([px[0].to_f32(), px[1].to_f32(), px[2].to_f32()]))
+        .collect::<Vec<_>>();
+    let mut file = BufWriter::new(File::create(path)?);
+    HdrEncoder::new(&mut file).encode(&data, dimensions.x, dimensions.y)?;
+    file.flush()?;
+    Ok(())
+}
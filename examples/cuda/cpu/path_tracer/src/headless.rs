@@ -0,0 +1,108 @@
+//! Rendering a fixed number of samples without the viewer and saving the image, for benchmarks and
+//! smoke tests:
+//!
+//! ```text
+//! path_tracer --headless --spp 256 --out image.png [--wavefront] [--denoise]
+//! ```
+
+use crate::{common::Camera, cuda::CudaRenderer, export, HEIGHT, WIDTH};
+use cust::vek::Vec2;
+use path_tracer_gpu::scene::Scene;
+use std::{error::Error, path::PathBuf, time::Duration};
+
+const USAGE: &str =
+    "usage: path_tracer [--headless [--spp N] [--out IMAGE] [--wavefront] [--denoise]]";
+
+/// The options of a headless render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// How many samples to render for every pixel.
+    pub spp: usize,
+    /// Where to save the image, as PNG, EXR or HDR depending on the extension.
+    pub out: PathBuf,
+    /// Trace with the wavefront kernels instead of the single render kernel.
+    pub wavefront: bool,
+    /// Denoise the image with OptiX before saving it.
+    pub denoise: bool,
+}
+
+impl Options {
+    /// Parses the command line arguments (without the program name), `None` if `--headless` is not
+    /// one of them.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut headless = false;
+        let mut options = Options {
+            spp: 64,
+            out: PathBuf::from("render.png"),
+            wavefront: false,
+            denoise: false,
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}\n{}", arg, USAGE))
+            };
+            match arg.as_str() {
+                "--headless" => headless = true,
+                "--spp" => {
+                    let spp = value()?;
+                    options.spp = match spp.parse() {
+                        Ok(spp) if spp > 0 => spp,
+                        _ => return Err(format!("invalid sample count: {}", spp)),
+                    };
+                }
+                "--out" => options.out = PathBuf::from(value()?),
+                "--wavefront" => options.wavefront = true,
+                "--denoise" => options.denoise = true,
+                _ => return Err(format!("unknown argument: {}\n{}", arg, USAGE)),
+            }
+        }
+        Ok(if headless { Some(options) } else { None })
+    }
+}
+
+/// Renders `scene` as seen by `camera` with the CUDA renderer, prints how long it took and saves the
+/// image.
+pub fn run(options: &Options, camera: &Camera, scene: &Scene) -> Result<(), Box<dyn Error>> {
+    let dimensions = Vec2::new(WIDTH as usize, HEIGHT as usize);
+    let mut renderer = CudaRenderer::new(dimensions, camera, scene)?;
+
+    // the durations are measured with events around the kernels, so they leave out the launch
+    // overhead and the time spent on the host.
+    let mut sampling = Duration::ZERO;
+    for sample in 0..options.spp {
+        sampling += renderer.render(sample, options.wavefront)?;
+    }
+    let (image, denoising, postprocessing) =
+        renderer.final_image(options.spp, options.denoise, None)?;
+
+    let samples = (dimensions.product() * options.spp) as f64;
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    println!(
+        "rendered {}x{} at {} spp with the {} kernel",
+        dimensions.x,
+        dimensions.y,
+        options.spp,
+        if options.wavefront {
+            "wavefront"
+        } else {
+            "megakernel"
+        }
+    );
+    println!(
+        "sampling: {:.2}ms ({:.3}ms per sample, {:.1} Msamples/s)",
+        ms(sampling),
+        ms(sampling) / options.spp as f64,
+        samples / sampling.as_secs_f64() / 1e6
+    );
+    if options.denoise {
+        println!("denoising: {:.2}ms", ms(denoising));
+    }
+    println!("postprocessing: {:.2}ms", ms(postprocessing));
+
+    export::save(&options.out, dimensions, image)?;
+    println!("saved {}", options.out.display());
+    Ok(())
+}
@@ -1,15 +1,20 @@
 pub mod common;
 pub mod cpu;
 pub mod cuda;
+pub mod export;
+pub mod headless;
 pub mod renderer;
 pub mod viewer;
 
 use common::Camera;
 use cust::vek::Vec3;
 use path_tracer_gpu::{
-    material::{DiffuseMaterial, MaterialKind, MetallicMaterial},
+    material::{
+        ConductorMaterial, DielectricMaterial, DiffuseMaterial, MaterialKind, MetallicMaterial,
+    },
     scene::Scene,
     sphere::Sphere,
+    texture::TextureKind,
     Object,
 };
 use std::error::Error;
@@ -27,28 +32,55 @@ fn main() -> Result<(), Box<dyn Error>> {
     };
 
     let materials = vec![
-        MaterialKind::Metallic(MetallicMaterial {
+        MaterialKind::Conductor(ConductorMaterial {
             color: Vec3::new(1.0, 0.85, 0.45),
-            roughness: 0.0,
+            roughness: 0.3,
         }),
         MaterialKind::Diffuse(DiffuseMaterial {
-            color: Vec3::new(0.5, 0.5, 1.0),
+            texture: TextureKind::Checker {
+                even: Vec3::new(0.5, 0.5, 1.0),
+                odd: Vec3::new(0.9, 0.9, 0.9),
+                scale: 2.0,
+            },
         }),
         MaterialKind::Metallic(MetallicMaterial {
             color: Vec3::new(1.0, 0.7, 0.7),
             roughness: 0.02,
         }),
+        // the CUDA renderer binds the image texture, the others use the fallback color.
+        MaterialKind::Diffuse(DiffuseMaterial {
+            texture: TextureKind::Image {
+                handle: 0,
+                fallback: Vec3::new(0.6, 0.6, 0.4),
+            },
+        }),
+        MaterialKind::Diffuse(DiffuseMaterial {
+            texture: TextureKind::Noise {
+                color: Vec3::new(0.9, 0.5, 0.3),
+                scale: 12.0,
+            },
+        }),
+        MaterialKind::Dielectric(DielectricMaterial {
+            ior: 1.5,
+            tint: Vec3::one(),
+        }),
     ];
 
     let objects = vec![
         Object::Sphere(Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, 0)),
         Object::Sphere(Sphere::new(Vec3::new(1.1, 0.2, -0.7), 0.2, 2)),
         Object::Sphere(Sphere::new(Vec3::new(0.0, -200.5, -1.0), 200.0, 1)),
+        Object::Sphere(Sphere::new(Vec3::new(-1.1, 0.0, -1.0), 0.5, 3)),
+        Object::Sphere(Sphere::new(Vec3::new(-0.5, -0.3, -0.3), 0.2, 4)),
+        Object::Sphere(Sphere::new(Vec3::new(0.5, -0.3, -0.2), 0.2, 5)),
     ];
     let cpu_scene = Scene {
         objects: &objects,
         materials: &materials,
     };
 
+    if let Some(options) = headless::Options::parse(std::env::args().skip(1))? {
+        return headless::run(&options, &camera, &cpu_scene);
+    }
     viewer::run(&camera, &cpu_scene);
 }
@@ -1,53 +1,92 @@
-use cust::vek::Vec2;
+use cust::{device::Device, vek::Vec2};
 use glutin::{event::Event, event_loop::ControlFlow};
 use imgui::Ui;
-use path_tracer_gpu::scene::Scene;
+use path_tracer_gpu::{aov::AovKind, scene::Scene, Rgba16F};
+use std::path::Path;
 use sysinfo::{System, SystemExt};
 
 use crate::{
     common::{Camera, CameraController},
     cpu::CpuRenderer,
-    cuda::CudaRenderer,
+    cuda::{CudaRenderer, MultiGpuRenderer},
+    export,
 };
 
 pub struct Renderer {
     cuda: CudaRenderer,
     cpu: CpuRenderer,
+    /// Only created if there is more than one GPU.
+    multi_gpu: Option<MultiGpuRenderer>,
     running_on_gpu: bool,
+    use_all_gpus: bool,
+    /// Trace the samples with the wavefront kernels instead of the single render kernel.
+    wavefront: bool,
     pub denoise: bool,
+    /// The AOV shown instead of the image, only supported on a single GPU.
+    aov: Option<AovKind>,
     accumulated_samples: usize,
     camera: Camera,
     controller: CameraController,
     system: System,
+    dimensions: Vec2<usize>,
+    export_path: String,
+    /// The outcome of the last export, shown below the export buttons.
+    export_status: Option<String>,
 }
 
 impl Renderer {
     pub fn new(dimensions: Vec2<usize>, camera: &Camera, scene: &Scene) -> Self {
+        let cuda =
+            CudaRenderer::new(dimensions, camera, scene).expect("Failed to make CUDA renderer");
+        let multi_gpu = if Device::num_devices().expect("Failed to count devices") > 1 {
+            Some(
+                MultiGpuRenderer::new(dimensions, camera, scene)
+                    .expect("Failed to make multi-GPU renderer"),
+            )
+        } else {
+            None
+        };
+
         Self {
-            cuda: CudaRenderer::new(dimensions, camera, scene)
-                .expect("Failed to make CUDA renderer"),
+            cuda,
             cpu: CpuRenderer::new(dimensions, camera, scene),
+            multi_gpu,
             running_on_gpu: true,
+            use_all_gpus: false,
+            wavefront: false,
             denoise: false,
+            aov: None,
             accumulated_samples: 0,
             camera: *camera,
             controller: CameraController::new(dimensions),
             system: System::new_all(),
+            dimensions,
+            export_path: String::from("render.exr"),
+            export_status: None,
         }
     }
 
     pub fn resize(&mut self, new: Vec2<usize>) {
         self.accumulated_samples = 0;
+        self.dimensions = new;
         self.cpu.resize(new);
         self.cuda
             .resize(new)
             .expect("Failed to resize CUDA renderer");
+        if let Some(multi_gpu) = &mut self.multi_gpu {
+            multi_gpu
+                .resize(new)
+                .expect("Failed to resize multi-GPU renderer");
+        }
     }
 
-    /// Renders the scene and returns a final image buffer that can be displayed.
-    pub fn render(&mut self, ui: &Ui) -> &[u8] {
+    /// Renders the scene and returns the final linear half-precision image, which can be displayed
+    /// or exported.
+    pub fn render(&mut self, ui: &Ui) -> &[Rgba16F] {
         self.cuda.info(ui);
         self.cpu.info(ui, &self.system);
+        // the random numbers of every pixel are derived from the index of the sample.
+        let sample = self.accumulated_samples;
         self.accumulated_samples += 1;
 
         ui.separator();
@@ -64,57 +103,93 @@ impl Renderer {
             self.clear_view(true);
         }
 
-        if self.running_on_gpu {
+        let output = if self.running_on_gpu {
             ui.separator();
             ui.text("Running on GPU");
             ui.checkbox("OptiX Denoise", &mut self.denoise);
+            ui.checkbox("Wavefront", &mut self.wavefront);
+            if let Some(multi_gpu) = &self.multi_gpu {
+                let label = format!("Use all {} GPUs (no denoising)", multi_gpu.num_gpus());
+                if ui.checkbox(label, &mut self.use_all_gpus) {
+                    self.clear_view(true);
+                }
+            }
             ui.separator();
 
-            let duration = self
-                .cuda
-                .render()
-                .expect("Failed to render using CUDA backend");
+            if let (true, Some(multi_gpu)) = (self.use_all_gpus, &mut self.multi_gpu) {
+                let duration = multi_gpu
+                    .render(sample)
+                    .expect("Failed to render using multiple GPUs");
 
-            ui.text(format!(
-                "Sampling time: {:.2}ms",
-                duration.as_secs_f32() * 1000.0
-            ));
+                ui.text(format!(
+                    "Sampling time: {:.2}ms",
+                    duration.as_secs_f32() * 1000.0
+                ));
+
+                let (output, postprocessing_time) = multi_gpu
+                    .final_image(self.accumulated_samples)
+                    .expect("Failed to get final image");
 
-            let (output, denoising_time, postprocessing_time) = self
-                .cuda
-                .final_image(self.accumulated_samples, self.denoise)
-                .expect("Failed to get final image");
+                ui.text(format!(
+                    "Postprocessing time: {:.2}ms",
+                    postprocessing_time.as_secs_f32() * 1000.0
+                ));
 
-            if self.denoise {
                 ui.text(format!(
-                    "Denoising time: {:.2}ms",
-                    denoising_time.as_secs_f32() * 1000.0
+                    "Total: {:.2}ms",
+                    (duration + postprocessing_time).as_secs_f32() * 1000.0
                 ));
+
+                output
             } else {
-                ui.text("Denoising time: N/A");
-            }
+                Self::aov_ui(ui, &mut self.aov);
+                ui.separator();
 
-            ui.text(format!(
-                "Postprocessing time: {:.2}ms",
-                postprocessing_time.as_secs_f32() * 1000.0
-            ));
+                let duration = self
+                    .cuda
+                    .render(sample, self.wavefront)
+                    .expect("Failed to render using CUDA backend");
 
-            ui.text(format!(
-                "Total: {:.2}ms",
-                (postprocessing_time.as_secs_f32()
-                    + denoising_time.as_secs_f32()
-                    + duration.as_secs_f32())
-                    * 1000.0
-            ));
+                ui.text(format!(
+                    "Sampling time: {:.2}ms",
+                    duration.as_secs_f32() * 1000.0
+                ));
 
-            // bytemuck could do this but vek doesnt have bytemuck
-            unsafe { std::slice::from_raw_parts(output.as_ptr().cast(), output.len() * 3) }
+                let (output, denoising_time, postprocessing_time) = self
+                    .cuda
+                    .final_image(self.accumulated_samples, self.denoise, self.aov)
+                    .expect("Failed to get final image");
+
+                if self.denoise && self.aov.is_none() {
+                    ui.text(format!(
+                        "Denoising time: {:.2}ms",
+                        denoising_time.as_secs_f32() * 1000.0
+                    ));
+                } else {
+                    ui.text("Denoising time: N/A");
+                }
+
+                ui.text(format!(
+                    "Postprocessing time: {:.2}ms",
+                    postprocessing_time.as_secs_f32() * 1000.0
+                ));
+
+                ui.text(format!(
+                    "Total: {:.2}ms",
+                    (postprocessing_time.as_secs_f32()
+                        + denoising_time.as_secs_f32()
+                        + duration.as_secs_f32())
+                        * 1000.0
+                ));
+
+                output
+            }
         } else {
             ui.separator();
             ui.text("Running on CPU");
             ui.separator();
 
-            let duration = self.cpu.render();
+            let duration = self.cpu.render(sample);
 
             ui.text(format!(
                 "Sampling time: {:.2}ms",
@@ -133,17 +208,67 @@ impl Renderer {
                 (duration + postprocessing_time).as_secs_f32() * 1000.0
             ));
 
-            unsafe { std::slice::from_raw_parts(output.as_ptr().cast(), output.len() * 3) }
+            output
+        };
+
+        ui.separator();
+        Self::export_ui(
+            ui,
+            &mut self.export_path,
+            &mut self.export_status,
+            self.dimensions,
+            output,
+        );
+        output
+    }
+
+    /// Shows the buttons for choosing what to present, the image or one of its AOVs.
+    fn aov_ui(ui: &Ui, aov: &mut Option<AovKind>) {
+        ui.text("Show");
+        ui.radio_button("Image", aov, None);
+        ui.radio_button("Albedo", aov, Some(AovKind::Albedo));
+        ui.radio_button("Normal", aov, Some(AovKind::Normal));
+        ui.radio_button("Depth", aov, Some(AovKind::Depth));
+    }
+
+    /// Shows the buttons for saving the current image as EXR or HDR.
+    fn export_ui(
+        ui: &Ui,
+        path: &mut String,
+        status: &mut Option<String>,
+        dimensions: Vec2<usize>,
+        image: &[Rgba16F],
+    ) {
+        ui.input_text("Path", path).build();
+        let format = if ui.button("Save EXR") {
+            Some("exr")
+        } else if ui.button("Save HDR") {
+            Some("hdr")
+        } else {
+            None
+        };
+        if let Some(format) = format {
+            let path = Path::new(path.as_str()).with_extension(format);
+            *status = Some(match export::save(&path, dimensions, image) {
+                Ok(()) => format!("Saved {}", path.display()),
+                Err(e) => format!("Failed to save {}: {}", path.display(), e),
+            });
+        }
+        if let Some(status) = status {
+            ui.text(status.as_str());
         }
     }
 
     fn clear_view(&mut self, force: bool) {
-        if force {
+        if force || self.running_on_gpu {
             self.cuda.update_camera(&self.camera).unwrap();
+            if let Some(multi_gpu) = &mut self.multi_gpu {
+                multi_gpu.update_camera(&self.camera).unwrap();
+            }
+        }
+        if force {
             self.cpu.update_camera(&self.camera);
-        } else if self.running_on_gpu {
-            self.cuda.update_camera(&self.camera).unwrap();
-        } else {
+        } else if !self.running_on_gpu {
             self.cpu.update_camera(&self.camera);
         }
         self.accumulated_samples = 0;
@@ -2,7 +2,7 @@ use cust::vek::Vec2;
 use glium::{
     implement_vertex,
     index::{NoIndices, PrimitiveType},
-    texture::{RawImage2d, SrgbTexture2d},
+    texture::{ClientFormat, MipmapsOption, RawImage2d, Texture2d, UncompressedFloatFormat},
     uniform, Display, Program, Rect, Surface, VertexBuffer,
 };
 use glutin::{
@@ -15,7 +15,7 @@ use glutin::{
 use imgui::Condition;
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use path_tracer_gpu::scene::Scene;
-use std::time::Instant;
+use std::{borrow::Cow, time::Instant};
 
 use crate::{common::Camera, renderer::Renderer, HEIGHT, WIDTH};
 
@@ -74,7 +74,9 @@ struct ViewerRenderer {
     image_size: Vec2<usize>,
     renderer: Renderer,
     imgui_ctx: imgui::Context,
-    texture: SrgbTexture2d,
+    /// The rendered image, an `Rgba16F` texture holding linear colors. The framebuffer is sRGB, so
+    /// the colors are gamma corrected when they are written to it.
+    texture: Texture2d,
     display: Display,
     imgui_renderer: imgui_glium_renderer::Renderer,
     platform: WinitPlatform,
@@ -87,8 +89,7 @@ impl ViewerRenderer {
 
         let size = display.gl_window().window().inner_size();
         let image_size = Vec2::new(size.width as usize, size.height as usize);
-        let texture =
-            SrgbTexture2d::empty(&display, image_size.x as u32, image_size.y as u32).unwrap();
+        let texture = hdr_texture(&display, image_size);
 
         let mut imgui_ctx = imgui::Context::create();
         imgui_ctx.set_ini_filename(None);
@@ -153,12 +154,7 @@ impl ViewerRenderer {
                 WindowEvent::Resized(new) => {
                     let image_size = Vec2::new(new.width as usize, new.height as usize);
                     self.image_size = image_size;
-                    self.texture = SrgbTexture2d::empty(
-                        &self.display,
-                        image_size.x as u32,
-                        image_size.y as u32,
-                    )
-                    .unwrap();
+                    self.texture = hdr_texture(&self.display, image_size);
                     self.renderer.resize(image_size);
                 }
                 _ => {}
@@ -188,8 +184,14 @@ impl ViewerRenderer {
             .build(&ui, || renderer.render(&ui))
             .unwrap();
 
-        let raw =
-            RawImage2d::from_raw_rgb(out.to_vec(), (image_size.x as u32, image_size.y as u32));
+        // glium has no half type, the texture is uploaded as the bits of the halves.
+        let bits = unsafe { std::slice::from_raw_parts(out.as_ptr().cast::<u16>(), out.len() * 4) };
+        let raw = RawImage2d {
+            data: Cow::Borrowed(bits),
+            width: image_size.x as u32,
+            height: image_size.y as u32,
+            format: ClientFormat::F16F16F16F16,
+        };
 
         texture.write(
             Rect {
@@ -225,3 +227,14 @@ impl ViewerRenderer {
         target.finish().unwrap();
     }
 }
+
+fn hdr_texture(display: &Display, size: Vec2<usize>) -> Texture2d {
+    Texture2d::empty_with_format(
+        display,
+        UncompressedFloatFormat::F16F16F16F16,
+        MipmapsOption::NoMipmap,
+        size.x as u32,
+        size.y as u32,
+    )
+    .unwrap()
+}
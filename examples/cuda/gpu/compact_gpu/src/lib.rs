@@ -0,0 +1,45 @@
+#![cfg_attr(
+    target_os = "cuda",
+    no_std,
+    feature(register_attr),
+    register_attr(nvvm_internal)
+)]
+
+use core::mem::MaybeUninit;
+use cuda_std::prelude::*;
+use cuda_std::{collective, shared_array};
+
+// every kernel passes its own closure to `collective::compact`, which is compiled into the kernel like any
+// other generic function, so the predicates cost no more than if they were written out by hand.
+
+#[kernel]
+#[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+pub unsafe fn keep_above(
+    input: &[f32],
+    threshold: f32,
+    output: *mut f32,
+    count: *mut u32,
+    prefixes: *mut u32,
+    flags: *mut u32,
+) {
+    let scratch = shared_array![u32; 32];
+    collective::compact(input, output, count, prefixes, flags, scratch, |x| {
+        *x > threshold
+    });
+}
+
+#[kernel]
+#[allow(improper_ctypes_definitions, clippy::missing_safety_doc)]
+pub unsafe fn keep_multiples(
+    input: &[u32],
+    divisor: u32,
+    output: *mut u32,
+    count: *mut u32,
+    prefixes: *mut u32,
+    flags: *mut u32,
+) {
+    let scratch = shared_array![u32; 32];
+    collective::compact(input, output, count, prefixes, flags, scratch, |x| {
+        *x % divisor == 0
+    });
+}
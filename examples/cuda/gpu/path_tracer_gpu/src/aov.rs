@@ -0,0 +1,89 @@
+//! Arbitrary output variables (AOVs), images of the properties of the surfaces seen by the camera
+//! which are written next to the rendered colors.
+//!
+//! Denoisers use the albedo and normal of the first hit to tell the noise of the samples from the
+//! detail of the scene, and they are handy for debugging the scene, so the viewer can show them
+//! instead of the image.
+
+use crate::{hittable::HitRecord, material::Material, render, *};
+
+/// The properties of the surface first hit by the camera ray of a sample.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Aovs {
+    /// The color of the surface, or the sky color if the ray missed.
+    pub albedo: Vec3,
+    /// The normal of the surface, zero if the ray missed.
+    pub normal: Vec3,
+    /// The distance along the ray to the surface, zero if the ray missed.
+    pub depth: f32,
+}
+
+impl Aovs {
+    /// The AOVs of a camera ray which hit a surface of `material`.
+    pub fn hit(ray: Ray, hit: HitRecord, material: &impl Material) -> Self {
+        Self {
+            albedo: material.albedo(&hit),
+            normal: hit.normal,
+            // the direction of camera rays is not normalized.
+            depth: hit.t * ray.dir.magnitude(),
+        }
+    }
+
+    /// The AOVs of a camera ray which missed every object.
+    pub fn miss(ray: Ray) -> Self {
+        Self {
+            albedo: render::color(ray),
+            ..Default::default()
+        }
+    }
+}
+
+/// Pointers to the buffers every sample of a pixel adds its [`Aovs`] to, one element per pixel.
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct AovBuffers {
+    pub albedo: *mut Vec3,
+    pub normal: *mut Vec3,
+    pub depth: *mut f32,
+}
+
+/// SAFETY: the pointers are created from device buffers.
+#[cfg(not(target_os = "cuda"))]
+unsafe impl cust::memory::DeviceCopy for AovBuffers {}
+
+impl AovBuffers {
+    /// Adds `aovs` to the buffers at pixel `idx`.
+    ///
+    /// # Safety
+    ///
+    /// `idx` must be in bounds of every buffer and no other thread may write to it at the same
+    /// time.
+    pub unsafe fn add(&self, idx: usize, aovs: Aovs) {
+        *self.albedo.add(idx) += aovs.albedo;
+        *self.normal.add(idx) += aovs.normal;
+        *self.depth.add(idx) += aovs.depth;
+    }
+}
+
+/// Which AOV to present instead of the rendered image.
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum AovKind {
+    Albedo,
+    Normal,
+    Depth,
+}
+
+/// Converts the average AOV of a pixel to a color which can be shown.
+///
+/// Normals are mapped from `[-1, 1]` to `[0, 1]`, and depths to a gray which is white for the
+/// camera and goes to black for far away surfaces.
+pub fn aov_color(kind: AovKind, albedo: Vec3, normal: Vec3, depth: f32) -> Vec3 {
+    match kind {
+        AovKind::Albedo => albedo,
+        AovKind::Normal => normal * 0.5 + Vec3::broadcast(0.5),
+        AovKind::Depth if depth > 0.0 => Vec3::broadcast(1.0 / (1.0 + depth)),
+        AovKind::Depth => Vec3::zero(),
+    }
+}
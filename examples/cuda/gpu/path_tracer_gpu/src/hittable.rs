@@ -1,12 +1,16 @@
-use crate::{Ray, Vec3};
+use crate::{Ray, Vec2, Vec3};
 use enum_dispatch::enum_dispatch;
 
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
 #[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
 pub struct HitRecord {
     pub material_handle: usize,
     pub t: f32,
     pub point: Vec3,
     pub normal: Vec3,
+    /// The texture coordinates of the point, in `[0, 1]`.
+    pub uv: Vec2,
 }
 
 #[enum_dispatch]
@@ -8,6 +8,7 @@
 
 extern crate alloc;
 
+pub mod aov;
 pub mod hittable;
 pub mod material;
 pub mod math;
@@ -15,6 +16,8 @@ pub mod render;
 pub mod render_kernels;
 pub mod scene;
 pub mod sphere;
+pub mod texture;
+pub mod wavefront;
 
 pub use cuda_std::vek;
 use enum_dispatch::enum_dispatch;
@@ -25,6 +28,14 @@ pub type Vec3 = vek::Vec3<f32>;
 pub type Point = vek::Vec3<f32>;
 pub type Vec2 = vek::Vec2<f32>;
 
+/// A pixel of the presented image, linear RGBA in half-precision floats.
+pub use cuda_image::pixel::Rgba16F;
+
+/// Converts a linear color to an opaque [`Rgba16F`] pixel, values too large for a half become infinity.
+pub fn rgba16f(color: Vec3) -> Rgba16F {
+    cuda_image::Pixel::from_linear(color.into_array())
+}
+
 #[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
 #[derive(Default, Clone, Copy)]
 #[repr(C)]
@@ -44,7 +55,9 @@ pub enum Object {
     Sphere(Sphere),
 }
 
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
 #[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
 pub struct Ray {
     pub dir: Vec3,
     pub origin: Point,
@@ -1,46 +1,60 @@
 use crate::{
     hittable::HitRecord,
-    math::{random_in_unit_sphere, reflect},
+    math::{orthonormal_basis, random_in_unit_sphere, reflect, refract, schlick, schlick_rgb},
+    texture::TextureKind,
     Ray, Vec3,
 };
+use cuda_std::{dispatch, gpu_or_cpu};
+#[cfg(target_os = "cuda")]
+use cuda_std::{fast, GpuFloat};
 use enum_dispatch::enum_dispatch;
-use gpu_rand::DefaultRand;
+use gpu_rand::{philox::Philox4x32, GpuRand};
 
 #[enum_dispatch]
 pub trait Material {
     /// Optionally scatters a ray and returns an attenuation color and an optional ray
-    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut DefaultRand) -> (Vec3, Option<Ray>);
+    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut Philox4x32) -> (Vec3, Option<Ray>);
+
+    /// The base color of the material at a hit, independent of lighting and direction.
+    fn albedo(&self, hit: &HitRecord) -> Vec3;
 }
 
 #[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
 #[enum_dispatch(Material)]
+#[dispatch]
 pub enum MaterialKind {
     Diffuse(DiffuseMaterial),
     Metallic(MetallicMaterial),
+    Dielectric(DielectricMaterial),
+    Conductor(ConductorMaterial),
 }
 
 #[derive(Clone, Copy, PartialEq)]
 #[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
 pub struct DiffuseMaterial {
-    pub color: Vec3,
+    pub texture: TextureKind,
 }
 
 impl Material for DiffuseMaterial {
-    fn scatter(&self, _: Ray, hit: HitRecord, rng: &mut DefaultRand) -> (Vec3, Option<Ray>) {
+    fn scatter(&self, _: Ray, hit: HitRecord, rng: &mut Philox4x32) -> (Vec3, Option<Ray>) {
         let mut scatter_dir = hit.normal + random_in_unit_sphere(rng);
 
         if scatter_dir.is_approx_zero() {
             scatter_dir = hit.normal;
         }
 
-        let attenuation = self.color;
+        let attenuation = self.albedo(&hit);
         let ray = Ray {
             origin: hit.point,
             dir: scatter_dir,
         };
         (attenuation, Some(ray))
     }
+
+    fn albedo(&self, hit: &HitRecord) -> Vec3 {
+        self.texture.value(hit.uv, hit.point)
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -51,7 +65,7 @@ pub struct MetallicMaterial {
 }
 
 impl Material for MetallicMaterial {
-    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut DefaultRand) -> (Vec3, Option<Ray>) {
+    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut Philox4x32) -> (Vec3, Option<Ray>) {
         let reflected = reflect(incoming.dir.normalized(), hit.normal);
         let scattered = Ray {
             origin: hit.point,
@@ -64,4 +78,112 @@ impl Material for MetallicMaterial {
             (attenuation, None)
         }
     }
+
+    fn albedo(&self, _: &HitRecord) -> Vec3 {
+        self.color
+    }
+}
+
+/// A transparent material like glass or water, which refracts rays by Snell's law and reflects
+/// them with a probability given by Schlick's approximation of the Fresnel equations.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+pub struct DielectricMaterial {
+    /// The index of refraction, about 1.5 for glass.
+    pub ior: f32,
+    /// The color every ray passing through or off the surface is multiplied by.
+    pub tint: Vec3,
+}
+
+impl Material for DielectricMaterial {
+    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut Philox4x32) -> (Vec3, Option<Ray>) {
+        let unit = incoming.dir.normalized();
+        // normals point out of the object, so rays leaving it hit the back of the surface.
+        let (normal, ratio) = if unit.dot(hit.normal) < 0.0 {
+            (hit.normal, 1.0 / self.ior)
+        } else {
+            (-hit.normal, self.ior)
+        };
+
+        let cos = (-unit).dot(normal).min(1.0);
+        let sin = (1.0 - cos * cos).sqrt();
+        let r0 = ((1.0 - ratio) / (1.0 + ratio)).powi(2);
+        // past the critical angle, every ray is reflected.
+        let dir = if ratio * sin > 1.0 || schlick(cos, r0) > rng.uniform_f32() {
+            reflect(unit, normal)
+        } else {
+            refract(unit, normal, ratio)
+        };
+        let ray = Ray {
+            origin: hit.point,
+            dir,
+        };
+        (self.tint, Some(ray))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Vec3 {
+        self.tint
+    }
+}
+
+/// A rough metal with a GGX (Trowbridge-Reitz) microfacet distribution, whose reflections are
+/// importance sampled by drawing microfacet normals from the distribution.
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+pub struct ConductorMaterial {
+    /// The reflectance at normal incidence.
+    pub color: Vec3,
+    /// The perceptual roughness in `[0, 1]`, the GGX alpha is its square.
+    pub roughness: f32,
+}
+
+impl ConductorMaterial {
+    /// Smith's masking function for GGX, the fraction of microfacets visible from a direction
+    /// whose cosine with the normal is `cos`.
+    fn smith_g1(cos: f32, alpha2: f32) -> f32 {
+        2.0 * cos / (cos + (alpha2 + (1.0 - alpha2) * cos * cos).sqrt())
+    }
+}
+
+impl Material for ConductorMaterial {
+    #[gpu_or_cpu]
+    fn scatter(&self, incoming: Ray, hit: HitRecord, rng: &mut Philox4x32) -> (Vec3, Option<Ray>) {
+        // perfectly smooth surfaces make the distribution a dirac, which does not sample well.
+        let alpha = (self.roughness * self.roughness).max(1e-3);
+        let alpha2 = alpha * alpha;
+        let view = -incoming.dir.normalized();
+        let n_dot_v = hit.normal.dot(view);
+        if n_dot_v <= 0.0 {
+            return (self.color, None);
+        }
+
+        // draw a microfacet normal with a density of D(h) * cos(theta_h).
+        let (u1, u2) = (rng.uniform_f32(), rng.uniform_f32());
+        let cos_h = ((1.0 - u1) / (1.0 + (alpha2 - 1.0) * u1)).sqrt();
+        let sin_h = (1.0 - cos_h * cos_h).max(0.0).sqrt();
+        // the approximate sine and cosine are only accurate in -pi..pi.
+        let (sin_phi, cos_phi) = fast::sin_cos(core::f32::consts::PI * (2.0 * u2 - 1.0));
+        let (tangent, bitangent) = orthonormal_basis(hit.normal);
+        let half = sin_h * cos_phi * tangent + sin_h * sin_phi * bitangent + cos_h * hit.normal;
+
+        let dir = reflect(-view, half);
+        let n_dot_l = hit.normal.dot(dir);
+        let v_dot_h = view.dot(half);
+        if n_dot_l <= 0.0 || v_dot_h <= 0.0 {
+            return (self.color, None);
+        }
+
+        // the BRDF times the cosine over the density of the reflected direction, D cancels out.
+        let g = Self::smith_g1(n_dot_v, alpha2) * Self::smith_g1(n_dot_l, alpha2);
+        let weight = schlick_rgb(v_dot_h, self.color) * (g * v_dot_h / (n_dot_v * cos_h));
+        let ray = Ray {
+            origin: hit.point,
+            dir,
+        };
+        (weight, Some(ray))
+    }
+
+    fn albedo(&self, _: &HitRecord) -> Vec3 {
+        self.color
+    }
 }
@@ -3,25 +3,25 @@
 use crate::Vec3;
 #[cfg(target_os = "cuda")]
 use cuda_std::GpuFloat;
-use gpu_rand::{DefaultRand, GpuRand};
+use gpu_rand::{philox::Philox4x32, GpuRand};
 
 /// Converts a float in the range of [0.0, 1.0] to a range of [-1.0, 1.0].
 pub fn norm_f32_to_snorm(x: f32) -> f32 {
     x * 2.0 - 1.0
 }
 
-pub fn random_unit_vec(state: &mut DefaultRand) -> Vec3 {
+pub fn random_unit_vec(state: &mut Philox4x32) -> Vec3 {
     let [x, y] = state.normal_f32_2();
     let z = state.normal_f32();
     Vec3::new(x, y, z)
 }
 
 /// Creates a random vector with each element being in the range of [-1.0, 1.0] (signed normalized).
-pub fn random_snorm_vec(state: &mut DefaultRand) -> Vec3 {
+pub fn random_snorm_vec(state: &mut Philox4x32) -> Vec3 {
     random_unit_vec(state).map(norm_f32_to_snorm)
 }
 
-pub fn random_in_unit_sphere(state: &mut DefaultRand) -> Vec3 {
+pub fn random_in_unit_sphere(state: &mut Philox4x32) -> Vec3 {
     loop {
         let p = random_snorm_vec(state);
         if p.magnitude_squared() >= 1.0 {
@@ -41,3 +41,26 @@ pub fn refract(uv: Vec3, n: Vec3, etai_over_etat: f32) -> Vec3 {
     let r_out_parallel = -((1.0 - r_out_perp.magnitude_squared()).abs()).sqrt() * n;
     r_out_perp + r_out_parallel
 }
+
+/// Schlick's approximation of the fraction of light reflected by a surface at an angle with cosine
+/// `cos` from its normal, for a reflectance of `f0` at normal incidence.
+pub fn schlick(cos: f32, f0: f32) -> f32 {
+    f0 + (1.0 - f0) * (1.0 - cos).powi(5)
+}
+
+/// [`schlick`] for every channel of the reflectance of a conductor.
+pub fn schlick_rgb(cos: f32, f0: Vec3) -> Vec3 {
+    f0 + (Vec3::one() - f0) * (1.0 - cos).powi(5)
+}
+
+/// Two vectors which make an orthonormal basis with the unit vector `n`, from "Building an
+/// Orthonormal Basis, Revisited" (Duff et al. 2017).
+pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0f32.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vec3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vec3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
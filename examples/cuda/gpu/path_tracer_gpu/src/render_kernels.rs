@@ -1,23 +1,71 @@
-use crate::{render::*, scene::Scene, *};
-use cuda_std::{vek::Clamp, *};
-use gpu_rand::{DefaultRand, GpuRand};
+use crate::{aov::*, material::MaterialKind, render::*, scene::Scene, *};
+use cuda_image::ImageView;
+use cuda_std::*;
+use gpu_rand::{philox, GpuRand};
 
+/// Traces sample `sample` of every pixel, adding its color to `fb` and its [`Aovs`] to `aovs`.
 #[kernel]
-pub unsafe fn render(fb: *mut Vec3, view: Viewport, scene: &Scene, rand_states: *mut DefaultRand) {
+pub unsafe fn render(fb: *mut Vec3, aovs: AovBuffers, view: Viewport, scene: &Scene, sample: u32) {
     let idx = thread::index_2d();
     if idx.x >= view.bounds.x as u32 || idx.y >= view.bounds.y as u32 {
         return;
     }
     let px_idx = idx.y as usize * view.bounds.x + idx.x as usize;
+    let sample_aovs = sample_pixel(fb.add(px_idx), idx, &view, scene, sample);
+    aovs.add(px_idx, sample_aovs);
+}
+
+/// Renders sample `sample` of `rows` rows of the image starting at `first_row`, where `fb` only
+/// covers those rows. This is used to split the image across multiple GPUs.
+///
+/// The scene is passed as its raw parts so that no device allocation has to outlive the launch.
+#[kernel]
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn render_tile(
+    fb: *mut Vec3,
+    view: Viewport,
+    first_row: usize,
+    rows: usize,
+    objects: *const Object,
+    num_objects: usize,
+    materials: *const MaterialKind,
+    num_materials: usize,
+    sample: u32,
+) {
+    let idx = thread::index_2d();
+    if idx.x >= view.bounds.x as u32 || idx.y as usize >= rows {
+        return;
+    }
+    let scene = Scene {
+        objects: core::slice::from_raw_parts(objects, num_objects),
+        materials: core::slice::from_raw_parts(materials, num_materials),
+    };
+    let px_idx = idx.y as usize * view.bounds.x + idx.x as usize;
+    let image_idx = vek::Vec2::new(idx.x, idx.y + first_row as u32);
+    sample_pixel(fb.add(px_idx), image_idx, &view, &scene, sample);
+}
+
+/// Traces sample `sample` of pixel `idx`, adds its color to `px` and returns its [`Aovs`].
+///
+/// The random numbers only depend on the pixel and the sample, so a sample looks the same no matter
+/// which kernel or GPU traces it.
+unsafe fn sample_pixel(
+    px: *mut Vec3,
+    idx: vek::Vec2<u32>,
+    view: &Viewport,
+    scene: &Scene,
+    sample: u32,
+) -> Aovs {
+    let rng = &mut philox::for_pixel(idx.x, idx.y, sample);
 
     // generate a tiny offset for the ray for antialiasing
-    let rng = &mut *rand_states.add(px_idx);
     let offset = Vec2::from(rng.normal_f32_2());
 
-    let ray = generate_ray(idx, &view, offset);
+    let ray = generate_ray(idx, view, offset);
 
-    let color = scene.ray_color(ray, rng);
-    *fb.add(px_idx) += color;
+    let (color, aovs) = scene.trace(ray, rng);
+    *px += color;
+    aovs
 }
 
 /// Scales an accumulated buffer by the sample count, storing each pixel in the corresponding `out` pixel.
@@ -36,21 +84,41 @@ pub unsafe fn scale_buffer(fb: *const Vec3, out: *mut Vec3, samples: u32, view:
     *out = scaled;
 }
 
-/// Postprocesses a (scaled) buffer into a final u8 buffer.
+/// Converts a (scaled) buffer of linear colors into the image which is presented and exported.
+///
+/// The half-precision image of the viewer stays linear and is not clamped, gamma correction
+/// happens when presenting it.
 #[kernel]
-pub unsafe fn postprocess(fb: *const Vec3, out: *mut vek::Vec3<u8>, view: Viewport) {
+pub unsafe fn present(fb: *const Vec3, out: ImageView, view: Viewport) {
     let idx_2d = thread::index_2d();
     if idx_2d.x >= view.bounds.x as u32 || idx_2d.y >= view.bounds.y as u32 {
         return;
     }
     let idx = idx_2d.y as usize * view.bounds.x + idx_2d.x as usize;
-    let original = &*fb.add(idx);
-    let out = &mut *out.add(idx);
-    // gamma=2.0
-    let gamma_corrected = original.sqrt();
+    out.write(idx_2d.x, idx_2d.y, (*fb.add(idx)).into_array());
+}
 
-    *out = (gamma_corrected * 255.0)
-        .clamped(Vec3::zero(), Vec3::broadcast(255.0))
-        .numcast()
-        .unwrap();
+/// Like [`present`], but presents the average of an AOV over `samples` samples instead of the
+/// rendered image.
+#[kernel]
+pub unsafe fn present_aov(
+    aovs: AovBuffers,
+    kind: AovKind,
+    samples: u32,
+    out: ImageView,
+    view: Viewport,
+) {
+    let idx_2d = thread::index_2d();
+    if idx_2d.x >= view.bounds.x as u32 || idx_2d.y >= view.bounds.y as u32 {
+        return;
+    }
+    let idx = idx_2d.y as usize * view.bounds.x + idx_2d.x as usize;
+    let scale = 1.0 / samples as f32;
+    let color = aov_color(
+        kind,
+        *aovs.albedo.add(idx) * scale,
+        *aovs.normal.add(idx) * scale,
+        *aovs.depth.add(idx) * scale,
+    );
+    out.write(idx_2d.x, idx_2d.y, color.into_array());
 }
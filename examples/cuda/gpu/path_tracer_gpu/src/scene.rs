@@ -1,9 +1,12 @@
-use gpu_rand::DefaultRand;
+use cuda_std::dispatch;
+use gpu_rand::philox::Philox4x32;
 
+use crate::aov::Aovs;
 use crate::material::*;
 use crate::*;
 
-const MAX_BOUNCES: u32 = 5;
+/// The number of times a path bounces off objects before it is terminated.
+pub const MAX_BOUNCES: u32 = 5;
 
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -44,27 +47,39 @@ impl Scene<'_> {
         hit
     }
 
-    pub fn ray_color(&self, ray: Ray, rng: &mut DefaultRand) -> Vec3 {
+    pub fn ray_color(&self, ray: Ray, rng: &mut Philox4x32) -> Vec3 {
+        self.trace(ray, rng).0
+    }
+
+    /// Traces a camera ray like [`ray_color`](Self::ray_color), also returning the [`Aovs`] of the
+    /// first surface it hits.
+    pub fn trace(&self, ray: Ray, rng: &mut Philox4x32) -> (Vec3, Aovs) {
         let mut cur_ray = ray;
         let mut attenuation = Vec3::one();
+        let mut aovs = Aovs::default();
 
-        for _ in 0..MAX_BOUNCES {
+        for bounce in 0..MAX_BOUNCES {
             if let Some(hit) = self.hit(cur_ray, 0.001, f32::INFINITY) {
-                let material = self.materials[hit.material_handle];
-                let (hit_attenuation, scattered) = material.scatter(cur_ray, hit, rng);
+                // matched by reference, a copy of the enum would live in local memory.
+                let material = &self.materials[hit.material_handle];
+                if bounce == 0 {
+                    aovs = Aovs::hit(cur_ray, hit, material);
+                }
+                let (hit_attenuation, scattered) =
+                    dispatch::coherent(material, |material| material.scatter(cur_ray, hit, rng));
                 if let Some(scattered) = scattered {
                     attenuation *= hit_attenuation;
                     cur_ray = scattered;
                 } else {
-                    return Vec3::zero();
+                    return (Vec3::zero(), aovs);
                 }
             } else {
-                let unit = cur_ray.dir.normalized();
-                let t = 0.5 * (unit.y + 1.0);
-                let c = (1.0 - t) * Vec3::one() + t * Vec3::new(0.5, 0.7, 1.0);
-                return attenuation * c;
+                if bounce == 0 {
+                    aovs = Aovs::miss(cur_ray);
+                }
+                return (attenuation * render::color(cur_ray), aovs);
             }
         }
-        Vec3::zero()
+        (Vec3::zero(), aovs)
     }
 }
@@ -19,6 +19,28 @@ impl Sphere {
             mat,
         }
     }
+
+    fn hit_record(&self, ray: Ray, t: f32) -> HitRecord {
+        let point = ray.at(t);
+        let normal = (point - self.center) / self.radius;
+        HitRecord {
+            t,
+            point,
+            normal,
+            material_handle: self.mat,
+            uv: Self::uv(normal),
+        }
+    }
+
+    /// The texture coordinates of the point of the unit sphere `p`, the longitude (starting at -x)
+    /// and the latitude (starting at -y).
+    fn uv(p: Vec3) -> Vec2 {
+        use core::f32::consts::PI;
+
+        let u = ((-p.z).atan2(p.x) + PI) / (2.0 * PI);
+        let v = p.y.clamp(-1.0, 1.0).asin() / PI + 0.5;
+        Vec2::new(u, v)
+    }
 }
 
 impl Hittable for Sphere {
@@ -36,21 +58,11 @@ impl Hittable for Sphere {
         if discriminant > 0.0 {
             let temp = (-b - discriminant.sqrt()) / a;
             if temp < t_max && temp > t_min {
-                return Some(HitRecord {
-                    t: temp,
-                    point: ray.at(temp),
-                    normal: (ray.at(temp) - self.center) / self.radius,
-                    material_handle: self.mat,
-                });
+                return Some(self.hit_record(ray, temp));
             }
             let temp = (-b + discriminant.sqrt()) / a;
             if temp < t_max && temp > t_min {
-                return Some(HitRecord {
-                    t: temp,
-                    point: ray.at(temp),
-                    normal: (ray.at(temp) - self.center) / self.radius,
-                    material_handle: self.mat,
-                });
+                return Some(self.hit_record(ray, temp));
             }
         }
         None
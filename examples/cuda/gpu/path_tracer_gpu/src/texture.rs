@@ -0,0 +1,87 @@
+use crate::*;
+#[cfg(target_os = "cuda")]
+use cuda_std::{texture::TextureObject, GpuFloat};
+
+/// The color of a material over its surface, looked up with the texture coordinates of a hit.
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+#[derive(Clone, Copy, PartialEq)]
+#[repr(C)]
+pub enum TextureKind {
+    /// The same color everywhere.
+    Solid(Vec3),
+    /// Cubes of space alternating between two colors, `scale` cubes per unit, which show up as
+    /// squares on flat surfaces.
+    Checker { even: Vec3, odd: Vec3, scale: f32 },
+    /// Smooth value noise in space between black and `color`, with features of about `1 / scale`.
+    Noise { color: Vec3, scale: f32 },
+    /// A texture object sampled with normalized coordinates.
+    ///
+    /// A `handle` of 0 is no texture, for renderers which did not create the texture object, and
+    /// texture objects cannot be sampled on the CPU, so `fallback` is used instead in both cases.
+    Image { handle: u64, fallback: Vec3 },
+}
+
+impl TextureKind {
+    /// The color at texture coordinates `uv` of the surface at `point`.
+    pub fn value(&self, uv: Vec2, point: Point) -> Vec3 {
+        match *self {
+            Self::Solid(color) => color,
+            Self::Checker { even, odd, scale } => {
+                let p = point * scale;
+                let cell = p.x.floor() + p.y.floor() + p.z.floor();
+                if (cell as i32).rem_euclid(2) == 0 {
+                    even
+                } else {
+                    odd
+                }
+            }
+            Self::Noise { color, scale } => color * value_noise(point * scale),
+            Self::Image { handle, fallback } => sample_image(handle, uv).unwrap_or(fallback),
+        }
+    }
+}
+
+#[cfg(target_os = "cuda")]
+fn sample_image(handle: u64, uv: Vec2) -> Option<Vec3> {
+    if handle == 0 {
+        return None;
+    }
+    // images start with their top row, unlike texture coordinates.
+    let [r, g, b, _] = unsafe { TextureObject::from_handle(handle).sample_2d(uv.x, 1.0 - uv.y) };
+    Some(Vec3::new(r, g, b))
+}
+
+#[cfg(not(target_os = "cuda"))]
+fn sample_image(_: u64, _: Vec2) -> Option<Vec3> {
+    None
+}
+
+/// Trilinearly interpolated random values in `[0, 1]` at the corners of the unit cubes of space.
+fn value_noise(point: Point) -> f32 {
+    let cell = Vec3::new(point.x.floor(), point.y.floor(), point.z.floor());
+    let t = point - cell;
+    // smoothstep, so that the noise has no creases at the faces of the cubes.
+    let t = t * t * (Vec3::broadcast(3.0) - 2.0 * t);
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        hash(cell.x as i32 + dx, cell.y as i32 + dy, cell.z as i32 + dz)
+    };
+
+    let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), t.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), t.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), t.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), t.x);
+    lerp(lerp(x00, x10, t.y), lerp(x01, x11, t.y), t.z)
+}
+
+/// A random value in `[0, 1]` for a corner of the integer lattice.
+fn hash(x: i32, y: i32, z: i32) -> f32 {
+    let mut h = (x as u32)
+        .wrapping_mul(0x8da6_b343)
+        .wrapping_add((y as u32).wrapping_mul(0xd816_3841))
+        .wrapping_add((z as u32).wrapping_mul(0xcb1a_b31f));
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2c1b_3c6d);
+    h ^= h >> 12;
+    (h >> 8) as f32 / (1 << 24) as f32
+}
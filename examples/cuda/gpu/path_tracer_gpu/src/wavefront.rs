@@ -0,0 +1,174 @@
+//! A wavefront version of the render kernel, which splits tracing a sample into a kernel per stage
+//! working on a queue of paths.
+//!
+//! The megakernel `render` traces every bounce of a path in one thread, so once some paths of a
+//! warp have left the scene, their threads idle until the longest path of the warp is done, and the
+//! material code of every kind of material runs in lockstep. Instead, a wavefront renderer keeps the
+//! state of every path in memory and runs a short kernel per stage:
+//!
+//! 1. [`generate_paths`] creates a path for every pixel, filling the queue.
+//! 2. [`intersect_paths`] finds the closest hit of every path in the queue.
+//! 3. [`shade_paths`] adds the sky color for paths which missed and scatters the others, marking
+//!    the paths which were absorbed or missed as terminated.
+//! 4. [`compact_paths`] copies the paths which are still alive into the next queue with
+//!    [`collective::compact`], keeping them in pixel order so that the next bounce stays coherent.
+//!
+//! Stages 2 to 4 run once per bounce. The length of a queue stays in device memory, so the host
+//! never waits for a bounce to finish: the kernels are launched for a full queue and loop over
+//! however many paths there are.
+
+use crate::{aov::*, hittable::HitRecord, material::Material, render::*, scene::Scene, *};
+use core::mem::MaybeUninit;
+use cuda_std::{collective, fusion::grid_stride, shared_array, *};
+use gpu_rand::{
+    philox::{self, Philox4x32},
+    GpuRand,
+};
+
+/// The pixel of a path which no longer contributes to the image.
+const TERMINATED: u32 = u32::MAX;
+
+/// How many blocks of random numbers every bounce of a path can use before they overlap with the
+/// numbers of the next bounce.
+const BLOCKS_PER_BOUNCE: u64 = 1 << 16;
+
+/// A path being traced, the element of the path queues.
+#[cfg_attr(not(target_os = "cuda"), derive(cust::DeviceCopy))]
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct PathState {
+    pub ray: Ray,
+    /// The product of the attenuations of every bounce so far.
+    pub throughput: Vec3,
+    /// The index of the pixel of the path, or [`TERMINATED`].
+    pub pixel: u32,
+    /// How many times the path bounced off objects.
+    pub bounce: u32,
+}
+
+impl PathState {
+    pub fn is_alive(&self) -> bool {
+        self.pixel != TERMINATED
+    }
+}
+
+/// The generator of bounce `bounce` of sample `sample` of pixel `pixel`, bounce 0 being the one of
+/// the first ray. The paths do not keep their generators, which would make them a lot larger, the
+/// generator of every bounce is created again from the pixel and the sample.
+fn path_rng(pixel: u32, view: &Viewport, sample: u32, bounce: u32) -> Philox4x32 {
+    let width = view.bounds.x as u32;
+    let mut rng = philox::for_pixel(pixel % width, pixel / width, sample);
+    rng.advance(bounce as u64 * BLOCKS_PER_BOUNCE);
+    rng
+}
+
+/// Fills `paths` with the first ray of sample `sample` of every pixel and sets `len` to their
+/// number.
+#[kernel]
+pub unsafe fn generate_paths(paths: *mut PathState, len: *mut u32, view: Viewport, sample: u32) {
+    let idx = thread::index_2d();
+    if idx.x >= view.bounds.x as u32 || idx.y >= view.bounds.y as u32 {
+        return;
+    }
+    let px_idx = idx.y as usize * view.bounds.x + idx.x as usize;
+    if px_idx == 0 {
+        *len = view.bounds.product() as u32;
+    }
+
+    let rng = &mut path_rng(px_idx as u32, &view, sample, 0);
+    let offset = Vec2::from(rng.normal_f32_2());
+    *paths.add(px_idx) = PathState {
+        ray: generate_ray(idx, &view, offset),
+        throughput: Vec3::one(),
+        pixel: px_idx as u32,
+        bounce: 0,
+    };
+}
+
+/// Finds the closest hit of each of the `*len` paths of the queue.
+#[kernel]
+pub unsafe fn intersect_paths(
+    paths: *const PathState,
+    len: *const u32,
+    scene: &Scene,
+    hits: *mut Option<HitRecord>,
+) {
+    grid_stride(*len as usize, |i| {
+        let path = &*paths.add(i);
+        *hits.add(i) = scene.hit(path.ray, 0.001, f32::INFINITY);
+    });
+}
+
+/// Shades each of the `*len` paths of the queue, adding the color of the paths which left the scene
+/// to `fb` and scattering the rest. Paths which did not bounce yet add their [`Aovs`] to `aovs`.
+#[kernel]
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn shade_paths(
+    paths: *mut PathState,
+    len: *const u32,
+    hits: *const Option<HitRecord>,
+    scene: &Scene,
+    fb: *mut Vec3,
+    aovs: AovBuffers,
+    view: Viewport,
+    sample: u32,
+) {
+    grid_stride(*len as usize, |i| {
+        let path = &mut *paths.add(i);
+        let pixel = path.pixel as usize;
+        let first_hit = path.bounce == 0;
+        path.bounce += 1;
+        match *hits.add(i) {
+            Some(hit) => {
+                let material = scene.materials[hit.material_handle];
+                if first_hit {
+                    aovs.add(pixel, Aovs::hit(path.ray, hit, &material));
+                }
+                let rng = &mut path_rng(path.pixel, &view, sample, path.bounce);
+                match material.scatter(path.ray, hit, rng) {
+                    (attenuation, Some(scattered)) => {
+                        path.throughput *= attenuation;
+                        path.ray = scattered;
+                    }
+                    // absorbed, the path adds nothing.
+                    (_, None) => path.pixel = TERMINATED,
+                }
+            }
+            None => {
+                if first_hit {
+                    aovs.add(pixel, Aovs::miss(path.ray));
+                }
+                // every pixel has at most one path in the queue, so nothing else writes to it.
+                *fb.add(pixel) += path.throughput * color(path.ray);
+                path.pixel = TERMINATED;
+            }
+        }
+    });
+}
+
+/// Copies the paths of the queue which are still alive to `next` in order and sets `next_len` to
+/// their number.
+///
+/// This is a [`collective::compact`], so the grid must be no larger than what the GPU runs at once,
+/// and `prefixes` and `flags` are its buffers, one element per block.
+#[kernel]
+pub unsafe fn compact_paths(
+    paths: *const PathState,
+    len: *const u32,
+    next: *mut PathState,
+    next_len: *mut u32,
+    prefixes: *mut u32,
+    flags: *mut u32,
+) {
+    let scratch = shared_array![u32; 32];
+    let queue = core::slice::from_raw_parts(paths, *len as usize);
+    collective::compact(
+        queue,
+        next,
+        next_len,
+        prefixes,
+        flags,
+        scratch,
+        PathState::is_alive,
+    );
+}